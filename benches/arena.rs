@@ -0,0 +1,83 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Once;
+
+use bencher::{benchmark_group, benchmark_main, Bencher};
+use rusqlite::types::Value;
+use rusqlite::Connection;
+
+// Counts allocations, to report how many each way of collecting the rows
+// makes.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+// 1M rows of mostly text under `cargo bench`, which passes `--bench`, and
+// only a few when `cargo test --benches` runs the benchmarks in debug
+fn sql() -> String {
+    let rows = if std::env::args().any(|arg| arg == "--bench") {
+        1_000_000
+    } else {
+        1000
+    };
+    format!(
+        "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < {rows})
+         SELECT i, 'name-' || i, printf('%040d', i), x'0102030405' FROM n"
+    )
+}
+
+// bencher calls each benchmark several times, report once
+fn report(once: &Once, name: &str, f: impl FnOnce()) {
+    once.call_once(|| {
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        f();
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+        eprintln!("{name}: {allocations} allocations");
+    });
+}
+
+fn bench_collect_values(b: &mut Bencher) {
+    let db = Connection::open_in_memory().unwrap();
+    let mut stmt = db.prepare(&sql()).unwrap();
+    let mut collect = || -> Vec<Vec<Value>> {
+        let mut rows = stmt.query([]).unwrap();
+        let mut values = Vec::new();
+        while let Some(row) = rows.next().unwrap() {
+            values.push(row.to_vec());
+        }
+        values
+    };
+    static ONCE: Once = Once::new();
+    report(&ONCE, "Vec<Vec<Value>>", || drop(collect()));
+    b.iter(&mut collect);
+}
+
+fn bench_query_arena(b: &mut Bencher) {
+    let db = Connection::open_in_memory().unwrap();
+    let mut stmt = db.prepare(&sql()).unwrap();
+    static ONCE: Once = Once::new();
+    report(&ONCE, "RowArena", || drop(stmt.query_arena([]).unwrap()));
+    b.iter(|| stmt.query_arena([]).unwrap());
+}
+
+benchmark_group!(arena_benches, bench_collect_values, bench_query_arena);
+benchmark_main!(arena_benches);
@@ -0,0 +1,31 @@
+use bencher::{benchmark_group, benchmark_main, Bencher};
+use chrono::{NaiveDate, NaiveDateTime};
+use rusqlite::Connection;
+
+fn date_time() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2022, 7, 14)
+        .unwrap()
+        .and_hms_micro_opt(12, 34, 56, 789_012)
+        .unwrap()
+}
+
+// How the timestamp used to be bound: formatted to a new `String`.
+fn bench_bind_formatted(b: &mut Bencher) {
+    let db = Connection::open_in_memory().unwrap();
+    let mut stmt = db.prepare("SELECT ?1").unwrap();
+    let dt = date_time();
+    b.iter(|| {
+        let text = dt.format("%F %T%.f").to_string();
+        stmt.raw_bind_parameter(1, text).unwrap()
+    });
+}
+
+fn bench_bind_to_sql(b: &mut Bencher) {
+    let db = Connection::open_in_memory().unwrap();
+    let mut stmt = db.prepare("SELECT ?1").unwrap();
+    let dt = date_time();
+    b.iter(|| stmt.raw_bind_parameter(1, dt).unwrap());
+}
+
+benchmark_group!(datetime_benches, bench_bind_formatted, bench_bind_to_sql);
+benchmark_main!(datetime_benches);
@@ -0,0 +1,98 @@
+use bencher::{benchmark_group, benchmark_main, Bencher};
+use rusqlite::functions::{Context, FunctionFlags, FunctionResult};
+use rusqlite::types::{Null, ValueRef};
+use rusqlite::{Connection, Result};
+
+// byte range of `substr_utf8(text, start, len)`, counting characters
+fn utf8_range(ctx: &Context<'_>) -> Result<(usize, usize)> {
+    let text = ctx.get_raw(0).as_str()?;
+    let start = text
+        .char_indices()
+        .nth(ctx.get(1)?)
+        .map_or(text.len(), |(i, _)| i);
+    let len = text[start..]
+        .char_indices()
+        .nth(ctx.get(2)?)
+        .map_or(text.len() - start, |(i, _)| i);
+    Ok((start, start + len))
+}
+
+fn bench(b: &mut Bencher, db: &Connection) {
+    let text = "ünïcödé ".repeat(64);
+    let mut stmt = db.prepare("SELECT substr_utf8(?1, 16, 256)").unwrap();
+    b.iter(|| {
+        let len: usize = stmt
+            .query_row([&text], |r| Ok(r.get_ref(0)?.as_str()?.len()))
+            .unwrap();
+        len
+    });
+}
+
+fn bench_substr_owned(b: &mut Bencher) {
+    let db = Connection::open_in_memory().unwrap();
+    db.create_scalar_function("substr_utf8", 3, FunctionFlags::SQLITE_UTF8, |ctx| {
+        let (start, end) = utf8_range(ctx)?;
+        Ok(ctx.get_raw(0).as_str()?[start..end].to_owned())
+    })
+    .unwrap();
+    bench(b, &db);
+}
+
+fn bench_substr_ref(b: &mut Bencher) {
+    let db = Connection::open_in_memory().unwrap();
+    db.create_scalar_function("substr_utf8", 3, FunctionFlags::SQLITE_UTF8, |ctx| {
+        let (start, end) = utf8_range(ctx)?;
+        ctx.set_result_ref(ValueRef::Text(&ctx.get_raw(0).as_bytes()?[start..end]));
+        Ok(FunctionResult::<Null>::Set)
+    })
+    .unwrap();
+    bench(b, &db);
+}
+
+fn bench_blob(b: &mut Bencher, db: &Connection) {
+    let blob = vec![0xa5u8; 1 << 20];
+    let mut stmt = db.prepare("SELECT coalesce_custom(NULL, ?1)").unwrap();
+    b.iter(|| {
+        let len: usize = stmt
+            .query_row([&blob], |r| Ok(r.get_ref(0)?.as_blob()?.len()))
+            .unwrap();
+        len
+    });
+}
+
+fn bench_coalesce_owned(b: &mut Bencher) {
+    let db = Connection::open_in_memory().unwrap();
+    db.create_scalar_function("coalesce_custom", 2, FunctionFlags::SQLITE_UTF8, |ctx| {
+        let idx = if ctx.get_raw(0) == ValueRef::Null {
+            1
+        } else {
+            0
+        };
+        Ok(ctx.get_raw(idx).as_blob()?.to_vec())
+    })
+    .unwrap();
+    bench_blob(b, &db);
+}
+
+fn bench_coalesce_passthrough(b: &mut Bencher) {
+    let db = Connection::open_in_memory().unwrap();
+    db.create_scalar_function("coalesce_custom", 2, FunctionFlags::SQLITE_UTF8, |ctx| {
+        let idx = if ctx.get_raw(0) == ValueRef::Null {
+            1
+        } else {
+            0
+        };
+        Ok(FunctionResult::<Null>::Passthrough(idx))
+    })
+    .unwrap();
+    bench_blob(b, &db);
+}
+
+benchmark_group!(
+    function_benches,
+    bench_substr_owned,
+    bench_substr_ref,
+    bench_coalesce_owned,
+    bench_coalesce_passthrough
+);
+benchmark_main!(function_benches);
@@ -0,0 +1,106 @@
+use bencher::{benchmark_group, benchmark_main, Bencher};
+use rusqlite::Connection;
+
+// The number of rows to read: `n` under `cargo bench`, which passes `--bench`,
+// and only a few when `cargo test --benches` runs the benchmarks in debug
+fn rows(n: u32) -> u32 {
+    if std::env::args().any(|arg| arg == "--bench") {
+        n
+    } else {
+        1000
+    }
+}
+
+fn sql() -> String {
+    format!(
+        "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < {})
+         SELECT i, i * 0.5 FROM n",
+        rows(5_000_000)
+    )
+}
+
+fn bench_get(b: &mut Bencher) {
+    let db = Connection::open_in_memory().unwrap();
+    let mut stmt = db.prepare(&sql()).unwrap();
+    b.iter(|| {
+        let mut rows = stmt.query([]).unwrap();
+        let mut sum = 0.0;
+        while let Some(row) = rows.next().unwrap() {
+            sum += row.get::<_, i64>(0).unwrap() as f64 + row.get::<_, f64>(1).unwrap();
+        }
+        sum
+    });
+}
+
+fn bench_get_i64(b: &mut Bencher) {
+    let db = Connection::open_in_memory().unwrap();
+    let mut stmt = db.prepare(&sql()).unwrap();
+    b.iter(|| {
+        let mut rows = stmt.query([]).unwrap();
+        let mut sum = 0.0;
+        while let Some(row) = rows.next().unwrap() {
+            sum += row.get_i64(0).unwrap() as f64 + row.get_f64(1).unwrap();
+        }
+        sum
+    });
+}
+
+fn bench_get_i64_unchecked(b: &mut Bencher) {
+    let db = Connection::open_in_memory().unwrap();
+    let mut stmt = db.prepare(&sql()).unwrap();
+    b.iter(|| {
+        let mut rows = stmt.query([]).unwrap();
+        let mut sum = 0.0;
+        while let Some(row) = rows.next().unwrap() {
+            sum += row.get_i64_unchecked(0) as f64 + row.get_f64_unchecked(1);
+        }
+        sum
+    });
+}
+
+// 32 columns, c0 to c31, looked up by the name of the last one
+fn wide_sql() -> String {
+    let columns: Vec<String> = (0..32).map(|i| format!("i + {i} AS c{i}")).collect();
+    format!(
+        "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < {})
+         SELECT {} FROM n",
+        rows(100_000),
+        columns.join(", ")
+    )
+}
+
+fn bench_get_by_name(b: &mut Bencher) {
+    let db = Connection::open_in_memory().unwrap();
+    let mut stmt = db.prepare(&wide_sql()).unwrap();
+    b.iter(|| {
+        let mut rows = stmt.query([]).unwrap();
+        let mut sum = 0;
+        while let Some(row) = rows.next().unwrap() {
+            sum += row.get::<_, i64>("c31").unwrap();
+        }
+        sum
+    });
+}
+
+fn bench_get_by_index(b: &mut Bencher) {
+    let db = Connection::open_in_memory().unwrap();
+    let mut stmt = db.prepare(&wide_sql()).unwrap();
+    b.iter(|| {
+        let mut rows = stmt.query([]).unwrap();
+        let mut sum = 0;
+        while let Some(row) = rows.next().unwrap() {
+            sum += row.get::<_, i64>(31).unwrap();
+        }
+        sum
+    });
+}
+
+benchmark_group!(
+    row_benches,
+    bench_get,
+    bench_get_i64,
+    bench_get_i64_unchecked,
+    bench_get_by_name,
+    bench_get_by_index
+);
+benchmark_main!(row_benches);
@@ -15,7 +15,7 @@ fn win_target() -> bool {
 /// See [`win_target`]
 #[cfg(any(feature = "bundled", feature = "bundled-windows"))]
 fn android_target() -> bool {
-    env::var("CARGO_CFG_TARGET_OS").map_or(false, |v| v == "android")
+    matches!(env::var("CARGO_CFG_TARGET_OS"), Ok(v) if v == "android")
 }
 
 /// Tells whether a given compiler will be used `compiler_name` is compared to
@@ -23,7 +23,7 @@ fn android_target() -> bool {
 ///
 /// See [`win_target`]
 fn is_compiler(compiler_name: &str) -> bool {
-    env::var("CARGO_CFG_TARGET_ENV").map_or(false, |v| v == compiler_name)
+    matches!(env::var("CARGO_CFG_TARGET_ENV"), Ok(v) if v == compiler_name)
 }
 
 fn main() {
@@ -111,6 +111,7 @@ mod build_bundled {
             .flag("-DSQLITE_DEFAULT_FOREIGN_KEYS=1")
             .flag("-DSQLITE_ENABLE_API_ARMOR")
             .flag("-DSQLITE_ENABLE_COLUMN_METADATA")
+            .flag("-DSQLITE_ENABLE_DBPAGE_VTAB")
             .flag("-DSQLITE_ENABLE_DBSTAT_VTAB")
             .flag("-DSQLITE_ENABLE_FTS3")
             .flag("-DSQLITE_ENABLE_FTS3_PARENTHESIS")
@@ -259,6 +260,15 @@ mod build_bundled {
         if cfg!(feature = "session") {
             cfg.flag("-DSQLITE_ENABLE_SESSION");
         }
+        if cfg!(feature = "normalize") {
+            cfg.flag("-DSQLITE_ENABLE_NORMALIZE");
+        }
+        if cfg!(feature = "scanstatus") {
+            cfg.flag("-DSQLITE_ENABLE_STMT_SCANSTATUS");
+        }
+        if cfg!(feature = "snapshot") {
+            cfg.flag("-DSQLITE_ENABLE_SNAPSHOT");
+        }
 
         if let Ok(limit) = env::var("SQLITE_MAX_VARIABLE_NUMBER") {
             cfg.flag(&format!("-DSQLITE_MAX_VARIABLE_NUMBER={limit}"));
@@ -22,7 +22,9 @@ pub fn SQLITE_STATIC() -> sqlite3_destructor_type {
 
 #[must_use]
 pub fn SQLITE_TRANSIENT() -> sqlite3_destructor_type {
-    Some(unsafe { mem::transmute(-1_isize) })
+    Some(unsafe {
+        mem::transmute::<isize, unsafe extern "C" fn(*mut std::os::raw::c_void)>(-1_isize)
+    })
 }
 
 #[allow(clippy::all)]
@@ -31,6 +33,13 @@ mod bindings {
 }
 pub use bindings::*;
 
+// sqlite3.h only declares it when SQLITE_ENABLE_NORMALIZE is defined, which
+// the bindings are generated without.
+#[cfg(feature = "normalize")]
+extern "C" {
+    pub fn sqlite3_normalized_sql(pStmt: *mut sqlite3_stmt) -> *const std::os::raw::c_char;
+}
+
 pub type sqlite3_index_constraint = sqlite3_index_info_sqlite3_index_constraint;
 pub type sqlite3_index_constraint_usage = sqlite3_index_info_sqlite3_index_constraint_usage;
 
@@ -0,0 +1,315 @@
+//! Derive macros for [rusqlite](https://docs.rs/rusqlite).
+//!
+//! These are re-exported by `rusqlite` when its `derive` feature is enabled,
+//! so most users should not depend on this crate directly.
+//!
+//! * `ToSqlText` / `FromSqlText` map the variants of a fieldless enum to
+//!   their names, stored as `TEXT`. A variant can be stored under another
+//!   name with `#[sql(rename = "...")]`.
+//! * `ToSqlRepr` / `FromSqlRepr` map the variants of a fieldless enum to
+//!   their discriminants, stored as `INTEGER`.
+//!
+//! * `FromRow` maps the columns of a result row to the fields of a struct by
+//!   name. A field can be read from another column with
+//!   `#[column(rename = "...")]`, and a nested struct implementing `FromRow`
+//!   can be read from the same row with `#[column(flatten)]`.
+//!
+//! Unknown stored values are reported on read as
+//! `FromSqlError::Other`, naming the enum and the unexpected value.
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, Ident, Lit, Meta, NestedMeta};
+
+/// Derive `ToSql` for a fieldless enum, storing each variant as `TEXT`.
+#[proc_macro_derive(ToSqlText, attributes(sql))]
+pub fn derive_to_sql_text(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_to_sql_text(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Derive `FromSql` for a fieldless enum, reading each variant from `TEXT`.
+#[proc_macro_derive(FromSqlText, attributes(sql))]
+pub fn derive_from_sql_text(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_from_sql_text(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Derive `ToSql` for a fieldless enum, storing each variant's discriminant
+/// as `INTEGER`.
+#[proc_macro_derive(ToSqlRepr)]
+pub fn derive_to_sql_repr(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_to_sql_repr(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Derive `FromSql` for a fieldless enum, reading each variant from its
+/// discriminant stored as `INTEGER`.
+#[proc_macro_derive(FromSqlRepr)]
+pub fn derive_from_sql_repr(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_from_sql_repr(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Derive `FromRow` for a struct, reading each named field from the column
+/// of the same name and each tuple field from the column at its position.
+#[proc_macro_derive(FromRow, attributes(column))]
+pub fn derive_from_row(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_from_row(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+struct Variant {
+    ident: Ident,
+    sql_name: String,
+}
+
+fn unit_variants(input: &DeriveInput, derive: &str) -> syn::Result<Vec<Variant>> {
+    let data = match input.data {
+        Data::Enum(ref data) => data,
+        _ => {
+            return Err(Error::new(
+                Span::call_site(),
+                format!("`{derive}` can only be derived for enums"),
+            ))
+        }
+    };
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            format!("`{derive}` cannot be derived for generic enums"),
+        ));
+    }
+    let mut variants = Vec::with_capacity(data.variants.len());
+    for v in &data.variants {
+        if !matches!(v.fields, Fields::Unit) {
+            return Err(Error::new_spanned(
+                v,
+                format!("`{derive}` requires all variants to be fieldless"),
+            ));
+        }
+        let mut sql_name = v.ident.to_string();
+        for attr in v.attrs.iter().filter(|a| a.path.is_ident("sql")) {
+            if let Some(rename) = parse_rename(attr)? {
+                sql_name = rename;
+            }
+        }
+        variants.push(Variant {
+            ident: v.ident.clone(),
+            sql_name,
+        });
+    }
+    Ok(variants)
+}
+
+// Parses `#[sql(rename = "...")]`.
+fn parse_rename(attr: &syn::Attribute) -> syn::Result<Option<String>> {
+    Ok(parse_attr(attr, "sql")?.rename)
+}
+
+#[derive(Default)]
+struct AttrArgs {
+    rename: Option<String>,
+    flatten: bool,
+}
+
+// Parses `#[<name>(rename = "...")]` and `#[<name>(flatten)]`.
+fn parse_attr(attr: &syn::Attribute, name: &str) -> syn::Result<AttrArgs> {
+    let list = match attr.parse_meta()? {
+        Meta::List(list) => list,
+        meta => {
+            return Err(Error::new_spanned(
+                meta,
+                format!("expected `#[{name}(rename = \"...\")]`"),
+            ))
+        }
+    };
+    let mut args = AttrArgs::default();
+    for nested in list.nested {
+        match nested {
+            NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.path.is_ident("rename") => {
+                match nv.lit {
+                    Lit::Str(ref s) => args.rename = Some(s.value()),
+                    ref lit => return Err(Error::new_spanned(lit, "expected a string literal")),
+                }
+            }
+            NestedMeta::Meta(Meta::Path(ref path))
+                if name == "column" && path.is_ident("flatten") =>
+            {
+                args.flatten = true;
+            }
+            other => {
+                return Err(Error::new_spanned(
+                    other,
+                    format!("unknown `{name}` attribute"),
+                ))
+            }
+        }
+    }
+    Ok(args)
+}
+
+fn expand_to_sql_text(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let variants = unit_variants(input, "ToSqlText")?;
+    let arms = variants.iter().map(|v| {
+        let ident = &v.ident;
+        let sql_name = &v.sql_name;
+        quote!(#name::#ident => #sql_name,)
+    });
+    Ok(quote! {
+        impl ::rusqlite::types::ToSql for #name {
+            #[inline]
+            fn to_sql(&self) -> ::rusqlite::types::ToSqlResult<'_> {
+                let s: &'static str = match *self {
+                    #(#arms)*
+                };
+                ::std::result::Result::Ok(::rusqlite::types::ToSqlOutput::from(s))
+            }
+        }
+    })
+}
+
+fn expand_from_sql_text(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let enum_name = name.to_string();
+    let variants = unit_variants(input, "FromSqlText")?;
+    let arms = variants.iter().map(|v| {
+        let ident = &v.ident;
+        let sql_name = &v.sql_name;
+        quote!(#sql_name => ::std::result::Result::Ok(#name::#ident),)
+    });
+    Ok(quote! {
+        impl ::rusqlite::types::FromSql for #name {
+            #[inline]
+            fn column_result(
+                value: ::rusqlite::types::ValueRef<'_>,
+            ) -> ::rusqlite::types::FromSqlResult<Self> {
+                match value.as_str()? {
+                    #(#arms)*
+                    other => ::std::result::Result::Err(::rusqlite::types::FromSqlError::Other(
+                        ::std::format!("unknown {} value: {:?}", #enum_name, other).into(),
+                    )),
+                }
+            }
+        }
+    })
+}
+
+fn expand_to_sql_repr(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let variants = unit_variants(input, "ToSqlRepr")?;
+    let arms = variants.iter().map(|v| {
+        let ident = &v.ident;
+        quote!(#name::#ident => #name::#ident as i64,)
+    });
+    Ok(quote! {
+        impl ::rusqlite::types::ToSql for #name {
+            #[inline]
+            fn to_sql(&self) -> ::rusqlite::types::ToSqlResult<'_> {
+                let i: i64 = match *self {
+                    #(#arms)*
+                };
+                ::std::result::Result::Ok(::rusqlite::types::ToSqlOutput::from(i))
+            }
+        }
+    })
+}
+
+fn expand_from_sql_repr(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let enum_name = name.to_string();
+    let variants = unit_variants(input, "FromSqlRepr")?;
+    let checks = variants.iter().map(|v| {
+        let ident = &v.ident;
+        quote! {
+            if i == #name::#ident as i64 {
+                return ::std::result::Result::Ok(#name::#ident);
+            }
+        }
+    });
+    Ok(quote! {
+        impl ::rusqlite::types::FromSql for #name {
+            #[inline]
+            fn column_result(
+                value: ::rusqlite::types::ValueRef<'_>,
+            ) -> ::rusqlite::types::FromSqlResult<Self> {
+                let i = value.as_i64()?;
+                #(#checks)*
+                ::std::result::Result::Err(::rusqlite::types::FromSqlError::Other(
+                    ::std::format!("unknown {} value: {}", #enum_name, i).into(),
+                ))
+            }
+        }
+    })
+}
+
+fn expand_from_row(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let data = match input.data {
+        Data::Struct(ref data) => data,
+        _ => {
+            return Err(Error::new(
+                Span::call_site(),
+                "`FromRow` can only be derived for structs",
+            ))
+        }
+    };
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let mut values = Vec::with_capacity(data.fields.len());
+    for (i, field) in data.fields.iter().enumerate() {
+        let mut args = AttrArgs::default();
+        for attr in field.attrs.iter().filter(|a| a.path.is_ident("column")) {
+            let parsed = parse_attr(attr, "column")?;
+            args.rename = parsed.rename.or(args.rename);
+            args.flatten |= parsed.flatten;
+        }
+        let ty = &field.ty;
+        let value = if args.flatten {
+            if args.rename.is_some() {
+                return Err(Error::new_spanned(
+                    field,
+                    "`rename` and `flatten` cannot be used together",
+                ));
+            }
+            quote!(<#ty as ::rusqlite::FromRow>::from_row(row)?)
+        } else {
+            match (field.ident.as_ref(), args.rename) {
+                (_, Some(column)) => quote!(row.get::<_, #ty>(#column)?),
+                (Some(ident), None) => {
+                    let column = ident.to_string();
+                    let column = column.strip_prefix("r#").unwrap_or(&column);
+                    quote!(row.get::<_, #ty>(#column)?)
+                }
+                (None, None) => quote!(row.get::<_, #ty>(#i)?),
+            }
+        };
+        values.push(value);
+    }
+    let body = match data.fields {
+        Fields::Named(_) => {
+            let idents = data.fields.iter().map(|f| &f.ident);
+            quote!(#name { #(#idents: #values,)* })
+        }
+        Fields::Unnamed(_) => quote!(#name(#(#values,)*)),
+        Fields::Unit => quote!(#name),
+    };
+    Ok(quote! {
+        impl #impl_generics ::rusqlite::FromRow for #name #ty_generics #where_clause {
+            #[inline]
+            fn from_row(row: &::rusqlite::Row<'_>) -> ::rusqlite::Result<Self> {
+                ::std::result::Result::Ok(#body)
+            }
+        }
+    })
+}
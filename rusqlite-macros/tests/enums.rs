@@ -0,0 +1,106 @@
+use rusqlite::types::FromSqlError;
+use rusqlite::{Connection, Error, Result};
+use rusqlite_macros::{FromSqlRepr, FromSqlText, ToSqlRepr, ToSqlText};
+
+#[derive(Debug, PartialEq, ToSqlText, FromSqlText)]
+enum Status {
+    Active,
+    #[sql(rename = "on_hold")]
+    OnHold,
+    Closed,
+}
+
+#[derive(Debug, PartialEq, ToSqlRepr, FromSqlRepr)]
+#[repr(i64)]
+enum Priority {
+    Low = 1,
+    Normal = 5,
+    High = 10,
+}
+
+fn checked_memory_handle() -> Result<Connection> {
+    let db = Connection::open_in_memory()?;
+    db.execute_batch("CREATE TABLE foo (status TEXT, priority INTEGER)")?;
+    Ok(db)
+}
+
+#[test]
+fn test_text_round_trip() -> Result<()> {
+    let db = checked_memory_handle()?;
+    for status in [Status::Active, Status::OnHold, Status::Closed] {
+        db.execute("INSERT INTO foo (status) VALUES (?1)", [&status])?;
+        let stored: Status = db.query_row(
+            "SELECT status FROM foo WHERE rowid = last_insert_rowid()",
+            [],
+            |r| r.get(0),
+        )?;
+        assert_eq!(status, stored);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_text_rename() -> Result<()> {
+    let db = checked_memory_handle()?;
+    db.execute("INSERT INTO foo (status) VALUES (?1)", [Status::OnHold])?;
+    let raw: String = db.query_row("SELECT status FROM foo", [], |r| r.get(0))?;
+    assert_eq!("on_hold", raw);
+    let raw: String = db.query_row("SELECT ?1", [Status::Active], |r| r.get(0))?;
+    assert_eq!("Active", raw);
+    Ok(())
+}
+
+#[test]
+fn test_repr_round_trip() -> Result<()> {
+    let db = checked_memory_handle()?;
+    for priority in [Priority::Low, Priority::Normal, Priority::High] {
+        db.execute("INSERT INTO foo (priority) VALUES (?1)", [&priority])?;
+        let (raw, stored): (i64, Priority) = db.query_row(
+            "SELECT priority, priority FROM foo WHERE rowid = last_insert_rowid()",
+            [],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )?;
+        assert_eq!(priority as i64, raw);
+        assert_eq!(raw, stored as i64);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_unknown_values() -> Result<()> {
+    let db = checked_memory_handle()?;
+    db.execute_batch("INSERT INTO foo VALUES ('Archived', 7)")?;
+
+    let err = db
+        .query_row("SELECT status FROM foo", [], |r| r.get::<_, Status>(0))
+        .unwrap_err();
+    match err {
+        Error::FromSqlConversionFailure(0, _, ref e) => {
+            assert_eq!(r#"unknown Status value: "Archived""#, e.to_string())
+        }
+        _ => panic!("unexpected error: {}", err),
+    }
+
+    let err = db
+        .query_row("SELECT priority FROM foo", [], |r| r.get::<_, Priority>(0))
+        .unwrap_err();
+    match err {
+        Error::FromSqlConversionFailure(0, _, ref e) => {
+            assert_eq!("unknown Priority value: 7", e.to_string())
+        }
+        _ => panic!("unexpected error: {}", err),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_wrong_storage_class() -> Result<()> {
+    let db = checked_memory_handle()?;
+    let err = db
+        .query_row("SELECT 1", [], |r| r.get::<_, Status>(0))
+        .unwrap_err();
+    assert!(matches!(err, Error::InvalidColumnType(0, ..)), "{}", err);
+    let err = <Priority as rusqlite::types::FromSql>::column_result("High".into()).unwrap_err();
+    assert_eq!(FromSqlError::InvalidType, err);
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use rusqlite::{Connection, Error, FromRow, Result};
+
+#[derive(Debug, PartialEq, FromRow)]
+struct Address {
+    city: String,
+    zip: Option<String>,
+}
+
+#[derive(Debug, PartialEq, FromRow)]
+struct Person {
+    id: i64,
+    #[column(rename = "full_name")]
+    name: String,
+    #[column(flatten)]
+    address: Address,
+}
+
+#[derive(Debug, PartialEq, FromRow)]
+struct Pair(i64, String);
+
+fn checked_memory_handle() -> Result<Connection> {
+    let db = Connection::open_in_memory()?;
+    db.execute_batch(
+        "CREATE TABLE people (id INTEGER, full_name TEXT, city TEXT, zip TEXT);
+         INSERT INTO people VALUES (1, 'Alice', 'Paris', '75001');
+         INSERT INTO people VALUES (2, 'Bob', 'Berlin', NULL);",
+    )?;
+    Ok(db)
+}
+
+#[test]
+fn test_columns_mapped_by_name() -> Result<()> {
+    let db = checked_memory_handle()?;
+    // Columns are selected in a different order than the fields are declared.
+    let mut stmt = db.prepare("SELECT zip, city, full_name, id FROM people ORDER BY id")?;
+    let people = stmt
+        .query_as::<Person, _>([])?
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(
+        people,
+        vec![
+            Person {
+                id: 1,
+                name: "Alice".to_owned(),
+                address: Address {
+                    city: "Paris".to_owned(),
+                    zip: Some("75001".to_owned()),
+                },
+            },
+            Person {
+                id: 2,
+                name: "Bob".to_owned(),
+                address: Address {
+                    city: "Berlin".to_owned(),
+                    zip: None,
+                },
+            },
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_tuple_struct() -> Result<()> {
+    let db = checked_memory_handle()?;
+    let pair: Pair = db.query_row_as("SELECT id, full_name FROM people WHERE id = 2", [])?;
+    assert_eq!(pair, Pair(2, "Bob".to_owned()));
+    Ok(())
+}
+
+#[test]
+fn test_missing_column() -> Result<()> {
+    let db = checked_memory_handle()?;
+    let err = db
+        .query_row_as::<Person, _>("SELECT id, full_name, city FROM people", [])
+        .unwrap_err();
+    assert_eq!(err, Error::InvalidColumnName("zip".to_owned()));
+
+    let row = db.query_row("SELECT id, city, zip FROM people", [], |row| {
+        Ok(Address::from_row(row))
+    })?;
+    assert!(row.is_ok());
+    Ok(())
+}
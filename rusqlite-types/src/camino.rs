@@ -0,0 +1,33 @@
+//! [`ToSql`] and [`FromSql`] implementation for [`camino::Utf8PathBuf`].
+use crate::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ToSqlResult, ValueRef};
+use camino::{Utf8Path, Utf8PathBuf};
+
+/// Serialize `Utf8Path` to text.
+impl ToSql for Utf8Path {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+/// Serialize `Utf8PathBuf` to text.
+impl ToSql for Utf8PathBuf {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+/// Deserialize text, or a blob holding valid UTF-8, to `Utf8PathBuf`.
+impl FromSql for Utf8PathBuf {
+    #[inline]
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value {
+            ValueRef::Text(_) => value.as_str().map(Utf8PathBuf::from),
+            ValueRef::Blob(b) => std::str::from_utf8(b)
+                .map(Utf8PathBuf::from)
+                .map_err(|_| FromSqlError::InvalidType),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
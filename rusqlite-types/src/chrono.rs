@@ -0,0 +1,225 @@
+//! Convert most of the [Time Strings](http://sqlite.org/lang_datefunc.html) to chrono types.
+
+use std::fmt::{self, Write};
+
+use chrono::{
+    DateTime, Datelike, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone,
+    Timelike, Utc,
+};
+
+use crate::{
+    FromSql, FromSqlError, FromSqlResult, InlineText, ToSql, ToSqlOutput, ToSqlResult, ValueRef,
+};
+
+// chrono's `format` allocates, so the formats used below are written by hand,
+// with the same output. The longest, like
+// "+262142-12-31 23:59:60.999999999+23:59", fits in an `InlineText`.
+
+/// `%F`: `YYYY-MM-DD`, with a sign and more digits for years out of
+/// 0..=9999.
+fn write_date(w: &mut InlineText, d: &NaiveDate) -> fmt::Result {
+    let year = d.year();
+    if (0..=9999).contains(&year) {
+        write!(w, "{:04}", year)?;
+    } else {
+        write!(w, "{:+05}", year)?;
+    }
+    write!(w, "-{:02}-{:02}", d.month(), d.day())
+}
+
+/// `%T%.f`: `HH:MM:SS` followed by the non-zero fraction of second, with 3,
+/// 6 or 9 digits.
+fn write_time(w: &mut InlineText, t: &NaiveTime) -> fmt::Result {
+    // a leap second is stored as an extra second of nanoseconds
+    let (second, nano) = (
+        t.second() + t.nanosecond() / 1_000_000_000,
+        t.nanosecond() % 1_000_000_000,
+    );
+    write!(w, "{:02}:{:02}:{:02}", t.hour(), t.minute(), second)?;
+    if nano == 0 {
+        Ok(())
+    } else if nano % 1_000_000 == 0 {
+        write!(w, ".{:03}", nano / 1_000_000)
+    } else if nano % 1_000 == 0 {
+        write!(w, ".{:06}", nano / 1_000)
+    } else {
+        write!(w, ".{:09}", nano)
+    }
+}
+
+/// `%:z`: `+HH:MM`, rounded to the minute.
+fn write_offset(w: &mut InlineText, offset: FixedOffset) -> fmt::Result {
+    let seconds = offset.local_minus_utc();
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let minutes = (seconds.abs() + 30) / 60;
+    write!(w, "{}{:02}:{:02}", sign, minutes / 60, minutes % 60)
+}
+
+fn write_date_time(w: &mut InlineText, dt: &NaiveDateTime) -> fmt::Result {
+    write_date(w, &dt.date())?;
+    w.write_char(' ')?;
+    write_time(w, &dt.time())
+}
+
+fn inline<F>(f: F) -> ToSqlResult<'static>
+where
+    F: FnOnce(&mut InlineText) -> fmt::Result,
+{
+    let mut text = InlineText::new();
+    f(&mut text)?;
+    Ok(ToSqlOutput::Inline(text))
+}
+
+/// ISO 8601 calendar date without timezone => "YYYY-MM-DD"
+impl ToSql for NaiveDate {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        inline(|w| write_date(w, self))
+    }
+}
+
+/// "YYYY-MM-DD" => ISO 8601 calendar date without timezone.
+impl FromSql for NaiveDate {
+    #[inline]
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value
+            .as_str()
+            .and_then(|s| match NaiveDate::parse_from_str(s, "%F") {
+                Ok(dt) => Ok(dt),
+                Err(err) => Err(FromSqlError::Other(Box::new(err))),
+            })
+    }
+}
+
+/// ISO 8601 time without timezone => "HH:MM:SS.SSS"
+impl ToSql for NaiveTime {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        inline(|w| write_time(w, self))
+    }
+}
+
+/// "HH:MM"/"HH:MM:SS"/"HH:MM:SS.SSS" => ISO 8601 time without timezone.
+impl FromSql for NaiveTime {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str().and_then(|s| {
+            let fmt = match s.len() {
+                5 => "%H:%M",
+                8 => "%T",
+                _ => "%T%.f",
+            };
+            match NaiveTime::parse_from_str(s, fmt) {
+                Ok(dt) => Ok(dt),
+                Err(err) => Err(FromSqlError::Other(Box::new(err))),
+            }
+        })
+    }
+}
+
+/// ISO 8601 combined date and time without timezone =>
+/// "YYYY-MM-DD HH:MM:SS.SSS"
+impl ToSql for NaiveDateTime {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        inline(|w| write_date_time(w, self))
+    }
+}
+
+/// "YYYY-MM-DD HH:MM:SS"/"YYYY-MM-DD HH:MM:SS.SSS" => ISO 8601 combined date
+/// and time without timezone. ("YYYY-MM-DDTHH:MM:SS"/"YYYY-MM-DDTHH:MM:SS.SSS"
+/// also supported)
+impl FromSql for NaiveDateTime {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str().and_then(|s| {
+            let fmt = if s.len() >= 11 && s.as_bytes()[10] == b'T' {
+                "%FT%T%.f"
+            } else {
+                "%F %T%.f"
+            };
+
+            match NaiveDateTime::parse_from_str(s, fmt) {
+                Ok(dt) => Ok(dt),
+                Err(err) => Err(FromSqlError::Other(Box::new(err))),
+            }
+        })
+    }
+}
+
+/// UTC time => UTC RFC3339 timestamp
+/// ("YYYY-MM-DD HH:MM:SS.SSS+00:00").
+impl ToSql for DateTime<Utc> {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        inline(|w| {
+            write_date_time(w, &self.naive_utc())?;
+            write_offset(w, self.offset().fix())
+        })
+    }
+}
+
+/// Local time => UTC RFC3339 timestamp
+/// ("YYYY-MM-DD HH:MM:SS.SSS+00:00").
+impl ToSql for DateTime<Local> {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        inline(|w| {
+            write_date_time(w, &self.naive_utc())?;
+            w.write_str("+00:00")
+        })
+    }
+}
+
+/// Date and time with time zone => RFC3339 timestamp
+/// ("YYYY-MM-DD HH:MM:SS.SSS[+-]HH:MM").
+impl ToSql for DateTime<FixedOffset> {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        inline(|w| {
+            write_date_time(w, &self.naive_local())?;
+            write_offset(w, *self.offset())
+        })
+    }
+}
+
+/// RFC3339 ("YYYY-MM-DD HH:MM:SS.SSS[+-]HH:MM") into `DateTime<Utc>`.
+impl FromSql for DateTime<Utc> {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        {
+            // Try to parse value as rfc3339 first.
+            let s = value.as_str()?;
+
+            let fmt = if s.len() >= 11 && s.as_bytes()[10] == b'T' {
+                "%FT%T%.f%#z"
+            } else {
+                "%F %T%.f%#z"
+            };
+
+            if let Ok(dt) = DateTime::parse_from_str(s, fmt) {
+                return Ok(dt.with_timezone(&Utc));
+            }
+        }
+
+        // Couldn't parse as rfc3339 - fall back to NaiveDateTime.
+        NaiveDateTime::column_result(value).map(|dt| Utc.from_utc_datetime(&dt))
+    }
+}
+
+/// RFC3339 ("YYYY-MM-DD HH:MM:SS.SSS[+-]HH:MM") into `DateTime<Local>`.
+impl FromSql for DateTime<Local> {
+    #[inline]
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let utc_dt = DateTime::<Utc>::column_result(value)?;
+        Ok(utc_dt.with_timezone(&Local))
+    }
+}
+
+/// RFC3339 ("YYYY-MM-DD HH:MM:SS.SSS[+-]HH:MM") into `DateTime<FixedOffset>`.
+impl FromSql for DateTime<FixedOffset> {
+    #[inline]
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let s = String::column_result(value)?;
+        Self::parse_from_rfc3339(s.as_str())
+            .or_else(|_| Self::parse_from_str(s.as_str(), "%F %T%.f%:z"))
+            .map_err(|e| FromSqlError::Other(Box::new(e)))
+    }
+}
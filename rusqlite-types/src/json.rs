@@ -0,0 +1,532 @@
+//! [`FromSql`] and [`ToSql`] for any serde type stored as JSON.
+
+use std::convert::TryFrom;
+use std::error;
+use std::fmt;
+
+use serde::de::value::BorrowedStrDeserializer;
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, Deserializer, EnumAccess, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use serde::Serialize;
+use serde_json::{Map, Number, Value};
+
+use crate::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ToSqlResult, ValueRef};
+
+/// A value stored as JSON, e.g. a `Vec<T>` read from the result of
+/// `json_group_array`, or a `HashMap<String, T>` from `json_group_object`.
+///
+/// Columns are read from JSON text, or from a BLOB in the
+/// [JSONB](https://sqlite.org/jsonb.html) format of SQLite 3.45 and later,
+/// falling back to JSON text for a BLOB which is not valid JSONB. Values are
+/// written as JSON text.
+///
+/// If `NULL_AS_DEFAULT` is `true`, SQL NULL is read as `T::default()`, e.g.
+/// an empty collection, as `json_group_array` never returns NULL but a
+/// `LEFT JOIN` or a scalar subquery may. Otherwise it is read as JSON `null`,
+/// which is an error unless `T` accepts it, like `Option`.
+///
+/// If the JSON does not match `T`, the error is a [`JsonError`] holding the
+/// [JSON pointer](https://www.rfc-editor.org/rfc/rfc6901) of the element
+/// which failed.
+///
+/// ```rust
+/// use rusqlite::{Connection, Result};
+/// use rusqlite_types::Json;
+/// use std::collections::HashMap;
+///
+/// fn stock(conn: &Connection) -> Result<HashMap<String, i64>> {
+///     let Json(stock): Json<_> =
+///         conn.query_row("SELECT json_group_object(name, qty) FROM items", [], |row| {
+///             row.get(0)
+///         })?;
+///     Ok(stock)
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Json<T, const NULL_AS_DEFAULT: bool = false>(pub T);
+
+impl<T: DeserializeOwned> FromSql for Json<T, false> {
+    #[inline]
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        from_value(&json_value(value)?).map(Json)
+    }
+}
+
+impl<T: DeserializeOwned + Default> FromSql for Json<T, true> {
+    #[inline]
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value {
+            ValueRef::Null => Ok(Json(T::default())),
+            _ => from_value(&json_value(value)?).map(Json),
+        }
+    }
+}
+
+impl<T: Serialize, const NULL_AS_DEFAULT: bool> ToSql for Json<T, NULL_AS_DEFAULT> {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        serde_json::to_string(&self.0)
+            .map(ToSqlOutput::from)
+            .map_err(Into::into)
+    }
+}
+
+fn json_value(value: ValueRef<'_>) -> FromSqlResult<Value> {
+    match value {
+        ValueRef::Blob(b) => match jsonb_value(b) {
+            Some(value) => Ok(value),
+            None => Value::column_result(value),
+        },
+        _ => Value::column_result(value),
+    }
+}
+
+fn from_value<T: DeserializeOwned>(value: &Value) -> FromSqlResult<T> {
+    T::deserialize(Tracked(value)).map_err(|err| FromSqlError::Other(Box::new(err)))
+}
+
+/// The error of deserializing a [`Json`] value, with the JSON pointer of the
+/// element which failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonError {
+    // path segments, innermost first
+    path: Vec<String>,
+    msg: String,
+}
+
+impl JsonError {
+    /// Returns the [JSON pointer](https://www.rfc-editor.org/rfc/rfc6901)
+    /// of the element which failed, e.g. `/2/name`, or an empty string for
+    /// the whole value.
+    #[must_use]
+    pub fn pointer(&self) -> String {
+        let mut pointer = String::new();
+        for segment in self.path.iter().rev() {
+            pointer.push('/');
+            pointer.push_str(&segment.replace('~', "~0").replace('/', "~1"));
+        }
+        pointer
+    }
+
+    /// Returns the error message, without the pointer.
+    #[must_use]
+    pub fn message(&self) -> &str {
+        &self.msg
+    }
+
+    fn within(mut self, segment: impl fmt::Display) -> JsonError {
+        self.path.push(segment.to_string());
+        self
+    }
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            self.msg.fmt(f)
+        } else {
+            write!(f, "{} at {}", self.msg, self.pointer())
+        }
+    }
+}
+
+impl error::Error for JsonError {}
+
+impl de::Error for JsonError {
+    fn custom<M: fmt::Display>(msg: M) -> JsonError {
+        JsonError {
+            path: Vec::new(),
+            msg: msg.to_string(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for JsonError {
+    fn from(err: serde_json::Error) -> JsonError {
+        de::Error::custom(err)
+    }
+}
+
+// Deserializes a JSON value, recording the path to the element which failed.
+// Arrays, objects and enums are walked here, anything else is left to
+// serde_json.
+struct Tracked<'de>(&'de Value);
+
+impl<'de> Deserializer<'de> for Tracked<'de> {
+    type Error = JsonError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, JsonError> {
+        match self.0 {
+            Value::Array(array) => {
+                let mut seq = Elements {
+                    iter: array.iter().enumerate(),
+                };
+                let value = visitor.visit_seq(&mut seq)?;
+                if seq.iter.len() == 0 {
+                    Ok(value)
+                } else {
+                    Err(de::Error::invalid_length(array.len(), &"fewer elements"))
+                }
+            }
+            Value::Object(object) => {
+                let mut map = Members {
+                    iter: object.iter(),
+                    value: None,
+                };
+                let value = visitor.visit_map(&mut map)?;
+                if map.iter.len() == 0 {
+                    Ok(value)
+                } else {
+                    Err(de::Error::invalid_length(object.len(), &"fewer members"))
+                }
+            }
+            value => value.deserialize_any(visitor).map_err(JsonError::from),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, JsonError> {
+        match self.0 {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, JsonError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, JsonError> {
+        match self.0 {
+            Value::Object(object) if object.len() == 1 => {
+                let (variant, value) = object.iter().next().unwrap();
+                visitor
+                    .visit_enum(Variant { variant, value })
+                    .map_err(|err| err.within(variant))
+            }
+            value => value
+                .deserialize_enum(name, variants, visitor)
+                .map_err(JsonError::from),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, JsonError> {
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier
+    }
+}
+
+struct Elements<'de> {
+    iter: std::iter::Enumerate<std::slice::Iter<'de, Value>>,
+}
+
+impl<'de> SeqAccess<'de> for Elements<'de> {
+    type Error = JsonError;
+
+    fn next_element_seed<S: DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<Option<S::Value>, JsonError> {
+        match self.iter.next() {
+            Some((i, value)) => seed
+                .deserialize(Tracked(value))
+                .map(Some)
+                .map_err(|err| err.within(i)),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct Members<'de> {
+    iter: serde_json::map::Iter<'de>,
+    value: Option<(&'de str, &'de Value)>,
+}
+
+impl<'de> MapAccess<'de> for Members<'de> {
+    type Error = JsonError;
+
+    fn next_key_seed<S: DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<Option<S::Value>, JsonError> {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.value = Some((key, value));
+                seed.deserialize(BorrowedStrDeserializer::<JsonError>::new(key.as_str()))
+                    .map(Some)
+                    .map_err(|err| err.within(key))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> Result<S::Value, JsonError> {
+        match self.value.take() {
+            Some((key, value)) => seed
+                .deserialize(Tracked(value))
+                .map_err(|err| err.within(key)),
+            None => Err(de::Error::custom("value is missing")),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+// The variant of an enum in its externally tagged form, `{"variant": value}`.
+struct Variant<'de> {
+    variant: &'de String,
+    value: &'de Value,
+}
+
+impl<'de> EnumAccess<'de> for Variant<'de> {
+    type Error = JsonError;
+    type Variant = Tracked<'de>;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(
+        self,
+        seed: S,
+    ) -> Result<(S::Value, Tracked<'de>), JsonError> {
+        seed.deserialize(BorrowedStrDeserializer::<JsonError>::new(
+            self.variant.as_str(),
+        ))
+        .map(|variant| (variant, Tracked(self.value)))
+    }
+}
+
+impl<'de> VariantAccess<'de> for Tracked<'de> {
+    type Error = JsonError;
+
+    fn unit_variant(self) -> Result<(), JsonError> {
+        match self.0 {
+            Value::Null => Ok(()),
+            _ => Err(de::Error::invalid_type(unexpected(self.0), &"unit variant")),
+        }
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<S::Value, JsonError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, JsonError> {
+        match self.0 {
+            Value::Array(_) => self.deserialize_any(visitor),
+            _ => Err(de::Error::invalid_type(
+                unexpected(self.0),
+                &"tuple variant",
+            )),
+        }
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, JsonError> {
+        match self.0 {
+            Value::Object(_) | Value::Array(_) => self.deserialize_any(visitor),
+            _ => Err(de::Error::invalid_type(
+                unexpected(self.0),
+                &"struct variant",
+            )),
+        }
+    }
+}
+
+fn unexpected(value: &Value) -> de::Unexpected<'_> {
+    match value {
+        Value::Null => de::Unexpected::Unit,
+        Value::Bool(b) => de::Unexpected::Bool(*b),
+        Value::Number(n) => match (n.as_i64(), n.as_u64(), n.as_f64()) {
+            (Some(i), _, _) => de::Unexpected::Signed(i),
+            (_, Some(u), _) => de::Unexpected::Unsigned(u),
+            (_, _, Some(f)) => de::Unexpected::Float(f),
+            _ => de::Unexpected::Other("number"),
+        },
+        Value::String(s) => de::Unexpected::Str(s),
+        Value::Array(_) => de::Unexpected::Seq,
+        Value::Object(_) => de::Unexpected::Map,
+    }
+}
+
+// JSONB element types, in the low four bits of the first header byte.
+const JSONB_NULL: u8 = 0;
+const JSONB_TRUE: u8 = 1;
+const JSONB_FALSE: u8 = 2;
+const JSONB_INT: u8 = 3;
+const JSONB_INT5: u8 = 4;
+const JSONB_FLOAT: u8 = 5;
+const JSONB_FLOAT5: u8 = 6;
+const JSONB_TEXT: u8 = 7;
+const JSONB_TEXTJ: u8 = 8;
+const JSONB_TEXT5: u8 = 9;
+const JSONB_TEXTRAW: u8 = 10;
+const JSONB_ARRAY: u8 = 11;
+const JSONB_OBJECT: u8 = 12;
+
+// Decodes `blob` as a single JSONB element, or returns `None` if it is not
+// valid JSONB, e.g. JSON text.
+fn jsonb_value(blob: &[u8]) -> Option<Value> {
+    match jsonb_element(blob)? {
+        (value, []) => Some(value),
+        _ => None,
+    }
+}
+
+// Decodes the JSONB element at the start of `bytes`, returning it with the
+// bytes which follow it.
+fn jsonb_element(bytes: &[u8]) -> Option<(Value, &[u8])> {
+    let (&header, rest) = bytes.split_first()?;
+    // the size of the payload is in the high four bits of the header, or in
+    // the 1, 2, 4 or 8 big-endian bytes which follow it
+    let (size, rest) = match header >> 4 {
+        size @ 0..=11 => (usize::from(size), rest),
+        n => {
+            let len = 1 << (n - 12);
+            if rest.len() < len {
+                return None;
+            }
+            let (size, rest) = rest.split_at(len);
+            let size = size.iter().fold(0u64, |acc, &b| (acc << 8) | u64::from(b));
+            (usize::try_from(size).ok()?, rest)
+        }
+    };
+    if rest.len() < size {
+        return None;
+    }
+    let (payload, rest) = rest.split_at(size);
+    let value = match header & 0x0f {
+        JSONB_NULL | JSONB_TRUE | JSONB_FALSE if size != 0 => return None,
+        JSONB_NULL => Value::Null,
+        JSONB_TRUE => Value::Bool(true),
+        JSONB_FALSE => Value::Bool(false),
+        JSONB_INT | JSONB_FLOAT => match serde_json::from_slice(payload).ok()? {
+            value @ Value::Number(_) => value,
+            _ => return None,
+        },
+        JSONB_INT5 => Value::Number(int5(std::str::from_utf8(payload).ok()?)?),
+        JSONB_FLOAT5 => {
+            let f: f64 = std::str::from_utf8(payload).ok()?.parse().ok()?;
+            Value::Number(Number::from_f64(f)?)
+        }
+        JSONB_TEXT | JSONB_TEXTRAW => Value::String(std::str::from_utf8(payload).ok()?.to_owned()),
+        JSONB_TEXTJ | JSONB_TEXT5 => Value::String(unescape(std::str::from_utf8(payload).ok()?)?),
+        JSONB_ARRAY => {
+            let mut array = Vec::new();
+            let mut elements = payload;
+            while !elements.is_empty() {
+                let (value, rest) = jsonb_element(elements)?;
+                array.push(value);
+                elements = rest;
+            }
+            Value::Array(array)
+        }
+        JSONB_OBJECT => {
+            let mut object = Map::new();
+            let mut members = payload;
+            while !members.is_empty() {
+                let (key, rest) = jsonb_element(members)?;
+                let (value, rest) = jsonb_element(rest)?;
+                match key {
+                    Value::String(key) => object.insert(key, value),
+                    _ => return None,
+                };
+                members = rest;
+            }
+            Value::Object(object)
+        }
+        _ => return None,
+    };
+    Some((value, rest))
+}
+
+// Parses a JSON5 hexadecimal integer, e.g. `-0x1F`.
+fn int5(s: &str) -> Option<Number> {
+    let (negative, s) = match s.as_bytes().first()? {
+        b'-' => (true, &s[1..]),
+        b'+' => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let hex = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X"))?;
+    let n = u64::from_str_radix(hex, 16).ok()?;
+    if !negative {
+        Some(Number::from(n))
+    } else if n <= 1 << 63 {
+        Some(Number::from((n as i64).wrapping_neg()))
+    } else {
+        Number::from_f64(-(n as f64))
+    }
+}
+
+// Resolves the JSON and JSON5 escapes of a JSONB string.
+fn unescape(s: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        let c = match chars.next()? {
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'v' => '\u{b}',
+            '0' => '\0',
+            'x' => {
+                let c = char::from(u8::from_str_radix(chars.as_str().get(..2)?, 16).ok()?);
+                chars.nth(1);
+                c
+            }
+            'u' => {
+                let hi = u32::from_str_radix(chars.as_str().get(..4)?, 16).ok()?;
+                chars.nth(3);
+                if (0xd800..0xdc00).contains(&hi) {
+                    let low = chars.as_str().strip_prefix("\\u")?.get(..4)?;
+                    let lo = u32::from_str_radix(low, 16).ok()?;
+                    chars.nth(5);
+                    char::from_u32(0x10000 + ((hi - 0xd800) << 10) + lo.checked_sub(0xdc00)?)?
+                } else {
+                    char::from_u32(hi)?
+                }
+            }
+            // line continuations
+            '\r' => {
+                if chars.as_str().starts_with('\n') {
+                    chars.next();
+                }
+                continue;
+            }
+            '\n' | '\u{2028}' | '\u{2029}' => continue,
+            c => c,
+        };
+        unescaped.push(c);
+    }
+    Some(unescaped)
+}
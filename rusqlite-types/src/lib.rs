@@ -0,0 +1,116 @@
+//! SQLite value types and the [`ToSql`] and [`FromSql`] conversion traits of
+//! [rusqlite](https://docs.rs/rusqlite), without linking SQLite.
+//!
+//! A library which only needs to convert its own types to and from SQLite
+//! values can depend on this crate rather than on `rusqlite`, and leave the
+//! choice between a bundled and a system SQLite to the application.
+//! `rusqlite` re-exports everything here from its `types` module, so the
+//! implementations work through a `rusqlite::Connection` as is.
+//!
+//! ```rust
+//! use rusqlite_types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ToSqlResult, ValueRef};
+//!
+//! pub struct Celsius(pub f64);
+//!
+//! impl ToSql for Celsius {
+//!     fn to_sql(&self) -> ToSqlResult<'_> {
+//!         Ok(ToSqlOutput::from(self.0))
+//!     }
+//! }
+//!
+//! impl FromSql for Celsius {
+//!     fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+//!         f64::column_result(value).map(Celsius)
+//!     }
+//! }
+//! ```
+//!
+//! Conversions for the types of a few other crates are provided behind
+//! features of the same name: `chrono`, `time`, `uuid`, `serde_json`, `url`,
+//! `semver` and `camino`. With the `i128_blob` feature, `i128` is stored as a
+//! 16-byte BLOB which sorts like the integer. With `serde_json`, any serde
+//! type can be stored as JSON through [`Json`].
+#![warn(missing_docs)]
+#![cfg_attr(docsrs, feature(doc_cfg))]
+
+pub use crate::from_sql::{FromSql, FromSqlError, FromSqlRef, FromSqlResult};
+#[cfg(feature = "serde_json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde_json")))]
+pub use crate::json::{Json, JsonError};
+#[cfg(feature = "semver")]
+#[cfg_attr(docsrs, doc(cfg(feature = "semver")))]
+pub use crate::semver::InvalidVersion;
+pub use crate::to_sql::{Extension, InlineText, ToSql, ToSqlError, ToSqlOutput, ToSqlResult};
+pub use crate::value::Value;
+pub use crate::value_ref::ValueRef;
+
+use std::fmt;
+
+#[cfg(feature = "camino")]
+#[cfg_attr(docsrs, doc(cfg(feature = "camino")))]
+mod camino;
+#[cfg(feature = "chrono")]
+#[cfg_attr(docsrs, doc(cfg(feature = "chrono")))]
+mod chrono;
+mod from_sql;
+#[cfg(feature = "serde_json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde_json")))]
+mod json;
+#[cfg(feature = "semver")]
+#[cfg_attr(docsrs, doc(cfg(feature = "semver")))]
+mod semver;
+#[cfg(feature = "serde_json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde_json")))]
+mod serde_json;
+#[cfg(feature = "time")]
+#[cfg_attr(docsrs, doc(cfg(feature = "time")))]
+pub mod time;
+mod to_sql;
+#[cfg(feature = "url")]
+#[cfg_attr(docsrs, doc(cfg(feature = "url")))]
+mod url;
+mod value;
+mod value_ref;
+
+/// Empty struct that can be used to fill in a query parameter as `NULL`.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// # use rusqlite::{Connection, Result};
+/// # use rusqlite::types::{Null};
+///
+/// fn insert_null(conn: &Connection) -> Result<usize> {
+///     conn.execute("INSERT INTO people (name) VALUES (?1)", [Null])
+/// }
+/// ```
+#[derive(Copy, Clone)]
+pub struct Null;
+
+/// SQLite data types.
+/// See [Fundamental Datatypes](https://sqlite.org/c3ref/c_blob.html).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Type {
+    /// NULL
+    Null,
+    /// 64-bit signed integer
+    Integer,
+    /// 64-bit IEEE floating point number
+    Real,
+    /// String
+    Text,
+    /// BLOB
+    Blob,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Type::Null => f.pad("Null"),
+            Type::Integer => f.pad("Integer"),
+            Type::Real => f.pad("Real"),
+            Type::Text => f.pad("Text"),
+            Type::Blob => f.pad("Blob"),
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! [`ToSql`] and [`FromSql`] implementation for [`semver::Version`].
+use std::error::Error;
+use std::fmt;
+
+use crate::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ToSqlResult, ValueRef};
+use semver::Version;
+
+/// Serialize `Version` to text, as `MAJOR.MINOR.PATCH[-PRE][+BUILD]`.
+impl ToSql for Version {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        Ok(ToSqlOutput::from(self.to_string()))
+    }
+}
+
+/// Deserialize text to `Version`.
+impl FromSql for Version {
+    #[inline]
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let s = value.as_str()?;
+        Version::parse(s).map_err(|e| {
+            FromSqlError::Other(Box::new(InvalidVersion {
+                input: s.to_owned(),
+                source: e,
+            }))
+        })
+    }
+}
+
+/// Error reported when a stored value is not a valid semantic version.
+#[derive(Debug)]
+pub struct InvalidVersion {
+    input: String,
+    source: semver::Error,
+}
+
+impl InvalidVersion {
+    /// The text that failed to parse.
+    #[inline]
+    #[must_use]
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+}
+
+impl fmt::Display for InvalidVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid version {:?}: {}", self.input, self.source)
+    }
+}
+
+impl Error for InvalidVersion {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
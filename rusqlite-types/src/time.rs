@@ -0,0 +1,108 @@
+//! [`ToSql`] and [`FromSql`] implementation for [`time::OffsetDateTime`].
+//!
+//! Values are written as `YYYY-MM-DD HH:MM:SS.SSSZ` in UTC, and read from
+//! that, from RFC 3339 and from the other formats of the SQLite
+//! [date and time functions](https://sqlite.org/lang_datefunc.html). The
+//! legacy `YYYY-MM-DD HH:MM:SS:SSS [+-]HH:MM` format of older versions is
+//! still read, and [`is_legacy_format`] finds the values to rewrite.
+use std::fmt::Write;
+use std::io;
+
+use crate::{
+    FromSql, FromSqlError, FromSqlResult, InlineText, ToSql, ToSqlOutput, ToSqlResult, ValueRef,
+};
+use time::format_description::well_known::Rfc3339;
+use time::format_description::FormatItem;
+use time::macros::format_description;
+use time::{OffsetDateTime, PrimitiveDateTime, UtcOffset};
+
+const PRIMITIVE_SHORT_DATE_TIME_FORMAT: &[FormatItem<'_>] =
+    format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
+const PRIMITIVE_DATE_TIME_FORMAT: &[FormatItem<'_>] =
+    format_description!("[year]-[month]-[day] [hour]:[minute]:[second].[subsecond]");
+const PRIMITIVE_DATE_TIME_Z_FORMAT: &[FormatItem<'_>] =
+    format_description!("[year]-[month]-[day] [hour]:[minute]:[second].[subsecond]Z");
+const OFFSET_SHORT_DATE_TIME_FORMAT: &[FormatItem<'_>] = format_description!(
+    "[year]-[month]-[day] [hour]:[minute]:[second][offset_hour sign:mandatory]:[offset_minute]"
+);
+const OFFSET_DATE_TIME_FORMAT: &[FormatItem<'_>] = format_description!(
+    "[year]-[month]-[day] [hour]:[minute]:[second].[subsecond][offset_hour sign:mandatory]:[offset_minute]"
+);
+const LEGACY_DATE_TIME_FORMAT: &[FormatItem<'_>] = format_description!(
+    "[year]-[month]-[day] [hour]:[minute]:[second]:[subsecond] [offset_hour sign:mandatory]:[offset_minute]"
+);
+
+impl ToSql for OffsetDateTime {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        // FIXME keep original offset
+        let mut text = InlineText::new();
+        self.to_offset(UtcOffset::UTC)
+            .format_into(&mut InlineWriter(&mut text), &PRIMITIVE_DATE_TIME_Z_FORMAT)?;
+        Ok(ToSqlOutput::Inline(text))
+    }
+}
+
+// Lets `time` format into an `InlineText`, without allocating.
+struct InlineWriter<'a>(&'a mut InlineText);
+
+impl io::Write for InlineWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let s =
+            std::str::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.0
+            .write_str(s)
+            .map_err(|_| io::Error::from(io::ErrorKind::WriteZero))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns whether `s` is a date and time in the legacy
+/// `YYYY-MM-DD HH:MM:SS:SSS [+-]HH:MM` format, which is read as an
+/// [`OffsetDateTime`] but no longer written.
+#[must_use]
+pub fn is_legacy_format(s: &str) -> bool {
+    matches!(parse(s), Ok((_, true)))
+}
+
+// The date and time in `s`, and whether it is in the legacy format.
+fn parse(s: &str) -> Result<(OffsetDateTime, bool), time::error::Parse> {
+    if s.len() > 10 && s.as_bytes()[10] == b'T' {
+        // YYYY-MM-DDTHH:MM:SS.SSS[+-]HH:MM
+        return OffsetDateTime::parse(s, &Rfc3339).map(|t| (t, false));
+    }
+    let s = s.strip_suffix('Z').unwrap_or(s);
+    match s.len() {
+        len if len <= 19 => {
+            // TODO YYYY-MM-DDTHH:MM:SS
+            PrimitiveDateTime::parse(s, &PRIMITIVE_SHORT_DATE_TIME_FORMAT)
+                .map(PrimitiveDateTime::assume_utc)
+        }
+        _ if s.as_bytes()[19] == b':' => {
+            // legacy
+            return OffsetDateTime::parse(s, &LEGACY_DATE_TIME_FORMAT).map(|t| (t, true));
+        }
+        _ if s.as_bytes()[19] == b'.' => OffsetDateTime::parse(s, &OFFSET_DATE_TIME_FORMAT)
+            .or_else(|err| {
+                PrimitiveDateTime::parse(s, &PRIMITIVE_DATE_TIME_FORMAT)
+                    .map(PrimitiveDateTime::assume_utc)
+                    .map_err(|_| err)
+            }),
+        _ => OffsetDateTime::parse(s, &OFFSET_SHORT_DATE_TIME_FORMAT),
+    }
+    .map(|t| (t, false))
+}
+
+impl FromSql for OffsetDateTime {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str().and_then(|s| {
+            parse(s)
+                .map(|(t, _)| t)
+                .map_err(|err| FromSqlError::Other(Box::new(err)))
+        })
+    }
+}
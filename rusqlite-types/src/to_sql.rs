@@ -0,0 +1,562 @@
+use super::{Null, Value, ValueRef};
+use std::any::Any;
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::fmt;
+use std::rc::Rc;
+
+/// `ToSqlOutput` represents the possible output types for implementers of the
+/// [`ToSql`] trait.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum ToSqlOutput<'a> {
+    /// A borrowed SQLite-representable value.
+    Borrowed(ValueRef<'a>),
+
+    /// An owned SQLite-representable value.
+    Owned(Value),
+
+    /// A value which only the crate binding it knows about, like the
+    /// zero-filled BLOBs and the arrays of rusqlite, which are created and
+    /// read through its `ToSqlOutputExt` trait.
+    Extension(Extension),
+
+    /// A short text formatted without allocating, which SQLite copies when
+    /// it is bound.
+    Inline(InlineText),
+}
+
+/// A text of at most [`InlineText::CAPACITY`] bytes, stored inline, for
+/// [`ToSqlOutput::Inline`].
+///
+/// It is written to through [`fmt::Write`], which fails once the capacity is
+/// exceeded.
+///
+/// ```rust
+/// use rusqlite_types::{InlineText, ToSqlOutput};
+/// use std::fmt::Write;
+///
+/// fn point(x: i32, y: i32) -> ToSqlOutput<'static> {
+///     let mut text = InlineText::new();
+///     match write!(text, "({},{})", x, y) {
+///         Ok(()) => ToSqlOutput::Inline(text),
+///         Err(_) => unreachable!("two i32 fit"),
+///     }
+/// }
+/// ```
+#[derive(Clone, Copy)]
+pub struct InlineText {
+    buf: [u8; InlineText::CAPACITY],
+    len: u8,
+}
+
+impl InlineText {
+    /// The maximum length of the text, in bytes.
+    pub const CAPACITY: usize = 48;
+
+    /// An empty text.
+    #[inline]
+    #[must_use]
+    pub fn new() -> InlineText {
+        InlineText {
+            buf: [0; InlineText::CAPACITY],
+            len: 0,
+        }
+    }
+
+    /// Returns the text written so far.
+    #[inline]
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        // only whole `str`s are copied in
+        std::str::from_utf8(&self.buf[..self.len as usize]).unwrap()
+    }
+}
+
+impl Default for InlineText {
+    #[inline]
+    fn default() -> InlineText {
+        InlineText::new()
+    }
+}
+
+impl fmt::Write for InlineText {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let start = self.len as usize;
+        let end = start + s.len();
+        if end > InlineText::CAPACITY {
+            return Err(fmt::Error);
+        }
+        self.buf[start..end].copy_from_slice(s.as_bytes());
+        self.len = end as u8;
+        Ok(())
+    }
+}
+
+impl fmt::Debug for InlineText {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl PartialEq for InlineText {
+    fn eq(&self, other: &InlineText) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+/// The opaque payload of a [`ToSqlOutput::Extension`].
+///
+/// The payload can only be read back by downcasting it to its type, so a
+/// crate which keeps that type private is the only one able to create and
+/// bind such values.
+#[derive(Clone)]
+pub struct Extension(Rc<dyn ExtensionValue>);
+
+impl Extension {
+    /// Wraps `value`.
+    #[inline]
+    pub fn new<T: Any + fmt::Debug + PartialEq>(value: T) -> Extension {
+        Extension(Rc::new(value))
+    }
+
+    /// Returns the payload if it is a `T`.
+    #[inline]
+    #[must_use]
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.0.as_any().downcast_ref()
+    }
+}
+
+impl fmt::Debug for Extension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl PartialEq for Extension {
+    fn eq(&self, other: &Extension) -> bool {
+        self.0.eq_value(other.0.as_any())
+    }
+}
+
+trait ExtensionValue: fmt::Debug {
+    fn as_any(&self) -> &dyn Any;
+    fn eq_value(&self, other: &dyn Any) -> bool;
+}
+
+impl<T: Any + fmt::Debug + PartialEq> ExtensionValue for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn eq_value(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<T>() == Some(self)
+    }
+}
+
+/// The error of a [`ToSql`] conversion which failed.
+pub type ToSqlError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Result type for implementors of the [`ToSql`] trait.
+pub type ToSqlResult<'a> = Result<ToSqlOutput<'a>, ToSqlError>;
+
+// Generically allow any type that can be converted into a ValueRef
+// to be converted into a ToSqlOutput as well.
+impl<'a, T: ?Sized> From<&'a T> for ToSqlOutput<'a>
+where
+    &'a T: Into<ValueRef<'a>>,
+{
+    #[inline]
+    fn from(t: &'a T) -> Self {
+        ToSqlOutput::Borrowed(t.into())
+    }
+}
+
+// We cannot also generically allow any type that can be converted
+// into a Value to be converted into a ToSqlOutput because of
+// coherence rules (https://github.com/rust-lang/rust/pull/46192),
+// so we'll manually implement it for all the types we know can
+// be converted into Values.
+macro_rules! from_value(
+    ($t:ty) => (
+        impl From<$t> for ToSqlOutput<'_> {
+            #[inline]
+            fn from(t: $t) -> Self { ToSqlOutput::Owned(t.into())}
+        }
+    )
+);
+from_value!(String);
+from_value!(Null);
+from_value!(bool);
+from_value!(i8);
+from_value!(i16);
+from_value!(i32);
+from_value!(i64);
+from_value!(isize);
+from_value!(u8);
+from_value!(u16);
+from_value!(u32);
+from_value!(f32);
+from_value!(f64);
+from_value!(Vec<u8>);
+
+// It would be nice if we could avoid the heap allocation (of the `Vec`) that
+// `i128` needs in `Into<Value>`, but it's probably fine for the moment, and not
+// worth adding another case to Value.
+#[cfg(feature = "i128_blob")]
+#[cfg_attr(docsrs, doc(cfg(feature = "i128_blob")))]
+from_value!(i128);
+
+#[cfg(feature = "uuid")]
+#[cfg_attr(docsrs, doc(cfg(feature = "uuid")))]
+from_value!(uuid::Uuid);
+
+impl ToSql for ToSqlOutput<'_> {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        Ok(match *self {
+            ToSqlOutput::Borrowed(v) => ToSqlOutput::Borrowed(v),
+            ToSqlOutput::Owned(ref v) => ToSqlOutput::Borrowed(ValueRef::from(v)),
+            ToSqlOutput::Extension(ref e) => ToSqlOutput::Extension(e.clone()),
+            ToSqlOutput::Inline(ref t) => {
+                ToSqlOutput::Borrowed(ValueRef::Text(t.as_str().as_bytes()))
+            }
+        })
+    }
+}
+
+/// A trait for types that can be converted into SQLite values.
+///
+/// rusqlite reports a failed conversion as its
+/// `Error::ToSqlConversionFailure`, unless the error is itself a
+/// `rusqlite::Error`, which is returned as is.
+///
+/// Implementations written against rusqlite 0.28 and earlier, which returned
+/// `rusqlite::Result<ToSqlOutput<'_>>`, only need their return type changed
+/// to [`ToSqlResult`]: `?` still converts a `rusqlite::Error`, and an explicit
+/// `Err(rusqlite::Error::...)` needs an `.into()`.
+pub trait ToSql {
+    /// Converts Rust value to SQLite value
+    fn to_sql(&self) -> ToSqlResult<'_>;
+}
+
+impl<T: ToSql + ToOwned + ?Sized> ToSql for Cow<'_, T> {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        self.as_ref().to_sql()
+    }
+}
+
+impl<T: ToSql + ?Sized> ToSql for Box<T> {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        self.as_ref().to_sql()
+    }
+}
+
+impl<T: ToSql + ?Sized> ToSql for std::rc::Rc<T> {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        self.as_ref().to_sql()
+    }
+}
+
+impl<T: ToSql + ?Sized> ToSql for std::sync::Arc<T> {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        self.as_ref().to_sql()
+    }
+}
+
+// We should be able to use a generic impl like this:
+//
+// impl<T: Copy> ToSql for T where T: Into<Value> {
+//     fn to_sql(&self) -> ToSqlResult<'_> {
+//         Ok(ToSqlOutput::from((*self).into()))
+//     }
+// }
+//
+// instead of the following macro, but this runs afoul of
+// https://github.com/rust-lang/rust/issues/30191 and reports conflicting
+// implementations even when there aren't any.
+
+macro_rules! to_sql_self(
+    ($t:ty) => (
+        impl ToSql for $t {
+            #[inline]
+            fn to_sql(&self) -> ToSqlResult<'_> {
+                Ok(ToSqlOutput::from(*self))
+            }
+        }
+    )
+);
+
+to_sql_self!(Null);
+to_sql_self!(bool);
+to_sql_self!(i8);
+to_sql_self!(i16);
+to_sql_self!(i32);
+to_sql_self!(i64);
+to_sql_self!(isize);
+to_sql_self!(u8);
+to_sql_self!(u16);
+to_sql_self!(u32);
+to_sql_self!(f32);
+to_sql_self!(f64);
+
+#[cfg(feature = "i128_blob")]
+#[cfg_attr(docsrs, doc(cfg(feature = "i128_blob")))]
+to_sql_self!(i128);
+
+#[cfg(feature = "uuid")]
+#[cfg_attr(docsrs, doc(cfg(feature = "uuid")))]
+to_sql_self!(uuid::Uuid);
+
+macro_rules! to_sql_self_fallible(
+    ($t:ty) => (
+        impl ToSql for $t {
+            #[inline]
+            fn to_sql(&self) -> ToSqlResult<'_> {
+                Ok(ToSqlOutput::Owned(Value::Integer(
+                    // TODO: Include the values in the error message.
+                    i64::try_from(*self)?
+                )))
+            }
+        }
+    )
+);
+
+// Special implementations for usize and u64 because these conversions can fail.
+to_sql_self_fallible!(u64);
+to_sql_self_fallible!(usize);
+
+impl<T: ?Sized> ToSql for &'_ T
+where
+    T: ToSql,
+{
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        (*self).to_sql()
+    }
+}
+
+impl ToSql for String {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl ToSql for str {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        Ok(ToSqlOutput::from(self))
+    }
+}
+
+impl ToSql for Vec<u8> {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        Ok(ToSqlOutput::from(self.as_slice()))
+    }
+}
+
+impl<const N: usize> ToSql for [u8; N] {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        Ok(ToSqlOutput::from(&self[..]))
+    }
+}
+
+impl ToSql for [u8] {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        Ok(ToSqlOutput::from(self))
+    }
+}
+
+impl ToSql for Value {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        Ok(ToSqlOutput::from(self))
+    }
+}
+
+/// Passes the array to rusqlite's `rarray` table-valued function, which binds
+/// it as a pointer. It cannot be bound as a value.
+#[cfg(feature = "array")]
+#[cfg_attr(docsrs, doc(cfg(feature = "array")))]
+impl ToSql for Rc<Vec<Value>> {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        Ok(ToSqlOutput::Extension(Extension::new(self.clone())))
+    }
+}
+
+impl<T: ToSql> ToSql for Option<T> {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        match *self {
+            None => Ok(ToSqlOutput::from(Null)),
+            Some(ref t) => t.to_sql(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Extension, InlineText, ToSql, ToSqlOutput};
+    use crate::{Value, ValueRef};
+    use std::fmt::Write;
+
+    fn is_to_sql<T: ToSql>() {}
+
+    #[test]
+    fn test_integral_types() {
+        is_to_sql::<i8>();
+        is_to_sql::<i16>();
+        is_to_sql::<i32>();
+        is_to_sql::<i64>();
+        is_to_sql::<u8>();
+        is_to_sql::<u16>();
+        is_to_sql::<u32>();
+    }
+
+    #[test]
+    fn test_u8_array() {
+        let a: [u8; 99] = [0u8; 99];
+        let _a: &[&dyn ToSql] = &[&a as &dyn ToSql];
+        let r = ToSql::to_sql(&a);
+
+        r.unwrap();
+    }
+
+    #[test]
+    fn test_cow_str() {
+        use std::borrow::Cow;
+        let s = "str";
+        let cow: Cow<str> = Cow::Borrowed(s);
+        let r = cow.to_sql();
+        r.unwrap();
+        let cow: Cow<str> = Cow::Owned::<str>(String::from(s));
+        let r = cow.to_sql();
+        r.unwrap();
+        // Ensure this compiles.
+        let _p: &[&dyn ToSql] = &[&cow as &dyn ToSql];
+    }
+
+    #[test]
+    fn test_box_dyn() {
+        let s: Box<dyn ToSql> = Box::new("Hello world!");
+        let _s: &[&dyn ToSql] = &[&s as &dyn ToSql];
+        let r = ToSql::to_sql(&s);
+
+        r.unwrap();
+    }
+
+    #[test]
+    fn test_box_deref() {
+        let s: Box<str> = "Hello world!".into();
+        let _s: &[&dyn ToSql] = &[&s as &dyn ToSql];
+        let r = s.to_sql();
+
+        r.unwrap();
+    }
+
+    #[test]
+    fn test_box_direct() {
+        let s: Box<str> = "Hello world!".into();
+        let _s: &[&dyn ToSql] = &[&s as &dyn ToSql];
+        let r = ToSql::to_sql(&s);
+
+        r.unwrap();
+    }
+
+    #[test]
+    fn test_cells() {
+        use std::{rc::Rc, sync::Arc};
+
+        let source_str: Box<str> = "Hello world!".into();
+
+        let s: Rc<Box<str>> = Rc::new(source_str.clone());
+        let _s: &[&dyn ToSql] = &[&s as &dyn ToSql];
+        let r = s.to_sql();
+        r.unwrap();
+
+        let s: Arc<Box<str>> = Arc::new(source_str.clone());
+        let _s: &[&dyn ToSql] = &[&s as &dyn ToSql];
+        let r = s.to_sql();
+        r.unwrap();
+
+        let s: Arc<str> = Arc::from(&*source_str);
+        let _s: &[&dyn ToSql] = &[&s as &dyn ToSql];
+        let r = s.to_sql();
+        r.unwrap();
+
+        let s: Arc<dyn ToSql> = Arc::new(source_str.clone());
+        let _s: &[&dyn ToSql] = &[&s as &dyn ToSql];
+        let r = s.to_sql();
+        r.unwrap();
+
+        let s: Rc<str> = Rc::from(&*source_str);
+        let _s: &[&dyn ToSql] = &[&s as &dyn ToSql];
+        let r = s.to_sql();
+        r.unwrap();
+
+        let s: Rc<dyn ToSql> = Rc::new(source_str);
+        let _s: &[&dyn ToSql] = &[&s as &dyn ToSql];
+        let r = s.to_sql();
+        r.unwrap();
+    }
+
+    #[test]
+    fn test_fallible_integers() {
+        assert_eq!(
+            u64::MAX.to_sql().unwrap_err().to_string(),
+            "out of range integral type conversion attempted"
+        );
+        assert_eq!(
+            (i64::MAX as u64).to_sql().unwrap(),
+            ToSqlOutput::Owned(Value::Integer(i64::MAX))
+        );
+    }
+
+    #[test]
+    fn test_extension() {
+        #[derive(Debug, PartialEq)]
+        struct Marker(i32);
+
+        let a = ToSqlOutput::Extension(Extension::new(Marker(1)));
+        let b = a.to_sql().unwrap();
+        assert_eq!(a, b);
+        assert_ne!(a, ToSqlOutput::Extension(Extension::new(Marker(2))));
+        assert_ne!(a, ToSqlOutput::Extension(Extension::new(1)));
+        match b {
+            ToSqlOutput::Extension(e) => {
+                assert_eq!(e.downcast_ref::<Marker>(), Some(&Marker(1)));
+                assert_eq!(e.downcast_ref::<i32>(), None);
+            }
+            _ => panic!("not an extension"),
+        }
+        assert_eq!(format!("{:?}", a), "Extension(Marker(1))");
+    }
+
+    #[test]
+    fn test_inline_text() {
+        let mut text = InlineText::new();
+        let (c, n) = ('é', 42);
+        write!(text, "{}-{}", c, n).unwrap();
+        assert_eq!(text.as_str(), "é-42");
+        let output = ToSqlOutput::Inline(text);
+        assert_eq!(
+            output.to_sql().unwrap(),
+            ToSqlOutput::Borrowed(ValueRef::Text("é-42".as_bytes()))
+        );
+
+        let mut text = InlineText::new();
+        text.write_str(&"x".repeat(InlineText::CAPACITY)).unwrap();
+        text.write_char('y').unwrap_err();
+        assert_eq!(text.as_str().len(), InlineText::CAPACITY);
+    }
+}
@@ -0,0 +1,70 @@
+//! Ensure conversions written against `rusqlite-types` alone work through a
+//! `rusqlite::Connection`.
+
+use rusqlite::{Connection, Error, Result};
+use rusqlite_types::{
+    FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ToSqlResult, ValueRef,
+};
+
+#[derive(Debug, PartialEq)]
+struct Celsius(f64);
+
+impl ToSql for Celsius {
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        if self.0 < -273.15 {
+            return Err(format!("{} is below absolute zero", self.0).into());
+        }
+        Ok(ToSqlOutput::from(self.0))
+    }
+}
+
+impl FromSql for Celsius {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value {
+            ValueRef::Real(f) => Ok(Celsius(f)),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+struct Passthrough;
+
+impl ToSql for Passthrough {
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        Err(Error::InvalidQuery.into())
+    }
+}
+
+#[test]
+fn test_round_trip() -> Result<()> {
+    let db = Connection::open_in_memory()?;
+    db.execute_batch("CREATE TABLE foo (t REAL)")?;
+    db.execute("INSERT INTO foo (t) VALUES (?)", [Celsius(21.5)])?;
+
+    let t: Celsius = db.query_row("SELECT t FROM foo", [], |r| r.get(0))?;
+    assert_eq!(t, Celsius(21.5));
+    Ok(())
+}
+
+#[test]
+fn test_to_sql_error() -> Result<()> {
+    let db = Connection::open_in_memory()?;
+    db.execute_batch("CREATE TABLE foo (t REAL)")?;
+
+    let err = db
+        .execute("INSERT INTO foo (t) VALUES (?)", [Celsius(-300.0)])
+        .unwrap_err();
+    match err {
+        Error::ToSqlConversionFailure(err) => {
+            assert_eq!(err.to_string(), "-300 is below absolute zero");
+        }
+        err => panic!("Unexpected error {}", err),
+    }
+
+    // A `rusqlite::Error` is returned as is.
+    let err = db
+        .execute("INSERT INTO foo (t) VALUES (?)", [Passthrough])
+        .unwrap_err();
+    assert_eq!(err, Error::InvalidQuery);
+    Ok(())
+}
@@ -0,0 +1,390 @@
+//! Query results collected into a single buffer, see
+//! [`Statement::query_arena`].
+use std::mem;
+use std::str;
+
+use crate::types::{Value, ValueRef};
+use crate::{Error, Params, Result, Statement};
+
+// Where the bytes of a TEXT or BLOB value are in the buffer of the arena.
+#[derive(Clone, Copy, Debug)]
+struct Span {
+    offset: usize,
+    len: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Cell {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(Span),
+    Blob(Span),
+}
+
+/// All the rows returned by a query, as returned by
+/// [`Statement::query_arena`].
+///
+/// The bytes of every TEXT and BLOB value are copied into one buffer, next to
+/// a table of the values of each row, so collecting the rows allocates a
+/// handful of times rather than once per value. The arena does not borrow the
+/// statement: it can be sent to another thread, and is freed as a whole.
+#[derive(Clone, Debug)]
+pub struct RowArena {
+    names: Vec<String>,
+    cells: Vec<Cell>,
+    bytes: Vec<u8>,
+    rows: usize,
+}
+
+impl RowArena {
+    /// Returns the number of rows.
+    #[inline]
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.rows
+    }
+
+    /// Returns whether the query returned no rows.
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.rows == 0
+    }
+
+    /// Returns the number of columns of each row.
+    #[inline]
+    #[must_use]
+    pub fn column_count(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Returns the names of the columns.
+    #[must_use]
+    pub fn column_names(&self) -> Vec<&str> {
+        self.names.iter().map(String::as_str).collect()
+    }
+
+    /// Returns the number of bytes used by the values: the TEXT and BLOB
+    /// bytes, and the table of values. This is what the limit given to
+    /// [`Statement::query_arena_with_limit`] applies to.
+    #[inline]
+    #[must_use]
+    pub fn bytes(&self) -> usize {
+        self.bytes.len() + self.cells.len() * mem::size_of::<Cell>()
+    }
+
+    /// Returns the row at `index`, or `None` if there are not that many
+    /// rows.
+    #[inline]
+    #[must_use]
+    pub fn row(&self, index: usize) -> Option<ArenaRow<'_>> {
+        if index < self.rows {
+            Some(ArenaRow { arena: self, index })
+        } else {
+            None
+        }
+    }
+
+    /// Returns an iterator over the rows, in the order the query returned
+    /// them.
+    #[inline]
+    pub fn rows(&self) -> impl ExactSizeIterator<Item = ArenaRow<'_>> + '_ {
+        (0..self.rows).map(move |index| ArenaRow { arena: self, index })
+    }
+
+    fn push_bytes(&mut self, bytes: &[u8]) -> Span {
+        let span = Span {
+            offset: self.bytes.len(),
+            len: bytes.len(),
+        };
+        self.bytes.extend_from_slice(bytes);
+        span
+    }
+
+    fn value(&self, cell: Cell) -> ValueRef<'_> {
+        match cell {
+            Cell::Null => ValueRef::Null,
+            Cell::Integer(i) => ValueRef::Integer(i),
+            Cell::Real(f) => ValueRef::Real(f),
+            Cell::Text(span) => ValueRef::Text(&self.bytes[span.offset..span.offset + span.len]),
+            Cell::Blob(span) => ValueRef::Blob(&self.bytes[span.offset..span.offset + span.len]),
+        }
+    }
+}
+
+/// A row of a [`RowArena`].
+#[derive(Clone, Copy, Debug)]
+pub struct ArenaRow<'a> {
+    arena: &'a RowArena,
+    index: usize,
+}
+
+impl<'a> ArenaRow<'a> {
+    /// Returns the index of the row in the arena.
+    #[inline]
+    #[must_use]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Get the value of a column of the row.
+    ///
+    /// ## Failure
+    ///
+    /// Returns an `Error::InvalidColumnIndex` if `idx` is outside the valid
+    /// column range for this row.
+    #[inline]
+    pub fn get_ref(&self, idx: usize) -> Result<ValueRef<'a>> {
+        let columns = self.arena.column_count();
+        if idx >= columns {
+            return Err(Error::InvalidColumnIndex(idx));
+        }
+        let cell = self.arena.cells[self.index * columns + idx];
+        Ok(self.arena.value(cell))
+    }
+
+    /// Get the value of a `TEXT` column of the row.
+    ///
+    /// ## Failure
+    ///
+    /// Returns an `Error::InvalidColumnType` if the value is not `TEXT`, an
+    /// `Error::Utf8Error` if it is not valid UTF-8, or an
+    /// `Error::InvalidColumnIndex` if `idx` is outside the valid column range.
+    #[inline]
+    pub fn get_str(&self, idx: usize) -> Result<&'a str> {
+        match self.get_ref(idx)? {
+            ValueRef::Text(t) => Ok(str::from_utf8(t)?),
+            value => Err(self.invalid_column_type(idx, value)),
+        }
+    }
+
+    /// Get the value of a `BLOB` column of the row.
+    ///
+    /// ## Failure
+    ///
+    /// Returns an `Error::InvalidColumnType` if the value is not a `BLOB`,
+    /// or an `Error::InvalidColumnIndex` if `idx` is outside the valid column
+    /// range.
+    #[inline]
+    pub fn get_blob(&self, idx: usize) -> Result<&'a [u8]> {
+        match self.get_ref(idx)? {
+            ValueRef::Blob(b) => Ok(b),
+            value => Err(self.invalid_column_type(idx, value)),
+        }
+    }
+
+    /// Get the value of an `INTEGER` column of the row.
+    ///
+    /// ## Failure
+    ///
+    /// Returns an `Error::InvalidColumnType` if the value is not an
+    /// `INTEGER`, or an `Error::InvalidColumnIndex` if `idx` is outside the
+    /// valid column range.
+    #[inline]
+    pub fn get_i64(&self, idx: usize) -> Result<i64> {
+        match self.get_ref(idx)? {
+            ValueRef::Integer(i) => Ok(i),
+            value => Err(self.invalid_column_type(idx, value)),
+        }
+    }
+
+    /// Get the value of an `INTEGER` or `REAL` column of the row.
+    ///
+    /// ## Failure
+    ///
+    /// Returns an `Error::InvalidColumnType` if the value is neither an
+    /// `INTEGER` nor a `REAL`, or an `Error::InvalidColumnIndex` if `idx` is
+    /// outside the valid column range.
+    #[inline]
+    pub fn get_f64(&self, idx: usize) -> Result<f64> {
+        match self.get_ref(idx)? {
+            ValueRef::Integer(i) => Ok(i as f64),
+            ValueRef::Real(f) => Ok(f),
+            value => Err(self.invalid_column_type(idx, value)),
+        }
+    }
+
+    /// Returns the values of every column of the row, in order, copying
+    /// TEXT and BLOB values out of the arena.
+    #[must_use]
+    pub fn to_values(&self) -> Vec<Value> {
+        (0..self.arena.column_count())
+            .map(|idx| Value::from(self.get_ref(idx).unwrap()))
+            .collect()
+    }
+
+    fn invalid_column_type(&self, idx: usize, value: ValueRef<'_>) -> Error {
+        Error::InvalidColumnType(idx, self.arena.names[idx].clone(), value.data_type())
+    }
+}
+
+impl Statement<'_> {
+    /// Execute the prepared statement, collecting all the rows it returns
+    /// into a [`RowArena`].
+    ///
+    /// ## Failure
+    ///
+    /// Will return `Err` if binding parameters fails, or if stepping the
+    /// statement fails.
+    #[inline]
+    pub fn query_arena<P: Params>(&mut self, params: P) -> Result<RowArena> {
+        self.query_arena_with_limit(params, usize::MAX)
+    }
+
+    /// Execute the prepared statement, collecting all the rows it returns
+    /// into a [`RowArena`] of at most `limit` [`bytes`](RowArena::bytes).
+    ///
+    /// ## Failure
+    ///
+    /// Will return `Err(Error::ArenaLimitExceeded(limit))` as soon as the
+    /// rows need more than `limit` bytes, or fail as
+    /// [`query_arena`](Statement::query_arena) does.
+    pub fn query_arena_with_limit<P: Params>(
+        &mut self,
+        params: P,
+        limit: usize,
+    ) -> Result<RowArena> {
+        let mut arena = RowArena {
+            names: self.column_names().into_iter().map(str::to_owned).collect(),
+            cells: Vec::new(),
+            bytes: Vec::new(),
+            rows: 0,
+        };
+        let columns = arena.column_count();
+        let mut rows = self.query(params)?;
+        while let Some(row) = rows.next()? {
+            for idx in 0..columns {
+                let cell = match row.get_ref_unwrap(idx) {
+                    ValueRef::Null => Cell::Null,
+                    ValueRef::Integer(i) => Cell::Integer(i),
+                    ValueRef::Real(f) => Cell::Real(f),
+                    ValueRef::Text(t) => Cell::Text(arena.push_bytes(t)),
+                    ValueRef::Blob(b) => Cell::Blob(arena.push_bytes(b)),
+                };
+                arena.cells.push(cell);
+                if arena.bytes() > limit {
+                    return Err(Error::ArenaLimitExceeded(limit));
+                }
+            }
+            arena.rows += 1;
+        }
+        Ok(arena)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use crate::types::{Type, Value, ValueRef};
+    use crate::{Connection, Error, Result};
+
+    #[test]
+    fn test_query_arena() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE foo(i INTEGER, r REAL, t TEXT, b BLOB);
+             INSERT INTO foo VALUES (1, 1.5, 'one', x'01');
+             INSERT INTO foo VALUES (NULL, NULL, NULL, NULL);
+             INSERT INTO foo VALUES (3, 3.0, '', x'');",
+        )?;
+        let mut stmt = db.prepare("SELECT i, r, t, b FROM foo ORDER BY rowid")?;
+        let arena = stmt.query_arena([])?;
+        assert_eq!(arena.len(), 3);
+        assert_eq!(arena.column_count(), 4);
+        assert_eq!(arena.column_names(), ["i", "r", "t", "b"]);
+
+        let row = arena.row(0).unwrap();
+        assert_eq!(row.get_i64(0)?, 1);
+        assert_eq!(row.get_f64(0)?, 1.0);
+        assert_eq!(row.get_f64(1)?, 1.5);
+        assert_eq!(row.get_str(2)?, "one");
+        assert_eq!(row.get_blob(3)?, [1]);
+
+        let nulls = arena.row(1).unwrap();
+        for idx in 0..4 {
+            assert_eq!(nulls.get_ref(idx)?, ValueRef::Null);
+        }
+        assert!(matches!(
+            nulls.get_str(2),
+            Err(Error::InvalidColumnType(2, ref name, Type::Null)) if name == "t"
+        ));
+
+        let empty = arena.row(2).unwrap();
+        assert_eq!(empty.get_str(2)?, "");
+        assert!(empty.get_blob(3)?.is_empty());
+        assert!(matches!(
+            empty.get_blob(2),
+            Err(Error::InvalidColumnType(2, _, Type::Text))
+        ));
+        assert_eq!(empty.get_ref(4), Err(Error::InvalidColumnIndex(4)));
+        assert!(arena.row(3).is_none());
+
+        assert_eq!(
+            row.to_values(),
+            [
+                Value::Integer(1),
+                Value::Real(1.5),
+                Value::Text("one".to_owned()),
+                Value::Blob(vec![1])
+            ]
+        );
+
+        // the arena outlives the statement, and moves to another thread
+        drop(stmt);
+        let texts = thread::spawn(move || {
+            arena
+                .rows()
+                .map(|row| {
+                    row.get_ref(2)
+                        .unwrap()
+                        .as_str_or_null()
+                        .unwrap()
+                        .map(str::len)
+                })
+                .collect::<Vec<_>>()
+        })
+        .join()
+        .unwrap();
+        assert_eq!(texts, [Some(3), None, Some(0)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_arena_growth() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        // blobs of 0 to 299 bytes, filled with their length, so that values
+        // straddle every reallocation of the buffer
+        let mut stmt = db.prepare(
+            "WITH RECURSIVE n(i) AS (SELECT 0 UNION ALL SELECT i + 1 FROM n WHERE i < 299)
+             SELECT i, zeroblob(i), replace(hex(zeroblob(i)), '00', 'x') FROM n",
+        )?;
+        let arena = stmt.query_arena([])?;
+        assert_eq!(arena.len(), 300);
+        for row in arena.rows() {
+            let len = row.get_i64(0)? as usize;
+            assert_eq!(row.get_blob(1)?, vec![0; len].as_slice());
+            assert_eq!(row.get_str(2)?, "x".repeat(len));
+        }
+        let text_bytes = 2 * (0..300).sum::<usize>();
+        assert!(arena.bytes() >= text_bytes);
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_arena_limit() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        let sql = "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 100)
+                   SELECT randomblob(100) FROM n";
+        let mut stmt = db.prepare(sql)?;
+        let bytes = stmt.query_arena([])?.bytes();
+        assert!(bytes > 100 * 100);
+        assert_eq!(stmt.query_arena_with_limit([], bytes)?.len(), 100);
+        let err = stmt.query_arena_with_limit([], bytes - 1).unwrap_err();
+        assert_eq!(err, Error::ArenaLimitExceeded(bytes - 1));
+        // the statement can be used again
+        assert_eq!(stmt.query_arena([])?.len(), 100);
+        Ok(())
+    }
+}
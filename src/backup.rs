@@ -9,6 +9,8 @@
 //! [`run_to_completion`](Backup::run_to_completion) will attempt to back up the
 //! entire source database, allowing you to specify how many pages are backed up
 //! at a time and how long the thread should sleep between chunks of pages.
+//! [`run_to_completion_with`](Backup::run_to_completion_with) does the same,
+//! reporting progress to a closure.
 //!
 //! The following example is equivalent to "Example 2: Online Backup of a
 //! Running Database" from [SQLite's Online Backup API
@@ -26,7 +28,7 @@
 //! ) -> Result<()> {
 //!     let mut dst = Connection::open(dst)?;
 //!     let backup = backup::Backup::new(src, &mut dst)?;
-//!     backup.run_to_completion(5, time::Duration::from_millis(250), Some(progress))
+//!     backup.run_to_completion(5, time::Duration::from_millis(250), Some(progress), None)
 //! }
 //! ```
 
@@ -41,7 +43,7 @@ use std::time::Duration;
 use crate::ffi;
 
 use crate::error::error_from_handle;
-use crate::{Connection, DatabaseName, Result};
+use crate::{CancellationToken, Connection, DatabaseName, Error, Result};
 
 impl Connection {
     /// Back up the `name` database to the given
@@ -193,16 +195,24 @@ impl Backup<'_, '_> {
     /// that `to` is a `&mut` - this is because SQLite forbids any API calls on
     /// the destination of a backup while the backup is taking place.
     ///
+    /// Names can be given as [`DatabaseName`]s or as strings, e.g.
+    /// `Backup::new_with_names(&src, "aux", &mut dst, "main")`.
+    ///
     /// # Failure
     ///
     /// Will return `Err` if the underlying `sqlite3_backup_init` call returns
     /// `NULL`.
-    pub fn new_with_names<'a, 'b>(
+    pub fn new_with_names<'a, 'b, 'n, F, T>(
         from: &'a Connection,
-        from_name: DatabaseName<'_>,
+        from_name: F,
         to: &'b mut Connection,
-        to_name: DatabaseName<'_>,
-    ) -> Result<Backup<'a, 'b>> {
+        to_name: T,
+    ) -> Result<Backup<'a, 'b>>
+    where
+        F: Into<DatabaseName<'n>>,
+        T: Into<DatabaseName<'n>>,
+    {
+        let (from_name, to_name) = (from_name.into(), to_name.into());
         let to_name = to_name.as_cstring()?;
         let from_name = from_name.as_cstring()?;
 
@@ -280,6 +290,11 @@ impl Backup<'_, '_> {
     /// not change if the step returns `Busy` or `Locked` even though the
     /// backup is still running.
     ///
+    /// If `cancel` is not `None`, it is checked before each step. Once it is
+    /// cancelled, no more pages are copied and [`Error::Cancelled`] is
+    /// returned: the destination is left unchanged when the `Backup` is
+    /// dropped, and a new `Backup` starts over.
+    ///
     /// # Failure
     ///
     /// Will return `Err` if any of the calls to [`step`](Backup::step) return
@@ -289,18 +304,60 @@ impl Backup<'_, '_> {
         pages_per_step: c_int,
         pause_between_pages: Duration,
         progress: Option<fn(Progress)>,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<()> {
+        self.run(
+            pages_per_step,
+            pause_between_pages,
+            |p| {
+                if let Some(progress) = progress {
+                    progress(p);
+                }
+            },
+            cancel,
+        )
+    }
+
+    /// Attempts to run the entire backup, calling `progress` after each
+    /// step. Will call [`step(pages_per_step)`](Backup::step) as many times
+    /// as necessary, sleeping for `sleep_between_steps` between each call.
+    ///
+    /// A step which cannot get its locks, returning `Busy` or `Locked`, is
+    /// retried after sleeping as well, until it succeeds: the source database
+    /// can keep being used, and written to, during the backup.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if any of the calls to [`step`](Backup::step) return
+    /// `Err`.
+    pub fn run_to_completion_with<F: FnMut(Progress)>(
+        &self,
+        pages_per_step: c_int,
+        sleep_between_steps: Duration,
+        progress: F,
+    ) -> Result<()> {
+        self.run(pages_per_step, sleep_between_steps, progress, None)
+    }
+
+    fn run<F: FnMut(Progress)>(
+        &self,
+        pages_per_step: c_int,
+        pause: Duration,
+        mut progress: F,
+        cancel: Option<&CancellationToken>,
     ) -> Result<()> {
         use self::StepResult::{Busy, Done, Locked, More};
 
         assert!(pages_per_step > 0, "pages_per_step must be positive");
 
         loop {
-            let r = self.step(pages_per_step)?;
-            if let Some(progress) = progress {
-                progress(self.progress());
+            if matches!(cancel, Some(token) if token.is_cancelled()) {
+                return Err(Error::Cancelled);
             }
+            let r = self.step(pages_per_step)?;
+            progress(self.progress());
             match r {
-                More | Busy | Locked => thread::sleep(pause_between_pages),
+                More | Busy | Locked => thread::sleep(pause),
                 Done => return Ok(()),
             }
         }
@@ -317,8 +374,8 @@ impl Drop for Backup<'_, '_> {
 #[cfg(test)]
 mod test {
     use super::Backup;
-    use crate::{Connection, DatabaseName, Result};
-    use std::time::Duration;
+    use crate::{CancellationToken, Connection, DatabaseName, Error, Result};
+    use std::time::{Duration, Instant};
 
     #[test]
     fn test_backup() -> Result<()> {
@@ -343,7 +400,7 @@ mod test {
 
         {
             let backup = Backup::new(&src, &mut dst)?;
-            backup.run_to_completion(5, Duration::from_millis(250), None)?;
+            backup.run_to_completion(5, Duration::from_millis(250), None, None)?;
         }
 
         let the_answer: i64 = dst.one_column("SELECT SUM(x) FROM foo")?;
@@ -351,6 +408,42 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_backup_cancelled() -> Result<()> {
+        let src = Connection::open_in_memory()?;
+        src.execute_batch(
+            "CREATE TABLE foo(x BLOB);
+             WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 2000)
+             INSERT INTO foo SELECT randomblob(1000) FROM n;",
+        )?;
+        let mut dst = Connection::open_in_memory()?;
+        let token = CancellationToken::new();
+
+        {
+            let backup = Backup::new(&src, &mut dst)?;
+            backup.step(10)?;
+            backup.step(10)?;
+            token.cancel();
+            let err = backup
+                .run_to_completion(10, Duration::from_millis(0), None, Some(&token))
+                .unwrap_err();
+            assert_eq!(err, Error::Cancelled);
+            let progress = backup.progress();
+            assert_eq!(progress.pagecount - progress.remaining, 20);
+        }
+        let tables: i64 = dst.one_column("SELECT count(*) FROM sqlite_master")?;
+        assert_eq!(tables, 0);
+
+        token.reset();
+        {
+            let backup = Backup::new(&src, &mut dst)?;
+            backup.run_to_completion(100, Duration::from_millis(0), None, Some(&token))?;
+        }
+        let rows: i64 = dst.one_column("SELECT count(*) FROM foo")?;
+        assert_eq!(rows, 2000);
+        Ok(())
+    }
+
     #[test]
     fn test_backup_temp() -> Result<()> {
         let src = Connection::open_in_memory()?;
@@ -376,7 +469,7 @@ mod test {
         {
             let backup =
                 Backup::new_with_names(&src, DatabaseName::Temp, &mut dst, DatabaseName::Main)?;
-            backup.run_to_completion(5, Duration::from_millis(250), None)?;
+            backup.run_to_completion(5, Duration::from_millis(250), None, None)?;
         }
 
         let the_answer: i64 = dst.one_column("SELECT SUM(x) FROM foo")?;
@@ -418,11 +511,65 @@ mod test {
                 &mut dst,
                 DatabaseName::Main,
             )?;
-            backup.run_to_completion(5, Duration::from_millis(250), None)?;
+            backup.run_to_completion(5, Duration::from_millis(250), None, None)?;
         }
 
         let the_answer: i64 = dst.one_column("SELECT SUM(x) FROM foo")?;
         assert_eq!(42 + 43, the_answer);
         Ok(())
     }
+
+    #[test]
+    fn test_backup_progress() -> Result<()> {
+        let src = Connection::open_in_memory()?;
+        src.execute_batch(
+            "ATTACH DATABASE ':memory:' AS aux;
+             CREATE TABLE aux.foo(x BLOB);
+             WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 100)
+             INSERT INTO aux.foo SELECT randomblob(1000) FROM n;",
+        )?;
+        let mut dst = Connection::open_in_memory()?;
+
+        let mut reports = Vec::new();
+        {
+            let backup = Backup::new_with_names(&src, "aux", &mut dst, "main")?;
+            backup.run_to_completion_with(5, Duration::from_millis(0), |p| reports.push(p))?;
+        }
+        assert!(reports.len() > 2, "{:?}", reports);
+        assert!(reports.windows(2).all(|w| w[1].remaining < w[0].remaining));
+        let last = reports.last().unwrap();
+        assert_eq!(last.remaining, 0);
+        assert_eq!(last.pagecount, reports[0].pagecount);
+
+        let rows: i64 = dst.one_column("SELECT count(*) FROM foo")?;
+        assert_eq!(rows, 100);
+        Ok(())
+    }
+
+    #[test]
+    fn test_backup_busy_retried() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("dst.db3");
+        let src = Connection::open_in_memory()?;
+        src.execute_batch("CREATE TABLE foo(x INTEGER); INSERT INTO foo VALUES (42);")?;
+        let mut dst = Connection::open(&path)?;
+
+        // another connection holds a write lock on the destination for a while
+        let other = Connection::open(&path)?;
+        other.execute_batch("BEGIN IMMEDIATE; CREATE TABLE bar(x);")?;
+        let holder = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            other.execute_batch("ROLLBACK")
+        });
+        let start = Instant::now();
+        {
+            let backup = Backup::new(&src, &mut dst)?;
+            backup.run_to_completion_with(5, Duration::from_millis(10), |_| {})?;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        holder.join().unwrap()?;
+        let the_answer: i64 = dst.one_column("SELECT x FROM foo")?;
+        assert_eq!(42, the_answer);
+        Ok(())
+    }
 }
@@ -0,0 +1,701 @@
+//! Batch jobs with adaptively sized transactions, and batched updates keyed
+//! by primary key.
+//!
+//! Committing after every operation of a long batch job is slow, because each
+//! commit waits for the disk, while committing rarely holds the write lock for
+//! a long time and, in WAL mode, grows the WAL file. [`AdaptiveBatcher`]
+//! commits whenever a transaction has been open for a target interval, and
+//! learns how many operations fit in that interval so that WAL growth can be
+//! capped too.
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result};
+//! use rusqlite::batch::{AdaptiveBatcher, AdaptiveConfig};
+//! use std::ops::ControlFlow;
+//! use std::time::Duration;
+//!
+//! fn import(conn: &mut Connection, lines: &[&str]) -> Result<()> {
+//!     let config = AdaptiveConfig {
+//!         target_commit_interval: Duration::from_millis(200),
+//!         max_wal_pages: Some(10_000),
+//!         ..AdaptiveConfig::default()
+//!     };
+//!     AdaptiveBatcher::new(conn, config).run(|tx, i| {
+//!         tx.execute("INSERT INTO lines (line) VALUES (?)", [lines[i]])?;
+//!         Ok(if i + 1 == lines.len() {
+//!             ControlFlow::Break(())
+//!         } else {
+//!             ControlFlow::Continue(())
+//!         })
+//!     })?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! [`Connection::update_many`] updates many rows, each with its own values,
+//! through one cached `UPDATE` statement, and reports the keys that matched
+//! no row.
+
+use std::convert::TryFrom;
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+
+use crate::pragma::Sql;
+use crate::types::{ToSql, ToSqlOutput, Value};
+use crate::{ffi, Connection, Error, Result, Savepoint, Statement, Transaction};
+
+/// Configuration of an [`AdaptiveBatcher`].
+#[derive(Clone, Debug)]
+pub struct AdaptiveConfig {
+    /// How long a transaction should stay open before it is committed.
+    pub target_commit_interval: Duration,
+    /// The fewest operations per transaction, except for the last one.
+    pub min_ops: usize,
+    /// The most operations per transaction.
+    pub max_ops: usize,
+    /// The most WAL frames a transaction should write. Ignored unless the
+    /// database is in WAL mode.
+    ///
+    /// When set, the dirty pages are flushed to the WAL after each operation,
+    /// and the transaction is committed once the next operation would go over
+    /// the cap, judging by the largest operation so far.
+    pub max_wal_pages: Option<u32>,
+}
+
+impl Default for AdaptiveConfig {
+    fn default() -> AdaptiveConfig {
+        AdaptiveConfig {
+            target_commit_interval: Duration::from_secs(1),
+            min_ops: 1,
+            max_ops: 100_000,
+            max_wal_pages: None,
+        }
+    }
+}
+
+/// A committed transaction of an [`AdaptiveBatcher::run`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BatchCommit {
+    /// The number of operations in the transaction.
+    pub ops: usize,
+    /// How long the transaction was open, including the commit.
+    pub elapsed: Duration,
+    /// The number of WAL frames the transaction wrote, if the database is in
+    /// WAL mode.
+    pub wal_frames: Option<u32>,
+}
+
+/// What an [`AdaptiveBatcher::run`] did.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BatchReport {
+    /// The total number of operations.
+    pub ops: usize,
+    /// Every committed transaction, in order.
+    pub commits: Vec<BatchCommit>,
+}
+
+/// Runs a batch job in a sequence of transactions, sizing each one so that it
+/// is committed after about
+/// [`target_commit_interval`](AdaptiveConfig::target_commit_interval).
+///
+/// In WAL mode, the WAL frames written by each transaction are counted from
+/// the dirty pages the connection writes, as reported by
+/// [`Connection::cache_stats`].
+#[derive(Debug)]
+pub struct AdaptiveBatcher<'conn> {
+    conn: &'conn mut Connection,
+    config: AdaptiveConfig,
+    ops_per_commit: usize,
+}
+
+impl AdaptiveBatcher<'_> {
+    /// Create a batcher running its transactions on `conn`.
+    #[must_use]
+    pub fn new(conn: &mut Connection, config: AdaptiveConfig) -> AdaptiveBatcher<'_> {
+        let min_ops = config.min_ops.max(1);
+        AdaptiveBatcher {
+            conn,
+            ops_per_commit: min_ops,
+            config,
+        }
+    }
+
+    /// The number of operations the next transaction may run, as learned
+    /// from the previous ones.
+    #[inline]
+    #[must_use]
+    pub fn ops_per_commit(&self) -> usize {
+        self.ops_per_commit
+    }
+
+    /// Run the batch job.
+    ///
+    /// `op` is called with the current transaction and the index of the
+    /// operation, counting from 0 across transactions, until it returns
+    /// `ControlFlow::Break`. A transaction is committed once it has run
+    /// [`ops_per_commit`](AdaptiveBatcher::ops_per_commit) operations, or
+    /// once it has been open for the target interval and has run at least
+    /// [`min_ops`](AdaptiveConfig::min_ops) operations. The work done by the
+    /// call returning `Break` is committed too.
+    ///
+    /// # Failure
+    ///
+    /// If `op` or a commit fails, the current transaction is rolled back and
+    /// the error is returned. Previous transactions stay committed.
+    pub fn run<F>(&mut self, mut op: F) -> Result<BatchReport>
+    where
+        F: FnMut(&Transaction<'_>, usize) -> Result<ControlFlow<()>>,
+    {
+        let mut report = BatchReport::default();
+        let wal = self
+            .conn
+            .pragma_query_value(None, "journal_mode", |row| row.get::<_, String>(0))?
+            .eq_ignore_ascii_case("wal");
+        loop {
+            let written = self.conn.cache_stats()?.dirty;
+            let start = Instant::now();
+            let tx = self.conn.transaction()?;
+            let mut ops = 0;
+            let mut done = false;
+            // WAL frames written so far, and the most written by one operation
+            let mut frames = 0;
+            let mut op_frames = 0;
+            while ops < self.ops_per_commit && !done {
+                done = op(&tx, report.ops)?.is_break();
+                report.ops += 1;
+                ops += 1;
+                if ops >= self.config.min_ops
+                    && start.elapsed() >= self.config.target_commit_interval
+                {
+                    break;
+                }
+                if let (true, Some(max_pages)) = (wal, self.config.max_wal_pages) {
+                    // write the dirty pages to the WAL now, so that they are
+                    // counted, instead of at the commit
+                    tx.cache_flush()?;
+                    let total = tx.cache_stats()?.dirty - written;
+                    op_frames = op_frames.max(total - frames);
+                    frames = total;
+                    // stop before the next operation would go over the cap,
+                    // keeping a frame for the commit
+                    if ops >= self.config.min_ops && frames + op_frames >= i64::from(max_pages) {
+                        break;
+                    }
+                }
+            }
+            tx.commit()?;
+            let elapsed = start.elapsed();
+
+            let wal_frames = if wal {
+                let frames = self.conn.cache_stats()?.dirty - written;
+                Some(u32::try_from(frames).unwrap_or(u32::MAX))
+            } else {
+                None
+            };
+            report.commits.push(BatchCommit {
+                ops,
+                elapsed,
+                wal_frames,
+            });
+            if done {
+                return Ok(report);
+            }
+            self.adapt(ops, elapsed, wal_frames);
+        }
+    }
+
+    fn adapt(&mut self, ops: usize, elapsed: Duration, wal_frames: Option<u32>) {
+        let target = self.config.target_commit_interval.as_secs_f64();
+        let mut estimate = if elapsed.as_secs_f64() > 0.0 {
+            ops as f64 * target / elapsed.as_secs_f64()
+        } else {
+            self.config.max_ops as f64
+        };
+        // move halfway towards the estimate, so that one unusually fast or
+        // slow transaction does not swing the size too far
+        estimate = (self.ops_per_commit as f64 + estimate) / 2.0;
+        if let (Some(max_pages), Some(frames)) = (self.config.max_wal_pages, wal_frames) {
+            if frames > 0 {
+                estimate = estimate.min(ops as f64 * f64::from(max_pages) / f64::from(frames));
+            }
+        }
+        let max_ops = self.config.max_ops.max(1);
+        let min_ops = self.config.min_ops.clamp(1, max_ops);
+        self.ops_per_commit = (estimate as usize).clamp(min_ops, max_ops);
+    }
+}
+
+/// Options of [`Connection::update_many`].
+#[derive(Clone, Debug)]
+pub struct UpdateManyOptions {
+    /// Fail with an [`Error::UpdateManyFailed`] on the first key that matches
+    /// no row, instead of reporting it as missing.
+    pub error_on_missing: bool,
+    /// The most missing keys to collect in
+    /// [`UpdateManyReport::missing`]. The others are only counted.
+    pub max_missing: usize,
+    /// Commit after this many updates, instead of running all of them in one
+    /// transaction.
+    pub chunk_size: Option<usize>,
+}
+
+impl Default for UpdateManyOptions {
+    fn default() -> UpdateManyOptions {
+        UpdateManyOptions {
+            error_on_missing: false,
+            max_missing: 1000,
+            chunk_size: None,
+        }
+    }
+}
+
+/// What a [`Connection::update_many`] did.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[non_exhaustive]
+pub struct UpdateManyReport {
+    /// The number of rows updated.
+    pub updated: usize,
+    /// The keys that matched no row, up to
+    /// [`max_missing`](UpdateManyOptions::max_missing) of them.
+    pub missing: Vec<Value>,
+    /// The number of keys that matched no row.
+    pub missing_count: usize,
+    /// The number of transactions committed.
+    pub chunks: usize,
+}
+
+impl Connection {
+    /// Update many rows of `table`, each with its own values.
+    ///
+    /// Every item of `updates` is a key, compared with `key_column`, and the
+    /// values of `value_columns`, in order. They run through one cached
+    /// statement:
+    ///
+    /// ```sql
+    /// UPDATE "table" SET "c1" = ?1, "c2" = ?2 WHERE "key_column" = ?3
+    /// ```
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// use rusqlite::batch::UpdateManyOptions;
+    /// use rusqlite::ToSql;
+    ///
+    /// fn reprice(conn: &Connection, prices: &[(i64, f64, &str)]) -> Result<()> {
+    ///     let report = conn.update_many(
+    ///         "product",
+    ///         "id",
+    ///         prices
+    ///             .iter()
+    ///             .map(|(id, price, currency)| (id, [price as &dyn ToSql, currency])),
+    ///         &["price", "currency"],
+    ///         &UpdateManyOptions::default(),
+    ///     )?;
+    ///     println!("{} updated, {} unknown", report.updated, report.missing_count);
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `value_columns` is empty, or if the statement
+    /// cannot be prepared. If an update fails, for instance because a value
+    /// does not fit the column type of a `STRICT` table, or because its key
+    /// matches no row and
+    /// [`error_on_missing`](UpdateManyOptions::error_on_missing) is set, an
+    /// [`Error::UpdateManyFailed`] with its key is returned and the current
+    /// transaction is rolled back. With a
+    /// [`chunk_size`](UpdateManyOptions::chunk_size), the chunks before it
+    /// stay committed.
+    pub fn update_many<K, V, I>(
+        &self,
+        table: &str,
+        key_column: &str,
+        updates: I,
+        value_columns: &[&str],
+        options: &UpdateManyOptions,
+    ) -> Result<UpdateManyReport>
+    where
+        K: ToSql,
+        V: IntoIterator,
+        V::Item: ToSql,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        if value_columns.is_empty() {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_MISUSE),
+                Some("update_many needs at least one value column".to_owned()),
+            ));
+        }
+        let assignments = value_columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| format!("{} = ?{}", identifier(column), i + 1))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let sql = format!(
+            "UPDATE {} SET {} WHERE {} = ?{}",
+            identifier(table),
+            assignments,
+            identifier(key_column),
+            value_columns.len() + 1
+        );
+        let mut stmt = self.prepare_cached(&sql)?;
+
+        let chunk_size = options.chunk_size.unwrap_or(usize::MAX).max(1);
+        let mut report = UpdateManyReport::default();
+        let mut updates = updates.into_iter().peekable();
+        loop {
+            let mut sp = Savepoint::with_depth(self, 0)?;
+            let mut chunk = || -> Result<()> {
+                for (key, values) in updates.by_ref().take(chunk_size) {
+                    let changes = update_one(&mut stmt, &key, values, value_columns.len())
+                        .map_err(|source| Error::UpdateManyFailed {
+                            key: to_value(&key),
+                            source: Box::new(source),
+                        })?;
+                    if changes > 0 {
+                        report.updated += changes;
+                        continue;
+                    }
+                    if options.error_on_missing {
+                        return Err(Error::UpdateManyFailed {
+                            key: to_value(&key),
+                            source: Box::new(Error::QueryReturnedNoRows),
+                        });
+                    }
+                    report.missing_count += 1;
+                    if report.missing.len() < options.max_missing {
+                        report.missing.push(to_value(&key));
+                    }
+                }
+                Ok(())
+            };
+            if let Err(err) = chunk() {
+                // `ROLLBACK TO` leaves the savepoint open, so release it too
+                sp.rollback()?;
+                sp.commit()?;
+                return Err(err);
+            }
+            sp.commit()?;
+            report.chunks += 1;
+            if updates.peek().is_none() {
+                return Ok(report);
+            }
+        }
+    }
+}
+
+fn update_one<K, V>(stmt: &mut Statement<'_>, key: &K, values: V, expected: usize) -> Result<usize>
+where
+    K: ToSql,
+    V: IntoIterator,
+    V::Item: ToSql,
+{
+    let mut count = 0;
+    for value in values {
+        count += 1;
+        if count > expected {
+            break;
+        }
+        stmt.raw_bind_parameter(count, value)?;
+    }
+    if count != expected {
+        stmt.clear_bindings();
+        return Err(Error::InvalidParameterCount(count, expected));
+    }
+    stmt.raw_bind_parameter(expected + 1, key)?;
+    stmt.raw_execute()
+}
+
+fn identifier(name: &str) -> String {
+    let mut sql = Sql::new();
+    sql.push_identifier(name);
+    sql.as_str().to_owned()
+}
+
+fn to_value(key: &dyn ToSql) -> Value {
+    match key.to_sql() {
+        Ok(ToSqlOutput::Borrowed(v)) => v.into(),
+        Ok(ToSqlOutput::Owned(v)) => v,
+        _ => Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ops::ControlFlow;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{AdaptiveBatcher, AdaptiveConfig, UpdateManyOptions};
+    use crate::types::Value;
+    use crate::{params, Connection, Error, Result};
+
+    fn checked_file_handle() -> Result<(tempfile::TempDir, Connection)> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db = Connection::open(temp_dir.path().join("batch.db3"))?;
+        let mode: String = db.pragma_update_and_check(None, "journal_mode", "WAL", |r| r.get(0))?;
+        assert_eq!(mode, "wal");
+        db.execute_batch("CREATE TABLE foo(x INTEGER, y BLOB)")?;
+        Ok((temp_dir, db))
+    }
+
+    #[test]
+    fn test_commit_interval() -> Result<()> {
+        let (_dir, mut db) = checked_file_handle()?;
+        let target = Duration::from_millis(40);
+        let config = AdaptiveConfig {
+            target_commit_interval: target,
+            min_ops: 2,
+            max_ops: 1000,
+            max_wal_pages: None,
+        };
+        let mut batcher = AdaptiveBatcher::new(&mut db, config);
+        let report = batcher.run(|tx, i| {
+            // 0.5ms to 2ms per operation
+            thread::sleep(Duration::from_micros(500 + (i as u64 * 7919) % 1500));
+            tx.execute("INSERT INTO foo (x) VALUES (?)", [i])?;
+            Ok(if i == 599 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            })
+        })?;
+        assert_eq!(report.ops, 600);
+        assert_eq!(report.commits.iter().map(|c| c.ops).sum::<usize>(), 600);
+        assert!(report.commits.len() > 3, "{:?}", report);
+        assert!(batcher.ops_per_commit() > 2);
+
+        // leave out the first commit, sized before anything was learned, and
+        // the last one, cut short by the end of the job
+        let commits = &report.commits[1..report.commits.len() - 1];
+        let mean = commits.iter().map(|c| c.elapsed).sum::<Duration>() / commits.len() as u32;
+        assert!(mean > target / 2 && mean < target * 3, "{:?}", report);
+        assert!(commits.iter().all(|c| c.wal_frames.is_some()));
+
+        let count: usize = db.query_row("SELECT count(*) FROM foo", [], |r| r.get(0))?;
+        assert_eq!(count, 600);
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_wal_pages() -> Result<()> {
+        let (_dir, mut db) = checked_file_handle()?;
+        let config = AdaptiveConfig {
+            target_commit_interval: Duration::from_secs(60),
+            min_ops: 1,
+            max_ops: 1000,
+            max_wal_pages: Some(50),
+        };
+        let report = AdaptiveBatcher::new(&mut db, config).run(|tx, i| {
+            // one page each at first, then about 3, so that the size learned
+            // from the first transactions is too large
+            let size = if i < 50 { 10 } else { 10000 };
+            tx.execute("INSERT INTO foo (y) VALUES (zeroblob(?))", [size])?;
+            Ok(if i == 199 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            })
+        })?;
+        assert_eq!(report.ops, 200);
+        assert!(report.commits.len() > 5, "{:?}", report);
+        for commit in &report.commits {
+            assert!(commit.wal_frames.unwrap() <= 50, "{:?}", report);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_rolls_back_current_transaction() -> Result<()> {
+        let (_dir, mut db) = checked_file_handle()?;
+        let config = AdaptiveConfig {
+            min_ops: 10,
+            max_ops: 10,
+            ..AdaptiveConfig::default()
+        };
+        let err = AdaptiveBatcher::new(&mut db, config)
+            .run(|tx, i| {
+                if i == 25 {
+                    return Err(Error::QueryReturnedNoRows);
+                }
+                tx.execute("INSERT INTO foo (x) VALUES (?)", [i])?;
+                Ok(ControlFlow::Continue(()))
+            })
+            .unwrap_err();
+        assert_eq!(err, Error::QueryReturnedNoRows);
+        assert!(db.is_autocommit());
+        let (count, max): (usize, usize) =
+            db.query_row("SELECT count(*), max(x) FROM foo", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })?;
+        assert_eq!((count, max), (20, 19));
+        Ok(())
+    }
+
+    fn people() -> Result<Connection> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            r#"CREATE TABLE "the people" (id INTEGER PRIMARY KEY, "first name" TEXT, age INTEGER);
+               INSERT INTO "the people" VALUES (1, 'a', 10), (2, 'b', 20), (3, 'c', 30);"#,
+        )?;
+        Ok(db)
+    }
+
+    #[test]
+    fn test_update_many_missing_keys() -> Result<()> {
+        let db = people()?;
+        let options = UpdateManyOptions {
+            max_missing: 2,
+            ..UpdateManyOptions::default()
+        };
+        let report = db.update_many(
+            "the people",
+            "id",
+            vec![
+                (1, params!["x", 11]),
+                (4, params!["y", 0]),
+                (3, params!["z", 33]),
+            ]
+            .into_iter()
+            .chain((5..8).map(|id| (id, params!["w", 0]))),
+            &["first name", "age"],
+            &options,
+        )?;
+        assert_eq!(report.updated, 2);
+        assert_eq!(report.missing, vec![Value::Integer(4), Value::Integer(5)]);
+        assert_eq!(report.missing_count, 4);
+        assert_eq!(report.chunks, 1);
+
+        let rows = db
+            .prepare(r#"SELECT "first name", age FROM "the people" ORDER BY id"#)?
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?
+            .collect::<Result<Vec<(String, i64)>>>()?;
+        assert_eq!(
+            rows,
+            vec![("x".into(), 11), ("b".into(), 20), ("z".into(), 33)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_many_error_on_missing() -> Result<()> {
+        let db = people()?;
+        let options = UpdateManyOptions {
+            error_on_missing: true,
+            ..UpdateManyOptions::default()
+        };
+        let err = db
+            .update_many(
+                "the people",
+                "id",
+                vec![(1, [11]), (2, [22]), (9, [99]), (3, [33])],
+                &["age"],
+                &options,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            Error::UpdateManyFailed {
+                key: Value::Integer(9),
+                source: Box::new(Error::QueryReturnedNoRows),
+            }
+        );
+        assert!(db.is_autocommit());
+        let ages: i64 = db.query_row(r#"SELECT sum(age) FROM "the people""#, [], |r| r.get(0))?;
+        assert_eq!(ages, 60);
+
+        let err = db
+            .update_many("the people", "id", vec![(1, [1, 2])], &["age"], &options)
+            .unwrap_err();
+        match err {
+            Error::UpdateManyFailed { key, source } => {
+                assert_eq!(key, Value::Integer(1));
+                assert_eq!(*source, Error::InvalidParameterCount(2, 1));
+            }
+            err => panic!("Unexpected error {}", err),
+        }
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "modern_sqlite")]
+    fn test_update_many_strict_type_error() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE t (k TEXT PRIMARY KEY, n INTEGER) STRICT;
+             INSERT INTO t VALUES ('a', 1), ('b', 2);",
+        )?;
+        let err = db
+            .update_many(
+                "t",
+                "k",
+                vec![("a", params![10]), ("b", params!["twenty"])],
+                &["n"],
+                &UpdateManyOptions::default(),
+            )
+            .unwrap_err();
+        match err {
+            Error::UpdateManyFailed { ref key, .. } => {
+                assert_eq!(*key, Value::from("b".to_owned()))
+            }
+            ref err => panic!("Unexpected error {}", err),
+        }
+        assert_eq!(
+            err.sqlite_error_code(),
+            Some(crate::ErrorCode::ConstraintViolation)
+        );
+        let n: i64 = db.query_row("SELECT n FROM t WHERE k = 'a'", [], |r| r.get(0))?;
+        assert_eq!(n, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_many_chunks() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE t (id INTEGER PRIMARY KEY, n INTEGER);
+             WITH RECURSIVE c(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM c WHERE i < 20000)
+             INSERT INTO t SELECT i, 0 FROM c;",
+        )?;
+        let updates = || (1..=20_000).map(|id| (id, [id * 2]));
+        let report = db.update_many("t", "id", updates(), &["n"], &UpdateManyOptions::default())?;
+        assert_eq!(report.updated, 20_000);
+        assert_eq!(report.chunks, 1);
+
+        let options = UpdateManyOptions {
+            chunk_size: Some(5000),
+            ..UpdateManyOptions::default()
+        };
+        let report = db.update_many(
+            "t",
+            "id",
+            updates().map(|(id, [n])| (id, [n + 1])),
+            &["n"],
+            &options,
+        )?;
+        assert_eq!(report.updated, 20_000);
+        assert_eq!(report.chunks, 4);
+        let sum: i64 = db.query_row("SELECT sum(n - 2 * id) FROM t", [], |r| r.get(0))?;
+        assert_eq!(sum, 20_000);
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_many_no_columns() {
+        let db = people().unwrap();
+        let updates: Vec<(i32, [i32; 0])> = vec![(1, [])];
+        db.update_many(
+            "the people",
+            "id",
+            updates,
+            &[],
+            &UpdateManyOptions::default(),
+        )
+        .unwrap_err();
+    }
+}
@@ -191,8 +191,8 @@ use std::io;
 use std::ptr;
 
 use super::ffi;
-use super::types::{ToSql, ToSqlOutput};
-use crate::{Connection, DatabaseName, Result};
+use super::types::{ToSql, ToSqlOutput, ToSqlOutputExt, ToSqlResult};
+use crate::{Connection, DatabaseName, Error, Result, RowId};
 
 mod pos_io;
 
@@ -211,39 +211,49 @@ impl Connection {
     ///
     /// # Failure
     ///
-    /// Will return `Err` if `db`/`table`/`column` cannot be converted to a
+    /// Will return `Err(Error::NoRowidTable)` if `table` was created `WITHOUT
+    /// ROWID`, or `Err` if `db`/`table`/`column` cannot be converted to a
     /// C-compatible string or if the underlying SQLite BLOB open call
     /// fails.
     #[inline]
-    pub fn blob_open<'a>(
+    pub fn blob_open<'a, R: Into<RowId>>(
         &'a self,
         db: DatabaseName<'_>,
         table: &str,
         column: &str,
-        row_id: i64,
+        row_id: R,
         read_only: bool,
     ) -> Result<Blob<'a>> {
         let c = self.db.borrow_mut();
         let mut blob = ptr::null_mut();
-        let db = db.as_cstring()?;
-        let table = super::str_to_cstring(table)?;
-        let column = super::str_to_cstring(column)?;
+        let c_db = db.as_cstring()?;
+        let c_table = super::str_to_cstring(table)?;
+        let c_column = super::str_to_cstring(column)?;
         let rc = unsafe {
             ffi::sqlite3_blob_open(
                 c.db(),
-                db.as_ptr(),
-                table.as_ptr(),
-                column.as_ptr(),
-                row_id,
+                c_db.as_ptr(),
+                c_table.as_ptr(),
+                c_column.as_ptr(),
+                row_id.into().0,
                 !read_only as std::os::raw::c_int,
                 &mut blob,
             )
         };
-        c.decode_result(rc).map(|_| Blob {
-            conn: self,
-            blob,
-            pos: 0,
-        })
+        let r = c.decode_result(rc);
+        drop(c);
+        match r {
+            Ok(()) => Ok(Blob {
+                conn: self,
+                blob,
+                pos: 0,
+            }),
+            // SQLite only reports that the table has no such column
+            Err(err) => match self.has_rowid(Some(db), table) {
+                Ok(false) => Err(Error::NoRowidTable(table.to_owned())),
+                _ => Err(err),
+            },
+        }
     }
 }
 
@@ -254,8 +264,8 @@ impl Blob<'_> {
     ///
     /// Will return `Err` if the underlying SQLite BLOB reopen call fails.
     #[inline]
-    pub fn reopen(&mut self, row: i64) -> Result<()> {
-        let rc = unsafe { ffi::sqlite3_blob_reopen(self.blob, row) };
+    pub fn reopen<R: Into<RowId>>(&mut self, row: R) -> Result<()> {
+        let rc = unsafe { ffi::sqlite3_blob_reopen(self.blob, row.into().0) };
         if rc != ffi::SQLITE_OK {
             return self.conn.decode_result(rc);
         }
@@ -413,18 +423,18 @@ pub struct ZeroBlob(pub i32);
 
 impl ToSql for ZeroBlob {
     #[inline]
-    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+    fn to_sql(&self) -> ToSqlResult<'_> {
         let ZeroBlob(length) = *self;
-        Ok(ToSqlOutput::ZeroBlob(length))
+        Ok(ToSqlOutput::zero_blob(length))
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{Connection, DatabaseName, Result};
+    use crate::{Connection, DatabaseName, Error, Result, RowId};
     use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 
-    fn db_with_test_blob() -> Result<(Connection, i64)> {
+    fn db_with_test_blob() -> Result<(Connection, RowId)> {
         let db = Connection::open_in_memory()?;
         let sql = "BEGIN;
                    CREATE TABLE test (content BLOB);
@@ -548,4 +558,24 @@ mod test {
             Ok(())
         }
     }
+
+    #[test]
+    fn test_blob_open_without_rowid() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE foo(id INTEGER PRIMARY KEY, content BLOB) WITHOUT ROWID;
+             INSERT INTO foo VALUES (1, zeroblob(10));",
+        )?;
+        match db.blob_open(DatabaseName::Main, "foo", "content", 1, true) {
+            Err(err) => assert_eq!(err, Error::NoRowidTable("foo".to_owned())),
+            Ok(_) => panic!("opened a blob of a WITHOUT ROWID table"),
+        }
+        // other failures are reported as before
+        let err = db
+            .blob_open(DatabaseName::Main, "bar", "content", 1, true)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("no such table"), "{}", err);
+        Ok(())
+    }
 }
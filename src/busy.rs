@@ -1,13 +1,111 @@
-///! Busy handler (when the database is locked)
+//! Busy handler (when the database is locked)
 use std::convert::TryInto;
-use std::mem;
+use std::ffi::CStr;
+#[cfg(feature = "busy-diagnostics")]
+use std::fmt;
 use std::os::raw::{c_int, c_void};
-use std::panic::catch_unwind;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
 use std::ptr;
+use std::sync::{Mutex, MutexGuard, PoisonError};
 use std::time::Duration;
 
 use crate::ffi;
-use crate::{Connection, InnerConnection, Result};
+use crate::panic_policy::stash_panic;
+#[cfg(all(feature = "busy-diagnostics", feature = "modern_sqlite"))]
+use crate::transaction::TransactionState;
+use crate::{Connection, Error, InnerConnection, Result};
+
+// Registered connections per canonical database path, across the whole
+// process. With the `busy-diagnostics` feature, every connection is
+// registered when opened, so that they can be counted. Otherwise, only those
+// which use a `WriteToken` are, on first use.
+static OPEN_PATHS: Mutex<Vec<OpenPath>> = Mutex::new(Vec::new());
+
+struct OpenPath {
+    path: PathBuf,
+    connections: usize,
+    // Commits reported through `Connection::commit_token`, see
+    // `write_token.rs`. Kept only while the path has open connections.
+    commits: u64,
+}
+
+/// Best-effort account of what may be holding a database lock, as returned by
+/// [`Connection::busy_diagnostics`].
+///
+/// Each field is gathered independently; a field that could not be determined
+/// is left as `None` (or `false`) rather than failing the whole report.
+#[cfg(feature = "busy-diagnostics")]
+#[cfg_attr(docsrs, doc(cfg(feature = "busy-diagnostics")))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BusyInfo {
+    /// Canonical path of the main database, or `None` for an in-memory or
+    /// temporary database.
+    pub path: Option<PathBuf>,
+    /// Journal mode of the main database, as reported by `PRAGMA
+    /// journal_mode`.
+    pub journal_mode: Option<String>,
+    /// Whether a `-wal` file exists next to the database.
+    pub wal_exists: bool,
+    /// Whether a `-shm` file exists next to the database.
+    pub shm_exists: bool,
+    /// Whether a `-journal` file exists next to the database.
+    pub journal_exists: bool,
+    /// Process holding a POSIX lock on the database file. Only available on
+    /// Linux, where it is read from `/proc/locks`; a write lock is reported in
+    /// preference to a read lock.
+    pub lock_holder_pid: Option<u32>,
+    /// Whether this connection has a transaction open.
+    pub in_transaction: bool,
+    /// Transaction state of this connection's main database.
+    #[cfg(feature = "modern_sqlite")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "modern_sqlite")))]
+    pub transaction_state: Option<TransactionState>,
+    /// Number of connections in this process, including this one, open on
+    /// the same database file.
+    pub connections: usize,
+}
+
+#[cfg(feature = "busy-diagnostics")]
+impl fmt::Display for BusyInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "busy diagnostics: {} connection(s) to this database in this process",
+            self.connections
+        )?;
+        match self.lock_holder_pid {
+            Some(pid) if pid == std::process::id() => {
+                write!(f, ", lock held by pid {pid} (this process)")?;
+            }
+            Some(pid) => write!(f, ", lock held by pid {pid}")?,
+            None => write!(f, ", lock holder unknown")?,
+        }
+        let sidecars: Vec<&str> = [
+            (self.wal_exists, "-wal"),
+            (self.shm_exists, "-shm"),
+            (self.journal_exists, "-journal"),
+        ]
+        .iter()
+        .filter(|(exists, _)| *exists)
+        .map(|(_, suffix)| *suffix)
+        .collect();
+        if sidecars.is_empty() {
+            write!(f, ", no sidecar files")?;
+        } else {
+            write!(f, ", sidecar files: {}", sidecars.join(" "))?;
+        }
+        if let Some(ref mode) = self.journal_mode {
+            write!(f, ", journal mode {mode}")?;
+        }
+        if self.in_transaction {
+            write!(f, ", this connection is in a transaction")
+        } else {
+            write!(f, ", this connection is not in a transaction")
+        }
+    }
+}
 
 impl Connection {
     /// Set a busy handler that sleeps for a specified amount of time when a
@@ -44,45 +142,309 @@ impl Connection {
     /// additional attempts are made to access the
     /// database and `SQLITE_BUSY` is returned to the
     /// application. If the callback returns `true`, then another attempt
-    /// is made to access the database and the cycle repeats.
+    /// is made to access the database and the cycle repeats. The callback
+    /// does its own waiting, e.g. sleeping for longer after each attempt.
     ///
     /// There can only be a single busy handler defined for each database
     /// connection. Setting a new busy handler clears any previously set
     /// handler. Note that calling [`busy_timeout()`](Connection::busy_timeout)
     /// or evaluating `PRAGMA busy_timeout=N` will change the busy handler
-    /// and thus clear any previously set busy handler.
+    /// and thus clear any previously set busy handler. The callback is
+    /// dropped when it is cleared, replaced, or the connection is closed.
+    ///
+    /// A panic in the callback gives up, as if it had returned `false`, and
+    /// is then handled according to the connection's
+    /// [`PanicPolicy`](crate::PanicPolicy).
     ///
     /// Newly created connections default to a
     /// [`busy_timeout()`](Connection::busy_timeout) handler with a timeout
     /// of 5000ms, although this is subject to change.
-    pub fn busy_handler(&self, callback: Option<fn(i32) -> bool>) -> Result<()> {
-        unsafe extern "C" fn busy_handler_callback(p_arg: *mut c_void, count: c_int) -> c_int {
-            let handler_fn: fn(i32) -> bool = mem::transmute(p_arg);
-            c_int::from(catch_unwind(|| handler_fn(count)).unwrap_or_default())
-        }
-        let c = self.db.borrow_mut();
-        let r = match callback {
-            Some(f) => unsafe {
-                ffi::sqlite3_busy_handler(c.db(), Some(busy_handler_callback), f as *mut c_void)
-            },
-            None => unsafe { ffi::sqlite3_busy_handler(c.db(), None, ptr::null_mut()) },
-        };
-        c.decode_result(r)
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// # use std::{thread, time::Duration};
+    /// fn back_off(conn: &Connection) -> Result<()> {
+    ///     conn.busy_handler(Some(|attempts: i32| {
+    ///         if attempts >= 10 {
+    ///             eprintln!("database still locked, giving up");
+    ///             return false;
+    ///         }
+    ///         thread::sleep(Duration::from_millis(1 << attempts));
+    ///         true
+    ///     }))
+    /// }
+    /// ```
+    pub fn busy_handler<F>(&self, callback: Option<F>) -> Result<()>
+    where
+        F: FnMut(i32) -> bool + Send + 'static,
+    {
+        self.db.borrow_mut().busy_handler(callback)
+    }
+
+    /// Gather a best-effort [`BusyInfo`] describing who may be holding a lock
+    /// on the main database: the journal mode, which sidecar files exist,
+    /// the process holding a POSIX lock on the file (Linux only), this
+    /// connection's transaction state, and how many connections in this
+    /// process have the same file open.
+    ///
+    /// To count them, the `busy-diagnostics` feature has every connection
+    /// record the canonical path of its main database when opened.
+    ///
+    /// # Failure
+    ///
+    /// Fields that cannot be determined are left empty; this only fails if
+    /// the connection is unusable.
+    #[cfg(feature = "busy-diagnostics")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "busy-diagnostics")))]
+    pub fn busy_diagnostics(&self) -> Result<BusyInfo> {
+        let mut info = self.db.borrow().busy_info();
+        info.journal_mode = self
+            .pragma_query_value(None, "journal_mode", |row| row.get(0))
+            .ok();
+        Ok(info)
+    }
+
+    /// When enabled, `SQLITE_BUSY` errors returned by this connection have a
+    /// summary of [`busy_diagnostics`](Connection::busy_diagnostics) appended
+    /// to their message. Disabled by default.
+    ///
+    /// The summary omits the journal mode, which would require running a
+    /// query against the locked database.
+    #[cfg(feature = "busy-diagnostics")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "busy-diagnostics")))]
+    #[inline]
+    pub fn set_busy_diagnostics(&self, enabled: bool) {
+        self.db.borrow_mut().busy_diagnostics = enabled;
     }
 }
 
 impl InnerConnection {
     #[inline]
-    fn busy_timeout(&mut self, timeout: c_int) -> Result<()> {
+    pub(crate) fn busy_timeout(&mut self, timeout: c_int) -> Result<()> {
         let r = unsafe { ffi::sqlite3_busy_timeout(self.db, timeout) };
+        // SQLite no longer calls the closure, if any
+        self.busy_handler = None;
+        self.decode_result(r)
+    }
+
+    pub(crate) fn busy_handler<F>(&mut self, callback: Option<F>) -> Result<()>
+    where
+        F: FnMut(i32) -> bool + Send + 'static,
+    {
+        unsafe extern "C" fn call_boxed_closure<F>(p_arg: *mut c_void, count: c_int) -> c_int
+        where
+            F: FnMut(i32) -> bool,
+        {
+            #[cfg(feature = "contention-profiling")]
+            let start = std::time::Instant::now();
+            let r = catch_unwind(AssertUnwindSafe(|| {
+                let boxed_handler: *mut F = p_arg.cast::<F>();
+                (*boxed_handler)(count)
+            }));
+            #[cfg(feature = "contention-profiling")]
+            crate::stats::record_busy_wait(start.elapsed());
+            c_int::from(r.unwrap_or_else(|payload| {
+                stash_panic(payload);
+                false
+            }))
+        }
+
+        let r = match callback {
+            Some(callback) => {
+                let boxed_handler = Box::new(callback);
+                let r = unsafe {
+                    ffi::sqlite3_busy_handler(
+                        self.db(),
+                        Some(call_boxed_closure::<F>),
+                        &*boxed_handler as *const F as *mut _,
+                    )
+                };
+                self.busy_handler = Some(boxed_handler);
+                r
+            }
+            None => {
+                let r = unsafe { ffi::sqlite3_busy_handler(self.db(), None, ptr::null_mut()) };
+                self.busy_handler = None;
+                r
+            }
+        };
         self.decode_result(r)
     }
+
+    #[cfg(feature = "busy-diagnostics")]
+    fn busy_info(&self) -> BusyInfo {
+        let path = self.registered_path().map(Path::to_path_buf);
+        let sidecar = |suffix: &str| match path {
+            Some(ref path) => {
+                let mut name = path.clone().into_os_string();
+                name.push(suffix);
+                Path::new(&name).exists()
+            }
+            None => false,
+        };
+        BusyInfo {
+            journal_mode: None,
+            wal_exists: sidecar("-wal"),
+            shm_exists: sidecar("-shm"),
+            journal_exists: sidecar("-journal"),
+            lock_holder_pid: path.as_deref().and_then(lock_holder_pid),
+            in_transaction: !self.is_autocommit(),
+            #[cfg(feature = "modern_sqlite")]
+            transaction_state: self.txn_state(None).ok(),
+            connections: path.as_deref().map_or(1, open_connections),
+            path,
+        }
+    }
+
+    // Appends a summary of `busy_info` to `SQLITE_BUSY` errors when enabled
+    // with `Connection::set_busy_diagnostics`.
+    #[cfg(feature = "busy-diagnostics")]
+    pub(crate) fn with_busy_diagnostics(&self, err: Error) -> Error {
+        if !self.busy_diagnostics {
+            return err;
+        }
+        match err {
+            Error::SqliteFailure(e, msg) if e.code == ffi::ErrorCode::DatabaseBusy => {
+                let summary = self.busy_info();
+                let msg = match msg {
+                    Some(msg) => format!("{msg} ({summary})"),
+                    None => format!("{e} ({summary})"),
+                };
+                Error::SqliteFailure(e, Some(msg))
+            }
+            err => err,
+        }
+    }
+
+    #[cfg(not(feature = "busy-diagnostics"))]
+    #[inline]
+    pub(crate) fn with_busy_diagnostics(&self, err: Error) -> Error {
+        err
+    }
+}
+
+// A panic while holding the registry leaves it consistent: counts are only
+// changed in place.
+fn open_paths() -> MutexGuard<'static, Vec<OpenPath>> {
+    OPEN_PATHS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Adds the main database of `db` to `OPEN_PATHS`, returning the canonical
+// path it was counted under.
+pub(crate) unsafe fn register_path(db: *mut ffi::sqlite3) -> Option<PathBuf> {
+    let filename = ffi::sqlite3_db_filename(db, b"main\0".as_ptr().cast());
+    if filename.is_null() {
+        return None;
+    }
+    let filename = CStr::from_ptr(filename).to_str().ok()?;
+    if filename.is_empty() {
+        return None;
+    }
+    let path = std::fs::canonicalize(filename).unwrap_or_else(|_| PathBuf::from(filename));
+    let mut paths = open_paths();
+    match paths.iter_mut().find(|p| p.path == path) {
+        Some(open) => open.connections += 1,
+        None => paths.push(OpenPath {
+            path: path.clone(),
+            connections: 1,
+            commits: 0,
+        }),
+    }
+    Some(path)
+}
+
+pub(crate) fn unregister_path(path: &Path) {
+    let mut paths = open_paths();
+    if let Some(i) = paths.iter().position(|p| p.path == path) {
+        paths[i].connections -= 1;
+        if paths[i].connections == 0 {
+            paths.swap_remove(i);
+        }
+    }
+}
+
+#[cfg(feature = "busy-diagnostics")]
+fn open_connections(path: &Path) -> usize {
+    let paths = open_paths();
+    paths
+        .iter()
+        .find(|p| p.path == path)
+        .map_or(0, |p| p.connections)
+}
+
+// Counts a new commit to `path`, returning its number.
+pub(crate) fn add_commit(path: &Path) -> u64 {
+    let mut paths = open_paths();
+    match paths.iter_mut().find(|p| p.path == path) {
+        Some(open) => {
+            open.commits += 1;
+            open.commits
+        }
+        None => 0,
+    }
+}
+
+// Returns the number of the latest commit counted by `add_commit`.
+pub(crate) fn last_commit(path: &Path) -> u64 {
+    let paths = open_paths();
+    paths
+        .iter()
+        .find(|p| p.path == path)
+        .map_or(0, |p| p.commits)
+}
+
+// Finds the holder of a POSIX lock on `path` in `/proc/locks`, whose lines
+// look like `1: POSIX  ADVISORY  WRITE 1234 08:02:131 1073741824 1073742335`.
+// Waiters are listed with a `->` before the lock type and are skipped.
+#[cfg(all(feature = "busy-diagnostics", target_os = "linux"))]
+fn lock_holder_pid(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+
+    let meta = std::fs::metadata(path).ok()?;
+    let dev = meta.dev();
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+    let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+    let locks = std::fs::read_to_string("/proc/locks").ok()?;
+    let mut holder = None;
+    for line in locks.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 6 || !matches!(fields[1], "POSIX" | "OFDLCK") {
+            continue;
+        }
+        let mut id = fields[5].split(':');
+        let matches = match (id.next(), id.next(), id.next()) {
+            (Some(maj), Some(min), Some(ino)) => {
+                u64::from_str_radix(maj, 16).ok() == Some(major)
+                    && u64::from_str_radix(min, 16).ok() == Some(minor)
+                    && ino.parse::<u64>().ok() == Some(meta.ino())
+            }
+            _ => false,
+        };
+        if !matches {
+            continue;
+        }
+        let pid = match fields[4].parse::<u32>() {
+            Ok(pid) => pid,
+            Err(_) => continue,
+        };
+        if fields[3] == "WRITE" {
+            return Some(pid);
+        }
+        holder = holder.or(Some(pid));
+    }
+    holder
+}
+
+#[cfg(all(feature = "busy-diagnostics", not(target_os = "linux")))]
+fn lock_holder_pid(_: &Path) -> Option<u32> {
+    None
 }
 
 #[cfg(test)]
 mod test {
     use std::sync::atomic::{AtomicBool, Ordering};
     use std::sync::mpsc::sync_channel;
+    use std::sync::{Arc, Mutex};
     use std::thread;
     use std::time::Duration;
 
@@ -104,6 +466,72 @@ mod test {
         tx1.rollback()
     }
 
+    #[test]
+    #[cfg(feature = "busy-diagnostics")]
+    fn test_busy_diagnostics() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+
+        let db1 = Connection::open(&path)?;
+        let info = db1.busy_diagnostics()?;
+        assert_eq!(info.connections, 1);
+        assert_eq!(info.path, Some(path.canonicalize().unwrap()));
+        assert_eq!(info.journal_mode.as_deref(), Some("delete"));
+        assert!(!info.in_transaction);
+
+        let db2 = Connection::open(&path)?;
+        assert_eq!(db1.busy_diagnostics()?.connections, 2);
+        assert_eq!(db2.busy_diagnostics()?.connections, 2);
+        drop(db2);
+        assert_eq!(db1.busy_diagnostics()?.connections, 1);
+
+        let memory = Connection::open_in_memory()?;
+        let info = memory.busy_diagnostics()?;
+        assert_eq!(info.path, None);
+        assert_eq!(info.connections, 1);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "busy-diagnostics")]
+    fn test_busy_diagnostics_error() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+
+        let mut db1 = Connection::open(&path)?;
+        db1.execute_batch("CREATE TABLE foo(x)")?;
+        let tx1 = db1.transaction_with_behavior(TransactionBehavior::Exclusive)?;
+        tx1.execute("INSERT INTO foo VALUES (1)", [])?;
+        let db2 = Connection::open(&path)?;
+        db2.busy_timeout(Duration::from_secs(0))?;
+
+        let err = db2
+            .query_row("SELECT count(*) FROM foo", [], |r| r.get::<_, i64>(0))
+            .unwrap_err();
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::DatabaseBusy));
+        assert!(!err.to_string().contains("busy diagnostics"));
+
+        db2.set_busy_diagnostics(true);
+        let err = db2
+            .query_row("SELECT count(*) FROM foo", [], |r| r.get::<_, i64>(0))
+            .unwrap_err();
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::DatabaseBusy));
+        let msg = err.to_string();
+        assert!(
+            msg.contains("2 connection(s) to this database in this process"),
+            "{}",
+            msg
+        );
+        assert!(msg.contains("sidecar files: -journal"), "{}", msg);
+
+        let info = db2.busy_diagnostics()?;
+        assert!(info.journal_exists);
+        if let Some(pid) = info.lock_holder_pid {
+            assert_eq!(pid, std::process::id());
+        }
+        tx1.rollback()
+    }
+
     #[test]
     #[ignore] // FIXME: unstable
     fn test_busy_timeout() {
@@ -132,6 +560,45 @@ mod test {
         child.join().unwrap();
     }
 
+    #[test]
+    fn test_busy_handler_closure() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+
+        let mut db1 = Connection::open(&path)?;
+        let tx1 = db1.transaction_with_behavior(TransactionBehavior::Exclusive)?;
+        let db2 = Connection::open(&path)?;
+        let counts = Arc::new(Mutex::new(Vec::new()));
+        let handler_counts = counts.clone();
+        db2.busy_handler(Some(move |count| {
+            handler_counts.lock().unwrap().push(count);
+            count < 3
+        }))?;
+        let err = db2.one_column::<i32>("PRAGMA schema_version").unwrap_err();
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::DatabaseBusy));
+        assert_eq!(*counts.lock().unwrap(), [0, 1, 2, 3]);
+
+        // busy_timeout replaces and drops the closure
+        db2.busy_timeout(Duration::from_millis(0))?;
+        assert_eq!(Arc::strong_count(&counts), 1);
+        let err = db2.one_column::<i32>("PRAGMA schema_version").unwrap_err();
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::DatabaseBusy));
+        assert_eq!(counts.lock().unwrap().len(), 4);
+
+        // a panic gives up
+        db2.busy_handler(Some(|_| -> bool { panic!("busy") }))?;
+        let err = db2.one_column::<i32>("PRAGMA schema_version").unwrap_err();
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::DatabaseBusy));
+
+        // the closure is dropped with the connection
+        let handler_counts = counts.clone();
+        db2.busy_handler(Some(move |_| handler_counts.lock().is_err()))?;
+        assert_eq!(Arc::strong_count(&counts), 2);
+        db2.close().unwrap();
+        assert_eq!(Arc::strong_count(&counts), 1);
+        tx1.rollback()
+    }
+
     #[test]
     #[ignore] // FIXME: unstable
     fn test_busy_handler() {
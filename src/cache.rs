@@ -1,11 +1,14 @@
 //! Prepared statements cache for faster execution.
 
 use crate::raw_statement::RawStatement;
-use crate::{Connection, Result, Statement};
+use crate::{Connection, Error, Result, Statement};
 use hashlink::LruCache;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::ops::{Deref, DerefMut};
+use std::os::raw::c_uint;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 impl Connection {
     /// Prepare a SQL statement for execution, returning a previously prepared
@@ -30,13 +33,39 @@ impl Connection {
     /// }
     /// ```
     ///
+    /// With the `modern_sqlite` feature, new statements are prepared with
+    /// [`SQLITE_PREPARE_PERSISTENT`](crate::PrepFlags::SQLITE_PREPARE_PERSISTENT).
+    ///
     /// # Failure
     ///
     /// Will return `Err` if `sql` cannot be converted to a C-compatible string
     /// or if the underlying SQLite call fails.
     #[inline]
     pub fn prepare_cached(&self, sql: &str) -> Result<CachedStatement<'_>> {
-        self.cache.get(self, sql)
+        #[cfg(feature = "modern_sqlite")]
+        let flags = crate::PrepFlags::SQLITE_PREPARE_PERSISTENT.bits();
+        #[cfg(not(feature = "modern_sqlite"))]
+        let flags = 0;
+        self.cache.get(self, sql, flags)
+    }
+
+    /// Like [`prepare_cached`](Connection::prepare_cached), but a new
+    /// statement is prepared with `flags`, and a cached statement is only
+    /// reused if it was prepared with the same flags.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be converted to a C-compatible string
+    /// or if the underlying SQLite call fails.
+    #[cfg(feature = "modern_sqlite")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "modern_sqlite")))]
+    #[inline]
+    pub fn prepare_cached_with_flags(
+        &self,
+        sql: &str,
+        flags: crate::PrepFlags,
+    ) -> Result<CachedStatement<'_>> {
+        self.cache.get(self, sql, flags.bits())
     }
 
     /// Set the maximum number of cached prepared statements this connection
@@ -54,11 +83,198 @@ impl Connection {
     pub fn flush_prepared_statement_cache(&self) {
         self.cache.flush();
     }
+
+    /// Like [`flush_prepared_statement_cache`](Connection::flush_prepared_statement_cache),
+    /// but returns the number of statements finalized. Pinned statements are
+    /// finalized too, but stay pinned.
+    #[inline]
+    pub fn clear_prepared_statement_cache(&self) -> usize {
+        self.cache.flush()
+    }
+
+    /// Keep the statement for `sql` in the cache once prepared, whatever the
+    /// capacity: it is never evicted, and does not count towards the
+    /// capacity.
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn insert_person(conn: &Connection, name: &str) -> Result<usize> {
+    ///     let sql = "INSERT INTO People (name) VALUES (?1)";
+    ///     conn.pin_prepared_statement(sql);
+    ///     conn.prepare_cached(sql)?.execute([name])
+    /// }
+    /// ```
+    #[inline]
+    pub fn pin_prepared_statement(&self, sql: &str) {
+        self.cache.pin(sql);
+    }
+
+    /// Undo [`pin_prepared_statement`](Connection::pin_prepared_statement):
+    /// the statement for `sql` can be evicted again. Returns false if it was
+    /// not pinned.
+    #[inline]
+    pub fn unpin_prepared_statement(&self, sql: &str) -> bool {
+        self.cache.unpin(sql)
+    }
+
+    /// Return the counters of the prepared statement cache, accumulated since
+    /// the connection was opened or
+    /// [`reset_prepared_statement_cache_stats`](Connection::reset_prepared_statement_cache_stats).
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::Connection;
+    /// fn is_thrashing(conn: &Connection) -> bool {
+    ///     let stats = conn.prepared_statement_cache_stats();
+    ///     stats.evictions > stats.hits
+    /// }
+    /// ```
+    #[inline]
+    pub fn prepared_statement_cache_stats(&self) -> StatementCacheStats {
+        self.cache.stats()
+    }
+
+    /// Set the hits, misses and evictions counters of the prepared statement
+    /// cache to zero.
+    #[inline]
+    pub fn reset_prepared_statement_cache_stats(&self) {
+        self.cache.reset_stats();
+    }
+
+    /// Prepare each of `sqls` with [`prepare_cached`](Connection::prepare_cached)
+    /// and return it to the cache, so that the first use of these statements
+    /// does not pay for preparing them.
+    ///
+    /// A statement that fails to prepare is reported, and does not stop the
+    /// others from being prepared. The cache capacity is not changed, so only
+    /// the last statements are kept if there are more than it can hold.
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn warm_up(conn: &Connection, hot_set: &[&str]) -> Result<()> {
+    ///     let report = conn.warm_statement_cache(hot_set)?;
+    ///     for failure in report.failures() {
+    ///         eprintln!("cannot prepare {:?}: {:?}", failure.sql, failure.error);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Statements that fail to prepare are reported in the [`WarmReport`]
+    /// rather than as an `Err`.
+    pub fn warm_statement_cache(&self, sqls: &[&str]) -> Result<WarmReport> {
+        let statements = sqls
+            .iter()
+            .map(|&sql| {
+                let start = Instant::now();
+                let r = self.prepare_cached(sql).map(drop);
+                let duration = start.elapsed();
+                let error = r.err();
+                WarmedStatement {
+                    sql: sql.to_owned(),
+                    duration,
+                    error_offset: error.as_ref().and_then(|err| error_offset(sql, err)),
+                    error,
+                }
+            })
+            .collect();
+        Ok(WarmReport { statements })
+    }
+
+    /// Return the SQL of the statements currently in the prepared statement
+    /// cache, e.g. to [warm up](Connection::warm_statement_cache) the cache
+    /// of another connection with them.
+    ///
+    /// Pinned statements come first, then the others from the least to the
+    /// most recently used. Statements currently in use are not in the cache,
+    /// so they are not included.
+    #[inline]
+    pub fn cached_statement_sqls(&self) -> Vec<String> {
+        self.cache.sqls()
+    }
+}
+
+/// What [`Connection::warm_statement_cache`] did with each statement.
+#[derive(Debug, Default)]
+#[non_exhaustive]
+pub struct WarmReport {
+    /// The statements, in the order they were given.
+    pub statements: Vec<WarmedStatement>,
+}
+
+impl WarmReport {
+    /// The statements which could not be prepared.
+    pub fn failures(&self) -> impl Iterator<Item = &WarmedStatement> {
+        self.statements.iter().filter(|stmt| stmt.error.is_some())
+    }
+
+    /// The time spent preparing all the statements.
+    #[must_use]
+    pub fn duration(&self) -> Duration {
+        self.statements.iter().map(|stmt| stmt.duration).sum()
+    }
+}
+
+/// A statement warmed up by [`Connection::warm_statement_cache`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct WarmedStatement {
+    /// The SQL of the statement, as given.
+    pub sql: String,
+    /// The time spent preparing the statement, or finding it in the cache.
+    pub duration: Duration,
+    /// The error preparing the statement, if it failed.
+    pub error: Option<Error>,
+    /// The byte offset in `sql` of the token SQLite rejected, when known.
+    pub error_offset: Option<usize>,
+}
+
+// Returns the offset of the error in `sql`, which is prepared trimmed.
+#[cfg(feature = "modern_sqlite")]
+fn error_offset(sql: &str, err: &Error) -> Option<usize> {
+    match *err {
+        Error::SqlInputError { offset, .. } if offset >= 0 => {
+            let leading = sql.len() - sql.trim_start().len();
+            Some(leading + offset as usize)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(not(feature = "modern_sqlite"))]
+fn error_offset(_: &str, _: &Error) -> Option<usize> {
+    None
+}
+
+/// Counters of a [`Connection`]'s prepared statement cache, returned by
+/// [`Connection::prepared_statement_cache_stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct StatementCacheStats {
+    /// Number of [`prepare_cached`](Connection::prepare_cached) calls which
+    /// reused a cached statement.
+    pub hits: u64,
+    /// Number of [`prepare_cached`](Connection::prepare_cached) calls which
+    /// had to prepare a new statement.
+    pub misses: u64,
+    /// Number of statements finalized to make room for others, or because
+    /// the capacity was reduced.
+    pub evictions: u64,
+    /// Number of statements currently in the cache, pinned ones included.
+    pub size: usize,
 }
 
 /// Prepared statements LRU cache.
 // #[derive(Debug)] // FIXME: https://github.com/kyren/hashlink/pull/4
-pub struct StatementCache(RefCell<LruCache<Arc<str>, RawStatement>>);
+pub struct StatementCache {
+    lru: RefCell<LruCache<Arc<str>, RawStatement>>,
+    // Pinned statement keys, with their statement when not in use.
+    pinned: RefCell<HashMap<Arc<str>, Option<RawStatement>>>,
+    hits: Cell<u64>,
+    misses: Cell<u64>,
+    evictions: Cell<u64>,
+}
 
 #[allow(clippy::non_send_fields_in_send_ty)]
 unsafe impl Send for StatementCache {}
@@ -120,16 +336,31 @@ impl StatementCache {
     /// Create a statement cache.
     #[inline]
     pub fn with_capacity(capacity: usize) -> StatementCache {
-        StatementCache(RefCell::new(LruCache::new(capacity)))
+        StatementCache {
+            lru: RefCell::new(LruCache::new(capacity)),
+            pinned: RefCell::new(HashMap::new()),
+            hits: Cell::new(0),
+            misses: Cell::new(0),
+            evictions: Cell::new(0),
+        }
     }
 
     #[inline]
     fn set_capacity(&self, capacity: usize) {
-        self.0.borrow_mut().set_capacity(capacity);
+        let mut cache = self.lru.borrow_mut();
+        let len = cache.len();
+        cache.set_capacity(capacity);
+        self.evicted(len - cache.len());
+    }
+
+    #[inline]
+    fn evicted(&self, n: usize) {
+        self.evictions.set(self.evictions.get() + n as u64);
     }
 
-    // Search the cache for a prepared-statement object that implements `sql`.
-    // If no such prepared-statement can be found, allocate and prepare a new one.
+    // Search the cache for a prepared-statement object that implements `sql`
+    // and was prepared with `flags`. If no such prepared-statement can be
+    // found, allocate and prepare a new one.
     //
     // # Failure
     //
@@ -139,12 +370,22 @@ impl StatementCache {
         &'conn self,
         conn: &'conn Connection,
         sql: &str,
+        flags: c_uint,
     ) -> Result<CachedStatement<'conn>> {
         let trimmed = sql.trim();
-        let mut cache = self.0.borrow_mut();
-        let stmt = match cache.remove(trimmed) {
-            Some(raw_stmt) => Ok(Statement::new(conn, raw_stmt)),
-            None => conn.prepare(trimmed),
+        let cached = match self.pinned.borrow_mut().get_mut(trimmed) {
+            Some(pinned) => pinned.take(),
+            None => self.lru.borrow_mut().remove(trimmed),
+        };
+        let stmt = match cached {
+            Some(raw_stmt) if raw_stmt.prep_flags() == flags => {
+                self.hits.set(self.hits.get() + 1);
+                Ok(Statement::new(conn, raw_stmt))
+            }
+            _ => {
+                self.misses.set(self.misses.get() + 1);
+                conn.db.borrow_mut().prepare(conn, trimmed, flags)
+            }
         };
         stmt.map(|mut stmt| {
             stmt.stmt.set_statement_cache_key(trimmed);
@@ -157,10 +398,12 @@ impl StatementCache {
         if stmt.is_null() {
             return;
         }
-        let mut cache = self.0.borrow_mut();
         stmt.clear_bindings();
         if let Some(sql) = stmt.statement_cache_key() {
-            cache.insert(sql, stmt);
+            match self.pinned.borrow_mut().get_mut(&sql) {
+                Some(pinned) => *pinned = Some(stmt),
+                None => self.insert(sql, stmt),
+            }
         } else {
             debug_assert!(
                 false,
@@ -169,10 +412,78 @@ impl StatementCache {
         }
     }
 
+    fn insert(&self, sql: Arc<str>, stmt: RawStatement) {
+        let mut cache = self.lru.borrow_mut();
+        if cache.contains_key(&sql) {
+            cache.insert(sql, stmt);
+        } else if cache.capacity() == 0 {
+            self.evicted(1);
+        } else {
+            if cache.len() >= cache.capacity() {
+                cache.remove_lru();
+                self.evicted(1);
+            }
+            cache.insert(sql, stmt);
+        }
+    }
+
+    fn pin(&self, sql: &str) {
+        let sql = sql.trim();
+        let mut pinned = self.pinned.borrow_mut();
+        if !pinned.contains_key(sql) {
+            let stmt = self.lru.borrow_mut().remove(sql);
+            pinned.insert(Arc::from(sql), stmt);
+        }
+    }
+
+    fn unpin(&self, sql: &str) -> bool {
+        let removed = self.pinned.borrow_mut().remove_entry(sql.trim());
+        match removed {
+            Some((sql, Some(stmt))) => self.insert(sql, stmt),
+            Some((_, None)) => {}
+            None => return false,
+        }
+        true
+    }
+
+    fn stats(&self) -> StatementCacheStats {
+        let pinned = self.pinned.borrow();
+        StatementCacheStats {
+            hits: self.hits.get(),
+            misses: self.misses.get(),
+            evictions: self.evictions.get(),
+            size: self.lru.borrow().len() + pinned.values().filter(|s| s.is_some()).count(),
+        }
+    }
+
+    fn sqls(&self) -> Vec<String> {
+        let mut pinned: Vec<String> = self
+            .pinned
+            .borrow()
+            .iter()
+            .filter(|(_, stmt)| stmt.is_some())
+            .map(|(sql, _)| sql.to_string())
+            .collect();
+        pinned.sort();
+        pinned.extend(self.lru.borrow().iter().map(|(sql, _)| sql.to_string()));
+        pinned
+    }
+
+    fn reset_stats(&self) {
+        self.hits.set(0);
+        self.misses.set(0);
+        self.evictions.set(0);
+    }
+
     #[inline]
-    fn flush(&self) {
-        let mut cache = self.0.borrow_mut();
+    fn flush(&self) -> usize {
+        let mut cache = self.lru.borrow_mut();
+        let mut n = cache.len();
         cache.clear();
+        for stmt in self.pinned.borrow_mut().values_mut() {
+            n += stmt.take().map_or(0, |_| 1);
+        }
+        n
     }
 }
 
@@ -184,15 +495,15 @@ mod test {
 
     impl StatementCache {
         fn clear(&self) {
-            self.0.borrow_mut().clear();
+            self.lru.borrow_mut().clear();
         }
 
         fn len(&self) -> usize {
-            self.0.borrow().len()
+            self.lru.borrow().len()
         }
 
         fn capacity(&self) -> usize {
-            self.0.borrow().capacity()
+            self.lru.borrow().capacity()
         }
     }
 
@@ -258,6 +569,117 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_stats() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        let sql = "PRAGMA schema_version";
+        for _ in 0..3 {
+            db.prepare_cached(sql)?;
+        }
+        let stats = db.prepared_statement_cache_stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (2, 1, 0));
+        assert_eq!(stats.size, 1);
+
+        db.reset_prepared_statement_cache_stats();
+        let stats = db.prepared_statement_cache_stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (0, 0, 0));
+        assert_eq!(stats.size, 1);
+
+        assert_eq!(db.clear_prepared_statement_cache(), 1);
+        assert_eq!(db.prepared_statement_cache_stats().size, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_warm_statement_cache() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(x)")?;
+        let sqls = [
+            "SELECT x FROM foo",
+            "  SELEKT x FROM foo",
+            "INSERT INTO foo VALUES (?1)",
+        ];
+        let report = db.warm_statement_cache(&sqls)?;
+        assert_eq!(report.statements.len(), 3);
+        let failures: Vec<_> = report.failures().collect();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].sql, sqls[1]);
+        assert!(failures[0].error.is_some());
+        #[cfg(feature = "modern_sqlite")]
+        assert_eq!(failures[0].error_offset, Some(2));
+        assert!(report.duration() >= report.statements[0].duration);
+
+        // the failure did not poison the cache
+        assert_eq!(
+            db.cached_statement_sqls(),
+            ["SELECT x FROM foo", "INSERT INTO foo VALUES (?1)"]
+        );
+        db.reset_prepared_statement_cache_stats();
+        db.prepare_cached(sqls[0])?;
+        db.prepare_cached(sqls[2])?.execute([1])?;
+        let stats = db.prepared_statement_cache_stats();
+        assert_eq!((stats.hits, stats.misses), (2, 0));
+
+        // pinned statements come first, then from the least recently used
+        db.pin_prepared_statement("SELECT 1");
+        db.prepare_cached("SELECT 1")?;
+        assert_eq!(
+            db.cached_statement_sqls(),
+            [
+                "SELECT 1",
+                "SELECT x FROM foo",
+                "INSERT INTO foo VALUES (?1)"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_eviction() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.set_prepared_statement_cache_capacity(2);
+        for sql in ["SELECT 1", "SELECT 2", "SELECT 3", "SELECT 1"] {
+            db.prepare_cached(sql)?;
+        }
+        // "SELECT 1" was evicted by "SELECT 3", then "SELECT 2" by "SELECT 1"
+        let stats = db.prepared_statement_cache_stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (0, 4, 2));
+        assert_eq!(stats.size, 2);
+
+        db.prepare_cached("SELECT 3")?;
+        db.set_prepared_statement_cache_capacity(1);
+        let stats = db.prepared_statement_cache_stats();
+        assert_eq!((stats.hits, stats.evictions, stats.size), (1, 3, 1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_pin() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.set_prepared_statement_cache_capacity(1);
+        db.prepare_cached("SELECT 1")?;
+        db.pin_prepared_statement(" SELECT 1 ");
+        assert_eq!(0, db.cache.len());
+
+        for sql in ["SELECT 2", "SELECT 3", "SELECT 1"] {
+            db.prepare_cached(sql)?;
+        }
+        let stats = db.prepared_statement_cache_stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (1, 3, 1));
+        assert_eq!(stats.size, 2);
+
+        assert_eq!(db.clear_prepared_statement_cache(), 2);
+        db.prepare_cached("SELECT 1")?;
+        db.prepare_cached("SELECT 2")?;
+        assert_eq!(db.prepared_statement_cache_stats().size, 2);
+
+        assert!(db.unpin_prepared_statement("SELECT 1"));
+        assert!(!db.unpin_prepared_statement("SELECT 1"));
+        let stats = db.prepared_statement_cache_stats();
+        assert_eq!((stats.evictions, stats.size), (2, 1));
+        Ok(())
+    }
+
     #[test]
     fn test_discard() -> Result<()> {
         let db = Connection::open_in_memory()?;
@@ -347,4 +769,41 @@ mod test {
         conn.prepare_cached("")?;
         Ok(())
     }
+
+    #[test]
+    #[cfg(feature = "modern_sqlite")]
+    fn test_cache_flags() -> Result<()> {
+        use crate::PrepFlags;
+
+        let db = Connection::open_in_memory()?;
+        let cache = &db.cache;
+        let sql = "SELECT name FROM pragma_table_info('sqlite_master')";
+        {
+            let stmt = db.prepare_cached(sql)?;
+            assert_eq!(
+                (*stmt).stmt.prep_flags(),
+                PrepFlags::SQLITE_PREPARE_PERSISTENT.bits()
+            );
+        }
+        assert_eq!(1, cache.len());
+
+        // the cached statement has other flags, so it is not reused
+        let err = db
+            .prepare_cached_with_flags(sql, PrepFlags::SQLITE_PREPARE_NO_VTAB)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("no such table"), "{}", err);
+        assert_eq!(0, cache.len());
+
+        let flags = PrepFlags::SQLITE_PREPARE_PERSISTENT | PrepFlags::SQLITE_PREPARE_NO_VTAB;
+        db.prepare_cached_with_flags("SELECT 1", flags)?;
+        assert_eq!(1, cache.len());
+        {
+            let stmt = db.prepare_cached_with_flags("SELECT 1", flags)?;
+            assert_eq!(0, cache.len());
+            assert_eq!((*stmt).stmt.prep_flags(), flags.bits());
+        }
+        assert_eq!(1, cache.len());
+        Ok(())
+    }
 }
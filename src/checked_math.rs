@@ -0,0 +1,259 @@
+//! Overflow-checked arithmetic SQL functions.
+use std::convert::TryFrom;
+
+use crate::functions::{Aggregate, Context, FunctionFlags};
+use crate::types::{Value, ValueRef};
+use crate::{Connection, Error, Result};
+
+fn overflow(name: &str) -> Error {
+    Error::UserFunctionError(format!("integer overflow in {name}()").into())
+}
+
+fn not_numeric(name: &str) -> Error {
+    Error::UserFunctionError(format!("{name}() requires numeric arguments").into())
+}
+
+#[derive(Default)]
+struct Sum {
+    int: i128,
+    real: Option<f64>,
+}
+
+// checked_sum(x): exact integer sum, falling back to REAL only when a REAL
+// argument is seen, like SUM() but without its silent overflow behaviour.
+struct CheckedSum;
+
+impl Aggregate<Sum, Value> for CheckedSum {
+    fn init(&self, _: &mut Context<'_>) -> Result<Sum> {
+        Ok(Sum::default())
+    }
+
+    fn step(&self, ctx: &mut Context<'_>, sum: &mut Sum) -> Result<()> {
+        match ctx.get_raw(0) {
+            ValueRef::Null => {}
+            ValueRef::Integer(i) => {
+                sum.int = sum
+                    .int
+                    .checked_add(i128::from(i))
+                    .ok_or_else(|| overflow("checked_sum"))?;
+            }
+            ValueRef::Real(f) => *sum.real.get_or_insert(0.0) += f,
+            _ => return Err(not_numeric("checked_sum")),
+        }
+        Ok(())
+    }
+
+    fn finalize(&self, _: &mut Context<'_>, sum: Option<Sum>) -> Result<Value> {
+        let sum = match sum {
+            Some(sum) => sum,
+            None => return Ok(Value::Null),
+        };
+        if let Some(real) = sum.real {
+            return Ok(Value::Real(sum.int as f64 + real));
+        }
+        match i64::try_from(sum.int) {
+            Ok(i) => Ok(Value::Integer(i)),
+            #[cfg(feature = "i128_blob")]
+            Err(_) => Ok(Value::from(sum.int)),
+            #[cfg(not(feature = "i128_blob"))]
+            Err(_) => Err(overflow("checked_sum")),
+        }
+    }
+}
+
+// exact_avg(x): average of INTEGER arguments computed from an exact sum.
+struct ExactAvg;
+
+impl Aggregate<(i128, i64), Option<f64>> for ExactAvg {
+    fn init(&self, _: &mut Context<'_>) -> Result<(i128, i64)> {
+        Ok((0, 0))
+    }
+
+    fn step(&self, ctx: &mut Context<'_>, acc: &mut (i128, i64)) -> Result<()> {
+        match ctx.get_raw(0) {
+            ValueRef::Null => {}
+            ValueRef::Integer(i) => {
+                acc.0 = acc
+                    .0
+                    .checked_add(i128::from(i))
+                    .ok_or_else(|| overflow("exact_avg"))?;
+                acc.1 += 1;
+            }
+            _ => {
+                return Err(Error::UserFunctionError(
+                    "exact_avg() requires INTEGER arguments".into(),
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn finalize(&self, _: &mut Context<'_>, acc: Option<(i128, i64)>) -> Result<Option<f64>> {
+        Ok(match acc {
+            Some((sum, count)) if count > 0 => {
+                // The quotient and remainder are exact; each is then rounded
+                // to the nearest f64.
+                let count = i128::from(count);
+                Some((sum / count) as f64 + (sum % count) as f64 / count as f64)
+            }
+            _ => None,
+        })
+    }
+}
+
+fn checked_binary(
+    ctx: &Context<'_>,
+    name: &str,
+    op: fn(i64, i64) -> Option<i64>,
+) -> Result<Option<i64>> {
+    match (ctx.get::<Option<i64>>(0)?, ctx.get::<Option<i64>>(1)?) {
+        (Some(a), Some(b)) => op(a, b).map(Some).ok_or_else(|| overflow(name)),
+        _ => Ok(None),
+    }
+}
+
+impl Connection {
+    /// Register overflow-checked arithmetic functions on this connection:
+    ///
+    /// * `checked_add(a, b)` and `checked_mul(a, b)` add or multiply two
+    ///   INTEGERs, failing the statement on overflow instead of converting
+    ///   the result to REAL. A NULL argument gives NULL.
+    /// * `checked_sum(x)` is an aggregate summing INTEGERs exactly, without
+    ///   `SUM()`'s "integer overflow" error on intermediate values. If the
+    ///   total does not fit in an INTEGER, it is returned as an `i128` BLOB
+    ///   with the `i128_blob` feature, and is an error otherwise. If any
+    ///   argument is REAL, the exact integer total is added to the sum of the
+    ///   REALs and the result is REAL.
+    /// * `exact_avg(x)` is an aggregate averaging INTEGERs from an exact sum.
+    ///   The result is REAL, computed as the exact quotient plus the exact
+    ///   remainder divided by the count, each rounded to the nearest `f64`.
+    ///
+    /// All of them ignore NULLs and reject TEXT and BLOB arguments.
+    ///
+    /// # Failure
+    ///
+    /// Will return Err if a function could not be attached to the connection.
+    pub fn register_checked_math_functions(&self) -> Result<()> {
+        let flags = FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC;
+        self.create_scalar_function("checked_add", 2, flags, |ctx| {
+            checked_binary(ctx, "checked_add", i64::checked_add)
+        })?;
+        self.create_scalar_function("checked_mul", 2, flags, |ctx| {
+            checked_binary(ctx, "checked_mul", i64::checked_mul)
+        })?;
+        self.create_aggregate_function("checked_sum", 1, flags, CheckedSum)?;
+        self.create_aggregate_function("exact_avg", 1, flags, ExactAvg)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Connection, Result};
+
+    fn checked_memory_handle() -> Result<Connection> {
+        let db = Connection::open_in_memory()?;
+        db.register_checked_math_functions()?;
+        db.execute_batch(
+            "CREATE TABLE big(x INTEGER);
+             INSERT INTO big VALUES (9223372036854775807), (9223372036854775807), (-1);",
+        )?;
+        Ok(db)
+    }
+
+    #[test]
+    fn test_checked_sum() -> Result<()> {
+        let db = checked_memory_handle()?;
+        let err = db
+            .query_row("SELECT sum(x) FROM big", [], |r| r.get::<_, i64>(0))
+            .unwrap_err();
+        assert!(err.to_string().contains("integer overflow"), "{}", err);
+
+        #[cfg(feature = "i128_blob")]
+        {
+            let total: i128 = db.query_row("SELECT checked_sum(x) FROM big", [], |r| r.get(0))?;
+            assert_eq!(total, 2 * i128::from(i64::MAX) - 1);
+        }
+        #[cfg(not(feature = "i128_blob"))]
+        {
+            let err = db
+                .query_row("SELECT checked_sum(x) FROM big", [], |r| r.get::<_, i64>(0))
+                .unwrap_err();
+            assert!(err
+                .to_string()
+                .contains("integer overflow in checked_sum()"));
+        }
+
+        // intermediate overflow is fine as long as the total fits
+        db.execute("INSERT INTO big VALUES (-9223372036854775807)", [])?;
+        let total: i64 = db.query_row("SELECT checked_sum(x) FROM big", [], |r| r.get(0))?;
+        assert_eq!(total, i64::MAX - 1);
+
+        let empty: Option<i64> =
+            db.query_row("SELECT checked_sum(x) FROM big WHERE 0", [], |r| r.get(0))?;
+        assert_eq!(empty, None);
+        let mixed: f64 = db.query_row(
+            "SELECT checked_sum(v) FROM (SELECT 1 AS v UNION ALL SELECT 0.5 UNION ALL SELECT NULL)",
+            [],
+            |r| r.get(0),
+        )?;
+        assert_eq!(mixed, 1.5);
+        assert!(db
+            .query_row("SELECT checked_sum('a')", [], |r| r.get::<_, i64>(0))
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_checked_add_mul() -> Result<()> {
+        let db = checked_memory_handle()?;
+        let (sum, product): (i64, i64) =
+            db.query_row("SELECT checked_add(2, 3), checked_mul(-4, 5)", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })?;
+        assert_eq!((sum, product), (5, -20));
+        let null: Option<i64> = db.query_row("SELECT checked_add(NULL, 1)", [], |r| r.get(0))?;
+        assert_eq!(null, None);
+
+        let err = db
+            .execute("UPDATE big SET x = checked_add(x, 1)", [])
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("integer overflow in checked_add()"),
+            "{}",
+            err
+        );
+        let err = db
+            .query_row("SELECT checked_mul(x, 2) FROM big", [], |r| {
+                r.get::<_, i64>(0)
+            })
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("integer overflow in checked_mul()"),
+            "{}",
+            err
+        );
+        // the failed UPDATE changed nothing
+        let max: i64 = db.query_row("SELECT max(x) FROM big", [], |r| r.get(0))?;
+        assert_eq!(max, i64::MAX);
+        Ok(())
+    }
+
+    #[test]
+    fn test_exact_avg() -> Result<()> {
+        let db = checked_memory_handle()?;
+        let avg: f64 = db.query_row("SELECT exact_avg(x) FROM big", [], |r| r.get(0))?;
+        assert_eq!(avg, (2.0 * i64::MAX as f64 - 1.0) / 3.0);
+        let avg: f64 = db.query_row(
+            "SELECT exact_avg(v) FROM (SELECT 1 AS v UNION ALL SELECT 2)",
+            [],
+            |r| r.get(0),
+        )?;
+        assert_eq!(avg, 1.5);
+        let empty: Option<f64> =
+            db.query_row("SELECT exact_avg(x) FROM big WHERE 0", [], |r| r.get(0))?;
+        assert_eq!(empty, None);
+        Ok(())
+    }
+}
@@ -1,13 +1,16 @@
 //! Add, remove, or modify a collation
 use std::cmp::Ordering;
 use std::os::raw::{c_char, c_int, c_void};
-use std::panic::{catch_unwind, UnwindSafe};
+use std::panic::{catch_unwind, AssertUnwindSafe, UnwindSafe};
 use std::ptr;
 use std::slice;
 
 use crate::ffi;
+use crate::panic_policy::stash_panic;
 use crate::{str_to_cstring, Connection, InnerConnection, Result};
 
+pub(crate) type BoxedCollationNeeded = Box<dyn FnMut(&Connection, &str) -> Result<()> + Send>;
+
 // FIXME copy/paste from function.rs
 unsafe extern "C" fn free_boxed_value<T>(p: *mut c_void) {
     drop(Box::from_raw(p.cast::<T>()));
@@ -25,13 +28,51 @@ impl Connection {
             .create_collation(collation_name, x_compare)
     }
 
-    /// Collation needed callback
+    /// Register `x_coll_needed` to be called with the name of a collation
+    /// when a statement uses one which is not defined, so that it can define
+    /// it lazily, e.g. with [`create_collation`](Connection::create_collation)
+    /// on the connection it is passed. It replaces any previous callback.
+    ///
+    /// Errors returned by the callback are ignored: the statement then fails
+    /// with "no such collation sequence" if the collation is still missing.
+    ///
+    /// ```rust
+    /// # use rusqlite::{Connection, Result};
+    /// fn lazy_collations(conn: &Connection) -> Result<()> {
+    ///     conn.collation_needed(|conn, name| match name {
+    ///         "reverse" => conn.create_collation(name, |a, b| b.cmp(a)),
+    ///         _ => Ok(()),
+    ///     })
+    /// }
+    /// ```
+    #[inline]
+    pub fn collation_needed<F>(&self, x_coll_needed: F) -> Result<()>
+    where
+        F: FnMut(&Connection, &str) -> Result<()> + Send + 'static,
+    {
+        self.db.borrow_mut().collation_needed(Some(x_coll_needed))
+    }
+
+    /// Register the `numeric` collation, which sorts text in natural order:
+    /// runs of ASCII digits are compared by their numeric value, so that
+    /// `'file2'` comes before `'file10'`, and everything else byte by byte,
+    /// as `BINARY` does.
+    #[inline]
+    pub fn register_collation_numeric(&self) -> Result<()> {
+        self.create_collation("numeric", numeric_compare)
+    }
+
+    /// Register the `unicode_nocase` collation, which compares text ignoring
+    /// case with Unicode case folding, where the builtin `NOCASE` only folds
+    /// ASCII letters: `'Maße'` and `'MASSE'` are equal.
+    #[cfg(feature = "unicase")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "unicase")))]
     #[inline]
-    pub fn collation_needed(
-        &self,
-        x_coll_needed: fn(&Connection, &str) -> Result<()>,
-    ) -> Result<()> {
-        self.db.borrow_mut().collation_needed(x_coll_needed)
+    pub fn register_collation_unicode_nocase(&self) -> Result<()> {
+        use unicase::UniCase;
+        self.create_collation("unicode_nocase", |a, b| {
+            UniCase::new(a).cmp(&UniCase::new(b))
+        })
     }
 
     /// Remove collation.
@@ -41,6 +82,47 @@ impl Connection {
     }
 }
 
+// Splits `s` after its leading ASCII digits.
+fn split_digits(s: &[u8]) -> (&[u8], &[u8]) {
+    let n = s.iter().take_while(|b| b.is_ascii_digit()).count();
+    s.split_at(n)
+}
+
+fn numeric_compare(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+    loop {
+        match (a.first(), b.first()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (da, ra) = split_digits(a);
+                let (db, rb) = split_digits(b);
+                let na = &da[da.iter().take_while(|&&d| d == b'0').count()..];
+                let nb = &db[db.iter().take_while(|&&d| d == b'0').count()..];
+                // then fewer leading zeros first, for a total order
+                let ord = na
+                    .len()
+                    .cmp(&nb.len())
+                    .then_with(|| na.cmp(nb))
+                    .then_with(|| da.len().cmp(&db.len()));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                a = ra;
+                b = rb;
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(y);
+                }
+                a = &a[1..];
+                b = &b[1..];
+            }
+        }
+    }
+}
+
 impl InnerConnection {
     fn create_collation<C>(&mut self, collation_name: &str, x_compare: C) -> Result<()>
     where
@@ -70,7 +152,8 @@ impl InnerConnection {
                 (*boxed_f)(s1.as_ref(), s2.as_ref())
             });
             let t = match r {
-                Err(_) => {
+                Err(payload) => {
+                    stash_panic(payload);
                     return -1; // FIXME How ?
                 }
                 Ok(r) => r,
@@ -105,18 +188,18 @@ impl InnerConnection {
         res
     }
 
-    fn collation_needed(
-        &mut self,
-        x_coll_needed: fn(&Connection, &str) -> Result<()>,
-    ) -> Result<()> {
-        use std::mem;
-        #[allow(clippy::needless_return)]
-        unsafe extern "C" fn collation_needed_callback(
+    pub(crate) fn collation_needed<F>(&mut self, x_coll_needed: Option<F>) -> Result<()>
+    where
+        F: FnMut(&Connection, &str) -> Result<()> + Send + 'static,
+    {
+        unsafe extern "C" fn collation_needed_callback<F>(
             arg1: *mut c_void,
             arg2: *mut ffi::sqlite3,
             e_text_rep: c_int,
             arg3: *const c_char,
-        ) {
+        ) where
+            F: FnMut(&Connection, &str) -> Result<()>,
+        {
             use std::ffi::CStr;
             use std::str;
 
@@ -125,26 +208,40 @@ impl InnerConnection {
                 return;
             }
 
-            let callback: fn(&Connection, &str) -> Result<()> = mem::transmute(arg1);
-            let res = catch_unwind(|| {
+            let res = catch_unwind(AssertUnwindSafe(|| {
+                let callback: *mut F = arg1.cast::<F>();
+                // a connection of its own, so that the callback can define
+                // the collation while this one is busy preparing
                 let conn = Connection::from_handle(arg2).unwrap();
                 let collation_name = {
                     let c_slice = CStr::from_ptr(arg3).to_bytes();
                     str::from_utf8(c_slice).expect("illegal collation sequence name")
                 };
-                callback(&conn, collation_name)
-            });
-            if res.is_err() {
-                return; // FIXME How ?
+                (*callback)(&conn, collation_name)
+            }));
+            if let Err(payload) = res {
+                stash_panic(payload);
             }
         }
 
-        let r = unsafe {
-            ffi::sqlite3_collation_needed(
-                self.db(),
-                x_coll_needed as *mut c_void,
-                Some(collation_needed_callback),
-            )
+        let r = match x_coll_needed {
+            Some(callback) => {
+                let boxed_callback = Box::new(callback);
+                let r = unsafe {
+                    ffi::sqlite3_collation_needed(
+                        self.db(),
+                        &*boxed_callback as *const F as *mut _,
+                        Some(collation_needed_callback::<F>),
+                    )
+                };
+                self.collation_needed = Some(boxed_callback);
+                r
+            }
+            None => {
+                let r = unsafe { ffi::sqlite3_collation_needed(self.db(), ptr::null_mut(), None) };
+                self.collation_needed = None;
+                r
+            }
         };
         self.decode_result(r)
     }
@@ -212,4 +309,89 @@ mod test {
         db.collation_needed(collation_needed)?;
         collate(db)
     }
+    #[test]
+    fn test_collation_needed_schema() -> Result<()> {
+        use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+        use std::sync::Arc;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+        {
+            let db = Connection::open(&path)?;
+            db.create_collation("mycoll", |a, b| b.cmp(a))?;
+            db.execute_batch(
+                "CREATE TABLE foo (bar TEXT COLLATE mycoll);
+                 INSERT INTO foo VALUES ('b'), ('c'), ('a');",
+            )?;
+        }
+
+        let db = Connection::open(&path)?;
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        db.collation_needed(move |db, name| {
+            counter.fetch_add(1, AtomicOrdering::SeqCst);
+            match name {
+                "mycoll" => db.create_collation(name, |a, b| b.cmp(a)),
+                _ => Ok(()),
+            }
+        })?;
+        let mut stmt = db.prepare("SELECT bar FROM foo ORDER BY bar")?;
+        let rows: Vec<String> = stmt.query_map([], |r| r.get(0))?.collect::<Result<_>>()?;
+        assert_eq!(rows, ["c", "b", "a"]);
+        assert_eq!(calls.load(AtomicOrdering::SeqCst), 1);
+
+        // still missing collations fail the statement
+        let err = db
+            .prepare("SELECT bar FROM foo ORDER BY bar COLLATE nocoll")
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("no such collation sequence"),
+            "{}",
+            err
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_numeric_compare() {
+        use super::numeric_compare;
+
+        let mut names = [
+            "file10", "file2", "file1", "file02", "a", "", "file", "file2b",
+        ];
+        names.sort_by(|a, b| numeric_compare(a, b));
+        assert_eq!(
+            names,
+            ["", "a", "file", "file1", "file2", "file2b", "file02", "file10"]
+        );
+        assert_eq!(numeric_compare("x007y", "x7y"), Ordering::Greater);
+        assert_eq!(numeric_compare("v1.10", "v1.9"), Ordering::Greater);
+        assert_eq!(numeric_compare("12", "12"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_collation_numeric() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.register_collation_numeric()?;
+        db.execute_batch(
+            "CREATE TABLE foo (bar TEXT COLLATE numeric);
+             INSERT INTO foo VALUES ('file10'), ('file2'), ('file1');",
+        )?;
+        let mut stmt = db.prepare("SELECT bar FROM foo ORDER BY bar")?;
+        let rows: Vec<String> = stmt.query_map([], |r| r.get(0))?.collect::<Result<_>>()?;
+        assert_eq!(rows, ["file1", "file2", "file10"]);
+        Ok(())
+    }
+
+    #[cfg(feature = "unicase")]
+    #[test]
+    fn test_collation_unicode_nocase() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.register_collation_unicode_nocase()?;
+        let equal: bool = db.one_column("SELECT 'Maße' = 'MASSE' COLLATE unicode_nocase")?;
+        assert!(equal);
+        let equal: bool = db.one_column("SELECT 'Maße' = 'MASSE' COLLATE nocase")?;
+        assert!(!equal);
+        Ok(())
+    }
 }
@@ -1,6 +1,6 @@
 use std::str;
 
-use crate::{Error, Result, Statement};
+use crate::{Error, Result, Statement, StatementStatus};
 
 /// Information about a column of a SQLite query.
 #[derive(Debug)]
@@ -25,6 +25,33 @@ impl Column<'_> {
     }
 }
 
+/// The table column a column of a query result is read from, as reported
+/// by [`Statement::column_origin`].
+#[cfg(feature = "column_metadata")]
+#[cfg_attr(docsrs, doc(cfg(feature = "column_metadata")))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct ColumnOrigin<'stmt> {
+    /// The database name, like `main` or the name of an attached database.
+    pub database: &'stmt str,
+    /// The table name.
+    pub table: &'stmt str,
+    /// The column name in the table, whatever alias the query gives it.
+    pub column: &'stmt str,
+}
+
+// `sqlite3_column_database_name` and friends only return something if SQLite
+// was built with SQLITE_ENABLE_COLUMN_METADATA.
+#[cfg(feature = "column_metadata")]
+fn column_metadata_enabled(option_used: impl Fn(&std::ffi::CStr) -> bool) -> bool {
+    option_used(std::ffi::CStr::from_bytes_with_nul(b"ENABLE_COLUMN_METADATA\0").unwrap())
+}
+
+#[cfg(feature = "column_metadata")]
+fn compile_option_used(option: &std::ffi::CStr) -> bool {
+    unsafe { crate::ffi::sqlite3_compileoption_used(option.as_ptr()) != 0 }
+}
+
 impl Statement<'_> {
     /// Get all the column names in the result set of the prepared statement.
     ///
@@ -104,6 +131,29 @@ impl Statement<'_> {
 
     /// Returns the column index in the result set for a given column name.
     ///
+    /// Names are compared ASCII case-insensitively and the first matching
+    /// column wins; see [`column_index_ci`](Statement::column_index_ci) to
+    /// prefer an exact match.
+    ///
+    /// Indices are cached by name until the statement is re-prepared, so
+    /// [`Row::get`](crate::Row::get) with a column name only searches the
+    /// columns once. Resolving the index before iterating over the rows
+    /// still saves a lookup per row:
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn total(conn: &Connection) -> Result<i64> {
+    ///     let mut stmt = conn.prepare("SELECT * FROM orders")?;
+    ///     let amount = stmt.column_index("amount")?;
+    ///     let mut rows = stmt.query([])?;
+    ///     let mut total = 0;
+    ///     while let Some(row) = rows.next()? {
+    ///         total += row.get::<_, i64>(amount)?;
+    ///     }
+    ///     Ok(total)
+    /// }
+    /// ```
+    ///
     /// If there is no AS clause then the name of the column is unspecified and
     /// may change from one release of SQLite to the next.
     ///
@@ -117,16 +167,52 @@ impl Statement<'_> {
     /// the specified `name`.
     #[inline]
     pub fn column_index(&self, name: &str) -> Result<usize> {
+        let bytes = name.as_bytes();
+        let matches = |i: usize| matches!(self.stmt.column_name(i), Some(column) if bytes.eq_ignore_ascii_case(column.to_bytes()));
+        let find = || (0..self.column_count()).find(|&i| matches(i));
+        let found = if self.stmt.is_null() {
+            find()
+        } else {
+            let reprepares = self.stmt.get_status(StatementStatus::RePrepare, false);
+            self.stmt
+                .column_index_cache()
+                .get_or_insert_with(name, reprepares, matches, find)
+        };
+        found.ok_or_else(|| Error::InvalidColumnName(String::from(name)))
+    }
+
+    /// Returns the column index in the result set for a given column name,
+    /// preferring a column whose name matches exactly over one that only
+    /// matches ASCII case-insensitively.
+    ///
+    /// If associated DB schema can be altered concurrently, you should make
+    /// sure that current statement has already been stepped once before
+    /// calling this method.
+    ///
+    /// # Failure
+    ///
+    /// Will return an `Error::InvalidColumnName` when there is no column with
+    /// the specified `name`. Unlike [`column_index`](Statement::column_index),
+    /// the error also lists the names of the available columns.
+    pub fn column_index_ci(&self, name: &str) -> Result<usize> {
         let bytes = name.as_bytes();
         let n = self.column_count();
+        let mut found = None;
         for i in 0..n {
-            // Note: `column_name` is only fallible if `i` is out of bounds,
-            // which we've already checked.
-            if bytes.eq_ignore_ascii_case(self.stmt.column_name(i).unwrap().to_bytes()) {
+            let column = self.stmt.column_name(i).unwrap().to_bytes();
+            if column == bytes {
                 return Ok(i);
             }
+            if found.is_none() && bytes.eq_ignore_ascii_case(column) {
+                found = Some(i);
+            }
         }
-        Err(Error::InvalidColumnName(String::from(name)))
+        found.ok_or_else(|| {
+            Error::InvalidColumnName(format!(
+                "{name} (available columns: {})",
+                self.column_names().join(", ")
+            ))
+        })
     }
 
     /// Returns a slice describing the columns of the result of the query.
@@ -141,14 +227,77 @@ impl Statement<'_> {
         let mut cols = Vec::with_capacity(n);
         for i in 0..n {
             let name = self.column_name_unwrap(i);
-            let slice = self.stmt.column_decltype(i);
-            let decl_type = slice.map(|s| {
-                str::from_utf8(s.to_bytes()).expect("Invalid UTF-8 sequence in column declaration")
-            });
+            let decl_type = self.column_decltype(i);
             cols.push(Column { name, decl_type });
         }
         cols
     }
+
+    /// Returns the declared type of the table column that column `col` of the
+    /// result is read from, or `None` if it is computed by an expression or
+    /// if `col` is out of range.
+    ///
+    /// Like [`Statement::columns`], this can be called before stepping the
+    /// statement.
+    #[cfg(feature = "column_decltype")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "column_decltype")))]
+    pub fn column_decltype(&self, col: usize) -> Option<&str> {
+        if col >= self.column_count() {
+            return None;
+        }
+        self.stmt.column_decltype(col).map(|s| {
+            str::from_utf8(s.to_bytes()).expect("Invalid UTF-8 sequence in column declaration")
+        })
+    }
+
+    /// Returns the table column that column `col` of the result is read
+    /// from, or `None` if it is computed by an expression, if `col` is out of
+    /// range, or if SQLite was built without `SQLITE_ENABLE_COLUMN_METADATA`
+    /// (the bundled build always has it).
+    ///
+    /// A column read through a view or a subquery is reported with the table
+    /// the view or subquery reads it from, not with the view itself.
+    ///
+    /// If associated DB schema can be altered concurrently, you should make
+    /// sure that current statement has already been stepped once before
+    /// calling this method.
+    #[cfg(feature = "column_metadata")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "column_metadata")))]
+    pub fn column_origin(&self, col: usize) -> Option<ColumnOrigin<'_>> {
+        if col >= self.column_count() || !column_metadata_enabled(compile_option_used) {
+            return None;
+        }
+        let (database, table, column) = self.stmt.column_origin(col)?;
+        Some(ColumnOrigin {
+            database: database.to_str().ok()?,
+            table: table.to_str().ok()?,
+            column: column.to_str().ok()?,
+        })
+    }
+
+    /// Returns the database name of [`Statement::column_origin`].
+    #[cfg(feature = "column_metadata")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "column_metadata")))]
+    #[inline]
+    pub fn column_database_name(&self, col: usize) -> Option<&str> {
+        self.column_origin(col).map(|origin| origin.database)
+    }
+
+    /// Returns the table name of [`Statement::column_origin`].
+    #[cfg(feature = "column_metadata")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "column_metadata")))]
+    #[inline]
+    pub fn column_table_name(&self, col: usize) -> Option<&str> {
+        self.column_origin(col).map(|origin| origin.table)
+    }
+
+    /// Returns the table column name of [`Statement::column_origin`].
+    #[cfg(feature = "column_metadata")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "column_metadata")))]
+    #[inline]
+    pub fn column_origin_name(&self, col: usize) -> Option<&str> {
+        self.column_origin(col).map(|origin| origin.column)
+    }
 }
 
 #[cfg(test)]
@@ -183,6 +332,128 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    #[cfg(feature = "column_decltype")]
+    fn test_column_decltype() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(id INTEGER PRIMARY KEY, x VARCHAR(10), y)")?;
+        let stmt = db.prepare("SELECT id, x, y, x || y FROM foo")?;
+        assert_eq!(stmt.column_decltype(0), Some("INTEGER"));
+        assert_eq!(stmt.column_decltype(1), Some("VARCHAR(10)"));
+        assert_eq!(stmt.column_decltype(2), None);
+        assert_eq!(stmt.column_decltype(3), None);
+        assert_eq!(stmt.column_decltype(4), None);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "column_metadata")]
+    fn test_column_origin() -> Result<()> {
+        use super::ColumnOrigin;
+
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "ATTACH ':memory:' AS aux;
+             CREATE TABLE foo(id INTEGER PRIMARY KEY, x INTEGER);
+             CREATE TABLE aux.bar(y TEXT);
+             CREATE VIEW foo_view AS SELECT x AS view_x FROM foo;",
+        )?;
+        let origin = |table, column| ColumnOrigin {
+            database: "main",
+            table,
+            column,
+        };
+        let stmt = db.prepare(
+            "SELECT id, x AS alias, x + 1, view_x, sub_x, y \
+             FROM foo, foo_view, (SELECT x AS sub_x FROM foo), bar",
+        )?;
+        assert_eq!(stmt.column_origin(0), Some(origin("foo", "id")));
+        assert_eq!(stmt.column_origin(1), Some(origin("foo", "x")));
+        assert_eq!(stmt.column_origin(2), None);
+        // views and subqueries report the table underneath
+        assert_eq!(stmt.column_origin(3), Some(origin("foo", "x")));
+        assert_eq!(stmt.column_origin(4), Some(origin("foo", "x")));
+        assert_eq!(
+            stmt.column_origin(5),
+            Some(ColumnOrigin {
+                database: "aux",
+                table: "bar",
+                column: "y"
+            })
+        );
+        assert_eq!(stmt.column_origin(6), None);
+
+        assert_eq!(stmt.column_database_name(5), Some("aux"));
+        assert_eq!(stmt.column_table_name(1), Some("foo"));
+        assert_eq!(stmt.column_origin_name(1), Some("x"));
+        assert_eq!(stmt.column_table_name(2), None);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "column_metadata")]
+    fn test_column_metadata_enabled() {
+        use super::column_metadata_enabled;
+
+        assert!(!column_metadata_enabled(|_| false));
+        assert!(column_metadata_enabled(
+            |option| option.to_bytes() == b"ENABLE_COLUMN_METADATA"
+        ));
+    }
+
+    #[test]
+    fn test_column_index_duplicates() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        let stmt = db.prepare("SELECT 1 AS x, 2 AS X, 3 AS y")?;
+        for _ in 0..2 {
+            assert_eq!(stmt.column_index("x")?, 0);
+            assert_eq!(stmt.column_index("X")?, 0);
+            assert_eq!(stmt.column_index("Y")?, 2);
+            stmt.column_index("z").unwrap_err();
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_column_index_schema_change() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE t(a, b); INSERT INTO t VALUES (1, 2);")?;
+        let mut stmt = db.prepare("SELECT * FROM t")?;
+        let b: i64 = stmt.query_row([], |row| row.get("b"))?;
+        assert_eq!(b, 2);
+        assert_eq!(stmt.column_index("b")?, 1);
+
+        db.execute_batch("DROP TABLE t; CREATE TABLE t(b, c, a); INSERT INTO t VALUES (3, 4, 5);")?;
+        let (b, a): (i64, i64) = stmt.query_row([], |row| Ok((row.get("b")?, row.get("a")?)))?;
+        assert_eq!((b, a), (3, 5));
+        assert_eq!(stmt.column_index("b")?, 0);
+        assert_eq!(stmt.column_index("c")?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_column_index_ci() -> Result<()> {
+        use crate::Error;
+
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(UserId INTEGER, name TEXT)")?;
+        let stmt = db.prepare("SELECT userid + 1 AS userid, UserId, name AS Label FROM foo")?;
+        assert_eq!(stmt.column_index_ci("UserId")?, 1);
+        assert_eq!(stmt.column_index_ci("userid")?, 0);
+        assert_eq!(stmt.column_index_ci("USERID")?, 0);
+        assert_eq!(stmt.column_index_ci("label")?, 2);
+        // `column_index` takes the first case-insensitive match
+        assert_eq!(stmt.column_index("UserId")?, 0);
+
+        match stmt.column_index_ci("name").unwrap_err() {
+            Error::InvalidColumnName(msg) => {
+                assert_eq!(msg, "name (available columns: userid, UserId, Label)");
+            }
+            e => panic!("Unexpected error {}", e),
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_column_name_in_error() -> Result<()> {
         use crate::{types::Type, Error};
@@ -1,14 +1,157 @@
 //! Configure database connections
+//!
+//! ## Page cache
+//!
+//! Each connection owns a private page cache, sized with
+//! `PRAGMA cache_size`. [`set_default_cache_size`] applies a size to every
+//! connection opened afterwards, and [`Connection::cache_stats`] reports how
+//! a connection's cache is being used.
+//! [`stats::reported_cache_stats`](crate::stats::reported_cache_stats) sums
+//! the latest of these reports of each connection.
+//!
+//! Connections to the same database file in one process can instead share a
+//! single cache by opening them with
+//! [`OpenFlags::SQLITE_OPEN_SHARED_CACHE`](crate::OpenFlags::SQLITE_OPEN_SHARED_CACHE).
+//! Shared-cache connections use table-level locking between themselves: a
+//! statement touching a table that another connection holds a conflicting
+//! lock on fails immediately with `SQLITE_LOCKED` (reported as
+//! [`ErrorCode::DatabaseLocked`](crate::ErrorCode::DatabaseLocked)) rather
+//! than waiting on the busy handler. With the `unlock_notify` feature, the
+//! statement instead blocks until the lock is released. See
+//! [SQLite Shared-Cache Mode](https://sqlite.org/sharedcache.html).
+//!
+//! ## Temporary files
+//!
+//! Sorts, temporary tables and indices, and statement journals may spill to
+//! temporary files. [`Connection::set_temp_store`] keeps them in memory
+//! instead, and [`set_temp_directory`] chooses where they are created for
+//! the whole process. See
+//! [Temporary Files Used By SQLite](https://sqlite.org/tempfiles.html).
 
-use std::os::raw::c_int;
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::os::raw::{c_char, c_int, c_void};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
 
 use crate::error::check;
 use crate::ffi;
-use crate::{Connection, Result};
+use crate::inner_connection::OPEN_CONNECTIONS;
+use crate::status::DbStatus;
+use crate::{Connection, Error, Result};
+
+static DEFAULT_CACHE_SIZE: AtomicU32 = AtomicU32::new(0);
+
+/// Sets the page cache size, in KiB, applied with `PRAGMA cache_size` to
+/// every connection opened afterwards. `0` (the default) leaves SQLite's own
+/// default in place.
+#[inline]
+pub fn set_default_cache_size(kib: u32) {
+    DEFAULT_CACHE_SIZE.store(kib, Ordering::Relaxed);
+}
+
+/// Returns the page cache size, in KiB, set by [`set_default_cache_size`].
+#[inline]
+#[must_use]
+pub fn default_cache_size() -> u32 {
+    DEFAULT_CACHE_SIZE.load(Ordering::Relaxed)
+}
+
+pub(crate) fn apply_defaults(conn: &Connection) -> Result<()> {
+    apply_cache_size(conn, default_cache_size())
+}
+
+fn apply_cache_size(conn: &Connection, kib: u32) -> Result<()> {
+    if kib != 0 {
+        // a negative cache_size is a size in KiB rather than in pages
+        conn.pragma_update(None, "cache_size", -i64::from(kib))?;
+    }
+    Ok(())
+}
+
+/// Sets the directory SQLite creates its temporary files in, for every
+/// connection of the process.
+///
+/// This sets the global
+/// [`sqlite3_temp_directory`](https://sqlite.org/c3ref/temp_directory.html)
+/// variable, which SQLite reads without any locking: it must only be changed
+/// while no connection is open, and never concurrently with another thread
+/// using SQLite outside of rusqlite.
+///
+/// # Failure
+///
+/// Will return `Err` if a connection opened by rusqlite is still open, or if
+/// `path` is not an existing directory.
+pub fn set_temp_directory<P: AsRef<Path>>(path: P) -> Result<()> {
+    let path = path.as_ref();
+    if OPEN_CONNECTIONS.load(Ordering::SeqCst) != 0 {
+        return Err(Error::SqliteFailure(
+            ffi::Error::new(ffi::SQLITE_MISUSE),
+            Some("the temporary directory cannot change while a connection is open".to_owned()),
+        ));
+    }
+    if !path.is_dir() {
+        return Err(Error::InvalidPath(path.to_owned()));
+    }
+    let path = crate::path_to_cstring(path)?;
+    let bytes = path.as_bytes_with_nul();
+    unsafe {
+        // SQLite frees the previous value with sqlite3_free, so the new one
+        // must come from sqlite3_malloc
+        let copy = ffi::sqlite3_malloc(bytes.len() as c_int).cast::<c_char>();
+        if copy.is_null() {
+            return Err(crate::error::error_from_sqlite_code(
+                ffi::SQLITE_NOMEM,
+                None,
+            ));
+        }
+        ptr::copy_nonoverlapping(bytes.as_ptr().cast::<c_char>(), copy, bytes.len());
+        let old = ffi::sqlite3_temp_directory;
+        ffi::sqlite3_temp_directory = copy;
+        ffi::sqlite3_free(old.cast::<c_void>());
+    }
+    Ok(())
+}
+
+/// Where temporary tables and indices are stored, as set by
+/// [`Connection::set_temp_store`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TempStore {
+    /// The compile-time default, `SQLITE_TEMP_STORE`.
+    Default = 0,
+    /// In temporary files.
+    File = 1,
+    /// In memory.
+    Memory = 2,
+}
+
+/// Page cache usage of a connection, as reported by
+/// [`Connection::cache_stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CacheStats {
+    /// Bytes of heap memory used by the page cache.
+    pub used_bytes: i64,
+    /// Number of page cache hits.
+    pub hit: i64,
+    /// Number of page cache misses.
+    pub miss: i64,
+    /// Number of dirty pages written to disk in the middle of a transaction
+    /// because the cache was full. Always `0` before SQLite 3.23.0.
+    pub spill: i64,
+    /// Number of dirty pages written to disk.
+    pub dirty: i64,
+}
 
 /// Database Connection Configuration Options
 /// See [Database Connection Configuration Options](https://sqlite.org/c3ref/c_dbconfig_enable_fkey.html) for details.
+///
+/// This covers the boolean options; `SQLITE_DBCONFIG_MAINDBNAME` is set with
+/// [`Connection::set_main_db_name`].
 #[repr(i32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[allow(non_snake_case, non_camel_case_types)]
 #[non_exhaustive]
 #[allow(clippy::upper_case_acronyms)]
@@ -22,7 +165,9 @@ pub enum DbConfig {
     /// Enable or disable the fts3_tokenizer() function which is part of the
     /// FTS3 full-text search engine extension.
     SQLITE_DBCONFIG_ENABLE_FTS3_TOKENIZER = ffi::SQLITE_DBCONFIG_ENABLE_FTS3_TOKENIZER, // 3.12.0
-    //SQLITE_DBCONFIG_ENABLE_LOAD_EXTENSION = 1005,
+    /// Enable or disable the `sqlite3_load_extension()` C interface, but not
+    /// the `load_extension()` SQL function.
+    SQLITE_DBCONFIG_ENABLE_LOAD_EXTENSION = 1005, // 3.13.0
     /// In WAL mode, enable or disable the checkpoint operation before closing
     /// the connection.
     SQLITE_DBCONFIG_NO_CKPT_ON_CLOSE = 1006, // 3.16.2
@@ -64,6 +209,91 @@ pub enum DbConfig {
 }
 
 impl Connection {
+    /// Returns the page cache usage of this connection.
+    ///
+    /// The counters are cumulative over the life of the connection. The
+    /// result replaces the previous one of this connection in
+    /// [`stats::reported_cache_stats`](crate::stats::reported_cache_stats).
+    pub fn cache_stats(&self) -> Result<CacheStats> {
+        let current = |status| self.db_status(status, false).map(|(current, _)| current);
+        let stats = CacheStats {
+            used_bytes: current(DbStatus::SQLITE_DBSTATUS_CACHE_USED)?,
+            hit: current(DbStatus::SQLITE_DBSTATUS_CACHE_HIT)?, // 3.7.9
+            miss: current(DbStatus::SQLITE_DBSTATUS_CACHE_MISS)?, // 3.7.9
+            spill: current(DbStatus::SQLITE_DBSTATUS_CACHE_SPILL).unwrap_or(0), // 3.23.0
+            dirty: current(DbStatus::SQLITE_DBSTATUS_CACHE_WRITE)?, // 3.7.9
+        };
+        let mut db = self.db.borrow_mut();
+        crate::stats::report_cache_stats(&db.reported_cache_stats, &stats);
+        db.reported_cache_stats = stats;
+        Ok(stats)
+    }
+
+    /// Returns whether dirty pages may be written to the database file in
+    /// the middle of a transaction when the page cache is full.
+    #[inline]
+    pub fn cache_spill(&self) -> Result<bool> {
+        self.pragma_query_value(None, "cache_spill", |row| row.get::<_, i64>(0))
+            .map(|v| v != 0)
+    }
+
+    /// Allows or forbids writing dirty pages to the database file in the
+    /// middle of a transaction when the page cache is full.
+    /// (See [PRAGMA cache_spill](https://sqlite.org/pragma.html#pragma_cache_spill))
+    #[inline]
+    pub fn set_cache_spill(&self, enabled: bool) -> Result<()> {
+        self.pragma_update(None, "cache_spill", enabled)
+    }
+
+    /// Returns where temporary tables and indices are stored.
+    #[inline]
+    pub fn temp_store(&self) -> Result<TempStore> {
+        let value: i64 = self.pragma_query_value(None, "temp_store", |row| row.get(0))?;
+        Ok(match value {
+            1 => TempStore::File,
+            2 => TempStore::Memory,
+            _ => TempStore::Default,
+        })
+    }
+
+    /// Sets where temporary tables and indices are stored.
+    /// (See [PRAGMA temp_store](https://sqlite.org/pragma.html#pragma_temp_store))
+    ///
+    /// SQLite ignores this if it was built with `SQLITE_TEMP_STORE` set to 0
+    /// (always files) or 3 (always memory).
+    #[inline]
+    pub fn set_temp_store(&self, store: TempStore) -> Result<()> {
+        self.pragma_update(None, "temp_store", store as i64)
+    }
+
+    /// Returns the directory the temporary files of the main database are
+    /// created in, as found by asking its VFS for a temporary file name with
+    /// [`SQLITE_FCNTL_TEMPFILENAME`](https://sqlite.org/c3ref/c_fcntl_begin_atomic_write.html#sqlitefcntltempfilename).
+    ///
+    /// This is a best effort: `None` is returned if the main database is not
+    /// backed by a file, if its VFS does not support the request, or if the
+    /// name is not a path. It reflects [`set_temp_directory`], but not
+    /// [`set_temp_store`](Connection::set_temp_store).
+    pub fn temp_directory_effective(&self) -> Option<PathBuf> {
+        let c = self.db.borrow();
+        let name = unsafe {
+            let mut name: *mut c_char = ptr::null_mut();
+            let rc = ffi::sqlite3_file_control(
+                c.db(),
+                ptr::null(),
+                ffi::SQLITE_FCNTL_TEMPFILENAME,
+                (&mut name as *mut *mut c_char).cast::<c_void>(),
+            );
+            if rc != ffi::SQLITE_OK || name.is_null() {
+                return None;
+            }
+            let path = CStr::from_ptr(name).to_str().ok().map(PathBuf::from);
+            ffi::sqlite3_free(name.cast::<c_void>());
+            path
+        };
+        name?.parent().map(Path::to_path_buf)
+    }
+
     /// Returns the current value of a `config`.
     ///
     /// - `SQLITE_DBCONFIG_ENABLE_FKEY`: return `false` or `true` to indicate
@@ -78,17 +308,22 @@ impl Connection {
     ///   whether the QPSG is disabled or enabled
     /// - `SQLITE_DBCONFIG_TRIGGER_EQP`: return `false` to indicate
     ///   output-for-trigger are not disabled or `true` if it is
+    ///
+    /// The other options report whether they are on.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the SQLite library in use does not support
+    /// `config`.
     #[inline]
     pub fn db_config(&self, config: DbConfig) -> Result<bool> {
         let c = self.db.borrow();
         unsafe {
             let mut val = 0;
-            check(ffi::sqlite3_db_config(
-                c.db(),
-                config as c_int,
-                -1,
-                &mut val,
-            ))?;
+            check_db_config(
+                format_args!("{config:?}"),
+                ffi::sqlite3_db_config(c.db(), config as c_int, -1, &mut val),
+            )?;
             Ok(val != 0)
         }
     }
@@ -107,26 +342,77 @@ impl Connection {
     ///   enable QPSG
     /// - `SQLITE_DBCONFIG_TRIGGER_EQP`: `false` to disable output for trigger
     ///   programs, `true` to enable it
+    /// - `SQLITE_DBCONFIG_DEFENSIVE`: `true` to disallow language features
+    ///   which can corrupt the database, e.g. writing to `sqlite_master` with
+    ///   `PRAGMA writable_schema=ON`
+    ///
+    /// Returns the new value of `config`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the SQLite library in use does not support
+    /// `config`.
     #[inline]
     pub fn set_db_config(&self, config: DbConfig, new_val: bool) -> Result<bool> {
         let c = self.db.borrow_mut();
         unsafe {
             let mut val = 0;
-            check(ffi::sqlite3_db_config(
-                c.db(),
-                config as c_int,
-                new_val as c_int,
-                &mut val,
-            ))?;
+            check_db_config(
+                format_args!("{config:?}"),
+                ffi::sqlite3_db_config(c.db(), config as c_int, new_val as c_int, &mut val),
+            )?;
             Ok(val != 0)
         }
     }
+
+    /// Rename the main database of this connection, e.g. for SQL which
+    /// qualifies table names with a schema name other than `main`, with
+    /// `SQLITE_DBCONFIG_MAINDBNAME`.
+    ///
+    /// The database can then be referred to by `name`, with
+    /// [`DatabaseName::Attached`](crate::DatabaseName::Attached), as well as
+    /// by `main`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `name` contains a nul byte, or if the underlying
+    /// SQLite call fails.
+    pub fn set_main_db_name(&self, name: &str) -> Result<()> {
+        let name = CString::new(name)?;
+        let mut c = self.db.borrow_mut();
+        unsafe {
+            check_db_config(
+                "SQLITE_DBCONFIG_MAINDBNAME",
+                ffi::sqlite3_db_config(c.db(), SQLITE_DBCONFIG_MAINDBNAME, name.as_ptr()),
+            )?;
+        }
+        // SQLite keeps the pointer, not a copy
+        c.main_db_name = Some(name);
+        Ok(())
+    }
+}
+
+// Not in the bindings of SQLite 3.14.0.
+const SQLITE_DBCONFIG_MAINDBNAME: c_int = 1000; // 3.15.0
+
+// SQLite returns `SQLITE_ERROR` for options it does not know.
+fn check_db_config(option: impl fmt::Display, code: c_int) -> Result<()> {
+    if code == ffi::SQLITE_ERROR {
+        return Err(Error::SqliteFailure(
+            ffi::Error::new(code),
+            Some(format!(
+                "{option} is not supported by SQLite {}",
+                crate::version()
+            )),
+        ));
+    }
+    check(code)
 }
 
 #[cfg(test)]
 mod test {
-    use super::DbConfig;
-    use crate::{Connection, Result};
+    use super::{DbConfig, TempStore};
+    use crate::{Connection, DatabaseName, Result};
 
     #[test]
     fn test_db_config() -> Result<()> {
@@ -153,4 +439,186 @@ mod test {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_defensive() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(x)")?;
+        let update_schema = "PRAGMA writable_schema=ON;
+             UPDATE sqlite_master SET sql = sql WHERE name = 'foo';
+             PRAGMA writable_schema=OFF;";
+
+        assert!(db.set_db_config(DbConfig::SQLITE_DBCONFIG_DEFENSIVE, true)?);
+        assert!(db.db_config(DbConfig::SQLITE_DBCONFIG_DEFENSIVE)?);
+        let err = db.execute_batch(update_schema).unwrap_err();
+        assert!(err.to_string().contains("may not be modified"), "{}", err);
+        db.execute_batch("PRAGMA writable_schema=OFF")?;
+
+        assert!(!db.set_db_config(DbConfig::SQLITE_DBCONFIG_DEFENSIVE, false)?);
+        db.execute_batch(update_schema)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_main_db_name() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.set_main_db_name("app")?;
+        db.execute_batch("CREATE TABLE app.foo(x); INSERT INTO main.foo VALUES (1);")?;
+        let n: i64 = db.one_column("SELECT count(*) FROM app.foo")?;
+        assert_eq!(n, 1);
+        assert!(!db.is_readonly(DatabaseName::Attached("app"))?);
+        assert!(db.set_main_db_name("nul\0").is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_default_cache_size() -> Result<()> {
+        // not through `set_default_cache_size`, which would apply to the
+        // connections of the tests running meanwhile
+        let db = Connection::open_in_memory()?;
+        let cache_size = || db.pragma_query_value(None, "cache_size", |r| r.get::<_, i64>(0));
+        let default = cache_size()?;
+        super::apply_cache_size(&db, 0)?;
+        assert_eq!(cache_size()?, default);
+        super::apply_cache_size(&db, 1234)?;
+        assert_eq!(cache_size()?, -1234);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_spill() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.set_cache_spill(false)?;
+        assert!(!db.cache_spill()?);
+        db.set_cache_spill(true)?;
+        assert!(db.cache_spill()?);
+        Ok(())
+    }
+
+    fn misses_on_rescan(cache_size: i64) -> Result<i64> {
+        let temp = tempfile::tempdir().unwrap();
+        let db = Connection::open(temp.path().join("cache.db3"))?;
+        db.execute_batch(
+            "CREATE TABLE foo (x BLOB);
+             WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 1000)
+             INSERT INTO foo SELECT zeroblob(500) FROM n;",
+        )?;
+        db.pragma_update(None, "cache_size", cache_size)?;
+        let scan = || {
+            db.query_row("SELECT count(*) FROM foo WHERE x IS NOT NULL", [], |_| {
+                Ok(())
+            })
+        };
+        scan()?;
+        let before = db.cache_stats()?;
+        scan()?;
+        let after = db.cache_stats()?;
+        assert!(after.hit > before.hit);
+        assert!(after.used_bytes > 0);
+        Ok(after.miss - before.miss)
+    }
+
+    #[test]
+    fn test_cache_stats() -> Result<()> {
+        // the whole table fits in a large cache, so a second scan never misses
+        assert_eq!(misses_on_rescan(2000)?, 0);
+        // but a small cache has evicted the pages read at the start of the scan
+        assert!(misses_on_rescan(10)? > 0);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(not(feature = "unlock_notify"))]
+    fn test_shared_cache_locked() -> Result<()> {
+        use crate::{ErrorCode, OpenFlags};
+
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("shared.db3");
+        let flags = OpenFlags::default() | OpenFlags::SQLITE_OPEN_SHARED_CACHE;
+        let db1 = Connection::open_with_flags(&path, flags)?;
+        let db2 = Connection::open_with_flags(&path, flags)?;
+        db1.execute_batch("CREATE TABLE foo (x INTEGER); BEGIN; INSERT INTO foo VALUES (1);")?;
+
+        let err = db2
+            .query_row("SELECT count(*) FROM foo", [], |r| r.get::<_, i64>(0))
+            .unwrap_err();
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::DatabaseLocked));
+
+        db1.execute_batch("COMMIT")?;
+        let count: i64 = db2.query_row("SELECT count(*) FROM foo", [], |r| r.get(0))?;
+        assert_eq!(count, 1);
+        Ok(())
+    }
+
+    // a temporary table too large for the page cache, which a sort must
+    // spill unless temporary storage is in memory
+    fn big_temp_table(db: &Connection) -> Result<()> {
+        db.execute_batch(
+            "PRAGMA cache_size = 10;
+             CREATE TEMP TABLE big (x BLOB);
+             WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 2000)
+             INSERT INTO big SELECT randomblob(1000) FROM n;",
+        )
+    }
+
+    #[test]
+    fn test_temp_store() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        assert_eq!(db.temp_store()?, TempStore::Default);
+        db.set_temp_store(TempStore::File)?;
+        assert_eq!(db.temp_store()?, TempStore::File);
+        db.set_temp_store(TempStore::Memory)?;
+        assert_eq!(db.temp_store()?, TempStore::Memory);
+        big_temp_table(&db)?;
+        let count: i64 = db.query_row(
+            "SELECT count(*) FROM (SELECT x FROM big ORDER BY x)",
+            [],
+            |r| r.get(0),
+        )?;
+        assert_eq!(count, 2000);
+        Ok(())
+    }
+
+    // temporary files are unlinked as soon as they are created, so they are
+    // found through the file descriptors of the process instead
+    #[cfg(target_os = "linux")]
+    fn open_temp_files() -> usize {
+        std::fs::read_dir("/proc/self/fd")
+            .unwrap()
+            .filter_map(|fd| std::fs::read_link(fd.ok()?.path()).ok())
+            .filter(|target| target.to_string_lossy().contains("etilqs_"))
+            .count()
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_temp_store_memory_creates_no_files() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.set_temp_store(TempStore::Memory)?;
+        big_temp_table(&db)?;
+        let mut stmt = db.prepare("SELECT x FROM big ORDER BY x")?;
+        let mut rows = stmt.query([])?;
+        // the sort is complete once the first row is returned
+        assert!(rows.next()?.is_some());
+        assert_eq!(open_temp_files(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_temp_directory_effective() -> Result<()> {
+        let temp = tempfile::tempdir().unwrap();
+        let db = Connection::open(temp.path().join("temp.db3"))?;
+        if let Some(dir) = db.temp_directory_effective() {
+            assert!(dir.is_dir(), "{:?}", dir);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_temp_directory_with_open_connection() -> Result<()> {
+        let temp = tempfile::tempdir().unwrap();
+        let _db = Connection::open_in_memory()?;
+        let err = super::set_temp_directory(temp.path()).unwrap_err();
+        assert!(err.to_string().contains("connection is open"), "{}", err);
+        Ok(())
+    }
 }
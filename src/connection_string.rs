@@ -0,0 +1,421 @@
+//! DSN-style connection strings, e.g. for configuring a database through a
+//! single environment variable:
+//!
+//! ```text
+//! sqlite:./data/app.db?mode=rwc&cache=shared&busy_timeout=5000&journal_mode=WAL
+//! ```
+//!
+//! The `sqlite:` scheme is optional and may be followed by `//`. The path is
+//! percent-decoded and may be `:memory:` or a `file:` URI. A Windows drive
+//! letter may be written after a slash (`sqlite:/C:/data/app.db`). The query
+//! parameters are:
+//!
+//! | Parameter      | Values                        | Effect                         |
+//! |----------------|-------------------------------|--------------------------------|
+//! | `mode`         | `ro`, `rw`, `rwc`, `memory`   | [`OpenFlags`] access mode      |
+//! | `cache`        | `shared`, `private`           | [`OpenFlags`] cache mode       |
+//! | `vfs`          | VFS name                      | VFS to open the database with  |
+//! | `busy_timeout` | milliseconds                  | [`Connection::busy_timeout`]   |
+//! | `journal_mode` | journal mode                  | `PRAGMA journal_mode`          |
+//! | `synchronous`  | `off`, `normal`, `full`, `extra` | `PRAGMA synchronous`        |
+//! | `foreign_keys` | `on`, `off`, `true`, `false`, `1`, `0` | `PRAGMA foreign_keys`  |
+//! | `cache_size`   | pages, or KiB if negative     | `PRAGMA cache_size`            |
+//!
+//! Any other parameter is an error.
+
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::ffi;
+use crate::{Connection, Error, OpenFlags, Result};
+
+const PARAMS: &[&str] = &[
+    "mode",
+    "cache",
+    "vfs",
+    "busy_timeout",
+    "journal_mode",
+    "synchronous",
+    "foreign_keys",
+    "cache_size",
+];
+
+fn invalid(msg: String) -> Error {
+    Error::SqliteFailure(ffi::Error::new(ffi::SQLITE_MISUSE), Some(msg))
+}
+
+/// A parsed connection string. See the [module documentation](self) for
+/// the syntax.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConnectionString {
+    path: String,
+    flags: OpenFlags,
+    vfs: Option<String>,
+    busy_timeout: Option<Duration>,
+    pragmas: Vec<(&'static str, String)>,
+    // the parameters as given, for `Display`
+    params: Vec<(String, String)>,
+}
+
+impl ConnectionString {
+    /// Parse a connection string.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the string is not valid percent-encoded UTF-8, or
+    /// if a parameter is unknown, repeated or has an invalid value.
+    pub fn parse(s: &str) -> Result<ConnectionString> {
+        let rest = s.strip_prefix("sqlite:").unwrap_or(s);
+        let rest = rest.strip_prefix("//").unwrap_or(rest);
+        let (path, query) = match rest.find('?') {
+            Some(i) => (&rest[..i], Some(&rest[i + 1..])),
+            None => (rest, None),
+        };
+        let mut path = percent_decode(path)?;
+        // `/C:/...` is a Windows drive letter written after the scheme
+        let b = path.as_bytes();
+        if b.len() >= 3 && b[0] == b'/' && b[1].is_ascii_alphabetic() && b[2] == b':' {
+            path.remove(0);
+        }
+        if path.is_empty() {
+            return Err(invalid(format!("no database path in {s:?}")));
+        }
+
+        let mut conn_str = ConnectionString {
+            path,
+            flags: OpenFlags::default(),
+            vfs: None,
+            busy_timeout: None,
+            pragmas: Vec::new(),
+            params: Vec::new(),
+        };
+        for pair in query.into_iter().flat_map(|q| q.split('&')) {
+            if pair.is_empty() {
+                continue;
+            }
+            let (key, value) = match pair.find('=') {
+                Some(i) => (percent_decode(&pair[..i])?, percent_decode(&pair[i + 1..])?),
+                None => (percent_decode(pair)?, String::new()),
+            };
+            conn_str.apply(&key, &value)?;
+            conn_str.params.push((key, value));
+        }
+        Ok(conn_str)
+    }
+
+    fn apply(&mut self, key: &str, value: &str) -> Result<()> {
+        let bad_value = || invalid(format!("invalid value {value:?} for parameter {key:?}"));
+        if self.params.iter().any(|(k, _)| k == key) {
+            return Err(invalid(format!("parameter {key:?} given more than once")));
+        }
+        match key {
+            "mode" => {
+                let access = OpenFlags::SQLITE_OPEN_READ_ONLY
+                    | OpenFlags::SQLITE_OPEN_READ_WRITE
+                    | OpenFlags::SQLITE_OPEN_CREATE
+                    | OpenFlags::SQLITE_OPEN_MEMORY;
+                self.flags.remove(access);
+                self.flags |= match value {
+                    "ro" => OpenFlags::SQLITE_OPEN_READ_ONLY,
+                    "rw" => OpenFlags::SQLITE_OPEN_READ_WRITE,
+                    "rwc" => OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE,
+                    "memory" => {
+                        OpenFlags::SQLITE_OPEN_READ_WRITE
+                            | OpenFlags::SQLITE_OPEN_CREATE
+                            | OpenFlags::SQLITE_OPEN_MEMORY
+                    }
+                    _ => return Err(bad_value()),
+                };
+            }
+            "cache" => {
+                self.flags |= match value {
+                    "shared" => OpenFlags::SQLITE_OPEN_SHARED_CACHE,
+                    "private" => OpenFlags::SQLITE_OPEN_PRIVATE_CACHE,
+                    _ => return Err(bad_value()),
+                };
+            }
+            "vfs" if !value.is_empty() => self.vfs = Some(value.to_owned()),
+            "busy_timeout" => {
+                let ms = value.parse::<u32>().map_err(|_| bad_value())?;
+                self.busy_timeout = Some(Duration::from_millis(ms.into()));
+            }
+            "journal_mode" => {
+                if !value.bytes().all(|b| b.is_ascii_alphabetic()) || value.is_empty() {
+                    return Err(bad_value());
+                }
+                self.pragmas.push(("journal_mode", value.to_owned()));
+            }
+            "synchronous" => {
+                let known = ["off", "normal", "full", "extra", "0", "1", "2", "3"];
+                if !known.iter().any(|k| value.eq_ignore_ascii_case(k)) {
+                    return Err(bad_value());
+                }
+                self.pragmas.push(("synchronous", value.to_owned()));
+            }
+            "foreign_keys" => {
+                let known = ["on", "off", "true", "false", "yes", "no", "1", "0"];
+                if !known.iter().any(|k| value.eq_ignore_ascii_case(k)) {
+                    return Err(bad_value());
+                }
+                self.pragmas.push(("foreign_keys", value.to_owned()));
+            }
+            "cache_size" => {
+                value.parse::<i64>().map_err(|_| bad_value())?;
+                self.pragmas.push(("cache_size", value.to_owned()));
+            }
+            "vfs" => return Err(bad_value()),
+            _ => {
+                return Err(invalid(format!(
+                    "unknown parameter {key:?}, expected one of: {}",
+                    PARAMS.join(", ")
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    /// The database path, after percent-decoding.
+    #[inline]
+    #[must_use]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The flags the database will be opened with.
+    #[inline]
+    #[must_use]
+    pub fn flags(&self) -> OpenFlags {
+        self.flags
+    }
+
+    /// The VFS the database will be opened with, if not the default one.
+    #[inline]
+    #[must_use]
+    pub fn vfs(&self) -> Option<&str> {
+        self.vfs.as_deref()
+    }
+
+    /// Open a connection as described by this connection string.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the database cannot be opened, or if setting one
+    /// of the parameters fails.
+    pub fn open(&self) -> Result<Connection> {
+        let conn = match self.vfs {
+            Some(ref vfs) => Connection::open_with_flags_and_vfs(&self.path, self.flags, vfs)?,
+            None => Connection::open_with_flags(&self.path, self.flags)?,
+        };
+        if let Some(timeout) = self.busy_timeout {
+            conn.busy_timeout(timeout)?;
+        }
+        for (name, value) in &self.pragmas {
+            if *name == "journal_mode" {
+                // returns the new mode, which may differ, e.g. for `:memory:`
+                conn.pragma_update_and_check(None, name, value, |_| Ok(()))?;
+            } else {
+                conn.pragma_update(None, name, value)?;
+            }
+        }
+        Ok(conn)
+    }
+}
+
+impl FromStr for ConnectionString {
+    type Err = Error;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<ConnectionString> {
+        ConnectionString::parse(s)
+    }
+}
+
+impl fmt::Display for ConnectionString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sqlite:{}", percent_encode(&self.path))?;
+        for (i, (key, value)) in self.params.iter().enumerate() {
+            let sep = if i == 0 { '?' } else { '&' };
+            write!(f, "{sep}{}={}", percent_encode(key), percent_encode(value))?;
+        }
+        Ok(())
+    }
+}
+
+fn percent_decode(s: &str) -> Result<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let byte = s
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| invalid(format!("invalid percent-encoding in {s:?}")))?;
+            out.push(byte);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).map_err(|_| invalid(format!("{s:?} does not decode to UTF-8")))
+}
+
+fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~/:\\".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{b:02X}"));
+        }
+    }
+    out
+}
+
+impl Connection {
+    /// Open a connection described by a connection string, as parsed by
+    /// [`ConnectionString::parse`].
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the connection string is invalid or the database
+    /// cannot be opened.
+    #[inline]
+    pub fn open_from_str(conn_str: &str) -> Result<Connection> {
+        ConnectionString::parse(conn_str)?.open()
+    }
+
+    /// Open a connection described by the connection string in the
+    /// environment variable `var`, as parsed by [`ConnectionString::parse`].
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `var` is not set or not valid Unicode, if the
+    /// connection string is invalid, or if the database cannot be opened.
+    pub fn open_from_env(var: &str) -> Result<Connection> {
+        let conn_str = env::var(var).map_err(|e| invalid(format!("{var}: {e}")))?;
+        Connection::open_from_str(&conn_str)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ConnectionString;
+    use crate::{Connection, OpenFlags, Result};
+
+    #[test]
+    fn test_params_take_effect() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("my app.db");
+        let dsn = format!(
+            "sqlite:{}?mode=rwc&busy_timeout=1234&journal_mode=WAL&synchronous=normal&foreign_keys=on&cache_size=-500",
+            path.to_str().unwrap().replace(' ', "%20")
+        );
+        let db = Connection::open_from_str(&dsn)?;
+        assert_eq!(db.path(), Some(path.to_str().unwrap()));
+        let pragma = |name: &str| -> Result<String> {
+            db.pragma_query_value(None, name, |r| {
+                r.get::<_, crate::types::Value>(0).map(|v| match v {
+                    crate::types::Value::Integer(i) => i.to_string(),
+                    crate::types::Value::Text(s) => s,
+                    v => panic!("{:?}", v),
+                })
+            })
+        };
+        assert_eq!(pragma("journal_mode")?, "wal");
+        assert_eq!(pragma("busy_timeout")?, "1234");
+        assert_eq!(pragma("synchronous")?, "1");
+        assert_eq!(pragma("foreign_keys")?, "1");
+        assert_eq!(pragma("cache_size")?, "-500");
+        db.execute_batch("CREATE TABLE foo(x)")?;
+        drop(db);
+
+        let dsn = format!(
+            "sqlite://{}?mode=ro",
+            path.to_str().unwrap().replace(' ', "%20")
+        );
+        let db = Connection::open_from_str(&dsn)?;
+        assert!(db.is_readonly(crate::MAIN_DB)?);
+        assert!(db.execute_batch("INSERT INTO foo VALUES (1)").is_err());
+
+        let missing = temp_dir.path().join("missing.db");
+        let dsn = format!("sqlite:{}?mode=rw", missing.to_str().unwrap());
+        assert!(Connection::open_from_str(&dsn).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_and_mode_flags() -> Result<()> {
+        let conn_str = ConnectionString::parse("sqlite:app.db?cache=shared&mode=ro")?;
+        assert_eq!(
+            conn_str.flags(),
+            OpenFlags::SQLITE_OPEN_READ_ONLY
+                | OpenFlags::SQLITE_OPEN_SHARED_CACHE
+                | OpenFlags::SQLITE_OPEN_URI
+                | OpenFlags::SQLITE_OPEN_NO_MUTEX
+        );
+        let conn_str = ConnectionString::parse("app.db?cache=private")?;
+        assert!(conn_str
+            .flags()
+            .contains(OpenFlags::SQLITE_OPEN_PRIVATE_CACHE));
+        assert_eq!(conn_str.vfs(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory() -> Result<()> {
+        let db = Connection::open_from_str("sqlite::memory:?foreign_keys=true")?;
+        assert_eq!(db.path(), Some(""));
+        let fk: i64 = db.pragma_query_value(None, "foreign_keys", |r| r.get(0))?;
+        assert_eq!(fk, 1);
+
+        let db = Connection::open_from_str("sqlite:file:shared_mem?mode=memory&cache=shared")?;
+        db.execute_batch("CREATE TABLE foo(x)")?;
+        let other = Connection::open_from_str("sqlite:file:shared_mem?mode=memory&cache=shared")?;
+        other.execute_batch("INSERT INTO foo VALUES (1)")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid() {
+        let err = ConnectionString::parse("sqlite:app.db?mode=rw&colour=blue").unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("unknown parameter \"colour\""), "{}", msg);
+        assert!(msg.contains("mode, cache, vfs, busy_timeout"), "{}", msg);
+
+        assert!(ConnectionString::parse("sqlite:app.db?mode=rwx").is_err());
+        assert!(ConnectionString::parse("sqlite:app.db?busy_timeout=soon").is_err());
+        assert!(ConnectionString::parse("sqlite:app.db?cache_size=1&cache_size=2").is_err());
+        assert!(ConnectionString::parse("sqlite:app.db?journal_mode=wal;drop").is_err());
+        assert!(ConnectionString::parse("sqlite:app%zz.db").is_err());
+        assert!(ConnectionString::parse("sqlite:").is_err());
+    }
+
+    #[test]
+    fn test_windows_path_round_trip() -> Result<()> {
+        let conn_str =
+            ConnectionString::parse("sqlite:/C:/My%20Data/app.db?mode=ro&busy_timeout=10")?;
+        assert_eq!(conn_str.path(), "C:/My Data/app.db");
+        let s = conn_str.to_string();
+        assert_eq!(s, "sqlite:C:/My%20Data/app.db?mode=ro&busy_timeout=10");
+        assert_eq!(s.parse::<ConnectionString>()?, conn_str);
+
+        let conn_str = ConnectionString::parse(r"sqlite:D:\data\app.db")?;
+        assert_eq!(conn_str.path(), r"D:\data\app.db");
+        assert_eq!(conn_str.to_string().parse::<ConnectionString>()?, conn_str);
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_from_env() -> Result<()> {
+        std::env::set_var("RUSQLITE_TEST_DSN", "sqlite::memory:?cache_size=42");
+        let db = Connection::open_from_env("RUSQLITE_TEST_DSN")?;
+        let size: i64 = db.pragma_query_value(None, "cache_size", |r| r.get(0))?;
+        assert_eq!(size, 42);
+        assert!(Connection::open_from_env("RUSQLITE_TEST_DSN_UNSET").is_err());
+        Ok(())
+    }
+}
@@ -8,6 +8,8 @@ use crate::ffi;
 use crate::ffi::sqlite3_context;
 
 use crate::str_for_sqlite;
+#[cfg(any(feature = "array", feature = "blob"))]
+use crate::types::ToSqlOutputExt;
 use crate::types::{ToSqlOutput, ValueRef};
 #[cfg(feature = "array")]
 use crate::vtab::array::{free_array, ARRAY_TYPE};
@@ -17,24 +19,25 @@ use crate::vtab::array::{free_array, ARRAY_TYPE};
 // simplify the function.
 #[inline]
 pub(super) unsafe fn set_result(ctx: *mut sqlite3_context, result: &ToSqlOutput<'_>) {
+    #[cfg(feature = "blob")]
+    if let Some(len) = result.as_zero_blob() {
+        // TODO sqlite3_result_zeroblob64 // 3.8.11
+        return ffi::sqlite3_result_zeroblob(ctx, len);
+    }
+    #[cfg(feature = "array")]
+    if let Some(a) = result.as_array() {
+        return ffi::sqlite3_result_pointer(
+            ctx,
+            Rc::into_raw(a.clone()) as *mut c_void,
+            ARRAY_TYPE,
+            Some(free_array),
+        );
+    }
     let value = match *result {
         ToSqlOutput::Borrowed(v) => v,
         ToSqlOutput::Owned(ref v) => ValueRef::from(v),
-
-        #[cfg(feature = "blob")]
-        ToSqlOutput::ZeroBlob(len) => {
-            // TODO sqlite3_result_zeroblob64 // 3.8.11
-            return ffi::sqlite3_result_zeroblob(ctx, len);
-        }
-        #[cfg(feature = "array")]
-        ToSqlOutput::Array(ref a) => {
-            return ffi::sqlite3_result_pointer(
-                ctx,
-                Rc::into_raw(a.clone()) as *mut c_void,
-                ARRAY_TYPE,
-                Some(free_array),
-            );
-        }
+        ToSqlOutput::Inline(ref t) => ValueRef::Text(t.as_str().as_bytes()),
+        _ => return ffi::sqlite3_result_error_code(ctx, ffi::SQLITE_MISUSE),
     };
 
     match value {
@@ -0,0 +1,281 @@
+//! Text encoding of a database.
+
+use std::fmt;
+use std::path::Path;
+
+use crate::ffi;
+use crate::pragma::Sql;
+use crate::{params_from_iter, Connection, DatabaseName, Error, Result};
+
+/// The encoding text is stored in, as reported by [`PRAGMA
+/// encoding`](https://sqlite.org/pragma.html#pragma_encoding).
+///
+/// The encoding only affects storage: text read through rusqlite is always
+/// UTF-8, as SQLite converts it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TextEncoding {
+    /// UTF-8
+    Utf8,
+    /// UTF-16, little-endian
+    Utf16le,
+    /// UTF-16, big-endian
+    Utf16be,
+}
+
+impl TextEncoding {
+    fn from_pragma(s: &str) -> Option<TextEncoding> {
+        match s {
+            "UTF-8" => Some(TextEncoding::Utf8),
+            "UTF-16le" => Some(TextEncoding::Utf16le),
+            "UTF-16be" => Some(TextEncoding::Utf16be),
+            _ => None,
+        }
+    }
+
+    /// The name of the encoding, as used by `PRAGMA encoding`.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TextEncoding::Utf8 => "UTF-8",
+            TextEncoding::Utf16le => "UTF-16le",
+            TextEncoding::Utf16be => "UTF-16be",
+        }
+    }
+}
+
+impl fmt::Display for TextEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+fn identifier(name: &str) -> String {
+    let mut sql = Sql::new();
+    sql.push_identifier(name);
+    sql.as_str().to_owned()
+}
+
+impl Connection {
+    /// Returns the text encoding of the `db` database.
+    ///
+    /// Every database attached to a connection must use the same encoding
+    /// as the main one.
+    pub fn text_encoding(&self, db: DatabaseName<'_>) -> Result<TextEncoding> {
+        let encoding: String = self.pragma_query_value(Some(db), "encoding", |row| row.get(0))?;
+        TextEncoding::from_pragma(&encoding).ok_or_else(|| {
+            Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_CORRUPT),
+                Some(format!("unknown text encoding {encoding:?}")),
+            )
+        })
+    }
+
+    /// Copies the schema and content of the main database into a new UTF-8
+    /// database at `dst_path`.
+    ///
+    /// A [backup](Connection::backup) keeps the encoding of the source, so
+    /// the copy is made by recreating each table, index, view and trigger
+    /// from its SQL and inserting every row, preserving rowids. The page
+    /// size, auto-vacuum mode, `user_version` and `application_id` are
+    /// copied too.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the destination cannot be opened or already
+    /// contains a schema, or if the main database has a virtual table, whose
+    /// storage cannot be recreated this way.
+    pub fn convert_encoding_to_utf8<P: AsRef<Path>>(&self, dst_path: P) -> Result<()> {
+        let mut dst = Connection::open(dst_path)?;
+        let objects: i64 =
+            dst.query_row("SELECT count(*) FROM main.sqlite_master", [], |r| r.get(0))?;
+        if objects != 0 {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_MISUSE),
+                Some("the destination database is not empty".to_owned()),
+            ));
+        }
+        // these only take effect before the first table is created
+        dst.pragma_update(None, "encoding", TextEncoding::Utf8.as_str())?;
+        for pragma in &["page_size", "auto_vacuum"] {
+            let value: i64 = self.pragma_query_value(None, pragma, |r| r.get(0))?;
+            dst.pragma_update(None, pragma, value)?;
+        }
+
+        let mut schema = Vec::new();
+        {
+            let mut stmt = self.prepare(
+                "SELECT type, name, sql FROM main.sqlite_master \
+                 WHERE sql NOT NULL AND name NOT LIKE 'sqlite_%' ORDER BY rowid",
+            )?;
+            let mut rows = stmt.query([])?;
+            while let Some(row) = rows.next()? {
+                let (kind, name, sql): (String, String, String) =
+                    (row.get(0)?, row.get(1)?, row.get(2)?);
+                if matches!(sql.get(..14), Some(s) if s.eq_ignore_ascii_case("CREATE VIRTUAL")) {
+                    return Err(Error::SqliteFailure(
+                        ffi::Error::new(ffi::SQLITE_MISUSE),
+                        Some(format!("cannot convert virtual table {name:?}")),
+                    ));
+                }
+                schema.push((kind, name, sql));
+            }
+        }
+
+        let tx = dst.transaction()?;
+        for (_, _, sql) in schema.iter().filter(|(kind, ..)| kind == "table") {
+            tx.execute_batch(sql)?;
+        }
+        let mut tables: Vec<&str> = schema
+            .iter()
+            .filter(|(kind, ..)| kind == "table")
+            .map(|(_, name, _)| name.as_str())
+            .collect();
+        let has_sequence: bool = self.query_row(
+            "SELECT count(*) FROM main.sqlite_master WHERE name = 'sqlite_sequence'",
+            [],
+            |r| r.get(0),
+        )?;
+        if has_sequence {
+            // inserting rows with AUTOINCREMENT keys has filled it already
+            tables.push("sqlite_sequence");
+        }
+        for table in tables {
+            let mut columns = Vec::new();
+            self.pragma(None, "table_info", table, |row| {
+                columns.push(identifier(&row.get::<_, String>(1)?));
+                Ok(())
+            })?;
+            // `_rowid_` is always the rowid, unless a column has that name
+            // or the table is WITHOUT ROWID
+            let with_rowid = !columns.iter().any(|c| c.eq_ignore_ascii_case("_rowid_"))
+                && self
+                    .prepare(&format!("SELECT _rowid_ FROM main.{}", identifier(table)))
+                    .is_ok();
+            if with_rowid {
+                columns.insert(0, "_rowid_".to_owned());
+            }
+            let placeholders = vec!["?"; columns.len()].join(", ");
+            let columns = columns.join(", ");
+            let table = identifier(table);
+            if table == "sqlite_sequence" {
+                tx.execute_batch("DELETE FROM main.sqlite_sequence")?;
+            }
+            let mut select = self.prepare(&format!("SELECT {columns} FROM main.{table}"))?;
+            let mut insert = tx.prepare(&format!(
+                "INSERT INTO main.{table} ({columns}) VALUES ({placeholders})"
+            ))?;
+            let mut rows = select.query([])?;
+            while let Some(row) = rows.next()? {
+                insert.execute(params_from_iter(row.to_vec()))?;
+            }
+        }
+        for (_, _, sql) in schema.iter().filter(|(kind, ..)| kind != "table") {
+            tx.execute_batch(sql)?;
+        }
+        for pragma in &["user_version", "application_id"] {
+            let value: i64 = self.pragma_query_value(None, pragma, |r| r.get(0))?;
+            tx.pragma_update(None, pragma, value)?;
+        }
+        tx.commit()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TextEncoding;
+    use crate::{Connection, Result, MAIN_DB};
+
+    fn utf16_fixture(path: &std::path::Path) -> Result<Connection> {
+        let db = Connection::open(path)?;
+        db.pragma_update(None, "encoding", "UTF-16le")?;
+        db.execute_batch(
+            "CREATE TABLE people(id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT, photo BLOB);
+             CREATE TABLE tags(tag TEXT PRIMARY KEY, n INTEGER) WITHOUT ROWID;
+             CREATE TABLE log(msg TEXT);
+             CREATE INDEX people_name ON people(name);
+             CREATE VIEW names AS SELECT name FROM people;
+             CREATE TRIGGER people_log AFTER INSERT ON people
+             BEGIN INSERT INTO log VALUES ('added ' || new.name); END;
+             INSERT INTO people(name, photo) VALUES ('Zoë', x'00ff'), ('Łukasz', NULL), ('東京', NULL);
+             DELETE FROM people WHERE name = 'Łukasz';
+             INSERT INTO log(rowid, msg) VALUES (100, 'ünïcödé');
+             INSERT INTO tags VALUES ('ça', 1), ('日本', 2);
+             PRAGMA user_version = 7;",
+        )?;
+        Ok(db)
+    }
+
+    #[test]
+    fn test_text_encoding() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        assert_eq!(db.text_encoding(MAIN_DB)?, TextEncoding::Utf8);
+        assert_eq!(TextEncoding::Utf16be.to_string(), "UTF-16be");
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db = utf16_fixture(&temp_dir.path().join("utf16.db3"))?;
+        assert_eq!(db.text_encoding(MAIN_DB)?, TextEncoding::Utf16le);
+        // text is read as UTF-8 anyway
+        let name: String =
+            db.query_row("SELECT name FROM people WHERE id = 1", [], |r| r.get(0))?;
+        assert_eq!(name, "Zoë");
+        let bytes: Vec<u8> = db.query_row(
+            "SELECT CAST(name AS BLOB) FROM people WHERE id = 1",
+            [],
+            |r| r.get(0),
+        )?;
+        assert_eq!(bytes, b"Z\0o\0\xeb\0");
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_encoding_to_utf8() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let src = utf16_fixture(&temp_dir.path().join("utf16.db3"))?;
+        let dst_path = temp_dir.path().join("utf8.db3");
+        src.convert_encoding_to_utf8(&dst_path)?;
+
+        let dst = Connection::open(&dst_path)?;
+        assert_eq!(dst.text_encoding(MAIN_DB)?, TextEncoding::Utf8);
+        let dump = |db: &Connection| -> Result<Vec<Vec<crate::types::Value>>> {
+            let mut all = Vec::new();
+            for sql in &[
+                "SELECT type, name, tbl_name, sql FROM sqlite_master ORDER BY name",
+                "SELECT rowid, * FROM people",
+                // BINARY collation compares encoded text, so the key order
+                // differs between UTF-16 and UTF-8
+                "SELECT * FROM tags ORDER BY n",
+                "SELECT rowid, * FROM log",
+                "SELECT * FROM names",
+                "SELECT * FROM sqlite_sequence",
+                "PRAGMA user_version",
+            ] {
+                let mut stmt = db.prepare(sql)?;
+                let rows: Vec<_> = stmt.query([])?.values().collect::<Result<_>>()?;
+                all.extend(rows);
+            }
+            Ok(all)
+        };
+        assert_eq!(dump(&src)?, dump(&dst)?);
+        let bytes: Vec<u8> = dst.query_row(
+            "SELECT CAST(name AS BLOB) FROM people WHERE id = 1",
+            [],
+            |r| r.get(0),
+        )?;
+        assert_eq!(bytes, "Zoë".as_bytes());
+
+        // the trigger came after the data, and still works
+        let log: i64 = dst.query_row("SELECT count(*) FROM log", [], |r| r.get(0))?;
+        assert_eq!(log, 4);
+        dst.execute("INSERT INTO people(name) VALUES ('Ana')", [])?;
+        let id: i64 =
+            dst.query_row("SELECT id FROM people WHERE name = 'Ana'", [], |r| r.get(0))?;
+        assert_eq!(id, 4);
+        let check: String = dst.query_row("PRAGMA integrity_check", [], |r| r.get(0))?;
+        assert_eq!(check, "ok");
+
+        // the destination must be empty
+        assert!(src.convert_encoding_to_utf8(&dst_path).is_err());
+        Ok(())
+    }
+}
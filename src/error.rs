@@ -1,11 +1,12 @@
-use crate::types::FromSqlError;
-use crate::types::Type;
+use crate::types::{FromSqlError, ToSqlError};
+use crate::types::{Type, Value};
 use crate::{errmsg_to_string, ffi, Result};
 use std::error;
 use std::fmt;
 use std::os::raw::c_int;
 use std::path::PathBuf;
 use std::str;
+use std::time::Duration;
 
 /// Enum listing possible errors from rusqlite.
 #[derive(Debug)]
@@ -40,6 +41,16 @@ pub enum Error {
     /// present in the SQL.
     InvalidParameterName(String),
 
+    /// Error when binding named parameters leaves parameters of the statement
+    /// unbound, or supplies names which are not parameters of the statement.
+    MissingParameters {
+        /// names of the parameters which were not bound, or `?N` for
+        /// positional ones
+        missing: Vec<String>,
+        /// supplied names which are not parameters of the statement
+        unexpected: Vec<String>,
+    },
+
     /// Error converting a file path to a string.
     InvalidPath(PathBuf),
 
@@ -51,6 +62,11 @@ pub enum Error {
     /// for [`query_row`](crate::Connection::query_row)) did not return any.
     QueryReturnedNoRows,
 
+    /// Error when a query that was expected to return exactly one row (e.g.,
+    /// for [`expect_single`](crate::Rows::expect_single)) returned more than
+    /// one.
+    QueryReturnedMoreThanOneRow,
+
     /// Error when the value of a particular column is requested, but the index
     /// is out of range for the statement.
     InvalidColumnIndex(usize),
@@ -128,6 +144,117 @@ pub enum Error {
     #[cfg(feature = "blob")]
     #[cfg_attr(docsrs, doc(cfg(feature = "blob")))]
     BlobSizeError,
+    /// Returned by [`Connection::query_de`](crate::Connection::query_de) and
+    /// [`serde_rows::from_row`](crate::serde_rows::from_row) when a row does
+    /// not fit the type it is deserialized as.
+    #[cfg(feature = "serde_rows")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "serde_rows")))]
+    DeserializeFailure {
+        /// The one-based number of the row at fault, if known.
+        row: Option<usize>,
+        /// The name of the column at fault, if known.
+        column: Option<String>,
+        /// What went wrong.
+        msg: String,
+    },
+    /// Returned when an operation was cancelled: by
+    /// [`Connection::apply_strm_with_progress`](crate::Connection::apply_strm_with_progress)
+    /// when applying the changeset was cancelled or interrupted, in which
+    /// case nothing from the changeset has been applied, by the yield
+    /// function of a query run with `Statement::query_with_budget`, or when a
+    /// [`CancellationToken`](crate::CancellationToken) stopped a backup or a
+    /// `VACUUM`.
+    Cancelled,
+    /// Returned when a statement run by
+    /// [`Connection::execute_with_deadline`](crate::Connection::execute_with_deadline)
+    /// or `Statement::query_with_deadline` was interrupted because it did not
+    /// finish within the given time.
+    QueryTimeout(Duration),
+    /// Error when a rowid is used with a table created `WITHOUT ROWID`, e.g.
+    /// by [`Connection::blob_open`](crate::Connection::blob_open).
+    NoRowidTable(String),
+    /// Error when [`SnapshotRegistry::read_at`](crate::snapshot::SnapshotRegistry::read_at)
+    /// is asked for a time older than its retention period or than every
+    /// snapshot it keeps.
+    #[cfg(feature = "snapshot")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "snapshot")))]
+    SnapshotTooOld {
+        /// how long ago the requested time was
+        requested: Duration,
+        /// how long ago the oldest snapshot kept was taken
+        oldest: Duration,
+    },
+    /// Error when a snapshot kept by a
+    /// [`SnapshotRegistry`](crate::snapshot::SnapshotRegistry) can no longer
+    /// be opened, because a checkpoint has copied later changes into the
+    /// database file.
+    #[cfg(feature = "snapshot")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "snapshot")))]
+    SnapshotInvalidated,
+    /// Error when the results of
+    /// [`Statement::query_arena_with_limit`](crate::Statement::query_arena_with_limit)
+    /// do not fit in the given number of bytes.
+    ArenaLimitExceeded(usize),
+    /// Error when executing one of the items passed to
+    /// [`Statement::execute_many`](crate::Statement::execute_many) or
+    /// [`Connection::execute_many`](crate::Connection::execute_many) fails.
+    ExecuteManyFailed {
+        /// zero-based index of the item that failed
+        index: usize,
+        /// error executing the item
+        source: Box<Error>,
+    },
+    /// Error when updating one of the rows passed to
+    /// [`Connection::update_many`](crate::Connection::update_many) fails, or
+    /// when its key matches no row and
+    /// [`error_on_missing`](crate::batch::UpdateManyOptions::error_on_missing)
+    /// is set, in which case `source` is `Error::QueryReturnedNoRows`.
+    UpdateManyFailed {
+        /// key of the row that failed
+        key: Value,
+        /// error updating the row
+        source: Box<Error>,
+    },
+    /// Error when [`Connection::insert_with_defaults`](crate::Connection::insert_with_defaults)
+    /// is not given a value for columns which are `NOT NULL` without a default.
+    MissingColumnValues {
+        /// the table inserted into
+        table: String,
+        /// the columns without a value, in declaration order
+        columns: Vec<String>,
+    },
+    /// Error when the stream read by
+    /// [`Connection::import_table`](crate::Connection::import_table) is not
+    /// a table export, is truncated, or cannot be read, or when the stream
+    /// written by [`Connection::export_table`](crate::Connection::export_table)
+    /// cannot be written.
+    TableStreamFailed(std::io::Error),
+    /// Error when [`Connection::import_table`](crate::Connection::import_table)
+    /// imports rows into a table whose columns differ from the exported ones.
+    TableSchemaMismatch {
+        /// the table imported into
+        table: String,
+        /// the exported columns, as `name type`
+        expected: Vec<String>,
+        /// the columns of the table, as `name type`
+        found: Vec<String>,
+    },
+    /// Error when a statement would wait for a lock held by another
+    /// connection to the same shared cache, which waits for this one.
+    #[cfg(feature = "unlock_notify")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "unlock_notify")))]
+    UnlockNotifyDeadlock,
+    /// Error when a transaction statement is run as raw SQL while
+    /// [`Connection::forbid_raw_transaction_sql`](crate::Connection::forbid_raw_transaction_sql)
+    /// is enabled.
+    #[cfg(feature = "hooks")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hooks")))]
+    RawTransactionSql {
+        /// the statement, e.g. `BEGIN` or `RELEASE`
+        verb: String,
+        /// the API to use instead
+        hint: &'static str,
+    },
     /// Error referencing a specific token in the input SQL
     #[cfg(feature = "modern_sqlite")] // 3.38.0
     #[cfg_attr(docsrs, doc(cfg(feature = "modern_sqlite")))]
@@ -154,9 +281,20 @@ impl PartialEq for Error {
             (Error::Utf8Error(e1), Error::Utf8Error(e2)) => e1 == e2,
             (Error::NulError(e1), Error::NulError(e2)) => e1 == e2,
             (Error::InvalidParameterName(n1), Error::InvalidParameterName(n2)) => n1 == n2,
+            (
+                Error::MissingParameters {
+                    missing: m1,
+                    unexpected: u1,
+                },
+                Error::MissingParameters {
+                    missing: m2,
+                    unexpected: u2,
+                },
+            ) => m1 == m2 && u1 == u2,
             (Error::InvalidPath(p1), Error::InvalidPath(p2)) => p1 == p2,
             (Error::ExecuteReturnedResults, Error::ExecuteReturnedResults) => true,
             (Error::QueryReturnedNoRows, Error::QueryReturnedNoRows) => true,
+            (Error::QueryReturnedMoreThanOneRow, Error::QueryReturnedMoreThanOneRow) => true,
             (Error::InvalidColumnIndex(i1), Error::InvalidColumnIndex(i2)) => i1 == i2,
             (Error::InvalidColumnName(n1), Error::InvalidColumnName(n2)) => n1 == n2,
             (Error::InvalidColumnType(i1, n1, t1), Error::InvalidColumnType(i2, n2, t2)) => {
@@ -174,6 +312,7 @@ impl PartialEq for Error {
                 Error::InvalidFilterParameterType(i2, t2),
             ) => i1 == i2 && t1 == t2,
             (Error::InvalidQuery, Error::InvalidQuery) => true,
+            (Error::MultipleStatement, Error::MultipleStatement) => true,
             #[cfg(feature = "vtab")]
             (Error::ModuleError(s1), Error::ModuleError(s2)) => s1 == s2,
             #[cfg(feature = "functions")]
@@ -185,6 +324,88 @@ impl PartialEq for Error {
             }
             #[cfg(feature = "blob")]
             (Error::BlobSizeError, Error::BlobSizeError) => true,
+            #[cfg(feature = "serde_rows")]
+            (
+                Error::DeserializeFailure {
+                    row: r1,
+                    column: c1,
+                    msg: m1,
+                },
+                Error::DeserializeFailure {
+                    row: r2,
+                    column: c2,
+                    msg: m2,
+                },
+            ) => r1 == r2 && c1 == c2 && m1 == m2,
+            (Error::Cancelled, Error::Cancelled) => true,
+            (Error::QueryTimeout(t1), Error::QueryTimeout(t2)) => t1 == t2,
+            (Error::NoRowidTable(t1), Error::NoRowidTable(t2)) => t1 == t2,
+            #[cfg(feature = "snapshot")]
+            (
+                Error::SnapshotTooOld {
+                    requested: r1,
+                    oldest: o1,
+                },
+                Error::SnapshotTooOld {
+                    requested: r2,
+                    oldest: o2,
+                },
+            ) => r1 == r2 && o1 == o2,
+            #[cfg(feature = "snapshot")]
+            (Error::SnapshotInvalidated, Error::SnapshotInvalidated) => true,
+            (Error::ArenaLimitExceeded(l1), Error::ArenaLimitExceeded(l2)) => l1 == l2,
+            (
+                Error::ExecuteManyFailed {
+                    index: i1,
+                    source: s1,
+                },
+                Error::ExecuteManyFailed {
+                    index: i2,
+                    source: s2,
+                },
+            ) => i1 == i2 && s1 == s2,
+            (
+                Error::UpdateManyFailed {
+                    key: k1,
+                    source: s1,
+                },
+                Error::UpdateManyFailed {
+                    key: k2,
+                    source: s2,
+                },
+            ) => k1 == k2 && s1 == s2,
+            (
+                Error::MissingColumnValues {
+                    table: t1,
+                    columns: c1,
+                },
+                Error::MissingColumnValues {
+                    table: t2,
+                    columns: c2,
+                },
+            ) => t1 == t2 && c1 == c2,
+            (Error::TableStreamFailed(e1), Error::TableStreamFailed(e2)) => {
+                e1.kind() == e2.kind() && e1.to_string() == e2.to_string()
+            }
+            (
+                Error::TableSchemaMismatch {
+                    table: t1,
+                    expected: e1,
+                    found: f1,
+                },
+                Error::TableSchemaMismatch {
+                    table: t2,
+                    expected: e2,
+                    found: f2,
+                },
+            ) => t1 == t2 && e1 == e2 && f1 == f2,
+            #[cfg(feature = "unlock_notify")]
+            (Error::UnlockNotifyDeadlock, Error::UnlockNotifyDeadlock) => true,
+            #[cfg(feature = "hooks")]
+            (
+                Error::RawTransactionSql { verb: v1, hint: h1 },
+                Error::RawTransactionSql { verb: v2, hint: h2 },
+            ) => v1 == v2 && h1 == h2,
             #[cfg(feature = "modern_sqlite")]
             (
                 Error::SqlInputError {
@@ -271,11 +492,26 @@ impl fmt::Display for Error {
             Error::Utf8Error(ref err) => err.fmt(f),
             Error::NulError(ref err) => err.fmt(f),
             Error::InvalidParameterName(ref name) => write!(f, "Invalid parameter name: {name}"),
+            Error::MissingParameters {
+                ref missing,
+                ref unexpected,
+            } => {
+                if !missing.is_empty() {
+                    write!(f, "Missing parameters: {}", missing.join(", "))?;
+                    if !unexpected.is_empty() {
+                        write!(f, "; unexpected parameters: {}", unexpected.join(", "))?;
+                    }
+                    Ok(())
+                } else {
+                    write!(f, "Unexpected parameters: {}", unexpected.join(", "))
+                }
+            }
             Error::InvalidPath(ref p) => write!(f, "Invalid path: {}", p.to_string_lossy()),
             Error::ExecuteReturnedResults => {
                 write!(f, "Execute returned results - did you mean to call query?")
             }
             Error::QueryReturnedNoRows => write!(f, "Query returned no rows"),
+            Error::QueryReturnedMoreThanOneRow => write!(f, "Query returned more than one row"),
             Error::InvalidColumnIndex(i) => write!(f, "Invalid column index: {i}"),
             Error::InvalidColumnName(ref name) => write!(f, "Invalid column name: {name}"),
             Error::InvalidColumnType(i, ref name, ref t) => write!(
@@ -311,6 +547,75 @@ impl fmt::Display for Error {
             Error::MultipleStatement => write!(f, "Multiple statements provided"),
             #[cfg(feature = "blob")]
             Error::BlobSizeError => "Blob size is insufficient".fmt(f),
+            #[cfg(feature = "serde_rows")]
+            Error::DeserializeFailure {
+                row,
+                ref column,
+                ref msg,
+            } => {
+                write!(f, "Deserializing")?;
+                if let Some(row) = row {
+                    write!(f, " row {row}")?;
+                }
+                if let Some(column) = column {
+                    write!(
+                        f,
+                        "{} column {column:?}",
+                        if row.is_some() { "," } else { "" }
+                    )?;
+                }
+                write!(f, " failed: {msg}")
+            }
+            Error::Cancelled => write!(f, "Operation was cancelled"),
+            Error::QueryTimeout(timeout) => write!(f, "Query did not finish within {timeout:?}"),
+            Error::NoRowidTable(ref table) => write!(f, "Table {table:?} has no rowid"),
+            #[cfg(feature = "snapshot")]
+            Error::SnapshotTooOld { requested, oldest } => write!(
+                f,
+                "No snapshot from {requested:?} ago, the oldest one kept is from {oldest:?} ago"
+            ),
+            #[cfg(feature = "snapshot")]
+            Error::SnapshotInvalidated => write!(f, "Snapshot was invalidated by a checkpoint"),
+            Error::ArenaLimitExceeded(limit) => {
+                write!(f, "Query results do not fit in {limit} bytes")
+            }
+            Error::ExecuteManyFailed { index, ref source } => {
+                write!(f, "Executing item {index} failed: {source}")
+            }
+            Error::UpdateManyFailed {
+                ref key,
+                ref source,
+            } => {
+                write!(f, "Updating the row with key {key:?} failed: {source}")
+            }
+            Error::MissingColumnValues {
+                ref table,
+                ref columns,
+            } => write!(
+                f,
+                "Missing values for NOT NULL columns of {table} without a default: {}",
+                columns.join(", ")
+            ),
+            Error::TableStreamFailed(ref err) => write!(f, "Table stream failed: {err}"),
+            Error::TableSchemaMismatch {
+                ref table,
+                ref expected,
+                ref found,
+            } => write!(
+                f,
+                "Columns of {table} ({}) differ from the exported ones ({})",
+                found.join(", "),
+                expected.join(", ")
+            ),
+            #[cfg(feature = "unlock_notify")]
+            Error::UnlockNotifyDeadlock => write!(
+                f,
+                "Waiting for a shared-cache lock would deadlock: roll back the transaction"
+            ),
+            #[cfg(feature = "hooks")]
+            Error::RawTransactionSql { ref verb, hint } => {
+                write!(f, "{verb} is not allowed as raw SQL: {hint}")
+            }
             #[cfg(feature = "modern_sqlite")]
             Error::SqlInputError {
                 ref msg,
@@ -322,6 +627,19 @@ impl fmt::Display for Error {
     }
 }
 
+/// Reports a failed [`ToSql`](crate::ToSql) conversion: an `Error` returned
+/// by the conversion is passed on as is, and any other error is wrapped in an
+/// [`Error::ToSqlConversionFailure`].
+impl From<ToSqlError> for Error {
+    #[cold]
+    fn from(err: ToSqlError) -> Error {
+        match err.downcast::<Error>() {
+            Ok(err) => *err,
+            Err(err) => Error::ToSqlConversionFailure(err),
+        }
+    }
+}
+
 impl error::Error for Error {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match *self {
@@ -332,8 +650,12 @@ impl error::Error for Error {
             Error::IntegralValueOutOfRange(..)
             | Error::SqliteSingleThreadedMode
             | Error::InvalidParameterName(_)
+            | Error::MissingParameters { .. }
+            | Error::MissingColumnValues { .. }
+            | Error::TableSchemaMismatch { .. }
             | Error::ExecuteReturnedResults
             | Error::QueryReturnedNoRows
+            | Error::QueryReturnedMoreThanOneRow
             | Error::InvalidColumnIndex(_)
             | Error::InvalidColumnName(_)
             | Error::InvalidColumnType(..)
@@ -365,6 +687,19 @@ impl error::Error for Error {
 
             #[cfg(feature = "blob")]
             Error::BlobSizeError => None,
+            #[cfg(feature = "serde_rows")]
+            Error::DeserializeFailure { .. } => None,
+            Error::Cancelled | Error::QueryTimeout(_) | Error::NoRowidTable(_) => None,
+            #[cfg(feature = "snapshot")]
+            Error::SnapshotTooOld { .. } | Error::SnapshotInvalidated => None,
+            Error::ArenaLimitExceeded(_) => None,
+            Error::ExecuteManyFailed { ref source, .. } => Some(&**source),
+            Error::UpdateManyFailed { ref source, .. } => Some(&**source),
+            Error::TableStreamFailed(ref err) => Some(err),
+            #[cfg(feature = "unlock_notify")]
+            Error::UnlockNotifyDeadlock => None,
+            #[cfg(feature = "hooks")]
+            Error::RawTransactionSql { .. } => None,
             #[cfg(feature = "modern_sqlite")]
             Error::SqlInputError { ref error, .. } => Some(error),
         }
@@ -372,23 +707,85 @@ impl error::Error for Error {
 }
 
 impl Error {
-    /// Returns the underlying SQLite error if this is [`Error::SqliteFailure`].
+    /// Returns the underlying SQLite error if this is [`Error::SqliteFailure`],
+    /// or an [`Error::ExecuteManyFailed`] or [`Error::UpdateManyFailed`]
+    /// caused by one.
     #[inline]
     pub fn sqlite_error(&self) -> Option<&ffi::Error> {
         match self {
             Self::SqliteFailure(error, _) => Some(error),
+            Self::ExecuteManyFailed { source, .. } | Self::UpdateManyFailed { source, .. } => {
+                source.sqlite_error()
+            }
             _ => None,
         }
     }
 
     /// Returns the underlying SQLite error code if this is
-    /// [`Error::SqliteFailure`].
+    /// [`Error::SqliteFailure`], or an [`Error::ExecuteManyFailed`] or
+    /// [`Error::UpdateManyFailed`] caused by one.
     #[inline]
     pub fn sqlite_error_code(&self) -> Option<ffi::ErrorCode> {
         self.sqlite_error().map(|error| error.code)
     }
 }
 
+/// Error returned by [`Connection::query_row_expected`](crate::Connection::query_row_expected)
+/// and [`Rows::expect_single`](crate::Rows::expect_single), distinguishing a
+/// missing row from any other failure.
+///
+/// `QueryError` converts back into [`Error`], so `?` still works in functions
+/// returning `rusqlite::Result`, while callers that care can match on
+/// [`QueryError::NotFound`] directly.
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum QueryError {
+    /// The query returned no rows.
+    NotFound,
+    /// Any other error (preparing, binding, stepping or converting), or
+    /// [`Error::QueryReturnedMoreThanOneRow`] if [`Rows::expect_single`](crate::Rows::expect_single)
+    /// found another row.
+    More(Error),
+}
+
+/// Wraps any `err` in [`QueryError::More`]: only the query itself reports
+/// [`QueryError::NotFound`], not an [`Error::QueryReturnedNoRows`] returned
+/// while mapping its row.
+impl From<Error> for QueryError {
+    #[cold]
+    fn from(err: Error) -> QueryError {
+        QueryError::More(err)
+    }
+}
+
+impl From<QueryError> for Error {
+    #[cold]
+    fn from(err: QueryError) -> Error {
+        match err {
+            QueryError::NotFound => Error::QueryReturnedNoRows,
+            QueryError::More(err) => err,
+        }
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            QueryError::NotFound => Error::QueryReturnedNoRows.fmt(f),
+            QueryError::More(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl error::Error for QueryError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            QueryError::NotFound => None,
+            QueryError::More(ref err) => Some(err),
+        }
+    }
+}
+
 // These are public but not re-exported by lib.rs, so only visible within crate.
 
 #[cold]
@@ -66,7 +66,10 @@ use crate::ffi::sqlite3_context;
 use crate::ffi::sqlite3_value;
 
 use crate::context::set_result;
-use crate::types::{FromSql, FromSqlError, ToSql, ValueRef};
+use crate::panic_policy::stash_panic;
+use crate::types::{
+    self, Extension, FromSql, FromSqlError, ToSql, ToSqlOutput, ToSqlResult, ValueRef,
+};
 
 use crate::{str_to_cstring, Connection, Error, InnerConnection, Result};
 
@@ -129,7 +132,7 @@ impl Context<'_> {
     /// `T`.
     pub fn get<T: FromSql>(&self, idx: usize) -> Result<T> {
         let arg = self.args[idx];
-        let value = unsafe { ValueRef::from_value(arg) };
+        let value = unsafe { types::value_ref(arg) };
         FromSql::column_result(value).map_err(|err| match err {
             FromSqlError::InvalidType => {
                 Error::InvalidFunctionParameterType(idx, value.data_type())
@@ -138,9 +141,7 @@ impl Context<'_> {
             FromSqlError::Other(err) => {
                 Error::FromSqlConversionFailure(idx, value.data_type(), err)
             }
-            FromSqlError::InvalidBlobSize { .. } => {
-                Error::FromSqlConversionFailure(idx, value.data_type(), Box::new(err))
-            }
+            _ => Error::FromSqlConversionFailure(idx, value.data_type(), Box::new(err)),
         })
     }
 
@@ -154,10 +155,15 @@ impl Context<'_> {
     #[must_use]
     pub fn get_raw(&self, idx: usize) -> ValueRef<'_> {
         let arg = self.args[idx];
-        unsafe { ValueRef::from_value(arg) }
+        unsafe { types::value_ref(arg) }
     }
 
-    /// Returns the subtype of `idx`th argument.
+    /// Returns the subtype of `idx`th argument, 0 if it has none. See
+    /// [`sqlite3_value_subtype`](https://sqlite.org/c3ref/value_subtype.html).
+    ///
+    /// The function must be registered with
+    /// [`FunctionFlags::SQLITE_SUBTYPE`], or SQLite may not pass the subtypes
+    /// of its arguments along.
     ///
     /// # Failure
     ///
@@ -195,6 +201,11 @@ impl Context<'_> {
     /// Sets the auxiliary data associated with a particular parameter. See
     /// `https://www.sqlite.org/c3ref/get_auxdata.html` for a discussion of
     /// this feature, or the unit tests of this module for an example.
+    ///
+    /// SQLite keeps the data only while the parameter is a constant of the
+    /// statement, and may discard it at any time, even before this returns:
+    /// [`get_aux`](Context::get_aux) then returns `Ok(None)`. The value is
+    /// dropped once both SQLite and the returned `Arc` are done with it.
     pub fn set_aux<T: Send + Sync + 'static>(&self, arg: c_int, value: T) -> Result<Arc<T>> {
         let orig: Arc<T> = Arc::new(value);
         let inner: AuxInner = orig.clone();
@@ -241,10 +252,177 @@ impl Context<'_> {
         })
     }
 
-    /// Set the Subtype of an SQL function
+    /// Set the subtype of the result of an SQL function, once the result is
+    /// set, e.g. the `74` (`'J'`) the JSON functions use. Only the lower 8
+    /// bits are kept. See
+    /// [`sqlite3_result_subtype`](https://sqlite.org/c3ref/result_subtype.html).
+    ///
+    /// The function should be registered with
+    /// [`FunctionFlags::SQLITE_RESULT_SUBTYPE`], which SQLite 3.45.0 and
+    /// later built with `SQLITE_STRICT_SUBTYPE` require.
     pub fn set_result_subtype(&self, sub_type: std::os::raw::c_uint) {
         unsafe { ffi::sqlite3_result_subtype(self.ctx, sub_type) };
     }
+
+    /// Sets the result of a scalar function to `value`, which may borrow
+    /// from the arguments. SQLite copies text and BLOBs, so there is no
+    /// allocation on the Rust side.
+    ///
+    /// The function must then return [`FunctionResult::Set`], or its return
+    /// value replaces the result.
+    ///
+    /// ```rust
+    /// # use rusqlite::{Connection, Result};
+    /// use rusqlite::functions::{FunctionFlags, FunctionResult};
+    /// use rusqlite::types::{Null, ValueRef};
+    ///
+    /// fn add_trim_function(db: &Connection) -> Result<()> {
+    ///     db.create_scalar_function("my_trim", 1, FunctionFlags::SQLITE_UTF8, |ctx| {
+    ///         let text = ctx.get_raw(0).as_str()?.trim();
+    ///         ctx.set_result_ref(ValueRef::Text(text.as_bytes()));
+    ///         Ok(FunctionResult::<Null>::Set)
+    ///     })
+    /// }
+    /// ```
+    pub fn set_result_ref(&self, value: ValueRef<'_>) {
+        unsafe { set_result(self.ctx, &ToSqlOutput::Borrowed(value)) }
+    }
+
+    /// Sets the result of a scalar function to the `idx`th argument,
+    /// unchanged, subtype included.
+    ///
+    /// The function must then return [`FunctionResult::Set`], or its return
+    /// value replaces the result. Returning
+    /// [`FunctionResult::Passthrough(idx)`](FunctionResult::Passthrough) does
+    /// the same.
+    ///
+    /// # Failure
+    ///
+    /// Will panic if `idx` is greater than or equal to
+    /// [`self.len()`](Context::len).
+    pub fn result_from_arg(&self, idx: usize) {
+        let arg = self.args[idx];
+        unsafe { ffi::sqlite3_result_value(self.ctx, arg) }
+    }
+
+    /// Returns the `idx`th argument if it is a pointer of type `type_name`
+    /// to a `T`, bound with [`Statement::bind_pointer`](crate::Statement::bind_pointer)
+    /// or returned by a function with [`Context::set_result_pointer`].
+    /// Otherwise, including for another type string or type, returns `None`.
+    ///
+    /// # Failure
+    ///
+    /// Will panic if `idx` is greater than or equal to
+    /// [`self.len()`](Context::len).
+    #[cfg(feature = "modern_sqlite")] // 3.20.0
+    #[cfg_attr(docsrs, doc(cfg(feature = "modern_sqlite")))]
+    #[must_use]
+    pub fn get_pointer<T: Any + Send + Sync>(
+        &self,
+        idx: usize,
+        type_name: &'static std::ffi::CStr,
+    ) -> Option<Arc<T>> {
+        unsafe { crate::pointer::from_value(self.args[idx], type_name) }
+    }
+
+    /// Sets the result of a scalar function to `value`, as a pointer of type
+    /// `type_name` which SQL sees as NULL, for another function or a virtual
+    /// table to read with [`Context::get_pointer`].
+    ///
+    /// The function must then return [`FunctionResult::Set`], or its return
+    /// value replaces the result.
+    #[cfg(feature = "modern_sqlite")] // 3.20.0
+    #[cfg_attr(docsrs, doc(cfg(feature = "modern_sqlite")))]
+    pub fn set_result_pointer<T: Any + Send + Sync>(
+        &self,
+        value: Arc<T>,
+        type_name: &'static std::ffi::CStr,
+    ) {
+        unsafe {
+            ffi::sqlite3_result_pointer(
+                self.ctx,
+                crate::pointer::into_raw(value),
+                type_name.as_ptr(),
+                Some(crate::pointer::free_pointer),
+            );
+        }
+    }
+}
+
+/// The return value of a scalar function which may avoid copying its result:
+/// either a value, or a result taken as is from the arguments or already set
+/// on the [`Context`].
+///
+/// ```rust
+/// # use rusqlite::{Connection, Result};
+/// use rusqlite::functions::{FunctionFlags, FunctionResult};
+///
+/// fn add_coalesce_text_function(db: &Connection) -> Result<()> {
+///     // returns its argument when it is text, and '' otherwise
+///     db.create_scalar_function("text_or_empty", 1, FunctionFlags::SQLITE_UTF8, |ctx| {
+///         Ok(match ctx.get_raw(0).as_str() {
+///             Ok(_) => FunctionResult::Passthrough(0),
+///             Err(_) => FunctionResult::Value(""),
+///         })
+///     })
+/// }
+/// ```
+///
+/// It can only be returned by scalar functions: aggregate and window
+/// functions fail with `SQLITE_MISUSE` for anything other than a `Value`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FunctionResult<T> {
+    /// Sets the result to the value.
+    Value(T),
+    /// Sets the result to the argument with this index, unchanged, as with
+    /// [`Context::result_from_arg`]. Fails if there is no such argument.
+    Passthrough(usize),
+    /// Keeps the result set with [`Context::set_result_ref`],
+    /// [`Context::result_from_arg`] or `Context::set_result_pointer`.
+    Set,
+}
+
+// Payload of the `ToSqlOutput` of a `FunctionResult` without a value, which
+// only the scalar function wrapper knows how to apply.
+#[derive(Debug, PartialEq)]
+enum ResultAction {
+    Passthrough(usize),
+    Set,
+}
+
+impl<T: ToSql> ToSql for FunctionResult<T> {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        let action = match *self {
+            FunctionResult::Value(ref value) => return value.to_sql(),
+            FunctionResult::Passthrough(idx) => ResultAction::Passthrough(idx),
+            FunctionResult::Set => ResultAction::Set,
+        };
+        Ok(ToSqlOutput::Extension(Extension::new(action)))
+    }
+}
+
+unsafe fn set_scalar_result(
+    ctx: *mut sqlite3_context,
+    argc: c_int,
+    argv: *mut *mut sqlite3_value,
+    result: &ToSqlOutput<'_>,
+) {
+    if let ToSqlOutput::Extension(ext) = result {
+        match ext.downcast_ref::<ResultAction>() {
+            Some(&ResultAction::Passthrough(idx)) if idx < argc as usize => {
+                return ffi::sqlite3_result_value(ctx, *argv.add(idx));
+            }
+            Some(&ResultAction::Passthrough(idx)) => {
+                let msg = format!("cannot pass argument {idx} through: there are {argc}");
+                return report_error(ctx, &Error::UserFunctionError(msg.into()));
+            }
+            Some(ResultAction::Set) => return,
+            None => {}
+        }
+    }
+    set_result(ctx, result);
 }
 
 /// A reference to a connection handle with a lifetime bound to something.
@@ -331,12 +509,14 @@ bitflags::bitflags! {
         const SQLITE_UTF16    = ffi::SQLITE_UTF16;
         /// Means that the function always gives the same output when the input parameters are the same.
         const SQLITE_DETERMINISTIC = ffi::SQLITE_DETERMINISTIC; // 3.8.3
-        /// Means that the function may only be invoked from top-level SQL.
+        /// Means that the function may only be invoked from top-level SQL, not from views, triggers, or the schema (`CHECK` constraints, `DEFAULT` values, indexes and generated columns).
         const SQLITE_DIRECTONLY    = 0x0000_0008_0000; // 3.30.0
         /// Indicates to SQLite that a function may call `sqlite3_value_subtype()` to inspect the sub-types of its arguments.
         const SQLITE_SUBTYPE       = 0x0000_0010_0000; // 3.30.0
-        /// Means that the function is unlikely to cause problems even if misused.
+        /// Means that the function is unlikely to cause problems even if misused, so that it may be used from views, triggers and the schema even with `PRAGMA trusted_schema = OFF`.
         const SQLITE_INNOCUOUS     = 0x0000_0020_0000; // 3.31.0
+        /// Indicates to SQLite that a function may call `sqlite3_result_subtype()` to set the sub-type of its result. Ignored before 3.45.0.
+        const SQLITE_RESULT_SUBTYPE = 0x0000_0100_0000; // 3.45.0
     }
 }
 
@@ -358,7 +538,9 @@ impl Connection {
     ///
     /// The function will remain available until the connection is closed or
     /// until it is explicitly removed via
-    /// [`remove_function`](Connection::remove_function).
+    /// [`remove_function`](Connection::remove_function). Attaching another
+    /// function with the same name, number of arguments and text encoding
+    /// replaces it.
     ///
     /// # Example
     ///
@@ -497,7 +679,8 @@ impl InnerConnection {
                 (*boxed_f)(&ctx)
             });
             let t = match r {
-                Err(_) => {
+                Err(payload) => {
+                    stash_panic(payload);
                     report_error(ctx, &Error::UnwindingPanic);
                     return;
                 }
@@ -506,8 +689,8 @@ impl InnerConnection {
             let t = t.as_ref().map(|t| ToSql::to_sql(t));
 
             match t {
-                Ok(Ok(ref value)) => set_result(ctx, value),
-                Ok(Err(err)) => report_error(ctx, &err),
+                Ok(Ok(ref value)) => set_scalar_result(ctx, argc, argv, value),
+                Ok(Err(err)) => report_error(ctx, &Error::from(err)),
                 Err(err) => report_error(ctx, err),
             }
         }
@@ -653,7 +836,8 @@ unsafe extern "C" fn call_boxed_step<A, D, T>(
         (*boxed_aggr).step(&mut ctx, &mut **pac)
     });
     let r = match r {
-        Err(_) => {
+        Err(payload) => {
+            stash_panic(payload);
             report_error(ctx, &Error::UnwindingPanic);
             return;
         }
@@ -695,7 +879,8 @@ unsafe extern "C" fn call_boxed_inverse<A, W, T>(
         (*boxed_aggr).inverse(&mut ctx, &mut **pac)
     });
     let r = match r {
-        Err(_) => {
+        Err(payload) => {
+            stash_panic(payload);
             report_error(ctx, &Error::UnwindingPanic);
             return;
         }
@@ -737,7 +922,8 @@ where
         (*boxed_aggr).finalize(&mut ctx, a)
     });
     let t = match r {
-        Err(_) => {
+        Err(payload) => {
+            stash_panic(payload);
             report_error(ctx, &Error::UnwindingPanic);
             return;
         }
@@ -746,7 +932,7 @@ where
     let t = t.as_ref().map(|t| ToSql::to_sql(t));
     match t {
         Ok(Ok(ref value)) => set_result(ctx, value),
-        Ok(Err(err)) => report_error(ctx, &err),
+        Ok(Err(err)) => report_error(ctx, &Error::from(err)),
         Err(err) => report_error(ctx, err),
     }
 }
@@ -781,7 +967,8 @@ where
         (*boxed_aggr).value(a)
     });
     let t = match r {
-        Err(_) => {
+        Err(payload) => {
+            stash_panic(payload);
             report_error(ctx, &Error::UnwindingPanic);
             return;
         }
@@ -790,11 +977,284 @@ where
     let t = t.as_ref().map(|t| ToSql::to_sql(t));
     match t {
         Ok(Ok(ref value)) => set_result(ctx, value),
-        Ok(Err(err)) => report_error(ctx, &err),
+        Ok(Err(err)) => report_error(ctx, &Error::from(err)),
         Err(err) => report_error(ctx, err),
     }
 }
 
+// Support for the `scalar!` and `aggregate!` macros.
+
+#[doc(hidden)]
+pub fn __arg<T: FromSql>(ctx: &Context<'_>, idx: usize, fn_name: &str, arg: &str) -> Result<T> {
+    ctx.get(idx).map_err(|err| {
+        Error::UserFunctionError(format!("{fn_name}(): argument {arg} (#{idx}): {err}").into())
+    })
+}
+
+#[doc(hidden)]
+pub fn __ok<A, T>(f: impl Fn(A) -> T) -> impl Fn(A) -> Result<T> {
+    move |a| Ok(f(a))
+}
+
+#[doc(hidden)]
+pub struct __Fold<I, S, F> {
+    init: I,
+    step: S,
+    finish: F,
+}
+
+impl<I, S, F> __Fold<I, S, F> {
+    #[doc(hidden)]
+    pub fn new<A, T>(init: I, step: S, finish: F) -> __Fold<I, S, F>
+    where
+        I: Fn() -> A,
+        S: Fn(&mut Context<'_>, &mut A) -> Result<()>,
+        F: Fn(A) -> Result<T>,
+    {
+        __Fold { init, step, finish }
+    }
+}
+
+impl<A, T, I, S, F> Aggregate<A, T> for __Fold<I, S, F>
+where
+    A: RefUnwindSafe + UnwindSafe,
+    T: ToSql,
+    I: Fn() -> A,
+    S: Fn(&mut Context<'_>, &mut A) -> Result<()>,
+    F: Fn(A) -> Result<T>,
+{
+    fn init(&self, _: &mut Context<'_>) -> Result<A> {
+        Ok((self.init)())
+    }
+
+    fn step(&self, ctx: &mut Context<'_>, acc: &mut A) -> Result<()> {
+        (self.step)(ctx, acc)
+    }
+
+    fn finalize(&self, _: &mut Context<'_>, acc: Option<A>) -> Result<T> {
+        (self.finish)(acc.unwrap_or_else(|| (self.init)()))
+    }
+}
+
+/// Register a scalar function written as a closure with typed parameters.
+///
+/// ```text
+/// scalar!(conn, name, [deterministic,] |arg: Type, ...| -> Return { body })
+/// ```
+///
+/// The number of arguments of the function is the number of parameters of
+/// the closure, and each argument is converted with
+/// [`Context::get`](crate::functions::Context::get), so that an `Option`
+/// parameter receives `None` for NULL. An argument that cannot be converted
+/// fails the call with an error naming the function and the parameter. The
+/// closure returns a value, or a `Result<Return>` to fail with an error.
+///
+/// Expands to a call to
+/// [`Connection::create_scalar_function`](crate::Connection::create_scalar_function),
+/// returning its `Result`.
+///
+/// ```rust
+/// # use rusqlite::{Connection, Result};
+/// use rusqlite::functions::scalar;
+///
+/// fn add_tax(db: &Connection) -> Result<f64> {
+///     scalar!(db, "add_tax", deterministic, |price: f64, rate: Option<f64>| -> f64 {
+///         price * (1.0 + rate.unwrap_or(0.2))
+///     })?;
+///     db.query_row("SELECT add_tax(10.0, NULL)", [], |row| row.get(0))
+/// }
+/// ```
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __functions_scalar {
+    ($conn:expr, $name:expr, deterministic, $($closure:tt)+) => {
+        $crate::__functions_scalar!(
+            @flags $conn,
+            $name,
+            $crate::functions::FunctionFlags::SQLITE_UTF8
+                | $crate::functions::FunctionFlags::SQLITE_DETERMINISTIC,
+            $($closure)+
+        )
+    };
+    ($conn:expr, $name:expr, $($closure:tt)+) => {
+        $crate::__functions_scalar!(
+            @flags $conn,
+            $name,
+            $crate::functions::FunctionFlags::SQLITE_UTF8,
+            $($closure)+
+        )
+    };
+    (@flags $conn:expr, $name:expr, $flags:expr, || -> Result<$ret:ty> $body:block) => {
+        $conn.create_scalar_function(
+            $name,
+            0,
+            $flags,
+            move |_: &$crate::functions::Context<'_>| -> $crate::Result<$ret> { $body },
+        )
+    };
+    (@flags $conn:expr, $name:expr, $flags:expr, || -> $ret:ty $body:block) => {
+        $conn.create_scalar_function(
+            $name,
+            0,
+            $flags,
+            move |_: &$crate::functions::Context<'_>| -> $crate::Result<$ret> {
+                $crate::functions::__ok(|()| -> $ret { $body })(())
+            },
+        )
+    };
+    (@flags $conn:expr, $name:expr, $flags:expr,
+        |$($arg:ident: $ty:ty),+ $(,)?| -> Result<$ret:ty> $body:block) => {{
+        let name: &str = $name;
+        let label = name.to_owned();
+        $conn.create_scalar_function(
+            name,
+            $crate::__functions_scalar!(@count $($arg)+),
+            $flags,
+            move |ctx: &$crate::functions::Context<'_>| -> $crate::Result<$ret> {
+                $crate::__functions_scalar!(@args ctx, &label, 0; $($arg: $ty),+);
+                (|$($arg: $ty),+| -> $crate::Result<$ret> { $body })($($arg),+)
+            },
+        )
+    }};
+    (@flags $conn:expr, $name:expr, $flags:expr,
+        |$($arg:ident: $ty:ty),+ $(,)?| -> $ret:ty $body:block) => {{
+        let name: &str = $name;
+        let label = name.to_owned();
+        $conn.create_scalar_function(
+            name,
+            $crate::__functions_scalar!(@count $($arg)+),
+            $flags,
+            move |ctx: &$crate::functions::Context<'_>| -> $crate::Result<$ret> {
+                $crate::__functions_scalar!(@args ctx, &label, 0; $($arg: $ty),+);
+                Ok((|$($arg: $ty),+| -> $ret { $body })($($arg),+))
+            },
+        )
+    }};
+    (@count $($arg:ident)*) => {
+        0 $(+ $crate::__functions_scalar!(@one $arg))*
+    };
+    (@one $arg:ident) => {
+        1
+    };
+    (@args $ctx:ident, $label:expr, $idx:expr;) => {};
+    (@args $ctx:ident, $label:expr, $idx:expr; $arg:ident: $ty:ty $(, $rest:ident: $rest_ty:ty)*) => {
+        let $arg: $ty = $crate::functions::__arg($ctx, $idx, $label, stringify!($arg))?;
+        $crate::__functions_scalar!(@args $ctx, $label, $idx + 1; $($rest: $rest_ty),*);
+    };
+}
+
+#[doc(inline)]
+pub use crate::__functions_scalar as scalar;
+
+/// Register an aggregate function folding its rows into an accumulator.
+///
+/// ```text
+/// aggregate!(conn, name, [deterministic,]
+///     init: expression,
+///     step: |acc: &mut Acc, arg: Type, ...| { body },
+///     finish: |acc: Acc| -> Return { body })
+/// ```
+///
+/// `init` builds the accumulator of each group, also handed to `finish` for
+/// a group without rows. The number of arguments of the function is the
+/// number of parameters of `step` after the accumulator, and they are
+/// converted as by [`scalar!`](crate::functions::scalar). `step` may return
+/// `Result<()>`, and `finish` `Result<Return>`, to fail with an error.
+///
+/// Expands to a call to
+/// [`Connection::create_aggregate_function`](crate::Connection::create_aggregate_function),
+/// returning its `Result`.
+///
+/// ```rust
+/// # use rusqlite::{Connection, Result};
+/// use rusqlite::functions::aggregate;
+///
+/// fn sum_of_squares(db: &Connection) -> Result<i64> {
+///     aggregate!(db, "sum_sq", deterministic,
+///         init: 0i64,
+///         step: |acc: &mut i64, x: i64| { *acc += x * x },
+///         finish: |acc: i64| -> i64 { acc })?;
+///     db.query_row(
+///         "WITH t(x) AS (VALUES (1), (2), (3)) SELECT sum_sq(x) FROM t",
+///         [],
+///         |row| row.get(0),
+///     )
+/// }
+/// ```
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __functions_aggregate {
+    ($conn:expr, $name:expr, deterministic, $($rest:tt)+) => {
+        $crate::__functions_aggregate!(
+            @flags $conn,
+            $name,
+            $crate::functions::FunctionFlags::SQLITE_UTF8
+                | $crate::functions::FunctionFlags::SQLITE_DETERMINISTIC,
+            $($rest)+
+        )
+    };
+    ($conn:expr, $name:expr, init: $($rest:tt)+) => {
+        $crate::__functions_aggregate!(
+            @flags $conn,
+            $name,
+            $crate::functions::FunctionFlags::SQLITE_UTF8,
+            init: $($rest)+
+        )
+    };
+    (@flags $conn:expr, $name:expr, $flags:expr,
+        init: $init:expr,
+        step: |$acc:ident: &mut $acc_ty:ty $(, $arg:ident: $ty:ty)* $(,)?| -> Result<()> $step:block,
+        $($finish:tt)+) => {
+        $crate::__functions_aggregate!(@finish $conn, $name, $flags, $init,
+            ($acc, $acc_ty, $($arg: $ty),*),
+            { (|$acc: &mut $acc_ty $(, $arg: $ty)*| -> $crate::Result<()> { $step })($acc $(, $arg)*) },
+            $($finish)+)
+    };
+    (@flags $conn:expr, $name:expr, $flags:expr,
+        init: $init:expr,
+        step: |$acc:ident: &mut $acc_ty:ty $(, $arg:ident: $ty:ty)* $(,)?| $step:block,
+        $($finish:tt)+) => {
+        $crate::__functions_aggregate!(@finish $conn, $name, $flags, $init,
+            ($acc, $acc_ty, $($arg: $ty),*),
+            {
+                (|$acc: &mut $acc_ty $(, $arg: $ty)*| { $step })($acc $(, $arg)*);
+                Ok(())
+            },
+            $($finish)+)
+    };
+    (@finish $conn:expr, $name:expr, $flags:expr, $init:expr, $args:tt, $step:block,
+        finish: |$facc:ident: $facc_ty:ty $(,)?| -> Result<$ret:ty> $finish:block $(,)?) => {
+        $crate::__functions_aggregate!(@register $conn, $name, $flags, $init, $args, $step,
+            $ret, |$facc: $facc_ty| -> $crate::Result<$ret> { $finish })
+    };
+    (@finish $conn:expr, $name:expr, $flags:expr, $init:expr, $args:tt, $step:block,
+        finish: |$facc:ident: $facc_ty:ty $(,)?| -> $ret:ty $finish:block $(,)?) => {
+        $crate::__functions_aggregate!(@register $conn, $name, $flags, $init, $args, $step,
+            $ret, $crate::functions::__ok(|$facc: $facc_ty| -> $ret { $finish }))
+    };
+    (@register $conn:expr, $name:expr, $flags:expr, $init:expr,
+        ($acc:ident, $acc_ty:ty, $($arg:ident: $ty:ty),*), $step:block, $ret:ty, $finish:expr) => {{
+        let name: &str = $name;
+        let label = name.to_owned();
+        $conn.create_aggregate_function::<$acc_ty, _, $ret>(
+            name,
+            $crate::__functions_scalar!(@count $($arg)*),
+            $flags,
+            $crate::functions::__Fold::new(
+                move || -> $acc_ty { $init },
+                move |ctx: &mut $crate::functions::Context<'_>, $acc: &mut $acc_ty| -> $crate::Result<()> {
+                    $crate::__functions_scalar!(@args ctx, &label, 0; $($arg: $ty),*);
+                    $step
+                },
+                $finish,
+            ),
+        )
+    }};
+}
+
+#[doc(inline)]
+pub use crate::__functions_aggregate as aggregate;
+
 #[cfg(test)]
 mod test {
     use regex::Regex;
@@ -802,7 +1262,8 @@ mod test {
 
     #[cfg(feature = "window")]
     use crate::functions::WindowAggregate;
-    use crate::functions::{Aggregate, Context, FunctionFlags};
+    use crate::functions::{aggregate, scalar, Aggregate, Context, FunctionFlags, FunctionResult};
+    use crate::types::{Null, ValueRef};
     use crate::{Connection, Error, Result};
 
     fn half(ctx: &Context<'_>) -> Result<c_double> {
@@ -826,6 +1287,36 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_panic_policy() -> Result<()> {
+        use crate::PanicPolicy;
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let db = Connection::open_in_memory()?;
+        db.create_scalar_function("boom", 0, FunctionFlags::SQLITE_UTF8, |_| {
+            if true {
+                panic!("boom");
+            }
+            Ok(0)
+        })?;
+
+        assert_eq!(db.panic_policy(), PanicPolicy::ConvertToError);
+        let err = db.one_column::<i64>("SELECT boom()").unwrap_err();
+        assert!(
+            matches!(err, Error::SqliteFailure(_, Some(ref msg)) if msg.contains("panic")),
+            "{}",
+            err
+        );
+
+        db.set_panic_policy(PanicPolicy::Propagate);
+        let payload =
+            catch_unwind(AssertUnwindSafe(|| db.one_column::<i64>("SELECT boom()"))).unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"boom"));
+        // the connection is still usable
+        assert_eq!(db.one_column::<i64>("SELECT 1")?, 1);
+        Ok(())
+    }
+
     #[test]
     fn test_remove_function() -> Result<()> {
         let db = Connection::open_in_memory()?;
@@ -844,6 +1335,93 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_replace_function() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.create_scalar_function("answer", 0, FunctionFlags::SQLITE_UTF8, |_| Ok(41))?;
+        db.create_scalar_function("answer", 1, FunctionFlags::SQLITE_UTF8, |_| Ok(1))?;
+        db.create_scalar_function("answer", 0, FunctionFlags::SQLITE_UTF8, |_| Ok(42))?;
+        let answers: (i64, i64) = db.query_row("SELECT answer(), answer(0)", [], |r| {
+            Ok((r.get(0)?, r.get(1)?))
+        })?;
+        assert_eq!(answers, (42, 1));
+
+        db.remove_function("answer", 0)?;
+        let err = db.one_column::<i64>("SELECT answer()").unwrap_err();
+        assert!(
+            err.to_string().contains("wrong number of arguments"),
+            "{}",
+            err
+        );
+        assert_eq!(db.one_column::<i64>("SELECT answer(0)")?, 1);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "modern_sqlite")]
+    fn test_direct_only() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.create_scalar_function(
+            "direct",
+            0,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DIRECTONLY,
+            |_| Ok(1),
+        )?;
+        assert_eq!(db.one_column::<i64>("SELECT direct()")?, 1);
+
+        db.execute_batch(
+            "CREATE VIEW v AS SELECT direct() AS x;
+             CREATE TABLE t(x);
+             CREATE TABLE log(x);
+             CREATE TRIGGER tr AFTER INSERT ON t BEGIN INSERT INTO log VALUES (direct()); END;",
+        )?;
+        let err = db.one_column::<i64>("SELECT x FROM v").unwrap_err();
+        assert!(
+            err.to_string().contains("unsafe use of direct()"),
+            "{}",
+            err
+        );
+        let err = db.execute("INSERT INTO t VALUES (1)", []).unwrap_err();
+        assert!(
+            err.to_string().contains("unsafe use of direct()"),
+            "{}",
+            err
+        );
+        assert_eq!(db.one_column::<i64>("SELECT count(*) FROM log")?, 0);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "modern_sqlite")]
+    fn test_subtype() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.create_scalar_function(
+            "tagged",
+            1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_RESULT_SUBTYPE,
+            |ctx| {
+                ctx.result_from_arg(0);
+                ctx.set_result_subtype(u32::from(b'J'));
+                Ok(FunctionResult::<Null>::Set)
+            },
+        )?;
+        db.create_scalar_function(
+            "subtype",
+            1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_SUBTYPE,
+            |ctx| Ok(ctx.get_subtype(0)),
+        )?;
+        let subtypes: (u32, u32) =
+            db.query_row("SELECT subtype(tagged('[1]')), subtype('[1]')", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })?;
+        assert_eq!(subtypes, (u32::from(b'J'), 0));
+        // the JSON functions recognize the subtype
+        let json: String = db.one_column("SELECT json_array(tagged('[1]'), '[1]')")?;
+        assert_eq!(json, r#"[[1],"[1]"]"#);
+        Ok(())
+    }
+
     // This implementation of a regexp scalar function uses SQLite's auxiliary data
     // (https://www.sqlite.org/c3ref/get_auxdata.html) to avoid recompiling the regular
     // expression multiple times within one query.
@@ -896,6 +1474,47 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_regexp_compiled_once() -> Result<()> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        static COMPILED: AtomicUsize = AtomicUsize::new(0);
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+        struct Compiled(Regex);
+        impl Drop for Compiled {
+            fn drop(&mut self) {
+                DROPPED.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let db = Connection::open_in_memory()?;
+        db.create_scalar_function("regexp", 2, FunctionFlags::SQLITE_UTF8, |ctx| {
+            let re: Arc<Compiled> = ctx.get_or_create_aux(0, |pattern| -> Result<_> {
+                COMPILED.fetch_add(1, Ordering::SeqCst);
+                Ok(Compiled(Regex::new(pattern.as_str()?).unwrap()))
+            })?;
+            Ok(re.0.is_match(ctx.get_raw(1).as_str()?))
+        })?;
+        db.execute_batch(
+            "CREATE TABLE foo (x TEXT);
+             WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 1000)
+             INSERT INTO foo SELECT 'row ' || i FROM n;",
+        )?;
+
+        let count: i64 = db.one_column("SELECT count(*) FROM foo WHERE x REGEXP '7$'")?;
+        assert_eq!(count, 100);
+        assert_eq!(COMPILED.load(Ordering::SeqCst), 1);
+        assert_eq!(DROPPED.load(Ordering::SeqCst), 1);
+
+        // a pattern varying per row is compiled each time
+        let count: i64 = db.one_column("SELECT count(*) FROM foo WHERE regexp(x, x)")?;
+        assert_eq!(count, 1000);
+        assert_eq!(COMPILED.load(Ordering::SeqCst), 1001);
+        assert_eq!(DROPPED.load(Ordering::SeqCst), 1001);
+        Ok(())
+    }
+
     #[test]
     fn test_varargs_function() -> Result<()> {
         let db = Connection::open_in_memory()?;
@@ -926,6 +1545,166 @@ mod test {
         Ok(())
     }
 
+    fn utf8_range(ctx: &Context<'_>) -> Result<(usize, usize)> {
+        let text = ctx
+            .get_raw(0)
+            .as_str()
+            .map_err(|e| Error::UserFunctionError(e.into()))?;
+        let start = text
+            .char_indices()
+            .nth(ctx.get(1)?)
+            .map_or(text.len(), |(i, _)| i);
+        let len = text[start..]
+            .char_indices()
+            .nth(ctx.get(2)?)
+            .map_or(text.len() - start, |(i, _)| i);
+        Ok((start, start + len))
+    }
+
+    #[test]
+    fn test_set_result_ref() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.create_scalar_function("substr_owned", 3, FunctionFlags::SQLITE_UTF8, |ctx| {
+            let (start, end) = utf8_range(ctx)?;
+            Ok(ctx.get_raw(0).as_str()?[start..end].to_owned())
+        })?;
+        db.create_scalar_function("substr_ref", 3, FunctionFlags::SQLITE_UTF8, |ctx| {
+            let (start, end) = utf8_range(ctx)?;
+            ctx.set_result_ref(ValueRef::Text(&ctx.get_raw(0).as_bytes()?[start..end]));
+            Ok(FunctionResult::<Null>::Set)
+        })?;
+        let (owned, borrowed): (String, String) = db.query_row(
+            "SELECT substr_owned('héllo wörld', 1, 7), substr_ref('héllo wörld', 1, 7)",
+            [],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )?;
+        assert_eq!(owned, "éllo wö");
+        assert_eq!(borrowed, owned);
+
+        db.create_scalar_function("head", 2, FunctionFlags::SQLITE_UTF8, |ctx| {
+            let n = ctx.get::<usize>(1)?;
+            ctx.set_result_ref(ValueRef::Blob(&ctx.get_raw(0).as_blob()?[..n]));
+            Ok(FunctionResult::<Null>::Set)
+        })?;
+        let head: Vec<u8> = db.one_column("SELECT head(x'01020304', 2)")?;
+        assert_eq!(head, [1, 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_passthrough() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.create_scalar_function("second", 2, FunctionFlags::SQLITE_UTF8, |ctx| {
+            Ok(match ctx.get::<Option<i64>>(0)? {
+                Some(_) => FunctionResult::Passthrough(1),
+                None => FunctionResult::Value("none"),
+            })
+        })?;
+        db.create_scalar_function("first", 1, FunctionFlags::SQLITE_UTF8, |ctx| {
+            ctx.result_from_arg(0);
+            Ok(FunctionResult::<Null>::Set)
+        })?;
+
+        let (blob, null, value): (Vec<u8>, Option<i64>, String) = db.query_row(
+            "SELECT second(1, x'cafe'), second(1, NULL), second(NULL, 1)",
+            [],
+            |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+        )?;
+        assert_eq!(blob, [0xca, 0xfe]);
+        assert_eq!(null, None);
+        assert_eq!(value, "none");
+
+        let (blob, null): (Vec<u8>, Option<i64>) =
+            db.query_row("SELECT first(x'cafe'), first(NULL)", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })?;
+        assert_eq!(blob, [0xca, 0xfe]);
+        assert_eq!(null, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_passthrough_large_blob() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.create_scalar_function("coalesce_custom", 2, FunctionFlags::SQLITE_UTF8, |ctx| {
+            Ok(match ctx.get_raw(0) {
+                ValueRef::Null => FunctionResult::<Null>::Passthrough(1),
+                _ => FunctionResult::Passthrough(0),
+            })
+        })?;
+        let blob: Vec<u8> = (0..1 << 20).map(|i| (i % 251) as u8).collect();
+        let (first, second): (Vec<u8>, Vec<u8>) = db.query_row(
+            "SELECT coalesce_custom(?1, x'00'), coalesce_custom(NULL, ?1)",
+            [&blob],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )?;
+        assert!(first == blob);
+        assert!(second == blob);
+        Ok(())
+    }
+
+    #[cfg(feature = "modern_sqlite")]
+    #[test]
+    fn test_pointer() -> Result<()> {
+        use std::ffi::CStr;
+        use std::sync::Arc;
+
+        let buffer_type = CStr::from_bytes_with_nul(b"test_buffer\0").unwrap();
+        let other_type = CStr::from_bytes_with_nul(b"test_other\0").unwrap();
+        let db = Connection::open_in_memory()?;
+        db.create_scalar_function("buffer_len", 1, FunctionFlags::SQLITE_UTF8, move |ctx| {
+            Ok(ctx
+                .get_pointer::<Vec<u8>>(0, buffer_type)
+                .map(|buffer| buffer.len() as i64))
+        })?;
+        db.create_scalar_function("make_buffer", 1, FunctionFlags::SQLITE_UTF8, move |ctx| {
+            let len = ctx.get::<usize>(0)?;
+            ctx.set_result_pointer(Arc::new(vec![0u8; len]), buffer_type);
+            Ok(FunctionResult::<Null>::Set)
+        })?;
+
+        let buffer = Arc::new(vec![1u8; 1 << 10]);
+        let mut stmt = db.prepare("SELECT buffer_len(?1), ?1 IS NULL")?;
+        stmt.bind_pointer(1, buffer.clone(), buffer_type)?;
+        let (len, null): (Option<i64>, bool) = {
+            let mut rows = stmt.raw_query();
+            let row = rows.next()?.unwrap();
+            (row.get(0)?, row.get(1)?)
+        };
+        assert_eq!(len, Some(1 << 10));
+        assert!(null);
+
+        // another type string, or another type, yields NULL
+        stmt.bind_pointer(1, buffer.clone(), other_type)?;
+        let len: Option<i64> = stmt.raw_query().next()?.unwrap().get(0)?;
+        assert_eq!(len, None);
+        stmt.bind_pointer(1, Arc::new(String::from("not a buffer")), buffer_type)?;
+        let len: Option<i64> = stmt.raw_query().next()?.unwrap().get(0)?;
+        assert_eq!(len, None);
+        drop(stmt);
+        assert_eq!(Arc::strong_count(&buffer), 1);
+
+        let len: Option<i64> = db.one_column("SELECT buffer_len(make_buffer(42))")?;
+        assert_eq!(len, Some(42));
+        let len: Option<i64> = db.one_column("SELECT buffer_len(x'00')")?;
+        assert_eq!(len, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_passthrough_out_of_range() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.create_scalar_function("bad", 1, FunctionFlags::SQLITE_UTF8, |_| {
+            Ok(FunctionResult::<Null>::Passthrough(1))
+        })?;
+        let err = db.one_column::<i64>("SELECT bad(1)").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "cannot pass argument 1 through: there are 1"
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_get_aux_type_checking() -> Result<()> {
         let db = Connection::open_in_memory()?;
@@ -1080,4 +1859,157 @@ mod test {
         assert_eq!(expected, results);
         Ok(())
     }
+
+    #[test]
+    #[cfg(feature = "window")]
+    fn test_window_frames() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.create_window_function("sumint", 1, FunctionFlags::SQLITE_UTF8, Sum)?;
+        db.execute_batch(
+            "CREATE TABLE t(g, x, y);
+             WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 50)
+             INSERT INTO t SELECT i % 3, i, (i * 7919) % 101 FROM n;",
+        )?;
+        for frame in [
+            "ORDER BY x ROWS BETWEEN 2 PRECEDING AND CURRENT ROW",
+            "ORDER BY x ROWS BETWEEN 3 PRECEDING AND 2 FOLLOWING",
+            "ORDER BY x ROWS BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW",
+            "PARTITION BY g ORDER BY x ROWS BETWEEN 1 PRECEDING AND 1 FOLLOWING",
+            "PARTITION BY g ORDER BY y RANGE BETWEEN 10 PRECEDING AND 10 FOLLOWING",
+        ] {
+            let mismatches: i64 = db.one_column(&format!(
+                "SELECT count(*) FROM (
+                     SELECT sumint(y) OVER w AS a, sum(y) OVER w AS b FROM t WINDOW w AS ({frame})
+                 ) WHERE a IS NOT b"
+            ))?;
+            assert_eq!(mismatches, 0, "{}", frame);
+        }
+        // registered as a plain aggregate, it still works without OVER
+        let total: i64 = db.one_column("SELECT sumint(y) FROM t")?;
+        assert_eq!(total, db.one_column::<i64>("SELECT sum(y) FROM t")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_scalar_macro() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        scalar!(db, "answer", || -> i64 { 42 })?;
+        scalar!(db, "neg", deterministic, |x: i64| -> i64 { -x })?;
+        scalar!(db, "add_tax", deterministic, |price: f64,
+                                               rate: f64|
+         -> f64 {
+            price * (1.0 + rate)
+        })?;
+        scalar!(db, "join3", |a: String,
+                              b: Option<String>,
+                              c: String|
+         -> String {
+            format!("{}{}{}", a, b.as_deref().unwrap_or("-"), c)
+        })?;
+        scalar!(db, "sum4", |a: i64,
+                             b: i64,
+                             c: i64,
+                             d: Option<i64>|
+         -> Option<i64> {
+            d.map(|d| a + b + c + d)
+        })?;
+
+        assert_eq!(42, db.one_column::<i64>("SELECT answer()")?);
+        assert_eq!(-3, db.one_column::<i64>("SELECT neg(3)")?);
+        assert_eq!(11.0, db.one_column::<f64>("SELECT add_tax(10.0, 0.1)")?);
+        assert_eq!(
+            "a-c",
+            db.one_column::<String>("SELECT join3('a', NULL, 'c')")?
+        );
+        assert_eq!(
+            "abc",
+            db.one_column::<String>("SELECT join3('a', 'b', 'c')")?
+        );
+        assert_eq!(
+            Some(10),
+            db.one_column::<Option<i64>>("SELECT sum4(1, 2, 3, 4)")?
+        );
+        assert_eq!(
+            None,
+            db.one_column::<Option<i64>>("SELECT sum4(1, 2, 3, NULL)")?
+        );
+
+        // SQLite checks the number of arguments
+        let err = db.prepare("SELECT neg(1, 2)").unwrap_err();
+        assert!(err.to_string().contains("wrong number of arguments"));
+        // a mistyped argument is reported with its name
+        let err = db
+            .one_column::<i64>("SELECT sum4(1, 'x', 3, 4)")
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("sum4(): argument b (#1)"),
+            "{}",
+            err
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_scalar_macro_result() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        scalar!(db, "checked_div", |a: i64, b: i64| -> Result<i64> {
+            if b == 0 {
+                return Err(Error::UserFunctionError("division by zero".into()));
+            }
+            Ok(a / b)
+        })?;
+        scalar!(db, "always_fails", || -> Result<i64> {
+            Err(Error::UserFunctionError("no".into()))
+        })?;
+        assert_eq!(3, db.one_column::<i64>("SELECT checked_div(7, 2)")?);
+        let err = db
+            .one_column::<i64>("SELECT checked_div(7, 0)")
+            .unwrap_err();
+        assert!(err.to_string().contains("division by zero"));
+        assert!(db.one_column::<i64>("SELECT always_fails()").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregate_macro() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE t(g, x);
+             INSERT INTO t VALUES (1, 1), (1, 2), (2, 3), (2, NULL);",
+        )?;
+        aggregate!(db, "sum_sq", deterministic,
+            init: 0i64,
+            step: |acc: &mut i64, x: Option<i64>| { *acc += x.map_or(0, |x| x * x) },
+            finish: |acc: i64| -> i64 { acc })?;
+        aggregate!(db, "concat_pairs",
+        init: String::new(),
+        step: |acc: &mut String, a: i64, b: Option<i64>| -> Result<()> {
+            let b = b.ok_or_else(|| Error::UserFunctionError("NULL pair".into()))?;
+            acc.push_str(&format!("{}:{};", a, b));
+            Ok(())
+        },
+        finish: |acc: String| -> Result<Option<String>> {
+            Ok(if acc.is_empty() { None } else { Some(acc) })
+        })?;
+
+        let sums: Vec<i64> = db
+            .prepare("SELECT sum_sq(x) FROM t GROUP BY g ORDER BY g")?
+            .query_map([], |r| r.get(0))?
+            .collect::<Result<_>>()?;
+        assert_eq!(sums, [5, 9]);
+        assert_eq!(0, db.one_column::<i64>("SELECT sum_sq(x) FROM t WHERE 0")?);
+        assert_eq!(
+            "1:1;1:2;",
+            db.one_column::<String>("SELECT concat_pairs(g, x) FROM t WHERE g = 1")?
+        );
+        assert_eq!(
+            None,
+            db.one_column::<Option<String>>("SELECT concat_pairs(g, x) FROM t WHERE 0")?
+        );
+        let err = db
+            .one_column::<String>("SELECT concat_pairs(g, x) FROM t")
+            .unwrap_err();
+        assert!(err.to_string().contains("NULL pair"));
+        Ok(())
+    }
 }
@@ -0,0 +1,168 @@
+//! Live values of fields of the [database
+//! header](https://sqlite.org/fileformat2.html#the_database_header).
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::pragma::Sql;
+use crate::{ffi, Connection, DatabaseName, Error, Result, Transaction, TransactionBehavior};
+
+// Offset of the file change counter in the header.
+const FILE_CHANGE_COUNTER_OFFSET: u64 = 24;
+
+impl Connection {
+    /// Returns the schema cookie of the database `db`, which SQLite
+    /// increments each time the schema changes, as reported by `PRAGMA
+    /// schema_version`.
+    ///
+    /// The value is read through SQLite, so it is up to date in every journal
+    /// mode, and includes schema changes made by an open transaction of this
+    /// connection.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `db` is not a database of this connection.
+    pub fn schema_cookie(&self, db: DatabaseName<'_>) -> Result<u32> {
+        let cookie: i32 = self.pragma_query_value(Some(db), "schema_version", |row| row.get(0))?;
+        Ok(cookie as u32)
+    }
+
+    /// Returns the file change counter of the database `db`: bytes 24 to 27
+    /// of its header, which SQLite increments when a transaction that
+    /// modified the database commits.
+    ///
+    /// Only databases with a rollback journal maintain the counter, so it is
+    /// read directly from the database file, under a shared lock so that no
+    /// commit is in progress. In WAL mode, commits are not written to the
+    /// file until a checkpoint, and the counter is not reliably incremented,
+    /// so this fails instead: use
+    /// [`schema_cookie`](Connection::schema_cookie) to detect schema changes,
+    /// or `PRAGMA data_version` to detect commits of other connections.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `db` is in WAL mode or has no database file, e.g.
+    /// an in-memory database, or if the file cannot be read.
+    pub fn file_change_counter(&self, db: DatabaseName<'_>) -> Result<u32> {
+        let journal_mode: String =
+            self.pragma_query_value(Some(db), "journal_mode", |row| row.get(0))?;
+        if journal_mode.eq_ignore_ascii_case("wal") {
+            return Err(unsupported(
+                "the file change counter is not maintained in WAL mode",
+            ));
+        }
+        let path = self.db_filename(db)?;
+        // the shared lock taken by reading the schema is held until the end
+        // of the transaction
+        let tx = if self.is_autocommit() {
+            Some(Transaction::new_unchecked(
+                self,
+                TransactionBehavior::Deferred,
+            )?)
+        } else {
+            None
+        };
+        let mut sql = Sql::new();
+        sql.push_schema_name(db);
+        self.query_row(
+            &format!("SELECT count(*) FROM {}.sqlite_master", sql.as_str()),
+            [],
+            |_| Ok(()),
+        )?;
+        let mut counter = [0; 4];
+        File::open(&path)
+            .and_then(|mut file| {
+                file.seek(SeekFrom::Start(FILE_CHANGE_COUNTER_OFFSET))?;
+                file.read_exact(&mut counter)
+            })
+            .map_err(|err| {
+                Error::SqliteFailure(
+                    ffi::Error::new(ffi::SQLITE_IOERR),
+                    Some(format!("cannot read the header of {path}: {err}")),
+                )
+            })?;
+        drop(tx);
+        Ok(u32::from_be_bytes(counter))
+    }
+
+    // Returns the file name of the database `db`, failing if it has none.
+    fn db_filename(&self, db: DatabaseName<'_>) -> Result<String> {
+        let name = db.as_cstring()?;
+        let path = unsafe {
+            let path = ffi::sqlite3_db_filename(self.handle(), name.as_ptr());
+            if path.is_null() {
+                return Err(Error::SqliteFailure(
+                    ffi::Error::new(ffi::SQLITE_ERROR),
+                    Some("no such database".to_owned()),
+                ));
+            }
+            CStr::from_ptr(path).to_str()?
+        };
+        if path.is_empty() {
+            return Err(unsupported("the database has no file"));
+        }
+        Ok(path.to_owned())
+    }
+}
+
+fn unsupported(msg: &str) -> Error {
+    Error::SqliteFailure(ffi::Error::new(ffi::SQLITE_MISUSE), Some(msg.to_owned()))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Connection, ErrorCode, Result, MAIN_DB};
+
+    #[test]
+    fn test_schema_cookie() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        for mode in ["delete", "wal"] {
+            let db = Connection::open(temp_dir.path().join(format!("{mode}.db3")))?;
+            db.pragma_update_and_check(None, "journal_mode", mode, |_| Ok(()))?;
+            let cookie = db.schema_cookie(MAIN_DB)?;
+            db.execute_batch("CREATE TABLE foo (x INTEGER)")?;
+            assert_eq!(db.schema_cookie(MAIN_DB)?, cookie + 1, "{}", mode);
+            db.execute_batch("INSERT INTO foo VALUES (1)")?;
+            assert_eq!(db.schema_cookie(MAIN_DB)?, cookie + 1, "{}", mode);
+
+            // seen by other connections
+            let other = Connection::open(temp_dir.path().join(format!("{mode}.db3")))?;
+            db.execute_batch("CREATE INDEX foo_x ON foo (x)")?;
+            assert_eq!(other.schema_cookie(MAIN_DB)?, cookie + 2, "{}", mode);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_change_counter() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let db = Connection::open(&path)?;
+        db.execute_batch("CREATE TABLE foo (x INTEGER)")?;
+        let counter = db.file_change_counter(MAIN_DB)?;
+        db.execute_batch("INSERT INTO foo VALUES (1)")?;
+        assert_eq!(db.file_change_counter(MAIN_DB)?, counter + 1);
+
+        // a read-only transaction does not change it
+        db.query_row("SELECT count(*) FROM foo", [], |_| Ok(()))?;
+        assert_eq!(db.file_change_counter(MAIN_DB)?, counter + 1);
+
+        // inside a transaction
+        let tx = db.unchecked_transaction()?;
+        assert_eq!(tx.file_change_counter(MAIN_DB)?, counter + 1);
+        tx.commit()?;
+
+        let other = Connection::open(&path)?;
+        other.execute_batch("INSERT INTO foo VALUES (2)")?;
+        assert_eq!(db.file_change_counter(MAIN_DB)?, counter + 2);
+
+        db.pragma_update_and_check(None, "journal_mode", "wal", |_| Ok(()))?;
+        let err = db.file_change_counter(MAIN_DB).unwrap_err();
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::ApiMisuse));
+
+        let memory = Connection::open_in_memory()?;
+        let err = memory.file_change_counter(MAIN_DB).unwrap_err();
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::ApiMisuse));
+        Ok(())
+    }
+}
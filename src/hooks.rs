@@ -1,13 +1,30 @@
 //! Commit, Data Change and Rollback Notification Callbacks
 #![allow(non_camel_case_types)]
 
+use std::fs;
+use std::ops::ControlFlow;
 use std::os::raw::{c_char, c_int, c_void};
-use std::panic::{catch_unwind, RefUnwindSafe};
+use std::panic::{catch_unwind, AssertUnwindSafe, RefUnwindSafe};
+use std::path::Path;
 use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use crate::ffi;
+use crate::panic_policy::stash_panic;
 
-use crate::{Connection, InnerConnection};
+use crate::{
+    CancellationToken, Connection, Error, ErrorCode, InnerConnection, Params, Result, Row, Rows,
+};
+
+#[cfg(feature = "preupdate_hook")]
+#[cfg_attr(docsrs, doc(cfg(feature = "preupdate_hook")))]
+mod preupdate;
+#[cfg(feature = "preupdate_hook")]
+#[cfg_attr(docsrs, doc(cfg(feature = "preupdate_hook")))]
+pub use preupdate::{PreUpdateCase, PreUpdateDelete, PreUpdateInsert, PreUpdateUpdate};
 
 /// Action Codes
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -347,17 +364,104 @@ impl Connection {
     where
         F: FnMut() -> bool + Send + 'static,
     {
-        self.db.borrow_mut().commit_hook(hook);
+        let mut db = self.db.borrow_mut();
+        db.events_closed = None;
+        db.commit_hook(hook);
     }
 
     /// Register a callback function to be invoked whenever
-    /// a transaction is committed.
+    /// a transaction is rolled back.
     #[inline]
     pub fn rollback_hook<F>(&self, hook: Option<F>)
     where
         F: FnMut() + Send + 'static,
     {
-        self.db.borrow_mut().rollback_hook(hook);
+        let mut db = self.db.borrow_mut();
+        db.events_closed = None;
+        db.rollback_hook(hook);
+    }
+
+    /// Register a callback function to be invoked each time a transaction
+    /// is committed to a database in WAL mode, with the name of the database
+    /// and the number of frames in its WAL.
+    ///
+    /// An `Err` returned by the callback is reported by the statement that
+    /// committed, although the commit has happened.
+    ///
+    /// This replaces the automatic checkpoints set up by SQLite or by
+    /// [`Connection::wal_autocheckpoint`], so the callback is expected to
+    /// run [`Connection::wal_checkpoint`] itself, e.g. on another connection.
+    #[inline]
+    pub fn wal_hook<F>(&self, hook: Option<F>)
+    where
+        F: FnMut(&str, i32) -> Result<()> + Send + 'static,
+    {
+        self.db.borrow_mut().wal_hook(hook);
+    }
+
+    /// Returns a channel receiving an event each time a transaction ends on
+    /// this connection, including the implicit transaction of a statement
+    /// run in autocommit mode.
+    ///
+    /// The events are sent by a commit hook and a rollback hook, which
+    /// replace those set with [`commit_hook`](Connection::commit_hook) and
+    /// [`rollback_hook`](Connection::rollback_hook), and are replaced by
+    /// them. Once the receiver is dropped, the hooks are unregistered by the
+    /// next statement run on this connection after a transaction ends.
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// # use rusqlite::hooks::TransactionEvent;
+    /// fn audit(conn: &Connection) -> Result<()> {
+    ///     let events = conn.commit_events();
+    ///     conn.execute("INSERT INTO log (msg) VALUES ('hello')", [])?;
+    ///     for event in events.try_iter() {
+    ///         if let TransactionEvent::Commit(commit) = event {
+    ///             println!("transaction #{} committed", commit.sequence);
+    ///         }
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn commit_events(&self) -> Receiver<TransactionEvent> {
+        let (sender, receiver) = mpsc::channel();
+        let closed = Arc::new(AtomicBool::new(false));
+        let sink = Arc::new(Mutex::new(EventSink {
+            sender,
+            sequence: 0,
+            closed: closed.clone(),
+        }));
+        let commit_sink = sink.clone();
+
+        let mut db = self.db.borrow_mut();
+        db.commit_hook(Some(move || {
+            if let Ok(mut sink) = commit_sink.lock() {
+                sink.send(true);
+            }
+            false
+        }));
+        db.rollback_hook(Some(move || {
+            if let Ok(mut sink) = sink.lock() {
+                sink.send(false);
+            }
+        }));
+        db.events_closed = Some(closed);
+        receiver
+    }
+
+    // Unregisters the hooks of `commit_events` once its receiver is gone.
+    #[inline]
+    pub(crate) fn release_closed_events(&self) {
+        let closed = match self.db.borrow().events_closed {
+            Some(ref closed) => closed.load(Ordering::Relaxed),
+            None => return,
+        };
+        if closed {
+            let mut db = self.db.borrow_mut();
+            db.events_closed = None;
+            db.commit_hook(None::<fn() -> bool>);
+            db.rollback_hook(None::<fn()>);
+        }
     }
 
     /// Register a callback function to be invoked whenever
@@ -395,6 +499,11 @@ impl Connection {
 
     /// Register an authorizer callback that's invoked
     /// as a statement is being prepared.
+    ///
+    /// Returning [`Authorization::Deny`] makes preparing the statement fail
+    /// with [`ErrorCode::AuthorizationForStatementDenied`], while
+    /// [`Authorization::Ignore`] makes a read return NULL or skips a write,
+    /// depending on the action.
     #[inline]
     pub fn authorizer<'c, F>(&self, hook: Option<F>)
     where
@@ -402,16 +511,445 @@ impl Connection {
     {
         self.db.borrow_mut().authorizer(hook);
     }
+
+    /// Reject the transaction statements, `BEGIN`, `COMMIT`, `ROLLBACK`,
+    /// `SAVEPOINT`, `RELEASE` and `ROLLBACK TO`, run as raw SQL rather than
+    /// through [`Transaction`](crate::Transaction) and
+    /// [`Savepoint`](crate::Savepoint), e.g. by a helper called inside a
+    /// transaction, which would end it behind the back of the `Transaction`.
+    ///
+    /// Preparing such a statement then fails with
+    /// [`Error::RawTransactionSql`]. This is checked by the authorizer, when
+    /// the statement is prepared: a statement prepared before, e.g. cached,
+    /// is not checked. It works alongside [`Connection::authorizer`].
+    #[inline]
+    pub fn forbid_raw_transaction_sql(&self, forbid: bool) {
+        self.db.borrow_mut().forbid_raw_transaction_sql(forbid);
+    }
+}
+
+/// The end of a transaction, as received from
+/// [`Connection::commit_events`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TransactionEvent {
+    /// A transaction is about to be committed.
+    Commit(CommitEvent),
+    /// A transaction was rolled back.
+    Rollback(RollbackEvent),
+}
+
+/// A transaction about to be committed, as sent by the commit hook.
+///
+/// The commit can still fail afterwards, e.g. if the database is busy, in
+/// which case a [`RollbackEvent`] may follow.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CommitEvent {
+    /// The number of transactions ended on the connection since
+    /// [`Connection::commit_events`] was called, including this one.
+    pub sequence: u64,
+}
+
+/// A rolled back transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RollbackEvent {
+    /// The number of transactions ended on the connection since
+    /// [`Connection::commit_events`] was called, including this one.
+    pub sequence: u64,
+}
+
+// State shared by the commit and rollback hooks of `commit_events`.
+struct EventSink {
+    sender: Sender<TransactionEvent>,
+    // number of transactions ended so far
+    sequence: u64,
+    closed: Arc<AtomicBool>,
+}
+
+impl EventSink {
+    fn send(&mut self, commit: bool) {
+        self.sequence += 1;
+        let sequence = self.sequence;
+        let event = if commit {
+            TransactionEvent::Commit(CommitEvent { sequence })
+        } else {
+            TransactionEvent::Rollback(RollbackEvent { sequence })
+        };
+        if self.sender.send(event).is_err() {
+            self.closed.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// How often [`Statement::query_with_budget`](crate::Statement::query_with_budget)
+/// gives control back to the caller while a query runs.
+#[derive(Clone, Copy, Debug)]
+pub struct StepBudget<F> {
+    /// The approximate number of virtual machine instructions evaluated
+    /// between calls to `yield_fn`. Must be at least one.
+    pub steps_per_yield: c_int,
+    /// Called every `steps_per_yield` instructions, e.g. to check a
+    /// cancellation flag or record progress. Returning `ControlFlow::Break`
+    /// stops the query with [`Error::Cancelled`].
+    pub yield_fn: F,
+}
+
+/// The rows of a query run by
+/// [`Statement::query_with_budget`](crate::Statement::query_with_budget).
+///
+/// The yield function stays installed as the connection's progress handler
+/// until this is dropped.
+pub struct BudgetedRows<'stmt> {
+    rows: Rows<'stmt>,
+    conn: &'stmt Connection,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl<'stmt> BudgetedRows<'stmt> {
+    #[inline]
+    pub(crate) fn new(
+        rows: Rows<'stmt>,
+        conn: &'stmt Connection,
+        cancelled: Arc<AtomicBool>,
+    ) -> BudgetedRows<'stmt> {
+        BudgetedRows {
+            rows,
+            conn,
+            cancelled,
+        }
+    }
+
+    /// Attempt to get the next row from the query, as
+    /// [`Rows::next`](crate::Rows::next) does.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err(Error::Cancelled)` if the yield function returned
+    /// `ControlFlow::Break`.
+    #[allow(clippy::should_implement_trait)] // cannot implement Iterator
+    pub fn next(&mut self) -> Result<Option<&Row<'stmt>>> {
+        let cancelled = &self.cancelled;
+        self.rows.next().map_err(|err| {
+            if cancelled.load(Ordering::Relaxed) {
+                Error::Cancelled
+            } else {
+                err
+            }
+        })
+    }
+}
+
+impl Drop for BudgetedRows<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        self.conn.progress_handler(0, None::<fn() -> bool>);
+    }
+}
+
+impl Connection {
+    // Installs `budget` as the progress handler, returning the flag set when
+    // its yield function cancels the query.
+    pub(crate) fn install_step_budget<F>(&self, budget: StepBudget<F>) -> Result<Arc<AtomicBool>>
+    where
+        F: FnMut() -> ControlFlow<()> + Send + RefUnwindSafe + 'static,
+    {
+        let misuse = |msg: &str| {
+            Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_MISUSE),
+                Some(msg.to_owned()),
+            ))
+        };
+        if budget.steps_per_yield < 1 {
+            return misuse("steps_per_yield must be at least one");
+        }
+        if self.db.borrow().progress_handler.is_some() {
+            return misuse("a progress handler is already registered on this connection");
+        }
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let flag = cancelled.clone();
+        let mut yield_fn = budget.yield_fn;
+        self.progress_handler(
+            budget.steps_per_yield,
+            Some(move || {
+                let stop = yield_fn().is_break();
+                if stop {
+                    flag.store(true, Ordering::Relaxed);
+                }
+                stop
+            }),
+        );
+        Ok(cancelled)
+    }
+}
+
+// Number of virtual machine instructions between checks of a
+// `CancellationToken`.
+const CANCELLATION_CHECK_OPS: c_int = 1000;
+
+impl Connection {
+    /// Rebuild the main database with `VACUUM`, stopping with
+    /// [`Error::Cancelled`] as soon as `token` is cancelled, in which case the
+    /// database is left as it was.
+    ///
+    /// The token is checked by a progress handler, which replaces the one
+    /// registered with [`Connection::progress_handler`] until this returns.
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{CancellationToken, Connection, Result};
+    /// # use std::{thread, time::Duration};
+    /// fn vacuum_for_a_minute(conn: &Connection) -> Result<()> {
+    ///     let token = CancellationToken::new();
+    ///     let timeout = token.clone();
+    ///     thread::spawn(move || {
+    ///         thread::sleep(Duration::from_secs(60));
+    ///         timeout.cancel();
+    ///     });
+    ///     conn.vacuum_cancellable(&token)
+    /// }
+    /// ```
+    pub fn vacuum_cancellable(&self, token: &CancellationToken) -> Result<()> {
+        self.with_cancellation(token, || self.vacuum())
+    }
+
+    /// Write a vacuumed copy of the main database to the new file `path`
+    /// with `VACUUM INTO`, stopping with [`Error::Cancelled`] as soon as
+    /// `token` is cancelled, in which case the partially written file is
+    /// deleted.
+    ///
+    /// The token is checked as by
+    /// [`vacuum_cancellable`](Connection::vacuum_cancellable). `VACUUM INTO`
+    /// requires SQLite 3.27.0 or later.
+    ///
+    /// # Failure
+    ///
+    /// Fails as [`vacuum_into`](Connection::vacuum_into) does.
+    pub fn vacuum_into_cancellable<P: AsRef<Path>>(
+        &self,
+        path: P,
+        token: &CancellationToken,
+    ) -> Result<()> {
+        let path = path.as_ref();
+        let r = self.vacuum_into_with(path, |utf8_path| {
+            self.with_cancellation(token, || {
+                self.execute("VACUUM INTO ?1", [utf8_path]).map(|_| ())
+            })
+        });
+        if let Err(Error::Cancelled) = r {
+            // best effort: the cancellation is what gets reported
+            let _ = fs::remove_file(path);
+        }
+        r
+    }
+
+    // Runs `f` with a progress handler which interrupts it once `token` is
+    // cancelled, then restores the previous progress handler.
+    fn with_cancellation<T, F>(&self, token: &CancellationToken, f: F) -> Result<T>
+    where
+        F: FnOnce() -> Result<T>,
+    {
+        if token.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        let handler_token = token.clone();
+        let previous = self
+            .replace_progress_handler(CANCELLATION_CHECK_OPS, move || handler_token.is_cancelled());
+        let r = f();
+        self.restore_progress_handler(previous);
+        match r {
+            Err(ref err)
+                if token.is_cancelled()
+                    && err.sqlite_error_code() == Some(ErrorCode::OperationInterrupted) =>
+            {
+                Err(Error::Cancelled)
+            }
+            r => r,
+        }
+    }
+}
+
+// A progress handler taken off a connection by
+// `Connection::replace_progress_handler`.
+pub(crate) struct PreviousProgressHandler {
+    handler: Option<Box<dyn FnMut() -> bool + Send>>,
+    num_ops: c_int,
+}
+
+impl Connection {
+    // Installs `handler` as the progress handler, returning the previous one
+    // for `restore_progress_handler`.
+    fn replace_progress_handler<F>(&self, num_ops: c_int, handler: F) -> PreviousProgressHandler
+    where
+        F: FnMut() -> bool + Send + RefUnwindSafe + 'static,
+    {
+        let previous = {
+            let mut db = self.db.borrow_mut();
+            PreviousProgressHandler {
+                handler: db.progress_handler.take(),
+                num_ops: db.progress_handler_ops,
+            }
+        };
+        self.progress_handler(num_ops, Some(handler));
+        previous
+    }
+
+    pub(crate) fn restore_progress_handler(&self, previous: PreviousProgressHandler) {
+        match previous.handler {
+            Some(handler) => {
+                let mut handler = AssertUnwindSafe(handler);
+                self.progress_handler(previous.num_ops, Some(move || (*handler)()));
+            }
+            None => self.progress_handler(0, None::<fn() -> bool>),
+        }
+    }
+
+    /// Execute the statement `sql` with `params`, as
+    /// [`execute`](Connection::execute) does, interrupting it if it has not
+    /// finished after `timeout`.
+    ///
+    /// The deadline is checked by a progress handler, which replaces the one
+    /// registered with [`Connection::progress_handler`] until this returns,
+    /// and is then restored.
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Error, Result};
+    /// # use std::time::Duration;
+    /// fn purge(conn: &Connection) -> Result<usize> {
+    ///     match conn.execute_with_deadline(
+    ///         "DELETE FROM log WHERE at < ?1",
+    ///         [1_600_000_000],
+    ///         Duration::from_secs(5),
+    ///     ) {
+    ///         Err(Error::QueryTimeout(_)) => Ok(0), // try again later
+    ///         r => r,
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err(Error::QueryTimeout(timeout))` if the statement was
+    /// interrupted at its deadline, in which case its changes are rolled
+    /// back, or `Err` if `sql` cannot be converted to a C-compatible string or
+    /// if the underlying SQLite call fails.
+    pub fn execute_with_deadline<P: Params>(
+        &self,
+        sql: &str,
+        params: P,
+        timeout: Duration,
+    ) -> Result<usize> {
+        let mut stmt = self.prepare(sql)?;
+        let (expired, previous) = self.install_deadline(timeout);
+        let r = stmt.execute(params);
+        self.restore_progress_handler(previous);
+        r.map_err(|err| deadline_error(err, &expired, timeout))
+    }
+
+    // Installs a progress handler which interrupts statements once `timeout`
+    // has elapsed, returning the flag it sets then and the previous handler.
+    pub(crate) fn install_deadline(
+        &self,
+        timeout: Duration,
+    ) -> (Arc<AtomicBool>, PreviousProgressHandler) {
+        let expired = Arc::new(AtomicBool::new(false));
+        let flag = expired.clone();
+        // a deadline too far away to be represented is never reached
+        let deadline = Instant::now().checked_add(timeout);
+        let previous = self.replace_progress_handler(CANCELLATION_CHECK_OPS, move || {
+            let stop = deadline.is_some_and(|deadline| Instant::now() >= deadline);
+            if stop {
+                flag.store(true, Ordering::Relaxed);
+            }
+            stop
+        });
+        (expired, previous)
+    }
+}
+
+// Turns the interruption of a statement at its deadline into
+// `Error::QueryTimeout`.
+fn deadline_error(err: Error, expired: &AtomicBool, timeout: Duration) -> Error {
+    if expired.load(Ordering::Relaxed)
+        && err.sqlite_error_code() == Some(ErrorCode::OperationInterrupted)
+    {
+        Error::QueryTimeout(timeout)
+    } else {
+        err
+    }
+}
+
+/// The rows of a query run by
+/// [`Statement::query_with_deadline`](crate::Statement::query_with_deadline).
+///
+/// The deadline stays installed as the connection's progress handler until
+/// this is dropped, when the previous progress handler is restored.
+pub struct DeadlineRows<'stmt> {
+    rows: Rows<'stmt>,
+    conn: &'stmt Connection,
+    expired: Arc<AtomicBool>,
+    timeout: Duration,
+    previous: Option<PreviousProgressHandler>,
+}
+
+impl<'stmt> DeadlineRows<'stmt> {
+    #[inline]
+    pub(crate) fn new(
+        rows: Rows<'stmt>,
+        conn: &'stmt Connection,
+        expired: Arc<AtomicBool>,
+        timeout: Duration,
+        previous: PreviousProgressHandler,
+    ) -> DeadlineRows<'stmt> {
+        DeadlineRows {
+            rows,
+            conn,
+            expired,
+            timeout,
+            previous: Some(previous),
+        }
+    }
+
+    /// Attempt to get the next row from the query, as
+    /// [`Rows::next`](crate::Rows::next) does.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err(Error::QueryTimeout(timeout))` if the query was
+    /// interrupted at its deadline.
+    #[allow(clippy::should_implement_trait)] // cannot implement Iterator
+    pub fn next(&mut self) -> Result<Option<&Row<'stmt>>> {
+        let (expired, timeout) = (&self.expired, self.timeout);
+        self.rows
+            .next()
+            .map_err(|err| deadline_error(err, expired, timeout))
+    }
+}
+
+impl Drop for DeadlineRows<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            self.conn.restore_progress_handler(previous);
+        }
+    }
 }
 
 impl InnerConnection {
     #[inline]
     pub fn remove_hooks(&mut self) {
         self.update_hook(None::<fn(Action, &str, &str, i64)>);
+        #[cfg(feature = "preupdate_hook")]
+        self.preupdate_hook(None::<fn(&str, &str, &PreUpdateCase<'_>)>);
         self.commit_hook(None::<fn() -> bool>);
         self.rollback_hook(None::<fn()>);
+        if self.free_wal_hook.is_some() {
+            self.wal_hook(None::<fn(&str, i32) -> Result<()>>);
+        }
         self.progress_handler(0, None::<fn() -> bool>);
         self.authorizer(None::<fn(AuthContext<'_>) -> Authorization>);
+        self.forbid_raw_transaction_sql(false);
+        self.events_closed = None;
     }
 
     fn commit_hook<F>(&mut self, hook: Option<F>)
@@ -426,7 +964,10 @@ impl InnerConnection {
                 let boxed_hook: *mut F = p_arg.cast::<F>();
                 (*boxed_hook)()
             });
-            c_int::from(r.unwrap_or_default())
+            c_int::from(r.unwrap_or_else(|payload| {
+                stash_panic(payload);
+                false
+            }))
         }
 
         // unlike `sqlite3_create_function_v2`, we cannot specify a `xDestroy` with
@@ -467,10 +1008,12 @@ impl InnerConnection {
         where
             F: FnMut(),
         {
-            drop(catch_unwind(|| {
+            if let Err(payload) = catch_unwind(|| {
                 let boxed_hook: *mut F = p_arg.cast::<F>();
                 (*boxed_hook)();
-            }));
+            }) {
+                stash_panic(payload);
+            }
         }
 
         let free_rollback_hook = if hook.is_some() {
@@ -500,6 +1043,64 @@ impl InnerConnection {
         self.free_rollback_hook = free_rollback_hook;
     }
 
+    pub fn wal_hook<F>(&mut self, hook: Option<F>)
+    where
+        F: FnMut(&str, i32) -> Result<()> + Send + 'static,
+    {
+        unsafe extern "C" fn call_boxed_closure<F>(
+            p_arg: *mut c_void,
+            _: *mut ffi::sqlite3,
+            p_db_name: *const c_char,
+            pages: c_int,
+        ) -> c_int
+        where
+            F: FnMut(&str, i32) -> Result<()>,
+        {
+            let r = catch_unwind(|| {
+                let boxed_hook: *mut F = p_arg.cast::<F>();
+                (*boxed_hook)(expect_utf8(p_db_name, "database name"), pages)
+            });
+            match r {
+                Ok(Ok(())) => ffi::SQLITE_OK,
+                Ok(Err(Error::SqliteFailure(err, _))) => err.extended_code,
+                Ok(Err(_)) => ffi::SQLITE_ERROR,
+                Err(payload) => {
+                    stash_panic(payload);
+                    ffi::SQLITE_ERROR
+                }
+            }
+        }
+
+        // as with `sqlite3_commit_hook`, the previous argument is returned but
+        // not destroyed, and `sqlite3_wal_autocheckpoint` registers a hook
+        // whose argument is not a pointer: only free ours.
+        let free_wal_hook = if hook.is_some() {
+            Some(free_boxed_hook::<F> as unsafe fn(*mut c_void))
+        } else {
+            None
+        };
+
+        let previous_hook = match hook {
+            Some(hook) => {
+                let boxed_hook: *mut F = Box::into_raw(Box::new(hook));
+                unsafe {
+                    ffi::sqlite3_wal_hook(
+                        self.db(),
+                        Some(call_boxed_closure::<F>),
+                        boxed_hook.cast(),
+                    )
+                }
+            }
+            _ => unsafe { ffi::sqlite3_wal_hook(self.db(), None, ptr::null_mut()) },
+        };
+        if !previous_hook.is_null() {
+            if let Some(free_boxed_hook) = self.free_wal_hook {
+                unsafe { free_boxed_hook(previous_hook) };
+            }
+        }
+        self.free_wal_hook = free_wal_hook;
+    }
+
     fn update_hook<F>(&mut self, hook: Option<F>)
     where
         F: FnMut(Action, &str, &str, i64) + Send + 'static,
@@ -514,7 +1115,7 @@ impl InnerConnection {
             F: FnMut(Action, &str, &str, i64),
         {
             let action = Action::from(action_code);
-            drop(catch_unwind(|| {
+            if let Err(payload) = catch_unwind(|| {
                 let boxed_hook: *mut F = p_arg.cast::<F>();
                 (*boxed_hook)(
                     action,
@@ -522,7 +1123,9 @@ impl InnerConnection {
                     expect_utf8(p_table_name, "table name"),
                     row_id,
                 );
-            }));
+            }) {
+                stash_panic(payload);
+            }
         }
 
         let free_update_hook = if hook.is_some() {
@@ -564,7 +1167,10 @@ impl InnerConnection {
                 let boxed_handler: *mut F = p_arg.cast::<F>();
                 (*boxed_handler)()
             });
-            c_int::from(r.unwrap_or_default())
+            c_int::from(r.unwrap_or_else(|payload| {
+                stash_panic(payload);
+                false
+            }))
         }
 
         if let Some(handler) = handler {
@@ -582,59 +1188,52 @@ impl InnerConnection {
             unsafe { ffi::sqlite3_progress_handler(self.db(), num_ops, None, ptr::null_mut()) }
             self.progress_handler = None;
         };
+        self.progress_handler_ops = num_ops;
     }
 
-    fn authorizer<'c, F>(&'c mut self, authorizer: Option<F>)
+    fn authorizer<F>(&mut self, authorizer: Option<F>)
     where
         F: for<'r> FnMut(AuthContext<'r>) -> Authorization + Send + RefUnwindSafe + 'static,
     {
-        unsafe extern "C" fn call_boxed_closure<'c, F>(
-            p_arg: *mut c_void,
-            action_code: c_int,
-            param1: *const c_char,
-            param2: *const c_char,
-            db_name: *const c_char,
-            trigger_or_view_name: *const c_char,
-        ) -> c_int
-        where
-            F: FnMut(AuthContext<'c>) -> Authorization + Send + 'static,
-        {
-            catch_unwind(|| {
-                let action = AuthAction::from_raw(
-                    action_code,
-                    expect_optional_utf8(param1, "authorizer param 1"),
-                    expect_optional_utf8(param2, "authorizer param 2"),
-                );
-                let auth_ctx = AuthContext {
-                    action,
-                    database_name: expect_optional_utf8(db_name, "database name"),
-                    accessor: expect_optional_utf8(
-                        trigger_or_view_name,
-                        "accessor (inner-most trigger or view)",
-                    ),
-                };
-                let boxed_hook: *mut F = p_arg.cast::<F>();
-                (*boxed_hook)(auth_ctx)
-            })
-            .map_or_else(|_| ffi::SQLITE_ERROR, Authorization::into_raw)
-        }
-
-        let callback_fn = authorizer
-            .as_ref()
-            .map(|_| call_boxed_closure::<'c, F> as unsafe extern "C" fn(_, _, _, _, _, _) -> _);
-        let boxed_authorizer = authorizer.map(Box::new);
-
-        match unsafe {
-            ffi::sqlite3_set_authorizer(
-                self.db(),
-                callback_fn,
-                boxed_authorizer
-                    .as_ref()
-                    .map_or_else(ptr::null_mut, |f| &**f as *const F as *mut _),
-            )
-        } {
+        let state = self.authorizer.get_or_insert_with(Default::default);
+        state.user = authorizer.map(|f| Box::new(f) as BoxedAuthorizer);
+        self.install_authorizer();
+    }
+
+    fn forbid_raw_transaction_sql(&mut self, forbid: bool) {
+        let state = self.authorizer.get_or_insert_with(Default::default);
+        state.forbid_raw_transaction_sql = forbid;
+        self.install_authorizer();
+    }
+
+    // Returns the error for a transaction statement denied by the guard of
+    // `forbid_raw_transaction_sql`, if that is why preparing failed.
+    pub fn take_raw_transaction_sql_error(&mut self) -> Option<Error> {
+        let (verb, hint) = self.authorizer.as_mut()?.denied.take()?;
+        Some(Error::RawTransactionSql {
+            verb: verb.to_owned(),
+            hint,
+        })
+    }
+
+    // Registers `call_authorizer` if the user's authorizer or the guard is
+    // set, or unregisters it and frees the state.
+    fn install_authorizer(&mut self) {
+        let active = matches!(
+            self.authorizer,
+            Some(ref state) if state.user.is_some() || state.forbid_raw_transaction_sql
+        );
+        let r = if active {
+            let state: *mut AuthorizerState = &mut **self.authorizer.as_mut().unwrap();
+            unsafe { ffi::sqlite3_set_authorizer(self.db(), Some(call_authorizer), state.cast()) }
+        } else {
+            unsafe { ffi::sqlite3_set_authorizer(self.db(), None, ptr::null_mut()) }
+        };
+        match r {
             ffi::SQLITE_OK => {
-                self.authorizer = boxed_authorizer.map(|ba| ba as _);
+                if !active {
+                    self.authorizer = None;
+                }
             }
             err_code => {
                 // The only error that `sqlite3_set_authorizer` returns is `SQLITE_MISUSE`
@@ -650,6 +1249,110 @@ impl InnerConnection {
     }
 }
 
+// What the authorizer registered with SQLite checks: the user's authorizer,
+// and the guard of `forbid_raw_transaction_sql`.
+#[derive(Default)]
+pub(crate) struct AuthorizerState {
+    user: Option<BoxedAuthorizer>,
+    forbid_raw_transaction_sql: bool,
+    // Set while the crate runs its own transaction statements.
+    expected: bool,
+    // The statement denied by the guard, and the API to use instead.
+    denied: Option<(&'static str, &'static str)>,
+}
+
+unsafe extern "C" fn call_authorizer(
+    p_arg: *mut c_void,
+    action_code: c_int,
+    param1: *const c_char,
+    param2: *const c_char,
+    db_name: *const c_char,
+    trigger_or_view_name: *const c_char,
+) -> c_int {
+    let state = &mut *p_arg.cast::<AuthorizerState>();
+    catch_unwind(AssertUnwindSafe(|| {
+        let operation = expect_optional_utf8(param1, "authorizer param 1");
+        if state.forbid_raw_transaction_sql && !state.expected {
+            if let Some(denied) = raw_transaction_sql(action_code, operation) {
+                state.denied = Some(denied);
+                return Authorization::Deny;
+            }
+        }
+        let user = match state.user {
+            Some(ref mut user) => user,
+            None => return Authorization::Allow,
+        };
+        let action = AuthAction::from_raw(
+            action_code,
+            operation,
+            expect_optional_utf8(param2, "authorizer param 2"),
+        );
+        user(AuthContext {
+            action,
+            database_name: expect_optional_utf8(db_name, "database name"),
+            accessor: expect_optional_utf8(
+                trigger_or_view_name,
+                "accessor (inner-most trigger or view)",
+            ),
+        })
+    }))
+    .map_or_else(
+        |payload| {
+            stash_panic(payload);
+            ffi::SQLITE_ERROR
+        },
+        Authorization::into_raw,
+    )
+}
+
+// Returns the verb of a transaction statement, and the API to use instead.
+fn raw_transaction_sql(
+    action_code: c_int,
+    operation: Option<&str>,
+) -> Option<(&'static str, &'static str)> {
+    const TRANSACTION: &str = "use `Connection::transaction` and commit or roll back the \
+                               `Transaction` instead";
+    const SAVEPOINT: &str = "use `Connection::savepoint` or `Transaction::savepoint` and \
+                             commit or roll back the `Savepoint` instead";
+    let verb = match (action_code, operation?) {
+        (ffi::SQLITE_TRANSACTION, "BEGIN") => ("BEGIN", TRANSACTION),
+        (ffi::SQLITE_TRANSACTION, "COMMIT") => ("COMMIT", TRANSACTION),
+        (ffi::SQLITE_TRANSACTION, "ROLLBACK") => ("ROLLBACK", TRANSACTION),
+        (ffi::SQLITE_SAVEPOINT, "BEGIN") => ("SAVEPOINT", SAVEPOINT),
+        (ffi::SQLITE_SAVEPOINT, "RELEASE") => ("RELEASE", SAVEPOINT),
+        (ffi::SQLITE_SAVEPOINT, "ROLLBACK") => ("ROLLBACK TO", SAVEPOINT),
+        _ => return None,
+    };
+    Some(verb)
+}
+
+// Lets the transaction statements run by the crate through the guard of
+// `forbid_raw_transaction_sql`, until dropped, even by a panic.
+pub(crate) struct ExpectTransactionSql<'conn> {
+    conn: &'conn Connection,
+    previous: bool,
+}
+
+impl ExpectTransactionSql<'_> {
+    pub(crate) fn new(conn: &Connection) -> ExpectTransactionSql<'_> {
+        let previous = match conn.db.borrow_mut().authorizer {
+            Some(ref mut state) => std::mem::replace(&mut state.expected, true),
+            None => false,
+        };
+        ExpectTransactionSql { conn, previous }
+    }
+}
+
+impl Drop for ExpectTransactionSql<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut db) = self.conn.db.try_borrow_mut() {
+            if let Some(ref mut state) = db.authorizer {
+                state.expected = self.previous;
+            }
+        }
+    }
+}
+
 unsafe fn free_boxed_hook<F>(p: *mut c_void) {
     drop(Box::from_raw(p.cast::<F>()));
 }
@@ -673,9 +1376,12 @@ unsafe fn expect_optional_utf8<'a>(
 
 #[cfg(test)]
 mod test {
-    use super::Action;
-    use crate::{Connection, Result};
-    use std::sync::atomic::{AtomicBool, Ordering};
+    use super::{Action, CommitEvent, RollbackEvent, StepBudget, TransactionEvent};
+    use crate::{ffi, CancellationToken, Connection, Error, Result};
+    use std::ops::ControlFlow;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
 
     #[test]
     fn test_commit_hook() -> Result<()> {
@@ -691,6 +1397,43 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_wal_hook() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db = Connection::open(temp_dir.path().join("test.db3"))?;
+        let mode: String = db.one_column("PRAGMA journal_mode = WAL")?;
+        assert_eq!(mode, "wal");
+
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let seen = calls.clone();
+        db.wal_hook(Some(move |name: &str, pages| {
+            seen.lock().unwrap().push((name.to_owned(), pages));
+            Ok(())
+        }));
+        db.execute_batch("CREATE TABLE foo (t TEXT); INSERT INTO foo VALUES ('a');")?;
+        {
+            let calls = calls.lock().unwrap();
+            assert_eq!(calls.len(), 2);
+            assert_eq!(calls[0].0, "main");
+            assert!(calls[1].1 > calls[0].1);
+        }
+
+        db.wal_hook(Some(|_: &str, _| {
+            Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_IOERR),
+                None,
+            ))
+        }));
+        db.execute("INSERT INTO foo VALUES ('b')", []).unwrap_err();
+        let count: i64 = db.one_column("SELECT count(*) FROM foo")?;
+        assert_eq!(count, 2);
+
+        db.wal_hook(None::<fn(&str, i32) -> Result<()>>);
+        db.execute("INSERT INTO foo VALUES ('c')", [])?;
+        assert_eq!(calls.lock().unwrap().len(), 2);
+        Ok(())
+    }
+
     #[test]
     fn test_fn_commit_hook() -> Result<()> {
         let db = Connection::open_in_memory()?;
@@ -718,6 +1461,45 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_commit_events() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo (t TEXT)")?;
+        let events = db.commit_events();
+
+        db.execute_batch(
+            "BEGIN; INSERT INTO foo VALUES ('a'); INSERT INTO foo VALUES ('b'); COMMIT;",
+        )?;
+        db.execute("INSERT INTO foo VALUES ('c')", [])?;
+        db.execute_batch("BEGIN; DELETE FROM foo; ROLLBACK;")?;
+        let events: Vec<_> = events.try_iter().collect();
+        assert_eq!(
+            events,
+            [
+                TransactionEvent::Commit(CommitEvent { sequence: 1 }),
+                TransactionEvent::Commit(CommitEvent { sequence: 2 }),
+                TransactionEvent::Rollback(RollbackEvent { sequence: 3 }),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit_events_dropped() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo (t TEXT)")?;
+        let events = db.commit_events();
+        assert!(db.db.borrow().free_commit_hook.is_some());
+        drop(events);
+
+        db.execute("INSERT INTO foo VALUES ('a')", [])?;
+        let db = db.db.borrow();
+        assert!(db.events_closed.is_none());
+        assert!(db.free_commit_hook.is_none());
+        assert!(db.free_rollback_hook.is_none());
+        Ok(())
+    }
+
     #[test]
     fn test_update_hook() -> Result<()> {
         let db = Connection::open_in_memory()?;
@@ -767,6 +1549,97 @@ mod test {
         Ok(())
     }
 
+    fn yields_for(db: &Connection, n: i64) -> Result<usize> {
+        let yields = Arc::new(AtomicUsize::new(0));
+        let counter = yields.clone();
+        let mut stmt = db.prepare(
+            "WITH RECURSIVE c(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM c WHERE i < ?1)
+             SELECT count(*) FROM c",
+        )?;
+        let budget = StepBudget {
+            steps_per_yield: 100,
+            yield_fn: move || {
+                counter.fetch_add(1, Ordering::Relaxed);
+                ControlFlow::Continue(())
+            },
+        };
+        let mut rows = stmt.query_with_budget([n], budget)?;
+        let count: i64 = rows.next()?.unwrap().get(0)?;
+        assert_eq!(count, n);
+        drop(rows);
+        let yields = yields.load(Ordering::Relaxed);
+        Ok(yields)
+    }
+
+    #[test]
+    fn test_step_budget() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        let small = yields_for(&db, 1_000)?;
+        let large = yields_for(&db, 10_000)?;
+        assert!(small > 10, "{}", small);
+        assert!(
+            large > 8 * small && large < 12 * small,
+            "{} {}",
+            small,
+            large
+        );
+        // the handler is removed with the rows
+        assert!(db.db.borrow().progress_handler.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_step_budget_cancel() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        let cancel = Arc::new(AtomicBool::new(false));
+        let flag = cancel.clone();
+        let yields = Arc::new(AtomicUsize::new(0));
+        let counter = yields.clone();
+        let budget = StepBudget {
+            steps_per_yield: 100,
+            yield_fn: move || {
+                counter.fetch_add(1, Ordering::Relaxed);
+                if flag.load(Ordering::Relaxed) {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            },
+        };
+        let mut stmt = db.prepare(
+            "WITH RECURSIVE c(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM c) SELECT i FROM c",
+        )?;
+        let mut rows = stmt.query_with_budget([], budget)?;
+        let mut seen = 0;
+        let err = loop {
+            match rows.next() {
+                Ok(Some(_)) => seen += 1,
+                Ok(None) => panic!("endless query ended"),
+                Err(err) => break err,
+            }
+            if seen == 1000 {
+                cancel.store(true, Ordering::Relaxed);
+            }
+        };
+        assert_eq!(err, Error::Cancelled);
+        // a few more rows may come before the yield function runs again
+        assert!((1000..1100).contains(&seen), "{}", seen);
+        drop(rows);
+        let before = yields.load(Ordering::Relaxed);
+        db.execute_batch("CREATE TABLE foo(x); INSERT INTO foo VALUES (1);")?;
+        assert_eq!(yields.load(Ordering::Relaxed), before);
+
+        // only one progress handler at a time
+        db.progress_handler(1000, Some(|| false));
+        let budget = StepBudget {
+            steps_per_yield: 100,
+            yield_fn: || ControlFlow::Continue(()),
+        };
+        let err = stmt.query_with_budget([], budget).err().unwrap();
+        assert_eq!(err.sqlite_error_code(), Some(crate::ErrorCode::ApiMisuse));
+        Ok(())
+    }
+
     #[test]
     fn test_authorizer() -> Result<()> {
         use super::{AuthAction, AuthContext, Authorization};
@@ -802,4 +1675,229 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_authorizer_column() -> Result<()> {
+        use super::{AuthAction, AuthContext, Authorization};
+        use crate::ErrorCode;
+
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo (public TEXT, private TEXT)")?;
+        db.execute("INSERT INTO foo VALUES ('pub txt', 'priv txt')", [])?;
+        let read_private = |db: &Connection| {
+            db.query_row("SELECT private FROM foo", [], |row| {
+                row.get::<_, Option<String>>(0)
+            })
+        };
+
+        let private = |authorization: Authorization| {
+            move |ctx: AuthContext<'_>| match ctx.action {
+                AuthAction::Read {
+                    column_name: "private",
+                    ..
+                } => authorization,
+                _ => Authorization::Allow,
+            }
+        };
+        db.authorizer(Some(private(Authorization::Ignore)));
+        assert_eq!(read_private(&db)?, None);
+
+        db.authorizer(Some(private(Authorization::Deny)));
+        let err = read_private(&db).unwrap_err();
+        assert_eq!(
+            err.sqlite_error_code(),
+            Some(ErrorCode::AuthorizationForStatementDenied)
+        );
+        let public: String = db.query_row("SELECT public FROM foo", [], |row| row.get(0))?;
+        assert_eq!(public, "pub txt");
+
+        db.authorizer(None::<fn(AuthContext<'_>) -> Authorization>);
+        assert_eq!(read_private(&db)?.as_deref(), Some("priv txt"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_forbid_raw_transaction_sql() -> Result<()> {
+        use super::{AuthAction, AuthContext, Authorization};
+        use crate::ErrorCode;
+
+        let mut db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(x)")?;
+        db.forbid_raw_transaction_sql(true);
+
+        let verb = |err: Error| match err {
+            Error::RawTransactionSql { verb, .. } => verb,
+            err => panic!("unexpected error {}", err),
+        };
+        assert_eq!(verb(db.execute_batch("BEGIN").unwrap_err()), "BEGIN");
+        assert_eq!(
+            verb(db.execute_batch("SAVEPOINT sp").unwrap_err()),
+            "SAVEPOINT"
+        );
+        assert!(db.is_autocommit());
+
+        {
+            let mut tx = db.transaction()?;
+            tx.execute("INSERT INTO foo VALUES (1)", [])?;
+            assert_eq!(verb(tx.execute_batch("COMMIT").unwrap_err()), "COMMIT");
+            assert_eq!(verb(tx.execute_batch("ROLLBACK").unwrap_err()), "ROLLBACK");
+            {
+                let mut sp = tx.savepoint()?;
+                sp.execute("INSERT INTO foo VALUES (2)", [])?;
+                let err = sp.execute_batch("RELEASE _rusqlite_sp_1").unwrap_err();
+                assert_eq!(verb(err), "RELEASE");
+                sp.rollback()?;
+                sp.commit()?;
+            }
+            tx.commit()?;
+        }
+        let count: i64 = db.one_column("SELECT count(*) FROM foo")?;
+        assert_eq!(count, 1);
+        assert_eq!(verb(db.execute_batch("BEGIN").unwrap_err()), "BEGIN");
+
+        // alongside a user authorizer
+        db.authorizer(Some(|ctx: AuthContext<'_>| match ctx.action {
+            AuthAction::Delete { .. } => Authorization::Deny,
+            _ => Authorization::Allow,
+        }));
+        assert_eq!(verb(db.execute_batch("BEGIN").unwrap_err()), "BEGIN");
+        let err = db.execute("DELETE FROM foo", []).unwrap_err();
+        assert_eq!(
+            err.sqlite_error_code(),
+            Some(ErrorCode::AuthorizationForStatementDenied)
+        );
+        db.execute_many("INSERT INTO foo VALUES (?1)", [[2], [3]])?;
+
+        db.forbid_raw_transaction_sql(false);
+        db.execute_batch("BEGIN; INSERT INTO foo VALUES (4); COMMIT;")?;
+        assert!(db.execute("DELETE FROM foo", []).is_err());
+        db.authorizer(None::<fn(AuthContext<'_>) -> Authorization>);
+        db.execute("DELETE FROM foo", [])?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_vacuum_cancellable() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(x); INSERT INTO foo VALUES (1);")?;
+        db.progress_handler(500, Some(|| false));
+
+        let token = CancellationToken::new();
+        db.vacuum_cancellable(&token)?;
+        {
+            let inner = db.db.borrow();
+            assert!(inner.progress_handler.is_some());
+            assert_eq!(inner.progress_handler_ops, 500);
+        }
+
+        token.cancel();
+        assert_eq!(db.vacuum_cancellable(&token).unwrap_err(), Error::Cancelled);
+        Ok(())
+    }
+
+    #[test]
+    fn test_vacuum_into_cancelled() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE foo(x BLOB);
+             WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 20000)
+             INSERT INTO foo SELECT randomblob(1000) FROM n;",
+        )?;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("copy.db3");
+
+        // cancel once SQLite has started writing the copy
+        let token = CancellationToken::new();
+        let watcher = {
+            let (token, path) = (token.clone(), path.clone());
+            std::thread::spawn(move || {
+                while !path.exists() {
+                    std::thread::yield_now();
+                }
+                token.cancel();
+            })
+        };
+        let err = db.vacuum_into_cancellable(&path, &token).unwrap_err();
+        watcher.join().unwrap();
+        assert_eq!(err, Error::Cancelled);
+        assert!(!path.exists());
+
+        token.reset();
+        db.vacuum_into_cancellable(&path, &token)?;
+        let copy = Connection::open(&path)?;
+        let rows: i64 = copy.one_column("SELECT count(*) FROM foo")?;
+        assert_eq!(rows, 20000);
+        Ok(())
+    }
+
+    // Never finishes: counts the rows of an endless recursive CTE.
+    const ENDLESS: &str =
+        "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c) SELECT count(*) FROM c";
+
+    #[test]
+    fn test_execute_with_deadline() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(n)")?;
+        let timeout = Duration::from_millis(100);
+        let start = Instant::now();
+        let err = db
+            .execute_with_deadline(&format!("INSERT INTO foo {ENDLESS}"), [], timeout)
+            .unwrap_err();
+        let elapsed = start.elapsed();
+        assert_eq!(err, Error::QueryTimeout(timeout));
+        assert!(
+            elapsed >= timeout && elapsed < timeout + Duration::from_secs(2),
+            "{:?}",
+            elapsed
+        );
+
+        // the connection is still usable
+        assert!(db.db.borrow().progress_handler.is_none());
+        let n = db.execute_with_deadline("INSERT INTO foo VALUES (1)", [], timeout)?;
+        assert_eq!(n, 1);
+        let count: i64 = db.one_column("SELECT count(*) FROM foo")?;
+        assert_eq!(count, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_with_deadline() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        let calls = Arc::new(AtomicUsize::new(0));
+        let handler_calls = calls.clone();
+        db.progress_handler(
+            10,
+            Some(move || {
+                handler_calls.fetch_add(1, Ordering::Relaxed);
+                false
+            }),
+        );
+
+        let timeout = Duration::from_millis(100);
+        let start = Instant::now();
+        {
+            let mut stmt = db.prepare(ENDLESS)?;
+            let mut rows = stmt.query_with_deadline([], timeout)?;
+            // nested: a statement run while the rows are open
+            db.execute_with_deadline("CREATE TABLE foo(x)", [], Duration::from_secs(10))?;
+            let err = rows.next().unwrap_err();
+            assert_eq!(err, Error::QueryTimeout(timeout));
+        }
+        assert!(start.elapsed() < timeout + Duration::from_secs(2));
+        // the previous handler was not called meanwhile, and is restored
+        assert_eq!(calls.load(Ordering::Relaxed), 0);
+        assert_eq!(db.db.borrow().progress_handler_ops, 10);
+        let count: i64 = db.one_column(
+            "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c WHERE x < 1000) \
+             SELECT count(*) FROM c",
+        )?;
+        assert_eq!(count, 1000);
+        assert!(calls.load(Ordering::Relaxed) > 0);
+
+        // a deadline which is not reached
+        let mut stmt = db.prepare("SELECT 1")?;
+        let mut rows = stmt.query_with_deadline([], Duration::from_secs(10))?;
+        assert_eq!(rows.next()?.unwrap().get::<_, i64>(0)?, 1);
+        Ok(())
+    }
 }
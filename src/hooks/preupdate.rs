@@ -0,0 +1,422 @@
+use std::marker::PhantomData;
+use std::os::raw::{c_char, c_int, c_void};
+use std::panic::catch_unwind;
+use std::ptr;
+
+use super::{expect_utf8, free_boxed_hook};
+use crate::ffi;
+use crate::panic_policy::stash_panic;
+use crate::types::ValueRef;
+use crate::{Connection, Error, InnerConnection, Result};
+
+/// The change about to be made to a row, as passed to the callback of
+/// [`Connection::preupdate_hook`].
+///
+/// Old values can only be read for a delete or an update, and new values for
+/// an insert or an update.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PreUpdateCase<'a> {
+    /// A row is about to be inserted.
+    Insert(PreUpdateInsert<'a>),
+    /// A row is about to be deleted.
+    Delete(PreUpdateDelete<'a>),
+    /// A row is about to be updated.
+    Update(PreUpdateUpdate<'a>),
+}
+
+impl PreUpdateCase<'_> {
+    /// Returns the number of columns of the row.
+    #[inline]
+    #[must_use]
+    pub fn column_count(&self) -> usize {
+        self.values().column_count()
+    }
+
+    /// Returns 0 for a change made directly by a statement, 1 for a change
+    /// made by a trigger fired by such a statement, and so on.
+    #[inline]
+    #[must_use]
+    pub fn depth(&self) -> i32 {
+        self.values().depth()
+    }
+
+    fn values(&self) -> &PreUpdateValues<'_> {
+        match self {
+            PreUpdateCase::Insert(insert) => &insert.0,
+            PreUpdateCase::Delete(delete) => &delete.0,
+            PreUpdateCase::Update(update) => &update.values,
+        }
+    }
+}
+
+// Reads the values of the row being changed, which is only possible while the
+// hook runs.
+#[derive(Debug)]
+struct PreUpdateValues<'a> {
+    db: *mut ffi::sqlite3,
+    marker: PhantomData<&'a ()>,
+}
+
+impl PreUpdateValues<'_> {
+    fn column_count(&self) -> usize {
+        unsafe { ffi::sqlite3_preupdate_count(self.db) as usize }
+    }
+
+    fn depth(&self) -> i32 {
+        unsafe { ffi::sqlite3_preupdate_depth(self.db) }
+    }
+
+    fn value(
+        &self,
+        i: usize,
+        read: unsafe extern "C" fn(*mut ffi::sqlite3, c_int, *mut *mut ffi::sqlite3_value) -> c_int,
+    ) -> Result<ValueRef<'_>> {
+        if i >= self.column_count() {
+            return Err(Error::InvalidColumnIndex(i));
+        }
+        let mut value = ptr::null_mut();
+        let rc = unsafe { read(self.db, i as c_int, &mut value) };
+        if rc != ffi::SQLITE_OK {
+            return Err(unsafe { crate::error::error_from_handle(self.db, rc) });
+        }
+        Ok(unsafe { crate::types::value_ref(value) })
+    }
+
+    fn old_value(&self, i: usize) -> Result<ValueRef<'_>> {
+        self.value(i, ffi::sqlite3_preupdate_old)
+    }
+
+    fn new_value(&self, i: usize) -> Result<ValueRef<'_>> {
+        self.value(i, ffi::sqlite3_preupdate_new)
+    }
+}
+
+/// A row about to be inserted.
+#[derive(Debug)]
+pub struct PreUpdateInsert<'a>(PreUpdateValues<'a>, i64);
+
+impl PreUpdateInsert<'_> {
+    /// Returns the number of columns of the row.
+    #[inline]
+    #[must_use]
+    pub fn column_count(&self) -> usize {
+        self.0.column_count()
+    }
+
+    /// See [`PreUpdateCase::depth`].
+    #[inline]
+    #[must_use]
+    pub fn depth(&self) -> i32 {
+        self.0.depth()
+    }
+
+    /// Returns the rowid of the new row, which is unspecified for a
+    /// `WITHOUT ROWID` table.
+    #[inline]
+    #[must_use]
+    pub fn new_rowid(&self) -> i64 {
+        self.1
+    }
+
+    /// Returns the value of column `i` of the new row.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `i` is out of range.
+    #[inline]
+    pub fn new_value(&self, i: usize) -> Result<ValueRef<'_>> {
+        self.0.new_value(i)
+    }
+}
+
+/// A row about to be deleted.
+#[derive(Debug)]
+pub struct PreUpdateDelete<'a>(PreUpdateValues<'a>, i64);
+
+impl PreUpdateDelete<'_> {
+    /// Returns the number of columns of the row.
+    #[inline]
+    #[must_use]
+    pub fn column_count(&self) -> usize {
+        self.0.column_count()
+    }
+
+    /// See [`PreUpdateCase::depth`].
+    #[inline]
+    #[must_use]
+    pub fn depth(&self) -> i32 {
+        self.0.depth()
+    }
+
+    /// Returns the rowid of the row, which is unspecified for a `WITHOUT
+    /// ROWID` table.
+    #[inline]
+    #[must_use]
+    pub fn old_rowid(&self) -> i64 {
+        self.1
+    }
+
+    /// Returns the value of column `i` of the row.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `i` is out of range.
+    #[inline]
+    pub fn old_value(&self, i: usize) -> Result<ValueRef<'_>> {
+        self.0.old_value(i)
+    }
+}
+
+/// A row about to be updated.
+#[derive(Debug)]
+pub struct PreUpdateUpdate<'a> {
+    values: PreUpdateValues<'a>,
+    old_rowid: i64,
+    new_rowid: i64,
+}
+
+impl PreUpdateUpdate<'_> {
+    /// Returns the number of columns of the row.
+    #[inline]
+    #[must_use]
+    pub fn column_count(&self) -> usize {
+        self.values.column_count()
+    }
+
+    /// See [`PreUpdateCase::depth`].
+    #[inline]
+    #[must_use]
+    pub fn depth(&self) -> i32 {
+        self.values.depth()
+    }
+
+    /// Returns the rowid of the row before the update, which is unspecified
+    /// for a `WITHOUT ROWID` table.
+    #[inline]
+    #[must_use]
+    pub fn old_rowid(&self) -> i64 {
+        self.old_rowid
+    }
+
+    /// Returns the rowid of the row after the update, which is unspecified
+    /// for a `WITHOUT ROWID` table.
+    #[inline]
+    #[must_use]
+    pub fn new_rowid(&self) -> i64 {
+        self.new_rowid
+    }
+
+    /// Returns the value of column `i` before the update.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `i` is out of range.
+    #[inline]
+    pub fn old_value(&self, i: usize) -> Result<ValueRef<'_>> {
+        self.values.old_value(i)
+    }
+
+    /// Returns the value of column `i` after the update.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `i` is out of range.
+    #[inline]
+    pub fn new_value(&self, i: usize) -> Result<ValueRef<'_>> {
+        self.values.new_value(i)
+    }
+
+    /// Returns the indexes of the columns whose value changes, in order.
+    pub fn changed_columns(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.column_count()).filter(move |&i| match (self.old_value(i), self.new_value(i)) {
+            (Ok(old), Ok(new)) => old != new,
+            _ => true,
+        })
+    }
+}
+
+impl Connection {
+    /// Register a callback function to be invoked before each row is
+    /// inserted, deleted or updated.
+    ///
+    /// The callback parameters are:
+    ///
+    /// - the name of the database ("main", "temp", ...),
+    /// - the name of the table that is changed,
+    /// - the change, from which the old and new values of the row can be
+    ///   read.
+    ///
+    /// There can only be one preupdate hook per connection, which the session
+    /// extension also uses.
+    #[inline]
+    pub fn preupdate_hook<F>(&self, hook: Option<F>)
+    where
+        F: FnMut(&str, &str, &PreUpdateCase<'_>) + Send + 'static,
+    {
+        self.db.borrow_mut().preupdate_hook(hook);
+    }
+}
+
+impl InnerConnection {
+    pub(crate) fn preupdate_hook<F>(&mut self, hook: Option<F>)
+    where
+        F: FnMut(&str, &str, &PreUpdateCase<'_>) + Send + 'static,
+    {
+        unsafe extern "C" fn call_boxed_closure<F>(
+            p_arg: *mut c_void,
+            db: *mut ffi::sqlite3,
+            action_code: c_int,
+            p_db_name: *const c_char,
+            p_table_name: *const c_char,
+            old_rowid: i64,
+            new_rowid: i64,
+        ) where
+            F: FnMut(&str, &str, &PreUpdateCase<'_>),
+        {
+            let values = PreUpdateValues {
+                db,
+                marker: PhantomData,
+            };
+            let case = match action_code {
+                ffi::SQLITE_INSERT => PreUpdateCase::Insert(PreUpdateInsert(values, new_rowid)),
+                ffi::SQLITE_DELETE => PreUpdateCase::Delete(PreUpdateDelete(values, old_rowid)),
+                ffi::SQLITE_UPDATE => PreUpdateCase::Update(PreUpdateUpdate {
+                    values,
+                    old_rowid,
+                    new_rowid,
+                }),
+                _ => return,
+            };
+            if let Err(payload) = catch_unwind(|| {
+                let boxed_hook: *mut F = p_arg.cast::<F>();
+                (*boxed_hook)(
+                    expect_utf8(p_db_name, "database name"),
+                    expect_utf8(p_table_name, "table name"),
+                    &case,
+                );
+            }) {
+                stash_panic(payload);
+            }
+        }
+
+        let free_preupdate_hook = if hook.is_some() {
+            Some(free_boxed_hook::<F> as unsafe fn(*mut c_void))
+        } else {
+            None
+        };
+
+        let previous_hook = match hook {
+            Some(hook) => {
+                let boxed_hook: *mut F = Box::into_raw(Box::new(hook));
+                unsafe {
+                    ffi::sqlite3_preupdate_hook(
+                        self.db(),
+                        Some(call_boxed_closure::<F>),
+                        boxed_hook.cast(),
+                    )
+                }
+            }
+            _ => unsafe { ffi::sqlite3_preupdate_hook(self.db(), None, ptr::null_mut()) },
+        };
+        if !previous_hook.is_null() {
+            if let Some(free_boxed_hook) = self.free_preupdate_hook {
+                unsafe { free_boxed_hook(previous_hook) };
+            }
+        }
+        self.free_preupdate_hook = free_preupdate_hook;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::PreUpdateCase;
+    use crate::types::Value;
+    use crate::{Connection, Result};
+
+    #[test]
+    fn test_preupdate_hook_update() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE foo (a INTEGER, b TEXT, c REAL);
+             INSERT INTO foo VALUES (1, 'x', 1.5);",
+        )?;
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let captured = seen.clone();
+        db.preupdate_hook(Some(
+            move |db: &str, table: &str, case: &PreUpdateCase<'_>| {
+                assert_eq!((db, table), ("main", "foo"));
+                if let PreUpdateCase::Update(update) = case {
+                    assert_eq!(update.column_count(), 3);
+                    assert_eq!(update.depth(), 0);
+                    assert_eq!((update.old_rowid(), update.new_rowid()), (1, 1));
+                    let old: Vec<Value> = (0..3)
+                        .map(|i| update.old_value(i).unwrap().into())
+                        .collect();
+                    let new: Vec<Value> = (0..3)
+                        .map(|i| update.new_value(i).unwrap().into())
+                        .collect();
+                    let changed: Vec<usize> = update.changed_columns().collect();
+                    assert!(update.old_value(3).is_err());
+                    captured.lock().unwrap().push((old, new, changed));
+                }
+            },
+        ));
+        db.execute("UPDATE foo SET b = 'y', c = NULL", [])?;
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(
+            *seen,
+            [(
+                vec![
+                    Value::Integer(1),
+                    Value::Text("x".to_owned()),
+                    Value::Real(1.5)
+                ],
+                vec![Value::Integer(1), Value::Text("y".to_owned()), Value::Null],
+                vec![1, 2]
+            )]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_preupdate_hook_depth() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE foo (a INTEGER);
+             CREATE TABLE audit (a INTEGER);
+             CREATE TRIGGER foo_delete AFTER DELETE ON foo BEGIN
+                 INSERT INTO audit VALUES (old.a);
+             END;
+             INSERT INTO foo VALUES (7);",
+        )?;
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let captured = seen.clone();
+        db.preupdate_hook(Some(
+            move |_: &str, table: &str, case: &PreUpdateCase<'_>| {
+                let value = match case {
+                    PreUpdateCase::Insert(insert) => insert.new_value(0).unwrap().as_i64().unwrap(),
+                    PreUpdateCase::Delete(delete) => delete.old_value(0).unwrap().as_i64().unwrap(),
+                    PreUpdateCase::Update(_) => unreachable!(),
+                };
+                captured
+                    .lock()
+                    .unwrap()
+                    .push((table.to_owned(), case.depth(), value));
+            },
+        ));
+        db.execute("DELETE FROM foo", [])?;
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(
+            *seen,
+            [("foo".to_owned(), 0, 7), ("audit".to_owned(), 1, 7)]
+        );
+        Ok(())
+    }
+}
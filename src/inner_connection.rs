@@ -1,10 +1,9 @@
 use std::ffi::CStr;
-use std::os::raw::{c_char, c_int};
-#[cfg(feature = "load_extension")]
-use std::path::Path;
+use std::os::raw::{c_char, c_int, c_uint};
+use std::path::{Path, PathBuf};
 use std::ptr;
 use std::str;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 
 use super::ffi;
@@ -31,16 +30,55 @@ pub struct InnerConnection {
     #[cfg(feature = "hooks")]
     pub free_update_hook: Option<unsafe fn(*mut std::os::raw::c_void)>,
     #[cfg(feature = "hooks")]
+    pub free_wal_hook: Option<unsafe fn(*mut std::os::raw::c_void)>,
+    #[cfg(feature = "preupdate_hook")]
+    pub free_preupdate_hook: Option<unsafe fn(*mut std::os::raw::c_void)>,
+    #[cfg(feature = "hooks")]
     pub progress_handler: Option<Box<dyn FnMut() -> bool + Send>>,
     #[cfg(feature = "hooks")]
-    pub authorizer: Option<crate::hooks::BoxedAuthorizer>,
+    pub progress_handler_ops: std::os::raw::c_int,
+    #[cfg(feature = "hooks")]
+    pub authorizer: Option<Box<crate::hooks::AuthorizerState>>,
+    // Set once the receiver returned by `commit_events` is dropped, so that
+    // its hooks can be unregistered.
+    #[cfg(feature = "hooks")]
+    pub events_closed: Option<Arc<AtomicBool>>,
+    // Canonical path of the main database while it is counted in the
+    // registry of open paths (see `busy.rs`).
+    registered_path: Option<PathBuf>,
+    // Whether `register_path` was called, even if there was no path.
+    path_registered: bool,
+    // Latest commit to `registered_path` this connection is known to see.
+    pub observed_commit: u64,
+    pub panic_policy: crate::PanicPolicy,
+    // Panic caught in a callback, waiting for the next call to handle it
+    // (see `panic_policy.rs`).
+    pub pending_panic: crate::panic_policy::PanicSlot,
+    #[cfg(feature = "busy-diagnostics")]
+    pub busy_diagnostics: bool,
+    // Name given to the main database with `Connection::set_main_db_name`,
+    // which SQLite refers to.
+    pub main_db_name: Option<std::ffi::CString>,
+    // Closure registered with `Connection::busy_handler`.
+    pub busy_handler: Option<Box<dyn FnMut(i32) -> bool + Send>>,
+    // Closure registered with `Connection::collation_needed`.
+    #[cfg(feature = "collation")]
+    pub collation_needed: Option<crate::collation::BoxedCollationNeeded>,
+    #[cfg(feature = "page-io")]
+    pub raw_page_writes: bool,
+    // Bound set with `Connection::set_unlock_notify_timeout`.
+    #[cfg(feature = "unlock_notify")]
+    pub unlock_notify_timeout: Option<std::time::Duration>,
+    // Cache statistics last added to the process-wide totals (see
+    // `stats`).
+    pub reported_cache_stats: crate::config::CacheStats,
     owned: bool,
 }
 
 unsafe impl Send for InnerConnection {}
 
 impl InnerConnection {
-    #[allow(clippy::mutex_atomic)]
+    #[allow(clippy::mutex_atomic, clippy::arc_with_non_send_sync)]
     #[inline]
     pub unsafe fn new(db: *mut ffi::sqlite3, owned: bool) -> InnerConnection {
         InnerConnection {
@@ -53,9 +91,33 @@ impl InnerConnection {
             #[cfg(feature = "hooks")]
             free_update_hook: None,
             #[cfg(feature = "hooks")]
+            free_wal_hook: None,
+            #[cfg(feature = "preupdate_hook")]
+            free_preupdate_hook: None,
+            #[cfg(feature = "hooks")]
             progress_handler: None,
             #[cfg(feature = "hooks")]
+            progress_handler_ops: 0,
+            #[cfg(feature = "hooks")]
             authorizer: None,
+            #[cfg(feature = "hooks")]
+            events_closed: None,
+            registered_path: None,
+            path_registered: false,
+            observed_commit: 0,
+            panic_policy: crate::PanicPolicy::ConvertToError,
+            pending_panic: crate::panic_policy::PanicSlot::default(),
+            #[cfg(feature = "busy-diagnostics")]
+            busy_diagnostics: false,
+            busy_handler: None,
+            #[cfg(feature = "collation")]
+            collation_needed: None,
+            main_db_name: None,
+            #[cfg(feature = "page-io")]
+            raw_page_writes: false,
+            #[cfg(feature = "unlock_notify")]
+            unlock_notify_timeout: None,
+            reported_cache_stats: crate::config::CacheStats::default(),
             owned,
         }
     }
@@ -125,7 +187,12 @@ impl InnerConnection {
                 return Err(e);
             }
 
-            Ok(InnerConnection::new(db, true))
+            #[allow(unused_mut)]
+            let mut conn = InnerConnection::new(db, true);
+            #[cfg(feature = "busy-diagnostics")]
+            conn.register_path();
+            OPEN_CONNECTIONS.fetch_add(1, Ordering::SeqCst);
+            Ok(conn)
         }
     }
 
@@ -136,16 +203,33 @@ impl InnerConnection {
 
     #[inline]
     pub fn decode_result(&self, code: c_int) -> Result<()> {
+        self.resume_panic();
         unsafe { InnerConnection::decode_result_raw(self.db(), code) }
+            .map_err(|e| self.with_busy_diagnostics(e))
+    }
+
+    #[cfg(feature = "busy-diagnostics")]
+    #[inline]
+    pub fn registered_path(&self) -> Option<&Path> {
+        self.registered_path.as_deref()
+    }
+
+    // Counts the connection in the registry of open paths, unless it already
+    // is, and returns the path of its main database.
+    pub fn register_path(&mut self) -> Option<&Path> {
+        if !self.path_registered && !self.db.is_null() {
+            self.path_registered = true;
+            self.registered_path = unsafe { crate::busy::register_path(self.db) };
+        }
+        self.registered_path.as_deref()
     }
 
     #[inline]
     unsafe fn decode_result_raw(db: *mut ffi::sqlite3, code: c_int) -> Result<()> {
         if code == ffi::SQLITE_OK {
-            Ok(())
-        } else {
-            Err(error_from_handle(db, code))
+            return Ok(());
         }
+        Err(error_from_handle(db, code))
     }
 
     #[allow(clippy::mutex_atomic)]
@@ -153,7 +237,14 @@ impl InnerConnection {
         if self.db.is_null() {
             return Ok(());
         }
+        // not to be resumed by the calls below
+        self.forget_panic();
         self.remove_hooks();
+        let _ = self.busy_handler(None::<fn(i32) -> bool>);
+        #[cfg(feature = "collation")]
+        if self.collation_needed.is_some() {
+            let _ = self.collation_needed(None::<fn(&crate::Connection, &str) -> Result<()>>);
+        }
         let mut shared_handle = self.interrupt_lock.lock().unwrap();
         assert!(
             !shared_handle.is_null(),
@@ -161,6 +252,11 @@ impl InnerConnection {
         );
         if !self.owned {
             self.db = ptr::null_mut();
+            if let Some(path) = self.registered_path.take() {
+                crate::busy::unregister_path(&path);
+            }
+            crate::stats::forget_cache_stats(&self.reported_cache_stats);
+            self.forget_panic();
             return Ok(());
         }
         unsafe {
@@ -171,6 +267,14 @@ impl InnerConnection {
             if r.is_ok() {
                 *shared_handle = ptr::null_mut();
                 self.db = ptr::null_mut();
+                if let Some(path) = self.registered_path.take() {
+                    crate::busy::unregister_path(&path);
+                }
+                OPEN_CONNECTIONS.fetch_sub(1, Ordering::SeqCst);
+                crate::stats::forget_cache_stats(&self.reported_cache_stats);
+                self.forget_panic();
+            } else {
+                crate::panic_policy::keep_caught_panic(&self.pending_panic);
             }
             r
         }
@@ -218,38 +322,29 @@ impl InnerConnection {
         unsafe { ffi::sqlite3_last_insert_rowid(self.db()) }
     }
 
-    pub fn prepare<'a>(&mut self, conn: &'a Connection, sql: &str) -> Result<Statement<'a>> {
+    // `flags` are `PrepFlags` bits, and must be 0 unless `modern_sqlite` is
+    // enabled.
+    pub fn prepare<'a>(
+        &mut self,
+        conn: &'a Connection,
+        sql: &str,
+        flags: c_uint,
+    ) -> Result<Statement<'a>> {
         let mut c_stmt = ptr::null_mut();
         let (c_sql, len, _) = str_for_sqlite(sql.as_bytes())?;
         let mut c_tail = ptr::null();
-        // TODO sqlite3_prepare_v3 (https://sqlite.org/c3ref/c_prepare_normalize.html) // 3.20.0, #728
         #[cfg(not(feature = "unlock_notify"))]
-        let r = unsafe {
-            ffi::sqlite3_prepare_v2(
-                self.db(),
-                c_sql,
-                len,
-                &mut c_stmt as *mut *mut ffi::sqlite3_stmt,
-                &mut c_tail as *mut *const c_char,
-            )
-        };
+        let r = unsafe { self.prepare_(c_sql, len, flags, &mut c_stmt, &mut c_tail) };
         #[cfg(feature = "unlock_notify")]
         let r = unsafe {
             use crate::unlock_notify;
             let mut rc;
             loop {
-                rc = ffi::sqlite3_prepare_v2(
-                    self.db(),
-                    c_sql,
-                    len,
-                    &mut c_stmt as *mut *mut ffi::sqlite3_stmt,
-                    &mut c_tail as *mut *const c_char,
-                );
+                rc = self.prepare_(c_sql, len, flags, &mut c_stmt, &mut c_tail);
                 if !unlock_notify::is_locked(self.db, rc) {
                     break;
                 }
-                rc = unlock_notify::wait_for_unlock_notify(self.db);
-                if rc != ffi::SQLITE_OK {
+                if !unlock_notify::wait_for_unlock_notify(self.db, self.unlock_notify_timeout)? {
                     break;
                 }
             }
@@ -257,7 +352,11 @@ impl InnerConnection {
         };
         // If there is an error, *ppStmt is set to NULL.
         if r != ffi::SQLITE_OK {
-            return Err(unsafe { error_with_offset(self.db, r, sql) });
+            #[cfg(feature = "hooks")]
+            if let Some(err) = self.take_raw_transaction_sql_error() {
+                return Err(err);
+            }
+            return Err(self.with_busy_diagnostics(unsafe { error_with_offset(self.db, r, sql) }));
         }
         // If the input text contains no SQL (if the input is an empty string or a
         // comment) then *ppStmt is set to NULL.
@@ -273,9 +372,30 @@ impl InnerConnection {
                 n as usize
             }
         };
-        Ok(Statement::new(conn, unsafe {
-            RawStatement::new(c_stmt, tail)
-        }))
+        let mut stmt = unsafe { RawStatement::new(c_stmt, tail) };
+        stmt.set_prep_flags(flags);
+        stmt.set_panic_slot(self.pending_panic.clone());
+        Ok(Statement::new(conn, stmt))
+    }
+
+    #[inline]
+    unsafe fn prepare_(
+        &self,
+        c_sql: *const c_char,
+        len: c_int,
+        flags: c_uint,
+        c_stmt: &mut *mut ffi::sqlite3_stmt,
+        c_tail: &mut *const c_char,
+    ) -> c_int {
+        #[cfg(feature = "modern_sqlite")] // 3.20.0
+        {
+            ffi::sqlite3_prepare_v3(self.db(), c_sql, len, flags, c_stmt, c_tail)
+        }
+        #[cfg(not(feature = "modern_sqlite"))]
+        {
+            debug_assert_eq!(flags, 0);
+            ffi::sqlite3_prepare_v2(self.db(), c_sql, len, c_stmt, c_tail)
+        }
     }
 
     #[inline]
@@ -386,11 +506,14 @@ impl Drop for InnerConnection {
 #[cfg(not(any(target_arch = "wasm32")))]
 static SQLITE_INIT: std::sync::Once = std::sync::Once::new();
 
+/// The number of connections opened by rusqlite and not yet closed.
+pub(crate) static OPEN_CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+
 pub static BYPASS_SQLITE_INIT: AtomicBool = AtomicBool::new(false);
 
 // threading mode checks are not necessary (and do not work) on target
 // platforms that do not have threading (such as webassembly)
-#[cfg(any(target_arch = "wasm32"))]
+#[cfg(target_arch = "wasm32")]
 fn ensure_safe_sqlite_threading_mode() -> Result<()> {
     Ok(())
 }
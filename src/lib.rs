@@ -65,7 +65,7 @@ use std::os::raw::{c_char, c_int};
 use std::path::Path;
 use std::result;
 use std::str;
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 
 use crate::cache::StatementCache;
@@ -73,39 +73,81 @@ use crate::inner_connection::{InnerConnection, BYPASS_SQLITE_INIT};
 use crate::raw_statement::RawStatement;
 use crate::types::ValueRef;
 
-pub use crate::cache::CachedStatement;
+pub use crate::arena::{ArenaRow, RowArena};
+#[cfg(feature = "busy-diagnostics")]
+#[cfg_attr(docsrs, doc(cfg(feature = "busy-diagnostics")))]
+pub use crate::busy::BusyInfo;
+pub use crate::cache::{CachedStatement, StatementCacheStats, WarmReport, WarmedStatement};
 pub use crate::column::Column;
-pub use crate::error::Error;
+#[cfg(feature = "column_metadata")]
+pub use crate::column::ColumnOrigin;
+pub use crate::connection_string::ConnectionString;
+pub use crate::encoding::TextEncoding;
+pub use crate::error::{Error, QueryError};
 pub use crate::ffi::ErrorCode;
 #[cfg(feature = "load_extension")]
 pub use crate::load_extension_guard::LoadExtensionGuard;
-pub use crate::params::{params_from_iter, Params, ParamsFromIter};
-pub use crate::row::{AndThenRows, Map, MappedRows, Row, RowIndex, Rows};
+pub use crate::panic_policy::PanicPolicy;
+pub use crate::params::{params_from_iter, params_from_map, Params, ParamsFromIter, ParamsFromMap};
+#[cfg(feature = "modern_sqlite")]
+#[cfg_attr(docsrs, doc(cfg(feature = "modern_sqlite")))]
+pub use crate::query_plan::{QueryPlan, QueryPlanNode};
+pub use crate::readonly::{OpenOutcome, ReadOnlyPolicy};
+pub use crate::row::{
+    AndThenRows, FromRow, GroupRows, Map, MappedRows, PeekableRows, Row, RowIndex, Rows,
+};
+pub use crate::rowid::RowId;
+#[cfg(feature = "serde_params")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde_params")))]
+pub use crate::serde_params::named_params_from;
+#[cfg(feature = "scanstatus")]
+#[cfg_attr(docsrs, doc(cfg(feature = "scanstatus")))]
+pub use crate::statement::ScanStatus;
 pub use crate::statement::{Statement, StatementStatus};
-pub use crate::transaction::{DropBehavior, Savepoint, Transaction, TransactionBehavior};
+#[cfg(feature = "contention-profiling")]
+#[cfg_attr(docsrs, doc(cfg(feature = "contention-profiling")))]
+pub use crate::sync_connection::SyncConnection;
+#[cfg(feature = "modern_sqlite")]
+#[cfg_attr(docsrs, doc(cfg(feature = "modern_sqlite")))]
+pub use crate::transaction::TransactionState;
+pub use crate::transaction::{
+    DropBehavior, RetryPolicy, Savepoint, Transaction, TransactionBehavior, TransactionBuilder,
+};
 pub use crate::types::ToSql;
 pub use crate::version::*;
+pub use crate::write_token::WriteToken;
+#[cfg(feature = "derive")]
+#[cfg_attr(docsrs, doc(cfg(feature = "derive")))]
+pub use rusqlite_macros::FromRow;
 
 mod error;
 
+mod arena;
 #[cfg(feature = "backup")]
 #[cfg_attr(docsrs, doc(cfg(feature = "backup")))]
 pub mod backup;
+pub mod batch;
 #[cfg(feature = "blob")]
 #[cfg_attr(docsrs, doc(cfg(feature = "blob")))]
 pub mod blob;
 mod busy;
 mod cache;
+#[cfg(feature = "functions")]
+#[cfg_attr(docsrs, doc(cfg(feature = "functions")))]
+mod checked_math;
 #[cfg(feature = "collation")]
 #[cfg_attr(docsrs, doc(cfg(feature = "collation")))]
 mod collation;
 mod column;
 pub mod config;
+pub mod connection_string;
 #[cfg(any(feature = "functions", feature = "vtab"))]
 mod context;
+mod encoding;
 #[cfg(feature = "functions")]
 #[cfg_attr(docsrs, doc(cfg(feature = "functions")))]
 pub mod functions;
+mod header;
 #[cfg(feature = "hooks")]
 #[cfg_attr(docsrs, doc(cfg(feature = "hooks")))]
 pub mod hooks;
@@ -115,14 +157,51 @@ mod inner_connection;
 pub mod limits;
 #[cfg(feature = "load_extension")]
 mod load_extension_guard;
+#[cfg(feature = "page-io")]
+#[cfg_attr(docsrs, doc(cfg(feature = "page-io")))]
+mod page_io;
+mod panic_policy;
 mod params;
+#[cfg(feature = "modern_sqlite")] // 3.20.0
+mod pointer;
 mod pragma;
+#[cfg(feature = "modern_sqlite")] // 3.24.0
+#[cfg_attr(docsrs, doc(cfg(feature = "modern_sqlite")))]
+mod query_plan;
 mod raw_statement;
+mod readonly;
+#[cfg(feature = "regexp")]
+#[cfg_attr(docsrs, doc(cfg(feature = "regexp")))]
+mod regexp;
 mod row;
+mod rowid;
+pub mod schema;
+#[cfg(feature = "serde_params")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde_params")))]
+pub mod serde_params;
+#[cfg(feature = "serde_rows")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde_rows")))]
+pub mod serde_rows;
+#[cfg(feature = "serialize")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serialize")))]
+pub mod serialize;
 #[cfg(feature = "session")]
 #[cfg_attr(docsrs, doc(cfg(feature = "session")))]
 pub mod session;
+pub mod shard;
+#[cfg(feature = "snapshot")]
+#[cfg_attr(docsrs, doc(cfg(feature = "snapshot")))]
+pub mod snapshot;
 mod statement;
+pub mod stats;
+pub mod status;
+pub mod storage;
+#[cfg(feature = "contention-profiling")]
+mod sync_connection;
+pub mod table_io;
+#[cfg(feature = "testing")]
+#[cfg_attr(docsrs, doc(cfg(feature = "testing")))]
+pub mod testing;
 #[cfg(feature = "trace")]
 #[cfg_attr(docsrs, doc(cfg(feature = "trace")))]
 pub mod trace;
@@ -130,10 +209,20 @@ mod transaction;
 pub mod types;
 #[cfg(feature = "unlock_notify")]
 mod unlock_notify;
+#[cfg(all(feature = "uuid", feature = "functions"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "uuid", feature = "functions"))))]
+mod uuid_functions;
 mod version;
+#[cfg(feature = "virtual_pragma")]
+#[cfg_attr(docsrs, doc(cfg(feature = "virtual_pragma")))]
+mod virtual_pragma;
 #[cfg(feature = "vtab")]
 #[cfg_attr(docsrs, doc(cfg(feature = "vtab")))]
 pub mod vtab;
+#[cfg(feature = "wal-tail")]
+#[cfg_attr(docsrs, doc(cfg(feature = "wal-tail")))]
+pub mod wal_tail;
+mod write_token;
 
 pub(crate) mod util;
 pub(crate) use util::SmallCString;
@@ -293,6 +382,24 @@ fn path_to_cstring(p: &Path) -> Result<CString> {
     Ok(CString::new(s)?)
 }
 
+// Whether preparing a statement failed because the linked SQLite does not
+// understand its syntax or one of its functions, rather than because of
+// something wrong with the database or the statement's references.
+fn is_unsupported_sql(err: &Error) -> bool {
+    let (error, msg) = match *err {
+        Error::SqliteFailure(ref error, Some(ref msg)) => (error, msg),
+        #[cfg(feature = "modern_sqlite")]
+        Error::SqlInputError {
+            ref error, ref msg, ..
+        } => (error, msg),
+        _ => return false,
+    };
+    error.extended_code == ffi::SQLITE_ERROR
+        && (msg.ends_with("syntax error")
+            || msg.starts_with("unrecognized token:")
+            || msg.starts_with("no such function:"))
+}
+
 /// Name for a database within a SQLite connection.
 #[derive(Copy, Clone, Debug)]
 pub enum DatabaseName<'a> {
@@ -306,6 +413,21 @@ pub enum DatabaseName<'a> {
     Attached(&'a str),
 }
 
+/// `"main"` and `"temp"`, in any case, are the main and temporary databases;
+/// any other name is an attached database.
+impl<'a> From<&'a str> for DatabaseName<'a> {
+    #[inline]
+    fn from(name: &'a str) -> DatabaseName<'a> {
+        if name.eq_ignore_ascii_case("main") {
+            DatabaseName::Main
+        } else if name.eq_ignore_ascii_case("temp") {
+            DatabaseName::Temp
+        } else {
+            DatabaseName::Attached(name)
+        }
+    }
+}
+
 /// Shorthand for [`DatabaseName::Main`].
 pub const MAIN_DB: DatabaseName<'static> = DatabaseName::Main;
 
@@ -380,10 +502,10 @@ impl Connection {
     ///   for details).
     /// - Disables the use of a per-connection mutex.
     ///
-    ///     Rusqlite enforces thread-safety at compile time, so additional
-    ///     locking is not needed and provides no benefit. (See the
-    ///     documentation on [`OpenFlags::SQLITE_OPEN_FULL_MUTEX`] for some
-    ///     additional discussion about this).
+    ///   Rusqlite enforces thread-safety at compile time, so additional
+    ///   locking is not needed and provides no benefit. (See the
+    ///   documentation on [`OpenFlags::SQLITE_OPEN_FULL_MUTEX`] for some
+    ///   additional discussion about this).
     ///
     /// Most of these are also the default settings for the C API, although
     /// technically the default locking behavior is controlled by the flags used
@@ -423,10 +545,12 @@ impl Connection {
     #[inline]
     pub fn open_with_flags<P: AsRef<Path>>(path: P, flags: OpenFlags) -> Result<Connection> {
         let c_path = path_to_cstring(path.as_ref())?;
-        InnerConnection::open_with_flags(&c_path, flags, None).map(|db| Connection {
+        let conn = InnerConnection::open_with_flags(&c_path, flags, None).map(|db| Connection {
             db: RefCell::new(db),
             cache: StatementCache::with_capacity(STATEMENT_CACHE_DEFAULT_CAPACITY),
-        })
+        })?;
+        config::apply_defaults(&conn)?;
+        Ok(conn)
     }
 
     /// Open a new connection to a SQLite database using the specific flags and
@@ -447,10 +571,14 @@ impl Connection {
     ) -> Result<Connection> {
         let c_path = path_to_cstring(path.as_ref())?;
         let c_vfs = str_to_cstring(vfs)?;
-        InnerConnection::open_with_flags(&c_path, flags, Some(&c_vfs)).map(|db| Connection {
-            db: RefCell::new(db),
-            cache: StatementCache::with_capacity(STATEMENT_CACHE_DEFAULT_CAPACITY),
-        })
+        let conn = InnerConnection::open_with_flags(&c_path, flags, Some(&c_vfs)).map(|db| {
+            Connection {
+                db: RefCell::new(db),
+                cache: StatementCache::with_capacity(STATEMENT_CACHE_DEFAULT_CAPACITY),
+            }
+        })?;
+        config::apply_defaults(&conn)?;
+        Ok(conn)
     }
 
     /// Open a new connection to an in-memory SQLite database.
@@ -575,6 +703,51 @@ impl Connection {
             .and_then(|mut stmt| stmt.check_no_tail().and_then(|_| stmt.execute(params)))
     }
 
+    /// Convenience method to prepare and execute a single SQL statement once
+    /// for each item of `iter`, binding the item as the parameters.
+    ///
+    /// The statement is prepared once, and the items are executed inside a
+    /// savepoint: if one fails, the changes made by the previous ones are
+    /// rolled back. On success, returns the total number of rows that were
+    /// changed or inserted or deleted.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn insert_names(conn: &Connection, names: &[&str]) -> Result<usize> {
+    ///     conn.execute_many(
+    ///         "INSERT INTO people (name) VALUES (?1)",
+    ///         names.iter().map(|name| [name]),
+    ///     )
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be converted to a C-compatible string
+    /// or if the underlying SQLite call fails, and an
+    /// `Error::ExecuteManyFailed` with the zero-based index of the item that
+    /// failed if executing an item fails.
+    pub fn execute_many<P, I>(&self, sql: &str, iter: I) -> Result<usize>
+    where
+        P: Params,
+        I: IntoIterator<Item = P>,
+    {
+        let mut stmt = self.prepare(sql)?;
+        stmt.check_no_tail()?;
+        let mut sp = Savepoint::with_depth(self, 0)?;
+        match stmt.execute_many(iter) {
+            Ok(changes) => sp.commit().map(|_| changes),
+            Err(err) => {
+                // `ROLLBACK TO` leaves the savepoint open, so release it too
+                sp.rollback()?;
+                sp.commit()?;
+                Err(err)
+            }
+        }
+    }
+
     /// Returns the path to the database file, if one exists and is known.
     ///
     /// Returns `Some("")` for a temporary or in-memory database.
@@ -631,8 +804,8 @@ impl Connection {
     /// Uses [sqlite3_last_insert_rowid](https://www.sqlite.org/c3ref/last_insert_rowid.html) under
     /// the hood.
     #[inline]
-    pub fn last_insert_rowid(&self) -> i64 {
-        self.db.borrow_mut().last_insert_rowid()
+    pub fn last_insert_rowid(&self) -> RowId {
+        RowId(self.db.borrow_mut().last_insert_rowid())
     }
 
     /// Convenience method to execute a query that is expected to return a
@@ -673,12 +846,189 @@ impl Connection {
         stmt.query_row(params, f)
     }
 
+    /// Convenience method to execute a query that is expected to return a
+    /// single row, converting it into a `T` using its [`FromRow`]
+    /// implementation.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Result, Connection};
+    /// fn person(conn: &Connection, id: i64) -> Result<(String, Option<u32>)> {
+    ///     conn.query_row_as("SELECT name, age FROM people WHERE id = ?1", [id])
+    /// }
+    /// ```
+    ///
+    /// If the query returns more than one row, all rows except the first are
+    /// ignored.
+    ///
+    /// Returns `Err(QueryReturnedNoRows)` if no results are returned.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be converted to a C-compatible string
+    /// or if the underlying SQLite call fails.
+    #[inline]
+    pub fn query_row_as<T, P>(&self, sql: &str, params: P) -> Result<T>
+    where
+        T: FromRow,
+        P: Params,
+    {
+        self.query_row(sql, params, T::from_row)
+    }
+
     // https://sqlite.org/tclsqlite.html#onecolumn
     #[cfg(test)]
     pub(crate) fn one_column<T: crate::types::FromSql>(&self, sql: &str) -> Result<T> {
         self.query_row(sql, [], |r| r.get(0))
     }
 
+    /// Convenience method to execute a query that returns at most one row
+    /// that matters, returning `Ok(None)` rather than an error if there is
+    /// none.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn preferred_locale(conn: &Connection) -> Result<String> {
+    ///     let locale = conn.query_row_opt(
+    ///         "SELECT value FROM preferences WHERE name='locale'",
+    ///         [],
+    ///         |row| row.get(0),
+    ///     )?;
+    ///     Ok(locale.unwrap_or_else(|| "en-US".to_owned()))
+    /// }
+    /// ```
+    ///
+    /// If the query returns more than one row, all rows except the first are
+    /// ignored. See [`Statement::query_row_opt`].
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be converted to a C-compatible string
+    /// or if the underlying SQLite call fails.
+    #[inline]
+    pub fn query_row_opt<T, P, F>(&self, sql: &str, params: P, f: F) -> Result<Option<T>>
+    where
+        P: Params,
+        F: FnOnce(&Row<'_>) -> Result<T>,
+    {
+        let mut stmt = self.prepare(sql)?;
+        stmt.check_no_tail()?;
+        stmt.query_row_opt(params, f)
+    }
+
+    /// Convenience method to execute a query that is expected to return a
+    /// single row, reporting a missing row as [`QueryError::NotFound`] rather
+    /// than as an [`Error`].
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, QueryError, Result};
+    /// fn preferred_locale(conn: &Connection) -> Result<String> {
+    ///     match conn.query_row_expected(
+    ///         "SELECT value FROM preferences WHERE name='locale'",
+    ///         [],
+    ///         |row| row.get(0),
+    ///     ) {
+    ///         Err(QueryError::NotFound) => Ok("en-US".to_owned()),
+    ///         // any other error converts back into `rusqlite::Error`
+    ///         r => Ok(r?),
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// If the query returns more than one row, all rows except the first are
+    /// ignored, as with [`Connection::query_row`]. Use
+    /// [`Rows::expect_single`] to enforce that exactly one row is returned.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err(QueryError::More(_))` if `sql` cannot be converted to
+    /// a C-compatible string, if the underlying SQLite call fails, or if `f`
+    /// fails, even with [`Error::QueryReturnedNoRows`].
+    #[inline]
+    pub fn query_row_expected<T, P, F>(&self, sql: &str, params: P, f: F) -> Result<T, QueryError>
+    where
+        P: Params,
+        F: FnOnce(&Row<'_>) -> Result<T>,
+    {
+        let mut stmt = self.prepare(sql)?;
+        stmt.check_no_tail()?;
+        let mut rows = stmt.query(params)?;
+        match rows.next()? {
+            Some(row) => Ok(f(row)?),
+            None => Err(QueryError::NotFound),
+        }
+    }
+
+    /// Convenience method to prepare and execute a single DML statement with
+    /// a `RETURNING` clause, converting each returned row with `f`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn delete_expired(conn: &Connection) -> Result<Vec<String>> {
+    ///     conn.execute_returning(
+    ///         "DELETE FROM sessions WHERE expires < unixepoch() RETURNING token",
+    ///         [],
+    ///         |row| row.get(0),
+    ///     )
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be converted to a C-compatible string,
+    /// if the statement returns no columns, typically because it has no
+    /// `RETURNING` clause, or if the underlying SQLite call fails.
+    pub fn execute_returning<T, P, F>(&self, sql: &str, params: P, f: F) -> Result<Vec<T>>
+    where
+        P: Params,
+        F: FnMut(&Row<'_>) -> Result<T>,
+    {
+        let mut stmt = self.prepare(sql)?;
+        stmt.check_no_tail()?;
+        let rows = stmt.query_returning(params)?.mapped(f);
+        rows.collect()
+    }
+
+    /// Convenience method to prepare and execute a single DML statement with
+    /// a `RETURNING` clause that is expected to return exactly one row, such
+    /// as an `INSERT` of one row.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn add_user(conn: &Connection, name: &str) -> Result<(i64, String)> {
+    ///     conn.query_one_returning(
+    ///         "INSERT INTO users (name) VALUES (?1) RETURNING id, created_at",
+    ///         [name],
+    ///         |row| Ok((row.get(0)?, row.get(1)?)),
+    ///     )
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err(QueryReturnedNoRows)` if no row is returned and
+    /// `Err(QueryReturnedMoreThanOneRow)` if several are; in the latter case
+    /// the changes have been made nonetheless. Otherwise fails as
+    /// [`Connection::execute_returning`] does.
+    pub fn query_one_returning<T, P, F>(&self, sql: &str, params: P, f: F) -> Result<T>
+    where
+        P: Params,
+        F: FnOnce(&Row<'_>) -> Result<T>,
+    {
+        let mut stmt = self.prepare(sql)?;
+        stmt.check_no_tail()?;
+        stmt.query_one_returning(params, f)
+    }
+
     /// Convenience method to execute a query with named parameter(s) that is
     /// expected to return a single row.
     ///
@@ -760,7 +1110,120 @@ impl Connection {
     /// or if the underlying SQLite call fails.
     #[inline]
     pub fn prepare(&self, sql: &str) -> Result<Statement<'_>> {
-        self.db.borrow_mut().prepare(self, sql)
+        self.db.borrow_mut().prepare(self, sql, 0)
+    }
+
+    /// Prepare a SQL statement for execution, passing `flags` to
+    /// [`sqlite3_prepare_v3`](https://sqlite.org/c3ref/prepare.html).
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, PrepFlags, Result};
+    /// fn count_people(conn: &Connection) -> Result<i64> {
+    ///     // the statement must not read from a virtual table
+    ///     let mut stmt = conn.prepare_with_flags(
+    ///         "SELECT count(*) FROM people",
+    ///         PrepFlags::SQLITE_PREPARE_NO_VTAB,
+    ///     )?;
+    ///     stmt.query_row([], |row| row.get(0))
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be converted to a C-compatible string
+    /// or if the underlying SQLite call fails.
+    #[cfg(feature = "modern_sqlite")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "modern_sqlite")))]
+    #[inline]
+    pub fn prepare_with_flags(&self, sql: &str, flags: PrepFlags) -> Result<Statement<'_>> {
+        self.db.borrow_mut().prepare(self, sql, flags.bits())
+    }
+
+    /// Prepare the first of several equivalent SQL statements that the linked
+    /// SQLite understands, returning its index in `variants` along with the
+    /// statement.
+    ///
+    /// A variant is skipped only if preparing it fails with a syntax error,
+    /// an unrecognized token or an unknown function, as happens when it uses
+    /// syntax or functions added in a later SQLite version. Any other error,
+    /// such as a missing table, is returned immediately.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn insert(conn: &Connection) -> Result<i64> {
+    ///     let (variant, mut stmt) = conn.prepare_first_supported(&[
+    ///         "INSERT INTO people (name) VALUES (?1) RETURNING id",
+    ///         "INSERT INTO people (name) VALUES (?1)",
+    ///     ])?;
+    ///     if variant == 0 {
+    ///         stmt.query_row(["Joe Smith"], |row| row.get(0))
+    ///     } else {
+    ///         stmt.execute(["Joe Smith"])?;
+    ///         Ok(conn.last_insert_rowid().into())
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `variants` is empty, or with the error from the
+    /// last variant if none of them is supported.
+    pub fn prepare_first_supported(&self, variants: &[&str]) -> Result<(usize, Statement<'_>)> {
+        let mut last_err = None;
+        for (i, sql) in variants.iter().enumerate() {
+            match self.prepare(sql) {
+                Ok(stmt) => return Ok((i, stmt)),
+                Err(e) if is_unsupported_sql(&e) => last_err = Some(e),
+                Err(e) => return Err(e),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| {
+            Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_MISUSE),
+                Some("no SQL variants given".to_owned()),
+            )
+        }))
+    }
+
+    /// Execute the first of several equivalent SQL statements that the linked
+    /// SQLite understands, as chosen by
+    /// [`prepare_first_supported`](Connection::prepare_first_supported).
+    ///
+    /// On success, returns the index of the variant that ran and the number
+    /// of rows that were changed or inserted or deleted.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn upsert(conn: &Connection, key: &str, value: &str) -> Result<usize> {
+    ///     let (_, changes) = conn.execute_first_supported(
+    ///         &[
+    ///             "INSERT INTO kv (k, v) VALUES (?1, ?2) ON CONFLICT (k) DO UPDATE SET v = ?2",
+    ///             "INSERT OR REPLACE INTO kv (k, v) VALUES (?1, ?2)",
+    ///         ],
+    ///         [key, value],
+    ///     )?;
+    ///     Ok(changes)
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if no variant is supported, or if executing the
+    /// chosen one fails.
+    pub fn execute_first_supported<P: Params>(
+        &self,
+        variants: &[&str],
+        params: P,
+    ) -> Result<(usize, usize)> {
+        let (i, mut stmt) = self.prepare_first_supported(variants)?;
+        stmt.check_no_tail()?;
+        stmt.execute(params).map(|changes| (i, changes))
     }
 
     /// Close the SQLite connection.
@@ -773,6 +1236,7 @@ impl Connection {
     ///
     /// Will return `Err` if the underlying SQLite call fails.
     #[inline]
+    #[allow(clippy::result_large_err)]
     pub fn close(self) -> Result<(), (Connection, Error)> {
         self.flush_prepared_statement_cache();
         let r = self.db.borrow_mut().close();
@@ -1154,6 +1618,27 @@ impl Default for OpenFlags {
     }
 }
 
+#[cfg(feature = "modern_sqlite")] // 3.20.0
+bitflags::bitflags! {
+    /// Flags for preparing SQL statements. See
+    /// [sqlite3_prepare_v3](https://sqlite.org/c3ref/c_prepare_normalize.html) for details.
+    #[cfg_attr(docsrs, doc(cfg(feature = "modern_sqlite")))]
+    #[repr(C)]
+    pub struct PrepFlags: ::std::os::raw::c_uint {
+        /// A hint that the statement will be retained for a long time and
+        /// probably reused many times, so that SQLite does not allocate it
+        /// from lookaside memory.
+        const SQLITE_PREPARE_PERSISTENT = ffi::SQLITE_PREPARE_PERSISTENT as ::std::os::raw::c_uint;
+        /// No-op since SQLite 3.22.0, which normalizes statements on demand
+        /// instead.
+        const SQLITE_PREPARE_NORMALIZE = ffi::SQLITE_PREPARE_NORMALIZE as ::std::os::raw::c_uint;
+        /// Preparing fails with `SQLITE_ERROR` if the statement uses a
+        /// virtual table. Eponymous virtual tables, like `pragma_table_info`,
+        /// are then reported as missing.
+        const SQLITE_PREPARE_NO_VTAB = ffi::SQLITE_PREPARE_NO_VTAB as ::std::os::raw::c_uint;
+    }
+}
+
 /// rusqlite's check for a safe SQLite threading mode requires SQLite 3.7.0 or
 /// later. If you are running against a SQLite older than that, rusqlite
 /// attempts to ensure safety by performing configuration and initialization of
@@ -1196,6 +1681,45 @@ impl InterruptHandle {
     }
 }
 
+/// Allows cancelling long-running operations which check it, like
+/// [`Backup::run_to_completion`](backup::Backup::run_to_completion) or
+/// `Connection::vacuum_cancellable`, from another thread.
+///
+/// Unlike an [`InterruptHandle`], it is not tied to a connection, and stays
+/// cancelled until [`reset`](CancellationToken::reset). Clones share the same
+/// state.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a token which is not cancelled.
+    #[inline]
+    #[must_use]
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Cancel the operations checking this token.
+    #[inline]
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true if [`cancel`](CancellationToken::cancel) was called since
+    /// the token was created or reset.
+    #[inline]
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Make the token usable again after a cancellation.
+    #[inline]
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
 #[cfg(doctest)]
 doc_comment::doctest!("../README.md");
 
@@ -1210,13 +1734,21 @@ mod test {
     // this function is never called, but is still type checked; in
     // particular, calls with specific instantiations will require
     // that those types are `Send`.
-    #[allow(dead_code, unconditional_recursion)]
+    #[allow(
+        dead_code,
+        unconditional_recursion,
+        clippy::extra_unused_type_parameters
+    )]
     fn ensure_send<T: Send>() {
         ensure_send::<Connection>();
         ensure_send::<InterruptHandle>();
     }
 
-    #[allow(dead_code, unconditional_recursion)]
+    #[allow(
+        dead_code,
+        unconditional_recursion,
+        clippy::extra_unused_type_parameters
+    )]
     fn ensure_sync<T: Sync>() {
         ensure_sync::<InterruptHandle>();
     }
@@ -1444,6 +1976,52 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_execute_many() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(x INTEGER PRIMARY KEY, y TEXT)")?;
+
+        let n = db.execute_many(
+            "INSERT INTO foo(x, y) VALUES (?1, ?2)",
+            (0..10_000).map(|i| (i, format!("row {i}"))),
+        )?;
+        assert_eq!(n, 10_000);
+        assert_eq!(10_000, db.one_column::<i64>("SELECT count(*) FROM foo")?);
+        assert!(db.is_autocommit());
+
+        // the duplicate key in item 3 rolls back items 0 to 2
+        let err = db
+            .execute_many(
+                "INSERT INTO foo(x) VALUES (?1)",
+                [[-1], [-2], [-3], [0], [-4]],
+            )
+            .unwrap_err();
+        match err {
+            Error::ExecuteManyFailed { index, ref source } => {
+                assert_eq!(index, 3);
+                assert_eq!(
+                    source.sqlite_error_code(),
+                    Some(ErrorCode::ConstraintViolation)
+                );
+            }
+            ref err => panic!("Unexpected error {}", err),
+        }
+        assert_eq!(
+            err.sqlite_error_code(),
+            Some(ErrorCode::ConstraintViolation)
+        );
+        assert!(err.to_string().starts_with("Executing item 3 failed: "));
+        assert_eq!(
+            0,
+            db.one_column::<i64>("SELECT count(*) FROM foo WHERE x < 0")?
+        );
+        assert!(db.is_autocommit());
+
+        db.execute_many("SELECT 1; SELECT 2", [crate::params![]])
+            .unwrap_err();
+        Ok(())
+    }
+
     #[test]
     #[cfg(feature = "extra_check")]
     fn test_execute_select() {
@@ -1613,12 +2191,100 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_query_row_opt() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(x INTEGER); INSERT INTO foo VALUES (1), (2);")?;
+        let sql = "SELECT x FROM foo WHERE x >= ?1 ORDER BY x";
+
+        assert_eq!(db.query_row_opt(sql, [3], |r| r.get::<_, i64>(0))?, None);
+        assert_eq!(db.query_row_opt(sql, [2], |r| r.get::<_, i64>(0))?, Some(2));
+        assert_eq!(db.query_row_opt(sql, [1], |r| r.get::<_, i64>(0))?, Some(1));
+        assert!(db
+            .query_row_opt("SELECT x FROM bar", [], |r| r.get::<_, i64>(0))
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_row_expected() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(x INTEGER); INSERT INTO foo VALUES (1), (2);")?;
+        let sql = "SELECT x FROM foo WHERE x >= ?1 ORDER BY x";
+
+        let r = db.query_row_expected(sql, [3], |r| r.get::<_, i64>(0));
+        assert_eq!(Err(QueryError::NotFound), r);
+        assert_eq!(
+            Ok(2),
+            db.query_row_expected(sql, [2], |r| r.get::<_, i64>(0))
+        );
+        // extra rows are ignored, like `query_row`
+        assert_eq!(
+            Ok(1),
+            db.query_row_expected(sql, [1], |r| r.get::<_, i64>(0))
+        );
+
+        let r = db.query_row_expected("SELECT x FROM bar", [], |r| r.get::<_, i64>(0));
+        assert!(matches!(r, Err(QueryError::More(Error::SqliteFailure(..)))));
+
+        // not found by the closure rather than by the query
+        let r = db.query_row_expected(sql, [1], |_| -> Result<i64> {
+            Err(Error::QueryReturnedNoRows)
+        });
+        assert_eq!(Err(QueryError::More(Error::QueryReturnedNoRows)), r);
+        Ok(())
+    }
+
+    #[test]
+    fn test_expect_single() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(x INTEGER); INSERT INTO foo VALUES (1), (2);")?;
+        let mut stmt = db.prepare("SELECT x FROM foo WHERE x >= ?1 ORDER BY x")?;
+
+        let r = stmt.query([3])?.expect_single(|r| r.get::<_, i64>(0));
+        assert_eq!(Err(QueryError::NotFound), r);
+        let r = stmt.query([2])?.expect_single(|r| r.get::<_, i64>(0));
+        assert_eq!(Ok(2), r);
+        let r = stmt.query([1])?.expect_single(|r| r.get::<_, i64>(0));
+        assert_eq!(Err(QueryError::More(Error::QueryReturnedMoreThanOneRow)), r);
+
+        let r = stmt.query([2])?.expect_single(|r| r.get::<_, String>(0));
+        assert!(matches!(
+            r,
+            Err(QueryError::More(Error::InvalidColumnType(0, ..)))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_error_conversion() -> Result<()> {
+        fn lookup(db: &Connection, sql: &str) -> Result<i64> {
+            Ok(db.query_row_expected(sql, [], |r| r.get(0))?)
+        }
+        let db = Connection::open_in_memory()?;
+        assert_eq!(Ok(1), lookup(&db, "SELECT 1"));
+        assert_eq!(
+            Err(Error::QueryReturnedNoRows),
+            lookup(&db, "SELECT 1 WHERE 0")
+        );
+        let err = lookup(&db, "SELECT 'a'").unwrap_err();
+        assert!(matches!(err, Error::InvalidColumnType(0, ref name, _) if name == "'a'"));
+        // the original error is preserved both ways
+        let err = Error::from(QueryError::More(Error::QueryReturnedMoreThanOneRow));
+        assert_eq!(Error::QueryReturnedMoreThanOneRow, err);
+        assert_eq!(
+            QueryError::More(Error::QueryReturnedNoRows),
+            QueryError::from(Error::QueryReturnedNoRows)
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_pragma_query_row() -> Result<()> {
         let db = Connection::open_in_memory()?;
         assert_eq!("memory", db.one_column::<String>("PRAGMA journal_mode")?);
         let mode = db.one_column::<String>("PRAGMA journal_mode=off")?;
-        if cfg!(features = "bundled") {
+        if cfg!(feature = "bundled") {
             assert_eq!(mode, "off");
         } else {
             // Note: system SQLite on macOS defaults to "off" rather than
@@ -1648,6 +2314,109 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    #[cfg(feature = "modern_sqlite")]
+    fn test_prepare_with_flags() -> Result<()> {
+        use crate::PrepFlags;
+
+        let db = Connection::open_in_memory()?;
+        let sql = "SELECT count(*) FROM pragma_table_info('sqlite_master')";
+        let count: i64 = db.prepare(sql)?.query_row([], |r| r.get(0))?;
+        assert_eq!(count, 5);
+        let count: i64 = db
+            .prepare_with_flags(sql, PrepFlags::SQLITE_PREPARE_PERSISTENT)?
+            .query_row([], |r| r.get(0))?;
+        assert_eq!(count, 5);
+
+        let err = db
+            .prepare_with_flags(sql, PrepFlags::SQLITE_PREPARE_NO_VTAB)
+            .unwrap_err();
+        // eponymous virtual tables are not even looked up
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::Unknown));
+        assert!(
+            err.to_string().contains("no such table: pragma_table_info"),
+            "{}",
+            err
+        );
+        db.prepare_with_flags("SELECT 1", PrepFlags::SQLITE_PREPARE_NO_VTAB)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_prepare_first_supported() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(x INTEGER PRIMARY KEY, y TEXT);")?;
+
+        // RETURNING is supported since 3.35.0
+        let (i, mut stmt) = db.prepare_first_supported(&[
+            "INSERT INTO foo (y) VALUES (?1) RETURNING x",
+            "INSERT INTO foo (y) VALUES (?1)",
+        ])?;
+        assert_eq!(i, 0);
+        let x: i64 = stmt.query_row(["a"], |r| r.get(0))?;
+        assert_eq!(x, 1);
+
+        let (i, _) = db.prepare_first_supported(&[
+            "INSERT INTO foo (y) VALUES (?1) RETURNING x FROM nowhere",
+            "INSERT INTO foo (y) VALUES (no_such_fn(?1))",
+            "INSERT INTO foo (y) VALUES (?1 ` )",
+            "INSERT INTO foo (y) VALUES (?1)",
+        ])?;
+        assert_eq!(i, 3);
+
+        // only parse failures fall through
+        let err = db
+            .prepare_first_supported(&["INSERT INTO bar VALUES (1)", "INSERT INTO foo VALUES (1)"])
+            .unwrap_err();
+        assert!(format!("{err}").contains("no such table: bar"));
+        let err = db
+            .prepare_first_supported(&["SELECT no_such_fn()", "SELECT no_such_fn(1)"])
+            .unwrap_err();
+        assert!(format!("{err}").contains("no_such_fn"));
+        assert!(db.prepare_first_supported(&[]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_first_supported() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(x INTEGER PRIMARY KEY, y TEXT);")?;
+
+        let upsert = [
+            "INSERT INTO foo VALUES (?1, ?2) ON CONFLICT (x) DO UPDATE SET y = ?2",
+            "INSERT OR REPLACE INTO foo VALUES (?1, ?2)",
+        ];
+        assert_eq!(db.execute_first_supported(&upsert, (1, "a"))?, (0, 1));
+        assert_eq!(db.execute_first_supported(&upsert, (1, "b"))?, (0, 1));
+        let y: String = db.query_row("SELECT y FROM foo WHERE x = 1", [], |r| r.get(0))?;
+        assert_eq!(y, "b");
+
+        let (i, changes) = db.execute_first_supported(
+            &[
+                "INSERT INTO foo VALUES (?1, bogus_fn(?2))",
+                "INSERT INTO foo VALUES (?1, ?2)",
+            ],
+            (2, "c"),
+        )?;
+        assert_eq!((i, changes), (1, 1));
+
+        // constraint violations are reported, not skipped
+        let err = db
+            .execute_first_supported(
+                &[
+                    "INSERT INTO foo VALUES (?1, ?2)",
+                    "INSERT OR IGNORE INTO foo VALUES (?1, ?2)",
+                ],
+                (2, "d"),
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.sqlite_error_code(),
+            Some(ErrorCode::ConstraintViolation)
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_last_insert_rowid() -> Result<()> {
         let db = Connection::open_in_memory()?;
@@ -2122,6 +2891,68 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    #[cfg(feature = "modern_sqlite")] // 3.35.0
+    fn test_execute_returning() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE foo(id INTEGER PRIMARY KEY, x TEXT, created TEXT DEFAULT 'now')",
+        )?;
+
+        let (id, created): (i64, String) = db.query_one_returning(
+            "INSERT INTO foo (x) VALUES (?1) RETURNING id, created",
+            ["a"],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )?;
+        assert_eq!((id, created.as_str()), (1, "now"));
+        let ids = db.execute_returning(
+            "INSERT INTO foo (x) VALUES ('b'), ('c') RETURNING id",
+            [],
+            |r| r.get::<_, i64>(0),
+        )?;
+        assert_eq!(ids, [2, 3]);
+
+        let mut stmt = db.prepare("UPDATE foo SET x = upper(x) WHERE id >= ?1 RETURNING x")?;
+        let xs: Vec<String> = stmt
+            .query_returning([2])?
+            .mapped(|r| r.get(0))
+            .collect::<Result<_>>()?;
+        assert_eq!(xs, ["B", "C"]);
+        let x: String = stmt.query_one_returning([3], |r| r.get(0))?;
+        assert_eq!(x, "C");
+        assert_eq!(
+            stmt.query_one_returning([4], |r| r.get::<_, String>(0)),
+            Err(Error::QueryReturnedNoRows)
+        );
+        // the rows are updated, then reported as too many
+        assert_eq!(
+            stmt.query_one_returning([1], |r| r.get::<_, String>(0)),
+            Err(Error::QueryReturnedMoreThanOneRow)
+        );
+        assert_eq!(
+            "A",
+            db.one_column::<String>("SELECT x FROM foo WHERE id = 1")?
+        );
+
+        let none = db.execute_returning("DELETE FROM foo WHERE id > 10 RETURNING id", [], |r| {
+            r.get::<_, i64>(0)
+        })?;
+        assert!(none.is_empty());
+        // unread rows are deleted too
+        let mut stmt = db.prepare("DELETE FROM foo WHERE id > 1 RETURNING id")?;
+        assert!(stmt.query_returning([])?.next()?.is_some());
+        drop(stmt);
+        assert_eq!(1, db.one_column::<i64>("SELECT count(*) FROM foo")?);
+
+        let err = db
+            .execute_returning("DELETE FROM foo", [], |r| r.get::<_, i64>(0))
+            .unwrap_err();
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::ApiMisuse));
+        assert!(err.to_string().contains("RETURNING"), "{}", err);
+        assert_eq!(1, db.one_column::<i64>("SELECT count(*) FROM foo")?);
+        Ok(())
+    }
+
     #[test]
     fn test_cache_flush() -> Result<()> {
         let db = Connection::open_in_memory()?;
@@ -56,6 +56,19 @@ impl Connection {
 
     /// Changes the [`Limit`] to `new_val`, returning the prior
     /// value of the limit.
+    ///
+    /// A value above the maximum SQLite was compiled with is lowered to that
+    /// maximum, as the next call returns. A negative value leaves the limit
+    /// unchanged.
+    ///
+    /// ```rust
+    /// # use rusqlite::Connection;
+    /// # use rusqlite::limits::Limit;
+    /// fn restrict(conn: &Connection) {
+    ///     // hostile SQL cannot attach any database
+    ///     conn.set_limit(Limit::SQLITE_LIMIT_ATTACHED, 0);
+    /// }
+    /// ```
     #[inline]
     #[cfg_attr(docsrs, doc(cfg(feature = "limits")))]
     pub fn set_limit(&self, limit: Limit, new_val: i32) -> i32 {
@@ -160,4 +173,33 @@ mod test {
         }
         Ok(())
     }
+
+    #[test]
+    fn test_limit_variable_number() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        let prior = db.set_limit(Limit::SQLITE_LIMIT_VARIABLE_NUMBER, 2);
+        assert!(prior >= 999);
+        db.prepare("SELECT ?, ?")?;
+        let err = db.prepare("SELECT ?, ?, ?").unwrap_err();
+        assert!(
+            err.to_string().contains("too many SQL variables"),
+            "{}",
+            err
+        );
+
+        assert_eq!(2, db.set_limit(Limit::SQLITE_LIMIT_VARIABLE_NUMBER, prior));
+        db.prepare("SELECT ?, ?, ?")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_limit_clamped() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        // limits start at their maximum
+        let max = db.limit(Limit::SQLITE_LIMIT_ATTACHED);
+        assert_eq!(max, db.set_limit(Limit::SQLITE_LIMIT_ATTACHED, i32::MAX));
+        assert_eq!(max, db.set_limit(Limit::SQLITE_LIMIT_ATTACHED, -1));
+        assert_eq!(max, db.limit(Limit::SQLITE_LIMIT_ATTACHED));
+        Ok(())
+    }
 }
@@ -0,0 +1,287 @@
+//! Raw page access through the
+//! [`sqlite_dbpage`](https://sqlite.org/dbpage.html) virtual table.
+//!
+//! This requires SQLite to be built with `SQLITE_ENABLE_DBPAGE_VTAB`, as the
+//! bundled build is. Without it, only the pages of databases held privately by
+//! the `memdb` VFS, e.g. deserialized ones, can be read, in place with
+//! [`sqlite3_serialize`](https://sqlite.org/c3ref/serialize.html). Pages are
+//! numbered from 1; page 1 starts with the database header.
+//!
+//! Together with [`Connection::data_version`], this is enough to ship a
+//! database page by page: poll `data_version` and, when it changes, read the
+//! pages whose content differs from the last copy.
+
+use std::slice;
+
+use crate::ffi;
+use crate::{Connection, DatabaseName, Error, Result};
+
+fn has_dbpage() -> bool {
+    unsafe { ffi::sqlite3_compileoption_used(b"ENABLE_DBPAGE_VTAB\0".as_ptr().cast()) != 0 }
+}
+
+fn schema<'a>(db: DatabaseName<'a>) -> &'a str {
+    match db {
+        DatabaseName::Main => "main",
+        DatabaseName::Temp => "temp",
+        DatabaseName::Attached(s) => s,
+    }
+}
+
+impl Connection {
+    /// Returns the number of pages in the database, as reported by `PRAGMA
+    /// page_count`.
+    pub fn page_count(&self, db: DatabaseName<'_>) -> Result<u32> {
+        self.pragma_query_value(Some(db), "page_count", |row| row.get(0))
+    }
+
+    /// Returns the page size of the database in bytes, as reported by
+    /// `PRAGMA page_size`.
+    pub fn page_size(&self, db: DatabaseName<'_>) -> Result<u32> {
+        self.pragma_query_value(Some(db), "page_size", |row| row.get(0))
+    }
+
+    /// Returns the value of `PRAGMA data_version`, which changes whenever
+    /// another connection commits a change to the main database.
+    ///
+    /// Changes made through this connection do not change the value.
+    pub fn data_version(&self) -> Result<u32> {
+        self.pragma_query_value(None, "data_version", |row| row.get(0))
+    }
+
+    /// Reads page `pgno` of the database.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` with `SQLITE_RANGE` if `pgno` is not a page of the
+    /// database, or if SQLite was built without the `sqlite_dbpage` virtual
+    /// table and the database is not held by the `memdb` VFS.
+    pub fn read_page(&self, db: DatabaseName<'_>, pgno: u32) -> Result<Vec<u8>> {
+        self.check_pgno(db, pgno)?;
+        if !has_dbpage() {
+            return self.read_serialized_page(db, pgno);
+        }
+        let mut stmt = self.prepare_cached("SELECT data FROM sqlite_dbpage(?1) WHERE pgno = ?2")?;
+        stmt.query_row((schema(db), pgno), |row| row.get(0))
+    }
+
+    // Only reads the databases which can be serialized without a copy, as
+    // copying the whole database for each page would make reading all pages
+    // quadratic.
+    fn read_serialized_page(&self, db: DatabaseName<'_>, pgno: u32) -> Result<Vec<u8>> {
+        let page_size = self.page_size(db)? as usize;
+        let name = db.as_cstring()?;
+        let c = self.db.borrow();
+        unsafe {
+            let mut size: ffi::sqlite3_int64 = 0;
+            let data = ffi::sqlite3_serialize(
+                c.db(),
+                name.as_ptr(),
+                &mut size,
+                ffi::SQLITE_SERIALIZE_NOCOPY as _,
+            );
+            if data.is_null() {
+                return Err(Error::SqliteFailure(
+                    ffi::Error::new(ffi::SQLITE_MISUSE),
+                    Some(format!(
+                        "reading the pages of database {:?} needs SQLite built with \
+                         SQLITE_ENABLE_DBPAGE_VTAB",
+                        schema(db)
+                    )),
+                ));
+            }
+            let bytes = slice::from_raw_parts(data, size as usize);
+            let start = (pgno as usize - 1) * page_size;
+            Ok(bytes[start..start + page_size].to_vec())
+        }
+    }
+
+    fn check_pgno(&self, db: DatabaseName<'_>, pgno: u32) -> Result<()> {
+        let page_count = self.page_count(db)?;
+        if pgno == 0 || pgno > page_count {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_RANGE),
+                Some(format!("page {pgno} is out of range 1..={page_count}")),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Allows [`write_page`](Connection::write_page) on this connection.
+    ///
+    /// Writing raw pages bypasses every check SQLite makes on the content of
+    /// the database: a page that is not consistent with the rest of the file
+    /// will corrupt it. Only write pages read from another copy of the same
+    /// database, and only while no other connection is using it.
+    #[inline]
+    pub fn enable_raw_page_writes(&self) {
+        self.db.borrow_mut().raw_page_writes = true;
+    }
+
+    /// Overwrites page `pgno` of the database with `data`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if raw page writes have not been enabled with
+    /// [`enable_raw_page_writes`](Connection::enable_raw_page_writes), if
+    /// `pgno` is not a page of the database, if `data` is not exactly one
+    /// page long, if the connection is in defensive mode, or if SQLite was
+    /// built without the `sqlite_dbpage` virtual table.
+    pub fn write_page(&self, db: DatabaseName<'_>, pgno: u32, data: &[u8]) -> Result<()> {
+        if !self.db.borrow().raw_page_writes {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_MISUSE),
+                Some("raw page writes are not enabled on this connection".to_owned()),
+            ));
+        }
+        self.check_pgno(db, pgno)?;
+        let page_size = self.page_size(db)?;
+        if data.len() != page_size as usize {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_MISUSE),
+                Some(format!(
+                    "page data is {} bytes, expected {page_size}",
+                    data.len()
+                )),
+            ));
+        }
+        self.execute(
+            "UPDATE sqlite_dbpage SET data = ?3 WHERE schema = ?1 AND pgno = ?2",
+            (schema(db), pgno, data),
+        )
+        .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Connection, ErrorCode, Result, MAIN_DB};
+
+    fn populated(conn: &Connection) -> Result<()> {
+        conn.execute_batch(
+            "CREATE TABLE foo(x INTEGER PRIMARY KEY, y TEXT);
+             WITH RECURSIVE c(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM c WHERE i < 500)
+             INSERT INTO foo SELECT i, printf('row %d', i) FROM c;",
+        )
+    }
+
+    #[test]
+    fn test_read_header() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        populated(&db)?;
+        let page = db.read_page(MAIN_DB, 1)?;
+        assert_eq!(&page[..16], b"SQLite format 3\0");
+        assert_eq!(page.len(), db.page_size(MAIN_DB)? as usize);
+        assert!(db.read_page(MAIN_DB, db.page_count(MAIN_DB)? + 1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_count() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        populated(&db)?;
+        let pragma: u32 = db.query_row("PRAGMA page_count", [], |r| r.get(0))?;
+        assert_eq!(db.page_count(MAIN_DB)?, pragma);
+        assert!(pragma > 1);
+        db.read_page(MAIN_DB, pragma)?;
+        for pgno in [0, pragma + 1] {
+            let err = db.read_page(MAIN_DB, pgno).unwrap_err();
+            assert_eq!(
+                err.sqlite_error_code(),
+                Some(ErrorCode::ParameterOutOfRange)
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_serialized_page() -> Result<()> {
+        let db = Connection::open("file:page_io?vfs=memdb")?;
+        populated(&db)?;
+        let pgno = db.page_count(MAIN_DB)?;
+        assert_eq!(
+            db.read_serialized_page(MAIN_DB, pgno)?,
+            db.read_page(MAIN_DB, pgno)?
+        );
+
+        // a file would have to be copied for each page
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db = Connection::open(temp_dir.path().join("file.db3"))?;
+        populated(&db)?;
+        let err = db.read_serialized_page(MAIN_DB, 1).unwrap_err();
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::ApiMisuse));
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_pages() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let src_path = temp_dir.path().join("src.db3");
+        let dst_path = temp_dir.path().join("dst.db3");
+
+        let src = Connection::open(&src_path)?;
+        populated(&src)?;
+        let mut copy = Vec::new();
+        for pgno in 1..=src.page_count(MAIN_DB)? {
+            copy.extend(src.read_page(MAIN_DB, pgno)?);
+        }
+        std::fs::write(&dst_path, &copy).unwrap();
+        assert_eq!(std::fs::read(&src_path).unwrap(), copy);
+
+        let dst = Connection::open(&dst_path)?;
+        let n: i64 = dst.query_row("SELECT count(*) FROM foo", [], |r| r.get(0))?;
+        assert_eq!(n, 500);
+        let check: String = dst.query_row("PRAGMA integrity_check", [], |r| r.get(0))?;
+        assert_eq!(check, "ok");
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "bundled")]
+    fn test_write_page() -> Result<()> {
+        let src = Connection::open_in_memory()?;
+        populated(&src)?;
+        src.execute("UPDATE foo SET y = 'changed' WHERE x = 1", [])?;
+        let dst = Connection::open_in_memory()?;
+        populated(&dst)?;
+        assert_eq!(src.page_count(MAIN_DB)?, dst.page_count(MAIN_DB)?);
+
+        let pgno = 3;
+        let page = src.read_page(MAIN_DB, pgno)?;
+        let err = dst.write_page(MAIN_DB, pgno, &page).unwrap_err();
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::ApiMisuse));
+
+        dst.enable_raw_page_writes();
+        let err = dst.write_page(MAIN_DB, pgno, &page[1..]).unwrap_err();
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::ApiMisuse));
+        let err = dst.write_page(MAIN_DB, 0, &page).unwrap_err();
+        assert_eq!(
+            err.sqlite_error_code(),
+            Some(ErrorCode::ParameterOutOfRange)
+        );
+
+        for pgno in 1..=src.page_count(MAIN_DB)? {
+            dst.write_page(MAIN_DB, pgno, &src.read_page(MAIN_DB, pgno)?)?;
+        }
+        let y: String = dst.query_row("SELECT y FROM foo WHERE x = 1", [], |r| r.get(0))?;
+        assert_eq!(y, "changed");
+        Ok(())
+    }
+
+    #[test]
+    fn test_data_version() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let db1 = Connection::open(&path)?;
+        let db2 = Connection::open(&path)?;
+        db1.execute_batch("CREATE TABLE foo(x)")?;
+
+        let version = db1.data_version()?;
+        db1.execute("INSERT INTO foo VALUES (1)", [])?;
+        assert_eq!(db1.data_version()?, version);
+        let version = db2.data_version()?;
+        db1.execute("INSERT INTO foo VALUES (2)", [])?;
+        assert_ne!(db2.data_version()?, version);
+        Ok(())
+    }
+}
@@ -0,0 +1,304 @@
+//! What happens when a Rust callback called by SQLite panics.
+//!
+//! A panic must not unwind through SQLite's C frames, so every callback
+//! trampoline catches it and reports a failure to SQLite instead. The payload
+//! is kept in a thread local until control is back in the Rust frame that
+//! called into SQLite, which knows the connection the callback ran on. It is
+//! then handled right away by the connection's [`PanicPolicy`] (stepping a
+//! statement, or any call whose result goes through
+//! `InnerConnection::decode_result`), or kept as pending for that connection
+//! until its next such call (resetting or finalizing a statement, which may
+//! close virtual table cursors). Panics still pending when the connection is
+//! closed, or caught while closing it, are dropped.
+use std::any::Any;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::{Connection, InnerConnection};
+
+/// What a connection does with a panic in one of its callbacks: a
+/// user-defined function, a collation, a hook or a virtual table method.
+///
+/// Set with [`Connection::set_panic_policy`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PanicPolicy {
+    /// The panic becomes an error of the statement that ran the callback,
+    /// e.g. [`Error::UnwindingPanic`](crate::Error::UnwindingPanic) for a
+    /// function. Panics in callbacks that cannot fail, like an update hook,
+    /// are ignored. This is the default.
+    ConvertToError,
+    /// The process aborts, after printing the panic message, once SQLite
+    /// has returned.
+    Abort,
+    /// The panic resumes, with its original payload, once SQLite has
+    /// returned, as if it had unwound through SQLite.
+    Propagate,
+}
+
+impl Default for PanicPolicy {
+    #[inline]
+    fn default() -> PanicPolicy {
+        PanicPolicy::ConvertToError
+    }
+}
+
+thread_local! {
+    // Payload of a panic caught in a callback, until the caller of SQLite
+    // takes it for its connection.
+    static CAUGHT_PANIC: RefCell<Option<Box<dyn Any + Send>>> = RefCell::new(None);
+}
+
+// Number of payloads caught or pending, across threads and connections, so
+// that checking for one is a single atomic load in the common case.
+static PENDING_PANICS: AtomicUsize = AtomicUsize::new(0);
+
+// The panic pending for a connection, shared with its statements, which do
+// not have the connection at hand when they are reset or finalized.
+pub(crate) type PanicSlot = Arc<Mutex<Option<Box<dyn Any + Send>>>>;
+
+// Keeps the payload of a panic caught in a callback, to be handled by
+// `resume_panic` once SQLite has returned. Only the first panic is kept.
+pub(crate) fn stash_panic(payload: Box<dyn Any + Send>) {
+    CAUGHT_PANIC.with(|caught| {
+        let mut caught = caught.borrow_mut();
+        if caught.is_none() {
+            *caught = Some(payload);
+            PENDING_PANICS.fetch_add(1, Ordering::Relaxed);
+        }
+    });
+}
+
+// Makes a panic caught during the call into SQLite that just returned pending
+// for the connection of `slot`. Only its first pending panic is kept.
+pub(crate) fn keep_caught_panic(slot: &PanicSlot) {
+    if PENDING_PANICS.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    if let Some(payload) = CAUGHT_PANIC.with(|caught| caught.borrow_mut().take()) {
+        let mut pending = slot.lock().unwrap_or_else(PoisonError::into_inner);
+        if pending.is_none() {
+            *pending = Some(payload);
+        } else {
+            PENDING_PANICS.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+fn take_panic(slot: &PanicSlot) -> Option<Box<dyn Any + Send>> {
+    keep_caught_panic(slot);
+    let payload = slot.lock().unwrap_or_else(PoisonError::into_inner).take();
+    if payload.is_some() {
+        PENDING_PANICS.fetch_sub(1, Ordering::Relaxed);
+    }
+    payload
+}
+
+fn apply(policy: PanicPolicy, payload: Box<dyn Any + Send>, abort: impl FnOnce()) {
+    match policy {
+        PanicPolicy::ConvertToError => {}
+        PanicPolicy::Abort => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("Box<dyn Any>");
+            eprintln!("panic in a SQLite callback: {message}; aborting");
+            abort();
+        }
+        PanicPolicy::Propagate => std::panic::resume_unwind(payload),
+    }
+}
+
+impl InnerConnection {
+    // Handles a panic caught in a callback since SQLite was last called, or
+    // pending for this connection, according to its policy.
+    #[inline]
+    pub(crate) fn resume_panic(&self) {
+        if PENDING_PANICS.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        if let Some(payload) = take_panic(&self.pending_panic) {
+            apply(self.panic_policy, payload, || std::process::abort());
+        }
+    }
+
+    // Drops the panic pending for this connection, and any caught while
+    // closing it.
+    pub(crate) fn forget_panic(&self) {
+        if PENDING_PANICS.load(Ordering::Relaxed) != 0 {
+            drop(take_panic(&self.pending_panic));
+        }
+    }
+}
+
+impl Connection {
+    #[inline]
+    pub(crate) fn resume_panic(&self) {
+        if PENDING_PANICS.load(Ordering::Relaxed) != 0 {
+            self.db.borrow().resume_panic();
+        }
+    }
+
+    /// Sets what this connection does when one of its callbacks panics.
+    ///
+    /// With [`PanicPolicy::Propagate`], the panic resumes in the call that
+    /// made SQLite run the callback, e.g. [`Statement::execute`](crate::Statement::execute),
+    /// after SQLite has returned. The statement may have done more work
+    /// in between when the callback could not fail, e.g. an update hook.
+    #[inline]
+    pub fn set_panic_policy(&self, policy: PanicPolicy) {
+        self.db.borrow_mut().panic_policy = policy;
+    }
+
+    /// Returns what this connection does when one of its callbacks panics.
+    #[inline]
+    #[must_use]
+    pub fn panic_policy(&self) -> PanicPolicy {
+        self.db.borrow().panic_policy
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use super::{apply, PanicPolicy};
+
+    #[test]
+    fn test_apply() {
+        let mut aborted = false;
+        apply(PanicPolicy::ConvertToError, Box::new("boom"), || {
+            aborted = true
+        });
+        assert!(!aborted);
+        apply(PanicPolicy::Abort, Box::new("boom"), || aborted = true);
+        assert!(aborted);
+
+        let payload = catch_unwind(AssertUnwindSafe(|| {
+            apply(PanicPolicy::Propagate, Box::new("boom"), || unreachable!());
+        }))
+        .unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"boom"));
+    }
+
+    // An eponymous table of two rows, whose cursors panic when closed.
+    #[cfg(feature = "vtab")]
+    fn open_with_panicking_close() -> crate::Result<crate::Connection> {
+        use std::marker::PhantomData;
+        use std::os::raw::c_int;
+
+        use crate::vtab::{
+            eponymous_only_module, sqlite3_vtab, sqlite3_vtab_cursor, Context, IndexInfo, VTab,
+            VTabConnection, VTabCursor, Values,
+        };
+        use crate::{Connection, Result};
+
+        #[repr(C)]
+        struct BoomTab {
+            base: sqlite3_vtab,
+        }
+
+        unsafe impl<'vtab> VTab<'vtab> for BoomTab {
+            type Aux = ();
+            type Cursor = BoomCursor<'vtab>;
+
+            fn connect(
+                _: &mut VTabConnection,
+                _: Option<&()>,
+                _: &[&[u8]],
+            ) -> Result<(String, BoomTab)> {
+                let vtab = BoomTab {
+                    base: sqlite3_vtab::default(),
+                };
+                Ok(("CREATE TABLE x(value)".to_owned(), vtab))
+            }
+
+            fn best_index(&self, info: &mut IndexInfo) -> Result<()> {
+                info.set_estimated_cost(1.);
+                Ok(())
+            }
+
+            fn open(&'vtab mut self) -> Result<BoomCursor<'vtab>> {
+                Ok(BoomCursor::default())
+            }
+        }
+
+        #[derive(Default)]
+        #[repr(C)]
+        struct BoomCursor<'vtab> {
+            base: sqlite3_vtab_cursor,
+            row_id: i64,
+            phantom: PhantomData<&'vtab BoomTab>,
+        }
+
+        impl Drop for BoomCursor<'_> {
+            fn drop(&mut self) {
+                panic!("xClose");
+            }
+        }
+
+        unsafe impl VTabCursor for BoomCursor<'_> {
+            fn filter(&mut self, _: c_int, _: Option<&str>, _: &Values<'_>) -> Result<()> {
+                self.row_id = 1;
+                Ok(())
+            }
+
+            fn next(&mut self) -> Result<()> {
+                self.row_id += 1;
+                Ok(())
+            }
+
+            fn eof(&self) -> bool {
+                self.row_id > 2
+            }
+
+            fn column(&self, ctx: &mut Context, _: c_int) -> Result<()> {
+                ctx.set_result(&self.row_id)
+            }
+
+            fn rowid(&self) -> Result<i64> {
+                Ok(self.row_id)
+            }
+        }
+
+        let db = Connection::open_in_memory()?;
+        db.create_module("boom", eponymous_only_module::<BoomTab>(), None)?;
+        Ok(db)
+    }
+
+    #[test]
+    #[cfg(feature = "vtab")]
+    fn test_panic_stays_with_its_connection() -> crate::Result<()> {
+        use crate::Connection;
+
+        // reads one row and leaves the cursor to be closed by the reset
+        fn read_one(db: &Connection) -> crate::Result<()> {
+            let mut stmt = db.prepare("SELECT value FROM boom()")?;
+            let mut rows = stmt.query([])?;
+            rows.next()?;
+            Ok(())
+        }
+
+        let db1 = open_with_panicking_close()?;
+        db1.set_panic_policy(PanicPolicy::Propagate);
+        let db2 = Connection::open_in_memory()?;
+        db2.set_panic_policy(PanicPolicy::Propagate);
+
+        read_one(&db1)?;
+        // not resumed by another connection
+        assert_eq!(db2.one_column::<i64>("SELECT 1")?, 1);
+        // but by the next statement of its own
+        let payload =
+            catch_unwind(AssertUnwindSafe(|| db1.one_column::<i64>("SELECT 1"))).unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"xClose"));
+        assert_eq!(db1.one_column::<i64>("SELECT 1")?, 1);
+
+        // dropped when the connection is closed
+        read_one(&db1)?;
+        db1.close().map_err(|(_, err)| err)?;
+        assert_eq!(db2.one_column::<i64>("SELECT 1")?, 1);
+        Ok(())
+    }
+}
@@ -1,11 +1,23 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::BuildHasher;
+
+use crate::types::Value;
 use crate::{Result, Statement, ToSql};
 
 mod sealed {
+    use crate::{Result, Statement};
+
     /// This trait exists just to ensure that the only impls of `trait Params`
     /// that are allowed are ones in this crate.
     pub trait Sealed {}
+
+    /// Maps of named parameters which can be wrapped in a
+    /// [`ParamsFromMap`](crate::ParamsFromMap).
+    pub trait NamedMap {
+        fn bind_named(self, stmt: &mut Statement<'_>, strict: bool) -> Result<()>;
+    }
 }
-use sealed::Sealed;
+pub(crate) use sealed::{NamedMap, Sealed};
 
 /// Trait used for [sets of parameter][params] passed into SQL
 /// statements/queries.
@@ -34,9 +46,9 @@ use sealed::Sealed;
 /// - For small lists of parameters up to 16 items, they may alternatively be
 ///   passed as a tuple, as in `thing.query((1, "foo"))`.
 ///
-///     This is somewhat inconvenient for a single item, since you need a
-///     weird-looking trailing comma: `thing.query(("example",))`. That case is
-///     perhaps more cleanly expressed as `thing.query(["example"])`.
+///   This is somewhat inconvenient for a single item, since you need a
+///   weird-looking trailing comma: `thing.query(("example",))`. That case is
+///   perhaps more cleanly expressed as `thing.query(["example"])`.
 ///
 /// - Using the [`rusqlite::params!`](crate::params!) macro, e.g.
 ///   `thing.query(rusqlite::params![1, "foo", bar])`. This is mostly useful for
@@ -51,15 +63,15 @@ use sealed::Sealed;
 ///     - a reference to an array of references, as in `thing.query(&["foo",
 ///       "bar", "baz"])` or `thing.query(&[&1i32, &2, &3])`.
 ///
-///         (Note: in this case we don't implement this for slices for coherence
-///         reasons, so it really is only for the "reference to array" types —
-///         hence why the number of parameters must be <= 32 or you need to
-///         reach for `rusqlite::params!`)
+///       (Note: in this case we don't implement this for slices for coherence
+///       reasons, so it really is only for the "reference to array" types —
+///       hence why the number of parameters must be <= 32 or you need to
+///       reach for `rusqlite::params!`)
 ///
-///     Unfortunately, in the current design it's not possible to allow this for
-///     references to arrays of non-references (e.g. `&[1i32, 2, 3]`). Code like
-///     this should instead either use `params!`, an array literal, a `&[&dyn
-///     ToSql]` or if none of those work, [`ParamsFromIter`].
+///   Unfortunately, in the current design it's not possible to allow this for
+///   references to arrays of non-references (e.g. `&[1i32, 2, 3]`). Code like
+///   this should instead either use `params!`, an array literal, a `&[&dyn
+///   ToSql]` or if none of those work, [`ParamsFromIter`].
 ///
 /// - As a slice of `ToSql` trait object references, e.g. `&[&dyn ToSql]`. This
 ///   is mostly useful for passing parameter lists around as arguments without
@@ -115,9 +127,16 @@ use sealed::Sealed;
 ///   `thing.query(&[(":foo", &1i32), (":bar", &2i32)])` or
 ///   `thing.query(&[(":foo", "abc"), (":bar", "def")])`.
 ///
-/// Note: Unbound named parameters will be left to the value they previously
-/// were bound with, falling back to `NULL` for parameters which have never been
-/// bound.
+/// - As a map from names to values: a `&HashMap<&str, &dyn ToSql>`, a
+///   `HashMap<String, Value>` or a reference to one, or a `&BTreeMap<String,
+///   Value>`. The names may be given with or without their prefix. See
+///   [`ParamsFromMap`] for ignoring keys which are not parameters of the
+///   statement.
+///
+/// Note: Every parameter of the statement must be bound, and every name must
+/// be a parameter of the statement; otherwise binding fails with
+/// [`Error::MissingParameters`](crate::Error::MissingParameters), which lists
+/// all of the missing and unexpected names at once.
 ///
 /// ### Example (named)
 ///
@@ -179,6 +198,9 @@ use sealed::Sealed;
 ///
 /// A lot of the considerations here are similar either way, so you should see
 /// the [`ParamsFromIter`] documentation for more info / examples.
+///
+/// Dynamically built sets of named parameters can be passed as a map, as
+/// described above.
 pub trait Params: Sealed {
     // XXX not public api, might not need to expose.
     //
@@ -226,7 +248,7 @@ impl Sealed for &[(&str, &dyn ToSql)] {}
 impl Params for &[(&str, &dyn ToSql)] {
     #[inline]
     fn __bind_in(self, stmt: &mut Statement<'_>) -> Result<()> {
-        stmt.bind_parameters_named(self)
+        stmt.bind_parameters_named(self.iter().copied(), true)
     }
 }
 
@@ -306,7 +328,7 @@ macro_rules! impl_for_array_ref {
         impl<T: ToSql + ?Sized> Sealed for &[(&str, &T); $N] {}
         impl<T: ToSql + ?Sized> Params for &[(&str, &T); $N] {
             fn __bind_in(self, stmt: &mut Statement<'_>) -> Result<()> {
-                stmt.bind_parameters_named(self)
+                stmt.bind_parameters_named(self.iter().copied(), true)
             }
         }
         impl<T: ToSql> Sealed for [T; $N] {}
@@ -456,3 +478,86 @@ where
         stmt.bind_parameters(self.0)
     }
 }
+
+/// Adapter type which binds a map of named parameters, created by the
+/// [`params_from_map`] function.
+///
+/// Each key is bound to the parameter of the same name, which may be written
+/// with a `:`, `@` or `$` prefix in the SQL whether or not the key has one
+/// (see [`Statement::parameter_index`]). Binding fails with
+/// [`Error::MissingParameters`] if a parameter of the statement is not
+/// bound by the map, or, unless the adapter is made lenient with
+/// [`ParamsFromMap::strict`], if a key is not a parameter of the statement.
+///
+/// Maps passed as [`Params`] directly are strict.
+///
+/// ```rust,no_run
+/// use rusqlite::types::Value;
+/// use rusqlite::{params_from_map, Connection, Result};
+/// use std::collections::HashMap;
+///
+/// fn insert(conn: &Connection, row: &HashMap<String, Value>) -> Result<usize> {
+///     // `row` may hold more columns than the ones inserted
+///     conn.execute(
+///         "INSERT INTO person (name, age) VALUES (:name, :age)",
+///         params_from_map(row).strict(false),
+///     )
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ParamsFromMap<M> {
+    map: M,
+    strict: bool,
+}
+
+/// Constructor function for a [`ParamsFromMap`]. See its documentation for
+/// more.
+#[inline]
+pub fn params_from_map<M: NamedMap>(map: M) -> ParamsFromMap<M> {
+    ParamsFromMap { map, strict: true }
+}
+
+impl<M> ParamsFromMap<M> {
+    /// Sets whether binding fails when a key is not a parameter of the
+    /// statement. Parameters of the statement missing from the map are always
+    /// an error.
+    #[inline]
+    #[must_use]
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+}
+
+impl<M: NamedMap> Sealed for ParamsFromMap<M> {}
+impl<M: NamedMap> Params for ParamsFromMap<M> {
+    #[inline]
+    fn __bind_in(self, stmt: &mut Statement<'_>) -> Result<()> {
+        self.map.bind_named(stmt, self.strict)
+    }
+}
+
+macro_rules! impl_for_named_map {
+    ($(($map:ty, $($gen:tt)*)),* $(,)?) => {$(
+        impl<$($gen)*> NamedMap for $map {
+            #[inline]
+            fn bind_named(self, stmt: &mut Statement<'_>, strict: bool) -> Result<()> {
+                stmt.bind_parameters_named(self.iter().map(|(k, v)| (&k[..], v)), strict)
+            }
+        }
+        impl<$($gen)*> Sealed for $map {}
+        impl<$($gen)*> Params for $map {
+            #[inline]
+            fn __bind_in(self, stmt: &mut Statement<'_>) -> Result<()> {
+                self.bind_named(stmt, true)
+            }
+        }
+    )*};
+}
+
+impl_for_named_map!(
+    (&HashMap<&str, &dyn ToSql, S>, S: BuildHasher),
+    (&HashMap<String, Value, S>, S: BuildHasher),
+    (HashMap<String, Value, S>, S: BuildHasher),
+    (&BTreeMap<String, Value>,),
+);
@@ -0,0 +1,41 @@
+//! Passing Rust values through SQL with the
+//! [pointer passing interfaces](https://sqlite.org/bindptr.html).
+//!
+//! SQLite only hands a pointer back to code asking for the same type string,
+//! and SQL sees it as NULL. The pointer is to a boxed `Arc<dyn Any>`, so that
+//! reading it back as another Rust type than the one it was made from yields
+//! nothing too.
+
+use std::any::Any;
+#[cfg(any(feature = "functions", feature = "vtab"))]
+use std::ffi::CStr;
+use std::os::raw::c_void;
+use std::sync::Arc;
+
+#[cfg(any(feature = "functions", feature = "vtab"))]
+use crate::ffi;
+
+type Pointer = Arc<dyn Any + Send + Sync>;
+
+pub(crate) fn into_raw<T: Any + Send + Sync>(value: Arc<T>) -> *mut c_void {
+    let value: Pointer = value;
+    Box::into_raw(Box::new(value)).cast()
+}
+
+pub(crate) unsafe extern "C" fn free_pointer(p: *mut c_void) {
+    drop(Box::from_raw(p as *mut Pointer));
+}
+
+// The value of `value` if it is a pointer of type `type_name` to a `T`.
+#[cfg(any(feature = "functions", feature = "vtab"))]
+pub(crate) unsafe fn from_value<T: Any + Send + Sync>(
+    value: *mut ffi::sqlite3_value,
+    type_name: &'static CStr,
+) -> Option<Arc<T>> {
+    let p = ffi::sqlite3_value_pointer(value, type_name.as_ptr());
+    if p.is_null() {
+        return None;
+    }
+    let pointer = &*(p as *const Pointer);
+    pointer.clone().downcast().ok()
+}
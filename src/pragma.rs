@@ -63,15 +63,8 @@ impl Sql {
         let value = match value {
             ToSqlOutput::Borrowed(v) => v,
             ToSqlOutput::Owned(ref v) => ValueRef::from(v),
-            #[cfg(feature = "blob")]
-            ToSqlOutput::ZeroBlob(_) => {
-                return Err(Error::SqliteFailure(
-                    ffi::Error::new(ffi::SQLITE_MISUSE),
-                    Some(format!("Unsupported value \"{value:?}\"")),
-                ));
-            }
-            #[cfg(feature = "array")]
-            ToSqlOutput::Array(_) => {
+            ToSqlOutput::Inline(ref t) => ValueRef::Text(t.as_str().as_bytes()),
+            _ => {
                 return Err(Error::SqliteFailure(
                     ffi::Error::new(ffi::SQLITE_MISUSE),
                     Some(format!("Unsupported value \"{value:?}\"")),
@@ -0,0 +1,226 @@
+use std::fmt;
+
+use crate::{Connection, Error, Params, Result, Rows, Statement};
+
+/// One step of a [`QueryPlan`], from a row of `EXPLAIN QUERY PLAN`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct QueryPlanNode {
+    /// Identifier of the node, unique within the plan.
+    pub id: i64,
+    /// Identifier of the parent node, or 0 for a top-level node.
+    pub parent: i64,
+    /// Description of the step, like `SCAN t` or
+    /// `SEARCH t USING INDEX t_x (x=?)`. The wording is not stable across
+    /// SQLite versions.
+    pub detail: String,
+}
+
+impl QueryPlanNode {
+    fn is_scan(&self) -> bool {
+        self.detail.starts_with("SCAN ") && self.detail != "SCAN CONSTANT ROW"
+    }
+
+    fn uses_index(&self, name: &str) -> bool {
+        let mut words = self.detail.split(' ');
+        while let Some(word) = words.next() {
+            if word == "INDEX" {
+                return words.next() == Some(name);
+            }
+        }
+        false
+    }
+}
+
+/// The plan SQLite chose for a query, as a tree of [`QueryPlanNode`]s built
+/// from the output of `EXPLAIN QUERY PLAN`.
+///
+/// Created by [`Connection::explain_query_plan`] or
+/// [`Statement::query_plan`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueryPlan {
+    nodes: Vec<QueryPlanNode>,
+}
+
+impl QueryPlan {
+    /// Returns every node of the plan, parents before their children, in the
+    /// order SQLite reports them.
+    #[inline]
+    #[must_use]
+    pub fn nodes(&self) -> &[QueryPlanNode] {
+        &self.nodes
+    }
+
+    /// Returns the top-level nodes of the plan.
+    pub fn roots(&self) -> impl Iterator<Item = &QueryPlanNode> {
+        self.children(0)
+    }
+
+    /// Returns the nodes whose parent is the node `id`.
+    pub fn children(&self, id: i64) -> impl Iterator<Item = &QueryPlanNode> {
+        self.nodes.iter().filter(move |node| node.parent == id)
+    }
+
+    /// Returns true if a step of the plan reads the index `name`.
+    #[must_use]
+    pub fn uses_index(&self, name: &str) -> bool {
+        self.nodes.iter().any(|node| node.uses_index(name))
+    }
+
+    /// Returns true if a step of the plan scans a whole table, index or
+    /// subquery rather than searching it.
+    #[must_use]
+    pub fn has_full_scan(&self) -> bool {
+        self.nodes.iter().any(QueryPlanNode::is_scan)
+    }
+
+    fn from_rows(mut rows: Rows<'_>) -> Result<QueryPlan> {
+        let mut nodes = Vec::new();
+        while let Some(row) = rows.next()? {
+            nodes.push(QueryPlanNode {
+                id: row.get(0)?,
+                parent: row.get(1)?,
+                detail: row.get(3)?,
+            });
+        }
+        Ok(QueryPlan { nodes })
+    }
+
+    fn fmt_children(&self, f: &mut fmt::Formatter<'_>, id: i64, indent: usize) -> fmt::Result {
+        for node in self.children(id) {
+            writeln!(f, "{:indent$}{}", "", node.detail, indent = indent)?;
+            self.fmt_children(f, node.id, indent + 2)?;
+        }
+        Ok(())
+    }
+}
+
+/// Displays the plan as an indented tree, one node per line.
+impl fmt::Display for QueryPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_children(f, 0, 0)
+    }
+}
+
+impl Connection {
+    /// Returns the plan SQLite chooses for the statement `sql` with `params`
+    /// bound, without running it.
+    ///
+    /// Bound values can change the plan, as the bundled SQLite is built with
+    /// `SQLITE_ENABLE_STAT4`.
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn is_indexed(conn: &Connection) -> Result<bool> {
+    ///     let plan = conn.explain_query_plan("SELECT * FROM person WHERE name = ?1", ["Tom"])?;
+    ///     Ok(!plan.has_full_scan())
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be prepared, or with
+    /// [`Error::MultipleStatement`] if it holds more than one statement.
+    pub fn explain_query_plan<P: Params>(&self, sql: &str, params: P) -> Result<QueryPlan> {
+        let mut stmt = self.prepare_query_plan(sql)?;
+        let plan = QueryPlan::from_rows(stmt.query(params)?);
+        plan
+    }
+
+    fn prepare_query_plan(&self, sql: &str) -> Result<Statement<'_>> {
+        let sql = format!("EXPLAIN QUERY PLAN {sql}");
+        let stmt = self.prepare(&sql)?;
+        let tail = stmt.stmt.tail();
+        if tail != 0 {
+            // the rest may only be whitespace, comments or semicolons
+            match self.prepare(&sql[tail..]) {
+                Ok(next) if next.stmt.is_null() => {}
+                _ => return Err(Error::MultipleStatement),
+            }
+        }
+        Ok(stmt)
+    }
+}
+
+impl Statement<'_> {
+    /// Returns the plan SQLite chooses for this statement.
+    ///
+    /// The values bound to this statement are not used, as SQLite cannot
+    /// report them: the plan is made with every parameter `NULL`. Use
+    /// [`Connection::explain_query_plan`] to pass values.
+    pub fn query_plan(&self) -> Result<QueryPlan> {
+        let sql = match self.stmt.sql() {
+            Some(sql) => sql.to_str()?,
+            None => return Ok(QueryPlan::default()),
+        };
+        let mut stmt = self.conn.prepare_query_plan(sql)?;
+        let plan = QueryPlan::from_rows(stmt.raw_query());
+        plan
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Connection, Error, Result};
+
+    #[test]
+    fn test_query_plan() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE t(x INTEGER, y TEXT)")?;
+        let sql = "SELECT y FROM t WHERE x = ?1";
+
+        let plan = db.explain_query_plan(sql, [1])?;
+        assert!(plan.has_full_scan());
+        assert!(!plan.uses_index("t_x"));
+
+        db.execute_batch("CREATE INDEX t_x ON t(x)")?;
+        let plan = db.explain_query_plan(sql, [1])?;
+        assert!(!plan.has_full_scan());
+        assert!(plan.uses_index("t_x"));
+        assert!(!plan.uses_index("t"));
+        assert_eq!(plan.roots().count(), 1);
+
+        let stmt = db.prepare(sql)?;
+        assert_eq!(stmt.query_plan()?, plan);
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_plan_tree() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE t(x INTEGER); CREATE TABLE u(x INTEGER);")?;
+        let plan =
+            db.explain_query_plan("SELECT x FROM t UNION SELECT x FROM u; -- both tables", [])?;
+        let roots: Vec<_> = plan.roots().collect();
+        assert_eq!(roots.len(), 1);
+        let scans: Vec<&str> = plan
+            .nodes()
+            .iter()
+            .filter(|node| node.parent != 0)
+            .map(|node| node.detail.as_str())
+            .filter(|detail| detail.starts_with("SCAN"))
+            .collect();
+        assert_eq!(scans, ["SCAN t", "SCAN u"]);
+        assert!(plan
+            .nodes()
+            .iter()
+            .all(|node| node.parent == 0 || plan.nodes().iter().any(|p| p.id == node.parent)));
+        assert_eq!(
+            plan.to_string()
+                .lines()
+                .filter(|l| l.starts_with("  "))
+                .count(),
+            plan.nodes().len() - 1
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_plan_multiple_statements() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        let err = db.explain_query_plan("SELECT 1; SELECT 2", []).unwrap_err();
+        assert_eq!(err, Error::MultipleStatement);
+        db.explain_query_plan("SELECT 1;  ", [])?;
+        Ok(())
+    }
+}
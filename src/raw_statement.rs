@@ -1,9 +1,10 @@
 use super::ffi;
 use super::StatementStatus;
-use crate::util::ParamIndexCache;
+use crate::panic_policy::{keep_caught_panic, PanicSlot};
 use crate::util::SqliteMallocString;
+use crate::util::{ColumnIndexCache, ParamIndexCache};
 use std::ffi::CStr;
-use std::os::raw::c_int;
+use std::os::raw::{c_int, c_uint};
 use std::ptr;
 use std::sync::Arc;
 
@@ -14,6 +15,8 @@ pub struct RawStatement {
     tail: usize,
     // Cached indices of named parameters, computed on the fly.
     cache: ParamIndexCache,
+    // Cached indices of columns looked up by name, computed on the fly.
+    column_cache: ColumnIndexCache,
     // Cached SQL (trimmed) that we use as the key when we're in the statement
     // cache. This is None for statements which didn't come from the statement
     // cache.
@@ -25,6 +28,11 @@ pub struct RawStatement {
     // One example of a case where the result of `sqlite_sql` and the value in
     // `statement_cache_key` might differ is if the statement has a `tail`.
     statement_cache_key: Option<Arc<str>>,
+    // The `PrepFlags` bits the statement was prepared with.
+    prep_flags: c_uint,
+    // Where panics caught while resetting or finalizing the statement are kept
+    // for its connection.
+    pending_panic: Option<PanicSlot>,
 }
 
 impl RawStatement {
@@ -34,7 +42,10 @@ impl RawStatement {
             ptr: stmt,
             tail,
             cache: ParamIndexCache::default(),
+            column_cache: ColumnIndexCache::default(),
             statement_cache_key: None,
+            prep_flags: 0,
+            pending_panic: None,
         }
     }
 
@@ -53,11 +64,38 @@ impl RawStatement {
         self.statement_cache_key.clone()
     }
 
+    #[inline]
+    pub(crate) fn set_prep_flags(&mut self, flags: c_uint) {
+        self.prep_flags = flags;
+    }
+
+    #[inline]
+    pub(crate) fn set_panic_slot(&mut self, slot: PanicSlot) {
+        self.pending_panic = Some(slot);
+    }
+
+    #[inline]
+    fn keep_caught_panic(&self) {
+        if let Some(ref slot) = self.pending_panic {
+            keep_caught_panic(slot);
+        }
+    }
+
+    #[inline]
+    pub(crate) fn prep_flags(&self) -> c_uint {
+        self.prep_flags
+    }
+
     #[inline]
     pub unsafe fn ptr(&self) -> *mut ffi::sqlite3_stmt {
         self.ptr
     }
 
+    #[inline]
+    pub(crate) fn column_index_cache(&self) -> &ColumnIndexCache {
+        &self.column_cache
+    }
+
     #[inline]
     pub fn column_count(&self) -> usize {
         // Note: Can't cache this as it changes if the schema is altered.
@@ -69,6 +107,16 @@ impl RawStatement {
         unsafe { ffi::sqlite3_column_type(self.ptr, idx as c_int) }
     }
 
+    #[inline]
+    pub fn column_int64(&self, idx: usize) -> i64 {
+        unsafe { ffi::sqlite3_column_int64(self.ptr, idx as c_int) }
+    }
+
+    #[inline]
+    pub fn column_double(&self, idx: usize) -> f64 {
+        unsafe { ffi::sqlite3_column_double(self.ptr, idx as c_int) }
+    }
+
     #[inline]
     #[cfg(feature = "column_decltype")]
     pub fn column_decltype(&self, idx: usize) -> Option<&CStr> {
@@ -82,6 +130,25 @@ impl RawStatement {
         }
     }
 
+    #[cfg(feature = "column_metadata")]
+    pub fn column_origin(&self, idx: usize) -> Option<(&CStr, &CStr, &CStr)> {
+        let idx = idx as c_int;
+        unsafe {
+            let database = ffi::sqlite3_column_database_name(self.ptr, idx);
+            let table = ffi::sqlite3_column_table_name(self.ptr, idx);
+            let column = ffi::sqlite3_column_origin_name(self.ptr, idx);
+            if database.is_null() || table.is_null() || column.is_null() {
+                None
+            } else {
+                Some((
+                    CStr::from_ptr(database),
+                    CStr::from_ptr(table),
+                    CStr::from_ptr(column),
+                ))
+            }
+        }
+    }
+
     #[inline]
     pub fn column_name(&self, idx: usize) -> Option<&CStr> {
         let idx = idx as c_int;
@@ -106,29 +173,30 @@ impl RawStatement {
         unsafe { ffi::sqlite3_step(self.ptr) }
     }
 
+    // Fails with `Error::UnlockNotifyDeadlock` if waiting for the lock would
+    // deadlock.
     #[cfg(feature = "unlock_notify")]
-    pub fn step(&self) -> c_int {
+    pub fn step(&self, timeout: Option<std::time::Duration>) -> crate::Result<c_int> {
         use crate::unlock_notify;
         let mut db = ptr::null_mut::<ffi::sqlite3>();
         loop {
             unsafe {
-                let mut rc = ffi::sqlite3_step(self.ptr);
+                let rc = ffi::sqlite3_step(self.ptr);
                 // Bail out early for success and errors unrelated to locking. We
                 // still need check `is_locked` after this, but checking now lets us
                 // avoid one or two (admittedly cheap) calls into SQLite that we
                 // don't need to make.
                 if (rc & 0xff) != ffi::SQLITE_LOCKED {
-                    break rc;
+                    break Ok(rc);
                 }
                 if db.is_null() {
                     db = ffi::sqlite3_db_handle(self.ptr);
                 }
                 if !unlock_notify::is_locked(db, rc) {
-                    break rc;
+                    break Ok(rc);
                 }
-                rc = unlock_notify::wait_for_unlock_notify(db);
-                if rc != ffi::SQLITE_OK {
-                    break rc;
+                if !unlock_notify::wait_for_unlock_notify(db, timeout)? {
+                    break Ok(rc);
                 }
                 self.reset();
             }
@@ -137,7 +205,9 @@ impl RawStatement {
 
     #[inline]
     pub fn reset(&self) -> c_int {
-        unsafe { ffi::sqlite3_reset(self.ptr) }
+        let r = unsafe { ffi::sqlite3_reset(self.ptr) };
+        self.keep_caught_panic();
+        r
     }
 
     #[inline]
@@ -193,11 +263,11 @@ impl RawStatement {
     fn finalize_(&mut self) -> c_int {
         let r = unsafe { ffi::sqlite3_finalize(self.ptr) };
         self.ptr = ptr::null_mut();
+        self.keep_caught_panic();
         r
     }
 
     // does not work for PRAGMA
-    #[cfg(feature = "extra_check")]
     #[inline]
     pub fn readonly(&self) -> bool {
         unsafe { ffi::sqlite3_stmt_readonly(self.ptr) != 0 }
@@ -231,7 +301,31 @@ impl RawStatement {
         unsafe { ffi::sqlite3_stmt_isexplain(self.ptr) }
     }
 
-    // TODO sqlite3_normalized_sql (https://sqlite.org/c3ref/expanded_sql.html) // 3.27.0 + SQLITE_ENABLE_NORMALIZE
+    /// Reads the `op` metric of loop `idx` into `out`, which must have the
+    /// type SQLite writes for `op`. Returns false if there is no such loop.
+    #[cfg(feature = "scanstatus")] // SQLITE_ENABLE_STMT_SCANSTATUS
+    pub unsafe fn scan_status<T>(&self, idx: usize, op: c_int, out: &mut T) -> bool {
+        ffi::sqlite3_stmt_scanstatus(self.ptr, idx as c_int, op, (out as *mut T).cast()) == 0
+    }
+
+    #[inline]
+    #[cfg(feature = "scanstatus")] // SQLITE_ENABLE_STMT_SCANSTATUS
+    pub fn scan_status_reset(&self) {
+        unsafe { ffi::sqlite3_stmt_scanstatus_reset(self.ptr) }
+    }
+
+    #[inline]
+    #[cfg(feature = "normalize")] // 3.27.0 + SQLITE_ENABLE_NORMALIZE
+    pub fn normalized_sql(&self) -> Option<&CStr> {
+        unsafe {
+            let sql = ffi::sqlite3_normalized_sql(self.ptr);
+            if sql.is_null() {
+                None
+            } else {
+                Some(CStr::from_ptr(sql))
+            }
+        }
+    }
 }
 
 impl Drop for RawStatement {
@@ -0,0 +1,237 @@
+//! Opening databases on read-only filesystems
+//!
+//! Reading a database in WAL mode needs its `-shm` file, which SQLite creates
+//! next to it when missing, so a plain read-only open fails on a read-only
+//! filesystem even though nothing is written.
+//! [`Connection::open_readonly_resilient`] then retries with the URI
+//! parameters SQLite provides for such files.
+
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::path::Path;
+
+use crate::{ffi, Connection, Error, ErrorCode, OpenFlags, Result};
+
+/// The fallbacks allowed by [`Connection::open_readonly_resilient`] when a
+/// plain read-only open fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ReadOnlyPolicy {
+    /// Do not retry.
+    Strict,
+    /// Retry without file locking (`nolock=1`), which is only safe if no
+    /// other process writes to the database.
+    NoLock,
+    /// As `NoLock`, then retry asserting that the file cannot change
+    /// (`immutable=1`), which also skips the WAL: frames not yet
+    /// checkpointed are not read.
+    Immutable,
+}
+
+/// How [`Connection::open_readonly_resilient`] opened the database.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OpenOutcome {
+    /// A plain read-only open.
+    ReadOnly,
+    /// A read-only open without file locking.
+    NoLock,
+    /// A read-only open of an immutable file.
+    Immutable,
+}
+
+impl Connection {
+    /// Opens the database at `path` read-only, retrying with the fallbacks
+    /// allowed by `policy` if SQLite cannot open or create the files it needs
+    /// alongside it, e.g. the `-shm` file of a database in WAL mode on a
+    /// read-only filesystem.
+    ///
+    /// Each attempt reads the schema, as SQLite only opens those files on
+    /// first use. Returns the connection and the attempt that succeeded.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `path` is not valid UTF-8, or with the error of
+    /// the last attempt if none succeeds.
+    pub fn open_readonly_resilient<P: AsRef<Path>>(
+        path: P,
+        policy: ReadOnlyPolicy,
+    ) -> Result<(Connection, OpenOutcome)> {
+        let path = path.as_ref();
+        let mut err = match open_probed(path, None) {
+            Ok(db) => return Ok((db, OpenOutcome::ReadOnly)),
+            Err(err) => err,
+        };
+        let fallbacks: &[(OpenOutcome, &str)] = match policy {
+            ReadOnlyPolicy::Strict => &[],
+            ReadOnlyPolicy::NoLock => &[(OpenOutcome::NoLock, "nolock=1")],
+            ReadOnlyPolicy::Immutable => &[
+                (OpenOutcome::NoLock, "nolock=1"),
+                (OpenOutcome::Immutable, "immutable=1"),
+            ],
+        };
+        for &(outcome, param) in fallbacks {
+            if !is_readonly_failure(&err) {
+                break;
+            }
+            err = match open_probed(path, Some(param)) {
+                Ok(db) => return Ok((db, outcome)),
+                Err(err) => err,
+            };
+        }
+        Err(err)
+    }
+
+    /// Returns whether the database at `path` is in WAL mode, so that
+    /// reading it needs a `-shm` file unless opened as immutable. A missing
+    /// or empty file is not.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the file cannot be read.
+    pub fn wal_requires_shm<P: AsRef<Path>>(path: P) -> Result<bool> {
+        let path = path.as_ref();
+        let cannot_open = |err: std::io::Error| {
+            Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_CANTOPEN),
+                Some(format!("{}: {err}", path.display())),
+            )
+        };
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(cannot_open(err)),
+        };
+        // The file format write and read versions are 2 in WAL mode.
+        let mut header = [0; 20];
+        match file.read_exact(&mut header) {
+            Ok(()) => Ok(header[18] == 2 && header[19] == 2),
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => Ok(false),
+            Err(err) => Err(cannot_open(err)),
+        }
+    }
+}
+
+fn open_probed(path: &Path, param: Option<&str>) -> Result<Connection> {
+    let flags = OpenFlags::SQLITE_OPEN_READ_ONLY
+        | OpenFlags::SQLITE_OPEN_URI
+        | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    let db = match param {
+        Some(param) => {
+            let path = path
+                .to_str()
+                .ok_or_else(|| Error::InvalidPath(path.to_owned()))?;
+            Connection::open_with_flags(format!("file:{}?{param}", uri_path(path)), flags)?
+        }
+        None => Connection::open_with_flags(path, flags)?,
+    };
+    db.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))?;
+    Ok(db)
+}
+
+// SQLite fails with `SQLITE_CANTOPEN` when it cannot create the `-shm` file,
+// and with `SQLITE_READONLY_*` when it cannot initialize or recover it.
+fn is_readonly_failure(err: &Error) -> bool {
+    matches!(
+        err,
+        Error::SqliteFailure(
+            ffi::Error {
+                code: ErrorCode::CannotOpen | ErrorCode::ReadOnly,
+                ..
+            },
+            _
+        )
+    )
+}
+
+fn uri_path(path: &str) -> String {
+    let mut uri = String::with_capacity(path.len());
+    for c in path.chars() {
+        match c {
+            '%' | '?' | '#' => uri.push_str(&format!("%{:02X}", c as u32)),
+            _ => uri.push(c),
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_readonly_failure, uri_path, OpenOutcome, ReadOnlyPolicy};
+    use crate::{Connection, ErrorCode, Result};
+    use std::path::Path;
+
+    fn create_wal_db(path: &Path) -> Result<()> {
+        let db = Connection::open(path)?;
+        let mode: String = db.one_column("PRAGMA journal_mode = WAL")?;
+        assert_eq!(mode, "wal");
+        db.execute_batch("CREATE TABLE t(x); INSERT INTO t VALUES (1);")
+    }
+
+    #[test]
+    fn test_uri_path() {
+        assert_eq!(uri_path("/tmp/a?b#c%d.db"), "/tmp/a%3Fb%23c%25d.db");
+    }
+
+    #[test]
+    fn test_wal_requires_shm() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+        assert!(!Connection::wal_requires_shm(&path)?);
+        let db = Connection::open(&path)?;
+        db.execute_batch("CREATE TABLE t(x)")?;
+        assert!(!Connection::wal_requires_shm(&path)?);
+        let mode: String = db.one_column("PRAGMA journal_mode = WAL")?;
+        assert_eq!(mode, "wal");
+        assert!(Connection::wal_requires_shm(&path)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_readonly_resilient() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+        create_wal_db(&path)?;
+
+        let (db, outcome) = Connection::open_readonly_resilient(&path, ReadOnlyPolicy::Strict)?;
+        assert_eq!(outcome, OpenOutcome::ReadOnly);
+        let count: i64 = db.one_column("SELECT count(*) FROM t")?;
+        assert_eq!(count, 1);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_open_readonly_resilient_readonly_dir() -> Result<()> {
+        use std::fs::{self, Permissions};
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+        create_wal_db(&path)?;
+        assert!(Connection::wal_requires_shm(&path)?);
+
+        fs::set_permissions(temp_dir.path(), Permissions::from_mode(0o555)).unwrap();
+        let writable = fs::write(temp_dir.path().join("probe"), b"").is_ok();
+        let result = (|| {
+            if writable {
+                // e.g. running as root: the directory is not read-only.
+                return Ok(());
+            }
+            let err =
+                Connection::open_readonly_resilient(&path, ReadOnlyPolicy::Strict).unwrap_err();
+            assert!(is_readonly_failure(&err), "{:?}", err);
+
+            let (db, outcome) =
+                Connection::open_readonly_resilient(&path, ReadOnlyPolicy::Immutable)?;
+            assert_eq!(outcome, OpenOutcome::Immutable);
+            let count: i64 = db.one_column("SELECT count(*) FROM t")?;
+            assert_eq!(count, 1);
+            let err = db.execute("INSERT INTO t VALUES (2)", []).unwrap_err();
+            assert_eq!(err.sqlite_error_code(), Some(ErrorCode::ReadOnly));
+            Ok(())
+        })();
+        fs::set_permissions(temp_dir.path(), Permissions::from_mode(0o755)).unwrap();
+        result
+    }
+}
@@ -0,0 +1,167 @@
+//! The `REGEXP` operator, backed by the [regex](https://docs.rs/regex) crate.
+//!
+//! SQLite parses `X REGEXP Y` but leaves it to the application to define the
+//! `regexp(Y, X)` function implementing it.
+//! [`Connection::register_regexp_function`] installs one using the syntax of
+//! the regex crate. The pattern is compiled once per statement when it is
+//! constant, being kept as
+//! [auxiliary data](https://sqlite.org/c3ref/get_auxdata.html).
+
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use regex::Regex;
+
+use crate::functions::{Context, FunctionFlags};
+use crate::types::ValueRef;
+use crate::{Connection, Error, Result};
+
+impl Connection {
+    /// Register the `regexp` function, so that `text REGEXP pattern` is true
+    /// if `pattern` matches anywhere in `text`, using the
+    /// [syntax](https://docs.rs/regex/latest/regex/#syntax) of the regex
+    /// crate. The result is NULL if either is NULL, and numbers are matched
+    /// as text.
+    ///
+    /// The function is deterministic and innocuous, so it may be used in
+    /// indexes, views and triggers.
+    ///
+    /// ```rust
+    /// # use rusqlite::{Connection, Result};
+    /// fn count_products(conn: &Connection) -> Result<i64> {
+    ///     conn.register_regexp_function()?;
+    ///     conn.query_row(
+    ///         r"SELECT count(*) FROM product WHERE sku REGEXP '^[A-Z]{3}-\d+$'",
+    ///         [],
+    ///         |row| row.get(0),
+    ///     )
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the function could not be registered. Using an
+    /// invalid pattern fails the statement with the error of the regex
+    /// crate.
+    pub fn register_regexp_function(&self) -> Result<()> {
+        self.create_scalar_function(
+            "regexp",
+            2,
+            FunctionFlags::SQLITE_UTF8
+                | FunctionFlags::SQLITE_DETERMINISTIC
+                | FunctionFlags::SQLITE_INNOCUOUS,
+            regexp,
+        )
+    }
+}
+
+fn regexp(ctx: &Context<'_>) -> Result<Option<bool>> {
+    if ctx.get_raw(0) == ValueRef::Null {
+        return Ok(None);
+    }
+    let text = match as_text(ctx.get_raw(1))? {
+        Some(text) => text,
+        None => return Ok(None),
+    };
+    let re: Arc<Regex> = ctx.get_or_create_aux(0, |pattern| -> Result<_> {
+        let pattern = as_text(pattern)?.unwrap_or_default();
+        #[cfg(test)]
+        test::COMPILED.with(|c| c.set(c.get() + 1));
+        Regex::new(&pattern).map_err(|e| Error::UserFunctionError(e.into()))
+    })?;
+    Ok(Some(re.is_match(&text)))
+}
+
+fn as_text(value: ValueRef<'_>) -> Result<Option<Cow<'_, str>>> {
+    Ok(Some(match value {
+        ValueRef::Null => return Ok(None),
+        ValueRef::Integer(i) => Cow::Owned(i.to_string()),
+        ValueRef::Real(f) => Cow::Owned(f.to_string()),
+        ValueRef::Text(t) | ValueRef::Blob(t) => {
+            Cow::Borrowed(std::str::from_utf8(t).map_err(|e| Error::UserFunctionError(e.into()))?)
+        }
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+
+    use crate::{Connection, Result};
+
+    thread_local! {
+        // The number of patterns compiled on this thread.
+        pub(super) static COMPILED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn db_with_skus() -> Result<Connection> {
+        let db = Connection::open_in_memory()?;
+        db.register_regexp_function()?;
+        db.execute_batch(
+            "CREATE TABLE foo (x TEXT);
+             INSERT INTO foo VALUES ('foo1'), ('foo42'), ('foo'), ('xfoo1'), ('foo1x'), (NULL);",
+        )?;
+        Ok(db)
+    }
+
+    #[test]
+    fn test_regexp() -> Result<()> {
+        let db = db_with_skus()?;
+        let mut stmt = db.prepare(r"SELECT x FROM foo WHERE x REGEXP '^foo\d+$' ORDER BY x")?;
+        let rows: Vec<String> = stmt.query_map([], |r| r.get(0))?.collect::<Result<_>>()?;
+        assert_eq!(rows, ["foo1", "foo42"]);
+
+        // unanchored patterns match anywhere, numbers are matched as text
+        let m: bool = db.one_column("SELECT 'xfoo1' REGEXP 'foo'")?;
+        assert!(m);
+        let m: bool = db.one_column(r"SELECT 12.5 REGEXP '^\d+\.5$'")?;
+        assert!(m);
+        Ok(())
+    }
+
+    #[test]
+    fn test_regexp_null() -> Result<()> {
+        let db = db_with_skus()?;
+        let m: Option<bool> = db.one_column("SELECT NULL REGEXP 'foo'")?;
+        assert_eq!(m, None);
+        let m: Option<bool> = db.one_column("SELECT 'foo' REGEXP NULL")?;
+        assert_eq!(m, None);
+        // even an invalid pattern yields NULL on NULL text
+        let m: Option<bool> = db.one_column("SELECT NULL REGEXP '('")?;
+        assert_eq!(m, None);
+        let n: i64 = db.one_column("SELECT count(*) FROM foo WHERE NOT (x REGEXP '^foo')")?;
+        assert_eq!(n, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_regexp_invalid_pattern() -> Result<()> {
+        let db = db_with_skus()?;
+        let err = db
+            .one_column::<bool>("SELECT count(*) FROM foo WHERE x REGEXP 'foo('")
+            .unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("unclosed group"), "{}", msg);
+        Ok(())
+    }
+
+    #[test]
+    fn test_regexp_compiled_once() -> Result<()> {
+        let db = db_with_skus()?;
+        db.execute_batch(
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 100)
+             INSERT INTO foo SELECT 'foo' || i FROM n;",
+        )?;
+        let before = COMPILED.with(Cell::get);
+        let n: i64 = db.one_column(r"SELECT count(*) FROM foo WHERE x REGEXP '^foo\d+$'")?;
+        assert_eq!(n, 102);
+        assert_eq!(COMPILED.with(Cell::get) - before, 1);
+
+        // a pattern varying per row is compiled for each row
+        let before = COMPILED.with(Cell::get);
+        let n: i64 = db.one_column("SELECT count(*) FROM foo WHERE x REGEXP x")?;
+        assert_eq!(n, 105);
+        assert_eq!(COMPILED.with(Cell::get) - before, 105);
+        Ok(())
+    }
+}
@@ -1,9 +1,11 @@
 use fallible_iterator::FallibleIterator;
 use fallible_streaming_iterator::FallibleStreamingIterator;
+use std::collections::HashMap;
 use std::convert;
 
-use super::{Error, Result, Statement};
-use crate::types::{FromSql, FromSqlError, ValueRef};
+use super::{Error, QueryError, Result, Statement};
+use crate::ffi;
+use crate::types::{FromSql, FromSqlError, FromSqlRef, Value, ValueRef};
 
 /// An handle for the resulting rows of a query.
 #[must_use = "Rows is lazy and will do nothing unless consumed"]
@@ -68,6 +70,22 @@ impl<'stmt> Rows<'stmt> {
         MappedRows { rows: self, map: f }
     }
 
+    /// Converts this `Rows` into an iterator over the values of every column
+    /// of each row, as returned by [`Row::to_vec`].
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn values(self) -> MappedRows<'stmt, fn(&Row<'_>) -> Result<Vec<Value>>> {
+        self.mapped(|row| Ok(row.to_vec()))
+    }
+
+    /// Converts this `Rows` into an iterator over the `(name, value)` pairs
+    /// of each row, as returned by [`Row::to_named_vec`].
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn named_values(self) -> MappedRows<'stmt, fn(&Row<'_>) -> Result<Vec<(String, Value)>>> {
+        self.mapped(|row| Ok(row.to_named_vec()))
+    }
+
     /// Map over this `Rows` with a fallible function, converting it to a
     /// [`AndThenRows`], which implements `Iterator` (instead of
     /// `FallibleStreamingIterator`).
@@ -79,6 +97,116 @@ impl<'stmt> Rows<'stmt> {
         AndThenRows { rows: self, map: f }
     }
 
+    /// Map the only row of this `Rows` with `f`, checking that there is
+    /// exactly one.
+    ///
+    /// Returns [`QueryError::NotFound`] if there are no rows, and
+    /// `QueryError::More(Error::QueryReturnedMoreThanOneRow)` if there is
+    /// more than one (the extra row is stepped to, but not mapped).
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, QueryError};
+    /// fn user_name(conn: &Connection, email: &str) -> Result<String, QueryError> {
+    ///     let mut stmt = conn.prepare("SELECT name FROM users WHERE email = ?1")?;
+    ///     let mut rows = stmt.query([email])?;
+    ///     rows.expect_single(|row| row.get(0))
+    /// }
+    /// ```
+    pub fn expect_single<T, F>(&mut self, f: F) -> Result<T, QueryError>
+    where
+        F: FnOnce(&Row<'_>) -> Result<T>,
+    {
+        let value = match self.next()? {
+            Some(row) => f(row)?,
+            None => return Err(QueryError::NotFound),
+        };
+        match self.next()? {
+            Some(_) => Err(QueryError::More(Error::QueryReturnedMoreThanOneRow)),
+            None => Ok(value),
+        }
+    }
+
+    /// Converts this `Rows` into a [`PeekableRows`], which can look at the
+    /// next row without consuming it.
+    #[inline]
+    pub fn peekable(self) -> PeekableRows<'stmt> {
+        PeekableRows {
+            rows: self,
+            peeked: false,
+        }
+    }
+
+    /// Splits the rows into runs of adjacent rows with equal values in the
+    /// `key` column, and calls `f` once per run with the key and a
+    /// [`GroupRows`] over the rows of the run. Returns the results of `f`, in
+    /// order.
+    ///
+    /// Rows of a run which `f` does not read are skipped. As with `GROUP BY`,
+    /// the query should be ordered by the key column for each key to make a
+    /// single run.
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// struct Author {
+    ///     name: String,
+    ///     books: Vec<String>,
+    /// }
+    ///
+    /// fn authors(conn: &Connection) -> Result<Vec<Author>> {
+    ///     let mut stmt = conn.prepare(
+    ///         "SELECT a.id, a.name, b.title FROM author a JOIN book b ON b.author_id = a.id
+    ///          ORDER BY a.id",
+    ///     )?;
+    ///     let rows = stmt.query([])?;
+    ///     rows.group_by_adjacent("id", |_id: i64, mut group| {
+    ///         let mut author = Author {
+    ///             name: String::new(),
+    ///             books: Vec::new(),
+    ///         };
+    ///         while let Some(row) = group.next()? {
+    ///             author.name = row.get("name")?;
+    ///             author.books.push(row.get("title")?);
+    ///         }
+    ///         Ok(author)
+    ///     })
+    /// }
+    /// ```
+    pub fn group_by_adjacent<I, K, T, F>(self, key: I, mut f: F) -> Result<Vec<T>>
+    where
+        I: RowIndex,
+        K: FromSql + PartialEq,
+        F: FnMut(K, GroupRows<'_, 'stmt, K>) -> Result<T>,
+    {
+        let col = match self.stmt {
+            Some(stmt) => key.idx(stmt)?,
+            None => return Ok(Vec::new()),
+        };
+        let mut rows = self.peekable();
+        let mut groups = Vec::new();
+        loop {
+            // read twice rather than requiring `K: Clone`
+            let (group_key, key) = match rows.peek()? {
+                Some(row) => (row.get(col)?, row.get(col)?),
+                None => break,
+            };
+            groups.push(f(
+                group_key,
+                GroupRows {
+                    rows: &mut rows,
+                    col,
+                    key: &key,
+                },
+            )?);
+            let mut rest = GroupRows {
+                rows: &mut rows,
+                col,
+                key: &key,
+            };
+            while rest.next()?.is_some() {}
+        }
+        Ok(groups)
+    }
+
     /// Give access to the underlying statement
     #[must_use]
     pub fn as_ref(&self) -> Option<&Statement<'stmt>> {
@@ -186,6 +314,69 @@ where
     }
 }
 
+/// Rows of a query which can look ahead by one row, created by
+/// [`Rows::peekable`].
+///
+/// Peeking steps the statement to the next row, which [`PeekableRows::next`]
+/// then returns without stepping again. This does not buffer the current row:
+/// a row borrowed from `next` must be dropped before calling `peek`, so the
+/// current row's values have to be read out first.
+#[must_use = "Rows is lazy and will do nothing unless consumed"]
+pub struct PeekableRows<'stmt> {
+    rows: Rows<'stmt>,
+    peeked: bool,
+}
+
+impl<'stmt> PeekableRows<'stmt> {
+    /// Attempt to get the next row from the query, like [`Rows::next`].
+    #[allow(clippy::should_implement_trait)] // cannot implement Iterator
+    #[inline]
+    pub fn next(&mut self) -> Result<Option<&Row<'stmt>>> {
+        if self.peeked {
+            self.peeked = false;
+            Ok(self.rows.get())
+        } else {
+            self.rows.next()
+        }
+    }
+
+    /// Returns the row which the next call to [`PeekableRows::next`] will
+    /// return, or `Ok(None)` if all rows have been retrieved.
+    #[inline]
+    pub fn peek(&mut self) -> Result<Option<&Row<'stmt>>> {
+        if !self.peeked {
+            self.rows.advance()?;
+            self.peeked = true;
+        }
+        Ok(self.rows.get())
+    }
+}
+
+/// The rows of one run of adjacent rows with the same key, passed to the
+/// callback of [`Rows::group_by_adjacent`].
+pub struct GroupRows<'a, 'stmt, K> {
+    rows: &'a mut PeekableRows<'stmt>,
+    col: usize,
+    key: &'a K,
+}
+
+impl<'stmt, K: FromSql + PartialEq> GroupRows<'_, 'stmt, K> {
+    /// Attempt to get the next row of the run. Returns `Ok(None)` once the
+    /// next row of the query has a different key, or there are no more rows.
+    #[allow(clippy::should_implement_trait)] // cannot implement Iterator
+    pub fn next(&mut self) -> Result<Option<&Row<'stmt>>> {
+        let same = match self.rows.peek()? {
+            Some(row) => row.get::<_, K>(self.col)? == *self.key,
+            None => false,
+        };
+        if same {
+            self.rows.next()
+        } else {
+            Ok(None)
+        }
+    }
+}
+
 /// `FallibleStreamingIterator` differs from the standard library's `Iterator`
 /// in two ways:
 /// * each call to `next` (`sqlite3_step`) can fail.
@@ -280,7 +471,142 @@ impl<'stmt> Row<'stmt> {
     pub fn get<I: RowIndex, T: FromSql>(&self, idx: I) -> Result<T> {
         let idx = idx.idx(self.stmt)?;
         let value = self.stmt.value_ref(idx);
-        FromSql::column_result(value).map_err(|err| match err {
+        FromSql::column_result(value).map_err(|err| self.column_error(idx, value, err))
+    }
+
+    /// Get the value of a particular column of the result row, deserialized
+    /// from JSON. This is `get::<_, Json<T>>(idx)`, see
+    /// [`Json`](crate::types::Json).
+    ///
+    /// ## Failure
+    ///
+    /// Returns an `Error::FromSqlConversionFailure` holding a
+    /// [`JsonError`](crate::types::JsonError) if the value does not
+    /// deserialize to `T`, or fails as [`get`](Row::get) does.
+    #[cfg(feature = "serde_json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "serde_json")))]
+    pub fn get_json<I: RowIndex, T: serde::de::DeserializeOwned>(&self, idx: I) -> Result<T> {
+        self.get::<_, crate::types::Json<T>>(idx).map(|json| json.0)
+    }
+
+    /// Returns the values of every column of the row, in order.
+    #[must_use]
+    pub fn to_vec(&self) -> Vec<Value> {
+        (0..self.stmt.column_count())
+            .map(|i| Value::from(self.stmt.value_ref(i)))
+            .collect()
+    }
+
+    /// Returns the name and value of every column of the row, in order.
+    /// Columns sharing a name are all kept.
+    #[must_use]
+    pub fn to_named_vec(&self) -> Vec<(String, Value)> {
+        (0..self.stmt.column_count())
+            .map(|i| {
+                (
+                    self.stmt.column_name_unwrap(i).to_owned(),
+                    Value::from(self.stmt.value_ref(i)),
+                )
+            })
+            .collect()
+    }
+
+    /// Returns the values of the row keyed by column name.
+    ///
+    /// If several columns have the same name, only the first is kept; use
+    /// [`to_named_vec`](Row::to_named_vec) to keep them all.
+    #[must_use]
+    pub fn to_map(&self) -> HashMap<String, Value> {
+        let mut map = HashMap::with_capacity(self.stmt.column_count());
+        for (name, value) in self.to_named_vec() {
+            map.entry(name).or_insert(value);
+        }
+        map
+    }
+
+    /// Get the value of the column named `name`, preferring a column whose
+    /// name matches exactly over one that only matches ASCII
+    /// case-insensitively.
+    ///
+    /// ## Failure
+    ///
+    /// Returns an `Error::InvalidColumnName`, listing the available column
+    /// names, if no column matches `name`. Otherwise fails as
+    /// [`get`](Row::get) does.
+    pub fn get_ci<T: FromSql>(&self, name: &str) -> Result<T> {
+        self.get(self.stmt.column_index_ci(name)?)
+    }
+
+    /// Convert the whole row into `T` using its [`FromRow`] implementation,
+    /// e.g. into a tuple of consecutive columns:
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn people(conn: &Connection) -> Result<Vec<(i64, String, Option<f64>)>> {
+    ///     let mut stmt = conn.prepare("SELECT id, name, height FROM people")?;
+    ///     let rows = stmt.query_map([], |row| row.get_all())?;
+    ///     rows.collect()
+    /// }
+    /// ```
+    ///
+    /// ## Failure
+    ///
+    /// For tuples, returns an `Error::InvalidColumnIndex` if the row does not
+    /// have exactly as many columns as the tuple, and fails as
+    /// [`get`](Row::get) does, naming the column, if a value cannot be
+    /// converted.
+    #[inline]
+    pub fn get_all<T: FromRow>(&self) -> Result<T> {
+        T::from_row(self)
+    }
+
+    /// Get the value of a particular column of the result row, borrowing it
+    /// from the row rather than copying it.
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn longest_name(conn: &Connection) -> Result<usize> {
+    ///     let mut stmt = conn.prepare("SELECT name FROM people")?;
+    ///     let mut rows = stmt.query([])?;
+    ///     let mut longest = 0;
+    ///     while let Some(row) = rows.next()? {
+    ///         let name: &str = row.get_borrowed(0)?;
+    ///         longest = longest.max(name.len());
+    ///     }
+    ///     Ok(longest)
+    /// }
+    /// ```
+    ///
+    /// The borrowed value cannot outlive the row:
+    ///
+    /// ```rust,compile_fail
+    /// # use rusqlite::{Connection, Result};
+    /// fn first_name(conn: &Connection) -> Result<&str> {
+    ///     let mut stmt = conn.prepare("SELECT name FROM people")?;
+    ///     let mut rows = stmt.query([])?;
+    ///     let row = rows.next()?.unwrap();
+    ///     row.get_borrowed(0)
+    /// }
+    /// ```
+    ///
+    /// ## Failure
+    ///
+    /// Returns an `Error::InvalidColumnType` if the underlying SQLite column
+    /// type is not a valid type as a source for `T`.
+    ///
+    /// Returns an `Error::InvalidColumnIndex` if `idx` is outside the valid
+    /// column range for this row.
+    ///
+    /// Returns an `Error::InvalidColumnName` if `idx` is not a valid column
+    /// name for this row.
+    pub fn get_borrowed<'a, I: RowIndex, T: FromSqlRef<'a>>(&'a self, idx: I) -> Result<T> {
+        let idx = idx.idx(self.stmt)?;
+        let value = self.stmt.value_ref(idx);
+        T::column_result_ref(value).map_err(|err| self.column_error(idx, value, err))
+    }
+
+    fn column_error(&self, idx: usize, value: ValueRef<'_>, err: FromSqlError) -> Error {
+        match err {
             FromSqlError::InvalidType => Error::InvalidColumnType(
                 idx,
                 self.stmt.column_name_unwrap(idx).into(),
@@ -290,10 +616,82 @@ impl<'stmt> Row<'stmt> {
             FromSqlError::Other(err) => {
                 Error::FromSqlConversionFailure(idx, value.data_type(), err)
             }
-            FromSqlError::InvalidBlobSize { .. } => {
-                Error::FromSqlConversionFailure(idx, value.data_type(), Box::new(err))
-            }
-        })
+            _ => Error::FromSqlConversionFailure(idx, value.data_type(), Box::new(err)),
+        }
+    }
+
+    /// Get the value of an `INTEGER` column of the result row, without going
+    /// through [`FromSql`].
+    ///
+    /// ## Failure
+    ///
+    /// Returns an `Error::InvalidColumnType` if the underlying SQLite column
+    /// type is not `INTEGER`.
+    ///
+    /// Returns an `Error::InvalidColumnIndex` if `idx` is outside the valid
+    /// column range for this row.
+    #[inline]
+    pub fn get_i64(&self, idx: usize) -> Result<i64> {
+        let idx = idx.idx(self.stmt)?;
+        match self.stmt.column_type(idx) {
+            ffi::SQLITE_INTEGER => Ok(self.stmt.column_int64(idx)),
+            _ => Err(self.invalid_column_type(idx)),
+        }
+    }
+
+    /// Get the value of an `INTEGER` or `REAL` column of the result row,
+    /// without going through [`FromSql`].
+    ///
+    /// ## Failure
+    ///
+    /// Returns an `Error::InvalidColumnType` if the underlying SQLite column
+    /// type is neither `INTEGER` nor `REAL`.
+    ///
+    /// Returns an `Error::InvalidColumnIndex` if `idx` is outside the valid
+    /// column range for this row.
+    #[inline]
+    pub fn get_f64(&self, idx: usize) -> Result<f64> {
+        let idx = idx.idx(self.stmt)?;
+        match self.stmt.column_type(idx) {
+            ffi::SQLITE_INTEGER | ffi::SQLITE_FLOAT => Ok(self.stmt.column_double(idx)),
+            _ => Err(self.invalid_column_type(idx)),
+        }
+    }
+
+    /// Get the value of a column of the result row converted to an `i64` the
+    /// way SQLite's
+    /// [`sqlite3_column_int64`](https://sqlite.org/c3ref/column_blob.html)
+    /// does, for reading numbers in hot loops.
+    ///
+    /// Unlike [`get`](Row::get), this never fails: `NULL` is read as `0`, a
+    /// `REAL` is truncated, and `TEXT` or a `BLOB` is parsed as a number,
+    /// stopping at the first character which is not part of one (so `"12abc"`
+    /// is read as `12`, and `"abc"` as `0`). An `idx` outside the valid column
+    /// range is also read as `0`.
+    #[inline]
+    pub fn get_i64_unchecked(&self, idx: usize) -> i64 {
+        self.stmt.column_int64(idx)
+    }
+
+    /// Get the value of a column of the result row converted to an `f64` the
+    /// way SQLite's
+    /// [`sqlite3_column_double`](https://sqlite.org/c3ref/column_blob.html)
+    /// does, for reading numbers in hot loops.
+    ///
+    /// Like [`get_i64_unchecked`](Row::get_i64_unchecked), this never fails and
+    /// follows the C API coercion rules rather than those of
+    /// [`FromSql`].
+    #[inline]
+    pub fn get_f64_unchecked(&self, idx: usize) -> f64 {
+        self.stmt.column_double(idx)
+    }
+
+    fn invalid_column_type(&self, idx: usize) -> Error {
+        Error::InvalidColumnType(
+            idx,
+            self.stmt.column_name_unwrap(idx).into(),
+            self.stmt.value_ref(idx).data_type(),
+        )
     }
 
     /// Get the value of a particular column of the result row as a `ValueRef`,
@@ -435,6 +833,36 @@ impl RowIndex for &'_ str {
     }
 }
 
+/// A trait for types that can be created from a whole result row.
+///
+/// It is implemented for tuples of up to 16 [`FromSql`] types, which are
+/// extracted from consecutive columns starting at index 0. Unlike the
+/// `TryFrom<&Row>` implementations for tuples, the row must have exactly as
+/// many columns as the tuple: otherwise `Error::InvalidColumnIndex` is
+/// returned with the index of the first column without a counterpart. With the `derive`
+/// feature, it can be derived for structs, mapping each field to the column
+/// with the same name:
+///
+/// ```rust,ignore
+/// use rusqlite::FromRow;
+///
+/// #[derive(FromRow)]
+/// struct Person {
+///     id: i64,
+///     #[column(rename = "full_name")]
+///     name: String,
+///     #[column(flatten)]
+///     address: Address, // itself `FromRow`, reading from the same row
+/// }
+/// ```
+///
+/// See [`Statement::query_as`] and
+/// [`Connection::query_row_as`](crate::Connection::query_row_as).
+pub trait FromRow: Sized {
+    /// Converts a result row into a Rust value.
+    fn from_row(row: &Row<'_>) -> Result<Self>;
+}
+
 macro_rules! tuple_try_from_row {
     ($($field:ident),*) => {
         impl<'a, $($field,)*> convert::TryFrom<&'a Row<'a>> for ($($field,)*) where $($field: FromSql,)* {
@@ -453,6 +881,17 @@ macro_rules! tuple_try_from_row {
                 Ok(($($field,)*))
             }
         }
+
+        impl<$($field,)*> FromRow for ($($field,)*) where $($field: FromSql,)* {
+            #[inline]
+            fn from_row(row: &Row<'_>) -> Result<Self> {
+                let arity = <[&str]>::len(&[$(stringify!($field)),*]);
+                if row.as_ref().column_count() > arity {
+                    return Err(Error::InvalidColumnIndex(arity));
+                }
+                convert::TryFrom::try_from(row)
+            }
+        }
     }
 }
 
@@ -474,6 +913,71 @@ mod tests {
     #![allow(clippy::redundant_closure)] // false positives due to lifetime issues; clippy issue #5594
     use crate::{Connection, Result};
 
+    #[test]
+    fn test_peekable() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        let mut stmt = conn.prepare("VALUES (1), (2), (3)")?;
+        let mut rows = stmt.query([])?.peekable();
+
+        assert_eq!(rows.peek()?.unwrap().get::<_, i64>(0)?, 1);
+        assert_eq!(rows.peek()?.unwrap().get::<_, i64>(0)?, 1);
+        assert_eq!(rows.next()?.unwrap().get::<_, i64>(0)?, 1);
+        assert_eq!(rows.next()?.unwrap().get::<_, i64>(0)?, 2);
+        assert_eq!(rows.peek()?.unwrap().get::<_, i64>(0)?, 3);
+        assert_eq!(rows.next()?.unwrap().get::<_, i64>(0)?, 3);
+        assert!(rows.peek()?.is_none());
+        assert!(rows.next()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_by_adjacent() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        conn.execute_batch(
+            "CREATE TABLE parent (id INTEGER PRIMARY KEY, name TEXT);
+             CREATE TABLE child (parent_id INTEGER, name TEXT);
+             INSERT INTO parent VALUES (1, 'a'), (2, 'b'), (3, 'c');
+             INSERT INTO child VALUES (1, 'a1'), (1, 'a2'), (2, 'b1'), (3, 'c1'), (3, 'c2'), (3, 'c3');",
+        )?;
+        let sql = "SELECT p.id, p.name, c.name FROM parent p JOIN child c ON c.parent_id = p.id
+                   WHERE p.id > ?1 ORDER BY p.id, c.name";
+        let mut stmt = conn.prepare(sql)?;
+
+        let groups = stmt
+            .query([0])?
+            .group_by_adjacent("id", |id: i64, mut group| {
+                let mut name = String::new();
+                let mut children = Vec::new();
+                while let Some(row) = group.next()? {
+                    name = row.get(1)?;
+                    children.push(row.get::<_, String>(2)?);
+                }
+                Ok((id, name, children))
+            })?;
+        assert_eq!(
+            groups,
+            vec![
+                (1, "a".to_owned(), vec!["a1".to_owned(), "a2".to_owned()]),
+                (2, "b".to_owned(), vec!["b1".to_owned()]),
+                (
+                    3,
+                    "c".to_owned(),
+                    vec!["c1".to_owned(), "c2".to_owned(), "c3".to_owned()]
+                ),
+            ]
+        );
+
+        // rows left unread by the callback are skipped
+        let firsts = stmt.query([1])?.group_by_adjacent(0, |_: i64, mut group| {
+            group.next()?.unwrap().get::<_, String>(2)
+        })?;
+        assert_eq!(firsts, vec!["b1".to_owned(), "c1".to_owned()]);
+
+        let empty = stmt.query([3])?.group_by_adjacent(0, |id: i64, _| Ok(id))?;
+        assert!(empty.is_empty());
+        Ok(())
+    }
+
     #[test]
     fn test_try_from_row_for_tuple_1() -> Result<()> {
         use crate::ToSql;
@@ -596,4 +1100,220 @@ mod tests {
         // We don't test one bigger because it's unimplemented
         Ok(())
     }
+
+    #[test]
+    fn test_get_all() -> Result<()> {
+        use crate::types::Type;
+        use crate::Error;
+
+        let conn = Connection::open_in_memory()?;
+        conn.execute_batch(
+            "CREATE TABLE test (a INTEGER, b TEXT, c REAL);
+             INSERT INTO test VALUES (1, 'one', 1.5), (2, 'two', NULL);",
+        )?;
+        let mut stmt = conn.prepare("SELECT a, b, c FROM test ORDER BY a")?;
+        let rows = stmt
+            .query_map([], |r| r.get_all::<(i64, String, Option<f64>)>())?
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            rows,
+            vec![
+                (1, "one".to_owned(), Some(1.5)),
+                (2, "two".to_owned(), None)
+            ]
+        );
+
+        // too few and too many columns
+        let err = stmt
+            .query_row([], |r| r.get_all::<(i64, String, Option<f64>, i64)>())
+            .unwrap_err();
+        assert_eq!(err, Error::InvalidColumnIndex(3));
+        let err = stmt
+            .query_row([], |r| r.get_all::<(i64, String)>())
+            .unwrap_err();
+        assert_eq!(err, Error::InvalidColumnIndex(2));
+
+        let err = stmt
+            .query_row([], |r| r.get_all::<(i64, i64, f64)>())
+            .unwrap_err();
+        match err {
+            Error::InvalidColumnType(1, name, Type::Text) => assert_eq!(name, "b"),
+            err => panic!("unexpected error {}", err),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_row_to_vec_and_map() -> Result<()> {
+        use crate::types::Value;
+        use crate::Connection;
+        let conn = Connection::open_in_memory()?;
+        let sql = "SELECT 1 AS a, NULL AS b, x'0102' AS a, 1 + 1, lower('X')";
+        let (vec, named, map) = conn.query_row(sql, [], |row| {
+            Ok((row.to_vec(), row.to_named_vec(), row.to_map()))
+        })?;
+        assert_eq!(
+            vec,
+            vec![
+                Value::Integer(1),
+                Value::Null,
+                Value::Blob(vec![1, 2]),
+                Value::Integer(2),
+                Value::Text("x".to_owned()),
+            ]
+        );
+        let names: Vec<&str> = named.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["a", "b", "a", "1 + 1", "lower('X')"]);
+        assert_eq!(named.into_iter().map(|(_, v)| v).collect::<Vec<_>>(), vec);
+
+        assert_eq!(map.len(), 4);
+        assert_eq!(map["a"], Value::Integer(1));
+        assert_eq!(map["b"], Value::Null);
+        assert_eq!(map["1 + 1"], Value::Integer(2));
+
+        let mut stmt = conn.prepare("SELECT 1, 'one' UNION ALL SELECT 2, NULL")?;
+        let rows = stmt.query([])?.values().collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::Integer(1), Value::Text("one".to_owned())],
+                vec![Value::Integer(2), Value::Null],
+            ]
+        );
+        let named = stmt.query([])?.named_values().collect::<Result<Vec<_>>>()?;
+        assert_eq!(named[1][1], ("'one'".to_owned(), Value::Null));
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_ci() -> Result<()> {
+        use crate::{Connection, Error};
+        let conn = Connection::open_in_memory()?;
+        let (exact, folded, aliased): (i64, i64, String) =
+            conn.query_row("SELECT 1 AS id, 2 AS ID, upper('a') AS Letter", [], |row| {
+                Ok((row.get_ci("ID")?, row.get_ci("Id")?, row.get_ci("letter")?))
+            })?;
+        assert_eq!((exact, folded, aliased.as_str()), (2, 1, "A"));
+
+        let err = conn
+            .query_row("SELECT 1 AS id", [], |row| row.get_ci::<i64>("userid"))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            Error::InvalidColumnName("userid (available columns: id)".to_owned())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_i64_f64() -> Result<()> {
+        use crate::types::Type;
+        use crate::Error;
+
+        let conn = Connection::open_in_memory()?;
+        let mut stmt = conn.prepare("SELECT 12, 2.5, '12abc', NULL, 'abc', x'3334'")?;
+        let mut rows = stmt.query([])?;
+        let row = rows.next()?.unwrap();
+
+        assert_eq!(row.get_i64(0)?, 12);
+        assert_eq!(row.get_f64(0)?, 12.0);
+        assert_eq!(row.get_f64(1)?, 2.5);
+        assert_eq!(
+            row.get_i64(1),
+            Err(Error::InvalidColumnType(1, "2.5".to_owned(), Type::Real))
+        );
+        assert_eq!(
+            row.get_f64(2),
+            Err(Error::InvalidColumnType(
+                2,
+                "'12abc'".to_owned(),
+                Type::Text
+            ))
+        );
+        assert_eq!(row.get_i64(6), Err(Error::InvalidColumnIndex(6)));
+
+        // The unchecked getters follow the coercion rules of the C API, which
+        // are deliberately much looser than those of `FromSql`.
+        assert_eq!(row.get_i64_unchecked(0), 12);
+        assert_eq!(row.get_i64_unchecked(1), 2);
+        assert_eq!(row.get_i64_unchecked(2), 12);
+        assert_eq!(row.get_i64_unchecked(3), 0);
+        assert_eq!(row.get_i64_unchecked(4), 0);
+        assert_eq!(row.get_i64_unchecked(5), 34);
+        assert_eq!(row.get_i64_unchecked(6), 0);
+        assert_eq!(row.get_f64_unchecked(1), 2.5);
+        assert_eq!(row.get_f64_unchecked(2), 12.0);
+        assert_eq!(row.get_f64_unchecked(3), 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_borrowed() -> Result<()> {
+        use crate::types::Type;
+        use crate::Error;
+        use std::borrow::Cow;
+
+        let conn = Connection::open_in_memory()?;
+        conn.execute_batch("CREATE TABLE foo (t TEXT, b BLOB, n TEXT)")?;
+        conn.execute(
+            "INSERT INTO foo VALUES (?1, ?2, NULL)",
+            crate::params!["{\"json\": true}", b"bytes".to_vec()],
+        )?;
+        let mut stmt = conn.prepare("SELECT t, b, n FROM foo")?;
+        let mut rows = stmt.query([])?;
+        let row = rows.next()?.unwrap();
+
+        let s: &str = row.get_borrowed(0)?;
+        assert_eq!(s, "{\"json\": true}");
+        // borrowed straight out of SQLite's buffer, not copied
+        assert_eq!(s.as_ptr(), row.get_ref(0)?.as_str()?.as_ptr());
+        match row.get_borrowed::<_, Cow<'_, str>>(0)? {
+            Cow::Borrowed(c) => assert_eq!(c.as_ptr(), s.as_ptr()),
+            Cow::Owned(_) => panic!("Unexpected copy"),
+        }
+
+        let b: &[u8] = row.get_borrowed(1)?;
+        assert_eq!(b, b"bytes");
+        assert_eq!(b.as_ptr(), row.get_ref(1)?.as_blob()?.as_ptr());
+
+        assert_eq!(row.get_borrowed::<_, Option<&str>>(2)?, None);
+        assert!(row.get_borrowed::<_, &str>(2).is_err());
+        assert!(row.get_borrowed::<_, &[u8]>(0).is_err());
+
+        // invalid UTF-8 is an error, not replaced
+        let mut stmt = conn.prepare("SELECT CAST(x'ff' AS TEXT)")?;
+        let mut rows = stmt.query([])?;
+        let row = rows.next()?.unwrap();
+        for err in [
+            row.get_borrowed::<_, &str>(0).unwrap_err(),
+            row.get_borrowed::<_, Cow<'_, str>>(0).unwrap_err(),
+        ] {
+            match err {
+                Error::FromSqlConversionFailure(0, Type::Text, e) => {
+                    assert!(e.is::<std::str::Utf8Error>(), "{}", e)
+                }
+                err => panic!("Unexpected error {}", err),
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_as_tuple() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        conn.execute_batch(
+            "CREATE TABLE foo (x INTEGER, y TEXT);
+             INSERT INTO foo VALUES (1, 'one');
+             INSERT INTO foo VALUES (2, 'two');",
+        )?;
+        let mut stmt = conn.prepare("SELECT x, y FROM foo ORDER BY x")?;
+        let rows = stmt
+            .query_as::<(i64, String), _>([])?
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(rows, vec![(1, "one".to_owned()), (2, "two".to_owned())]);
+
+        let row: (String,) = conn.query_row_as("SELECT y FROM foo WHERE x = ?1", [2])?;
+        assert_eq!(row, ("two".to_owned(),));
+        Ok(())
+    }
 }
@@ -0,0 +1,162 @@
+//! The rowid of a row, and whether a table has rowids at all.
+use std::fmt;
+
+use crate::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ToSqlResult, ValueRef};
+use crate::{ffi, Connection, DatabaseName, Error, Result};
+
+/// The [rowid](https://sqlite.org/lang_createtable.html#rowid) of a row of a
+/// table, as returned by [`Connection::last_insert_rowid`] and accepted by
+/// [`Connection::blob_open`](crate::Connection::blob_open).
+///
+/// It converts from and into `i64`, and compares equal to the `i64` it wraps.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RowId(pub i64);
+
+impl From<i64> for RowId {
+    #[inline]
+    fn from(rowid: i64) -> RowId {
+        RowId(rowid)
+    }
+}
+
+impl From<RowId> for i64 {
+    #[inline]
+    fn from(rowid: RowId) -> i64 {
+        rowid.0
+    }
+}
+
+impl PartialEq<i64> for RowId {
+    #[inline]
+    fn eq(&self, other: &i64) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<RowId> for i64 {
+    #[inline]
+    fn eq(&self, other: &RowId) -> bool {
+        *self == other.0
+    }
+}
+
+impl fmt::Display for RowId {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl ToSql for RowId {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        Ok(ToSqlOutput::from(self.0))
+    }
+}
+
+impl FromSql for RowId {
+    #[inline]
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        i64::column_result(value).map(RowId)
+    }
+}
+
+impl Connection {
+    /// Returns whether `table` has rowids, i.e. was not created `WITHOUT
+    /// ROWID`.
+    ///
+    /// Virtual tables have rowids, as do views, whose rows cannot be opened
+    /// as BLOBs either.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if there is no such table, or if the underlying
+    /// SQLite call fails.
+    pub fn table_has_rowid(&self, table: &str) -> Result<bool> {
+        self.has_rowid(None, table)
+    }
+
+    pub(crate) fn has_rowid(&self, db: Option<DatabaseName<'_>>, table: &str) -> Result<bool> {
+        let mut exists = false;
+        self.pragma(db, "table_info", table, |_| {
+            exists = true;
+            Ok(())
+        })?;
+        if !exists {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_ERROR),
+                Some(format!("no such table: {table}")),
+            ));
+        }
+        // the primary key of a WITHOUT ROWID table is an index which, unlike
+        // any index of a rowid table, does not end with the rowid (cid -1)
+        let mut pk_indexes = Vec::new();
+        self.pragma(db, "index_list", table, |row| {
+            if row.get_ref(3)?.as_str()? == "pk" {
+                pk_indexes.push(row.get::<_, String>(1)?);
+            }
+            Ok(())
+        })?;
+        for index in pk_indexes {
+            let mut rowid = false;
+            self.pragma(db, "index_xinfo", &index, |row| {
+                rowid |= row.get::<_, i64>(1)? == -1;
+                Ok(())
+            })?;
+            if !rowid {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RowId;
+    use crate::{Connection, Result};
+
+    #[test]
+    fn test_table_has_rowid() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE plain(x);
+             CREATE TABLE alias(id INTEGER PRIMARY KEY, x);
+             CREATE TABLE text_pk(id TEXT PRIMARY KEY, x);
+             CREATE TABLE without(id TEXT PRIMARY KEY, x) WITHOUT ROWID;
+             CREATE TABLE without_int(id INTEGER PRIMARY KEY) WITHOUT ROWID;",
+        )?;
+        assert!(db.table_has_rowid("plain")?);
+        assert!(db.table_has_rowid("alias")?);
+        assert!(db.table_has_rowid("text_pk")?);
+        assert!(!db.table_has_rowid("without")?);
+        assert!(!db.table_has_rowid("without_int")?);
+        #[cfg(feature = "csvtab")]
+        {
+            crate::vtab::csvtab::load_module(&db)?;
+            db.execute_batch("CREATE VIRTUAL TABLE virt USING csv(filename='test.csv')")?;
+            assert!(db.table_has_rowid("virt")?);
+        }
+        assert!(db.table_has_rowid("missing").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_rowid() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(x); INSERT INTO foo VALUES ('a');")?;
+        let rowid = db.last_insert_rowid();
+        assert_eq!(rowid, RowId(1));
+        // i64 based code keeps working
+        let id: i64 = rowid.into();
+        assert_eq!(rowid, id);
+
+        let x: String = db.query_row("SELECT x FROM foo WHERE rowid = ?1", [rowid], |row| {
+            row.get(0)
+        })?;
+        assert_eq!(x, "a");
+        let max: RowId = db.one_column("SELECT max(rowid) FROM foo")?;
+        assert_eq!(max, rowid);
+        Ok(())
+    }
+}
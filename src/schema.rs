@@ -0,0 +1,968 @@
+//! Table introspection through `PRAGMA table_info`, with typed column
+//! defaults, and declarative table definitions.
+//!
+//! `PRAGMA table_info` reports a column's default as the SQL text it was
+//! declared with: `'abc'`, `42`, `CURRENT_TIMESTAMP`, or `1 + 2` for
+//! `DEFAULT (1 + 2)`.
+//! [`Connection::table_info`] parses constant literals into a
+//! [`DefaultValue`] and keeps anything else as an
+//! [`DefaultValue::Expression`], which SQLite evaluates on each insert.
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result};
+//! use rusqlite::schema::DefaultValue;
+//!
+//! fn status_default(conn: &Connection) -> Result<Option<DefaultValue>> {
+//!     let columns = conn.table_info("task")?;
+//!     Ok(columns
+//!         .into_iter()
+//!         .find(|column| column.name == "status")
+//!         .and_then(|column| column.default))
+//! }
+//! ```
+//!
+//! [`Connection::ensure_table`] creates a table from a [`TableDef`], or adds
+//! the columns and indexes an existing table lacks:
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result};
+//! use rusqlite::schema::{ColumnDef, DefaultValue, IndexDef, TableDef};
+//!
+//! fn ensure_task_table(conn: &Connection) -> Result<()> {
+//!     let task = TableDef::new(
+//!         "task",
+//!         vec![
+//!             ColumnDef::new("id", "INTEGER").primary_key(),
+//!             ColumnDef::new("title", "TEXT").not_null(),
+//!             ColumnDef::new("status", "TEXT")
+//!                 .not_null()
+//!                 .default(DefaultValue::Text("open".to_owned())),
+//!         ],
+//!     )
+//!     .index(IndexDef::new("task_status", &["status"]));
+//!     conn.ensure_table(&task)?;
+//!     Ok(())
+//! }
+//! ```
+
+use std::fmt::{self, Write};
+
+use crate::pragma::Sql;
+use crate::{params_from_iter, Connection, Error, Result, ToSql};
+
+/// The default value of a table column, as reported by
+/// [`Connection::table_info`].
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum DefaultValue {
+    /// `DEFAULT NULL`
+    Null,
+    /// An integer literal, or `TRUE` / `FALSE`.
+    Integer(i64),
+    /// A floating point literal, or an integer literal too large for `i64`.
+    Real(f64),
+    /// A string literal, unquoted.
+    Text(String),
+    /// A BLOB literal (`X'...'`), decoded.
+    Blob(Vec<u8>),
+    /// Any other default, like `CURRENT_TIMESTAMP` or `random()`, as its SQL
+    /// text. SQLite evaluates it for each inserted row.
+    Expression(String),
+}
+
+impl DefaultValue {
+    /// Parses the SQL text of a column default, as found in the `dflt_value`
+    /// column of `PRAGMA table_info`.
+    #[must_use]
+    pub fn parse(sql: &str) -> DefaultValue {
+        let sql = sql.trim();
+        if sql.eq_ignore_ascii_case("NULL") {
+            return DefaultValue::Null;
+        }
+        if sql.eq_ignore_ascii_case("TRUE") {
+            return DefaultValue::Integer(1);
+        }
+        if sql.eq_ignore_ascii_case("FALSE") {
+            return DefaultValue::Integer(0);
+        }
+        if let Some(text) = parse_string(sql) {
+            return DefaultValue::Text(text);
+        }
+        if let Some(blob) = sql
+            .strip_prefix(&['x', 'X'][..])
+            .and_then(parse_string)
+            .and_then(|hex| parse_hex(&hex))
+        {
+            return DefaultValue::Blob(blob);
+        }
+        parse_number(sql).unwrap_or_else(|| DefaultValue::Expression(sql.to_owned()))
+    }
+}
+
+/// `'it''s'` -> `it's`
+fn parse_string(sql: &str) -> Option<String> {
+    let inner = sql.strip_prefix('\'')?.strip_suffix('\'')?;
+    let text = inner.replace("''", "'");
+    // a lone quote inside means this was not a single literal
+    if text.matches('\'').count() * 2 != inner.matches('\'').count() {
+        return None;
+    }
+    Some(text)
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            &[hi, lo] => Some(hex_digit(hi)? << 4 | hex_digit(lo)?),
+            _ => None,
+        })
+        .collect()
+}
+
+fn hex_digit(b: u8) -> Option<u8> {
+    (b as char).to_digit(16).map(|d| d as u8)
+}
+
+fn parse_number(sql: &str) -> Option<DefaultValue> {
+    let (negative, digits) = match sql.as_bytes().first()? {
+        b'-' => (true, &sql[1..]),
+        b'+' => (false, &sql[1..]),
+        _ => (false, sql),
+    };
+    if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        // hexadecimal literals are 64-bit two's complement
+        let value = u64::from_str_radix(hex, 16).ok()? as i64;
+        return Some(DefaultValue::Integer(if negative {
+            value.wrapping_neg()
+        } else {
+            value
+        }));
+    }
+    // leave out `inf`, `NaN` and friends, which Rust parses but SQLite does not
+    if !digits.starts_with(|c: char| c.is_ascii_digit() || c == '.')
+        || !digits
+            .bytes()
+            .all(|b| b.is_ascii_digit() || matches!(b, b'.' | b'e' | b'E' | b'+' | b'-'))
+    {
+        return None;
+    }
+    if digits.bytes().all(|b| b.is_ascii_digit()) {
+        if let Ok(value) = sql.parse::<i64>() {
+            return Some(DefaultValue::Integer(value));
+        }
+    }
+    sql.parse::<f64>().ok().map(DefaultValue::Real)
+}
+
+/// A column of a table, as reported by [`Connection::table_info`].
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct TableColumn {
+    /// The column name.
+    pub name: String,
+    /// The declared type, empty if none was given.
+    pub decl_type: String,
+    /// Whether the column is declared `NOT NULL`.
+    pub not_null: bool,
+    /// The default value, or `None` if the column has no `DEFAULT` clause.
+    pub default: Option<DefaultValue>,
+    /// The one-based position of the column in the primary key, or 0 if it
+    /// is not part of the primary key.
+    pub primary_key: u32,
+}
+
+fn identifier(name: &str) -> String {
+    let mut sql = Sql::new();
+    sql.push_identifier(name);
+    sql.as_str().to_owned()
+}
+
+impl Connection {
+    /// Returns the columns of `table`, in declaration order.
+    ///
+    /// Returns an empty `Vec` if there is no such table.
+    pub fn table_info(&self, table: &str) -> Result<Vec<TableColumn>> {
+        let mut columns = Vec::new();
+        self.pragma(None, "table_info", table, |row| {
+            columns.push(TableColumn {
+                name: row.get(1)?,
+                decl_type: row.get(2)?,
+                not_null: row.get(3)?,
+                default: row
+                    .get::<_, Option<String>>(4)?
+                    .map(|sql| DefaultValue::parse(&sql)),
+                primary_key: row.get(5)?,
+            });
+            Ok(())
+        })?;
+        Ok(columns)
+    }
+
+    /// Inserts a row into `table` with the `provided` column values, letting
+    /// SQLite fill in the defaults of the other columns, and returns the rowid
+    /// of the new row.
+    ///
+    /// Columns declared `NOT NULL` without a default must be provided, other
+    /// than an `INTEGER PRIMARY KEY`, which gets a new rowid. If any is
+    /// missing, [`Error::MissingColumnValues`] lists them all, and nothing is
+    /// inserted.
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn add_task(conn: &Connection, title: &str) -> Result<i64> {
+    ///     // `status` and `created_at` get their defaults
+    ///     conn.insert_with_defaults("task", &[("title", &title)])
+    /// }
+    /// ```
+    pub fn insert_with_defaults(
+        &self,
+        table: &str,
+        provided: &[(&str, &dyn ToSql)],
+    ) -> Result<i64> {
+        let columns = self.table_info(table)?;
+        let rowid_alias = columns.iter().filter(|c| c.primary_key > 0).count() == 1;
+        let missing: Vec<String> = columns
+            .iter()
+            .filter(|c| c.not_null && c.default.is_none())
+            .filter(|c| {
+                !(rowid_alias && c.primary_key == 1 && c.decl_type.eq_ignore_ascii_case("INTEGER"))
+            })
+            .filter(|c| {
+                !provided
+                    .iter()
+                    .any(|(name, _)| name.eq_ignore_ascii_case(&c.name))
+            })
+            .map(|c| c.name.clone())
+            .collect();
+        if !missing.is_empty() {
+            return Err(Error::MissingColumnValues {
+                table: table.to_owned(),
+                columns: missing,
+            });
+        }
+
+        let mut sql = format!("INSERT INTO {}", identifier(table));
+        if provided.is_empty() {
+            sql.push_str(" DEFAULT VALUES");
+        } else {
+            let names: Vec<String> = provided.iter().map(|(name, _)| identifier(name)).collect();
+            let _ = write!(
+                sql,
+                " ({}) VALUES ({})",
+                names.join(", "),
+                vec!["?"; provided.len()].join(", ")
+            );
+        }
+        self.execute(
+            &sql,
+            params_from_iter(provided.iter().map(|(_, value)| value)),
+        )?;
+        Ok(self.last_insert_rowid().into())
+    }
+}
+
+/// A column of a [`TableDef`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnDef {
+    /// The column name.
+    pub name: String,
+    /// The declared type, like `INTEGER` or `VARCHAR(10)`, or empty.
+    pub decl_type: String,
+    /// Whether the column is declared `NOT NULL`.
+    pub not_null: bool,
+    /// The `DEFAULT` clause, if any.
+    pub default: Option<DefaultValue>,
+    /// Whether the column is part of the primary key.
+    pub primary_key: bool,
+}
+
+impl ColumnDef {
+    /// A nullable column without default.
+    #[must_use]
+    pub fn new(name: &str, decl_type: &str) -> ColumnDef {
+        ColumnDef {
+            name: name.to_owned(),
+            decl_type: decl_type.to_owned(),
+            not_null: false,
+            default: None,
+            primary_key: false,
+        }
+    }
+
+    /// Declare the column `NOT NULL`.
+    #[must_use]
+    pub fn not_null(mut self) -> ColumnDef {
+        self.not_null = true;
+        self
+    }
+
+    /// Give the column a `DEFAULT` clause.
+    #[must_use]
+    pub fn default(mut self, default: DefaultValue) -> ColumnDef {
+        self.default = Some(default);
+        self
+    }
+
+    /// Make the column part of the primary key.
+    #[must_use]
+    pub fn primary_key(mut self) -> ColumnDef {
+        self.primary_key = true;
+        self
+    }
+
+    // Whether `ALTER TABLE ADD COLUMN` accepts this column.
+    fn is_addable(&self) -> bool {
+        !self.primary_key
+            && !matches!(self.default, Some(DefaultValue::Expression(_)))
+            && (!self.not_null || !matches!(self.default, None | Some(DefaultValue::Null)))
+    }
+
+    fn push_sql(&self, sql: &mut String, single_primary_key: bool) {
+        sql.push_str(&identifier(&self.name));
+        if !self.decl_type.is_empty() {
+            sql.push(' ');
+            sql.push_str(&normalize_type(&self.decl_type));
+        }
+        if self.primary_key && single_primary_key {
+            sql.push_str(" PRIMARY KEY");
+        }
+        if self.not_null {
+            sql.push_str(" NOT NULL");
+        }
+        if let Some(ref default) = self.default {
+            sql.push_str(" DEFAULT ");
+            sql.push_str(&default_sql(default));
+        }
+    }
+}
+
+/// An index of a [`TableDef`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexDef {
+    /// The index name.
+    pub name: String,
+    /// The indexed columns, in order.
+    pub columns: Vec<String>,
+    /// Whether the index is `UNIQUE`.
+    pub unique: bool,
+}
+
+impl IndexDef {
+    /// A non-unique index on `columns`.
+    #[must_use]
+    pub fn new(name: &str, columns: &[&str]) -> IndexDef {
+        IndexDef {
+            name: name.to_owned(),
+            columns: columns.iter().map(|c| (*c).to_owned()).collect(),
+            unique: false,
+        }
+    }
+
+    /// Make the index `UNIQUE`.
+    #[must_use]
+    pub fn unique(mut self) -> IndexDef {
+        self.unique = true;
+        self
+    }
+}
+
+/// The definition of a table, for [`Connection::ensure_table`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableDef {
+    /// The table name.
+    pub name: String,
+    /// The columns, in order.
+    pub columns: Vec<ColumnDef>,
+    /// The indexes on the table.
+    pub indexes: Vec<IndexDef>,
+    /// Whether the table is created `STRICT`, which needs SQLite 3.37.0 or
+    /// later.
+    pub strict: bool,
+    /// Whether the table is created `WITHOUT ROWID`.
+    pub without_rowid: bool,
+}
+
+impl TableDef {
+    /// A table with `columns`, no index and no table option.
+    #[must_use]
+    pub fn new(name: &str, columns: Vec<ColumnDef>) -> TableDef {
+        TableDef {
+            name: name.to_owned(),
+            columns,
+            indexes: Vec::new(),
+            strict: false,
+            without_rowid: false,
+        }
+    }
+
+    /// Add an index.
+    #[must_use]
+    pub fn index(mut self, index: IndexDef) -> TableDef {
+        self.indexes.push(index);
+        self
+    }
+
+    /// Create the table `STRICT`.
+    #[must_use]
+    pub fn strict(mut self) -> TableDef {
+        self.strict = true;
+        self
+    }
+
+    /// Create the table `WITHOUT ROWID`.
+    #[must_use]
+    pub fn without_rowid(mut self) -> TableDef {
+        self.without_rowid = true;
+        self
+    }
+
+    fn create_sql(&self) -> String {
+        let primary_key: Vec<&ColumnDef> = self.columns.iter().filter(|c| c.primary_key).collect();
+        let mut sql = format!("CREATE TABLE {} (", identifier(&self.name));
+        for (i, column) in self.columns.iter().enumerate() {
+            if i > 0 {
+                sql.push_str(", ");
+            }
+            column.push_sql(&mut sql, primary_key.len() == 1);
+        }
+        if primary_key.len() > 1 {
+            let names: Vec<String> = primary_key.iter().map(|c| identifier(&c.name)).collect();
+            let _ = write!(sql, ", PRIMARY KEY ({})", names.join(", "));
+        }
+        sql.push(')');
+        let mut options = Vec::new();
+        if self.without_rowid {
+            options.push("WITHOUT ROWID");
+        }
+        if self.strict {
+            options.push("STRICT");
+        }
+        if !options.is_empty() {
+            sql.push(' ');
+            sql.push_str(&options.join(", "));
+        }
+        sql
+    }
+
+    fn create_index_sql(&self, index: &IndexDef) -> String {
+        let columns: Vec<String> = index.columns.iter().map(|c| identifier(c)).collect();
+        format!(
+            "CREATE {}INDEX IF NOT EXISTS {} ON {} ({})",
+            if index.unique { "UNIQUE " } else { "" },
+            identifier(&index.name),
+            identifier(&self.name),
+            columns.join(", ")
+        )
+    }
+}
+
+/// What [`Connection::ensure_table`] did.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum EnsureOutcome {
+    /// The table already matched its definition.
+    Unchanged,
+    /// The table did not exist and was created, with its indexes.
+    Created,
+    /// The table existed, and the missing columns or indexes were added.
+    Altered {
+        /// Names of the columns added.
+        added_columns: Vec<String>,
+        /// Names of the indexes created.
+        added_indexes: Vec<String>,
+    },
+    /// The table differs from its definition in a way which cannot be fixed
+    /// by adding columns. Nothing was changed.
+    Incompatible {
+        /// Every difference found.
+        differences: Vec<ColumnDifference>,
+    },
+}
+
+/// A difference between an existing table and its [`TableDef`] which
+/// [`Connection::ensure_table`] cannot fix.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum ColumnDifference {
+    /// The column is missing, and `ALTER TABLE ADD COLUMN` cannot add it: it
+    /// is part of the primary key, `NOT NULL` without a default, or its
+    /// default is an expression.
+    NotAddable {
+        /// The column name.
+        column: String,
+    },
+    /// The declared types differ.
+    Type {
+        /// The column name.
+        column: String,
+        /// The declared type in the definition, normalized.
+        expected: String,
+        /// The declared type in the table, normalized.
+        actual: String,
+    },
+    /// The column is `NOT NULL` in one and not the other.
+    NotNull {
+        /// The column name.
+        column: String,
+        /// Whether the definition declares it `NOT NULL`.
+        expected: bool,
+    },
+    /// The defaults differ.
+    Default {
+        /// The column name.
+        column: String,
+        /// The default in the definition.
+        expected: Option<DefaultValue>,
+        /// The default in the table.
+        actual: Option<DefaultValue>,
+    },
+    /// The column is part of the primary key in one and not the other.
+    PrimaryKey {
+        /// The column name.
+        column: String,
+        /// Whether the definition makes it part of the primary key.
+        expected: bool,
+    },
+}
+
+impl fmt::Display for ColumnDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColumnDifference::NotAddable { column } => {
+                write!(f, "column {} is missing and cannot be added", column)
+            }
+            ColumnDifference::Type {
+                column,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "column {} has type {:?} instead of {:?}",
+                column, actual, expected
+            ),
+            ColumnDifference::NotNull { column, expected } => write!(
+                f,
+                "column {} is {} instead of {}",
+                column,
+                if *expected { "nullable" } else { "NOT NULL" },
+                if *expected { "NOT NULL" } else { "nullable" },
+            ),
+            ColumnDifference::Default {
+                column,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "column {} has default {:?} instead of {:?}",
+                column, actual, expected
+            ),
+            ColumnDifference::PrimaryKey { column, expected } => write!(
+                f,
+                "column {} is {}part of the primary key",
+                column,
+                if *expected { "not " } else { "" }
+            ),
+        }
+    }
+}
+
+/// `varchar( 10 )` -> `VARCHAR(10)`
+fn normalize_type(decl_type: &str) -> String {
+    let upper = decl_type.to_ascii_uppercase();
+    let mut normalized = String::with_capacity(upper.len());
+    for word in upper.split_whitespace() {
+        if !normalized.is_empty()
+            && !normalized.ends_with(&['(', ','][..])
+            && !word.starts_with(&['(', ')', ','][..])
+        {
+            normalized.push(' ');
+        }
+        normalized.push_str(word);
+    }
+    normalized
+}
+
+fn default_sql(default: &DefaultValue) -> String {
+    let mut sql = Sql::new();
+    match default {
+        DefaultValue::Null => return "NULL".to_owned(),
+        DefaultValue::Integer(i) => sql.push_int(*i),
+        // `{:?}` keeps the decimal point, so the value reads back as a real
+        DefaultValue::Real(f) => return format!("{:?}", f),
+        DefaultValue::Text(s) => sql.push_string_literal(s),
+        DefaultValue::Blob(b) => {
+            let mut hex = String::with_capacity(3 + 2 * b.len());
+            hex.push_str("X'");
+            for byte in b {
+                let _ = write!(hex, "{:02X}", byte);
+            }
+            hex.push('\'');
+            return hex;
+        }
+        DefaultValue::Expression(e) => return format!("({})", e),
+    }
+    sql.as_str().to_owned()
+}
+
+fn column_differences(def: &ColumnDef, column: &TableColumn) -> Vec<ColumnDifference> {
+    let mut differences = Vec::new();
+    let (expected, actual) = (
+        normalize_type(&def.decl_type),
+        normalize_type(&column.decl_type),
+    );
+    if expected != actual {
+        differences.push(ColumnDifference::Type {
+            column: def.name.clone(),
+            expected,
+            actual,
+        });
+    }
+    if def.not_null != column.not_null {
+        differences.push(ColumnDifference::NotNull {
+            column: def.name.clone(),
+            expected: def.not_null,
+        });
+    }
+    if def.default != column.default {
+        differences.push(ColumnDifference::Default {
+            column: def.name.clone(),
+            expected: def.default.clone(),
+            actual: column.default.clone(),
+        });
+    }
+    if def.primary_key != (column.primary_key > 0) {
+        differences.push(ColumnDifference::PrimaryKey {
+            column: def.name.clone(),
+            expected: def.primary_key,
+        });
+    }
+    differences
+}
+
+impl Connection {
+    /// Makes sure the table `def` describes exists: creates it if it is
+    /// missing, or else adds its missing columns with
+    /// `ALTER TABLE ADD COLUMN` and creates its missing indexes.
+    ///
+    /// Existing columns are never changed or dropped, and columns missing from
+    /// the definition are left alone. If an existing column differs from its
+    /// definition, or a missing one cannot be added, nothing is changed and
+    /// [`EnsureOutcome::Incompatible`] lists the differences. Declared types
+    /// are compared case-insensitively and ignoring spacing; indexes are only
+    /// compared by name, and `strict` and `without_rowid` only apply when the
+    /// table is created.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if a statement fails, in which case nothing is
+    /// changed.
+    pub fn ensure_table(&self, def: &TableDef) -> Result<EnsureOutcome> {
+        let existing = self.table_info(&def.name)?;
+        let mut statements = Vec::new();
+        let outcome = if existing.is_empty() {
+            statements.push(def.create_sql());
+            statements.extend(def.indexes.iter().map(|index| def.create_index_sql(index)));
+            EnsureOutcome::Created
+        } else {
+            let mut differences = Vec::new();
+            let mut added_columns = Vec::new();
+            for column in &def.columns {
+                match existing
+                    .iter()
+                    .find(|c| c.name.eq_ignore_ascii_case(&column.name))
+                {
+                    Some(c) => differences.extend(column_differences(column, c)),
+                    None if column.is_addable() => {
+                        let mut sql = format!("ALTER TABLE {} ADD COLUMN ", identifier(&def.name));
+                        column.push_sql(&mut sql, false);
+                        statements.push(sql);
+                        added_columns.push(column.name.clone());
+                    }
+                    None => differences.push(ColumnDifference::NotAddable {
+                        column: column.name.clone(),
+                    }),
+                }
+            }
+            if !differences.is_empty() {
+                return Ok(EnsureOutcome::Incompatible { differences });
+            }
+            let mut indexes = Vec::new();
+            self.pragma(None, "index_list", &def.name, |row| {
+                indexes.push(row.get::<_, String>(1)?);
+                Ok(())
+            })?;
+            let mut added_indexes = Vec::new();
+            for index in &def.indexes {
+                if !indexes.iter().any(|i| i.eq_ignore_ascii_case(&index.name)) {
+                    statements.push(def.create_index_sql(index));
+                    added_indexes.push(index.name.clone());
+                }
+            }
+            if statements.is_empty() {
+                return Ok(EnsureOutcome::Unchanged);
+            }
+            EnsureOutcome::Altered {
+                added_columns,
+                added_indexes,
+            }
+        };
+
+        self.execute_transaction_sql("SAVEPOINT _rusqlite_ensure_table")?;
+        let r = statements
+            .iter()
+            .try_for_each(|sql| self.execute_batch(sql));
+        if r.is_ok() {
+            self.execute_transaction_sql("RELEASE _rusqlite_ensure_table")?;
+        } else {
+            self.execute_transaction_sql(
+                "ROLLBACK TO _rusqlite_ensure_table; RELEASE _rusqlite_ensure_table",
+            )?;
+        }
+        r.map(|_| outcome)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        ColumnDef, ColumnDifference, DefaultValue, EnsureOutcome, IndexDef, TableColumn, TableDef,
+    };
+    use crate::{Connection, Error, Result};
+
+    #[test]
+    fn test_parse_default() {
+        assert_eq!(DefaultValue::parse("NULL"), DefaultValue::Null);
+        assert_eq!(DefaultValue::parse("42"), DefaultValue::Integer(42));
+        assert_eq!(DefaultValue::parse("-7"), DefaultValue::Integer(-7));
+        assert_eq!(DefaultValue::parse("0x1F"), DefaultValue::Integer(31));
+        assert_eq!(DefaultValue::parse("TRUE"), DefaultValue::Integer(1));
+        assert_eq!(DefaultValue::parse("1.5"), DefaultValue::Real(1.5));
+        assert_eq!(DefaultValue::parse("-2e3"), DefaultValue::Real(-2000.0));
+        assert_eq!(DefaultValue::parse(".5"), DefaultValue::Real(0.5));
+        assert_eq!(
+            DefaultValue::parse("99999999999999999999"),
+            DefaultValue::Real(1e20)
+        );
+        assert_eq!(
+            DefaultValue::parse("'it''s'"),
+            DefaultValue::Text("it's".to_owned())
+        );
+        assert_eq!(DefaultValue::parse("''"), DefaultValue::Text(String::new()));
+        assert_eq!(
+            DefaultValue::parse("X'00fF'"),
+            DefaultValue::Blob(vec![0, 255])
+        );
+        for expr in &[
+            "CURRENT_TIMESTAMP",
+            "(1 + 2)",
+            "'a' || 'b'",
+            "X'0'",
+            "inf",
+            "\"abc\"",
+        ] {
+            assert_eq!(
+                DefaultValue::parse(expr),
+                DefaultValue::Expression((*expr).to_owned())
+            );
+        }
+    }
+
+    #[test]
+    fn test_table_info() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE foo(
+                id INTEGER PRIMARY KEY,
+                i INT DEFAULT 1,
+                r REAL DEFAULT -0.25,
+                t TEXT NOT NULL DEFAULT 'x',
+                b BLOB DEFAULT x'0102',
+                n DEFAULT NULL,
+                ts TEXT DEFAULT CURRENT_TIMESTAMP,
+                e DEFAULT (1 + 2),
+                plain
+            )",
+        )?;
+        let columns = db.table_info("foo")?;
+        let defaults: Vec<_> = columns.iter().map(|c| c.default.clone()).collect();
+        assert_eq!(
+            defaults,
+            vec![
+                None,
+                Some(DefaultValue::Integer(1)),
+                Some(DefaultValue::Real(-0.25)),
+                Some(DefaultValue::Text("x".to_owned())),
+                Some(DefaultValue::Blob(vec![1, 2])),
+                Some(DefaultValue::Null),
+                Some(DefaultValue::Expression("CURRENT_TIMESTAMP".to_owned())),
+                Some(DefaultValue::Expression("1 + 2".to_owned())),
+                None,
+            ]
+        );
+        assert_eq!(
+            columns[3],
+            TableColumn {
+                name: "t".to_owned(),
+                decl_type: "TEXT".to_owned(),
+                not_null: true,
+                default: Some(DefaultValue::Text("x".to_owned())),
+                primary_key: 0,
+            }
+        );
+        assert_eq!(columns[0].primary_key, 1);
+        assert!(db.table_info("missing")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_with_defaults() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE task(
+                id INTEGER PRIMARY KEY NOT NULL,
+                title TEXT NOT NULL,
+                owner TEXT NOT NULL,
+                status TEXT NOT NULL DEFAULT 'open',
+                note TEXT,
+                created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )",
+        )?;
+
+        let err = db
+            .insert_with_defaults("task", &[("note", &"n")])
+            .unwrap_err();
+        assert_eq!(
+            err,
+            Error::MissingColumnValues {
+                table: "task".to_owned(),
+                columns: vec!["title".to_owned(), "owner".to_owned()],
+            }
+        );
+        let err = db
+            .insert_with_defaults("task", &[("TITLE", &"t")])
+            .unwrap_err();
+        assert_eq!(
+            err,
+            Error::MissingColumnValues {
+                table: "task".to_owned(),
+                columns: vec!["owner".to_owned()],
+            }
+        );
+        let count: i64 = db.one_column("SELECT count(*) FROM task")?;
+        assert_eq!(count, 0);
+
+        let id = db.insert_with_defaults("task", &[("title", &"t"), ("owner", &"o")])?;
+        let (status, created): (String, String) = db.query_row(
+            "SELECT status, created_at FROM task WHERE id = ?1",
+            [id],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )?;
+        assert_eq!(status, "open");
+        assert!(!created.is_empty());
+
+        db.execute_batch("CREATE TABLE counter(n INTEGER DEFAULT 0)")?;
+        assert_eq!(db.insert_with_defaults("counter", &[])?, 1);
+        Ok(())
+    }
+
+    fn person() -> TableDef {
+        TableDef::new(
+            "person",
+            vec![
+                ColumnDef::new("id", "INTEGER").primary_key(),
+                ColumnDef::new("name", "VARCHAR(20)").not_null(),
+                ColumnDef::new("age", "INTEGER").default(DefaultValue::Integer(0)),
+            ],
+        )
+        .index(IndexDef::new("person_name", &["name"]).unique())
+    }
+
+    #[test]
+    fn test_ensure_table() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        assert_eq!(db.ensure_table(&person())?, EnsureOutcome::Created);
+        let columns = db.table_info("person")?;
+        assert_eq!(columns.len(), 3);
+        assert_eq!(columns[1].decl_type, "VARCHAR(20)");
+        assert_eq!(columns[2].default, Some(DefaultValue::Integer(0)));
+        let unique: bool = db.one_column("SELECT \"unique\" FROM pragma_index_list('person')")?;
+        assert!(unique);
+
+        assert_eq!(db.ensure_table(&person())?, EnsureOutcome::Unchanged);
+
+        // spelling differences are not differences
+        let mut def = person();
+        def.columns[1].decl_type = "varchar ( 20 )".to_owned();
+        assert_eq!(db.ensure_table(&def)?, EnsureOutcome::Unchanged);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ensure_table_altered() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE person(id INTEGER PRIMARY KEY, name VARCHAR(20) NOT NULL)")?;
+        db.execute("INSERT INTO person(name) VALUES (?1)", ["Ann"])?;
+
+        let mut def = person().index(IndexDef::new("person_age", &["age"]));
+        def.columns.push(
+            ColumnDef::new("email", "TEXT")
+                .not_null()
+                .default(DefaultValue::Text(String::new())),
+        );
+        assert_eq!(
+            db.ensure_table(&def)?,
+            EnsureOutcome::Altered {
+                added_columns: vec!["age".to_owned(), "email".to_owned()],
+                added_indexes: vec!["person_name".to_owned(), "person_age".to_owned()],
+            }
+        );
+        let (age, email): (i64, String) =
+            db.query_row("SELECT age, email FROM person", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })?;
+        assert_eq!((age, email.as_str()), (0, ""));
+        assert_eq!(db.ensure_table(&def)?, EnsureOutcome::Unchanged);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ensure_table_incompatible() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE person(id INTEGER PRIMARY KEY, name VARCHAR(20) NOT NULL, age TEXT DEFAULT 0)",
+        )?;
+        let mut def = person();
+        def.columns.push(ColumnDef::new("email", "TEXT").not_null());
+        let outcome = db.ensure_table(&def)?;
+        let differences = match outcome {
+            EnsureOutcome::Incompatible { differences } => differences,
+            outcome => panic!("Unexpected outcome {:?}", outcome),
+        };
+        assert_eq!(
+            differences,
+            vec![
+                ColumnDifference::Type {
+                    column: "age".to_owned(),
+                    expected: "INTEGER".to_owned(),
+                    actual: "TEXT".to_owned(),
+                },
+                ColumnDifference::NotAddable {
+                    column: "email".to_owned(),
+                },
+            ]
+        );
+        assert_eq!(
+            differences[0].to_string(),
+            "column age has type \"TEXT\" instead of \"INTEGER\""
+        );
+        // nothing was changed
+        assert!(db.table_info("person")?.iter().all(|c| c.name != "email"));
+        let indexes: i64 = db.one_column("SELECT count(*) FROM pragma_index_list('person')")?;
+        assert_eq!(indexes, 0);
+        Ok(())
+    }
+}
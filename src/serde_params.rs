@@ -0,0 +1,556 @@
+//! Bind the fields of a [`Serialize`] struct or map as named parameters.
+//!
+//! ```rust,no_run
+//! # use rusqlite::{named_params_from, Connection, Result};
+//! # use serde::Serialize;
+//! #[derive(Serialize)]
+//! struct Person {
+//!     name: String,
+//!     age: Option<u32>,
+//! }
+//!
+//! fn insert(conn: &Connection, person: &Person) -> Result<usize> {
+//!     conn.execute(
+//!         "INSERT INTO person (name, age) VALUES (:name, :age)",
+//!         named_params_from(person)?,
+//!     )
+//! }
+//! ```
+//!
+//! Each field is bound to the parameter of the same name, prefixed with `:`,
+//! `@` or `$`. Only flat structures can be bound: a field whose value is
+//! itself a struct, map, sequence or tuple is rejected.
+//!
+//! serde serializes a `Vec<u8>` or `&[u8]` as a sequence of integers, which
+//! is rejected too. To bind it as a blob, serialize it as bytes, e.g. with
+//! [`serde_bytes`](https://docs.rs/serde_bytes):
+//!
+//! ```rust,no_run
+//! # use serde::Serialize;
+//! #[derive(Serialize)]
+//! struct Attachment {
+//!     name: String,
+//!     #[serde(with = "serde_bytes")]
+//!     data: Option<Vec<u8>>,
+//! }
+//! ```
+use std::convert::TryFrom;
+use std::error::Error as StdError;
+use std::fmt;
+
+use serde::ser::{self, Impossible, Serialize};
+
+use crate::params::{Params, Sealed};
+use crate::types::{ToSqlOutput, Value};
+use crate::{Error, Result, Statement};
+
+/// Named parameters built from a [`Serialize`] value by
+/// [`named_params_from`].
+#[derive(Clone, Debug)]
+pub struct NamedParams {
+    params: Vec<(String, ToSqlOutput<'static>)>,
+    strict: bool,
+}
+
+/// Serializes `value`, which must be a flat struct or map, into named
+/// parameters.
+///
+/// By default, fields which are not referenced by the statement are ignored;
+/// use [`NamedParams::strict`] to reject them instead.
+///
+/// # Failure
+///
+/// Will return `Err` if `value` is not a struct or map, or if one of its
+/// fields cannot be bound as a single SQLite value.
+pub fn named_params_from<T: Serialize + ?Sized>(value: &T) -> Result<NamedParams> {
+    let params = value
+        .serialize(ParamsSerializer)
+        .map_err(|e| Error::ToSqlConversionFailure(Box::new(e)))?;
+    Ok(NamedParams {
+        params,
+        strict: false,
+    })
+}
+
+impl NamedParams {
+    /// Sets whether binding fails with [`Error::InvalidParameterName`] when a
+    /// field is not referenced by the statement.
+    #[inline]
+    #[must_use]
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Returns the parameter names and values, in serialization order.
+    #[inline]
+    #[must_use]
+    pub fn into_inner(self) -> Vec<(String, ToSqlOutput<'static>)> {
+        self.params
+    }
+}
+
+impl Sealed for NamedParams {}
+
+impl Params for NamedParams {
+    fn __bind_in(self, stmt: &mut Statement<'_>) -> Result<()> {
+        for (name, value) in self.params {
+            match stmt.parameter_index(&name)? {
+                Some(i) => stmt.raw_bind_parameter(i, value)?,
+                None if self.strict => return Err(Error::InvalidParameterName(name)),
+                None => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Error returned when a value cannot be serialized into named parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerializeError(String);
+
+impl fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl StdError for SerializeError {}
+
+impl ser::Error for SerializeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        SerializeError(msg.to_string())
+    }
+}
+
+type SerResult<T> = std::result::Result<T, SerializeError>;
+
+fn not_flat(what: &str) -> SerializeError {
+    SerializeError(format!(
+        "named parameters must be a struct or map, not {what}"
+    ))
+}
+
+fn nested(what: &str) -> SerializeError {
+    SerializeError(format!(
+        "{what} cannot be bound as a parameter, only flat structures are supported"
+    ))
+}
+
+// Also what a `Vec<u8>` serializes as.
+fn sequence() -> SerializeError {
+    SerializeError(
+        "a sequence cannot be bound as a parameter, serialize a byte vector as bytes to bind it \
+         as a blob, e.g. with `serde_bytes`"
+            .to_owned(),
+    )
+}
+
+// Serializes the top-level struct or map.
+struct ParamsSerializer;
+
+struct ParamsCollector {
+    params: Vec<(String, ToSqlOutput<'static>)>,
+    key: Option<String>,
+}
+
+macro_rules! reject {
+    ($($method:ident($($ty:ty),*) -> $ok:ty: $what:expr;)*) => {
+        $(
+            fn $method(self, $(_: $ty),*) -> SerResult<$ok> {
+                Err($what)
+            }
+        )*
+    };
+}
+
+impl ser::Serializer for ParamsSerializer {
+    type Ok = Vec<(String, ToSqlOutput<'static>)>;
+    type Error = SerializeError;
+    type SerializeSeq = Impossible<Self::Ok, SerializeError>;
+    type SerializeTuple = Impossible<Self::Ok, SerializeError>;
+    type SerializeTupleStruct = Impossible<Self::Ok, SerializeError>;
+    type SerializeTupleVariant = Impossible<Self::Ok, SerializeError>;
+    type SerializeMap = ParamsCollector;
+    type SerializeStruct = ParamsCollector;
+    type SerializeStructVariant = Impossible<Self::Ok, SerializeError>;
+
+    reject! {
+        serialize_bool(bool) -> Self::Ok: not_flat("a bool");
+        serialize_i8(i8) -> Self::Ok: not_flat("an integer");
+        serialize_i16(i16) -> Self::Ok: not_flat("an integer");
+        serialize_i32(i32) -> Self::Ok: not_flat("an integer");
+        serialize_i64(i64) -> Self::Ok: not_flat("an integer");
+        serialize_u8(u8) -> Self::Ok: not_flat("an integer");
+        serialize_u16(u16) -> Self::Ok: not_flat("an integer");
+        serialize_u32(u32) -> Self::Ok: not_flat("an integer");
+        serialize_u64(u64) -> Self::Ok: not_flat("an integer");
+        serialize_f32(f32) -> Self::Ok: not_flat("a float");
+        serialize_f64(f64) -> Self::Ok: not_flat("a float");
+        serialize_char(char) -> Self::Ok: not_flat("a char");
+        serialize_str(&str) -> Self::Ok: not_flat("a string");
+        serialize_bytes(&[u8]) -> Self::Ok: not_flat("bytes");
+        serialize_none() -> Self::Ok: not_flat("None");
+        serialize_unit() -> Self::Ok: not_flat("()");
+        serialize_unit_struct(&'static str) -> Self::Ok: not_flat("a unit struct");
+        serialize_unit_variant(&'static str, u32, &'static str) -> Self::Ok:
+            not_flat("an enum");
+        serialize_seq(Option<usize>) -> Self::SerializeSeq: not_flat("a sequence");
+        serialize_tuple(usize) -> Self::SerializeTuple: not_flat("a tuple");
+        serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct:
+            not_flat("a tuple struct");
+        serialize_tuple_variant(&'static str, u32, &'static str, usize)
+            -> Self::SerializeTupleVariant: not_flat("an enum");
+        serialize_struct_variant(&'static str, u32, &'static str, usize)
+            -> Self::SerializeStructVariant: not_flat("an enum");
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> SerResult<Self::Ok> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> SerResult<Self::Ok> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> SerResult<Self::Ok> {
+        Err(not_flat("an enum"))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> SerResult<Self::SerializeMap> {
+        Ok(ParamsCollector {
+            params: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _: &'static str, len: usize) -> SerResult<Self::SerializeStruct> {
+        self.serialize_map(Some(len))
+    }
+}
+
+impl ParamsCollector {
+    fn push<T: ?Sized + Serialize>(&mut self, key: String, value: &T) -> SerResult<()> {
+        let value = value
+            .serialize(ValueSerializer)
+            .map_err(|e| SerializeError(format!("field `{key}`: {e}")))?;
+        self.params.push((key, ToSqlOutput::Owned(value)));
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for ParamsCollector {
+    type Ok = Vec<(String, ToSqlOutput<'static>)>;
+    type Error = SerializeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> SerResult<()> {
+        self.push(key.to_owned(), value)
+    }
+
+    fn end(self) -> SerResult<Self::Ok> {
+        Ok(self.params)
+    }
+}
+
+impl ser::SerializeMap for ParamsCollector {
+    type Ok = Vec<(String, ToSqlOutput<'static>)>;
+    type Error = SerializeError;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> SerResult<()> {
+        match key.serialize(ValueSerializer)? {
+            Value::Text(key) => {
+                self.key = Some(key);
+                Ok(())
+            }
+            _ => Err(SerializeError(
+                "named parameter map keys must be strings".to_owned(),
+            )),
+        }
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> SerResult<()> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| SerializeError("map value serialized before its key".to_owned()))?;
+        self.push(key, value)
+    }
+
+    fn end(self) -> SerResult<Self::Ok> {
+        Ok(self.params)
+    }
+}
+
+// Serializes a single field into a SQLite value.
+struct ValueSerializer;
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = SerializeError;
+    type SerializeSeq = Impossible<Value, SerializeError>;
+    type SerializeTuple = Impossible<Value, SerializeError>;
+    type SerializeTupleStruct = Impossible<Value, SerializeError>;
+    type SerializeTupleVariant = Impossible<Value, SerializeError>;
+    type SerializeMap = Impossible<Value, SerializeError>;
+    type SerializeStruct = Impossible<Value, SerializeError>;
+    type SerializeStructVariant = Impossible<Value, SerializeError>;
+
+    fn serialize_bool(self, v: bool) -> SerResult<Value> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn serialize_i8(self, v: i8) -> SerResult<Value> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn serialize_i16(self, v: i16) -> SerResult<Value> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn serialize_i32(self, v: i32) -> SerResult<Value> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn serialize_i64(self, v: i64) -> SerResult<Value> {
+        Ok(Value::Integer(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> SerResult<Value> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn serialize_u16(self, v: u16) -> SerResult<Value> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn serialize_u32(self, v: u32) -> SerResult<Value> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn serialize_u64(self, v: u64) -> SerResult<Value> {
+        i64::try_from(v)
+            .map(Value::Integer)
+            .map_err(|_| SerializeError(format!("integer {v} is out of range for SQLite")))
+    }
+
+    fn serialize_f32(self, v: f32) -> SerResult<Value> {
+        Ok(Value::Real(v.into()))
+    }
+
+    fn serialize_f64(self, v: f64) -> SerResult<Value> {
+        Ok(Value::Real(v))
+    }
+
+    fn serialize_char(self, v: char) -> SerResult<Value> {
+        Ok(Value::Text(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> SerResult<Value> {
+        Ok(Value::Text(v.to_owned()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> SerResult<Value> {
+        Ok(Value::Blob(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> SerResult<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> SerResult<Value> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> SerResult<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> SerResult<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+    ) -> SerResult<Value> {
+        Ok(Value::Text(variant.to_owned()))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> SerResult<Value> {
+        value.serialize(self)
+    }
+
+    reject! {
+        serialize_seq(Option<usize>) -> Self::SerializeSeq: sequence();
+        serialize_tuple(usize) -> Self::SerializeTuple: nested("a tuple");
+        serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct:
+            nested("a tuple struct");
+        serialize_tuple_variant(&'static str, u32, &'static str, usize)
+            -> Self::SerializeTupleVariant: nested("an enum variant with data");
+        serialize_map(Option<usize>) -> Self::SerializeMap: nested("a map");
+        serialize_struct(&'static str, usize) -> Self::SerializeStruct: nested("a struct");
+        serialize_struct_variant(&'static str, u32, &'static str, usize)
+            -> Self::SerializeStructVariant: nested("an enum variant with data");
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> SerResult<Value> {
+        Err(nested("an enum variant with data"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::named_params_from;
+    use crate::{Connection, Error, Result};
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize)]
+    struct Person {
+        name: String,
+        age: Option<u32>,
+        #[serde(with = "serde_bytes")]
+        data: Option<Vec<u8>>,
+    }
+
+    #[derive(Serialize)]
+    struct RawBytes {
+        data: Vec<u8>,
+    }
+
+    #[derive(Serialize)]
+    struct Address {
+        city: &'static str,
+    }
+
+    #[derive(Serialize)]
+    struct Nested {
+        name: &'static str,
+        address: Address,
+    }
+
+    #[test]
+    fn test_named_params_from_struct() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE person (name TEXT, age INTEGER, data BLOB)")?;
+        let people = [
+            Person {
+                name: "Alice".to_owned(),
+                age: Some(30),
+                data: Some(vec![1, 2, 3]),
+            },
+            Person {
+                name: "Bob".to_owned(),
+                age: None,
+                data: None,
+            },
+        ];
+        let mut stmt =
+            db.prepare("INSERT INTO person (name, age, data) VALUES (:name, @age, $data)")?;
+        for person in &people {
+            stmt.execute(named_params_from(person)?)?;
+        }
+
+        let mut stmt = db.prepare("SELECT name, age, data FROM person ORDER BY name")?;
+        let rows = stmt
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))?
+            .collect::<Result<Vec<(String, Option<u32>, Option<Vec<u8>>)>>>()?;
+        assert_eq!(
+            rows,
+            vec![
+                ("Alice".to_owned(), Some(30), Some(vec![1, 2, 3])),
+                ("Bob".to_owned(), None, None)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_named_params_from_map() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        let mut map = BTreeMap::new();
+        map.insert("a", 1);
+        map.insert("b", 2);
+        let sum: i64 = db.query_row("SELECT $a + $b", named_params_from(&map)?, |r| r.get(0))?;
+        assert_eq!(sum, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        let person = Person {
+            name: "Alice".to_owned(),
+            age: None,
+            data: None,
+        };
+        let name: String =
+            db.query_row("SELECT :name", named_params_from(&person)?, |r| r.get(0))?;
+        assert_eq!(name, "Alice");
+
+        let err = db
+            .query_row(
+                "SELECT :name",
+                named_params_from(&person)?.strict(true),
+                |r| r.get::<_, String>(0),
+            )
+            .unwrap_err();
+        assert_eq!(err, Error::InvalidParameterName("age".to_owned()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_nested() {
+        let nested = Nested {
+            name: "Alice",
+            address: Address { city: "Paris" },
+        };
+        match named_params_from(&nested).unwrap_err() {
+            Error::ToSqlConversionFailure(err) => assert_eq!(
+                err.to_string(),
+                "field `address`: a struct cannot be bound as a parameter, only flat structures are supported"
+            ),
+            err => panic!("Unexpected error {}", err),
+        }
+        assert!(named_params_from(&[1, 2]).is_err());
+    }
+
+    #[test]
+    fn test_rejects_byte_sequence() {
+        let raw = RawBytes { data: vec![1, 2] };
+        match named_params_from(&raw).unwrap_err() {
+            Error::ToSqlConversionFailure(err) => assert!(
+                err.to_string().contains("e.g. with `serde_bytes`"),
+                "{}",
+                err
+            ),
+            err => panic!("Unexpected error {}", err),
+        }
+    }
+}
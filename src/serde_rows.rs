@@ -0,0 +1,637 @@
+//! Deserialize the rows of a query with [`serde`].
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result};
+//! # use serde::Deserialize;
+//! # use std::collections::HashMap;
+//! #[derive(Deserialize)]
+//! struct Person {
+//!     name: String,
+//!     age: Option<u32>,
+//! }
+//!
+//! fn load(conn: &Connection) -> Result<(Vec<Person>, HashMap<i64, Person>)> {
+//!     let people: Vec<Person> = conn.query_de("SELECT name, age FROM person", [])?;
+//!     let by_id = conn.query_de("SELECT id, name, age FROM person", [])?;
+//!     Ok((people, by_id))
+//! }
+//! ```
+//!
+//! A row is deserialized as a struct or map from its column names, as a tuple
+//! or sequence from its columns in order, and as a single value if it has
+//! only one column. The result of a query can be:
+//! - a sequence, like `Vec<T>`, with one element per row, deserialized as
+//!   they are stepped through;
+//! - a map, like `HashMap<K, V>`, keyed by the first column, with the other
+//!   columns as the value;
+//! - an `Option<T>`, for a query returning no row or one;
+//! - anything else, for a query returning exactly one row.
+use std::error::Error as StdError;
+use std::fmt;
+
+use fallible_streaming_iterator::FallibleStreamingIterator;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::forward_to_deserialize_any;
+
+use crate::types::ValueRef;
+use crate::{Connection, Error, Params, Result, Row, Rows};
+
+/// Deserializes `row`, as [`Connection::query_de`] does each row.
+///
+/// # Failure
+///
+/// Will return `Err` with [`Error::DeserializeFailure`] if `row` does not fit
+/// `T`.
+pub fn from_row<T: DeserializeOwned>(row: &Row<'_>) -> Result<T> {
+    T::deserialize(RowDeserializer {
+        row,
+        offset: 0,
+        number: None,
+    })
+    .map_err(|DeError(err)| err)
+}
+
+impl Connection {
+    /// Runs the query `sql` with `params` bound, and deserializes its rows as
+    /// a `T`. See the [module documentation](crate::serde_rows) for the
+    /// shapes `T` can take.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be prepared or run, with
+    /// [`Error::DeserializeFailure`] naming the row and column at fault if the
+    /// rows do not fit `T`, or with [`Error::QueryReturnedNoRows`] or
+    /// [`Error::QueryReturnedMoreThanOneRow`] if `T` takes a single row.
+    pub fn query_de<T, P>(&self, sql: &str, params: P) -> Result<T>
+    where
+        T: DeserializeOwned,
+        P: Params,
+    {
+        let mut stmt = self.prepare(sql)?;
+        let rows = stmt.query(params)?;
+        T::deserialize(QueryDeserializer { rows, number: 0 }).map_err(|DeError(err)| err)
+    }
+}
+
+// The serde error type, which wraps an `Error::DeserializeFailure` or the
+// error of a failed step.
+#[derive(Debug)]
+struct DeError(Error);
+
+impl DeError {
+    // Adds the row number and column name to an error that lacks them.
+    fn at(mut self, number: Option<usize>, name: Option<&str>) -> DeError {
+        if let Error::DeserializeFailure {
+            ref mut row,
+            ref mut column,
+            ..
+        } = self.0
+        {
+            if row.is_none() {
+                *row = number;
+            }
+            if column.is_none() {
+                *column = name.map(str::to_owned);
+            }
+        }
+        self
+    }
+}
+
+impl From<Error> for DeError {
+    fn from(err: Error) -> DeError {
+        DeError(err)
+    }
+}
+
+impl fmt::Display for DeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl StdError for DeError {}
+
+impl de::Error for DeError {
+    fn custom<T: fmt::Display>(msg: T) -> DeError {
+        DeError(Error::DeserializeFailure {
+            row: None,
+            column: None,
+            msg: msg.to_string(),
+        })
+    }
+}
+
+type DeResult<T> = std::result::Result<T, DeError>;
+
+struct QueryDeserializer<'stmt> {
+    rows: Rows<'stmt>,
+    // one-based number of the current row
+    number: usize,
+}
+
+impl QueryDeserializer<'_> {
+    fn next_row(&mut self) -> DeResult<Option<RowDeserializer<'_, '_>>> {
+        self.number += 1;
+        let number = Some(self.number);
+        Ok(self.rows.next()?.map(|row| RowDeserializer {
+            row,
+            offset: 0,
+            number,
+        }))
+    }
+
+    // Deserializes the only row, failing if there is no row or more than one.
+    fn single<T>(mut self, f: impl FnOnce(RowDeserializer<'_, '_>) -> DeResult<T>) -> DeResult<T> {
+        let value = match self.next_row()? {
+            Some(row) => f(row)?,
+            None => return Err(Error::QueryReturnedNoRows.into()),
+        };
+        if self.rows.next()?.is_some() {
+            return Err(Error::QueryReturnedMoreThanOneRow.into());
+        }
+        Ok(value)
+    }
+}
+
+// Deserializes the only row with the same method of `RowDeserializer`.
+macro_rules! single_row {
+    ($($method:ident($($arg:ident: $ty:ty),*))*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, $($arg: $ty,)* visitor: V) -> DeResult<V::Value> {
+                self.single(|row| {
+                    let number = row.number;
+                    row.$method($($arg,)* visitor).map_err(|err| err.at(number, None))
+                })
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for QueryDeserializer<'_> {
+    type Error = DeError;
+
+    fn deserialize_option<V: Visitor<'de>>(mut self, visitor: V) -> DeResult<V::Value> {
+        if self.rows.next()?.is_none() {
+            return visitor.visit_none();
+        }
+        self.number = 1;
+        let value = visitor.visit_some(RowDeserializer {
+            row: self.rows.get().unwrap(),
+            offset: 0,
+            number: Some(1),
+        })?;
+        if self.rows.next()?.is_some() {
+            return Err(Error::QueryReturnedMoreThanOneRow.into());
+        }
+        Ok(value)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        visitor.visit_seq(self)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        visitor.visit_map(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> DeResult<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    single_row! {
+        deserialize_any() deserialize_bool() deserialize_i8() deserialize_i16()
+        deserialize_i32() deserialize_i64() deserialize_i128() deserialize_u8()
+        deserialize_u16() deserialize_u32() deserialize_u64() deserialize_u128()
+        deserialize_f32() deserialize_f64() deserialize_char() deserialize_str()
+        deserialize_string() deserialize_bytes() deserialize_byte_buf()
+        deserialize_unit() deserialize_identifier() deserialize_ignored_any()
+        deserialize_unit_struct(name: &'static str)
+        deserialize_tuple(len: usize)
+        deserialize_tuple_struct(name: &'static str, len: usize)
+        deserialize_struct(name: &'static str, fields: &'static [&'static str])
+        deserialize_enum(name: &'static str, variants: &'static [&'static str])
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for QueryDeserializer<'_> {
+    type Error = DeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> DeResult<Option<T::Value>> {
+        match self.next_row()? {
+            Some(row) => {
+                let number = row.number;
+                seed.deserialize(row)
+                    .map(Some)
+                    .map_err(|err| err.at(number, None))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+impl<'de> de::MapAccess<'de> for QueryDeserializer<'_> {
+    type Error = DeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> DeResult<Option<K::Value>> {
+        match self.next_row()? {
+            Some(row) => {
+                let number = row.number;
+                let name = row.column_name(0)?;
+                seed.deserialize(ValueDeserializer(row.row.get_ref(0)?))
+                    .map(Some)
+                    .map_err(|err| err.at(number, Some(name)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> DeResult<V::Value> {
+        let number = Some(self.number);
+        let row = RowDeserializer {
+            row: self.rows.get().unwrap(),
+            offset: 1,
+            number,
+        };
+        seed.deserialize(row).map_err(|err| err.at(number, None))
+    }
+}
+
+// Deserializes the columns of `row` from `offset` on.
+struct RowDeserializer<'r, 'stmt> {
+    row: &'r Row<'stmt>,
+    offset: usize,
+    number: Option<usize>,
+}
+
+impl RowDeserializer<'_, '_> {
+    fn column_count(&self) -> usize {
+        self.row.as_ref().column_count()
+    }
+
+    fn column_name(&self, idx: usize) -> DeResult<&str> {
+        Ok(self.row.as_ref().column_name(idx)?)
+    }
+
+    // Returns the only column, failing if there are several.
+    fn single_column(&self) -> DeResult<(ValueDeserializer<'_>, &str)> {
+        let count = self.column_count() - self.offset;
+        if count != 1 {
+            return Err(de::Error::custom(format_args!(
+                "expected a single column, found {count}"
+            )));
+        }
+        let value = self.row.get_ref(self.offset)?;
+        Ok((ValueDeserializer(value), self.column_name(self.offset)?))
+    }
+}
+
+// Deserializes the only column with the same method of `ValueDeserializer`.
+macro_rules! single_column {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+                let (value, name) = self.single_column()?;
+                value.$method(visitor).map_err(|err| err.at(self.number, Some(name)))
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for RowDeserializer<'_, '_> {
+    type Error = DeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        if self.column_count() - self.offset == 1 {
+            let (value, name) = self.single_column()?;
+            value
+                .deserialize_option(visitor)
+                .map_err(|err| err.at(self.number, Some(name)))
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        visitor.visit_map(ColumnAccess {
+            idx: self.offset,
+            row: self,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> DeResult<V::Value> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        visitor.visit_seq(ColumnAccess {
+            idx: self.offset,
+            row: self,
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> DeResult<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> DeResult<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> DeResult<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> DeResult<V::Value> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> DeResult<V::Value> {
+        let (value, column) = self.single_column()?;
+        value
+            .deserialize_enum(name, variants, visitor)
+            .map_err(|err| err.at(self.number, Some(column)))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        visitor.visit_unit()
+    }
+
+    single_column! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32
+        deserialize_i64 deserialize_i128 deserialize_u8 deserialize_u16
+        deserialize_u32 deserialize_u64 deserialize_u128 deserialize_f32
+        deserialize_f64 deserialize_char deserialize_str deserialize_string
+        deserialize_bytes deserialize_byte_buf deserialize_unit
+        deserialize_identifier
+    }
+}
+
+// Steps through the columns of a row, as map entries keyed by column name or
+// as sequence elements.
+struct ColumnAccess<'r, 'stmt> {
+    row: RowDeserializer<'r, 'stmt>,
+    idx: usize,
+}
+
+impl<'de> de::MapAccess<'de> for ColumnAccess<'_, '_> {
+    type Error = DeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> DeResult<Option<K::Value>> {
+        if self.idx == self.row.column_count() {
+            return Ok(None);
+        }
+        let name = self.row.column_name(self.idx)?;
+        seed.deserialize(name.into_deserializer()).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> DeResult<V::Value> {
+        let idx = self.idx;
+        self.idx += 1;
+        let name = self.row.column_name(idx)?;
+        seed.deserialize(ValueDeserializer(self.row.row.get_ref(idx)?))
+            .map_err(|err| err.at(self.row.number, Some(name)))
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for ColumnAccess<'_, '_> {
+    type Error = DeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> DeResult<Option<T::Value>> {
+        if self.idx == self.row.column_count() {
+            return Ok(None);
+        }
+        de::MapAccess::next_value_seed(self, seed).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.row.column_count() - self.idx)
+    }
+}
+
+// Deserializes a single SQLite value.
+struct ValueDeserializer<'a>(ValueRef<'a>);
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'_> {
+    type Error = DeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        match self.0 {
+            ValueRef::Null => visitor.visit_unit(),
+            ValueRef::Integer(i) => visitor.visit_i64(i),
+            ValueRef::Real(f) => visitor.visit_f64(f),
+            ValueRef::Text(s) => match std::str::from_utf8(s) {
+                Ok(s) => visitor.visit_str(s),
+                Err(err) => Err(de::Error::custom(err)),
+            },
+            ValueRef::Blob(b) => visitor.visit_bytes(b),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        match self.0 {
+            ValueRef::Integer(i) => visitor.visit_bool(i != 0),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        match self.0 {
+            ValueRef::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> DeResult<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> DeResult<V::Value> {
+        match self.0 {
+            ValueRef::Text(s) => match std::str::from_utf8(s) {
+                Ok(s) => visitor.visit_enum(s.into_deserializer()),
+                Err(err) => Err(de::Error::custom(err)),
+            },
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use serde::Deserialize;
+
+    use super::from_row;
+    use crate::{Connection, Error, Result};
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Player {
+        name: String,
+        score: f64,
+        team: Option<String>,
+    }
+
+    fn players() -> Result<Connection> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE player (id INTEGER PRIMARY KEY, name TEXT, score REAL, team TEXT);
+             INSERT INTO player VALUES (1, 'ann', 3.5, 'red');
+             INSERT INTO player VALUES (2, 'bob', 2, NULL);
+             INSERT INTO player VALUES (3, 'cid', 'abc', 'blue');",
+        )?;
+        Ok(db)
+    }
+
+    #[test]
+    fn test_vec() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE t (x INTEGER, y TEXT)")?;
+        let tx = db.unchecked_transaction()?;
+        for i in 0..10_000 {
+            tx.execute("INSERT INTO t VALUES (?1, ?2)", (i, format!("row {i}")))?;
+        }
+        tx.commit()?;
+
+        #[derive(Deserialize)]
+        struct Item {
+            x: i64,
+            y: String,
+        }
+        let items: Vec<Item> = db.query_de("SELECT x, y FROM t ORDER BY x", [])?;
+        assert_eq!(items.len(), 10_000);
+        assert_eq!((items[42].x, items[42].y.as_str()), (42, "row 42"));
+
+        let tuples: Vec<(i64, String)> = db.query_de("SELECT x, y FROM t WHERE x < 2", [])?;
+        assert_eq!(tuples, [(0, "row 0".to_owned()), (1, "row 1".to_owned())]);
+        let xs: Vec<u32> = db.query_de("SELECT x FROM t WHERE x < 3", [])?;
+        assert_eq!(xs, [0, 1, 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_map_and_single() -> Result<()> {
+        let db = players()?;
+        let by_id: HashMap<i64, Player> =
+            db.query_de("SELECT id, name, score, team FROM player WHERE id < 3", [])?;
+        assert_eq!(by_id.len(), 2);
+        assert_eq!(
+            by_id[&2],
+            Player {
+                name: "bob".to_owned(),
+                score: 2.0,
+                team: None
+            }
+        );
+        let names: HashMap<String, Option<String>> =
+            db.query_de("SELECT name, team FROM player", [])?;
+        assert_eq!(names["ann"].as_deref(), Some("red"));
+
+        let ann: Player = db.query_de("SELECT name, score, team FROM player WHERE id = 1", [])?;
+        assert_eq!(ann.team.as_deref(), Some("red"));
+        let none: Option<Player> =
+            db.query_de("SELECT name, score, team FROM player WHERE id = 9", [])?;
+        assert_eq!(none, None);
+        let count: i64 = db.query_de("SELECT count(*) FROM player", [])?;
+        assert_eq!(count, 3);
+
+        let err = db
+            .query_de::<Player, _>("SELECT name, score, team FROM player WHERE id < 3", [])
+            .unwrap_err();
+        assert_eq!(err, Error::QueryReturnedMoreThanOneRow);
+        let err = db
+            .query_de::<Player, _>("SELECT name, score, team FROM player WHERE id = 9", [])
+            .unwrap_err();
+        assert_eq!(err, Error::QueryReturnedNoRows);
+
+        let mut stmt = db.prepare("SELECT name, score, team FROM player WHERE id = 2")?;
+        let bob: Player = stmt.query_row([], from_row)?;
+        assert_eq!(bob.name, "bob");
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors() -> Result<()> {
+        let db = players()?;
+        let err = db
+            .query_de::<Vec<Player>, _>("SELECT name, score, team FROM player ORDER BY id", [])
+            .unwrap_err();
+        match err {
+            Error::DeserializeFailure {
+                row: Some(3),
+                column: Some(ref column),
+                ..
+            } if column == "score" => {}
+            err => panic!("Unexpected error {}", err),
+        }
+        assert!(err
+            .to_string()
+            .starts_with("Deserializing row 3, column \"score\" failed"));
+
+        let err = db
+            .query_de::<Vec<Player>, _>("SELECT name, team FROM player", [])
+            .unwrap_err();
+        match err {
+            Error::DeserializeFailure {
+                row: Some(1),
+                column: None,
+                ref msg,
+            } if msg.contains("score") => {}
+            err => panic!("Unexpected error {}", err),
+        }
+        Ok(())
+    }
+}
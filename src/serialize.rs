@@ -0,0 +1,370 @@
+//! Copy a whole database to and from memory with
+//! [`sqlite3_serialize`](https://sqlite.org/c3ref/serialize.html) and
+//! [`sqlite3_deserialize`](https://sqlite.org/c3ref/deserialize.html).
+//!
+//! ```rust
+//! # use rusqlite::{Connection, Result, MAIN_DB};
+//! # use rusqlite::serialize::DeserializeFlags;
+//! fn clone_memory_db(src: &Connection) -> Result<Connection> {
+//!     let data = src.serialize(MAIN_DB)?;
+//!     let dst = Connection::open_in_memory()?;
+//!     dst.deserialize_owned(MAIN_DB, data, DeserializeFlags::SQLITE_DESERIALIZE_RESIZEABLE)?;
+//!     Ok(dst)
+//! }
+//! ```
+use std::convert::TryFrom;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::ptr::{self, NonNull};
+use std::{fmt, mem, slice};
+
+use crate::pragma::Sql;
+use crate::{ffi, Connection, DatabaseName, Error, Result, MAIN_DB};
+
+bitflags::bitflags! {
+    /// Flags for [`Connection::deserialize`]. See
+    /// [sqlite3_deserialize](https://sqlite.org/c3ref/c_deserialize_freeonclose.html)
+    /// for details.
+    #[repr(C)]
+    pub struct DeserializeFlags: ::std::os::raw::c_int {
+        /// SQLite frees the buffer when the database is closed. Always set
+        /// for a buffer allocated by SQLite.
+        const SQLITE_DESERIALIZE_FREE_ON_CLOSE = ffi::SQLITE_DESERIALIZE_FREEONCLOSE;
+        /// The database can grow beyond the size of the buffer, which SQLite
+        /// then reallocates.
+        const SQLITE_DESERIALIZE_RESIZEABLE = ffi::SQLITE_DESERIALIZE_RESIZEABLE;
+        /// The database is read-only.
+        const SQLITE_DESERIALIZE_READ_ONLY = ffi::SQLITE_DESERIALIZE_READONLY;
+    }
+}
+
+impl Default for DeserializeFlags {
+    #[inline]
+    fn default() -> DeserializeFlags {
+        DeserializeFlags::SQLITE_DESERIALIZE_RESIZEABLE
+    }
+}
+
+/// The content of a database serialized by [`Connection::serialize`], in a
+/// buffer allocated by SQLite and freed on drop.
+pub struct OwnedSerializedDb {
+    data: NonNull<u8>,
+    len: usize,
+}
+
+// The buffer is only owned by this value, and `sqlite3_free` is thread-safe.
+unsafe impl Send for OwnedSerializedDb {}
+unsafe impl Sync for OwnedSerializedDb {}
+
+impl OwnedSerializedDb {
+    /// Copy `data` into a buffer allocated by SQLite, e.g. to pass it to
+    /// [`Connection::deserialize_owned`].
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the buffer cannot be allocated.
+    pub fn from_slice(data: &[u8]) -> Result<OwnedSerializedDb> {
+        let owned = OwnedSerializedDb::alloc(data.len())?;
+        unsafe { ptr::copy_nonoverlapping(data.as_ptr(), owned.data.as_ptr(), data.len()) };
+        Ok(owned)
+    }
+
+    // Allocates an uninitialized buffer of `len` bytes.
+    fn alloc(len: usize) -> Result<OwnedSerializedDb> {
+        // sqlite3_malloc64(0) returns NULL
+        let size = u64::try_from(len.max(1)).unwrap();
+        let data = unsafe { ffi::sqlite3_malloc64(size) };
+        match NonNull::new(data.cast()) {
+            Some(data) => Ok(OwnedSerializedDb { data, len }),
+            None => Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_NOMEM),
+                Some(format!("cannot allocate {len} bytes")),
+            )),
+        }
+    }
+
+    // Gives up the buffer, to be freed by SQLite.
+    fn into_raw(self) -> (*mut u8, usize) {
+        let raw = (self.data.as_ptr(), self.len);
+        mem::forget(self);
+        raw
+    }
+}
+
+impl Deref for OwnedSerializedDb {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.data.as_ptr(), self.len) }
+    }
+}
+
+impl AsRef<[u8]> for OwnedSerializedDb {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl fmt::Debug for OwnedSerializedDb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OwnedSerializedDb")
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl Drop for OwnedSerializedDb {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { ffi::sqlite3_free(self.data.as_ptr().cast()) };
+    }
+}
+
+impl Connection {
+    /// Copy the content of the database `db` into memory, as it would be
+    /// stored in a file.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `db` is not a database of this connection, or if
+    /// the copy cannot be allocated.
+    pub fn serialize(&self, db: DatabaseName<'_>) -> Result<OwnedSerializedDb> {
+        let name = db.as_cstring()?;
+        let mut size: ffi::sqlite3_int64 = 0;
+        let data = {
+            let c = self.db.borrow();
+            unsafe { ffi::sqlite3_serialize(c.db(), name.as_ptr(), &mut size, 0) }
+        };
+        if let Some(data) = NonNull::new(data) {
+            return Ok(OwnedSerializedDb {
+                data,
+                len: size as usize,
+            });
+        }
+        // an empty database has nothing to allocate
+        let page_count: u32 = self.pragma_query_value(Some(db), "page_count", |row| row.get(0))?;
+        if page_count == 0 {
+            return OwnedSerializedDb::alloc(0);
+        }
+        Err(Error::SqliteFailure(
+            ffi::Error::new(ffi::SQLITE_NOMEM),
+            Some("cannot serialize the database".to_owned()),
+        ))
+    }
+
+    /// Replace the database `db` with a copy of `data`, the content of a
+    /// database file, e.g. as returned by
+    /// [`serialize`](Connection::serialize).
+    ///
+    /// The copy is allocated by SQLite, and freed when the database is
+    /// closed, whether `flags` contains `SQLITE_DESERIALIZE_FREE_ON_CLOSE` or
+    /// not. Without `SQLITE_DESERIALIZE_RESIZEABLE`, the database cannot grow
+    /// beyond the size of `data`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `db` is not a database of this connection, is
+    /// `temp` or is in use, or with `ErrorCode::NotADatabase` if `data` is
+    /// not the content of a database.
+    pub fn deserialize(
+        &self,
+        db: DatabaseName<'_>,
+        data: &[u8],
+        flags: DeserializeFlags,
+    ) -> Result<()> {
+        self.deserialize_owned(db, OwnedSerializedDb::from_slice(data)?, flags)
+    }
+
+    /// Replace the database `db` with `data` without copying it, SQLite
+    /// taking ownership of the buffer.
+    ///
+    /// # Failure
+    ///
+    /// Fails as [`deserialize`](Connection::deserialize) does.
+    pub fn deserialize_owned(
+        &self,
+        db: DatabaseName<'_>,
+        data: OwnedSerializedDb,
+        flags: DeserializeFlags,
+    ) -> Result<()> {
+        let name = db.as_cstring()?;
+        let (data, len) = data.into_raw();
+        let flags = flags | DeserializeFlags::SQLITE_DESERIALIZE_FREE_ON_CLOSE;
+        // SQLite frees the buffer even if this fails
+        unsafe { self.deserialize_raw(db, &name, data, len, flags) }
+    }
+
+    // Replaces the database `db` with the `len` bytes at `data`, which must
+    // outlive it unless `flags` contains SQLITE_DESERIALIZE_FREE_ON_CLOSE,
+    // and which SQLite writes to unless `flags` contains
+    // SQLITE_DESERIALIZE_READ_ONLY.
+    unsafe fn deserialize_raw(
+        &self,
+        db: DatabaseName<'_>,
+        name: &std::ffi::CStr,
+        data: *mut u8,
+        len: usize,
+        flags: DeserializeFlags,
+    ) -> Result<()> {
+        {
+            let c = self.db.borrow();
+            let size = len as ffi::sqlite3_int64;
+            let r = ffi::sqlite3_deserialize(
+                c.db(),
+                name.as_ptr(),
+                data,
+                size,
+                size,
+                flags.bits() as _,
+            );
+            c.decode_result(r)?;
+        }
+        // SQLite only finds out that the content is not a database when it
+        // reads the schema
+        let mut sql = Sql::new();
+        sql.push_schema_name(db);
+        self.query_row(
+            &format!("SELECT count(*) FROM {}.sqlite_master", sql.as_str()),
+            [],
+            |_| Ok(()),
+        )
+    }
+}
+
+/// An in-memory connection reading a database from a buffer it borrows,
+/// without copying it. The database is read-only.
+///
+/// It dereferences to the [`Connection`].
+///
+/// ```rust
+/// # use rusqlite::{Connection, Result, MAIN_DB};
+/// # use rusqlite::serialize::BorrowedConnection;
+/// fn count(data: &[u8]) -> Result<i64> {
+///     let conn = BorrowedConnection::open(data)?;
+///     conn.query_row("SELECT count(*) FROM sqlite_master", [], |row| row.get(0))
+/// }
+/// ```
+pub struct BorrowedConnection<'a> {
+    conn: Connection,
+    data: PhantomData<&'a [u8]>,
+}
+
+impl<'a> BorrowedConnection<'a> {
+    /// Open the database whose content is `data`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` with `ErrorCode::NotADatabase` if `data` is not the
+    /// content of a database, or if the connection cannot be opened.
+    pub fn open(data: &'a [u8]) -> Result<BorrowedConnection<'a>> {
+        let conn = Connection::open_in_memory()?;
+        let name = MAIN_DB.as_cstring()?;
+        // with SQLITE_DESERIALIZE_READ_ONLY, SQLite never writes to `data`
+        unsafe {
+            conn.deserialize_raw(
+                MAIN_DB,
+                &name,
+                data.as_ptr() as *mut u8,
+                data.len(),
+                DeserializeFlags::SQLITE_DESERIALIZE_READ_ONLY,
+            )?;
+        }
+        Ok(BorrowedConnection {
+            conn,
+            data: PhantomData,
+        })
+    }
+}
+
+impl Deref for BorrowedConnection<'_> {
+    type Target = Connection;
+
+    #[inline]
+    fn deref(&self) -> &Connection {
+        &self.conn
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BorrowedConnection, DeserializeFlags};
+    use crate::{Connection, ErrorCode, Result, MAIN_DB};
+
+    fn populated() -> Result<Connection> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE foo(x INTEGER, y TEXT);
+             INSERT INTO foo VALUES (1, 'one'), (2, 'two'), (3, 'three');",
+        )?;
+        Ok(db)
+    }
+
+    fn rows(db: &Connection) -> Result<Vec<(i64, String)>> {
+        let mut stmt = db.prepare("SELECT x, y FROM foo ORDER BY x")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    #[test]
+    fn test_serialize() -> Result<()> {
+        let src = populated()?;
+        let data = src.serialize(MAIN_DB)?;
+        let page_size: usize = src.pragma_query_value(None, "page_size", |row| row.get(0))?;
+        assert_eq!(data.len() % page_size, 0);
+        assert_eq!(&data[..16], b"SQLite format 3\0");
+
+        let copy = Connection::open_in_memory()?;
+        copy.deserialize(MAIN_DB, &data, DeserializeFlags::default())?;
+        assert_eq!(rows(&copy)?, rows(&src)?);
+        // resizeable: the copy can grow
+        copy.execute_batch("INSERT INTO foo SELECT x + 3, y FROM foo")?;
+        assert_eq!(rows(&copy)?.len(), 6);
+        assert_eq!(rows(&src)?.len(), 3);
+
+        let owned = Connection::open_in_memory()?;
+        owned.deserialize_owned(MAIN_DB, data, DeserializeFlags::default())?;
+        assert_eq!(rows(&owned)?, rows(&src)?);
+
+        // an empty database
+        let empty = Connection::open_in_memory()?.serialize(MAIN_DB)?;
+        assert!(empty.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_read_only() -> Result<()> {
+        let data = populated()?.serialize(MAIN_DB)?.to_vec();
+        let copy = Connection::open_in_memory()?;
+        copy.deserialize(
+            MAIN_DB,
+            &data,
+            DeserializeFlags::SQLITE_DESERIALIZE_READ_ONLY,
+        )?;
+        assert_eq!(rows(&copy)?.len(), 3);
+        let err = copy.execute("DELETE FROM foo", []).unwrap_err();
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::ReadOnly));
+
+        let borrowed = BorrowedConnection::open(&data)?;
+        assert_eq!(rows(&borrowed)?, rows(&copy)?);
+        let err = borrowed.execute("DELETE FROM foo", []).unwrap_err();
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::ReadOnly));
+        drop(borrowed);
+        assert_eq!(data, populated()?.serialize(MAIN_DB)?.to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_not_a_database() -> Result<()> {
+        let garbage = vec![0x55; 4096];
+        let db = Connection::open_in_memory()?;
+        let err = db
+            .deserialize(MAIN_DB, &garbage, DeserializeFlags::default())
+            .unwrap_err();
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::NotADatabase));
+        let err = BorrowedConnection::open(&garbage).err().unwrap();
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::NotADatabase));
+        Ok(())
+    }
+}
@@ -8,14 +8,16 @@ use std::os::raw::{c_char, c_int, c_uchar, c_void};
 use std::panic::{catch_unwind, RefUnwindSafe};
 use std::ptr;
 use std::slice::{from_raw_parts, from_raw_parts_mut};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
 use fallible_streaming_iterator::FallibleStreamingIterator;
 
 use crate::error::{check, error_from_sqlite_code};
 use crate::ffi;
 use crate::hooks::Action;
-use crate::types::ValueRef;
-use crate::{errmsg_to_string, str_to_cstring, Connection, DatabaseName, Result};
+use crate::types::{self, ValueRef};
+use crate::{errmsg_to_string, str_to_cstring, Connection, DatabaseName, Error, ErrorCode, Result};
 
 // https://sqlite.org/session.html
 
@@ -426,7 +428,7 @@ impl ChangesetItem {
                 col as i32,
                 &mut p_value,
             ))?;
-            Ok(ValueRef::from_value(p_value))
+            Ok(types::value_ref(p_value))
         }
     }
 
@@ -446,26 +448,33 @@ impl ChangesetItem {
     /// Obtain new.* Values
     ///
     /// May only be called if the type of change is either `SQLITE_UPDATE` or
-    /// `SQLITE_INSERT`.
+    /// `SQLITE_INSERT`. For an update, the columns left unchanged are `Null`.
     #[inline]
     pub fn new_value(&self, col: usize) -> Result<ValueRef<'_>> {
         unsafe {
             let mut p_value: *mut ffi::sqlite3_value = ptr::null_mut();
             check(ffi::sqlite3changeset_new(self.it, col as i32, &mut p_value))?;
-            Ok(ValueRef::from_value(p_value))
+            if p_value.is_null() {
+                return Ok(ValueRef::Null);
+            }
+            Ok(types::value_ref(p_value))
         }
     }
 
     /// Obtain old.* Values
     ///
     /// May only be called if the type of change is either `SQLITE_DELETE` or
-    /// `SQLITE_UPDATE`.
+    /// `SQLITE_UPDATE`. For an update, the columns left unchanged other than
+    /// the primary key are `Null`.
     #[inline]
     pub fn old_value(&self, col: usize) -> Result<ValueRef<'_>> {
         unsafe {
             let mut p_value: *mut ffi::sqlite3_value = ptr::null_mut();
             check(ffi::sqlite3changeset_old(self.it, col as i32, &mut p_value))?;
-            Ok(ValueRef::from_value(p_value))
+            if p_value.is_null() {
+                return Ok(ValueRef::Null);
+            }
+            Ok(types::value_ref(p_value))
         }
     }
 
@@ -649,6 +658,154 @@ impl Connection {
             }
         })
     }
+
+    /// Apply a changeset to a database, calling `progress` every `every`
+    /// modified rows.
+    ///
+    /// If `progress` returns `false`, no more of the changeset is read and
+    /// [`Error::Cancelled`] is returned. The changeset is applied inside a
+    /// savepoint, so none of its changes are kept in that case. Interrupting
+    /// the connection through an [`InterruptHandle`](crate::InterruptHandle)
+    /// or aborting from the conflict handler with
+    /// [`ConflictAction::SQLITE_CHANGESET_ABORT`] is reported the same way.
+    ///
+    /// Rows are counted with the update hook, so changes to `WITHOUT ROWID`
+    /// tables are not reported, and this fails if an update hook is already
+    /// registered with [`Connection::update_hook`].
+    pub fn apply_strm_with_progress<F, C, P>(
+        &self,
+        input: &mut dyn Read,
+        filter: Option<F>,
+        conflict: C,
+        every: u64,
+        mut progress: P,
+    ) -> Result<ApplyReport>
+    where
+        F: Fn(&str) -> bool + Send + RefUnwindSafe + 'static,
+        C: Fn(ConflictType, ChangesetItem) -> ConflictAction + Send + RefUnwindSafe + 'static,
+        P: FnMut(&ApplyProgress) -> bool + Send + 'static,
+    {
+        if self.db.borrow().free_update_hook.is_some() {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_MISUSE),
+                Some("an update hook is already registered".to_owned()),
+            ));
+        }
+        let every = every.max(1);
+        let state = Arc::new(Mutex::new(ApplyProgress::default()));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let conflicts = Arc::new(AtomicU64::new(0));
+
+        // SQLite only notices the cancellation once it needs more input, so
+        // the changeset is applied inside our own savepoint to be able to
+        // undo the rows applied since.
+        self.execute_transaction_sql("SAVEPOINT _rusqlite_apply_strm")?;
+
+        let hook_state = state.clone();
+        let hook_cancelled = cancelled.clone();
+        self.update_hook(Some(
+            move |action: Action, _db: &str, table: &str, _rowid: i64| {
+                if hook_cancelled.load(Ordering::Relaxed) {
+                    return;
+                }
+                let mut state = hook_state.lock().unwrap();
+                match action {
+                    Action::SQLITE_INSERT => state.inserts += 1,
+                    Action::SQLITE_UPDATE => state.updates += 1,
+                    Action::SQLITE_DELETE => state.deletes += 1,
+                    _ => return,
+                }
+                if !state.tables.iter().any(|t| t == table) {
+                    state.tables.push(table.to_owned());
+                }
+                if state.operations() % every == 0 && !progress(&state) {
+                    hook_cancelled.store(true, Ordering::Relaxed);
+                }
+            },
+        ));
+        let counter = conflicts.clone();
+        let mut input = CancellableRead {
+            inner: input,
+            cancelled: &cancelled,
+        };
+        let r = self.apply_strm(&mut input, filter, move |conflict_type, item| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            conflict(conflict_type, item)
+        });
+        self.update_hook(None::<fn(Action, &str, &str, i64)>);
+
+        let cancelled = cancelled.load(Ordering::Relaxed)
+            || matches!(
+                r.as_ref().err().and_then(Error::sqlite_error_code),
+                Some(ErrorCode::OperationAborted | ErrorCode::OperationInterrupted)
+            );
+        if r.is_ok() && !cancelled {
+            self.execute_transaction_sql("RELEASE _rusqlite_apply_strm")?;
+        } else {
+            self.execute_transaction_sql(
+                "ROLLBACK TO _rusqlite_apply_strm; RELEASE _rusqlite_apply_strm",
+            )?;
+        }
+        if cancelled {
+            return Err(Error::Cancelled);
+        }
+        r?;
+        let progress = state.lock().unwrap().clone();
+        Ok(ApplyReport {
+            progress,
+            conflicts: conflicts.load(Ordering::Relaxed),
+        })
+    }
+}
+
+// Fails reads once the changeset application has been cancelled.
+struct CancellableRead<'a> {
+    inner: &'a mut dyn Read,
+    cancelled: &'a AtomicBool,
+}
+
+impl Read for CancellableRead<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err(std::io::ErrorKind::Other.into());
+        }
+        self.inner.read(buf)
+    }
+}
+
+/// Progress of a changeset being applied by
+/// [`Connection::apply_strm_with_progress`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ApplyProgress {
+    /// Number of rows inserted so far.
+    pub inserts: u64,
+    /// Number of rows updated so far.
+    pub updates: u64,
+    /// Number of rows deleted so far.
+    pub deletes: u64,
+    /// Tables modified so far, in the order they were first modified.
+    pub tables: Vec<String>,
+}
+
+impl ApplyProgress {
+    /// Returns the total number of rows modified so far.
+    #[inline]
+    #[must_use]
+    pub fn operations(&self) -> u64 {
+        self.inserts + self.updates + self.deletes
+    }
+}
+
+/// Summary of a changeset applied by
+/// [`Connection::apply_strm_with_progress`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ApplyReport {
+    /// Rows and tables modified by the changeset.
+    pub progress: ApplyProgress,
+    /// Number of times the conflict handler was invoked.
+    pub conflicts: u64,
 }
 
 /// Constants passed to the conflict handler
@@ -765,11 +922,12 @@ unsafe extern "C" fn x_output(p_out: *mut c_void, data: *const c_void, len: c_in
 mod test {
     use fallible_streaming_iterator::FallibleStreamingIterator;
     use std::io::Read;
-    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
 
     use super::{Changeset, ChangesetIter, ConflictAction, ConflictType, Session};
     use crate::hooks::Action;
-    use crate::{Connection, Result};
+    use crate::{Connection, Error, Result};
 
     fn one_changeset() -> Result<Changeset> {
         let db = Connection::open_in_memory()?;
@@ -894,6 +1052,108 @@ mod test {
         Ok(())
     }
 
+    fn large_changeset_strm() -> Result<Vec<u8>> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(id INTEGER PRIMARY KEY, t TEXT NOT NULL);")?;
+
+        let mut session = Session::new(&db)?;
+        session.attach(None)?;
+        db.execute_batch(
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 1000)
+             INSERT INTO foo SELECT i, 'row ' || i FROM n;",
+        )?;
+
+        let mut output = Vec::new();
+        session.changeset_strm(&mut output)?;
+        Ok(output)
+    }
+
+    #[test]
+    fn test_changeset_apply_strm_with_progress() -> Result<()> {
+        let output = large_changeset_strm()?;
+
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(id INTEGER PRIMARY KEY, t TEXT NOT NULL);")?;
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let report = db.apply_strm_with_progress(
+            &mut output.as_slice(),
+            None::<fn(&str) -> bool>,
+            |_conflict_type, _item| ConflictAction::SQLITE_CHANGESET_OMIT,
+            100,
+            move |progress| {
+                let calls = counter.fetch_add(1, Ordering::Relaxed) + 1;
+                assert_eq!(progress.inserts, 100 * calls as u64);
+                true
+            },
+        )?;
+        assert_eq!(calls.load(Ordering::Relaxed), 10);
+        assert_eq!(report.progress.inserts, 1000);
+        assert_eq!(report.progress.operations(), 1000);
+        assert_eq!(report.progress.tables, vec!["foo".to_owned()]);
+        assert_eq!(report.conflicts, 0);
+
+        let count: i64 = db.query_row("SELECT count(*) FROM foo", [], |r| r.get(0))?;
+        assert_eq!(count, 1000);
+        Ok(())
+    }
+
+    #[test]
+    fn test_changeset_apply_strm_cancelled() -> Result<()> {
+        let output = large_changeset_strm()?;
+
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(id INTEGER PRIMARY KEY, t TEXT NOT NULL);")?;
+
+        let err = db
+            .apply_strm_with_progress(
+                &mut output.as_slice(),
+                None::<fn(&str) -> bool>,
+                |_conflict_type, _item| ConflictAction::SQLITE_CHANGESET_OMIT,
+                100,
+                |progress| progress.operations() < 500,
+            )
+            .unwrap_err();
+        assert_eq!(err, Error::Cancelled);
+
+        let count: i64 = db.query_row("SELECT count(*) FROM foo", [], |r| r.get(0))?;
+        assert_eq!(count, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_changeset_apply_strm_conflicts() -> Result<()> {
+        let output = large_changeset_strm()?;
+
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE foo(id INTEGER PRIMARY KEY, t TEXT NOT NULL);
+             WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 10)
+             INSERT INTO foo SELECT i * 100, 'existing' FROM n;",
+        )?;
+
+        let report = db.apply_strm_with_progress(
+            &mut output.as_slice(),
+            None::<fn(&str) -> bool>,
+            |conflict_type, _item| {
+                assert_eq!(ConflictType::SQLITE_CHANGESET_CONFLICT, conflict_type);
+                ConflictAction::SQLITE_CHANGESET_OMIT
+            },
+            100,
+            |_progress| true,
+        )?;
+        assert_eq!(report.conflicts, 10);
+        assert_eq!(report.progress.inserts, 990);
+
+        let existing: i64 =
+            db.query_row("SELECT count(*) FROM foo WHERE t = 'existing'", [], |r| {
+                r.get(0)
+            })?;
+        assert_eq!(existing, 10);
+        Ok(())
+    }
+
     #[test]
     fn test_session_empty() -> Result<()> {
         let db = Connection::open_in_memory()?;
@@ -0,0 +1,327 @@
+//! Queries across databases attached as shards, e.g. one file per month of a
+//! time series.
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result};
+//! use rusqlite::shard::ShardSet;
+//!
+//! fn total(conn: &Connection) -> Result<f64> {
+//!     let shards = ShardSet::attach(conn, ["2024-01.db", "2024-02.db", "2024-03.db"])?;
+//!     let sums = shards.query_union(
+//!         "SELECT sum(v) FROM {shard}.samples WHERE ts >= ?1",
+//!         [1_700_000_000],
+//!         |row| row.get::<_, Option<f64>>(0),
+//!     )?;
+//!     Ok(sums.into_iter().flatten().sum())
+//! }
+//! ```
+use std::path::Path;
+
+use crate::pragma::Sql;
+use crate::{ffi, Connection, Error, Params, Result, Row};
+
+/// The placeholder replaced by the quoted name of each shard in the SQL given
+/// to [`ShardSet::query_union`] and [`ShardSet::query_each`].
+pub const SHARD_PLACEHOLDER: &str = "{shard}";
+
+/// Databases attached to a connection as shards of the same data, which are
+/// detached when the set is dropped.
+#[derive(Debug)]
+pub struct ShardSet<'conn> {
+    conn: &'conn Connection,
+    names: Vec<String>,
+}
+
+impl<'conn> ShardSet<'conn> {
+    /// Attaches the database files at `paths`, each under its file name
+    /// without the extension, e.g. `2024-01` for `data/2024-01.db`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the shards would go over the limit of attached
+    /// databases (`SQLITE_LIMIT_ATTACHED`, 10 by default), in which case
+    /// nothing is attached, if a file name is not valid UTF-8, or if a file
+    /// cannot be attached.
+    pub fn attach<I>(conn: &'conn Connection, paths: I) -> Result<ShardSet<'conn>>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<Path>,
+    {
+        let shards = paths
+            .into_iter()
+            .map(|path| {
+                let path = path.as_ref();
+                let name = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .ok_or_else(|| Error::InvalidPath(path.to_owned()))?;
+                Ok((name.to_owned(), path.to_owned()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        ShardSet::attach_named(conn, shards)
+    }
+
+    /// Attaches the database files at the given paths, each under the given
+    /// schema name.
+    ///
+    /// # Failure
+    ///
+    /// See [`attach`](ShardSet::attach).
+    pub fn attach_named<I, N, P>(conn: &'conn Connection, shards: I) -> Result<ShardSet<'conn>>
+    where
+        I: IntoIterator<Item = (N, P)>,
+        N: Into<String>,
+        P: AsRef<Path>,
+    {
+        let shards: Vec<(String, P)> = shards.into_iter().map(|(n, p)| (n.into(), p)).collect();
+        let limit =
+            unsafe { ffi::sqlite3_limit(conn.handle(), ffi::SQLITE_LIMIT_ATTACHED, -1) } as usize;
+        // `main` and `temp` do not count towards the limit
+        let attached = conn
+            .prepare("PRAGMA database_list")?
+            .query_map([], |_| Ok(()))?
+            .count()
+            .saturating_sub(2);
+        if attached + shards.len() > limit {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_ERROR),
+                Some(format!(
+                    "cannot attach {} shards: {attached} of at most {limit} databases are \
+                     already attached; query the shards in batches",
+                    shards.len()
+                )),
+            ));
+        }
+        let mut set = ShardSet {
+            conn,
+            names: Vec::with_capacity(shards.len()),
+        };
+        for (name, path) in shards {
+            let path = path.as_ref();
+            let file = path
+                .to_str()
+                .ok_or_else(|| Error::InvalidPath(path.to_owned()))?;
+            // on error, the shards attached so far are detached by `drop`
+            conn.execute(
+                &format!("ATTACH DATABASE ?1 AS {}", identifier(&name)),
+                [file],
+            )?;
+            set.names.push(name);
+        }
+        Ok(set)
+    }
+
+    /// Returns the schema names of the shards, in the order they were
+    /// attached.
+    #[inline]
+    #[must_use]
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Runs `sql` as one query over every shard, with the results combined by
+    /// `UNION ALL`, and maps each row with `f`.
+    ///
+    /// `sql` is a `SELECT` in which `{shard}` stands for the quoted schema
+    /// name of a shard, e.g. `SELECT ts, v FROM {shard}.samples WHERE ts > ?1`.
+    /// As the statement is repeated once per shard, parameters should be
+    /// numbered or named so that each copy uses the same values.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the combined statement cannot be prepared, e.g.
+    /// because the shards have different schemas (see
+    /// [`query_each`](ShardSet::query_each)), or if `f` fails.
+    pub fn query_union<T, P, F>(&self, sql: &str, params: P, f: F) -> Result<Vec<T>>
+    where
+        P: Params,
+        F: FnMut(&Row<'_>) -> Result<T>,
+    {
+        let sql = self
+            .names
+            .iter()
+            .map(|name| substitute(sql, name))
+            .collect::<Vec<_>>()
+            .join(" UNION ALL ");
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(params, f)?;
+        rows.collect()
+    }
+
+    /// Runs `sql` on each shard in turn, and maps each row with `f`, which
+    /// is also passed the name of the shard.
+    ///
+    /// `sql` is as for [`query_union`](ShardSet::query_union), but the query
+    /// is prepared separately for each shard, so shards can have different
+    /// schemas.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the statement cannot be prepared for a shard, or
+    /// if `f` fails.
+    pub fn query_each<T, P, F>(&self, sql: &str, params: P, mut f: F) -> Result<Vec<T>>
+    where
+        P: Params + Clone,
+        F: FnMut(&str, &Row<'_>) -> Result<T>,
+    {
+        let mut values = Vec::new();
+        for name in &self.names {
+            let mut stmt = self.conn.prepare(&substitute(sql, name))?;
+            let mut rows = stmt.query(params.clone())?;
+            while let Some(row) = rows.next()? {
+                values.push(f(name, row)?);
+            }
+        }
+        Ok(values)
+    }
+
+    /// Detaches the shards, reporting the first error. Dropping the set
+    /// detaches them too, ignoring errors.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if a shard cannot be detached, e.g. because a
+    /// statement reading from it is still running.
+    pub fn detach(mut self) -> Result<()> {
+        self.detach_()
+    }
+
+    fn detach_(&mut self) -> Result<()> {
+        let mut result = Ok(());
+        for name in self.names.drain(..).rev() {
+            let r = self
+                .conn
+                .execute_batch(&format!("DETACH DATABASE {}", identifier(&name)));
+            if result.is_ok() {
+                result = r;
+            }
+        }
+        result
+    }
+}
+
+impl Drop for ShardSet<'_> {
+    #[allow(unused_must_use)]
+    fn drop(&mut self) {
+        self.detach_();
+    }
+}
+
+fn identifier(name: &str) -> String {
+    let mut sql = Sql::new();
+    sql.push_identifier(name);
+    sql.as_str().to_owned()
+}
+
+// Replaces the placeholder in `sql` with the quoted `shard` name.
+fn substitute(sql: &str, shard: &str) -> String {
+    sql.replace(SHARD_PLACEHOLDER, &identifier(shard))
+}
+
+#[cfg(test)]
+mod test {
+    use super::ShardSet;
+    use crate::{Connection, Result};
+
+    fn create_shards(dir: &std::path::Path, months: &[&str]) -> Result<Vec<std::path::PathBuf>> {
+        let mut paths = Vec::new();
+        for (i, month) in months.iter().enumerate() {
+            let path = dir.join(format!("{month}.db"));
+            let db = Connection::open(&path)?;
+            db.execute_batch("CREATE TABLE samples (ts INTEGER, v REAL)")?;
+            db.execute(
+                "INSERT INTO samples VALUES (?1, ?2)",
+                [i as f64, i as f64 * 10.0],
+            )?;
+            db.execute("INSERT INTO samples VALUES (?1, ?2)", [i as f64 + 0.5, 1.0])?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+
+    #[test]
+    fn test_query_union_and_each() -> Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = create_shards(dir.path(), &["2024-01", "2024-02", "2024-03"])?;
+        let db = Connection::open_in_memory()?;
+        {
+            let shards = ShardSet::attach(&db, &paths)?;
+            assert_eq!(shards.names(), ["2024-01", "2024-02", "2024-03"]);
+
+            let mut values =
+                shards.query_union("SELECT v FROM {shard}.samples WHERE ts >= ?1", [1], |row| {
+                    row.get::<_, f64>(0)
+                })?;
+            values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            assert_eq!(values, [1.0, 1.0, 10.0, 20.0]);
+
+            let counts = shards.query_each(
+                "SELECT count(*) FROM {shard}.samples WHERE v > ?1",
+                [5],
+                |shard, row| Ok((shard.to_owned(), row.get::<_, i64>(0)?)),
+            )?;
+            assert_eq!(
+                counts,
+                [
+                    ("2024-01".to_owned(), 0),
+                    ("2024-02".to_owned(), 1),
+                    ("2024-03".to_owned(), 1)
+                ]
+            );
+        }
+        // detached on drop
+        let attached = db
+            .prepare("PRAGMA database_list")?
+            .query_map([], |_| Ok(()))?
+            .count();
+        assert_eq!(attached, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_attach_limit() -> Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let months: Vec<String> = (1..=11).map(|m| format!("2024-{m:02}")).collect();
+        let months: Vec<&str> = months.iter().map(String::as_str).collect();
+        let paths = create_shards(dir.path(), &months)?;
+        let db = Connection::open_in_memory()?;
+
+        let err = ShardSet::attach(&db, &paths).unwrap_err();
+        assert!(err.to_string().contains("in batches"), "{}", err);
+        let attached = db
+            .prepare("PRAGMA database_list")?
+            .query_map([], |_| Ok(()))?
+            .count();
+        assert_eq!(attached, 1);
+
+        let shards = ShardSet::attach(&db, &paths[..10])?;
+        assert_eq!(shards.names().len(), 10);
+        shards.detach()
+    }
+
+    #[test]
+    fn test_hostile_shard_name() -> Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = create_shards(dir.path(), &["a"])?;
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE samples (ts INTEGER, v REAL)")?;
+
+        let name = "x\".samples; DROP TABLE main.samples; --";
+        let shards = ShardSet::attach_named(&db, [(name, &paths[0])])?;
+        let rows =
+            shards.query_each("SELECT count(*) FROM {shard}.samples", [], |shard, row| {
+                assert_eq!(shard, name);
+                row.get::<_, i64>(0)
+            })?;
+        assert_eq!(rows, [2]);
+        assert_eq!(
+            shards.query_union("SELECT v FROM {shard}.samples", [], |row| row
+                .get::<_, f64>(0))?,
+            [0.0, 1.0]
+        );
+        shards.detach()?;
+        let tables: i64 = db.one_column("SELECT count(*) FROM main.sqlite_master")?;
+        assert_eq!(tables, 1);
+        Ok(())
+    }
+}
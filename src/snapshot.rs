@@ -0,0 +1,553 @@
+//! Reads of a WAL database as it was at an earlier point in time, using
+//! [snapshots](https://sqlite.org/c3ref/snapshot.html).
+//!
+//! A [`Snapshot`] records the state of a database seen by a read
+//! transaction, which another connection can then read with
+//! [`Connection::snapshot_open`]. It can only be opened as long as no
+//! checkpoint has copied later changes into the database file, which a read
+//! transaction still using the snapshot prevents.
+//!
+//! A [`SnapshotRegistry`] builds on this to read the database as of a given
+//! [`Instant`]: a background thread takes a snapshot every `granularity`,
+//! and keeps each pinned by a read transaction on a dedicated connection for
+//! `retention`.
+//!
+//! ```rust,no_run
+//! # use rusqlite::Result;
+//! use rusqlite::snapshot::SnapshotRegistry;
+//! use std::time::{Duration, Instant};
+//!
+//! fn thirty_seconds_ago(registry: &SnapshotRegistry) -> Result<i64> {
+//!     let at = Instant::now() - Duration::from_secs(30);
+//!     let conn = registry.read_at(at)?;
+//!     conn.query_row("SELECT count(*) FROM orders", [], |row| row.get(0))
+//! }
+//! ```
+//!
+//! Requires SQLite built with `SQLITE_ENABLE_SNAPSHOT`, as it is with the
+//! `bundled` feature, and a database in WAL mode.
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::{ffi, Connection, DatabaseName, Error, OpenFlags, Result, MAIN_DB};
+
+/// The state of a database seen by a read transaction, taken with
+/// [`Connection::snapshot_get`].
+///
+/// A snapshot is not tied to the connection it was taken from: any
+/// connection to the same database can open it.
+pub struct Snapshot {
+    snapshot: *mut ffi::sqlite3_snapshot,
+}
+
+// A snapshot is a plain copy of the WAL header, which SQLite only reads.
+unsafe impl Send for Snapshot {}
+unsafe impl Sync for Snapshot {}
+
+impl Drop for Snapshot {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { ffi::sqlite3_snapshot_free(self.snapshot) };
+    }
+}
+
+impl Snapshot {
+    /// Compare the age of two snapshots: `Less` if this one is older than
+    /// `other`, see
+    /// [`sqlite3_snapshot_cmp`](https://sqlite.org/c3ref/snapshot_cmp.html).
+    ///
+    /// Only snapshots of the same database, taken since its WAL was last
+    /// restarted, can be compared: the result for others is meaningless.
+    #[inline]
+    #[must_use]
+    pub fn cmp_age(&self, other: &Snapshot) -> Ordering {
+        unsafe { ffi::sqlite3_snapshot_cmp(self.snapshot, other.snapshot) }.cmp(&0)
+    }
+}
+
+impl Connection {
+    /// Take a snapshot of the database `db` as seen by the read transaction
+    /// of this connection.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if no transaction was started with `BEGIN`, or if
+    /// `db` is not in WAL mode. Older versions of SQLite also fail if no
+    /// statement has read from `db` since `BEGIN`, where newer ones start the
+    /// read transaction.
+    pub fn snapshot_get(&self, db: DatabaseName<'_>) -> Result<Snapshot> {
+        self.check_snapshot_transaction("snapshot_get")?;
+        let name = db.as_cstring()?;
+        let c = self.db.borrow();
+        let mut snapshot = ptr::null_mut();
+        let r = unsafe { ffi::sqlite3_snapshot_get(c.db(), name.as_ptr(), &mut snapshot) };
+        c.decode_result(r).map(|_| Snapshot { snapshot })
+    }
+
+    /// Start the read transaction on the database `db` at `snapshot`, so
+    /// that this connection reads the database as it was when the snapshot
+    /// was taken, until the transaction ends.
+    ///
+    /// A transaction must have been started with `BEGIN`, and no statement
+    /// may be running. SQLite only finds out that `db` is in WAL mode once it
+    /// has been read, so a new connection must read from it first, e.g. from
+    /// `sqlite_master`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if no transaction was started, if `db` is not in WAL
+    /// mode or was never read, or with `ErrorCode::Unknown` and the extended
+    /// code `SQLITE_ERROR_SNAPSHOT` if a checkpoint has made the snapshot
+    /// unavailable.
+    pub fn snapshot_open(&self, db: DatabaseName<'_>, snapshot: &Snapshot) -> Result<()> {
+        self.check_snapshot_transaction("snapshot_open")?;
+        let mode: String = self.pragma_query_value(Some(db), "journal_mode", |row| row.get(0))?;
+        if !mode.eq_ignore_ascii_case("wal") {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_ERROR),
+                Some(format!(
+                    "snapshot_open needs a database in WAL mode, not {mode}: read from it first \
+                     if it is"
+                )),
+            ));
+        }
+        let name = db.as_cstring()?;
+        let c = self.db.borrow();
+        let r = unsafe { ffi::sqlite3_snapshot_open(c.db(), name.as_ptr(), snapshot.snapshot) };
+        c.decode_result(r)
+    }
+
+    /// Make the snapshots of the database `db` that are still in its WAL
+    /// available again once it has been closed and reopened, e.g. after a
+    /// restart of the application, as the WAL index does not persist them.
+    /// See [`sqlite3_snapshot_recover`](https://sqlite.org/c3ref/snapshot_recover.html).
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if a read transaction is open on `db`, or if `db`
+    /// is not in WAL mode.
+    #[cfg(feature = "modern_sqlite")] // 3.21.0
+    #[cfg_attr(docsrs, doc(cfg(feature = "modern_sqlite")))]
+    pub fn snapshot_recover(&self, db: DatabaseName<'_>) -> Result<()> {
+        let name = db.as_cstring()?;
+        let c = self.db.borrow();
+        let r = unsafe { ffi::sqlite3_snapshot_recover(c.db(), name.as_ptr()) };
+        c.decode_result(r)
+    }
+
+    fn check_snapshot_transaction(&self, function: &str) -> Result<()> {
+        if self.is_autocommit() {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_MISUSE),
+                Some(format!(
+                    "{function} must be called in a transaction started with BEGIN"
+                )),
+            ));
+        }
+        Ok(())
+    }
+}
+
+// A snapshot, kept available by the read transaction of `_reader`.
+struct Pin {
+    taken_at: Instant,
+    snapshot: Snapshot,
+    _reader: Connection,
+}
+
+struct Pins {
+    pins: Vec<Pin>,
+    max: usize,
+}
+
+/// Snapshots of a WAL database taken in the background, to read it as it
+/// was at an earlier point in time with [`read_at`](SnapshotRegistry::read_at).
+///
+/// Each snapshot is kept available by a read transaction on a dedicated
+/// connection, for the retention period. As long as that transaction is
+/// open, no checkpoint can copy changes made after the snapshot into the
+/// database file, and the WAL cannot be restarted: it grows with all the
+/// writes made during the retention period, whatever the `wal_autocheckpoint`
+/// setting, and `wal_checkpoint(RESTART)` or `(TRUNCATE)` keep failing with
+/// `SQLITE_BUSY`. Choose the retention accordingly.
+///
+/// Dropping the registry stops the background thread and releases every
+/// snapshot.
+pub struct SnapshotRegistry {
+    path: PathBuf,
+    retention: Duration,
+    pins: Arc<Mutex<Pins>>,
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SnapshotRegistry {
+    /// Start taking a snapshot of the database at `path` every
+    /// `granularity`, keeping each for `retention`.
+    ///
+    /// At most enough snapshots to cover the retention period are kept,
+    /// see [`set_max_snapshots`](SnapshotRegistry::set_max_snapshots). The
+    /// first snapshot is taken before this returns.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `granularity` is zero, if the database is not in
+    /// WAL mode, or if it cannot be opened.
+    pub fn new<P: AsRef<Path>>(
+        path: P,
+        retention: Duration,
+        granularity: Duration,
+    ) -> Result<SnapshotRegistry> {
+        if granularity.is_zero() {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_MISUSE),
+                Some("the snapshot granularity must not be zero".to_owned()),
+            ));
+        }
+        let path = path.as_ref().to_owned();
+        let first = take_pin(&path)?;
+        let covered = retention.as_nanos() / granularity.as_nanos();
+        let pins = Arc::new(Mutex::new(Pins {
+            pins: vec![first],
+            max: usize::try_from(covered)
+                .unwrap_or(usize::MAX)
+                .saturating_add(1),
+        }));
+        let (stop, stopped) = mpsc::channel();
+        let thread = {
+            let (path, pins) = (path.clone(), pins.clone());
+            thread::spawn(move || loop {
+                match stopped.recv_timeout(granularity) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => return,
+                }
+                // a failure, e.g. SQLITE_BUSY, only leaves a gap
+                let pin = take_pin(&path).ok();
+                let mut pins = pins.lock().unwrap();
+                pins.pins.extend(pin);
+                pins.release(retention);
+            })
+        };
+        Ok(SnapshotRegistry {
+            path,
+            retention,
+            pins,
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+
+    /// Set the maximum number of snapshots kept, releasing the oldest ones
+    /// beyond it.
+    pub fn set_max_snapshots(&self, max: usize) {
+        let mut pins = self.pins.lock().unwrap();
+        pins.max = max.max(1);
+        pins.release(self.retention);
+    }
+
+    /// Return the number of snapshots currently kept.
+    #[must_use]
+    pub fn snapshot_count(&self) -> usize {
+        self.pins.lock().unwrap().pins.len()
+    }
+
+    /// Open a new connection to the database, reading it as it was at `at`:
+    /// its read transaction is at the last snapshot taken at or before `at`,
+    /// or at the latest one if `at` is more recent.
+    ///
+    /// The connection is in a transaction, which should be ended with
+    /// `ROLLBACK`, or by dropping the connection, to read the current state
+    /// of the database again. It keeps the snapshot available until then,
+    /// even if the registry has released it.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err(Error::SnapshotTooOld)` if `at` is older than the
+    /// retention period or than every snapshot kept,
+    /// `Err(Error::SnapshotInvalidated)` if a checkpoint has made the
+    /// snapshot unavailable, or `Err` if the database cannot be opened.
+    pub fn read_at(&self, at: Instant) -> Result<Connection> {
+        let now = Instant::now();
+        let requested = now.saturating_duration_since(at);
+        let pins = self.pins.lock().unwrap();
+        let oldest = pins.pins.first().map(|pin| pin.taken_at);
+        let pin = pins
+            .pins
+            .iter()
+            .rev()
+            .find(|pin| pin.taken_at <= at)
+            .filter(|_| requested <= self.retention);
+        let pin = match pin {
+            Some(pin) => pin,
+            None => {
+                return Err(Error::SnapshotTooOld {
+                    requested,
+                    oldest: oldest.map_or(Duration::ZERO, |oldest| {
+                        now.saturating_duration_since(oldest)
+                    }),
+                })
+            }
+        };
+        let conn = Connection::open_with_flags(&self.path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        conn.execute_batch("BEGIN")?;
+        read_schema(&conn)?;
+        match conn.snapshot_open(MAIN_DB, &pin.snapshot) {
+            Ok(()) => Ok(conn),
+            Err(Error::SqliteFailure(err, _))
+                if err.extended_code == ffi::SQLITE_ERROR_SNAPSHOT =>
+            {
+                Err(Error::SnapshotInvalidated)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl Pins {
+    // Release the snapshots older than `retention`, and the oldest ones
+    // beyond the maximum, always keeping the latest one.
+    fn release(&mut self, retention: Duration) {
+        let now = Instant::now();
+        let expired = self
+            .pins
+            .iter()
+            .take(self.pins.len().saturating_sub(1))
+            .take_while(|pin| now.saturating_duration_since(pin.taken_at) > retention)
+            .count();
+        let excess = self.pins.len().saturating_sub(self.max);
+        // dropping the connections ends their read transactions
+        self.pins.drain(..expired.max(excess));
+    }
+}
+
+impl Drop for SnapshotRegistry {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// Opens a connection to `path` with a read transaction, and takes its
+// snapshot.
+fn take_pin(path: &Path) -> Result<Pin> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    // everything committed before `taken_at` is in the snapshot
+    let taken_at = Instant::now();
+    conn.execute_batch("BEGIN")?;
+    read_schema(&conn)?;
+    let snapshot = conn.snapshot_get(MAIN_DB)?;
+    Ok(Pin {
+        taken_at,
+        snapshot,
+        _reader: conn,
+    })
+}
+
+// Reads the schema, which starts the read transaction of a transaction.
+fn read_schema(conn: &Connection) -> Result<()> {
+    conn.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::cmp::Ordering;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::SnapshotRegistry;
+    use crate::{Connection, Error, ErrorCode, Result, MAIN_DB};
+
+    fn wal_db(path: &std::path::Path) -> Result<Connection> {
+        let db = Connection::open(path)?;
+        db.pragma_update_and_check(None, "journal_mode", "wal", |_| Ok(()))?;
+        db.execute_batch("CREATE TABLE foo(v INTEGER); INSERT INTO foo VALUES (0);")?;
+        Ok(db)
+    }
+
+    // Returns (busy, log, checkpointed) of a passive checkpoint.
+    fn checkpoint(db: &Connection) -> Result<(i64, i64, i64)> {
+        db.query_row("PRAGMA wal_checkpoint(PASSIVE)", [], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })
+    }
+
+    #[test]
+    fn test_snapshot() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let db = wal_db(&path)?;
+
+        let reader = Connection::open(&path)?;
+        reader.execute_batch("BEGIN")?;
+        let v: i64 = reader.one_column("SELECT v FROM foo")?;
+        assert_eq!(v, 0);
+        let snapshot = reader.snapshot_get(MAIN_DB)?;
+        db.execute_batch("UPDATE foo SET v = 1; INSERT INTO foo VALUES (2);")?;
+
+        let other = Connection::open(&path)?;
+        other.execute_batch("BEGIN")?;
+        other.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))?;
+        other.snapshot_open(MAIN_DB, &snapshot)?;
+        let v: i64 = other.one_column("SELECT v FROM foo")?;
+        assert_eq!(v, 0);
+        let count: i64 = other.one_column("SELECT count(*) FROM foo")?;
+        assert_eq!(count, 1);
+        other.execute_batch("ROLLBACK")?;
+        let count: i64 = other.one_column("SELECT count(*) FROM foo")?;
+        assert_eq!(count, 2);
+
+        // no transaction
+        let err = db.snapshot_get(MAIN_DB).err().unwrap();
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::ApiMisuse));
+        let err = other.snapshot_open(MAIN_DB, &snapshot).unwrap_err();
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::ApiMisuse));
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_cmp() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let db = wal_db(&path)?;
+        let take = || -> Result<_> {
+            db.execute_batch("BEGIN")?;
+            db.query_row("SELECT count(*) FROM foo", [], |_| Ok(()))?;
+            let snapshot = db.snapshot_get(MAIN_DB);
+            db.execute_batch("COMMIT")?;
+            snapshot
+        };
+        let first = take()?;
+        assert_eq!(first.cmp_age(&take()?), Ordering::Equal);
+        db.execute_batch("INSERT INTO foo VALUES (1)")?;
+        let second = take()?;
+        assert_eq!(first.cmp_age(&second), Ordering::Less);
+        assert_eq!(second.cmp_age(&first), Ordering::Greater);
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_open_not_wal() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let db = wal_db(&path)?;
+        db.execute_batch("BEGIN")?;
+        db.query_row("SELECT count(*) FROM foo", [], |_| Ok(()))?;
+        let snapshot = db.snapshot_get(MAIN_DB)?;
+        db.execute_batch("COMMIT")?;
+
+        let other = Connection::open(temp_dir.path().join("other.db3"))?;
+        other.execute_batch("CREATE TABLE foo(v); BEGIN;")?;
+        let err = other.snapshot_open(MAIN_DB, &snapshot).unwrap_err();
+        assert!(err.to_string().contains("WAL mode"), "{}", err);
+        Ok(())
+    }
+
+    #[cfg(feature = "modern_sqlite")]
+    #[test]
+    fn test_snapshot_recover() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let db = wal_db(&path)?;
+        db.wal_autocheckpoint(0)?;
+        db.execute_batch("BEGIN")?;
+        db.query_row("SELECT count(*) FROM foo", [], |_| Ok(()))?;
+        let snapshot = db.snapshot_get(MAIN_DB)?;
+        db.execute_batch("COMMIT")?;
+        db.snapshot_recover(MAIN_DB)?;
+
+        db.execute_batch("BEGIN")?;
+        db.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))?;
+        assert!(db.snapshot_recover(MAIN_DB).is_err());
+        db.execute_batch("ROLLBACK; BEGIN;")?;
+        db.snapshot_open(MAIN_DB, &snapshot)?;
+        db.execute_batch("ROLLBACK")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_at() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let db = wal_db(&path)?;
+        let registry =
+            SnapshotRegistry::new(&path, Duration::from_secs(10), Duration::from_millis(20))?;
+
+        let mut written = Vec::new();
+        for v in 1..=4 {
+            db.execute("UPDATE foo SET v = ?1", [v])?;
+            written.push(Instant::now());
+            thread::sleep(Duration::from_millis(200));
+        }
+        for (v, at) in (1..=4).zip(written) {
+            let conn = registry.read_at(at + Duration::from_millis(150))?;
+            let seen: i64 = conn.one_column("SELECT v FROM foo")?;
+            assert_eq!(seen, v);
+        }
+
+        // reading later than the latest snapshot sees it
+        let conn = registry.read_at(Instant::now() + Duration::from_secs(1))?;
+        let seen: i64 = conn.one_column("SELECT v FROM foo")?;
+        assert_eq!(seen, 4);
+
+        let err = registry
+            .read_at(Instant::now() - Duration::from_secs(60))
+            .err()
+            .unwrap();
+        assert!(
+            matches!(err, Error::SnapshotTooOld { requested, .. } if requested >= Duration::from_secs(60)),
+            "{}",
+            err
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_retention() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let db = wal_db(&path)?;
+        let retention = Duration::from_millis(300);
+        let registry = SnapshotRegistry::new(&path, retention, Duration::from_millis(20))?;
+
+        // the first snapshot pins the WAL before the update
+        db.execute_batch("UPDATE foo SET v = 1")?;
+        let (busy, log, checkpointed) = checkpoint(&db)?;
+        assert_eq!(busy, 0);
+        assert!(checkpointed < log, "{} {}", checkpointed, log);
+
+        // once it expires, only snapshots of the latest state are left
+        thread::sleep(retention + Duration::from_millis(200));
+        let (_, log, checkpointed) = checkpoint(&db)?;
+        assert_eq!(checkpointed, log);
+        assert!(registry.snapshot_count() > 1);
+
+        registry.set_max_snapshots(1);
+        assert_eq!(registry.snapshot_count(), 1);
+        let err = registry
+            .read_at(Instant::now() - retention / 2)
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::SnapshotTooOld { .. }), "{}", err);
+
+        // dropping the registry releases everything
+        drop(registry);
+        let (busy, log, checkpointed) =
+            db.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, i64>(2)?,
+                ))
+            })?;
+        assert_eq!((busy, log, checkpointed), (0, 0, 0));
+        Ok(())
+    }
+}
@@ -8,15 +8,28 @@ use std::{fmt, mem, ptr, str};
 use super::ffi;
 use super::{len_as_c_int, str_for_sqlite};
 use super::{
-    AndThenRows, Connection, Error, MappedRows, Params, RawStatement, Result, Row, Rows, ValueRef,
+    AndThenRows, Connection, Error, FromRow, MappedRows, Params, RawStatement, Result, Row, Rows,
+    ValueRef,
 };
+#[cfg(feature = "hooks")]
+use crate::hooks::{BudgetedRows, DeadlineRows, StepBudget};
+#[cfg(any(feature = "array", feature = "blob"))]
+use crate::types::ToSqlOutputExt;
 use crate::types::{ToSql, ToSqlOutput};
 #[cfg(feature = "array")]
 use crate::vtab::array::{free_array, ARRAY_TYPE};
+#[cfg(feature = "hooks")]
+use std::ops::ControlFlow;
+#[cfg(feature = "hooks")]
+use std::panic::RefUnwindSafe;
+#[cfg(feature = "hooks")]
+use std::time::Duration;
 
 /// A prepared statement.
 pub struct Statement<'conn> {
-    conn: &'conn Connection,
+    pub(crate) conn: &'conn Connection,
+    // Finalized when dropped. A panic caught meanwhile stays pending for the
+    // connection, instead of resuming in a destructor.
     pub(crate) stmt: RawStatement,
 }
 
@@ -156,11 +169,56 @@ impl Statement<'_> {
     pub fn insert<P: Params>(&mut self, params: P) -> Result<i64> {
         let changes = self.execute(params)?;
         match changes {
-            1 => Ok(self.conn.last_insert_rowid()),
+            1 => Ok(self.conn.last_insert_rowid().into()),
             _ => Err(Error::StatementChangedRows(changes)),
         }
     }
 
+    /// Execute the prepared statement once for each item of `iter`, binding
+    /// the item as the parameters, and return the total number of rows that
+    /// were changed or inserted or deleted.
+    ///
+    /// The statement is not wrapped in a transaction: unless one is already
+    /// open, each item is committed on its own. See
+    /// [`Connection::execute_many`] for a version that rolls back every item
+    /// if one fails.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn insert_people(conn: &mut Connection, people: &[(&str, u32)]) -> Result<usize> {
+    ///     let tx = conn.transaction()?;
+    ///     let n = tx
+    ///         .prepare("INSERT INTO people (name, age) VALUES (?1, ?2)")?
+    ///         .execute_many(people.iter().copied())?;
+    ///     tx.commit()?;
+    ///     Ok(n)
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Stops at the first item that fails, returning an
+    /// `Error::ExecuteManyFailed` with its zero-based index. The items before
+    /// it have been executed.
+    pub fn execute_many<P, I>(&mut self, iter: I) -> Result<usize>
+    where
+        P: Params,
+        I: IntoIterator<Item = P>,
+    {
+        let mut changes = 0;
+        for (index, params) in iter.into_iter().enumerate() {
+            changes += self
+                .execute(params)
+                .map_err(|source| Error::ExecuteManyFailed {
+                    index,
+                    source: Box::new(source),
+                })?;
+        }
+        Ok(changes)
+    }
+
     /// Execute the prepared statement, returning a handle to the resulting
     /// rows.
     ///
@@ -423,6 +481,36 @@ impl Statement<'_> {
         self.query(params).map(|rows| rows.and_then(f))
     }
 
+    /// Executes the prepared statement and converts each resulting row into a
+    /// `T` using its [`FromRow`] implementation, returning an iterator over
+    /// the results.
+    ///
+    /// This is equivalent to `stmt.query_map(params, T::from_row)`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn get_people(conn: &Connection) -> Result<Vec<(i64, String)>> {
+    ///     let mut stmt = conn.prepare("SELECT id, name FROM people")?;
+    ///     let rows = stmt.query_as::<(i64, String), _>([])?;
+    ///     rows.collect()
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if binding parameters fails.
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn query_as<T, P>(&mut self, params: P) -> Result<MappedRows<'_, fn(&Row<'_>) -> Result<T>>>
+    where
+        T: FromRow,
+        P: Params,
+    {
+        self.query_map(params, T::from_row as fn(&Row<'_>) -> Result<T>)
+    }
+
     /// Execute the prepared statement with named parameter(s), returning an
     /// iterator over the result of calling the mapping function over the
     /// query's rows.
@@ -487,6 +575,155 @@ impl Statement<'_> {
         rows.get_expected_row().and_then(f)
     }
 
+    /// Execute the prepared statement, calling `budget.yield_fn` every
+    /// `budget.steps_per_yield` virtual machine instructions while it runs.
+    ///
+    /// This lets a long query give control back to the caller, e.g. to check
+    /// a cancellation flag, while it is stepped. The yield function is
+    /// installed as the connection's progress handler until the returned rows
+    /// are dropped, so it also runs for other statements executed on the
+    /// connection in the meantime.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if a progress handler is already registered, if
+    /// `steps_per_yield` is less than one, or if binding parameters fails.
+    #[cfg(feature = "hooks")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hooks")))]
+    pub fn query_with_budget<P, F>(
+        &mut self,
+        params: P,
+        budget: StepBudget<F>,
+    ) -> Result<BudgetedRows<'_>>
+    where
+        P: Params,
+        F: FnMut() -> ControlFlow<()> + Send + RefUnwindSafe + 'static,
+    {
+        let conn = self.conn;
+        let cancelled = conn.install_step_budget(budget)?;
+        match self.query(params) {
+            Ok(rows) => Ok(BudgetedRows::new(rows, conn, cancelled)),
+            Err(err) => {
+                conn.progress_handler(0, None::<fn() -> bool>);
+                Err(err)
+            }
+        }
+    }
+
+    /// Execute the prepared statement, interrupting it if it has not finished
+    /// after `timeout`.
+    ///
+    /// The deadline is checked by a progress handler, which replaces the one
+    /// registered with [`Connection::progress_handler`] until the returned
+    /// rows are dropped, and is then restored. The time spent between reading
+    /// rows counts towards the deadline, but the query can only be
+    /// interrupted while it is stepped.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if binding parameters fails. Reading the rows
+    /// returns `Err(Error::QueryTimeout(timeout))` once the deadline has
+    /// passed.
+    #[cfg(feature = "hooks")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hooks")))]
+    pub fn query_with_deadline<P: Params>(
+        &mut self,
+        params: P,
+        timeout: Duration,
+    ) -> Result<DeadlineRows<'_>> {
+        let conn = self.conn;
+        let (expired, previous) = conn.install_deadline(timeout);
+        match self.query(params) {
+            Ok(rows) => Ok(DeadlineRows::new(rows, conn, expired, timeout, previous)),
+            Err(err) => {
+                conn.restore_progress_handler(previous);
+                Err(err)
+            }
+        }
+    }
+
+    /// Execute a DML statement with a `RETURNING` clause, returning a handle
+    /// to the rows it returns.
+    ///
+    /// SQLite makes every change of the statement on the first step, so the
+    /// changes are made even if the rows are not all read.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn archive_old(conn: &Connection) -> Result<Vec<i64>> {
+    ///     let mut stmt = conn.prepare(
+    ///         "UPDATE posts SET archived = 1 WHERE created < ?1 RETURNING id",
+    ///     )?;
+    ///     let rows = stmt.query_returning(["2020-01-01"])?.mapped(|r| r.get(0));
+    ///     rows.collect()
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the statement returns no columns, typically
+    /// because it has no `RETURNING` clause, if binding parameters fails, or
+    /// if the underlying SQLite call fails.
+    pub fn query_returning<P: Params>(&mut self, params: P) -> Result<Rows<'_>> {
+        self.check_returning()?;
+        self.query(params)
+    }
+
+    /// Execute a DML statement with a `RETURNING` clause that is expected to
+    /// return exactly one row, and convert that row with `f`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err(QueryReturnedNoRows)` if no row is returned and
+    /// `Err(QueryReturnedMoreThanOneRow)` if several are; in the latter case
+    /// the changes have been made nonetheless. Otherwise fails as
+    /// [`query_returning`](Statement::query_returning) does.
+    pub fn query_one_returning<T, P, F>(&mut self, params: P, f: F) -> Result<T>
+    where
+        P: Params,
+        F: FnOnce(&Row<'_>) -> Result<T>,
+    {
+        self.query_returning(params)?
+            .expect_single(f)
+            .map_err(Error::from)
+    }
+
+    fn check_returning(&self) -> Result<()> {
+        if self.column_count() == 0 {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_MISUSE),
+                Some("statement returns no columns, is its RETURNING clause missing?".to_owned()),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Convenience method to execute a query that returns at most one row
+    /// that matters, returning `Ok(None)` rather than an error if there is
+    /// none.
+    ///
+    /// If the query returns more than one row, all rows except the first are
+    /// ignored. Unlike calling [`.optional()`](crate::OptionalExtension::optional)
+    /// on the result of [`query_row`](Statement::query_row), a
+    /// `QueryReturnedNoRows` error returned by `f` itself is passed on.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn query_row_opt<T, P, F>(&mut self, params: P, f: F) -> Result<Option<T>>
+    where
+        P: Params,
+        F: FnOnce(&Row<'_>) -> Result<T>,
+    {
+        let mut rows = self.query(params)?;
+        match rows.next()? {
+            Some(row) => f(row).map(Some),
+            None => Ok(None),
+        }
+    }
+
     /// Convenience method to execute a query with named parameter(s) that is
     /// expected to return a single row.
     ///
@@ -532,7 +769,10 @@ impl Statement<'_> {
     /// Return the (one-based) index of an SQL parameter given its name.
     ///
     /// Note that the initial ":" or "$" or "@" or "?" used to specify the
-    /// parameter is included as part of the name.
+    /// parameter is included as part of the name. If no parameter has exactly
+    /// that name, the name is looked up with each of the ":", "@" and "$"
+    /// prefixes in turn, so that `example`, `:example` and `@example` all
+    /// find the parameter written `:example` in the SQL.
     ///
     /// ```rust,no_run
     /// # use rusqlite::{Connection, Result};
@@ -540,6 +780,7 @@ impl Statement<'_> {
     ///     let stmt = conn.prepare("SELECT * FROM test WHERE name = :example")?;
     ///     let index = stmt.parameter_index(":example")?;
     ///     assert_eq!(index, Some(1));
+    ///     assert_eq!(stmt.parameter_index("$example")?, Some(1));
     ///     Ok(())
     /// }
     /// ```
@@ -548,9 +789,20 @@ impl Statement<'_> {
     ///
     /// Will return Err if `name` is invalid. Will return Ok(None) if the name
     /// is valid but not a bound parameter of this statement.
-    #[inline]
     pub fn parameter_index(&self, name: &str) -> Result<Option<usize>> {
-        Ok(self.stmt.bind_parameter_index(name))
+        if let Some(index) = self.stmt.bind_parameter_index(name) {
+            return Ok(Some(index));
+        }
+        let bare = name.strip_prefix(&[':', '@', '$'][..]).unwrap_or(name);
+        for prefix in &[':', '@', '$'] {
+            let prefixed = format!("{prefix}{bare}");
+            if prefixed != name {
+                if let Some(index) = self.stmt.bind_parameter_index(&prefixed) {
+                    return Ok(Some(index));
+                }
+            }
+        }
+        Ok(None)
     }
 
     /// Return the SQL parameter name given its (one-based) index (the inverse
@@ -577,6 +829,26 @@ impl Statement<'_> {
         })
     }
 
+    /// Return the name of each SQL parameter, in (one-based) index order, or
+    /// `None` for positional parameters.
+    ///
+    /// A name used several times in the SQL is one parameter, so it is yielded
+    /// once.
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn example(conn: &Connection) -> Result<()> {
+    ///     let stmt = conn.prepare("SELECT * FROM test WHERE a = :a AND b = ? AND c = @c")?;
+    ///     let names: Vec<_> = stmt.parameter_names().collect();
+    ///     assert_eq!(names, [Some(":a"), None, Some("@c")]);
+    ///     Ok(())
+    /// }
+    /// ```
+    #[inline]
+    pub fn parameter_names(&self) -> impl Iterator<Item = Option<&'_ str>> + '_ {
+        (1..=self.parameter_count()).map(move |index| self.parameter_name(index))
+    }
+
     #[inline]
     pub(crate) fn bind_parameters<P>(&mut self, params: P) -> Result<()>
     where
@@ -609,20 +881,46 @@ impl Statement<'_> {
         }
     }
 
-    #[inline]
-    pub(crate) fn bind_parameters_named<T: ?Sized + ToSql>(
-        &mut self,
-        params: &[(&str, &T)],
-    ) -> Result<()> {
-        for &(name, value) in params {
-            if let Some(i) = self.parameter_index(name)? {
-                let ts: &dyn ToSql = &value;
-                self.bind_parameter(ts, i)?;
-            } else {
-                return Err(Error::InvalidParameterName(name.into()));
+    /// Binds each value to the parameter of the same name, and fails with
+    /// [`Error::MissingParameters`] if any parameter of the statement is left
+    /// unbound or, when `strict`, if any name is not a parameter of the
+    /// statement.
+    pub(crate) fn bind_parameters_named<'a, T, I>(&mut self, params: I, strict: bool) -> Result<()>
+    where
+        T: ?Sized + ToSql + 'a,
+        I: IntoIterator<Item = (&'a str, &'a T)>,
+    {
+        let mut bound = vec![false; self.parameter_count()];
+        let mut unexpected = Vec::new();
+        for (name, value) in params {
+            match self.parameter_index(name)? {
+                Some(i) => {
+                    let ts: &dyn ToSql = &value;
+                    self.bind_parameter(ts, i)?;
+                    bound[i - 1] = true;
+                }
+                None if strict => unexpected.push(name.to_owned()),
+                None => {}
             }
         }
-        Ok(())
+        let missing: Vec<String> = self
+            .parameter_names()
+            .zip(bound)
+            .enumerate()
+            .filter(|&(_, (_, bound))| !bound)
+            .map(|(i, (name, _))| match name {
+                Some(name) => name.to_owned(),
+                None => format!("?{}", i + 1),
+            })
+            .collect();
+        if missing.is_empty() && unexpected.is_empty() {
+            return Ok(());
+        }
+        unexpected.sort();
+        Err(Error::MissingParameters {
+            missing,
+            unexpected,
+        })
     }
 
     /// Return the number of parameters that can be bound to this statement.
@@ -685,6 +983,41 @@ impl Statement<'_> {
         self.bind_parameter(&param, one_based_col_index)
     }
 
+    /// Binds `value` as a pointer of type `type_name` to the parameter at
+    /// `one_based_col_index`, for use with the "raw" statement functions as
+    /// [`Statement::raw_bind_parameter`].
+    ///
+    /// SQL sees the parameter as NULL, but a function or virtual table
+    /// reading it with [`Context::get_pointer`](crate::functions::Context::get_pointer)
+    /// or [`Values::get_pointer`](crate::vtab::Values::get_pointer) with the
+    /// same type string and type `T` gets `value` back. See
+    /// [Pointer Passing Interfaces](https://sqlite.org/bindptr.html).
+    ///
+    /// `value` is dropped once SQLite no longer needs it, i.e. when the
+    /// parameter is rebound or cleared, or the statement finalized.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `one_based_col_index` is out of range.
+    #[cfg(feature = "modern_sqlite")] // 3.20.0
+    #[cfg_attr(docsrs, doc(cfg(feature = "modern_sqlite")))]
+    pub fn bind_pointer<T: std::any::Any + Send + Sync>(
+        &mut self,
+        one_based_col_index: usize,
+        value: std::sync::Arc<T>,
+        type_name: &'static std::ffi::CStr,
+    ) -> Result<()> {
+        self.conn.decode_result(unsafe {
+            ffi::sqlite3_bind_pointer(
+                self.stmt.ptr(),
+                one_based_col_index as c_int,
+                crate::pointer::into_raw(value),
+                type_name.as_ptr(),
+                Some(crate::pointer::free_pointer),
+            )
+        })
+    }
+
     /// Low level API to execute a statement given that all parameters were
     /// bound explicitly with the [`Statement::raw_bind_parameter`] API.
     ///
@@ -726,28 +1059,33 @@ impl Statement<'_> {
         let value = param.to_sql()?;
 
         let ptr = unsafe { self.stmt.ptr() };
+        #[cfg(feature = "blob")]
+        if let Some(len) = value.as_zero_blob() {
+            // TODO sqlite3_bind_zeroblob64 // 3.8.11
+            return self
+                .conn
+                .decode_result(unsafe { ffi::sqlite3_bind_zeroblob(ptr, col as c_int, len) });
+        }
+        #[cfg(feature = "array")]
+        if let Some(a) = value.as_array() {
+            return self.conn.decode_result(unsafe {
+                ffi::sqlite3_bind_pointer(
+                    ptr,
+                    col as c_int,
+                    Rc::into_raw(a.clone()) as *mut c_void,
+                    ARRAY_TYPE,
+                    Some(free_array),
+                )
+            });
+        }
         let value = match value {
             ToSqlOutput::Borrowed(v) => v,
             ToSqlOutput::Owned(ref v) => ValueRef::from(v),
-
-            #[cfg(feature = "blob")]
-            ToSqlOutput::ZeroBlob(len) => {
-                // TODO sqlite3_bind_zeroblob64 // 3.8.11
-                return self
-                    .conn
-                    .decode_result(unsafe { ffi::sqlite3_bind_zeroblob(ptr, col as c_int, len) });
-            }
-            #[cfg(feature = "array")]
-            ToSqlOutput::Array(a) => {
-                return self.conn.decode_result(unsafe {
-                    ffi::sqlite3_bind_pointer(
-                        ptr,
-                        col as c_int,
-                        Rc::into_raw(a) as *mut c_void,
-                        ARRAY_TYPE,
-                        Some(free_array),
-                    )
-                });
+            ToSqlOutput::Inline(ref t) => ValueRef::Text(t.as_str().as_bytes()),
+            _ => {
+                return Err(Error::ToSqlConversionFailure(
+                    format!("Unsupported value {value:?}").into(),
+                ))
             }
         };
         self.conn.decode_result(match value {
@@ -780,12 +1118,15 @@ impl Statement<'_> {
     #[inline]
     fn execute_with_bound_parameters(&mut self) -> Result<usize> {
         self.check_update()?;
-        let r = self.stmt.step();
+        let r = self.step_raw();
         self.stmt.reset();
-        match r {
+        self.conn.resume_panic();
+        #[cfg(feature = "hooks")]
+        self.conn.release_closed_events();
+        match r? {
             ffi::SQLITE_DONE => Ok(self.conn.changes() as usize),
             ffi::SQLITE_ROW => Err(Error::ExecuteReturnedResults),
-            _ => Err(self.conn.decode_result(r).unwrap_err()),
+            code => Err(self.conn.decode_result(code).unwrap_err()),
         }
     }
 
@@ -815,12 +1156,98 @@ impl Statement<'_> {
 
     /// Returns a string containing the SQL text of prepared statement with
     /// bound parameters expanded.
+    ///
+    /// Text is quoted as an SQL string literal, with its quotes doubled, and
+    /// a parameter that has not been bound is expanded to `NULL`. `None` is
+    /// returned if the expanded text would be longer than the
+    /// `SQLITE_LIMIT_LENGTH` limit.
     pub fn expanded_sql(&self) -> Option<String> {
         self.stmt
             .expanded_sql()
             .map(|s| s.to_string_lossy().to_string())
     }
 
+    /// Returns the statistics of each loop of the statement, as collected
+    /// since it was prepared or since the last
+    /// [`scan_status_reset`](Statement::scan_status_reset).
+    ///
+    /// SQLite must have been built with `SQLITE_ENABLE_STMT_SCANSTATUS`, which
+    /// the `bundled` build does when the `scanstatus` feature is enabled.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if SQLite was built without
+    /// `SQLITE_ENABLE_STMT_SCANSTATUS`.
+    #[cfg(feature = "scanstatus")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "scanstatus")))]
+    pub fn scan_status(&self) -> Result<Vec<ScanStatus>> {
+        check_scan_status()?;
+        let mut loops = Vec::new();
+        loop {
+            let idx = loops.len();
+            let mut nloop: i64 = 0;
+            // SAFETY: each `op` is given the type SQLite documents for it
+            unsafe {
+                if !self
+                    .stmt
+                    .scan_status(idx, ffi::SQLITE_SCANSTAT_NLOOP, &mut nloop)
+                {
+                    break;
+                }
+                let mut nvisit: i64 = 0;
+                self.stmt
+                    .scan_status(idx, ffi::SQLITE_SCANSTAT_NVISIT, &mut nvisit);
+                let mut est: f64 = 0.0;
+                self.stmt
+                    .scan_status(idx, ffi::SQLITE_SCANSTAT_EST, &mut est);
+                let mut name: *const std::os::raw::c_char = ptr::null();
+                self.stmt
+                    .scan_status(idx, ffi::SQLITE_SCANSTAT_NAME, &mut name);
+                let mut explain: *const std::os::raw::c_char = ptr::null();
+                self.stmt
+                    .scan_status(idx, ffi::SQLITE_SCANSTAT_EXPLAIN, &mut explain);
+                loops.push(ScanStatus {
+                    loop_index: idx,
+                    nloop,
+                    nvisit,
+                    est,
+                    name: c_string(name),
+                    explain: c_string(explain),
+                });
+            }
+        }
+        Ok(loops)
+    }
+
+    /// Resets the statistics returned by
+    /// [`scan_status`](Statement::scan_status) to zero.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if SQLite was built without
+    /// `SQLITE_ENABLE_STMT_SCANSTATUS`.
+    #[cfg(feature = "scanstatus")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "scanstatus")))]
+    pub fn scan_status_reset(&self) -> Result<()> {
+        check_scan_status()?;
+        self.stmt.scan_status_reset();
+        Ok(())
+    }
+
+    /// Returns the normalized SQL text of the statement, in which literals
+    /// are replaced by `?` and keywords and identifiers are folded to a
+    /// canonical case, so that statements differing only by their literals
+    /// share the same text.
+    ///
+    /// SQLite must have been built with `SQLITE_ENABLE_NORMALIZE`, which the
+    /// `bundled` build does when the `normalize` feature is enabled.
+    #[cfg(feature = "normalize")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "normalize")))]
+    #[inline]
+    pub fn normalized_sql(&self) -> Option<&str> {
+        self.stmt.normalized_sql().and_then(|s| s.to_str().ok())
+    }
+
     /// Get the value for one of the status counters for this statement.
     #[inline]
     pub fn get_status(&self, status: StatementStatus) -> i32 {
@@ -834,6 +1261,39 @@ impl Statement<'_> {
         self.stmt.get_status(status, true)
     }
 
+    /// Get the value for one of the status counters for this statement,
+    /// resetting it to zero afterwards if `reset` is `true`.
+    ///
+    /// The counters are kept for the life of the prepared statement, so the
+    /// counters of a [cached statement](crate::Connection::prepare_cached)
+    /// add up across uses.
+    #[inline]
+    pub fn status(&self, counter: StatementStatus, reset: bool) -> i32 {
+        self.stmt.get_status(counter, reset)
+    }
+
+    /// Reset every status counter for this statement to zero.
+    ///
+    /// `StatementStatus::MemUsed` is not a counter and is left as is.
+    pub fn reset_all_status(&self) {
+        for &counter in StatementStatus::ALL {
+            self.stmt.get_status(counter, true);
+        }
+    }
+
+    /// Returns true if the statement makes no direct changes to the database
+    /// file, as reported by
+    /// [`sqlite3_stmt_readonly`](https://sqlite.org/c3ref/stmt_readonly.html).
+    ///
+    /// Transaction control statements like `BEGIN` and `COMMIT` are
+    /// readonly, and so are statements changing only temporary or virtual
+    /// tables, for which SQLite may still write to the database file.
+    #[inline]
+    #[must_use]
+    pub fn readonly(&self) -> bool {
+        self.stmt.readonly()
+    }
+
     /// Returns 1 if the prepared statement is an EXPLAIN statement,
     /// or 2 if the statement is an EXPLAIN QUERY PLAN,
     /// or 0 if it is an ordinary statement or a NULL pointer.
@@ -892,14 +1352,6 @@ impl fmt::Debug for Statement<'_> {
     }
 }
 
-impl Drop for Statement<'_> {
-    #[allow(unused_must_use)]
-    #[inline]
-    fn drop(&mut self) {
-        self.finalize_();
-    }
-}
-
 impl Statement<'_> {
     #[inline]
     pub(super) fn new(conn: &Connection, stmt: RawStatement) -> Statement<'_> {
@@ -911,12 +1363,8 @@ impl Statement<'_> {
 
         match self.stmt.column_type(col) {
             ffi::SQLITE_NULL => ValueRef::Null,
-            ffi::SQLITE_INTEGER => {
-                ValueRef::Integer(unsafe { ffi::sqlite3_column_int64(raw, col as c_int) })
-            }
-            ffi::SQLITE_FLOAT => {
-                ValueRef::Real(unsafe { ffi::sqlite3_column_double(raw, col as c_int) })
-            }
+            ffi::SQLITE_INTEGER => ValueRef::Integer(self.stmt.column_int64(col)),
+            ffi::SQLITE_FLOAT => ValueRef::Real(self.stmt.column_double(col)),
             ffi::SQLITE_TEXT => {
                 let s = unsafe {
                     // Quoting from "Using SQLite" book:
@@ -962,9 +1410,43 @@ impl Statement<'_> {
         }
     }
 
+    #[inline]
+    pub(super) fn column_type(&self, col: usize) -> c_int {
+        self.stmt.column_type(col)
+    }
+
+    #[inline]
+    pub(super) fn column_int64(&self, col: usize) -> i64 {
+        self.stmt.column_int64(col)
+    }
+
+    #[inline]
+    pub(super) fn column_double(&self, col: usize) -> f64 {
+        self.stmt.column_double(col)
+    }
+
+    #[inline]
+    #[cfg(not(feature = "unlock_notify"))]
+    fn step_raw(&self) -> Result<c_int> {
+        Ok(self.stmt.step())
+    }
+
+    // Steps the statement, waiting for the locks of other connections to a
+    // shared cache.
+    #[inline]
+    #[cfg(feature = "unlock_notify")]
+    fn step_raw(&self) -> Result<c_int> {
+        let timeout = self.conn.db.borrow().unlock_notify_timeout;
+        self.stmt.step(timeout)
+    }
+
     #[inline]
     pub(super) fn step(&self) -> Result<bool> {
-        match self.stmt.step() {
+        let r = self.step_raw();
+        self.conn.resume_panic();
+        #[cfg(feature = "hooks")]
+        self.conn.release_closed_events();
+        match r? {
             ffi::SQLITE_ROW => Ok(true),
             ffi::SQLITE_DONE => Ok(false),
             code => Err(self.conn.decode_result(code).unwrap_err()),
@@ -977,6 +1459,54 @@ impl Statement<'_> {
     }
 }
 
+/// Statistics of one loop of a statement, as returned by
+/// [`Statement::scan_status`].
+///
+/// See `https://www.sqlite.org/c3ref/c_scanstat_est.html` for details.
+#[cfg(feature = "scanstatus")]
+#[cfg_attr(docsrs, doc(cfg(feature = "scanstatus")))]
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct ScanStatus {
+    /// Zero-based index of the loop in the statement.
+    pub loop_index: usize,
+    /// Number of times the loop has run.
+    pub nloop: i64,
+    /// Number of rows visited by the loop, over all of its runs.
+    pub nvisit: i64,
+    /// Number of rows the query planner estimated each run of the loop would
+    /// output.
+    pub est: f64,
+    /// Name of the table or index the loop reads.
+    pub name: Option<String>,
+    /// `EXPLAIN QUERY PLAN` description of the loop.
+    pub explain: Option<String>,
+}
+
+#[cfg(feature = "scanstatus")]
+fn check_scan_status() -> Result<()> {
+    let used = unsafe {
+        ffi::sqlite3_compileoption_used(b"ENABLE_STMT_SCANSTATUS\0".as_ptr().cast()) != 0
+    };
+    if used {
+        Ok(())
+    } else {
+        Err(Error::SqliteFailure(
+            ffi::Error::new(ffi::SQLITE_MISUSE),
+            Some("scan status is not supported: SQLite was built without SQLITE_ENABLE_STMT_SCANSTATUS".to_owned()),
+        ))
+    }
+}
+
+#[cfg(feature = "scanstatus")]
+unsafe fn c_string(s: *const std::os::raw::c_char) -> Option<String> {
+    if s.is_null() {
+        None
+    } else {
+        Some(std::ffi::CStr::from_ptr(s).to_string_lossy().into_owned())
+    }
+}
+
 /// Prepared statement status counters.
 ///
 /// See `https://www.sqlite.org/c3ref/c_stmtstatus_counter.html`
@@ -985,7 +1515,7 @@ impl Statement<'_> {
 /// Note that depending on your version of SQLite, all of these
 /// may not be available.
 #[repr(i32)]
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 #[non_exhaustive]
 pub enum StatementStatus {
     /// Equivalent to SQLITE_STMTSTATUS_FULLSCAN_STEP
@@ -1008,6 +1538,21 @@ pub enum StatementStatus {
     MemUsed = 99,
 }
 
+impl StatementStatus {
+    /// Every status counter, in the order of their SQLite values.
+    pub const ALL: &'static [StatementStatus] = &[
+        StatementStatus::FullscanStep,
+        StatementStatus::Sort,
+        StatementStatus::AutoIndex,
+        StatementStatus::VmStep,
+        StatementStatus::RePrepare,
+        StatementStatus::Run,
+        StatementStatus::FilterMiss,
+        StatementStatus::FilterHit,
+        StatementStatus::MemUsed,
+    ];
+}
+
 #[cfg(test)]
 mod test {
     use crate::types::ToSql;
@@ -1054,6 +1599,80 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_execute_map() -> Result<()> {
+        use crate::params_from_map;
+        use crate::types::Value;
+        use std::collections::{BTreeMap, HashMap};
+
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(a INTEGER, b TEXT, c BLOB, d REAL)")?;
+        let sql = "INSERT INTO foo VALUES (:a, @b, $c, :d)";
+
+        let mut by_ref: HashMap<&str, &dyn ToSql> = HashMap::new();
+        by_ref.insert(":a", &1);
+        by_ref.insert("b", &"one");
+        by_ref.insert("$c", &[1u8, 2]);
+        by_ref.insert("d", &None::<f64>);
+        assert_eq!(db.execute(sql, &by_ref)?, 1);
+
+        let mut values: HashMap<String, Value> = HashMap::new();
+        values.insert("a".into(), Value::Integer(2));
+        values.insert("b".into(), Value::Text("two".into()));
+        values.insert("c".into(), Value::Blob(vec![3]));
+        values.insert("d".into(), Value::Real(2.5));
+        assert_eq!(db.execute(sql, &values)?, 1);
+        let sorted: BTreeMap<String, Value> = values.clone().into_iter().collect();
+        assert_eq!(db.execute(sql, &sorted)?, 1);
+        assert_eq!(db.execute(sql, values)?, 1);
+
+        let rows = db
+            .prepare("SELECT a, b, c, d FROM foo")?
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)))?
+            .collect::<Result<Vec<(i64, String, Vec<u8>, Option<f64>)>>>()?;
+        assert_eq!(rows[0], (1, "one".into(), vec![1, 2], None));
+        assert_eq!(rows[3], (2, "two".into(), vec![3], Some(2.5)));
+
+        let mut extra = sorted.clone();
+        extra.insert(":e".into(), Value::Null);
+        extra.insert("f".into(), Value::Null);
+        assert_eq!(
+            db.execute(sql, &extra).unwrap_err(),
+            Error::MissingParameters {
+                missing: vec![],
+                unexpected: vec![":e".into(), "f".into()],
+            }
+        );
+        assert_eq!(db.execute(sql, params_from_map(&extra).strict(false))?, 1);
+
+        let mut missing = extra;
+        missing.remove("b");
+        missing.remove("d");
+        let err = db
+            .execute(sql, params_from_map(&missing).strict(false))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            Error::MissingParameters {
+                missing: vec!["@b".into(), ":d".into()],
+                unexpected: vec![],
+            }
+        );
+        let err = db.execute("SELECT :a, ?, @b", &missing).unwrap_err();
+        assert_eq!(
+            err,
+            Error::MissingParameters {
+                missing: vec!["?2".into(), "@b".into()],
+                unexpected: vec![":e".into(), "c".into(), "f".into()],
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Missing parameters: ?2, @b; unexpected parameters: :e, c, f"
+        );
+        Ok(())
+    }
+
     #[test]
     #[allow(deprecated)]
     fn test_stmt_execute_named() -> Result<()> {
@@ -1208,20 +1827,68 @@ mod test {
     }
 
     #[test]
-    #[allow(deprecated)]
     fn test_unbound_parameters_are_null() -> Result<()> {
         let db = Connection::open_in_memory()?;
         let sql = "CREATE TABLE test (x TEXT, y TEXT)";
         db.execute_batch(sql)?;
 
         let mut stmt = db.prepare("INSERT INTO test (x, y) VALUES (:x, :y)")?;
-        stmt.execute_named(&[(":x", &"one")])?;
+        stmt.raw_bind_parameter(1, "one")?;
+        stmt.raw_execute()?;
 
         let result: Option<String> = db.one_column("SELECT y FROM test WHERE x = 'one'")?;
         assert!(result.is_none());
         Ok(())
     }
 
+    #[test]
+    #[allow(deprecated)]
+    fn test_missing_parameters() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE test (x TEXT, y TEXT, z TEXT)")?;
+        let sql = "INSERT INTO test (x, y, z) VALUES (:x, :y, :z)";
+
+        let err = db
+            .execute(sql, &[(":x", &"one"), (":z", &"three"), (":w", &"four")])
+            .unwrap_err();
+        assert_eq!(
+            err,
+            Error::MissingParameters {
+                missing: vec![":y".into()],
+                unexpected: vec![":w".into()],
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Missing parameters: :y; unexpected parameters: :w"
+        );
+
+        let mut stmt = db.prepare(sql)?;
+        let err = stmt.execute_named(&[(":x", &"one")]).unwrap_err();
+        assert_eq!(
+            err,
+            Error::MissingParameters {
+                missing: vec![":y".into(), ":z".into()],
+                unexpected: vec![],
+            }
+        );
+        let err = stmt
+            .query_row(
+                &[
+                    (":x", &"one"),
+                    (":y", &"two"),
+                    (":z", &"three"),
+                    (":v", &""),
+                ],
+                |_| Ok(()),
+            )
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Unexpected parameters: :v");
+        let count: i64 = db.one_column("SELECT count(*) FROM test")?;
+        assert_eq!(count, 0);
+        Ok(())
+    }
+
     #[test]
     fn test_raw_binding() -> Result<()> {
         let db = Connection::open_in_memory()?;
@@ -1260,14 +1927,92 @@ mod test {
         db.execute_batch(sql)?;
 
         let mut stmt = db.prepare("INSERT INTO test (x, y) VALUES (:x, :y)")?;
-        stmt.execute(&[(":x", "one")])?;
-        stmt.execute(&[(":y", "two")])?;
+        stmt.raw_bind_parameter(1, "one")?;
+        stmt.raw_execute()?;
+        stmt.raw_bind_parameter(2, "two")?;
+        stmt.raw_execute()?;
 
         let result: String = db.one_column("SELECT x FROM test WHERE y = 'two'")?;
         assert_eq!(result, "one");
         Ok(())
     }
 
+    #[test]
+    fn test_execute_many() -> Result<()> {
+        use crate::StatementStatus;
+
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(x INTEGER UNIQUE)")?;
+        let mut stmt = db.prepare("INSERT INTO foo (x) VALUES (:x)")?;
+        let n = stmt.execute_many((0..10_000).map(|x| [x]))?;
+        assert_eq!(n, 10_000);
+        // one statement, run once per item
+        assert_eq!(stmt.get_status(StatementStatus::Run), 10_000);
+        assert_eq!(stmt.get_status(StatementStatus::RePrepare), 0);
+
+        let n = stmt.execute_many(vec![&[(":x", &-1)], &[(":x", &-2)]])?;
+        assert_eq!(n, 2);
+        let err = stmt.execute_many([[-3], [5], [-4]]).unwrap_err();
+        match err {
+            Error::ExecuteManyFailed { index: 1, .. } => {}
+            err => panic!("Unexpected error {}", err),
+        }
+        let err = stmt.execute_many([[-5, 1]]).unwrap_err();
+        assert_eq!(
+            err,
+            Error::ExecuteManyFailed {
+                index: 0,
+                source: Box::new(Error::InvalidParameterCount(2, 1))
+            }
+        );
+        // items before the failing one are not rolled back
+        let count: i64 = db.one_column("SELECT count(*) FROM foo")?;
+        assert_eq!(count, 10_003);
+        Ok(())
+    }
+
+    #[test]
+    fn test_status() -> Result<()> {
+        use crate::StatementStatus;
+
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE foo(x INTEGER, y TEXT);
+             WITH RECURSIVE c(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM c WHERE i < 100)
+             INSERT INTO foo SELECT i, i FROM c;",
+        )?;
+        let sql = "SELECT y FROM foo WHERE x > ?1 ORDER BY y";
+        {
+            let mut stmt = db.prepare_cached(sql)?;
+            assert!(stmt.exists([50])?);
+            assert!(stmt.status(StatementStatus::FullscanStep, false) > 0);
+            assert_eq!(stmt.status(StatementStatus::Sort, false), 1);
+            assert!(stmt.status(StatementStatus::VmStep, false) > 0);
+            assert!(stmt.status(StatementStatus::MemUsed, false) > 0);
+        }
+        {
+            // the counters add up across uses of a cached statement
+            let mut stmt = db.prepare_cached(sql)?;
+            assert!(stmt.exists([50])?);
+            assert_eq!(stmt.status(StatementStatus::Sort, true), 2);
+            assert_eq!(stmt.status(StatementStatus::Sort, false), 0);
+            assert_eq!(stmt.status(StatementStatus::Run, false), 2);
+            stmt.reset_all_status();
+            for &counter in StatementStatus::ALL {
+                if counter != StatementStatus::MemUsed {
+                    assert_eq!(stmt.status(counter, false), 0, "{:?}", counter);
+                }
+            }
+        }
+
+        // with an index, there is no full scan
+        db.execute_batch("CREATE INDEX foo_x ON foo(x)")?;
+        let mut stmt = db.prepare("SELECT y FROM foo WHERE x = ?1")?;
+        assert!(stmt.exists([50])?);
+        assert_eq!(stmt.status(StatementStatus::FullscanStep, false), 0);
+        Ok(())
+    }
+
     #[test]
     fn test_insert() -> Result<()> {
         let db = Connection::open_in_memory()?;
@@ -1369,6 +2114,26 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_query_row_opt() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(x INTEGER, y INTEGER); INSERT INTO foo VALUES(1, 3);")?;
+        let mut stmt = db.prepare("SELECT y FROM foo WHERE x = ?1")?;
+        assert_eq!(stmt.query_row_opt([0i32], |r| r.get::<_, i64>(0))?, None);
+        assert_eq!(stmt.query_row_opt([1i32], |r| r.get::<_, i64>(0))?, Some(3));
+        // the statement is reset and can be reused
+        assert_eq!(stmt.query_row_opt([0i32], |r| r.get::<_, i64>(0))?, None);
+        assert!(stmt.exists([1i32])?);
+        assert_eq!(stmt.query_row_opt([1i32], |r| r.get::<_, i64>(0))?, Some(3));
+
+        // errors from `f` are not swallowed
+        let err = stmt
+            .query_row_opt([1i32], |_| Err::<i64, _>(Error::QueryReturnedNoRows))
+            .unwrap_err();
+        assert_eq!(err, Error::QueryReturnedNoRows);
+        Ok(())
+    }
+
     #[test]
     fn test_query_by_column_name() -> Result<()> {
         let db = Connection::open_in_memory()?;
@@ -1403,6 +2168,63 @@ mod test {
         let stmt = db.prepare("SELECT ?1")?;
         stmt.bind_parameter(&1, 1)?;
         assert_eq!(Some("SELECT 1".to_owned()), stmt.expanded_sql());
+
+        let stmt = db.prepare("SELECT ?1, ?2, :unbound")?;
+        stmt.bind_parameter(&"it's", 1)?;
+        stmt.bind_parameter(&vec![0xCAu8, 0xFE], 2)?;
+        assert_eq!(
+            stmt.expanded_sql().as_deref(),
+            Some("SELECT 'it''s', x'cafe', NULL")
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "scanstatus")]
+    fn test_scan_status() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE parent(id INTEGER PRIMARY KEY, name TEXT);
+             CREATE TABLE child(parent_id INTEGER, name TEXT);
+             CREATE INDEX child_parent ON child(parent_id);
+             INSERT INTO parent VALUES (1, 'a'), (2, 'b');
+             INSERT INTO child VALUES (1, 'a1'), (1, 'a2'), (2, 'b1');",
+        )?;
+        let mut stmt =
+            db.prepare("SELECT p.name, c.name FROM parent p JOIN child c ON c.parent_id = p.id")?;
+        assert_eq!(stmt.query_map([], |_| Ok(()))?.count(), 3);
+
+        let loops = stmt.scan_status()?;
+        assert!(!loops.is_empty());
+        assert!(loops
+            .iter()
+            .any(|l| matches!(l.name.as_deref(), Some(name) if !name.is_empty())));
+        assert!(loops.iter().any(|l| l.nvisit > 0));
+        for (i, l) in loops.iter().enumerate() {
+            assert_eq!(l.loop_index, i);
+            assert!(l.explain.is_some());
+        }
+
+        stmt.scan_status_reset()?;
+        assert!(stmt
+            .scan_status()?
+            .iter()
+            .all(|l| l.nloop == 0 && l.nvisit == 0));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "normalize")]
+    fn test_normalized_sql() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(x INTEGER, y TEXT)")?;
+        let a = db.prepare("select x from foo where y = 'it''s' and x > 10")?;
+        let b = db.prepare("SELECT x FROM foo WHERE y = 'other' AND x > 42")?;
+        assert_eq!(a.normalized_sql(), b.normalized_sql());
+        assert_eq!(
+            a.normalized_sql(),
+            Some("SELECT x FROM foo WHERE y=?AND x>?;")
+        );
         Ok(())
     }
 
@@ -1459,6 +2281,29 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_parameter_names() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        let stmt = db.prepare("SELECT :a, ?, $b, @c, :a, ?, $b")?;
+        assert_eq!(stmt.parameter_count(), 5);
+        assert_eq!(
+            stmt.parameter_names().collect::<Vec<_>>(),
+            [Some(":a"), None, Some("$b"), Some("@c"), None]
+        );
+        assert_eq!(stmt.parameter_index(":a")?, Some(1));
+        assert_eq!(stmt.parameter_index("a")?, Some(1));
+        assert_eq!(stmt.parameter_index("@a")?, Some(1));
+        assert_eq!(stmt.parameter_index("b")?, Some(3));
+        assert_eq!(stmt.parameter_index(":c")?, Some(4));
+        assert_eq!(stmt.parameter_index("d")?, None);
+
+        // an exact match wins over another prefix
+        let stmt = db.prepare("SELECT :x, $x")?;
+        assert_eq!(stmt.parameter_index("$x")?, Some(2));
+        assert_eq!(stmt.parameter_index("x")?, Some(1));
+        Ok(())
+    }
+
     #[test]
     fn test_empty_stmt() -> Result<()> {
         let conn = Connection::open_in_memory()?;
@@ -1523,6 +2368,21 @@ mod test {
         let db = Connection::open_in_memory()?;
         let stmt = db.prepare("SELECT 1;")?;
         assert_eq!(0, stmt.is_explain());
+        let stmt = db.prepare("EXPLAIN SELECT 1;")?;
+        assert_eq!(1, stmt.is_explain());
+        let stmt = db.prepare("EXPLAIN QUERY PLAN SELECT 1;")?;
+        assert_eq!(2, stmt.is_explain());
+        Ok(())
+    }
+
+    #[test]
+    fn test_readonly() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(x INTEGER)")?;
+        assert!(db.prepare("SELECT x FROM foo")?.readonly());
+        assert!(db.prepare("BEGIN")?.readonly());
+        assert!(!db.prepare("UPDATE foo SET x = 1")?.readonly());
+        assert!(!db.prepare("CREATE TABLE bar(y)")?.readonly());
         Ok(())
     }
 
@@ -0,0 +1,263 @@
+//! Contention statistics, collected with the `contention-profiling` feature.
+
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::Duration;
+
+// Bucket `i` counts the waits of less than 2^i microseconds which are not in
+// the previous buckets. The last one also counts the longer waits, above an
+// hour.
+const BUCKETS: usize = 33;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU64 = AtomicU64::new(0);
+static LOCK_WAITS: [AtomicU64; BUCKETS] = [ZERO; BUCKETS];
+static CHECKOUTS: AtomicU64 = AtomicU64::new(0);
+static BUSY_WAIT_NANOS: AtomicU64 = AtomicU64::new(0);
+static MAX_QUEUE_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+type Alert = (Duration, Arc<dyn Fn(ContentionEvent) + Send + Sync>);
+
+static ALERT: RwLock<Option<Alert>> = RwLock::new(None);
+
+// Held by the tests using a `SyncConnection`, whose checkouts would skew the
+// statistics of the others.
+#[cfg(test)]
+pub(crate) static TEST_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// A snapshot of the contention statistics, as returned by
+/// [`contention_snapshot`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ContentionStats {
+    /// The median wait to check out a connection.
+    pub lock_wait_p50: Duration,
+    /// The 95th percentile of the waits to check out a connection.
+    pub lock_wait_p95: Duration,
+    /// The 99th percentile of the waits to check out a connection.
+    pub lock_wait_p99: Duration,
+    /// The total time spent in busy handlers.
+    pub busy_wait_total: Duration,
+    /// The number of checkouts of a connection.
+    pub checkouts: u64,
+    /// The most threads seen waiting for the same connection at once.
+    pub max_queue_depth: usize,
+}
+
+/// What kind of wait a [`ContentionEvent`] reports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ContentionKind {
+    /// Waiting to check out a [`SyncConnection`](crate::SyncConnection).
+    LockWait,
+    /// One call to the busy handler of a connection.
+    BusyWait,
+}
+
+/// A wait longer than the threshold of [`set_contention_alert`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ContentionEvent {
+    /// What was waited for.
+    pub kind: ContentionKind,
+    /// How long.
+    pub wait: Duration,
+    /// The SQL about to run, when known.
+    pub sql: Option<String>,
+}
+
+/// Returns the statistics collected since the start of the process or the
+/// last [`reset_contention_stats`].
+#[must_use]
+pub fn contention_snapshot() -> ContentionStats {
+    let counts: Vec<u64> = LOCK_WAITS
+        .iter()
+        .map(|count| count.load(Ordering::Relaxed))
+        .collect();
+    let total: u64 = counts.iter().sum();
+    ContentionStats {
+        lock_wait_p50: percentile(&counts, total, 50),
+        lock_wait_p95: percentile(&counts, total, 95),
+        lock_wait_p99: percentile(&counts, total, 99),
+        busy_wait_total: Duration::from_nanos(BUSY_WAIT_NANOS.load(Ordering::Relaxed)),
+        checkouts: CHECKOUTS.load(Ordering::Relaxed),
+        max_queue_depth: MAX_QUEUE_DEPTH.load(Ordering::Relaxed),
+    }
+}
+
+/// Reset the statistics to zero.
+pub fn reset_contention_stats() {
+    for count in &LOCK_WAITS {
+        count.store(0, Ordering::Relaxed);
+    }
+    CHECKOUTS.store(0, Ordering::Relaxed);
+    BUSY_WAIT_NANOS.store(0, Ordering::Relaxed);
+    MAX_QUEUE_DEPTH.store(0, Ordering::Relaxed);
+}
+
+/// Call `alert` after every wait of at least `threshold`, from the thread
+/// which waited, before it goes on. It replaces the previous alert, if any.
+pub fn set_contention_alert<F>(threshold: Duration, alert: F)
+where
+    F: Fn(ContentionEvent) + Send + Sync + 'static,
+{
+    *ALERT.write().unwrap_or_else(PoisonError::into_inner) = Some((threshold, Arc::new(alert)));
+}
+
+/// Remove the alert set by [`set_contention_alert`].
+pub fn clear_contention_alert() {
+    *ALERT.write().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+pub(crate) fn record_lock_wait(wait: Duration, sql: Option<&str>) {
+    let micros = u64::try_from(wait.as_micros()).unwrap_or(u64::MAX);
+    let bucket = (64 - micros.leading_zeros() as usize).min(BUCKETS - 1);
+    LOCK_WAITS[bucket].fetch_add(1, Ordering::Relaxed);
+    CHECKOUTS.fetch_add(1, Ordering::Relaxed);
+    alert(ContentionKind::LockWait, wait, sql);
+}
+
+pub(crate) fn record_queue_depth(depth: usize) {
+    MAX_QUEUE_DEPTH.fetch_max(depth, Ordering::Relaxed);
+}
+
+pub(crate) fn record_busy_wait(wait: Duration) {
+    let nanos = u64::try_from(wait.as_nanos()).unwrap_or(u64::MAX);
+    BUSY_WAIT_NANOS.fetch_add(nanos, Ordering::Relaxed);
+    alert(ContentionKind::BusyWait, wait, None);
+}
+
+fn alert(kind: ContentionKind, wait: Duration, sql: Option<&str>) {
+    // not holding the lock while calling it, which may set another
+    let alert = match *ALERT.read().unwrap_or_else(PoisonError::into_inner) {
+        Some((threshold, ref alert)) if wait >= threshold => alert.clone(),
+        _ => return,
+    };
+    alert(ContentionEvent {
+        kind,
+        wait,
+        sql: sql.map(str::to_owned),
+    });
+}
+
+// The upper bound of the bucket holding the `p`th percentile.
+fn percentile(counts: &[u64], total: u64, p: u64) -> Duration {
+    if total == 0 {
+        return Duration::ZERO;
+    }
+    let rank = (total * p).div_ceil(100);
+    let mut seen = 0;
+    for (bucket, count) in counts.iter().enumerate() {
+        seen += count;
+        if seen >= rank {
+            return match bucket {
+                0 => Duration::ZERO,
+                _ => Duration::from_micros(1 << bucket),
+            };
+        }
+    }
+    Duration::MAX
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc::sync_channel;
+    use std::sync::{Arc, Mutex, PoisonError};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{
+        clear_contention_alert, contention_snapshot, percentile, reset_contention_stats,
+        set_contention_alert, ContentionEvent, ContentionKind,
+    };
+    use crate::{Connection, ErrorCode, Result, SyncConnection, TransactionBehavior};
+
+    #[test]
+    fn test_percentile() {
+        let mut counts = [0; super::BUCKETS];
+        assert_eq!(percentile(&counts, 0, 50), Duration::ZERO);
+        counts[0] = 90;
+        counts[10] = 9;
+        counts[20] = 1;
+        assert_eq!(percentile(&counts, 100, 50), Duration::ZERO);
+        assert_eq!(percentile(&counts, 100, 95), Duration::from_micros(1 << 10));
+        assert_eq!(percentile(&counts, 100, 99), Duration::from_micros(1 << 10));
+        assert_eq!(
+            percentile(&counts, 100, 100),
+            Duration::from_micros(1 << 20)
+        );
+    }
+
+    // Both kinds of waits in one test, as the statistics are global
+    #[test]
+    fn test_contention() -> Result<()> {
+        let _guard = super::TEST_LOCK
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        reset_contention_stats();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let alerts = events.clone();
+        set_contention_alert(Duration::from_millis(20), move |event: ContentionEvent| {
+            alerts.lock().unwrap().push(event);
+        });
+
+        // one thread sleeps while having the connection
+        let conn = Arc::new(SyncConnection::new(Connection::open_in_memory()?));
+        conn.execute_batch("CREATE TABLE t(x)")?;
+        let (tx, rx) = sync_channel(0);
+        let holder = {
+            let conn = conn.clone();
+            thread::spawn(move || {
+                let _conn = conn.lock();
+                tx.send(()).unwrap();
+                thread::sleep(Duration::from_millis(200));
+            })
+        };
+        rx.recv().unwrap();
+        conn.execute("INSERT INTO t VALUES (1)", [])?;
+        holder.join().unwrap();
+
+        let stats = contention_snapshot();
+        assert_eq!(stats.checkouts, 3);
+        assert!(
+            stats.lock_wait_p95 >= Duration::from_millis(100),
+            "{:?}",
+            stats
+        );
+        assert_eq!(stats.max_queue_depth, 1);
+        {
+            let events = events.lock().unwrap();
+            let blocked = events
+                .iter()
+                .find(|e| e.kind == ContentionKind::LockWait)
+                .expect("no lock wait alert");
+            assert_eq!(blocked.sql.as_deref(), Some("INSERT INTO t VALUES (1)"));
+            assert!(blocked.wait >= Duration::from_millis(100));
+        }
+
+        // another connection holds a lock on the database
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let mut db1 = Connection::open(&path)?;
+        let db2 = Connection::open(&path)?;
+        db2.busy_handler(Some(|attempts| {
+            thread::sleep(Duration::from_millis(25));
+            attempts < 3
+        }))?;
+        let tx1 = db1.transaction_with_behavior(TransactionBehavior::Exclusive)?;
+        let err = db2
+            .query_row("PRAGMA schema_version", [], |_| Ok(()))
+            .unwrap_err();
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::DatabaseBusy));
+        tx1.rollback()?;
+        assert!(contention_snapshot().busy_wait_total >= Duration::from_millis(90));
+        assert!(events
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|e| e.kind == ContentionKind::BusyWait));
+        clear_contention_alert();
+        Ok(())
+    }
+}
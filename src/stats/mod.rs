@@ -0,0 +1,123 @@
+//! Process-wide statistics.
+//!
+//! ## Page cache
+//!
+//! [`reported_cache_stats`] sums the page cache usage last reported by
+//! [`Connection::cache_stats`](crate::Connection::cache_stats) for each
+//! connection.
+//!
+//! ## Contention
+//!
+//! With the `contention-profiling` feature, two kinds of waits are recorded:
+//! * lock waits, of threads checking out a [`SyncConnection`] while another
+//!   thread has it,
+//! * busy waits, spent in a handler set with
+//!   [`Connection::busy_handler`](crate::Connection::busy_handler) while
+//!   another connection holds a lock on the database. The sleeps of
+//!   [`Connection::busy_timeout`](crate::Connection::busy_timeout), the
+//!   default, happen within SQLite and are not seen.
+//!
+//! Lock waits are kept in a histogram of fixed size, with buckets doubling
+//! from one microsecond, so that percentiles are only exact to within a
+//! factor of two.
+//!
+//! ```rust,no_run
+//! # #[cfg(feature = "contention-profiling")] {
+//! # use rusqlite::stats::{self, ContentionEvent};
+//! # use std::time::Duration;
+//! fn profile() {
+//!     stats::set_contention_alert(Duration::from_millis(100), |event: ContentionEvent| {
+//!         eprintln!("waited {:?} to run {:?}", event.wait, event.sql);
+//!     });
+//!     // ...
+//!     let stats = stats::contention_snapshot();
+//!     eprintln!("p99 wait for the connection: {:?}", stats.lock_wait_p99);
+//! }
+//! # }
+//! ```
+//!
+//! [`SyncConnection`]: crate::SyncConnection
+
+use std::sync::atomic::{AtomicI64, Ordering};
+
+use crate::config::CacheStats;
+
+#[cfg(feature = "contention-profiling")]
+mod contention;
+
+#[cfg(all(test, feature = "contention-profiling"))]
+pub(crate) use self::contention::TEST_LOCK;
+#[cfg(feature = "contention-profiling")]
+#[cfg_attr(docsrs, doc(cfg(feature = "contention-profiling")))]
+pub use self::contention::{
+    clear_contention_alert, contention_snapshot, reset_contention_stats, set_contention_alert,
+    ContentionEvent, ContentionKind, ContentionStats,
+};
+#[cfg(feature = "contention-profiling")]
+pub(crate) use self::contention::{record_busy_wait, record_lock_wait, record_queue_depth};
+
+// The sums of the `CacheStats` last reported by each connection, with the
+// `used_bytes` of a connection taken out when it is closed.
+static TOTAL_USED_BYTES: AtomicI64 = AtomicI64::new(0);
+static TOTAL_HIT: AtomicI64 = AtomicI64::new(0);
+static TOTAL_MISS: AtomicI64 = AtomicI64::new(0);
+static TOTAL_SPILL: AtomicI64 = AtomicI64::new(0);
+static TOTAL_DIRTY: AtomicI64 = AtomicI64::new(0);
+
+/// Returns the sum of the page cache usage last reported by
+/// [`Connection::cache_stats`](crate::Connection::cache_stats) for each
+/// connection.
+///
+/// This is not a live measure of the process: nothing is read from the
+/// connections here, so a connection only counts with the statistics it had
+/// at its latest `cache_stats` call, and not at all if there was none.
+/// `used_bytes` is summed over the connections still open, and the counters
+/// over all connections, including closed ones.
+#[must_use]
+pub fn reported_cache_stats() -> CacheStats {
+    CacheStats {
+        used_bytes: TOTAL_USED_BYTES.load(Ordering::Relaxed),
+        hit: TOTAL_HIT.load(Ordering::Relaxed),
+        miss: TOTAL_MISS.load(Ordering::Relaxed),
+        spill: TOTAL_SPILL.load(Ordering::Relaxed),
+        dirty: TOTAL_DIRTY.load(Ordering::Relaxed),
+    }
+}
+
+// Replaces the `previous` report of a connection with `stats`.
+pub(crate) fn report_cache_stats(previous: &CacheStats, stats: &CacheStats) {
+    TOTAL_USED_BYTES.fetch_add(stats.used_bytes - previous.used_bytes, Ordering::Relaxed);
+    TOTAL_HIT.fetch_add(stats.hit - previous.hit, Ordering::Relaxed);
+    TOTAL_MISS.fetch_add(stats.miss - previous.miss, Ordering::Relaxed);
+    TOTAL_SPILL.fetch_add(stats.spill - previous.spill, Ordering::Relaxed);
+    TOTAL_DIRTY.fetch_add(stats.dirty - previous.dirty, Ordering::Relaxed);
+}
+
+// The cache of a closed connection is freed.
+pub(crate) fn forget_cache_stats(reported: &CacheStats) {
+    TOTAL_USED_BYTES.fetch_sub(reported.used_bytes, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Connection, Result};
+
+    #[test]
+    fn test_reported_cache_stats() -> Result<()> {
+        let temp = tempfile::tempdir().unwrap();
+        let db = Connection::open(temp.path().join("cache.db3"))?;
+        db.execute_batch("CREATE TABLE foo (x); INSERT INTO foo VALUES (1);")?;
+        let first = db.cache_stats()?;
+        let before = super::reported_cache_stats();
+        for _ in 0..10 {
+            db.query_row("SELECT count(*) FROM foo", [], |_| Ok(()))?;
+        }
+        let second = db.cache_stats()?;
+        let after = super::reported_cache_stats();
+        // other tests may report their own connections meanwhile
+        assert!(second.hit > first.hit);
+        assert!(after.hit - before.hit >= second.hit - first.hit);
+        assert!(after.used_bytes >= second.used_bytes);
+        Ok(())
+    }
+}
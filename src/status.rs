@@ -0,0 +1,207 @@
+//! Memory and cache statistics, and heap limits
+//!
+//! [`Connection::db_status`] reports the resources used by one connection,
+//! while [`memory_used`] and [`memory_highwater`] report the heap memory
+//! used by SQLite in the whole process. See
+//! [Run-Time Status](https://sqlite.org/c3ref/db_status.html).
+
+use std::os::raw::c_int;
+
+use crate::error::check;
+use crate::{ffi, Connection, Result};
+
+/// Connection status counters, for use with [`Connection::db_status`].
+///
+/// See the official documentation for more information:
+/// <https://sqlite.org/c3ref/c_dbstatus_options.html>
+#[repr(i32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+#[allow(clippy::upper_case_acronyms, non_camel_case_types)]
+pub enum DbStatus {
+    /// The number of lookaside memory slots currently checked out.
+    SQLITE_DBSTATUS_LOOKASIDE_USED = ffi::SQLITE_DBSTATUS_LOOKASIDE_USED,
+    /// The bytes of heap memory used by all pager caches of the connection.
+    SQLITE_DBSTATUS_CACHE_USED = ffi::SQLITE_DBSTATUS_CACHE_USED,
+    /// The bytes of heap memory used to store the schemas of all databases
+    /// of the connection.
+    SQLITE_DBSTATUS_SCHEMA_USED = ffi::SQLITE_DBSTATUS_SCHEMA_USED,
+    /// The bytes of heap and lookaside memory used by all prepared
+    /// statements of the connection.
+    SQLITE_DBSTATUS_STMT_USED = ffi::SQLITE_DBSTATUS_STMT_USED,
+    /// The number of allocations satisfied by lookaside memory, as the
+    /// highwater value.
+    SQLITE_DBSTATUS_LOOKASIDE_HIT = ffi::SQLITE_DBSTATUS_LOOKASIDE_HIT,
+    /// The number of allocations too large for lookaside memory, as the
+    /// highwater value.
+    SQLITE_DBSTATUS_LOOKASIDE_MISS_SIZE = ffi::SQLITE_DBSTATUS_LOOKASIDE_MISS_SIZE,
+    /// The number of allocations that found lookaside memory full, as the
+    /// highwater value.
+    SQLITE_DBSTATUS_LOOKASIDE_MISS_FULL = ffi::SQLITE_DBSTATUS_LOOKASIDE_MISS_FULL,
+    /// The number of page cache hits.
+    SQLITE_DBSTATUS_CACHE_HIT = ffi::SQLITE_DBSTATUS_CACHE_HIT,
+    /// The number of page cache misses.
+    SQLITE_DBSTATUS_CACHE_MISS = ffi::SQLITE_DBSTATUS_CACHE_MISS,
+    /// The number of dirty pages written to disk.
+    SQLITE_DBSTATUS_CACHE_WRITE = ffi::SQLITE_DBSTATUS_CACHE_WRITE,
+    /// 1 if there are foreign key constraints violations waiting for the end
+    /// of the transaction, 0 otherwise.
+    SQLITE_DBSTATUS_DEFERRED_FKS = ffi::SQLITE_DBSTATUS_DEFERRED_FKS,
+    /// Like `SQLITE_DBSTATUS_CACHE_USED`, but a page cache shared between
+    /// connections only counts for its share.
+    SQLITE_DBSTATUS_CACHE_USED_SHARED = ffi::SQLITE_DBSTATUS_CACHE_USED_SHARED, // 3.14.0
+    /// The number of dirty pages written to disk in the middle of a
+    /// transaction because the cache was full.
+    SQLITE_DBSTATUS_CACHE_SPILL = 12, // 3.23.0
+}
+
+impl Connection {
+    /// Returns the current and highwater values of the `status` counter,
+    /// resetting the highwater value to the current one if `reset` is set.
+    ///
+    /// Some counters have no current value or no highwater value, which is
+    /// then 0. See
+    /// [sqlite3_db_status](https://sqlite.org/c3ref/db_status.html).
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if SQLite does not know `status`, being older than
+    /// it.
+    pub fn db_status(&self, status: DbStatus, reset: bool) -> Result<(i64, i64)> {
+        let c = self.db.borrow();
+        let mut current = 0;
+        let mut highwater = 0;
+        check(unsafe {
+            ffi::sqlite3_db_status(
+                c.db(),
+                status as c_int,
+                &mut current,
+                &mut highwater,
+                c_int::from(reset),
+            )
+        })?;
+        Ok((current.into(), highwater.into()))
+    }
+}
+
+/// Returns the bytes of heap memory currently used by SQLite.
+///
+/// See [`sqlite3_memory_used`](https://sqlite.org/c3ref/memory_highwater.html).
+#[inline]
+#[must_use]
+pub fn memory_used() -> i64 {
+    unsafe { ffi::sqlite3_memory_used() }
+}
+
+/// Returns the most bytes of heap memory used by SQLite since the highwater
+/// mark was last reset, resetting it to the current use if `reset` is set.
+///
+/// See [`sqlite3_memory_highwater`](https://sqlite.org/c3ref/memory_highwater.html).
+#[inline]
+pub fn memory_highwater(reset: bool) -> i64 {
+    unsafe { ffi::sqlite3_memory_highwater(c_int::from(reset)) }
+}
+
+/// Attempts to free `bytes` bytes of heap memory held by SQLite but not
+/// needed, e.g. unused pages of the page caches, and returns the number of
+/// bytes freed.
+///
+/// Nothing is freed unless SQLite was built with
+/// `SQLITE_ENABLE_MEMORY_MANAGEMENT`. See
+/// [`sqlite3_release_memory`](https://sqlite.org/c3ref/release_memory.html).
+#[inline]
+pub fn release_memory(bytes: i32) -> i32 {
+    unsafe { ffi::sqlite3_release_memory(bytes) }
+}
+
+/// Returns the soft heap limit, 0 if there is none.
+#[inline]
+#[must_use]
+pub fn soft_heap_limit() -> i64 {
+    unsafe { ffi::sqlite3_soft_heap_limit64(-1) }
+}
+
+/// Sets the soft heap limit, or removes it if `limit` is 0, and returns the
+/// previous one. SQLite tries to keep its heap memory below the soft limit
+/// by freeing unused cache pages, but goes over it rather than failing.
+///
+/// A limit above the hard heap limit is lowered to it. See
+/// [`sqlite3_soft_heap_limit64`](https://sqlite.org/c3ref/hard_heap_limit64.html).
+#[inline]
+pub fn set_soft_heap_limit(limit: i64) -> i64 {
+    unsafe { ffi::sqlite3_soft_heap_limit64(limit.max(0)) }
+}
+
+/// Returns the hard heap limit, 0 if there is none.
+#[cfg(feature = "modern_sqlite")] // 3.31.0
+#[cfg_attr(docsrs, doc(cfg(feature = "modern_sqlite")))]
+#[inline]
+#[must_use]
+pub fn hard_heap_limit() -> i64 {
+    unsafe { ffi::sqlite3_hard_heap_limit64(-1) }
+}
+
+/// Sets the hard heap limit, or removes it if `limit` is 0, and returns the
+/// previous one. Once SQLite uses that much heap memory, allocations fail
+/// with `SQLITE_NOMEM`.
+///
+/// The soft heap limit is lowered to the hard one if needed. See
+/// [`sqlite3_hard_heap_limit64`](https://sqlite.org/c3ref/hard_heap_limit64.html).
+#[cfg(feature = "modern_sqlite")] // 3.31.0
+#[cfg_attr(docsrs, doc(cfg(feature = "modern_sqlite")))]
+#[inline]
+pub fn set_hard_heap_limit(limit: i64) -> i64 {
+    unsafe { ffi::sqlite3_hard_heap_limit64(limit.max(0)) }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        memory_highwater, memory_used, release_memory, set_soft_heap_limit, soft_heap_limit,
+        DbStatus,
+    };
+    use crate::{Connection, Result};
+
+    #[test]
+    fn test_db_status() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE foo(x);
+             WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 2000)
+             INSERT INTO foo SELECT randomblob(100) FROM n;",
+        )?;
+        let (used, _) = db.db_status(DbStatus::SQLITE_DBSTATUS_CACHE_USED, false)?;
+        assert!(used > 0);
+        let (schema, _) = db.db_status(DbStatus::SQLITE_DBSTATUS_SCHEMA_USED, false)?;
+        assert!(schema > 0);
+        assert_eq!(db.cache_stats()?.used_bytes, used);
+
+        #[cfg(feature = "release_memory")]
+        db.release_memory()?;
+        release_memory(i32::MAX);
+        let (after, _) = db.db_status(DbStatus::SQLITE_DBSTATUS_CACHE_USED, false)?;
+        assert!(after <= used);
+
+        let (_, hit) = db.db_status(DbStatus::SQLITE_DBSTATUS_LOOKASIDE_HIT, true)?;
+        assert!(hit >= 0);
+        let (_, hit) = db.db_status(DbStatus::SQLITE_DBSTATUS_LOOKASIDE_HIT, false)?;
+        assert_eq!(hit, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_used() -> Result<()> {
+        let _db = Connection::open_in_memory()?;
+        let used = memory_used();
+        assert!(used > 0);
+        assert!(memory_highwater(false) >= used);
+        Ok(())
+    }
+
+    #[test]
+    fn test_soft_heap_limit() {
+        let prior = set_soft_heap_limit(1 << 30);
+        assert_eq!(soft_heap_limit(), 1 << 30);
+        assert_eq!(set_soft_heap_limit(prior), 1 << 30);
+    }
+}
@@ -0,0 +1,935 @@
+//! Reports on unused space in a database, and whether reclaiming it with
+//! `VACUUM` or a WAL checkpoint is worthwhile, wrappers for `VACUUM`, `VACUUM
+//! INTO` and `PRAGMA incremental_vacuum`, and periodic maintenance with
+//! [`Connection::maintain`].
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result};
+//! use rusqlite::storage::ReclaimPlan;
+//!
+//! fn maintain(conn: &Connection) -> Result<()> {
+//!     let report = conn.storage_report()?;
+//!     if report.recommendation != ReclaimPlan::NoAction {
+//!         conn.reclaim(report.recommendation)?;
+//!     }
+//!     Ok(())
+//! }
+//! ```
+use std::fs;
+use std::path::Path;
+use std::ptr;
+use std::time::{Duration, Instant};
+
+use crate::pragma::Sql;
+#[cfg(feature = "hooks")]
+use crate::CancellationToken;
+use crate::{
+    ffi, str_to_cstring, Connection, DatabaseName, Error, ErrorCode, OptionalExtension, Result,
+};
+
+/// The `auto_vacuum` setting of a database.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AutoVacuum {
+    /// Free pages are kept until a `VACUUM`.
+    None,
+    /// Free pages are moved to the end of the file and truncated at every
+    /// commit.
+    Full,
+    /// Free pages are truncated by `PRAGMA incremental_vacuum`.
+    Incremental,
+}
+
+/// A way to reclaim unused space, as recommended by a [`StorageReport`] and
+/// run by [`Connection::reclaim`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ReclaimPlan {
+    /// Nothing worth reclaiming.
+    NoAction,
+    /// Truncate `pages` free pages with `PRAGMA incremental_vacuum`, which
+    /// needs `auto_vacuum` to be `INCREMENTAL`.
+    IncrementalVacuum {
+        /// The number of pages to free.
+        pages: u32,
+    },
+    /// Rebuild the database with `VACUUM`, which needs as much free disk
+    /// space as the database takes and holds the write lock throughout.
+    FullVacuum,
+    /// Write the WAL back to the database and truncate it with `PRAGMA
+    /// wal_checkpoint(TRUNCATE)`.
+    CheckpointTruncate,
+}
+
+/// Thresholds above which a [`StorageReport`] recommends reclaiming space.
+#[derive(Clone, Debug)]
+pub struct StorageThresholds {
+    /// The least share of free pages, in percent of all pages, worth a
+    /// vacuum.
+    pub min_freelist_percent: f64,
+    /// The least number of bytes in free pages worth a vacuum.
+    pub min_reclaimable_bytes: u64,
+    /// The largest WAL file, in bytes, not worth a checkpoint.
+    pub max_wal_bytes: u64,
+}
+
+impl Default for StorageThresholds {
+    fn default() -> StorageThresholds {
+        StorageThresholds {
+            min_freelist_percent: 20.0,
+            min_reclaimable_bytes: 1 << 20,
+            max_wal_bytes: 64 << 20,
+        }
+    }
+}
+
+/// Space used by the main database, as returned by
+/// [`Connection::storage_report`].
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct StorageReport {
+    /// The size of a page, in bytes.
+    pub page_size: u32,
+    /// The number of pages in the database.
+    pub page_count: u32,
+    /// The number of unused pages.
+    pub freelist_count: u32,
+    /// The share of unused pages, in percent of all pages.
+    pub freelist_percent: f64,
+    /// The number of bytes in unused pages, which a vacuum would free.
+    pub estimated_reclaimable_bytes: u64,
+    /// The `auto_vacuum` setting.
+    pub auto_vacuum_mode: AutoVacuum,
+    /// The size of the WAL file, or 0 if there is none.
+    pub wal_bytes: u64,
+    /// What to do about it.
+    pub recommendation: ReclaimPlan,
+}
+
+impl StorageReport {
+    // A WAL over the threshold comes first, as a vacuum in WAL mode would
+    // grow it further.
+    fn recommend(&self, thresholds: &StorageThresholds) -> ReclaimPlan {
+        if self.wal_bytes > thresholds.max_wal_bytes {
+            ReclaimPlan::CheckpointTruncate
+        } else if self.freelist_count == 0
+            || self.freelist_percent < thresholds.min_freelist_percent
+            || self.estimated_reclaimable_bytes < thresholds.min_reclaimable_bytes
+        {
+            ReclaimPlan::NoAction
+        } else if self.auto_vacuum_mode == AutoVacuum::Incremental {
+            ReclaimPlan::IncrementalVacuum {
+                pages: self.freelist_count,
+            }
+        } else {
+            ReclaimPlan::FullVacuum
+        }
+    }
+}
+
+/// The mode of a WAL checkpoint. See
+/// [`sqlite3_wal_checkpoint_v2`](https://sqlite.org/c3ref/wal_checkpoint_v2.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CheckpointMode {
+    /// Checkpoint as many frames as possible without waiting for other
+    /// connections.
+    Passive,
+    /// Wait for writers, then checkpoint all frames.
+    Full,
+    /// As `Full`, then wait for readers so that the WAL restarts from the
+    /// beginning.
+    Restart,
+    /// As `Restart`, then truncate the WAL file to zero bytes.
+    Truncate,
+}
+
+impl CheckpointMode {
+    fn to_c_int(self) -> std::os::raw::c_int {
+        match self {
+            CheckpointMode::Passive => ffi::SQLITE_CHECKPOINT_PASSIVE,
+            CheckpointMode::Full => ffi::SQLITE_CHECKPOINT_FULL,
+            CheckpointMode::Restart => ffi::SQLITE_CHECKPOINT_RESTART,
+            CheckpointMode::Truncate => ffi::SQLITE_CHECKPOINT_TRUNCATE,
+        }
+    }
+}
+
+/// The outcome of [`Connection::wal_checkpoint`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CheckpointResult {
+    /// Whether the checkpoint could not complete because other connections
+    /// were reading or writing.
+    pub busy: bool,
+    /// The number of frames in the WAL, -1 if the database is not in WAL
+    /// mode.
+    pub log_frames: i32,
+    /// The number of frames of the WAL copied back into the database, -1 if
+    /// the database is not in WAL mode.
+    pub checkpointed_frames: i32,
+}
+
+/// What [`Connection::maintain`] does.
+#[derive(Clone, Debug)]
+pub struct MaintenanceConfig {
+    /// Runs `ANALYZE` with `PRAGMA analysis_limit` set to this number of
+    /// rows per index, 0 meaning no limit. `None` skips it.
+    pub analysis_limit: Option<u32>,
+    /// Runs a WAL checkpoint in this mode. `None` skips it.
+    pub checkpoint_mode: Option<CheckpointMode>,
+    /// Runs `PRAGMA quick_check`.
+    pub quick_check: bool,
+    /// Frees up to this number of pages with `PRAGMA incremental_vacuum`,
+    /// 0 meaning all free pages. `None` skips it.
+    pub incremental_vacuum_pages: Option<u32>,
+    /// Skips the remaining phases once this has elapsed. `None` runs all of
+    /// them.
+    pub time_budget: Option<Duration>,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> MaintenanceConfig {
+        MaintenanceConfig {
+            analysis_limit: Some(400),
+            checkpoint_mode: None,
+            quick_check: false,
+            incremental_vacuum_pages: None,
+            time_budget: None,
+        }
+    }
+}
+
+/// A phase of [`Connection::maintain`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MaintenancePhase {
+    /// `PRAGMA optimize`, which always runs.
+    Optimize,
+    /// `ANALYZE`, bounded by [`MaintenanceConfig::analysis_limit`].
+    Analyze,
+    /// `PRAGMA wal_checkpoint`.
+    Checkpoint,
+    /// `PRAGMA incremental_vacuum`.
+    IncrementalVacuum,
+    /// `PRAGMA quick_check`.
+    QuickCheck,
+}
+
+/// How a phase of [`Connection::maintain`] ended.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PhaseOutcome {
+    /// The phase completed.
+    Done,
+    /// The phase was skipped as the time budget had elapsed.
+    Skipped,
+    /// The phase failed, e.g. with
+    /// [`ErrorCode::DatabaseBusy`](crate::ErrorCode::DatabaseBusy) if a
+    /// checkpoint could not complete because of other connections.
+    Failed(Error),
+    /// `PRAGMA quick_check` found these problems.
+    Problems(Vec<String>),
+}
+
+/// A phase of [`Connection::maintain`] and how it went.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct PhaseReport {
+    /// The phase.
+    pub phase: MaintenancePhase,
+    /// How long it took.
+    pub duration: Duration,
+    /// How it ended.
+    pub outcome: PhaseOutcome,
+}
+
+/// What [`Connection::maintain`] did.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct MaintenanceReport {
+    /// The enabled phases, in the order they ran or were skipped.
+    pub phases: Vec<PhaseReport>,
+}
+
+impl Connection {
+    /// Reports how much of the main database is unused, with the default
+    /// [`StorageThresholds`].
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if a pragma cannot be queried.
+    pub fn storage_report(&self) -> Result<StorageReport> {
+        self.storage_report_with(&StorageThresholds::default())
+    }
+
+    /// Reports how much of the main database is unused, recommending a
+    /// [`ReclaimPlan`] once `thresholds` are exceeded.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if a pragma cannot be queried.
+    pub fn storage_report_with(&self, thresholds: &StorageThresholds) -> Result<StorageReport> {
+        let page_size: u32 = self.pragma_query_value(None, "page_size", |row| row.get(0))?;
+        let page_count: u32 = self.pragma_query_value(None, "page_count", |row| row.get(0))?;
+        let freelist_count: u32 =
+            self.pragma_query_value(None, "freelist_count", |row| row.get(0))?;
+        let auto_vacuum_mode =
+            match self.pragma_query_value(None, "auto_vacuum", |row| row.get::<_, i32>(0))? {
+                1 => AutoVacuum::Full,
+                2 => AutoVacuum::Incremental,
+                _ => AutoVacuum::None,
+            };
+        let wal_bytes = match self.path() {
+            Some(path) if !path.is_empty() => {
+                fs::metadata(format!("{path}-wal")).map_or(0, |meta| meta.len())
+            }
+            _ => 0,
+        };
+        let freelist_percent = if page_count == 0 {
+            0.0
+        } else {
+            f64::from(freelist_count) * 100.0 / f64::from(page_count)
+        };
+        let mut report = StorageReport {
+            page_size,
+            page_count,
+            freelist_count,
+            freelist_percent,
+            estimated_reclaimable_bytes: u64::from(freelist_count) * u64::from(page_size),
+            auto_vacuum_mode,
+            wal_bytes,
+            recommendation: ReclaimPlan::NoAction,
+        };
+        report.recommendation = report.recommend(thresholds);
+        Ok(report)
+    }
+
+    /// Runs `plan`, usually the recommendation of a [`StorageReport`].
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails, or with
+    /// [`ErrorCode::DatabaseBusy`](crate::ErrorCode::DatabaseBusy) if a
+    /// checkpoint could not complete because of other connections.
+    pub fn reclaim(&self, plan: ReclaimPlan) -> Result<()> {
+        self.reclaim_(plan, || self.vacuum())
+    }
+
+    /// Runs `plan` as [`reclaim`](Connection::reclaim), stopping a full
+    /// vacuum with [`Error::Cancelled`] as soon as `token` is cancelled, as
+    /// [`vacuum_cancellable`](Connection::vacuum_cancellable).
+    ///
+    /// # Failure
+    ///
+    /// See [`reclaim`](Connection::reclaim).
+    #[cfg(feature = "hooks")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hooks")))]
+    pub fn reclaim_cancellable(&self, plan: ReclaimPlan, token: &CancellationToken) -> Result<()> {
+        self.reclaim_(plan, || self.vacuum_cancellable(token))
+    }
+
+    /// Rebuild the main database with `VACUUM`, which needs as much free
+    /// disk space as the database takes and holds the write lock throughout.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if a transaction is open, or if the underlying
+    /// SQLite call fails.
+    pub fn vacuum(&self) -> Result<()> {
+        self.execute_batch("VACUUM")
+    }
+
+    /// Write a vacuumed copy of the main database to the new file `path`
+    /// with `VACUUM INTO`, e.g. to ship a consistent snapshot of a database
+    /// which is being written to. `VACUUM INTO` requires SQLite 3.27.0 or
+    /// later.
+    ///
+    /// The path is bound as a parameter, so it needs no quoting. The copy has
+    /// the page size and `auto_vacuum` setting of the database, and is in
+    /// rollback journal mode even if the database is in WAL mode.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err(Error::InvalidPath)` if `path` is not valid UTF-8,
+    /// `Err` with `ErrorCode::CannotOpen` if it already exists or cannot be
+    /// created, `Err` with `ErrorCode::AuthorizationForStatementDenied` if an
+    /// authorizer denies the statement, or `Err` if the underlying SQLite call
+    /// fails.
+    pub fn vacuum_into<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.vacuum_into_with(path.as_ref(), |path| {
+            self.execute("VACUUM INTO ?1", [path]).map(|_| ())
+        })
+    }
+
+    // Checks `path` and runs `vacuum` with it as a string, making its
+    // failures explicit about the destination.
+    pub(crate) fn vacuum_into_with(
+        &self,
+        path: &Path,
+        vacuum: impl FnOnce(&str) -> Result<()>,
+    ) -> Result<()> {
+        let utf8_path = path
+            .to_str()
+            .ok_or_else(|| Error::InvalidPath(path.to_owned()))?;
+        if path.exists() {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_CANTOPEN),
+                Some(format!(
+                    "cannot vacuum into {utf8_path}: the file already exists"
+                )),
+            ));
+        }
+        vacuum(utf8_path).map_err(|err| match err {
+            Error::SqliteFailure(code, msg)
+                if code.code == ErrorCode::CannotOpen
+                    || code.code == ErrorCode::AuthorizationForStatementDenied =>
+            {
+                let reason = msg.unwrap_or_else(|| code.to_string());
+                Error::SqliteFailure(
+                    code,
+                    Some(format!("cannot vacuum into {utf8_path}: {reason}")),
+                )
+            }
+            err => err,
+        })
+    }
+
+    /// Free up to `pages` unused pages of the database `db` with `PRAGMA
+    /// incremental_vacuum`, 0 meaning all of them. This does nothing unless
+    /// `auto_vacuum` is `INCREMENTAL`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `db` is not a database of this connection, or if
+    /// the underlying SQLite call fails.
+    pub fn incremental_vacuum(&self, db: DatabaseName<'_>, pages: u32) -> Result<()> {
+        let mut sql = Sql::new();
+        sql.push_pragma(Some(db), "incremental_vacuum")?;
+        sql.open_brace();
+        sql.push_int(i64::from(pages));
+        sql.close_brace();
+        // each step frees one page
+        let mut stmt = self.prepare(&sql)?;
+        let mut rows = stmt.query([])?;
+        while rows.next()?.is_some() {}
+        Ok(())
+    }
+
+    fn reclaim_(&self, plan: ReclaimPlan, vacuum: impl FnOnce() -> Result<()>) -> Result<()> {
+        match plan {
+            ReclaimPlan::NoAction => Ok(()),
+            ReclaimPlan::IncrementalVacuum { pages } => {
+                self.incremental_vacuum(DatabaseName::Main, pages)
+            }
+            ReclaimPlan::FullVacuum => vacuum(),
+            ReclaimPlan::CheckpointTruncate => self.checkpoint(CheckpointMode::Truncate),
+        }
+    }
+
+    fn checkpoint(&self, mode: CheckpointMode) -> Result<()> {
+        if self.wal_checkpoint(None, mode)?.busy {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_BUSY),
+                Some(
+                    "the checkpoint could not complete: other connections are reading or \
+                     writing"
+                        .to_owned(),
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Copies the frames of the WAL back into the database `db`, or into all
+    /// attached databases if `db` is `None`. See
+    /// [`sqlite3_wal_checkpoint_v2`](https://sqlite.org/c3ref/wal_checkpoint_v2.html).
+    ///
+    /// A checkpoint that cannot complete because of other connections, e.g.
+    /// a `Restart` one while some are reading, is not an error: it is
+    /// reported by [`CheckpointResult::busy`]. A database not in WAL mode
+    /// is a no-op.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `db` is not attached, or if the checkpoint fails.
+    pub fn wal_checkpoint(
+        &self,
+        db: Option<&str>,
+        mode: CheckpointMode,
+    ) -> Result<CheckpointResult> {
+        let db_name = db.map(str_to_cstring).transpose()?;
+        let c = self.db.borrow();
+        let mut log_frames = 0;
+        let mut checkpointed_frames = 0;
+        let rc = unsafe {
+            ffi::sqlite3_wal_checkpoint_v2(
+                c.db(),
+                db_name.as_ref().map_or(ptr::null(), |name| name.as_ptr()),
+                mode.to_c_int(),
+                &mut log_frames,
+                &mut checkpointed_frames,
+            )
+        };
+        let busy = rc == ffi::SQLITE_BUSY;
+        if !busy {
+            c.decode_result(rc)?;
+        }
+        Ok(CheckpointResult {
+            busy,
+            log_frames,
+            checkpointed_frames,
+        })
+    }
+
+    /// Runs a `Passive` checkpoint after each commit that leaves at least
+    /// `pages` frames in the WAL, or never if `pages` is 0 or less. SQLite
+    /// defaults to 1000 pages. See
+    /// [`sqlite3_wal_autocheckpoint`](https://sqlite.org/c3ref/wal_autocheckpoint.html).
+    ///
+    /// This replaces any callback registered with `wal_hook`, and
+    /// registering one disables the automatic checkpoints.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn wal_autocheckpoint(&self, pages: i32) -> Result<()> {
+        // SQLite installs its own WAL hook, so free ours first.
+        #[cfg(feature = "hooks")]
+        self.db
+            .borrow_mut()
+            .wal_hook(None::<fn(&str, i32) -> Result<()>>);
+        let c = self.db.borrow();
+        let rc = unsafe { ffi::sqlite3_wal_autocheckpoint(c.db(), pages) };
+        c.decode_result(rc)
+    }
+
+    /// Runs the maintenance phases enabled by `config`, in order: `PRAGMA
+    /// optimize`, a bounded `ANALYZE`, a WAL checkpoint, an incremental vacuum
+    /// and `PRAGMA quick_check`.
+    ///
+    /// Meant to be called periodically, e.g. when an application is idle. A
+    /// phase that fails does not stop the next ones; its error is in the
+    /// report. Once [`time_budget`](MaintenanceConfig::time_budget) has
+    /// elapsed, the remaining phases are skipped, so the call can take longer
+    /// than the budget by as long as a single phase.
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// # use std::time::Duration;
+    /// use rusqlite::storage::{CheckpointMode, MaintenanceConfig};
+    ///
+    /// fn on_idle(conn: &Connection) -> Result<()> {
+    ///     let report = conn.maintain(&MaintenanceConfig {
+    ///         checkpoint_mode: Some(CheckpointMode::Passive),
+    ///         time_budget: Some(Duration::from_millis(200)),
+    ///         ..MaintenanceConfig::default()
+    ///     })?;
+    ///     for phase in &report.phases {
+    ///         println!("{:?}: {:?} in {:?}", phase.phase, phase.outcome, phase.duration);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if a transaction is open, as `ANALYZE` and
+    /// checkpoints would not see or could not include its changes.
+    pub fn maintain(&self, config: &MaintenanceConfig) -> Result<MaintenanceReport> {
+        if !self.is_autocommit() {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_MISUSE),
+                Some("maintain called inside a transaction".to_owned()),
+            ));
+        }
+        let mut phases = vec![MaintenancePhase::Optimize];
+        if config.analysis_limit.is_some() {
+            phases.push(MaintenancePhase::Analyze);
+        }
+        if config.checkpoint_mode.is_some() {
+            phases.push(MaintenancePhase::Checkpoint);
+        }
+        if config.incremental_vacuum_pages.is_some() {
+            phases.push(MaintenancePhase::IncrementalVacuum);
+        }
+        if config.quick_check {
+            phases.push(MaintenancePhase::QuickCheck);
+        }
+
+        let start = Instant::now();
+        let mut report = MaintenanceReport { phases: Vec::new() };
+        for phase in phases {
+            let over_budget = config
+                .time_budget
+                .is_some_and(|budget| start.elapsed() >= budget);
+            // the first phase always runs
+            if over_budget && !report.phases.is_empty() {
+                report.phases.push(PhaseReport {
+                    phase,
+                    duration: Duration::ZERO,
+                    outcome: PhaseOutcome::Skipped,
+                });
+                continue;
+            }
+            let phase_start = Instant::now();
+            let outcome = match self.run_phase(phase, config) {
+                Ok(outcome) => outcome,
+                Err(err) => PhaseOutcome::Failed(err),
+            };
+            report.phases.push(PhaseReport {
+                phase,
+                duration: phase_start.elapsed(),
+                outcome,
+            });
+        }
+        Ok(report)
+    }
+
+    fn run_phase(
+        &self,
+        phase: MaintenancePhase,
+        config: &MaintenanceConfig,
+    ) -> Result<PhaseOutcome> {
+        match phase {
+            MaintenancePhase::Optimize => self.execute_batch("PRAGMA optimize")?,
+            MaintenancePhase::Analyze => {
+                // `PRAGMA analysis_limit` returns nothing before SQLite 3.32.0
+                let set_limit = |limit: u32| {
+                    self.pragma_update_and_check(None, "analysis_limit", limit, |_| Ok(()))
+                };
+                let previous: Option<u32> = self
+                    .pragma_query_value(None, "analysis_limit", |row| row.get(0))
+                    .optional()?;
+                if previous.is_some() {
+                    set_limit(config.analysis_limit.unwrap_or(0))?;
+                }
+                let result = self.execute_batch("ANALYZE");
+                if let Some(previous) = previous {
+                    set_limit(previous)?;
+                }
+                result?;
+            }
+            MaintenancePhase::Checkpoint => {
+                self.checkpoint(config.checkpoint_mode.unwrap_or(CheckpointMode::Passive))?;
+            }
+            MaintenancePhase::IncrementalVacuum => {
+                let pages = config.incremental_vacuum_pages.unwrap_or(0);
+                self.reclaim(ReclaimPlan::IncrementalVacuum { pages })?;
+            }
+            MaintenancePhase::QuickCheck => {
+                let mut stmt = self.prepare("PRAGMA quick_check")?;
+                let problems = stmt
+                    .query_map([], |row| row.get::<_, String>(0))?
+                    .collect::<Result<Vec<_>>>()?;
+                if problems != ["ok"] {
+                    return Ok(PhaseOutcome::Problems(problems));
+                }
+            }
+        }
+        Ok(PhaseOutcome::Done)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{
+        CheckpointMode, MaintenanceConfig, MaintenancePhase, PhaseOutcome, ReclaimPlan,
+        StorageThresholds,
+    };
+    use crate::{Connection, Error, ErrorCode, Result, MAIN_DB};
+
+    fn fill(db: &Connection, rows: i64) -> Result<()> {
+        db.execute_batch("CREATE TABLE t (x INTEGER PRIMARY KEY, y BLOB)")?;
+        let tx = db.unchecked_transaction()?;
+        for i in 0..rows {
+            tx.execute("INSERT INTO t VALUES (?1, zeroblob(1000))", [i])?;
+        }
+        tx.commit()
+    }
+
+    #[test]
+    fn test_vacuum_recommended() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let db = Connection::open(&path)?;
+        fill(&db, 2000)?;
+        db.execute("DELETE FROM t WHERE x >= 400", [])?;
+
+        let report = db.storage_report()?;
+        assert!(report.freelist_percent > 70.0, "{:?}", report);
+        assert_eq!(report.recommendation, ReclaimPlan::FullVacuum);
+
+        let size = std::fs::metadata(&path).unwrap().len();
+        db.reclaim(report.recommendation)?;
+        assert!(std::fs::metadata(&path).unwrap().len() < size / 2);
+
+        let report = db.storage_report()?;
+        assert_eq!(report.freelist_count, 0);
+        assert_eq!(report.recommendation, ReclaimPlan::NoAction);
+        Ok(())
+    }
+
+    #[test]
+    fn test_incremental_vacuum_recommended() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("PRAGMA auto_vacuum = INCREMENTAL")?;
+        fill(&db, 2000)?;
+        db.execute("DELETE FROM t WHERE x >= 400", [])?;
+
+        let report = db.storage_report()?;
+        let pages = report.freelist_count;
+        assert_eq!(
+            report.recommendation,
+            ReclaimPlan::IncrementalVacuum { pages }
+        );
+        db.reclaim(report.recommendation)?;
+        assert_eq!(db.storage_report()?.freelist_count, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_vacuum_into() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        fill(&db, 500)?;
+        db.execute("DELETE FROM t WHERE x >= 100", [])?;
+        let dir = tempfile::tempdir().unwrap();
+        // needs no quoting
+        let path = dir.path().join("it's a copy.db3");
+        db.vacuum_into(&path)?;
+
+        let copy = Connection::open(&path)?;
+        let rows: i64 = copy.one_column("SELECT count(*) FROM t")?;
+        assert_eq!(rows, 100);
+        assert_eq!(copy.storage_report()?.freelist_count, 0);
+
+        let err = db.vacuum_into(&path).unwrap_err();
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::CannotOpen));
+        assert!(err.to_string().contains("already exists"), "{}", err);
+
+        let missing = dir.path().join("missing").join("copy.db3");
+        let err = db.vacuum_into(&missing).unwrap_err();
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::CannotOpen));
+        assert!(err.to_string().contains("cannot vacuum into"), "{}", err);
+        assert!(!missing.exists());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_vacuum_into_non_utf8() -> Result<()> {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let db = Connection::open_in_memory()?;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(OsStr::from_bytes(b"copy-\xff.db3"));
+        let err = db.vacuum_into(&path).unwrap_err();
+        assert_eq!(err, Error::InvalidPath(path.clone()));
+        assert!(!path.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_vacuum() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("PRAGMA auto_vacuum = INCREMENTAL")?;
+        fill(&db, 500)?;
+        db.execute("DELETE FROM t WHERE x >= 100", [])?;
+        let free = db.storage_report()?.freelist_count;
+        assert!(free > 10);
+        db.incremental_vacuum(MAIN_DB, 10)?;
+        assert_eq!(db.storage_report()?.freelist_count, free - 10);
+        db.vacuum()?;
+        assert_eq!(db.storage_report()?.freelist_count, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_checkpoint_recommended() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let db = Connection::open(&path)?;
+        db.pragma_update_and_check(None, "journal_mode", "wal", |_| Ok(()))?;
+        db.pragma_update_and_check(None, "wal_autocheckpoint", 0, |_| Ok(()))?;
+        fill(&db, 500)?;
+
+        let thresholds = StorageThresholds {
+            max_wal_bytes: 64 << 10,
+            ..StorageThresholds::default()
+        };
+        let report = db.storage_report_with(&thresholds)?;
+        assert!(report.wal_bytes > 64 << 10, "{:?}", report);
+        assert_eq!(report.recommendation, ReclaimPlan::CheckpointTruncate);
+        db.reclaim(report.recommendation)?;
+
+        let report = db.storage_report_with(&thresholds)?;
+        assert_eq!(report.wal_bytes, 0);
+        assert_eq!(report.recommendation, ReclaimPlan::NoAction);
+        Ok(())
+    }
+
+    #[test]
+    fn test_maintain() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db = Connection::open(temp_dir.path().join("test.db3"))?;
+        db.execute_batch("PRAGMA auto_vacuum = INCREMENTAL")?;
+        db.pragma_update_and_check(None, "journal_mode", "wal", |_| Ok(()))?;
+        fill(&db, 200)?;
+        db.execute_batch("CREATE INDEX t_y ON t (y)")?;
+        db.execute("DELETE FROM t WHERE x >= 100", [])?;
+
+        let config = MaintenanceConfig {
+            checkpoint_mode: Some(CheckpointMode::Truncate),
+            incremental_vacuum_pages: Some(0),
+            quick_check: true,
+            ..MaintenanceConfig::default()
+        };
+        let report = db.maintain(&config)?;
+        let phases: Vec<_> = report.phases.iter().map(|p| p.phase).collect();
+        assert_eq!(
+            phases,
+            [
+                MaintenancePhase::Optimize,
+                MaintenancePhase::Analyze,
+                MaintenancePhase::Checkpoint,
+                MaintenancePhase::IncrementalVacuum,
+                MaintenancePhase::QuickCheck
+            ]
+        );
+        for phase in &report.phases {
+            assert!(matches!(phase.outcome, PhaseOutcome::Done), "{:?}", phase);
+        }
+        let stats: i64 = db.one_column("SELECT count(*) FROM sqlite_stat1")?;
+        assert!(stats > 0);
+        assert_eq!(db.storage_report()?.freelist_count, 0);
+        let limit: u32 = db.pragma_query_value(None, "analysis_limit", |row| row.get(0))?;
+        assert_eq!(limit, 0);
+
+        // in a transaction
+        let tx = db.unchecked_transaction()?;
+        assert!(tx.maintain(&config).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_maintain_time_budget() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        fill(&db, 10)?;
+        let report = db.maintain(&MaintenanceConfig {
+            quick_check: true,
+            time_budget: Some(Duration::from_nanos(1)),
+            ..MaintenanceConfig::default()
+        })?;
+        let outcomes: Vec<_> = report
+            .phases
+            .iter()
+            .map(|p| (p.phase, matches!(p.outcome, PhaseOutcome::Skipped)))
+            .collect();
+        assert_eq!(
+            outcomes,
+            [
+                (MaintenancePhase::Optimize, false),
+                (MaintenancePhase::Analyze, true),
+                (MaintenancePhase::QuickCheck, true)
+            ]
+        );
+        assert_eq!(report.phases[1].duration, Duration::ZERO);
+        Ok(())
+    }
+
+    #[test]
+    fn test_maintain_with_readers() -> Result<()> {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+        use std::thread;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let db = Connection::open(&path)?;
+        db.pragma_update_and_check(None, "journal_mode", "wal", |_| Ok(()))?;
+        fill(&db, 200)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let stop = stop.clone();
+            thread::spawn(move || -> Result<u32> {
+                let db = Connection::open(path)?;
+                let mut reads = 0;
+                loop {
+                    let count: i64 = db.one_column("SELECT count(*) FROM t")?;
+                    assert_eq!(count, 200);
+                    reads += 1;
+                    if stop.load(Ordering::Relaxed) {
+                        return Ok(reads);
+                    }
+                }
+            })
+        };
+        let config = MaintenanceConfig {
+            checkpoint_mode: Some(CheckpointMode::Passive),
+            quick_check: true,
+            ..MaintenanceConfig::default()
+        };
+        for _ in 0..5 {
+            let report = db.maintain(&config)?;
+            for phase in &report.phases {
+                assert!(matches!(phase.outcome, PhaseOutcome::Done), "{:?}", phase);
+            }
+        }
+        stop.store(true, Ordering::Relaxed);
+        assert!(reader.join().unwrap()? > 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_wal_checkpoint() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let wal = temp_dir.path().join("test.db3-wal");
+        let db = Connection::open(&path)?;
+        let mode: String = db.one_column("PRAGMA journal_mode = WAL")?;
+        assert_eq!(mode, "wal");
+        db.wal_autocheckpoint(0)?;
+        fill(&db, 200)?;
+        let size = std::fs::metadata(&wal).unwrap().len();
+        assert!(size > 0);
+
+        let result = db.wal_checkpoint(Some("main"), CheckpointMode::Passive)?;
+        assert!(!result.busy);
+        assert!(result.log_frames > 0);
+        assert_eq!(result.checkpointed_frames, result.log_frames);
+
+        let reader = Connection::open(&path)?;
+        reader.execute_batch("BEGIN")?;
+        let count: i64 = reader.one_column("SELECT count(*) FROM t")?;
+        assert_eq!(count, 200);
+        db.execute("DELETE FROM t WHERE x >= 100", [])?;
+        db.busy_timeout(Duration::ZERO)?;
+        let result = db.wal_checkpoint(None, CheckpointMode::Restart)?;
+        assert!(result.busy);
+        reader.execute_batch("COMMIT")?;
+
+        let result = db.wal_checkpoint(None, CheckpointMode::Truncate)?;
+        assert!(!result.busy);
+        assert_eq!(result.log_frames, 0);
+        assert_eq!(std::fs::metadata(&wal).unwrap().len(), 0);
+
+        let err = db
+            .wal_checkpoint(Some("nope"), CheckpointMode::Passive)
+            .unwrap_err();
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::Unknown));
+        Ok(())
+    }
+
+    #[test]
+    fn test_wal_checkpoint_not_wal() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        let result = db.wal_checkpoint(None, CheckpointMode::Full)?;
+        assert_eq!(result.log_frames, -1);
+        assert_eq!(result.checkpointed_frames, -1);
+        Ok(())
+    }
+}
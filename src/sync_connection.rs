@@ -0,0 +1,211 @@
+//! A connection shared between threads, whose checkouts are profiled.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError, TryLockError};
+use std::time::Instant;
+
+use crate::{Connection, Params, Result, Row};
+
+/// A [`Connection`] behind a mutex, so that threads can take turns using it,
+/// instrumented for the `contention-profiling` feature.
+///
+/// Each use checks the connection out for the calling thread, which waits
+/// while another thread has it. These waits, and how many threads queue up,
+/// are recorded in [`stats`](crate::stats).
+///
+/// ```rust,no_run
+/// # use rusqlite::{Connection, Result, SyncConnection};
+/// # use std::sync::Arc;
+/// # use std::thread;
+/// fn log_from_threads() -> Result<()> {
+///     let conn = Arc::new(SyncConnection::new(Connection::open("log.db3")?));
+///     let workers: Vec<_> = (0..4)
+///         .map(|i| {
+///             let conn = conn.clone();
+///             thread::spawn(move || conn.execute("INSERT INTO log VALUES (?1)", [i]))
+///         })
+///         .collect();
+///     for worker in workers {
+///         worker.join().unwrap()?;
+///     }
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct SyncConnection {
+    conn: Mutex<Connection>,
+    // Threads waiting for the connection
+    waiting: AtomicUsize,
+}
+
+impl SyncConnection {
+    /// Share `conn` between threads.
+    #[must_use]
+    pub fn new(conn: Connection) -> SyncConnection {
+        SyncConnection {
+            conn: Mutex::new(conn),
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    /// Check the connection out, waiting until no other thread has it.
+    ///
+    /// A thread which panicked while having it does not make it unusable:
+    /// the transaction it left open, if any, is rolled back.
+    pub fn lock(&self) -> MutexGuard<'_, Connection> {
+        self.checkout(None)
+    }
+
+    /// Run `sql` on the connection, see [`Connection::execute`].
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` as [`Connection::execute`] does.
+    pub fn execute<P: Params>(&self, sql: &str, params: P) -> Result<usize> {
+        self.checkout(Some(sql)).execute(sql, params)
+    }
+
+    /// Run the statements of `sql` on the connection, see
+    /// [`Connection::execute_batch`].
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` as [`Connection::execute_batch`] does.
+    pub fn execute_batch(&self, sql: &str) -> Result<()> {
+        self.checkout(Some(sql)).execute_batch(sql)
+    }
+
+    /// Run `sql` on the connection and map its first row with `f`, see
+    /// [`Connection::query_row`].
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` as [`Connection::query_row`] does.
+    pub fn query_row<T, P, F>(&self, sql: &str, params: P, f: F) -> Result<T>
+    where
+        P: Params,
+        F: FnOnce(&Row<'_>) -> Result<T>,
+    {
+        self.checkout(Some(sql)).query_row(sql, params, f)
+    }
+
+    /// Returns the connection.
+    ///
+    /// As with [`lock`](SyncConnection::lock), a transaction left open by a
+    /// thread which panicked is rolled back.
+    #[must_use]
+    pub fn into_inner(self) -> Connection {
+        match self.conn.into_inner() {
+            Ok(conn) => conn,
+            Err(err) => {
+                let conn = err.into_inner();
+                rollback_abandoned(&conn);
+                conn
+            }
+        }
+    }
+
+    fn checkout(&self, sql: Option<&str>) -> MutexGuard<'_, Connection> {
+        let start = Instant::now();
+        let guard = match self.conn.try_lock() {
+            Ok(guard) => guard,
+            Err(TryLockError::Poisoned(err)) => recover(err),
+            Err(TryLockError::WouldBlock) => {
+                let depth = self.waiting.fetch_add(1, Ordering::Relaxed) + 1;
+                crate::stats::record_queue_depth(depth);
+                let guard = self.conn.lock().unwrap_or_else(recover);
+                self.waiting.fetch_sub(1, Ordering::Relaxed);
+                guard
+            }
+        };
+        crate::stats::record_lock_wait(start.elapsed(), sql);
+        guard
+    }
+}
+
+// The mutex stays poisoned, so this runs on each checkout after a panic, but
+// only costs a look at the autocommit flag once the transaction is gone.
+fn recover(err: PoisonError<MutexGuard<'_, Connection>>) -> MutexGuard<'_, Connection> {
+    let guard = err.into_inner();
+    rollback_abandoned(&guard);
+    guard
+}
+
+// A thread panicked while having the connection, maybe in the middle of a
+// transaction that nobody is going to finish.
+fn rollback_abandoned(conn: &Connection) {
+    if !conn.is_autocommit() {
+        let _ = conn.execute_batch("ROLLBACK");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::SyncConnection;
+    use crate::{Connection, Result};
+
+    #[test]
+    fn test_sync_connection() -> Result<()> {
+        let _guard = crate::stats::TEST_LOCK
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let conn = Arc::new(SyncConnection::new(Connection::open_in_memory()?));
+        conn.execute_batch("CREATE TABLE t(x)")?;
+        let workers: Vec<_> = (0..4)
+            .map(|i| {
+                let conn = conn.clone();
+                thread::spawn(move || -> Result<()> {
+                    for j in 0..25 {
+                        conn.execute("INSERT INTO t VALUES (?1)", [i * 25 + j])?;
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap()?;
+        }
+        let sum: i64 = conn.query_row("SELECT sum(x) FROM t", [], |r| r.get(0))?;
+        assert_eq!(sum, 4950);
+
+        // a panic while checked out does not poison it
+        let panicking = conn.clone();
+        thread::spawn(move || {
+            let _conn = panicking.lock();
+            panic!("while checked out");
+        })
+        .join()
+        .unwrap_err();
+        let conn = Arc::try_unwrap(conn).unwrap().into_inner();
+        let n: i64 = conn.one_column("SELECT count(*) FROM t")?;
+        assert_eq!(n, 100);
+        Ok(())
+    }
+
+    #[test]
+    fn test_panic_in_transaction() -> Result<()> {
+        let _guard = crate::stats::TEST_LOCK
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let conn = Arc::new(SyncConnection::new(Connection::open_in_memory()?));
+        conn.execute_batch("CREATE TABLE t(x)")?;
+        let panicking = conn.clone();
+        thread::spawn(move || {
+            let conn = panicking.lock();
+            conn.execute_batch("BEGIN; INSERT INTO t VALUES (1)")
+                .unwrap();
+            panic!("in a transaction");
+        })
+        .join()
+        .unwrap_err();
+        assert!(conn.lock().is_autocommit());
+        conn.execute("INSERT INTO t VALUES (2)", [])?;
+        let conn = Arc::try_unwrap(conn).unwrap().into_inner();
+        let sum: i64 = conn.one_column("SELECT sum(x) FROM t")?;
+        assert_eq!(sum, 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,752 @@
+//! Export the rows of a single table to a compact binary stream, and import
+//! them back, e.g. to back up individual tables rather than whole database
+//! files.
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result};
+//! use rusqlite::table_io::{ExportOptions, ImportPolicy};
+//! use std::fs::File;
+//! use std::io::{BufReader, BufWriter};
+//!
+//! fn copy_users(src: &Connection, dst: &Connection) -> Result<()> {
+//!     let mut out = BufWriter::new(File::create("users.tbl").unwrap());
+//!     src.export_table("users", &mut out, &ExportOptions::default())?;
+//!     drop(out);
+//!     let mut input = BufReader::new(File::open("users.tbl").unwrap());
+//!     dst.import_table("users", &mut input, ImportPolicy::Replace)?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! The stream stores every value with its storage class, so that all of them
+//! round-trip exactly, REAL bit patterns and TEXT that is not valid UTF-8 or
+//! contains NUL characters included. Version 1 of the format is, with all
+//! integers little-endian:
+//!
+//! - the magic bytes `RSQLTBL`, the version (1) and a flags byte, whose bit 0
+//!   means that the rest of the stream is compressed with zstd;
+//! - the number of columns (`u32`), and the name and declared type of each
+//!   one, both as a `u32` length followed by UTF-8;
+//! - each row, as the byte 1 followed by a tag for each value: 0 for NULL, 1
+//!   for an INTEGER (`i64`), 2 for a REAL (the `u64` bits of the `f64`), 3 for
+//!   TEXT and 4 for a BLOB (both as a `u32` length followed by the bytes);
+//! - the byte 0, followed by the number of rows (`u64`).
+
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+
+use crate::pragma::Sql;
+use crate::types::{ToSqlOutput, ValueRef};
+use crate::{ffi, Connection, Error, Result, Savepoint};
+
+const MAGIC: &[u8; 7] = b"RSQLTBL";
+const VERSION: u8 = 1;
+const FLAG_ZSTD: u8 = 1;
+
+const ROW: u8 = 1;
+const END: u8 = 0;
+
+const NULL: u8 = 0;
+const INTEGER: u8 = 1;
+const REAL: u8 = 2;
+const TEXT: u8 = 3;
+const BLOB: u8 = 4;
+
+/// Options of [`Connection::export_table`].
+#[derive(Clone, Debug, Default)]
+pub struct ExportOptions {
+    /// Compress the stream with zstd, at this level (0 for the default
+    /// level of zstd).
+    #[cfg(feature = "zstd")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "zstd")))]
+    pub zstd_level: Option<i32>,
+}
+
+/// What a [`Connection::export_table`] did.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ExportStats {
+    /// The number of rows exported.
+    pub rows: u64,
+    /// The number of bytes written, after compression.
+    pub bytes: u64,
+}
+
+/// What [`Connection::import_table`] does with the table imported into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ImportPolicy {
+    /// Create the table from the exported columns if it does not exist,
+    /// otherwise check that its columns are the exported ones and append the
+    /// rows.
+    CreateIfMissing,
+    /// Check that the table exists with the exported columns, and append the
+    /// rows.
+    AppendOnly,
+    /// Drop the table if it exists, and create it again from the exported
+    /// columns.
+    Replace,
+}
+
+/// What [`Connection::import_table`] does with a row that violates a
+/// constraint of the table, e.g. a duplicate primary key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConflictPolicy {
+    /// Fail, rolling back the current chunk (`INSERT OR ABORT`).
+    Abort,
+    /// Skip the row (`INSERT OR IGNORE`).
+    Ignore,
+    /// Replace the rows it conflicts with (`INSERT OR REPLACE`).
+    Replace,
+}
+
+/// Options of [`Connection::import_table_with`].
+#[derive(Clone, Debug)]
+pub struct ImportOptions {
+    /// What to do with the table imported into.
+    pub policy: ImportPolicy,
+    /// What to do with a row that violates a constraint.
+    pub on_conflict: ConflictPolicy,
+    /// Commit after this many rows.
+    pub chunk_rows: usize,
+}
+
+impl Default for ImportOptions {
+    fn default() -> ImportOptions {
+        ImportOptions {
+            policy: ImportPolicy::CreateIfMissing,
+            on_conflict: ConflictPolicy::Abort,
+            chunk_rows: 10_000,
+        }
+    }
+}
+
+/// What a [`Connection::import_table`] did.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ImportStats {
+    /// The number of rows read from the stream.
+    pub rows: u64,
+    /// The number of rows inserted, less than `rows` if some were ignored.
+    pub inserted: u64,
+    /// The number of transactions committed.
+    pub chunks: usize,
+    /// Whether the table was created.
+    pub created: bool,
+}
+
+// A column, as exported.
+#[derive(Clone, Debug, PartialEq)]
+struct Column {
+    name: String,
+    decl_type: String,
+}
+
+impl Column {
+    fn describe(&self) -> String {
+        if self.decl_type.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{} {}", self.name, self.decl_type)
+        }
+    }
+
+    fn same_as(&self, other: &Column) -> bool {
+        self.name.eq_ignore_ascii_case(&other.name)
+            && self.decl_type.eq_ignore_ascii_case(&other.decl_type)
+    }
+}
+
+// Counts the bytes written to the caller's writer.
+struct Counting<'w, W: ?Sized> {
+    inner: &'w mut W,
+    bytes: u64,
+}
+
+impl<W: Write + ?Sized> Write for Counting<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.bytes += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn stream_error(msg: String) -> Error {
+    Error::TableStreamFailed(io::Error::new(io::ErrorKind::InvalidData, msg))
+}
+
+fn write_len<W: Write + ?Sized>(w: &mut W, bytes: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(bytes.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "value too large"))?;
+    w.write_all(&len.to_le_bytes())?;
+    w.write_all(bytes)
+}
+
+fn read_u8<R: Read + ?Sized>(r: &mut R) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u64<R: Read + ?Sized>(r: &mut R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+// Reads a length-prefixed value into `buf`, without trusting the length to
+// allocate.
+fn read_len<R: Read + ?Sized>(r: &mut R, buf: &mut Vec<u8>) -> io::Result<()> {
+    let mut len = [0; 4];
+    r.read_exact(&mut len)?;
+    let len = u64::from(u32::from_le_bytes(len));
+    buf.clear();
+    if r.take(len).read_to_end(buf)? as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+fn read_string<R: Read + ?Sized>(r: &mut R) -> Result<String> {
+    let mut buf = Vec::new();
+    read_len(r, &mut buf).map_err(Error::TableStreamFailed)?;
+    String::from_utf8(buf).map_err(|_| stream_error("column name is not UTF-8".to_owned()))
+}
+
+fn identifier(name: &str) -> String {
+    let mut sql = Sql::new();
+    sql.push_identifier(name);
+    sql.as_str().to_owned()
+}
+
+impl Connection {
+    /// Write the rows of `table` to `writer`, in the format described in the
+    /// [module documentation](crate::table_io), preceded by the names and
+    /// declared types of its columns.
+    ///
+    /// The stream is written in small pieces, so `writer` should be
+    /// buffered.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if there is no such table, or with an
+    /// [`Error::TableStreamFailed`] if `writer` fails.
+    pub fn export_table<W: Write>(
+        &self,
+        table: &str,
+        writer: &mut W,
+        options: &ExportOptions,
+    ) -> Result<ExportStats> {
+        let columns: Vec<Column> = self
+            .table_info(table)?
+            .into_iter()
+            .map(|c| Column {
+                name: c.name,
+                decl_type: c.decl_type,
+            })
+            .collect();
+        if columns.is_empty() {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_ERROR),
+                Some(format!("no such table: {table}")),
+            ));
+        }
+        let mut out = Counting {
+            inner: writer,
+            bytes: 0,
+        };
+        #[cfg(feature = "zstd")]
+        let flags = if options.zstd_level.is_some() {
+            FLAG_ZSTD
+        } else {
+            0
+        };
+        #[cfg(not(feature = "zstd"))]
+        let flags = {
+            let _ = options;
+            0
+        };
+        out.write_all(MAGIC)
+            .and_then(|_| out.write_all(&[VERSION, flags]))
+            .map_err(Error::TableStreamFailed)?;
+        #[cfg(feature = "zstd")]
+        if let Some(level) = options.zstd_level {
+            let mut encoder =
+                zstd::Encoder::new(&mut out, level).map_err(Error::TableStreamFailed)?;
+            let rows = self.write_rows(table, &columns, &mut encoder)?;
+            encoder.finish().map_err(Error::TableStreamFailed)?;
+            return Ok(ExportStats {
+                rows,
+                bytes: out.bytes,
+            });
+        }
+        let rows = self.write_rows(table, &columns, &mut out)?;
+        out.flush().map_err(Error::TableStreamFailed)?;
+        Ok(ExportStats {
+            rows,
+            bytes: out.bytes,
+        })
+    }
+
+    fn write_rows<W: Write>(&self, table: &str, columns: &[Column], w: &mut W) -> Result<u64> {
+        let io = Error::TableStreamFailed;
+        w.write_all(&(columns.len() as u32).to_le_bytes())
+            .map_err(io)?;
+        for column in columns {
+            write_len(w, column.name.as_bytes()).map_err(io)?;
+            write_len(w, column.decl_type.as_bytes()).map_err(io)?;
+        }
+        let names: Vec<String> = columns.iter().map(|c| identifier(&c.name)).collect();
+        let sql = format!("SELECT {} FROM {}", names.join(", "), identifier(table));
+        let mut stmt = self.prepare(&sql)?;
+        let mut rows = stmt.query([])?;
+        let mut count = 0u64;
+        while let Some(row) = rows.next()? {
+            w.write_all(&[ROW]).map_err(io)?;
+            for i in 0..columns.len() {
+                let written = match row.get_ref_unwrap(i) {
+                    ValueRef::Null => w.write_all(&[NULL]),
+                    ValueRef::Integer(i) => w
+                        .write_all(&[INTEGER])
+                        .and_then(|_| w.write_all(&i.to_le_bytes())),
+                    ValueRef::Real(f) => w
+                        .write_all(&[REAL])
+                        .and_then(|_| w.write_all(&f.to_bits().to_le_bytes())),
+                    ValueRef::Text(s) => w.write_all(&[TEXT]).and_then(|_| write_len(w, s)),
+                    ValueRef::Blob(b) => w.write_all(&[BLOB]).and_then(|_| write_len(w, b)),
+                };
+                written.map_err(io)?;
+            }
+            count += 1;
+        }
+        w.write_all(&[END])
+            .and_then(|_| w.write_all(&count.to_le_bytes()))
+            .map_err(io)?;
+        Ok(count)
+    }
+
+    /// Import the rows exported by
+    /// [`export_table`](Connection::export_table) from `reader` into
+    /// `table`, with the default [`ImportOptions`] but for `policy`.
+    ///
+    /// The stream is read in small pieces, so `reader` should be buffered.
+    ///
+    /// # Failure
+    ///
+    /// Fails as [`import_table_with`](Connection::import_table_with) does.
+    pub fn import_table<R: Read>(
+        &self,
+        table: &str,
+        reader: &mut R,
+        policy: ImportPolicy,
+    ) -> Result<ImportStats> {
+        let options = ImportOptions {
+            policy,
+            ..ImportOptions::default()
+        };
+        self.import_table_with(table, reader, &options)
+    }
+
+    /// Import the rows exported by
+    /// [`export_table`](Connection::export_table) from `reader` into
+    /// `table`, which may have another name than the table exported.
+    ///
+    /// The rows are inserted in transactions of
+    /// [`chunk_rows`](ImportOptions::chunk_rows) rows, the table being
+    /// created or replaced in the first one. A table created from the stream
+    /// has the exported columns and declared types, but none of the
+    /// constraints of the table exported.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` with an [`Error::TableSchemaMismatch`] if the
+    /// columns of `table` differ from the exported ones, or with an
+    /// [`Error::TableStreamFailed`] if the stream is not a table export, is
+    /// truncated or cannot be read. If a row cannot be read or inserted, the
+    /// current transaction is rolled back, and the chunks before it stay
+    /// committed.
+    pub fn import_table_with<R: Read>(
+        &self,
+        table: &str,
+        reader: &mut R,
+        options: &ImportOptions,
+    ) -> Result<ImportStats> {
+        let mut magic = [0; 7];
+        reader
+            .read_exact(&mut magic)
+            .map_err(Error::TableStreamFailed)?;
+        if &magic != MAGIC {
+            return Err(stream_error("not a table export".to_owned()));
+        }
+        let mut header = [0; 2];
+        reader
+            .read_exact(&mut header)
+            .map_err(Error::TableStreamFailed)?;
+        let [version, flags] = header;
+        if version != VERSION {
+            return Err(stream_error(format!(
+                "unsupported table export version {version}"
+            )));
+        }
+        if flags & FLAG_ZSTD != 0 {
+            #[cfg(feature = "zstd")]
+            {
+                let mut decoder = zstd::Decoder::new(reader).map_err(Error::TableStreamFailed)?;
+                return self.read_rows(table, &mut decoder, options);
+            }
+            #[cfg(not(feature = "zstd"))]
+            return Err(stream_error(
+                "compressed table export, without the zstd feature".to_owned(),
+            ));
+        }
+        self.read_rows(table, reader, options)
+    }
+
+    fn read_rows<R: Read + ?Sized>(
+        &self,
+        table: &str,
+        r: &mut R,
+        options: &ImportOptions,
+    ) -> Result<ImportStats> {
+        let io = Error::TableStreamFailed;
+        let mut count = [0; 4];
+        r.read_exact(&mut count).map_err(io)?;
+        let count = u32::from_le_bytes(count);
+        if count == 0 {
+            return Err(stream_error("table export without columns".to_owned()));
+        }
+        let mut columns = Vec::new();
+        for _ in 0..count {
+            columns.push(Column {
+                name: read_string(r)?,
+                decl_type: read_string(r)?,
+            });
+        }
+
+        let verb = match options.on_conflict {
+            ConflictPolicy::Abort => "INSERT",
+            ConflictPolicy::Ignore => "INSERT OR IGNORE",
+            ConflictPolicy::Replace => "INSERT OR REPLACE",
+        };
+        let names: Vec<String> = columns.iter().map(|c| identifier(&c.name)).collect();
+        let insert = format!(
+            "{verb} INTO {} ({}) VALUES ({})",
+            identifier(table),
+            names.join(", "),
+            vec!["?"; columns.len()].join(", ")
+        );
+
+        let chunk_rows = options.chunk_rows.max(1);
+        let mut stats = ImportStats::default();
+        let mut buf = Vec::new();
+        let mut done = false;
+        while !done {
+            let mut sp = Savepoint::with_depth(self, 0)?;
+            let mut chunk = || -> Result<()> {
+                if stats.chunks == 0 {
+                    stats.created = self.prepare_table(table, &columns, options.policy)?;
+                }
+                let mut stmt = self.prepare_cached(&insert)?;
+                for _ in 0..chunk_rows {
+                    if read_u8(r).map_err(io)? == END {
+                        let exported = read_u64(r).map_err(io)?;
+                        if exported != stats.rows {
+                            return Err(stream_error(format!(
+                                "{} rows read, {exported} exported",
+                                stats.rows
+                            )));
+                        }
+                        done = true;
+                        return Ok(());
+                    }
+                    for i in 1..=columns.len() {
+                        let value = match read_u8(r).map_err(io)? {
+                            NULL => ValueRef::Null,
+                            INTEGER => ValueRef::Integer(read_u64(r).map_err(io)? as i64),
+                            REAL => ValueRef::Real(f64::from_bits(read_u64(r).map_err(io)?)),
+                            TEXT => {
+                                read_len(r, &mut buf).map_err(io)?;
+                                ValueRef::Text(&buf)
+                            }
+                            BLOB => {
+                                read_len(r, &mut buf).map_err(io)?;
+                                ValueRef::Blob(&buf)
+                            }
+                            tag => return Err(stream_error(format!("invalid value tag {tag}"))),
+                        };
+                        stmt.raw_bind_parameter(i, ToSqlOutput::Borrowed(value))?;
+                    }
+                    stats.inserted += stmt.raw_execute()? as u64;
+                    stats.rows += 1;
+                }
+                Ok(())
+            };
+            if let Err(err) = chunk() {
+                // `ROLLBACK TO` leaves the savepoint open, so release it too
+                sp.rollback()?;
+                sp.commit()?;
+                return Err(err);
+            }
+            sp.commit()?;
+            stats.chunks += 1;
+        }
+        Ok(stats)
+    }
+
+    // Creates `table` or checks its columns, as `policy` says, returning
+    // whether it was created.
+    fn prepare_table(&self, table: &str, columns: &[Column], policy: ImportPolicy) -> Result<bool> {
+        let existing = self.table_info(table)?;
+        let create = match policy {
+            ImportPolicy::Replace => {
+                if !existing.is_empty() {
+                    self.execute_batch(&format!("DROP TABLE {}", identifier(table)))?;
+                }
+                true
+            }
+            ImportPolicy::CreateIfMissing => existing.is_empty(),
+            ImportPolicy::AppendOnly => false,
+        };
+        if create {
+            let definitions: Vec<String> = columns
+                .iter()
+                .map(|c| {
+                    if c.decl_type.is_empty() {
+                        identifier(&c.name)
+                    } else {
+                        format!("{} {}", identifier(&c.name), c.decl_type)
+                    }
+                })
+                .collect();
+            self.execute(
+                &format!(
+                    "CREATE TABLE {} ({})",
+                    identifier(table),
+                    definitions.join(", ")
+                ),
+                [],
+            )?;
+        }
+        // also catches declared types that are not just types
+        let found: Vec<Column> = self
+            .table_info(table)?
+            .into_iter()
+            .map(|c| Column {
+                name: c.name,
+                decl_type: c.decl_type,
+            })
+            .collect();
+        if found.len() != columns.len() || !found.iter().zip(columns).all(|(f, c)| f.same_as(c)) {
+            return Err(Error::TableSchemaMismatch {
+                table: table.to_owned(),
+                expected: columns.iter().map(Column::describe).collect(),
+                found: found.iter().map(Column::describe).collect(),
+            });
+        }
+        Ok(create)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::Hasher;
+    use std::io::ErrorKind;
+
+    use super::{ExportOptions, ImportOptions, ImportPolicy};
+    use crate::types::ValueRef;
+    use crate::{Connection, Error, Result};
+
+    // Hashes every value of `table` with its storage class, in rowid order.
+    fn table_hash(db: &Connection, table: &str) -> Result<u64> {
+        let mut stmt = db.prepare(&format!("SELECT * FROM {table} ORDER BY rowid"))?;
+        let n = stmt.column_count();
+        let mut rows = stmt.query([])?;
+        let mut hasher = DefaultHasher::new();
+        while let Some(row) = rows.next()? {
+            for i in 0..n {
+                match row.get_ref(i)? {
+                    ValueRef::Null => hasher.write_u8(0),
+                    ValueRef::Integer(v) => {
+                        hasher.write_u8(1);
+                        hasher.write_i64(v);
+                    }
+                    ValueRef::Real(v) => {
+                        hasher.write_u8(2);
+                        hasher.write_u64(v.to_bits());
+                    }
+                    ValueRef::Text(v) => {
+                        hasher.write_u8(3);
+                        hasher.write(v);
+                        hasher.write_usize(v.len());
+                    }
+                    ValueRef::Blob(v) => {
+                        hasher.write_u8(4);
+                        hasher.write(v);
+                        hasher.write_usize(v.len());
+                    }
+                }
+            }
+        }
+        Ok(hasher.finish())
+    }
+
+    fn sample() -> Result<Connection> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE t(id INTEGER PRIMARY KEY, a, b TEXT, c BLOB);
+             INSERT INTO t(a, b, c) VALUES
+                (NULL, 'plain', x''),
+                (-9223372036854775808, CAST(x'61006200ff' AS TEXT), x'00ff'),
+                (-0.0, '', NULL),
+                (4.9e-324, 'é', zeroblob(3)),
+                (1.7976931348623157e308, NULL, x'0102');",
+        )?;
+        Ok(db)
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        let src = sample()?;
+        let mut out = Vec::new();
+        let stats = src.export_table("t", &mut out, &ExportOptions::default())?;
+        assert_eq!(stats.rows, 5);
+        assert_eq!(stats.bytes, out.len() as u64);
+
+        let dst = Connection::open_in_memory()?;
+        let stats = dst.import_table("copy", &mut &out[..], ImportPolicy::CreateIfMissing)?;
+        assert_eq!(stats.rows, 5);
+        assert_eq!(stats.inserted, 5);
+        assert!(stats.created);
+        assert_eq!(table_hash(&src, "t")?, table_hash(&dst, "copy")?);
+        let text: Vec<u8> = dst.query_row("SELECT b FROM copy WHERE id = 2", [], |r| {
+            Ok(r.get_ref(0)?.as_bytes()?.to_vec())
+        })?;
+        assert_eq!(text, b"a\0b\0\xff");
+        Ok(())
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_round_trip_zstd() -> Result<()> {
+        let src = sample()?;
+        src.execute_batch("INSERT INTO t(b) SELECT t1.b FROM t t1, t t2, t t3, t t4")?;
+        let mut plain = Vec::new();
+        src.export_table("t", &mut plain, &ExportOptions::default())?;
+        let mut out = Vec::new();
+        let options = ExportOptions {
+            zstd_level: Some(0),
+        };
+        src.export_table("t", &mut out, &options)?;
+        assert!(out.len() < plain.len());
+
+        let dst = Connection::open_in_memory()?;
+        dst.import_table("t", &mut &out[..], ImportPolicy::CreateIfMissing)?;
+        assert_eq!(table_hash(&src, "t")?, table_hash(&dst, "t")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_only_mismatch() -> Result<()> {
+        let src = sample()?;
+        let mut out = Vec::new();
+        src.export_table("t", &mut out, &ExportOptions::default())?;
+
+        let dst = Connection::open_in_memory()?;
+        let err = dst
+            .import_table("t", &mut &out[..], ImportPolicy::AppendOnly)
+            .unwrap_err();
+        assert!(matches!(err, Error::TableSchemaMismatch { ref found, .. } if found.is_empty()));
+
+        dst.execute_batch("CREATE TABLE t(id INTEGER PRIMARY KEY, a, b BLOB, c BLOB)")?;
+        let err = dst
+            .import_table("t", &mut &out[..], ImportPolicy::AppendOnly)
+            .unwrap_err();
+        match err {
+            Error::TableSchemaMismatch {
+                table,
+                expected,
+                found,
+            } => {
+                assert_eq!(table, "t");
+                assert_eq!(expected[2], "b TEXT");
+                assert_eq!(found[2], "b BLOB");
+            }
+            err => panic!("unexpected error {}", err),
+        }
+
+        dst.execute_batch(
+            "DROP TABLE t; CREATE TABLE t(id integer PRIMARY KEY, a, b text, c BLOB)",
+        )?;
+        let stats = dst.import_table("t", &mut &out[..], ImportPolicy::AppendOnly)?;
+        assert!(!stats.created);
+        assert_eq!(table_hash(&src, "t")?, table_hash(&dst, "t")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace() -> Result<()> {
+        let src = sample()?;
+        let mut out = Vec::new();
+        src.export_table("t", &mut out, &ExportOptions::default())?;
+
+        let dst = Connection::open_in_memory()?;
+        dst.execute_batch("CREATE TABLE t(x); INSERT INTO t VALUES (1), (2)")?;
+        let stats = dst.import_table("t", &mut &out[..], ImportPolicy::Replace)?;
+        assert!(stats.created);
+        assert_eq!(table_hash(&src, "t")?, table_hash(&dst, "t")?);
+        let columns: Vec<String> = dst.table_info("t")?.into_iter().map(|c| c.name).collect();
+        assert_eq!(columns, ["id", "a", "b", "c"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncated_stream() -> Result<()> {
+        let src = Connection::open_in_memory()?;
+        src.execute_batch(
+            "CREATE TABLE t(x INTEGER);
+             WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 25)
+             INSERT INTO t SELECT i FROM n;",
+        )?;
+        let mut out = Vec::new();
+        src.export_table("t", &mut out, &ExportOptions::default())?;
+        // each row takes 10 bytes: cut the stream in the middle of row 24
+        out.truncate(out.len() - 9 - 10 - 5);
+
+        let dst = Connection::open_in_memory()?;
+        let options = ImportOptions {
+            chunk_rows: 10,
+            ..ImportOptions::default()
+        };
+        let err = dst
+            .import_table_with("t", &mut &out[..], &options)
+            .unwrap_err();
+        match err {
+            Error::TableStreamFailed(err) => assert_eq!(err.kind(), ErrorKind::UnexpectedEof),
+            err => panic!("unexpected error {}", err),
+        }
+        // the first two chunks stay committed
+        let n: i64 = dst.query_row("SELECT count(*) FROM t", [], |r| r.get(0))?;
+        assert_eq!(n, 20);
+        assert!(dst.is_autocommit());
+        Ok(())
+    }
+
+    #[test]
+    fn test_not_an_export() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        let err = db
+            .import_table("t", &mut &b"SQLite format 3\0"[..], ImportPolicy::Replace)
+            .unwrap_err();
+        assert!(
+            matches!(err, Error::TableStreamFailed(ref e) if e.kind() == ErrorKind::InvalidData)
+        );
+        assert!(db.table_info("t")?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,445 @@
+//! Utilities for testing code built on rusqlite.
+//!
+//! [`stress`] runs a concurrent read/write workload against a temporary
+//! database file, checking that every reader sees a consistent snapshot and
+//! that the file passes `PRAGMA integrity_check` afterwards.
+//!
+//! ```rust,no_run
+//! # use rusqlite::Result;
+//! use rusqlite::testing::{stress, StressConfig};
+//! use std::time::Duration;
+//!
+//! fn main() -> Result<()> {
+//!     let report = stress(StressConfig {
+//!         writers: 4,
+//!         readers: 8,
+//!         duration: Duration::from_secs(10),
+//!         ..StressConfig::default()
+//!     })?;
+//!     assert!(report.is_clean(), "{:?}", report);
+//!     Ok(())
+//! }
+//! ```
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::ffi;
+use crate::{Connection, Error, ErrorCode, Result, TransactionBehavior};
+
+/// What [`stress`] does when an operation fails with an error other than
+/// `SQLITE_BUSY` or `SQLITE_LOCKED`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OnError {
+    /// Stop every thread and return the error.
+    Stop,
+    /// Count the error in [`StressReport::errors`] and carry on.
+    Continue,
+}
+
+/// Configuration for [`stress`].
+#[derive(Clone, Debug)]
+pub struct StressConfig {
+    /// Number of writer threads, each inserting and updating its own rows.
+    pub writers: usize,
+    /// Number of reader threads, each checking that the row counts agree
+    /// within a read transaction.
+    pub readers: usize,
+    /// How long to run for.
+    pub duration: Duration,
+    /// Maximum number of operations per thread, or `None` to run for the
+    /// whole `duration`.
+    pub ops: Option<u64>,
+    /// Journal mode of the database, e.g. `"wal"` or `"delete"`.
+    pub journal_mode: String,
+    /// What to do on unexpected errors.
+    pub on_error: OnError,
+}
+
+impl Default for StressConfig {
+    fn default() -> StressConfig {
+        StressConfig {
+            writers: 2,
+            readers: 2,
+            duration: Duration::from_secs(2),
+            ops: None,
+            journal_mode: "wal".to_owned(),
+            on_error: OnError::Stop,
+        }
+    }
+}
+
+/// Outcome of a [`stress`] run.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct StressReport {
+    /// Number of committed inserts.
+    pub inserts: u64,
+    /// Number of committed updates.
+    pub updates: u64,
+    /// Number of completed read transactions.
+    pub reads: u64,
+    /// Number of times an operation was retried after `SQLITE_BUSY` or
+    /// `SQLITE_LOCKED`.
+    pub busy_retries: u64,
+    /// Number of unexpected errors, counted with [`OnError::Continue`].
+    pub errors: u64,
+    /// Number of times a reader saw inconsistent counts, or a writer's
+    /// rows did not match what it committed.
+    pub invariant_violations: u64,
+    /// Longest write transaction, retries included.
+    pub max_write_latency: Duration,
+    /// Longest read transaction, retries included.
+    pub max_read_latency: Duration,
+    /// Whether `PRAGMA integrity_check` reported `ok` at the end.
+    pub integrity_ok: bool,
+}
+
+impl StressReport {
+    /// Returns `true` if the run had no errors, no invariant violations and
+    /// a clean integrity check.
+    #[inline]
+    #[must_use]
+    pub fn is_clean(&self) -> bool {
+        self.errors == 0 && self.invariant_violations == 0 && self.integrity_ok
+    }
+
+    fn merge(&mut self, other: &StressReport) {
+        self.inserts += other.inserts;
+        self.updates += other.updates;
+        self.reads += other.reads;
+        self.busy_retries += other.busy_retries;
+        self.errors += other.errors;
+        self.invariant_violations += other.invariant_violations;
+        self.max_write_latency = self.max_write_latency.max(other.max_write_latency);
+        self.max_read_latency = self.max_read_latency.max(other.max_read_latency);
+    }
+}
+
+/// Run a concurrent stress test against a new temporary database file.
+///
+/// Each of `writers` threads opens its own connection and repeatedly, in an
+/// immediate transaction, either inserts a row or increments a counter on one
+/// of its rows, keeping per-writer totals in a separate table. Each of
+/// `readers` threads checks, inside a read transaction, that the rows agree
+/// with the totals. Operations failing with `SQLITE_BUSY` or
+/// `SQLITE_LOCKED` are rolled back and retried. Once every thread has
+/// finished, each writer's rows are compared against what it committed and
+/// `PRAGMA integrity_check` is run.
+///
+/// The database file is removed afterwards.
+///
+/// # Failure
+///
+/// Will return `Err` if the database cannot be set up, or, with
+/// [`OnError::Stop`], on the first unexpected error.
+pub fn stress(config: StressConfig) -> Result<StressReport> {
+    let db = TempDb::new();
+    let conn = Connection::open(db.path())?;
+    let mode: String =
+        conn.pragma_update_and_check(None, "journal_mode", &config.journal_mode, |row| row.get(0))?;
+    if !mode.eq_ignore_ascii_case(&config.journal_mode) {
+        return Err(Error::SqliteFailure(
+            ffi::Error::new(ffi::SQLITE_MISUSE),
+            Some(format!(
+                "journal_mode {} is not available (got {})",
+                config.journal_mode, mode
+            )),
+        ));
+    }
+    conn.execute_batch(
+        "CREATE TABLE stress(id INTEGER PRIMARY KEY, writer INTEGER NOT NULL, counter INTEGER NOT NULL, payload BLOB);
+         CREATE INDEX stress_writer ON stress(writer);
+         CREATE TABLE stress_totals(writer INTEGER PRIMARY KEY, inserted INTEGER NOT NULL, updated INTEGER NOT NULL);",
+    )?;
+    for writer in 0..config.writers {
+        conn.execute(
+            "INSERT INTO stress_totals VALUES (?, 0, 0)",
+            [writer as i64],
+        )?;
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let deadline = Instant::now() + config.duration;
+    let mut handles = Vec::with_capacity(config.writers + config.readers);
+    for writer in 0..config.writers {
+        let worker = Worker::new(&config, db.path(), &stop, deadline, writer);
+        handles.push(thread::spawn(move || worker.run(Worker::write)));
+    }
+    for reader in 0..config.readers {
+        let worker = Worker::new(&config, db.path(), &stop, deadline, config.writers + reader);
+        handles.push(thread::spawn(move || worker.run(Worker::read)));
+    }
+
+    let mut report = StressReport::default();
+    let mut first_error = None;
+    for handle in handles {
+        match handle.join().expect("stress thread panicked") {
+            Ok(stats) => report.merge(&stats),
+            Err(e) => first_error = first_error.or(Some(e)),
+        }
+    }
+    if let Some(e) = first_error {
+        return Err(e);
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT t.writer, t.inserted, t.updated, count(s.id), coalesce(sum(s.counter), 0)
+         FROM stress_totals t LEFT JOIN stress s ON s.writer = t.writer
+         GROUP BY t.writer",
+    )?;
+    let mut rows = stmt.query([])?;
+    let mut committed = (0, 0);
+    while let Some(row) = rows.next()? {
+        let (inserted, updated): (u64, u64) = (row.get(1)?, row.get(2)?);
+        if (inserted, updated) != (row.get(3)?, row.get(4)?) {
+            report.invariant_violations += 1;
+        }
+        committed.0 += inserted;
+        committed.1 += updated;
+    }
+    if committed != (report.inserts, report.updates) {
+        report.invariant_violations += 1;
+    }
+    let integrity: String = conn.query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+    report.integrity_ok = integrity == "ok";
+    Ok(report)
+}
+
+struct Worker {
+    path: PathBuf,
+    stop: Arc<AtomicBool>,
+    deadline: Instant,
+    ops: Option<u64>,
+    on_error: OnError,
+    id: usize,
+    rng: u64,
+    stats: StressReport,
+}
+
+impl Worker {
+    fn new(
+        config: &StressConfig,
+        path: &Path,
+        stop: &Arc<AtomicBool>,
+        deadline: Instant,
+        id: usize,
+    ) -> Worker {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_nanos() as u64);
+        Worker {
+            path: path.to_owned(),
+            stop: Arc::clone(stop),
+            deadline,
+            ops: config.ops,
+            on_error: config.on_error,
+            id,
+            // xorshift must not start from zero
+            rng: (seed ^ (id as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)) | 1,
+            stats: StressReport::default(),
+        }
+    }
+
+    fn run(mut self, op: fn(&mut Worker, &mut Connection) -> Result<()>) -> Result<StressReport> {
+        let mut conn = Connection::open(&self.path)?;
+        conn.busy_timeout(Duration::from_millis(50))?;
+        let mut done = 0;
+        while !self.stop.load(Ordering::Relaxed)
+            && Instant::now() < self.deadline
+            && !matches!(self.ops, Some(ops) if done >= ops)
+        {
+            if let Err(e) = op(&mut self, &mut conn) {
+                match self.on_error {
+                    OnError::Stop => {
+                        self.stop.store(true, Ordering::Relaxed);
+                        return Err(e);
+                    }
+                    OnError::Continue => self.stats.errors += 1,
+                }
+            }
+            done += 1;
+        }
+        Ok(self.stats)
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+
+    // Runs `f` until it fails with something other than `SQLITE_BUSY` or
+    // `SQLITE_LOCKED`, returning how long that took.
+    fn retry<T>(&mut self, mut f: impl FnMut() -> Result<T>) -> Result<(T, Duration)> {
+        let start = Instant::now();
+        loop {
+            match f() {
+                Err(ref e)
+                    if matches!(
+                        e.sqlite_error_code(),
+                        Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked)
+                    ) =>
+                {
+                    self.stats.busy_retries += 1;
+                    thread::yield_now();
+                }
+                r => return r.map(|t| (t, start.elapsed())),
+            }
+        }
+    }
+
+    fn write(&mut self, conn: &mut Connection) -> Result<()> {
+        let writer = self.id as i64;
+        // two inserts for every update
+        let insert = self.next_random() % 3 < 2;
+        let pick = self.next_random() as i64;
+        let len = (self.next_random() % 256) as usize;
+        let ((), latency) = self.retry(|| {
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let updated = if insert {
+                tx.execute(
+                    "INSERT INTO stress (writer, counter, payload) VALUES (?, 0, zeroblob(?))",
+                    [writer, len as i64],
+                )?;
+                tx.execute(
+                    "UPDATE stress_totals SET inserted = inserted + 1 WHERE writer = ?",
+                    [writer],
+                )?
+            } else {
+                let n = tx.execute(
+                    "UPDATE stress SET counter = counter + 1 WHERE id = (
+                         SELECT id FROM stress WHERE writer = ?1
+                         LIMIT 1 OFFSET (abs(?2) % max(1, (SELECT count(*) FROM stress WHERE writer = ?1))))",
+                    [writer, pick],
+                )?;
+                tx.execute(
+                    "UPDATE stress_totals SET updated = updated + ?2 WHERE writer = ?1",
+                    [writer, n as i64],
+                )?;
+                n
+            };
+            tx.commit()?;
+            Ok(updated)
+        })
+        .map(|(updated, latency)| {
+            if insert {
+                self.stats.inserts += 1;
+            } else {
+                self.stats.updates += updated as u64;
+            }
+            ((), latency)
+        })?;
+        self.stats.max_write_latency = self.stats.max_write_latency.max(latency);
+        Ok(())
+    }
+
+    fn read(&mut self, conn: &mut Connection) -> Result<()> {
+        let (consistent, latency) = self.retry(|| {
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Deferred)?;
+            let (rows, counters): (i64, i64) = tx.query_row(
+                "SELECT count(*), coalesce(sum(counter), 0) FROM stress",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+            let (inserted, updated): (i64, i64) = tx.query_row(
+                "SELECT coalesce(sum(inserted), 0), coalesce(sum(updated), 0) FROM stress_totals",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+            tx.commit()?;
+            Ok(rows == inserted && counters == updated)
+        })?;
+        self.stats.reads += 1;
+        if !consistent {
+            self.stats.invariant_violations += 1;
+        }
+        self.stats.max_read_latency = self.stats.max_read_latency.max(latency);
+        Ok(())
+    }
+}
+
+// A database path in the temporary directory, removed (along with its
+// sidecar files) on drop.
+struct TempDb(PathBuf);
+
+impl TempDb {
+    fn new() -> TempDb {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_nanos());
+        TempDb(std::env::temp_dir().join(format!(
+            "rusqlite-stress-{}-{}-{}.db3",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+            nanos
+        )))
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        for suffix in &["", "-wal", "-shm", "-journal"] {
+            let mut name = self.0.clone().into_os_string();
+            name.push(suffix);
+            let _ = std::fs::remove_file(name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{stress, StressConfig};
+    use crate::Result;
+    use std::time::Duration;
+
+    fn check(journal_mode: &str) -> Result<()> {
+        let report = stress(StressConfig {
+            writers: 3,
+            readers: 3,
+            duration: Duration::from_secs(2),
+            journal_mode: journal_mode.to_owned(),
+            ..StressConfig::default()
+        })?;
+        assert!(report.is_clean(), "{:?}", report);
+        assert!(report.inserts > 0, "{:?}", report);
+        assert!(report.reads > 0, "{:?}", report);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stress_wal() -> Result<()> {
+        check("wal")
+    }
+
+    #[test]
+    fn test_stress_delete() -> Result<()> {
+        check("delete")
+    }
+
+    #[test]
+    fn test_stress_ops_limit() -> Result<()> {
+        let report = stress(StressConfig {
+            writers: 1,
+            readers: 1,
+            duration: Duration::from_secs(60),
+            ops: Some(20),
+            ..StressConfig::default()
+        })?;
+        assert!(report.is_clean(), "{:?}", report);
+        assert_eq!(report.reads, 20);
+        // an update made while the writer has no rows yet changes nothing
+        assert!(report.inserts + report.updates <= 20, "{:?}", report);
+        Ok(())
+    }
+}
@@ -67,7 +67,8 @@ impl Connection {
     /// used for tracing the execution of SQL statements.
     ///
     /// Prepared statement placeholders are replaced/logged with their assigned
-    /// values. There can only be a single tracer defined for each database
+    /// values, as by [`Statement::expanded_sql`](crate::Statement::expanded_sql).
+    /// There can only be a single tracer defined for each database
     /// connection. Setting a new tracer clears the old one.
     pub fn trace(&mut self, trace_fn: Option<fn(&str)>) {
         unsafe extern "C" fn trace_callback(p_arg: *mut c_void, z_sql: *const c_char) {
@@ -146,6 +147,7 @@ mod test {
         {
             let _ = db.query_row("SELECT ?1", [1i32], |_| Ok(()));
             let _ = db.query_row("SELECT ?1", ["hello"], |_| Ok(()));
+            let _ = db.query_row("SELECT ?1", ["it's"], |_| Ok(()));
         }
         db.trace(None);
         {
@@ -154,9 +156,10 @@ mod test {
         }
 
         let traced_stmts = TRACED_STMTS.lock().unwrap();
-        assert_eq!(traced_stmts.len(), 2);
+        assert_eq!(traced_stmts.len(), 3);
         assert_eq!(traced_stmts[0], "SELECT 1");
         assert_eq!(traced_stmts[1], "SELECT 'hello'");
+        assert_eq!(traced_stmts[2], "SELECT 'it''s'");
         Ok(())
     }
 
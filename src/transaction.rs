@@ -1,9 +1,14 @@
-use crate::{Connection, Result};
+use crate::{Connection, Error, ErrorCode, Result};
 use std::ops::Deref;
+use std::thread;
+use std::time::Duration;
+
+// Savepoint standing in for a transaction nested by `with_transaction`.
+const NESTED_SAVEPOINT: &str = "_rusqlite_nested_tx";
 
 /// Options for transaction behavior. See [BEGIN
 /// TRANSACTION](http://www.sqlite.org/lang_transaction.html) for details.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 #[non_exhaustive]
 pub enum TransactionBehavior {
     /// DEFERRED means that the transaction does not actually start until the
@@ -35,6 +40,72 @@ pub enum DropBehavior {
     Panic,
 }
 
+/// How [`Connection::transaction_with_retry`] retries beginning (and
+/// optionally committing) a transaction while the database is busy.
+///
+/// The delay before the second attempt is `initial_delay`, and each further
+/// delay is `backoff` times the previous one, up to `max_delay`.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// The most attempts, including the first one.
+    pub max_attempts: u32,
+    /// The delay before the second attempt.
+    pub initial_delay: Duration,
+    /// The factor by which the delay grows after each attempt.
+    pub backoff: f64,
+    /// The longest delay between two attempts.
+    pub max_delay: Duration,
+    /// Called with the number of failed attempts so far and the delay before
+    /// the next one, returning the delay to use instead, e.g. to add random
+    /// jitter so that writers do not retry in lockstep.
+    pub jitter: Option<fn(u32, Duration) -> Duration>,
+    /// Whether `COMMIT` is retried as well, with the same policy.
+    pub retry_commit: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 10,
+            initial_delay: Duration::from_millis(10),
+            backoff: 2.0,
+            max_delay: Duration::from_secs(1),
+            jitter: None,
+            retry_commit: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    // Runs `f` until it does not fail with `SQLITE_BUSY` or `SQLITE_LOCKED`,
+    // or the attempts are exhausted.
+    fn run(&self, mut f: impl FnMut() -> Result<()>) -> Result<()> {
+        let mut delay = self.initial_delay;
+        let mut attempt = 1;
+        loop {
+            match f() {
+                Err(e) if attempt < self.max_attempts && is_busy(&e) => {
+                    let wait = match self.jitter {
+                        Some(jitter) => jitter(attempt, delay),
+                        None => delay,
+                    };
+                    thread::sleep(wait);
+                    delay = delay.mul_f64(self.backoff).min(self.max_delay);
+                    attempt += 1;
+                }
+                r => return r,
+            }
+        }
+    }
+}
+
+fn is_busy(err: &Error) -> bool {
+    matches!(
+        err.sqlite_error_code(),
+        Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked)
+    )
+}
+
 /// Represents a transaction on a database connection.
 ///
 /// ## Note
@@ -62,6 +133,106 @@ pub enum DropBehavior {
 pub struct Transaction<'conn> {
     conn: &'conn Connection,
     drop_behavior: DropBehavior,
+    // Set when started inside another transaction by `with_transaction`, in
+    // which case it is a savepoint, and `released` once that is gone.
+    nested: bool,
+    released: bool,
+    // Set by `transaction_with_retry` when `COMMIT` is to be retried.
+    commit_retry: Option<RetryPolicy>,
+    // The busy timeout to restore on drop, in milliseconds, when
+    // `TransactionBuilder::busy_timeout` overrides it.
+    restore_busy_timeout: Option<i32>,
+}
+
+/// Configures a transaction before it begins, as returned by
+/// [`Connection::transaction_builder`].
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// # use rusqlite::{Connection, DropBehavior, Result, TransactionBehavior};
+/// # use std::time::Duration;
+/// fn interactive(conn: &mut Connection) -> Result<()> {
+///     let tx = conn
+///         .transaction_builder()
+///         .behavior(TransactionBehavior::Immediate)
+///         .busy_timeout(Duration::from_millis(100))
+///         .drop_behavior(DropBehavior::Rollback)
+///         .begin()?;
+///     tx.execute("UPDATE counter SET n = n + 1", [])?;
+///     tx.commit()
+/// }
+/// ```
+#[derive(Debug)]
+#[must_use = "a transaction builder does nothing until `begin` is called"]
+pub struct TransactionBuilder<'conn> {
+    conn: &'conn Connection,
+    behavior: TransactionBehavior,
+    busy_timeout: Option<Duration>,
+    drop_behavior: DropBehavior,
+}
+
+impl<'conn> TransactionBuilder<'conn> {
+    /// Sets how the transaction begins, `DEFERRED` by default.
+    #[inline]
+    pub fn behavior(mut self, behavior: TransactionBehavior) -> Self {
+        self.behavior = behavior;
+        self
+    }
+
+    /// Sets the [busy timeout](Connection::busy_timeout) of the connection
+    /// from `BEGIN` until the transaction is committed, rolled back or
+    /// dropped, when the previous timeout is restored. Savepoints in the
+    /// transaction use it as well.
+    ///
+    /// The previous timeout is read with `PRAGMA busy_timeout`, so a handler
+    /// set with [`busy_handler`](Connection::busy_handler) is not restored.
+    #[inline]
+    pub fn busy_timeout(mut self, timeout: Duration) -> Self {
+        self.busy_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets what happens to the transaction when it is dropped, see
+    /// [`Transaction::set_drop_behavior`].
+    #[inline]
+    pub fn drop_behavior(mut self, drop_behavior: DropBehavior) -> Self {
+        self.drop_behavior = drop_behavior;
+        self
+    }
+
+    /// Begins the transaction.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the busy timeout cannot be changed or the
+    /// transaction cannot begin, in which case the previous busy timeout is
+    /// restored.
+    pub fn begin(self) -> Result<Transaction<'conn>> {
+        let conn = self.conn;
+        let restore_busy_timeout = match self.busy_timeout {
+            Some(timeout) => {
+                let previous: i32 =
+                    conn.pragma_query_value(None, "busy_timeout", |row| row.get(0))?;
+                conn.busy_timeout(timeout)?;
+                Some(previous)
+            }
+            None => None,
+        };
+        match Transaction::new_unchecked(conn, self.behavior) {
+            Ok(mut tx) => {
+                tx.drop_behavior = self.drop_behavior;
+                tx.restore_busy_timeout = restore_busy_timeout;
+                Ok(tx)
+            }
+            Err(err) => {
+                if let Some(ms) = restore_busy_timeout {
+                    conn.db.borrow_mut().busy_timeout(ms)?;
+                }
+                Err(err)
+            }
+        }
+    }
 }
 
 /// Represents a savepoint on a database connection.
@@ -123,10 +294,63 @@ impl Transaction<'_> {
             TransactionBehavior::Immediate => "BEGIN IMMEDIATE",
             TransactionBehavior::Exclusive => "BEGIN EXCLUSIVE",
         };
-        conn.execute_batch(query).map(move |_| Transaction {
-            conn,
-            drop_behavior: DropBehavior::Rollback,
-        })
+        conn.execute_transaction_sql(query)
+            .map(move |_| Transaction {
+                conn,
+                drop_behavior: DropBehavior::Rollback,
+                nested: false,
+                released: false,
+                commit_retry: None,
+                restore_busy_timeout: None,
+            })
+    }
+
+    fn new_with_retry<'a>(
+        conn: &'a Connection,
+        behavior: TransactionBehavior,
+        policy: &RetryPolicy,
+    ) -> Result<Transaction<'a>> {
+        let mut tx = None;
+        policy.run(|| {
+            tx = Some(Transaction::new_unchecked(conn, behavior)?);
+            Ok(())
+        })?;
+        let mut tx = tx.unwrap();
+        if policy.retry_commit {
+            tx.commit_retry = Some(policy.clone());
+        }
+        Ok(tx)
+    }
+
+    fn new_nested(conn: &Connection) -> Result<Transaction<'_>> {
+        conn.execute_transaction_sql(&format!("SAVEPOINT {NESTED_SAVEPOINT}"))
+            .map(move |_| Transaction {
+                conn,
+                drop_behavior: DropBehavior::Rollback,
+                nested: true,
+                released: false,
+                commit_retry: None,
+                restore_busy_timeout: None,
+            })
+    }
+
+    /// Runs `f` in a nested transaction, which is committed if `f` returns
+    /// `Ok` and rolled back otherwise. See [`Connection::with_transaction`].
+    ///
+    /// The nested transaction is a savepoint, so `behavior` is ignored: locks
+    /// are those of this transaction.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `f` does, or if the savepoint cannot be started or
+    /// released.
+    pub fn with_transaction<T, E, F>(&self, behavior: TransactionBehavior, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&Transaction<'_>) -> Result<T, E>,
+        E: From<Error>,
+    {
+        let _ = behavior;
+        run_transaction(Transaction::new_nested(self.conn)?, f)
     }
 
     /// Starts a new [savepoint](http://www.sqlite.org/lang_savepoint.html), allowing nested
@@ -190,7 +414,16 @@ impl Transaction<'_> {
 
     #[inline]
     fn commit_(&mut self) -> Result<()> {
-        self.conn.execute_batch("COMMIT")?;
+        if self.nested {
+            self.conn
+                .execute_transaction_sql(&format!("RELEASE {NESTED_SAVEPOINT}"))?;
+            self.released = true;
+            return Ok(());
+        }
+        match self.commit_retry {
+            Some(ref policy) => policy.run(|| self.conn.execute_transaction_sql("COMMIT"))?,
+            None => self.conn.execute_transaction_sql("COMMIT")?,
+        }
         Ok(())
     }
 
@@ -202,7 +435,14 @@ impl Transaction<'_> {
 
     #[inline]
     fn rollback_(&mut self) -> Result<()> {
-        self.conn.execute_batch("ROLLBACK")?;
+        if self.nested {
+            self.conn.execute_transaction_sql(&format!(
+                "ROLLBACK TO {NESTED_SAVEPOINT}; RELEASE {NESTED_SAVEPOINT}"
+            ))?;
+            self.released = true;
+            return Ok(());
+        }
+        self.conn.execute_transaction_sql("ROLLBACK")?;
         Ok(())
     }
 
@@ -218,7 +458,7 @@ impl Transaction<'_> {
 
     #[inline]
     fn finish_(&mut self) -> Result<()> {
-        if self.conn.is_autocommit() {
+        if self.released || self.conn.is_autocommit() {
             return Ok(());
         }
         match self.drop_behavior() {
@@ -239,11 +479,26 @@ impl Deref for Transaction<'_> {
     }
 }
 
+// Commits `tx` if `f` succeeds, and lets it roll back on drop otherwise,
+// including when the commit fails.
+fn run_transaction<T, E, F>(mut tx: Transaction<'_>, f: F) -> Result<T, E>
+where
+    F: FnOnce(&Transaction<'_>) -> Result<T, E>,
+    E: From<Error>,
+{
+    let value = f(&tx)?;
+    tx.commit_()?;
+    Ok(value)
+}
+
 #[allow(unused_must_use)]
 impl Drop for Transaction<'_> {
     #[inline]
     fn drop(&mut self) {
         self.finish_();
+        if let Some(ms) = self.restore_busy_timeout {
+            self.conn.db.borrow_mut().busy_timeout(ms);
+        }
     }
 }
 
@@ -255,7 +510,7 @@ impl Savepoint<'_> {
         name: T,
     ) -> Result<Savepoint<'_>> {
         let name = name.into();
-        conn.execute_batch(&format!("SAVEPOINT {name}"))
+        conn.execute_transaction_sql(&format!("SAVEPOINT {name}"))
             .map(|_| Savepoint {
                 conn,
                 name,
@@ -266,7 +521,7 @@ impl Savepoint<'_> {
     }
 
     #[inline]
-    fn with_depth(conn: &Connection, depth: u32) -> Result<Savepoint<'_>> {
+    pub(crate) fn with_depth(conn: &Connection, depth: u32) -> Result<Savepoint<'_>> {
         let name = format!("_rusqlite_sp_{depth}");
         Savepoint::with_depth_and_name(conn, depth, name)
     }
@@ -295,6 +550,23 @@ impl Savepoint<'_> {
         Savepoint::with_depth_and_name(self.conn, self.depth + 1, name)
     }
 
+    /// Returns the name of the savepoint, as used in `SAVEPOINT`, `RELEASE`
+    /// and `ROLLBACK TO` statements.
+    #[inline]
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns how deeply the savepoint is nested: 0 for one begun on a
+    /// connection, 1 for one begun in a transaction or in a savepoint of
+    /// depth 0, and so on.
+    #[inline]
+    #[must_use]
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
     /// Get the current setting for what happens to the savepoint when it is
     /// dropped.
     #[inline]
@@ -318,7 +590,9 @@ impl Savepoint<'_> {
 
     #[inline]
     fn commit_(&mut self) -> Result<()> {
-        self.conn.execute_batch(&format!("RELEASE {}", self.name))?;
+        self.conn
+            .execute_transaction_sql(&format!("RELEASE {}", self.name))
+            .map_err(|err| self.no_such_savepoint(err))?;
         self.committed = true;
         Ok(())
     }
@@ -332,7 +606,27 @@ impl Savepoint<'_> {
     #[inline]
     pub fn rollback(&mut self) -> Result<()> {
         self.conn
-            .execute_batch(&format!("ROLLBACK TO {}", self.name))
+            .execute_transaction_sql(&format!("ROLLBACK TO {}", self.name))
+            .map_err(|err| self.no_such_savepoint(err))
+    }
+
+    // Explains SQLite's "no such savepoint" error, which is returned when the
+    // savepoint was released by SQL run on the connection.
+    fn no_such_savepoint(&self, err: Error) -> Error {
+        match err {
+            Error::SqliteFailure(err, Some(ref msg)) if msg.starts_with("no such savepoint") => {
+                Error::SqliteFailure(
+                    err,
+                    Some(format!(
+                        "savepoint {} no longer exists: it was released, or rolled back as \
+                         part of an enclosing savepoint or transaction, by SQL run on the \
+                         connection",
+                        self.name
+                    )),
+                )
+            }
+            err => err,
+        }
     }
 
     /// Consumes the savepoint, committing or rolling back according to the
@@ -391,6 +685,14 @@ pub enum TransactionState {
 }
 
 impl Connection {
+    // Runs a transaction statement of the crate's own, which the guard of
+    // `forbid_raw_transaction_sql` lets through.
+    pub(crate) fn execute_transaction_sql(&self, sql: &str) -> Result<()> {
+        #[cfg(feature = "hooks")]
+        let _expected = crate::hooks::ExpectTransactionSql::new(self);
+        self.execute_batch(sql)
+    }
+
     /// Begin a new transaction with the default behavior (DEFERRED).
     ///
     /// The transaction defaults to rolling back when it is dropped. If you
@@ -437,6 +739,95 @@ impl Connection {
         Transaction::new(self, behavior)
     }
 
+    /// Runs `f` in a new transaction with the specified behavior, which is
+    /// committed if `f` returns `Ok` and rolled back if it returns `Err` or
+    /// panics.
+    ///
+    /// Inside a transaction, e.g. one begun with `BEGIN` or
+    /// [`unchecked_transaction`](Connection::unchecked_transaction), a
+    /// savepoint is used instead, as with
+    /// [`Transaction::with_transaction`] for calls nested in `f`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result, TransactionBehavior};
+    /// fn transfer(conn: &mut Connection, amount: i64) -> Result<()> {
+    ///     conn.with_transaction(TransactionBehavior::Immediate, |tx| {
+    ///         tx.execute("UPDATE account SET balance = balance - ?1 WHERE id = 1", [amount])?;
+    ///         tx.execute("UPDATE account SET balance = balance + ?1 WHERE id = 2", [amount])?;
+    ///         Ok(())
+    ///     })
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `f` does, or if the transaction cannot be begun or
+    /// committed, in which case it is rolled back.
+    pub fn with_transaction<T, E, F>(&mut self, behavior: TransactionBehavior, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&Transaction<'_>) -> Result<T, E>,
+        E: From<Error>,
+    {
+        let tx = if self.is_autocommit() {
+            Transaction::new(self, behavior)?
+        } else {
+            Transaction::new_nested(self)?
+        };
+        run_transaction(tx, f)
+    }
+
+    /// Begin a new transaction with the specified behavior, retrying with
+    /// `policy` while `BEGIN` fails with `SQLITE_BUSY` or `SQLITE_LOCKED`.
+    ///
+    /// Only `BEGIN`, and `COMMIT` if [`RetryPolicy::retry_commit`] is set, are
+    /// retried; statements run in the transaction are not. Each attempt still
+    /// waits for the connection's [busy
+    /// timeout](Connection::busy_timeout) first, so set it to zero for the
+    /// policy alone to decide how long to wait.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result, RetryPolicy, TransactionBehavior};
+    /// # use std::time::Duration;
+    /// fn increment(conn: &mut Connection) -> Result<()> {
+    ///     conn.busy_timeout(Duration::ZERO)?;
+    ///     let policy = RetryPolicy {
+    ///         retry_commit: true,
+    ///         ..RetryPolicy::default()
+    ///     };
+    ///     let tx = conn.transaction_with_retry(TransactionBehavior::Immediate, &policy)?;
+    ///     tx.execute("UPDATE counter SET n = n + 1", [])?;
+    ///     tx.commit()
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the last attempt fails, or with the first error
+    /// other than `SQLITE_BUSY` or `SQLITE_LOCKED`.
+    pub fn transaction_with_retry(
+        &mut self,
+        behavior: TransactionBehavior,
+        policy: &RetryPolicy,
+    ) -> Result<Transaction<'_>> {
+        Transaction::new_with_retry(self, behavior, policy)
+    }
+
+    /// Returns a [`TransactionBuilder`] to configure a transaction, e.g. with
+    /// its own busy timeout, before it begins.
+    #[inline]
+    pub fn transaction_builder(&mut self) -> TransactionBuilder<'_> {
+        TransactionBuilder {
+            conn: self,
+            behavior: TransactionBehavior::Deferred,
+            busy_timeout: None,
+            drop_behavior: DropBehavior::Rollback,
+        }
+    }
+
     /// Begin a new transaction with the default behavior (DEFERRED).
     ///
     /// Attempt to open a nested transaction will result in a SQLite error.
@@ -516,6 +907,18 @@ impl Connection {
     }
 
     /// Determine the transaction state of a database
+    ///
+    /// With `Some(name)`, returns the state of the main, temporary or
+    /// attached database `name`. With `None`, returns the highest state of
+    /// all of them, e.g. [`TransactionState::Write`] if any database is being
+    /// written.
+    ///
+    /// Useful to check that a pooled connection is not left in a transaction,
+    /// or that no read transaction would stop a checkpoint.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if no database is called `name`.
     #[cfg(feature = "modern_sqlite")] // 3.37.0
     #[cfg_attr(docsrs, doc(cfg(feature = "modern_sqlite")))]
     pub fn transaction_state(
@@ -528,8 +931,8 @@ impl Connection {
 
 #[cfg(test)]
 mod test {
-    use super::DropBehavior;
-    use crate::{Connection, Error, Result};
+    use super::{DropBehavior, RetryPolicy};
+    use crate::{Connection, Error, Result, TransactionBehavior};
 
     fn checked_memory_handle() -> Result<Connection> {
         let db = Connection::open_in_memory()?;
@@ -706,6 +1109,57 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_nested_named_savepoints() -> Result<()> {
+        let mut db = checked_memory_handle()?;
+        {
+            let mut sp1 = db.savepoint_with_name("outer")?;
+            assert_eq!((sp1.name(), sp1.depth()), ("outer", 0));
+            insert(1, &sp1)?;
+            {
+                let mut sp2 = sp1.savepoint_with_name("middle")?;
+                assert_eq!((sp2.name(), sp2.depth()), ("middle", 1));
+                insert(2, &sp2)?;
+                {
+                    let sp3 = sp2.savepoint()?;
+                    assert_eq!(sp3.depth(), 2);
+                    insert(4, &sp3)?;
+                    sp3.commit()?;
+                }
+                assert_current_sum(7, &sp2)?;
+                sp2.rollback()?;
+                assert_current_sum(1, &sp2)?;
+                sp2.commit()?;
+            }
+            assert_current_sum(1, &sp1)?;
+            sp1.commit()?;
+        }
+        assert_current_sum(1, &db)?;
+
+        let mut tx = db.transaction()?;
+        let sp = tx.savepoint_with_name("in_tx")?;
+        assert_eq!(sp.depth(), 1);
+        sp.commit()?;
+        tx.commit()
+    }
+
+    #[test]
+    fn test_savepoint_released_by_sql() -> Result<()> {
+        let mut db = checked_memory_handle()?;
+        let mut sp = db.savepoint_with_name("my_sp")?;
+        insert(1, &sp)?;
+        sp.execute_batch("RELEASE my_sp")?;
+        let err = sp.rollback().unwrap_err();
+        assert!(
+            err.to_string().contains("savepoint my_sp no longer exists"),
+            "{}",
+            err
+        );
+        let err = sp.commit().unwrap_err();
+        assert!(err.to_string().contains("my_sp"), "{}", err);
+        assert_current_sum(1, &db)
+    }
+
     #[test]
     fn test_rc() -> Result<()> {
         use std::rc::Rc;
@@ -717,6 +1171,223 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_transaction_builder_busy_timeout() -> Result<()> {
+        use std::time::{Duration, Instant};
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let mut holder = Connection::open(&path)?;
+        let mut db = Connection::open(&path)?;
+        let busy_timeout = |db: &Connection| {
+            db.pragma_query_value(None, "busy_timeout", |row| row.get::<_, i32>(0))
+        };
+        assert_eq!(busy_timeout(&db)?, 5000);
+
+        let _lock = holder.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let start = Instant::now();
+        let err = db
+            .transaction_builder()
+            .behavior(TransactionBehavior::Immediate)
+            .busy_timeout(Duration::from_millis(100))
+            .begin()
+            .unwrap_err();
+        assert_eq!(
+            err.sqlite_error_code(),
+            Some(crate::ErrorCode::DatabaseBusy)
+        );
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(busy_timeout(&db)?, 5000);
+
+        // the restored timeout is the one waited for
+        db.busy_timeout(Duration::from_millis(300))?;
+        let start = Instant::now();
+        db.transaction_builder()
+            .behavior(TransactionBehavior::Immediate)
+            .busy_timeout(Duration::from_millis(10))
+            .begin()
+            .unwrap_err();
+        assert!(start.elapsed() < Duration::from_millis(250));
+        let start = Instant::now();
+        db.transaction_with_behavior(TransactionBehavior::Immediate)
+            .unwrap_err();
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        Ok(())
+    }
+
+    #[test]
+    fn test_transaction_builder_savepoints() -> Result<()> {
+        use std::time::Duration;
+
+        let mut db = checked_memory_handle()?;
+        let busy_timeout = |db: &Connection| {
+            db.pragma_query_value(None, "busy_timeout", |row| row.get::<_, i32>(0))
+        };
+        {
+            let mut tx = db
+                .transaction_builder()
+                .busy_timeout(Duration::from_millis(100))
+                .drop_behavior(DropBehavior::Commit)
+                .begin()?;
+            assert_eq!(tx.drop_behavior(), DropBehavior::Commit);
+            {
+                let mut sp = tx.savepoint()?;
+                assert_eq!(busy_timeout(&sp)?, 100);
+                sp.savepoint()?.commit()?;
+                sp.execute_batch("INSERT INTO foo VALUES (1)")?;
+                sp.commit()?;
+            }
+            assert_eq!(busy_timeout(&tx)?, 100);
+        }
+        assert_eq!(busy_timeout(&db)?, 5000);
+        let x: i32 = db.one_column("SELECT SUM(x) FROM foo")?;
+        assert_eq!(x, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_transaction_with_retry() -> Result<()> {
+        use std::sync::atomic::{AtomicU32, Ordering};
+        use std::sync::mpsc::sync_channel;
+        use std::thread;
+        use std::time::Duration;
+
+        static RETRIES: AtomicU32 = AtomicU32::new(0);
+        fn count_retries(_: u32, delay: Duration) -> Duration {
+            RETRIES.fetch_add(1, Ordering::SeqCst);
+            delay
+        }
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let mut db = Connection::open(&path)?;
+        db.pragma_update_and_check(None, "journal_mode", "wal", |_| Ok(()))?;
+        db.execute_batch("CREATE TABLE foo (x INTEGER)")?;
+        db.busy_timeout(Duration::ZERO)?;
+
+        let (locked_tx, locked_rx) = sync_channel(0);
+        let holder = {
+            let path = path.clone();
+            thread::spawn(move || -> Result<()> {
+                let mut db = Connection::open(path)?;
+                let tx = db.transaction_with_behavior(TransactionBehavior::Immediate)?;
+                locked_tx.send(()).unwrap();
+                thread::sleep(Duration::from_millis(100));
+                tx.commit()
+            })
+        };
+        locked_rx.recv().unwrap();
+
+        let policy = RetryPolicy {
+            max_attempts: 2,
+            initial_delay: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        };
+        let err = db
+            .transaction_with_retry(TransactionBehavior::Immediate, &policy)
+            .unwrap_err();
+        assert_eq!(
+            err.sqlite_error_code(),
+            Some(crate::ErrorCode::DatabaseBusy)
+        );
+
+        let policy = RetryPolicy {
+            max_attempts: 20,
+            jitter: Some(count_retries),
+            retry_commit: true,
+            ..RetryPolicy::default()
+        };
+        let tx = db.transaction_with_retry(TransactionBehavior::Immediate, &policy)?;
+        assert!(RETRIES.load(Ordering::SeqCst) > 0);
+        insert(1, &tx)?;
+        tx.commit()?;
+        holder.join().unwrap()?;
+        assert_current_sum(1, &db)
+    }
+
+    #[test]
+    fn test_with_transaction() -> Result<()> {
+        let mut db = checked_memory_handle()?;
+        let sum = db.with_transaction(TransactionBehavior::Immediate, |tx| {
+            insert(1, tx)?;
+            tx.one_column::<i32>("SELECT SUM(x) FROM foo")
+        })?;
+        assert_eq!(sum, 1);
+
+        let r: Result<()> = db.with_transaction(TransactionBehavior::Deferred, |tx| {
+            insert(2, tx)?;
+            Err(Error::QueryReturnedNoRows)
+        });
+        assert_eq!(r.unwrap_err(), Error::QueryReturnedNoRows);
+        assert!(db.is_autocommit());
+        assert_current_sum(1, &db)
+    }
+
+    #[test]
+    fn test_with_transaction_commit_failure() -> Result<()> {
+        #[derive(Debug)]
+        struct MyError(Error);
+        impl From<Error> for MyError {
+            fn from(err: Error) -> MyError {
+                MyError(err)
+            }
+        }
+
+        let mut db = checked_memory_handle()?;
+        db.execute_batch(
+            "PRAGMA foreign_keys = ON;
+             CREATE TABLE parent (id INTEGER PRIMARY KEY);
+             CREATE TABLE child (
+                 parent INTEGER REFERENCES parent(id) DEFERRABLE INITIALLY DEFERRED
+             );",
+        )?;
+        let r = db.with_transaction(TransactionBehavior::Deferred, |tx| {
+            insert(1, tx)?;
+            tx.execute_batch("INSERT INTO child VALUES (42)")?;
+            Ok::<_, MyError>(())
+        });
+        let MyError(err) = r.unwrap_err();
+        assert_eq!(
+            err.sqlite_error_code(),
+            Some(crate::ErrorCode::ConstraintViolation)
+        );
+        assert!(db.is_autocommit());
+        assert_eq!(0, db.one_column::<i32>("SELECT count(*) FROM foo")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_transaction_nested() -> Result<()> {
+        let mut db = checked_memory_handle()?;
+        db.with_transaction(TransactionBehavior::Deferred, |tx| {
+            insert(1, tx)?;
+            let r: Result<()> = tx.with_transaction(TransactionBehavior::Deferred, |tx| {
+                insert(2, tx)?;
+                Err(Error::InvalidQuery)
+            });
+            assert_eq!(r.unwrap_err(), Error::InvalidQuery);
+            assert_current_sum(1, tx)?;
+            tx.with_transaction(TransactionBehavior::Deferred, |tx| {
+                insert(4, tx)?;
+                tx.with_transaction(TransactionBehavior::Deferred, |tx| insert(8, tx))
+            })?;
+            assert!(!tx.is_autocommit());
+            assert_current_sum(13, tx)
+        })?;
+        assert_current_sum(13, &db)?;
+
+        // a transaction begun by hand is joined with a savepoint
+        db.execute_batch("BEGIN")?;
+        let r: Result<()> = db.with_transaction(TransactionBehavior::Immediate, |tx| {
+            insert(16, tx)?;
+            Err(Error::InvalidQuery)
+        });
+        assert!(r.is_err());
+        assert!(!db.is_autocommit());
+        db.execute_batch("COMMIT")?;
+        assert_current_sum(13, &db)
+    }
+
     fn insert(x: i32, conn: &Connection) -> Result<usize> {
         conn.execute("INSERT INTO foo VALUES(?1)", [x])
     }
@@ -747,4 +1418,34 @@ mod test {
         db.execute_batch("ROLLBACK")?;
         Ok(())
     }
+
+    #[test]
+    #[cfg(feature = "modern_sqlite")]
+    fn txn_state_attached() -> Result<()> {
+        use super::TransactionState;
+        use crate::DatabaseName;
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("ATTACH ':memory:' AS aux; CREATE TABLE aux.t (x INTEGER);")?;
+        let aux = || db.transaction_state(Some(DatabaseName::Attached("aux")));
+        assert_eq!(TransactionState::None, aux()?);
+
+        db.execute_batch("BEGIN DEFERRED")?;
+        db.one_column::<i64>("SELECT count(*) FROM aux.t")?;
+        assert_eq!(TransactionState::Read, aux()?);
+        assert_eq!(
+            TransactionState::None,
+            db.transaction_state(Some(DatabaseName::Main))?
+        );
+        db.execute("INSERT INTO aux.t VALUES (1)", [])?;
+        assert_eq!(TransactionState::Write, aux()?);
+        assert_eq!(TransactionState::Write, db.transaction_state(None)?);
+        db.execute_batch("COMMIT")?;
+        assert_eq!(TransactionState::None, aux()?);
+
+        let err = db
+            .transaction_state(Some(DatabaseName::Attached("nope")))
+            .unwrap_err();
+        assert_eq!(err.sqlite_error_code(), Some(crate::ErrorCode::ApiMisuse));
+        Ok(())
+    }
 }
@@ -0,0 +1,53 @@
+//! Round trips through SQLite of the `camino` paths conversions of `rusqlite-types`.
+#[cfg(test)]
+mod test {
+    use crate::types::Type;
+    use crate::{Connection, Error, Result};
+    use camino::{Utf8Path, Utf8PathBuf};
+
+    fn checked_memory_handle() -> Result<Connection> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE paths (i INTEGER, v)")?;
+        Ok(db)
+    }
+
+    fn get_path(db: &Connection, id: i64) -> Result<Option<Utf8PathBuf>> {
+        db.query_row("SELECT v FROM paths WHERE i = ?", [id], |r| r.get(0))
+    }
+
+    #[test]
+    fn test_sql_utf8_path() -> Result<()> {
+        let db = checked_memory_handle()?;
+        let path = Utf8Path::new("/tmp/my files/résumé 履歴書.txt");
+        db.execute("INSERT INTO paths (i, v) VALUES (0, ?)", [path])?;
+        db.execute(
+            "INSERT INTO paths (i, v) VALUES (1, ?)",
+            [path.to_path_buf()],
+        )?;
+        db.execute(
+            "INSERT INTO paths (i, v) VALUES (2, CAST(? AS BLOB))",
+            [path],
+        )?;
+        db.execute("INSERT INTO paths (i, v) VALUES (3, NULL)", [])?;
+
+        let typ: String =
+            db.query_row("SELECT typeof(v) FROM paths WHERE i = 0", [], |r| r.get(0))?;
+        assert_eq!(typ, "text");
+        for i in 0..3 {
+            assert_eq!(get_path(&db, i)?.as_deref(), Some(path));
+        }
+        assert_eq!(get_path(&db, 3)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_utf8_blob() -> Result<()> {
+        let db = checked_memory_handle()?;
+        db.execute("INSERT INTO paths (i, v) VALUES (0, x'66ff6f')", [])?;
+        match get_path(&db, 0).unwrap_err() {
+            Error::InvalidColumnType(_, _, Type::Blob) => {}
+            e => panic!("Expected InvalidColumnType, got {}", e),
+        }
+        Ok(())
+    }
+}
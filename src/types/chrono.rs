@@ -1,165 +1,8 @@
-//! Convert most of the [Time Strings](http://sqlite.org/lang_datefunc.html) to chrono types.
-
-use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
-
-use crate::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
-use crate::Result;
-
-/// ISO 8601 calendar date without timezone => "YYYY-MM-DD"
-impl ToSql for NaiveDate {
-    #[inline]
-    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
-        let date_str = self.format("%F").to_string();
-        Ok(ToSqlOutput::from(date_str))
-    }
-}
-
-/// "YYYY-MM-DD" => ISO 8601 calendar date without timezone.
-impl FromSql for NaiveDate {
-    #[inline]
-    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
-        value
-            .as_str()
-            .and_then(|s| match NaiveDate::parse_from_str(s, "%F") {
-                Ok(dt) => Ok(dt),
-                Err(err) => Err(FromSqlError::Other(Box::new(err))),
-            })
-    }
-}
-
-/// ISO 8601 time without timezone => "HH:MM:SS.SSS"
-impl ToSql for NaiveTime {
-    #[inline]
-    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
-        let date_str = self.format("%T%.f").to_string();
-        Ok(ToSqlOutput::from(date_str))
-    }
-}
-
-/// "HH:MM"/"HH:MM:SS"/"HH:MM:SS.SSS" => ISO 8601 time without timezone.
-impl FromSql for NaiveTime {
-    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
-        value.as_str().and_then(|s| {
-            let fmt = match s.len() {
-                5 => "%H:%M",
-                8 => "%T",
-                _ => "%T%.f",
-            };
-            match NaiveTime::parse_from_str(s, fmt) {
-                Ok(dt) => Ok(dt),
-                Err(err) => Err(FromSqlError::Other(Box::new(err))),
-            }
-        })
-    }
-}
-
-/// ISO 8601 combined date and time without timezone =>
-/// "YYYY-MM-DD HH:MM:SS.SSS"
-impl ToSql for NaiveDateTime {
-    #[inline]
-    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
-        let date_str = self.format("%F %T%.f").to_string();
-        Ok(ToSqlOutput::from(date_str))
-    }
-}
-
-/// "YYYY-MM-DD HH:MM:SS"/"YYYY-MM-DD HH:MM:SS.SSS" => ISO 8601 combined date
-/// and time without timezone. ("YYYY-MM-DDTHH:MM:SS"/"YYYY-MM-DDTHH:MM:SS.SSS"
-/// also supported)
-impl FromSql for NaiveDateTime {
-    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
-        value.as_str().and_then(|s| {
-            let fmt = if s.len() >= 11 && s.as_bytes()[10] == b'T' {
-                "%FT%T%.f"
-            } else {
-                "%F %T%.f"
-            };
-
-            match NaiveDateTime::parse_from_str(s, fmt) {
-                Ok(dt) => Ok(dt),
-                Err(err) => Err(FromSqlError::Other(Box::new(err))),
-            }
-        })
-    }
-}
-
-/// UTC time => UTC RFC3339 timestamp
-/// ("YYYY-MM-DD HH:MM:SS.SSS+00:00").
-impl ToSql for DateTime<Utc> {
-    #[inline]
-    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
-        let date_str = self.format("%F %T%.f%:z").to_string();
-        Ok(ToSqlOutput::from(date_str))
-    }
-}
-
-/// Local time => UTC RFC3339 timestamp
-/// ("YYYY-MM-DD HH:MM:SS.SSS+00:00").
-impl ToSql for DateTime<Local> {
-    #[inline]
-    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
-        let date_str = self.with_timezone(&Utc).format("%F %T%.f%:z").to_string();
-        Ok(ToSqlOutput::from(date_str))
-    }
-}
-
-/// Date and time with time zone => RFC3339 timestamp
-/// ("YYYY-MM-DD HH:MM:SS.SSS[+-]HH:MM").
-impl ToSql for DateTime<FixedOffset> {
-    #[inline]
-    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
-        let date_str = self.format("%F %T%.f%:z").to_string();
-        Ok(ToSqlOutput::from(date_str))
-    }
-}
-
-/// RFC3339 ("YYYY-MM-DD HH:MM:SS.SSS[+-]HH:MM") into `DateTime<Utc>`.
-impl FromSql for DateTime<Utc> {
-    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
-        {
-            // Try to parse value as rfc3339 first.
-            let s = value.as_str()?;
-
-            let fmt = if s.len() >= 11 && s.as_bytes()[10] == b'T' {
-                "%FT%T%.f%#z"
-            } else {
-                "%F %T%.f%#z"
-            };
-
-            if let Ok(dt) = DateTime::parse_from_str(s, fmt) {
-                return Ok(dt.with_timezone(&Utc));
-            }
-        }
-
-        // Couldn't parse as rfc3339 - fall back to NaiveDateTime.
-        NaiveDateTime::column_result(value).map(|dt| Utc.from_utc_datetime(&dt))
-    }
-}
-
-/// RFC3339 ("YYYY-MM-DD HH:MM:SS.SSS[+-]HH:MM") into `DateTime<Local>`.
-impl FromSql for DateTime<Local> {
-    #[inline]
-    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
-        let utc_dt = DateTime::<Utc>::column_result(value)?;
-        Ok(utc_dt.with_timezone(&Local))
-    }
-}
-
-/// RFC3339 ("YYYY-MM-DD HH:MM:SS.SSS[+-]HH:MM") into `DateTime<FixedOffset>`.
-impl FromSql for DateTime<FixedOffset> {
-    #[inline]
-    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
-        let s = String::column_result(value)?;
-        Self::parse_from_rfc3339(s.as_str())
-            .or_else(|_| Self::parse_from_str(s.as_str(), "%F %T%.f%:z"))
-            .map_err(|e| FromSqlError::Other(Box::new(e)))
-    }
-}
-
+//! Round trips through SQLite of the `chrono` types conversions of `rusqlite-types`.
 #[cfg(test)]
 mod test {
     use crate::{
-        types::{FromSql, ValueRef},
+        types::{FromSql, ToSql, ToSqlOutput, ValueRef},
         Connection, Result,
     };
     use chrono::{
@@ -316,4 +159,55 @@ mod test {
         DateTime::<Utc>::column_result(ValueRef::Text(b"1970-01-01T00:00:00Z")).unwrap();
         DateTime::<Utc>::column_result(ValueRef::Text(b"1970-01-01T00:00:00+00")).unwrap();
     }
+
+    fn text(value: &dyn ToSql) -> String {
+        match value.to_sql().unwrap() {
+            ToSqlOutput::Inline(text) => text.as_str().to_owned(),
+            output => panic!("Unexpected output {:?}", output),
+        }
+    }
+
+    #[test]
+    fn test_same_as_format() {
+        let dates = [
+            (1, 1, 1),
+            (999, 3, 4),
+            (2016, 2, 29),
+            (9999, 12, 31),
+            (10_000, 1, 1),
+            (-1, 6, 15),
+            (-44, 3, 15),
+            (262_000, 12, 31),
+        ];
+        let times = [
+            NaiveTime::from_hms_opt(0, 0, 0).unwrap(),
+            NaiveTime::from_hms_milli_opt(23, 56, 4, 120).unwrap(),
+            NaiveTime::from_hms_micro_opt(1, 2, 3, 4).unwrap(),
+            NaiveTime::from_hms_nano_opt(12, 0, 0, 1).unwrap(),
+            NaiveTime::from_hms_nano_opt(23, 59, 59, 1_999_999_999).unwrap(),
+        ];
+        let offsets = [0, 3600, -(9 * 3600 + 30 * 60), 5 * 3600 + 45 * 60 + 29, -59];
+        for &(y, m, d) in &dates {
+            let date = NaiveDate::from_ymd_opt(y, m, d).unwrap();
+            assert_eq!(text(&date), date.format("%F").to_string());
+            for time in &times {
+                assert_eq!(text(time), time.format("%T%.f").to_string());
+                let dt = date.and_time(*time);
+                assert_eq!(text(&dt), dt.format("%F %T%.f").to_string());
+                let utc = Utc.from_utc_datetime(&dt);
+                assert_eq!(text(&utc), utc.format("%F %T%.f%:z").to_string());
+                for &offset in &offsets {
+                    let fixed = FixedOffset::east_opt(offset)
+                        .unwrap()
+                        .from_utc_datetime(&dt);
+                    assert_eq!(text(&fixed), fixed.format("%F %T%.f%:z").to_string());
+                }
+            }
+        }
+        let local = Local::now();
+        assert_eq!(
+            text(&local),
+            local.with_timezone(&Utc).format("%F %T%.f%:z").to_string()
+        );
+    }
 }
@@ -0,0 +1,179 @@
+//! [`ToSql`] and [`FromSql`] implementation for [`FixedAscii`].
+use std::error::Error;
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+
+use crate::types::{
+    FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ToSqlResult, ValueRef,
+};
+
+/// An ASCII string of exactly `N` bytes, such as an IMEI or a country code,
+/// stored as `TEXT`.
+///
+/// Both [`FixedAscii::new`] and [`FromSql`] reject values that are not ASCII
+/// or not exactly `N` bytes long.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FixedAscii<const N: usize>([u8; N]);
+
+/// Error returned when a string cannot be converted into a [`FixedAscii`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FixedAsciiError {
+    /// The input was not exactly the expected number of bytes long.
+    WrongLength {
+        /// The offending input.
+        input: String,
+        /// The expected length, in bytes.
+        expected: usize,
+    },
+    /// The input contained a non-ASCII character.
+    NonAscii(String),
+}
+
+impl fmt::Display for FixedAsciiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            FixedAsciiError::WrongLength {
+                ref input,
+                expected,
+            } => write!(
+                f,
+                "expected {} ASCII characters, got {} bytes: {:?}",
+                expected,
+                input.len(),
+                input
+            ),
+            FixedAsciiError::NonAscii(ref input) => {
+                write!(f, "expected only ASCII characters: {:?}", input)
+            }
+        }
+    }
+}
+
+impl Error for FixedAsciiError {}
+
+impl<const N: usize> FixedAscii<N> {
+    /// Checks that `s` is ASCII and exactly `N` bytes long.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `s` is not ASCII or is not `N` bytes long.
+    pub fn new(s: &str) -> Result<Self, FixedAsciiError> {
+        if !s.is_ascii() {
+            return Err(FixedAsciiError::NonAscii(s.to_owned()));
+        }
+        if s.len() != N {
+            return Err(FixedAsciiError::WrongLength {
+                input: s.to_owned(),
+                expected: N,
+            });
+        }
+        let mut buf = [0; N];
+        buf.copy_from_slice(s.as_bytes());
+        Ok(FixedAscii(buf))
+    }
+
+    /// Returns the value as a string slice.
+    #[inline]
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        // SAFETY: the contents were checked to be ASCII on construction.
+        unsafe { std::str::from_utf8_unchecked(&self.0) }
+    }
+}
+
+impl<const N: usize> Deref for FixedAscii<N> {
+    type Target = str;
+
+    #[inline]
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<const N: usize> FromStr for FixedAscii<N> {
+    type Err = FixedAsciiError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, FixedAsciiError> {
+        FixedAscii::new(s)
+    }
+}
+
+impl<const N: usize> fmt::Debug for FixedAscii<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl<const N: usize> fmt::Display for FixedAscii<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// Serialize `FixedAscii` to text.
+impl<const N: usize> ToSql for FixedAscii<N> {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+/// Deserialize text to `FixedAscii`.
+impl<const N: usize> FromSql for FixedAscii<N> {
+    #[inline]
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        FixedAscii::new(value.as_str()?).map_err(|e| FromSqlError::Other(Box::new(e)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FixedAscii, FixedAsciiError};
+    use crate::{Connection, Error, Result};
+
+    type Imei = FixedAscii<15>;
+
+    #[test]
+    fn test_fixed_ascii() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        let imei = Imei::new("490154203237518").unwrap();
+        assert_eq!(&*imei, "490154203237518");
+        let stored: Imei = db.query_row("SELECT ?1", [imei], |r| r.get(0))?;
+        assert_eq!(stored, imei);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fixed_ascii_wrong_length() -> Result<()> {
+        assert_eq!(
+            Imei::new("49015420323751"),
+            Err(FixedAsciiError::WrongLength {
+                input: "49015420323751".to_owned(),
+                expected: 15,
+            })
+        );
+        assert_eq!(
+            FixedAscii::<2>::new("é"),
+            Err(FixedAsciiError::NonAscii("é".to_owned()))
+        );
+
+        let db = Connection::open_in_memory()?;
+        let err = db
+            .query_row("SELECT '4901542032375189'", [], |r| r.get::<_, Imei>(0))
+            .unwrap_err();
+        match err {
+            Error::FromSqlConversionFailure(_, _, e) => assert_eq!(
+                *e.downcast::<FixedAsciiError>().unwrap(),
+                FixedAsciiError::WrongLength {
+                    input: "4901542032375189".to_owned(),
+                    expected: 15,
+                }
+            ),
+            e => panic!("Expected conversion failure, got {}", e),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+//! [`ToSql`] and [`FromSql`] implementation for [`LenientBool`].
+use std::error::Error;
+use std::fmt;
+
+use crate::types::{
+    FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ToSqlResult, ValueRef,
+};
+
+/// A `bool` which can also be read from the `TEXT` and `REAL` spellings
+/// commonly found in imported data.
+///
+/// `FromSql for bool` only accepts `INTEGER` values. `LenientBool` also
+/// accepts, ignoring case and surrounding whitespace:
+///
+/// * `TEXT` `'true'`/`'false'`, `'t'`/`'f'`, `'yes'`/`'no'` and `'1'`/`'0'`
+/// * `REAL` `1.0`/`0.0`
+///
+/// It is always written as an `INTEGER`, like `bool`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LenientBool(pub bool);
+
+impl From<bool> for LenientBool {
+    #[inline]
+    fn from(b: bool) -> Self {
+        LenientBool(b)
+    }
+}
+
+impl From<LenientBool> for bool {
+    #[inline]
+    fn from(b: LenientBool) -> Self {
+        b.0
+    }
+}
+
+/// Error returned when a value cannot be read as a [`LenientBool`].
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum InvalidBool {
+    /// Unrecognized text.
+    Text(String),
+    /// A `REAL` other than `0.0` or `1.0`.
+    Real(f64),
+}
+
+impl fmt::Display for InvalidBool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            InvalidBool::Text(ref s) => write!(f, "invalid boolean text: {:?}", s),
+            InvalidBool::Real(r) => write!(f, "invalid boolean real: {}", r),
+        }
+    }
+}
+
+impl Error for InvalidBool {}
+
+/// Serialize `LenientBool` to an integer.
+impl ToSql for LenientBool {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        Ok(ToSqlOutput::from(self.0))
+    }
+}
+
+/// Deserialize an integer, real or text to `LenientBool`.
+impl FromSql for LenientBool {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value {
+            ValueRef::Integer(i) => Ok(LenientBool(i != 0)),
+            ValueRef::Real(r) => {
+                if r == 0.0 {
+                    Ok(LenientBool(false))
+                } else if r == 1.0 {
+                    Ok(LenientBool(true))
+                } else {
+                    Err(FromSqlError::Other(Box::new(InvalidBool::Real(r))))
+                }
+            }
+            ValueRef::Text(_) => {
+                let s = value.as_str()?;
+                let t = s.trim();
+                const TRUE: [&str; 4] = ["true", "t", "yes", "1"];
+                const FALSE: [&str; 4] = ["false", "f", "no", "0"];
+                if TRUE.iter().any(|v| v.eq_ignore_ascii_case(t)) {
+                    Ok(LenientBool(true))
+                } else if FALSE.iter().any(|v| v.eq_ignore_ascii_case(t)) {
+                    Ok(LenientBool(false))
+                } else {
+                    Err(FromSqlError::Other(Box::new(InvalidBool::Text(
+                        s.to_owned(),
+                    ))))
+                }
+            }
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{InvalidBool, LenientBool};
+    use crate::{Connection, Error, Result};
+
+    fn get(db: &Connection, sql: &str) -> Result<bool> {
+        db.query_row(sql, [], |r| r.get::<_, LenientBool>(0))
+            .map(bool::from)
+    }
+
+    #[test]
+    fn test_spellings() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        for t in [
+            "'true'", "'t'", "'yes'", "'1'", "'TRUE'", "'Yes'", "' T '", "1", "2", "1.0",
+        ] {
+            assert!(get(&db, &format!("SELECT {t}"))?, "{}", t);
+        }
+        for f in [
+            "'false'", "'f'", "'no'", "'0'", "'False'", "'NO'", "0", "0.0",
+        ] {
+            assert!(!get(&db, &format!("SELECT {f}"))?, "{}", f);
+        }
+        let stored: i64 = db.query_row("SELECT ?1", [LenientBool(true)], |r| r.get(0))?;
+        assert_eq!(stored, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejected() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        match get(&db, "SELECT 'maybe'").unwrap_err() {
+            Error::FromSqlConversionFailure(_, _, e) => {
+                assert_eq!(e.to_string(), "invalid boolean text: \"maybe\"");
+                assert_eq!(
+                    *e.downcast::<InvalidBool>().unwrap(),
+                    InvalidBool::Text("maybe".to_owned())
+                );
+            }
+            e => panic!("Expected conversion failure, got {}", e),
+        }
+        assert!(get(&db, "SELECT 0.5").is_err());
+        assert!(matches!(
+            get(&db, "SELECT NULL"),
+            Err(Error::InvalidColumnType(..))
+        ));
+        Ok(())
+    }
+}
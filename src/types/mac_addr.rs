@@ -0,0 +1,212 @@
+//! [`ToSql`] and [`FromSql`] implementation for [`MacAddr`].
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::types::{
+    FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ToSqlResult, ValueRef,
+};
+
+/// A 48-bit MAC address.
+///
+/// It is always stored as `TEXT` in the canonical `AA:BB:CC:DD:EE:FF` form,
+/// but can be read from `TEXT` in that form or in the dotted
+/// `aabb.ccdd.eeff` form, or from a 6-byte `BLOB`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MacAddr(pub [u8; 6]);
+
+/// Error returned when a string cannot be parsed as a [`MacAddr`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MacAddrError(String);
+
+impl MacAddrError {
+    /// Returns the offending input.
+    #[inline]
+    #[must_use]
+    pub fn input(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for MacAddrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid MAC address: {:?}", self.0)
+    }
+}
+
+impl Error for MacAddrError {}
+
+impl FromStr for MacAddr {
+    type Err = MacAddrError;
+
+    fn from_str(s: &str) -> Result<MacAddr, MacAddrError> {
+        let err = || MacAddrError(s.to_owned());
+        // `AA:BB:CC:DD:EE:FF` is 6 groups of 2 digits, `aabb.ccdd.eeff` is 3
+        // groups of 4 digits.
+        let (sep, groups, digits) = match s.len() {
+            17 => (':', 6, 2),
+            14 => ('.', 3, 4),
+            _ => return Err(err()),
+        };
+        let mut bytes = [0; 6];
+        let mut n = 0;
+        for group in s.split(sep) {
+            if group.len() != digits
+                || !group.bytes().all(|b| b.is_ascii_hexdigit())
+                || n + digits / 2 > bytes.len()
+            {
+                return Err(err());
+            }
+            for i in (0..digits).step_by(2) {
+                bytes[n] = u8::from_str_radix(&group[i..i + 2], 16).map_err(|_| err())?;
+                n += 1;
+            }
+        }
+        if n != groups * digits / 2 {
+            return Err(err());
+        }
+        Ok(MacAddr(bytes))
+    }
+}
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(
+            f,
+            "{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}",
+            a, b, c, d, e, g
+        )
+    }
+}
+
+/// Serialize `MacAddr` to text in the canonical `AA:BB:CC:DD:EE:FF` form.
+impl ToSql for MacAddr {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        Ok(ToSqlOutput::from(self.to_string()))
+    }
+}
+
+/// Deserialize text or a 6-byte blob to `MacAddr`.
+impl FromSql for MacAddr {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value {
+            ValueRef::Text(_) => value
+                .as_str()?
+                .parse()
+                .map_err(|e| FromSqlError::Other(Box::new(e))),
+            ValueRef::Blob(b) => {
+                <[u8; 6]>::try_from(b)
+                    .map(MacAddr)
+                    .map_err(|_| FromSqlError::InvalidBlobSize {
+                        expected_size: 6,
+                        blob_size: b.len(),
+                    })
+            }
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MacAddr, MacAddrError};
+    use crate::types::ValueRef;
+    use crate::{Connection, Error, Result};
+
+    const MAC: MacAddr = MacAddr([0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff]);
+
+    #[test]
+    fn test_accepted_formats() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        for sql in [
+            "SELECT 'AA:BB:CC:DD:EE:FF'",
+            "SELECT 'aa:bb:cc:dd:ee:ff'",
+            "SELECT 'aabb.ccdd.eeff'",
+            "SELECT x'aabbccddeeff'",
+        ] {
+            let mac: MacAddr = db.query_row(sql, [], |r| r.get(0))?;
+            assert_eq!(mac, MAC, "{}", sql);
+        }
+        let stored: String = db.query_row("SELECT ?1", [MAC], |r| r.get(0))?;
+        assert_eq!(stored, "AA:BB:CC:DD:EE:FF");
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejected_formats() {
+        for s in [
+            "",
+            "AA:BB:CC:DD:EE",
+            "AA-BB-CC-DD-EE-FF",
+            "AAB:B:CC:DD:EE:FF",
+            "aabb.ccdd.eefg",
+            "aabbc.cdd.eeff",
+            "AA:BB:CC:DD:EE:+F",
+        ] {
+            assert_eq!(s.parse::<MacAddr>(), Err(MacAddrError(s.to_owned())));
+        }
+        assert!(
+            <MacAddr as crate::types::FromSql>::column_result(ValueRef::Blob(&[0; 5])).is_err()
+        );
+    }
+
+    #[test]
+    fn test_join_across_representations() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE devices (mac, name TEXT);
+             CREATE TABLE leases (mac, ip TEXT);
+             INSERT INTO devices VALUES ('aabb.ccdd.eeff', 'printer');
+             INSERT INTO devices VALUES ('00:11:22:33:44:55', 'router');
+             INSERT INTO leases VALUES (x'aabbccddeeff', '10.0.0.2');
+             INSERT INTO leases VALUES (x'001122334455', '10.0.0.1');",
+        )?;
+        let joined: i64 = db.query_row(
+            "SELECT count(*) FROM devices JOIN leases USING (mac)",
+            [],
+            |r| r.get(0),
+        )?;
+        assert_eq!(joined, 0);
+
+        // rewrite both columns in the canonical form
+        for table in ["devices", "leases"] {
+            let mut select = db.prepare(&format!("SELECT rowid, mac FROM {table}"))?;
+            let macs = select
+                .query_map([], |r| Ok((r.get::<_, i64>(0)?, r.get::<_, MacAddr>(1)?)))?
+                .collect::<Result<Vec<_>>>()?;
+            for (rowid, mac) in macs {
+                db.execute(
+                    &format!("UPDATE {table} SET mac = ?1 WHERE rowid = ?2"),
+                    crate::params![mac, rowid],
+                )?;
+            }
+        }
+
+        let mut stmt =
+            db.prepare("SELECT name, ip FROM devices JOIN leases USING (mac) ORDER BY ip")?;
+        let joined = stmt
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?
+            .collect::<Result<Vec<(String, String)>>>()?;
+        assert_eq!(
+            joined,
+            vec![
+                ("router".to_owned(), "10.0.0.1".to_owned()),
+                ("printer".to_owned(), "10.0.0.2".to_owned()),
+            ]
+        );
+
+        let err = db
+            .query_row("SELECT 'not a mac'", [], |r| r.get::<_, MacAddr>(0))
+            .unwrap_err();
+        match err {
+            Error::FromSqlConversionFailure(_, _, e) => {
+                assert_eq!(e.downcast::<MacAddrError>().unwrap().input(), "not a mac");
+            }
+            e => panic!("Expected conversion failure, got {}", e),
+        }
+        Ok(())
+    }
+}
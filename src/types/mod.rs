@@ -41,8 +41,7 @@ For example, to store datetimes as `i64`s counting the number of seconds since
 the Unix epoch:
 
 ```
-use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
-use rusqlite::Result;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlResult, ValueRef};
 
 pub struct DateTimeSql(pub time::OffsetDateTime);
 
@@ -57,7 +56,7 @@ impl FromSql for DateTimeSql {
 }
 
 impl ToSql for DateTimeSql {
-    fn to_sql(&self) -> Result<ToSqlOutput> {
+    fn to_sql(&self) -> ToSqlResult {
         Ok(self.0.unix_timestamp().into())
     }
 }
@@ -68,74 +67,178 @@ impl ToSql for DateTimeSql {
 //! [`ToSql`] and [`FromSql`] are also implemented for `Option<T>` where `T`
 //! implements [`ToSql`] or [`FromSql`] for the cases where you want to know if
 //! a value was NULL (which gets translated to `None`).
+//!
+//! The types and traits here, except for the SQLite-specific
+//! [`ToSqlOutputExt`] and the helper types, are defined in the
+//! `rusqlite-types` crate, which does not link SQLite. A library which only
+//! implements [`ToSql`] and [`FromSql`] for its own types can depend on that
+//! crate alone.
+
+pub use self::fixed_ascii::{FixedAscii, FixedAsciiError};
+pub use self::lenient_bool::{InvalidBool, LenientBool};
+pub use self::mac_addr::{MacAddr, MacAddrError};
+#[cfg(feature = "semver")]
+#[cfg_attr(docsrs, doc(cfg(feature = "semver")))]
+pub use self::semver::VersionKey;
+#[cfg(feature = "derive")]
+#[cfg_attr(docsrs, doc(cfg(feature = "derive")))]
+pub use rusqlite_macros::{FromSqlRepr, FromSqlText, ToSqlRepr, ToSqlText};
+#[cfg(feature = "semver")]
+#[cfg_attr(docsrs, doc(cfg(feature = "semver")))]
+pub use rusqlite_types::InvalidVersion;
+pub use rusqlite_types::{
+    Extension, FromSql, FromSqlError, FromSqlRef, FromSqlResult, InlineText, Null, ToSql,
+    ToSqlError, ToSqlOutput, ToSqlResult, Type, Value, ValueRef,
+};
+#[cfg(feature = "serde_json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde_json")))]
+pub use rusqlite_types::{Json, JsonError};
 
-pub use self::from_sql::{FromSql, FromSqlError, FromSqlResult};
-pub use self::to_sql::{ToSql, ToSqlOutput};
-pub use self::value::Value;
-pub use self::value_ref::ValueRef;
-
-use std::fmt;
+#[cfg(feature = "array")]
+use crate::vtab::array::Array;
 
-#[cfg(feature = "chrono")]
-#[cfg_attr(docsrs, doc(cfg(feature = "chrono")))]
+#[cfg(all(test, feature = "camino"))]
+mod camino;
+#[cfg(all(test, feature = "chrono"))]
 mod chrono;
+mod fixed_ascii;
+#[cfg(test)]
 mod from_sql;
-#[cfg(feature = "serde_json")]
-#[cfg_attr(docsrs, doc(cfg(feature = "serde_json")))]
+mod lenient_bool;
+mod mac_addr;
+#[cfg(feature = "semver")]
+#[cfg_attr(docsrs, doc(cfg(feature = "semver")))]
+mod semver;
+#[cfg(all(test, feature = "serde_json"))]
 mod serde_json;
 #[cfg(feature = "time")]
 #[cfg_attr(docsrs, doc(cfg(feature = "time")))]
-mod time;
+pub mod time;
+#[cfg(test)]
 mod to_sql;
-#[cfg(feature = "url")]
-#[cfg_attr(docsrs, doc(cfg(feature = "url")))]
+#[cfg(all(test, feature = "url"))]
 mod url;
-mod value;
-mod value_ref;
 
-/// Empty struct that can be used to fill in a query parameter as `NULL`.
-///
-/// ## Example
-///
-/// ```rust,no_run
-/// # use rusqlite::{Connection, Result};
-/// # use rusqlite::types::{Null};
+mod sealed {
+    /// This trait exists just to ensure that [`ToSqlOutputExt`] is only
+    /// implemented here.
+    ///
+    /// [`ToSqlOutputExt`]: super::ToSqlOutputExt
+    pub trait Sealed {}
+}
+
+/// The SQLite-specific outputs of [`ToSql`], which rusqlite stores in a
+/// [`ToSqlOutput::Extension`].
 ///
-/// fn insert_null(conn: &Connection) -> Result<usize> {
-///     conn.execute("INSERT INTO people (name) VALUES (?1)", [Null])
-/// }
-/// ```
-#[derive(Copy, Clone)]
-pub struct Null;
-
-/// SQLite data types.
-/// See [Fundamental Datatypes](https://sqlite.org/c3ref/c_blob.html).
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub enum Type {
-    /// NULL
-    Null,
-    /// 64-bit signed integer
-    Integer,
-    /// 64-bit IEEE floating point number
-    Real,
-    /// String
-    Text,
-    /// BLOB
-    Blob,
+/// This trait is sealed: it is only implemented for [`ToSqlOutput`].
+pub trait ToSqlOutputExt: sealed::Sealed {
+    /// A BLOB of the given length that is filled with zeroes.
+    #[cfg(feature = "blob")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "blob")))]
+    fn zero_blob(len: i32) -> Self;
+
+    /// The length of a BLOB made by [`zero_blob`](ToSqlOutputExt::zero_blob).
+    #[cfg(feature = "blob")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "blob")))]
+    fn as_zero_blob(&self) -> Option<i32>;
+
+    /// An array passed to the `rarray` table-valued function.
+    #[cfg(feature = "array")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "array")))]
+    fn array(array: Array) -> Self;
+
+    /// The array of an output made by [`array`](ToSqlOutputExt::array).
+    #[cfg(feature = "array")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "array")))]
+    fn as_array(&self) -> Option<&Array>;
 }
 
-impl fmt::Display for Type {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
-            Type::Null => f.pad("Null"),
-            Type::Integer => f.pad("Integer"),
-            Type::Real => f.pad("Real"),
-            Type::Text => f.pad("Text"),
-            Type::Blob => f.pad("Blob"),
+#[cfg(feature = "blob")]
+#[derive(Debug, PartialEq)]
+struct ZeroBlobLen(i32);
+
+impl sealed::Sealed for ToSqlOutput<'_> {}
+
+impl ToSqlOutputExt for ToSqlOutput<'_> {
+    #[cfg(feature = "blob")]
+    #[inline]
+    fn zero_blob(len: i32) -> Self {
+        ToSqlOutput::Extension(Extension::new(ZeroBlobLen(len)))
+    }
+
+    #[cfg(feature = "blob")]
+    #[inline]
+    fn as_zero_blob(&self) -> Option<i32> {
+        match self {
+            ToSqlOutput::Extension(e) => e.downcast_ref::<ZeroBlobLen>().map(|z| z.0),
+            _ => None,
+        }
+    }
+
+    #[cfg(feature = "array")]
+    #[inline]
+    fn array(array: Array) -> Self {
+        ToSqlOutput::Extension(Extension::new(array))
+    }
+
+    #[cfg(feature = "array")]
+    #[inline]
+    fn as_array(&self) -> Option<&Array> {
+        match self {
+            ToSqlOutput::Extension(e) => e.downcast_ref::<Array>(),
+            _ => None,
         }
     }
 }
 
+#[cfg(any(feature = "functions", feature = "session", feature = "vtab"))]
+pub(crate) unsafe fn value_ref<'a>(value: *mut crate::ffi::sqlite3_value) -> ValueRef<'a> {
+    use crate::ffi;
+    use std::slice::from_raw_parts;
+
+    match ffi::sqlite3_value_type(value) {
+        ffi::SQLITE_NULL => ValueRef::Null,
+        ffi::SQLITE_INTEGER => ValueRef::Integer(ffi::sqlite3_value_int64(value)),
+        ffi::SQLITE_FLOAT => ValueRef::Real(ffi::sqlite3_value_double(value)),
+        ffi::SQLITE_TEXT => {
+            let text = ffi::sqlite3_value_text(value);
+            let len = ffi::sqlite3_value_bytes(value);
+            assert!(
+                !text.is_null(),
+                "unexpected SQLITE_TEXT value type with NULL data"
+            );
+            let s = from_raw_parts(text.cast::<u8>(), len as usize);
+            ValueRef::Text(s)
+        }
+        ffi::SQLITE_BLOB => {
+            let (blob, len) = (
+                ffi::sqlite3_value_blob(value),
+                ffi::sqlite3_value_bytes(value),
+            );
+
+            assert!(
+                len >= 0,
+                "unexpected negative return from sqlite3_value_bytes"
+            );
+            if len > 0 {
+                assert!(
+                    !blob.is_null(),
+                    "unexpected SQLITE_BLOB value type with NULL data"
+                );
+                ValueRef::Blob(from_raw_parts(blob.cast::<u8>(), len as usize))
+            } else {
+                // The return value from sqlite3_value_blob() for a zero-length BLOB
+                // is a NULL pointer.
+                ValueRef::Blob(&[])
+            }
+        }
+        _ => unreachable!("sqlite3_value_type returned invalid value"),
+    }
+}
+
+// TODO sqlite3_value_nochange // 3.22.0 & VTab xUpdate
+// TODO sqlite3_value_frombind // 3.28.0
+
 #[cfg(test)]
 mod test {
     use super::Value;
@@ -207,6 +310,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::unnecessary_literal_unwrap)]
     fn test_option() -> Result<()> {
         let db = checked_memory_handle()?;
 
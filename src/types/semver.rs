@@ -0,0 +1,153 @@
+//! Sortable storage of [`semver::Version`].
+use crate::types::{ToSql, ToSqlOutput, ToSqlResult};
+use semver::Version;
+
+/// Stores a [`Version`] as text that sorts in semver precedence order, so
+/// `ORDER BY` and range comparisons on the column work without parsing.
+///
+/// The numeric components are zero-padded, a pre-release sorts before the
+/// corresponding release, and pre-release identifiers compare as semver
+/// specifies (numerically when numeric, lexically otherwise). Build metadata
+/// does not take part in precedence and is not stored, so the key cannot be
+/// read back: keep it in a column alongside the `Version` itself.
+///
+/// ```rust,no_run
+/// # use rusqlite::{Connection, Result};
+/// # use rusqlite::types::VersionKey;
+/// # use semver::Version;
+/// fn insert(conn: &Connection, v: &Version) -> Result<usize> {
+///     conn.execute(
+///         "INSERT INTO plugins (version, version_key) VALUES (?1, ?2)",
+///         (v, VersionKey(v)),
+///     )
+/// }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct VersionKey<'a>(pub &'a Version);
+
+impl VersionKey<'_> {
+    /// Returns the sortable text stored for this version.
+    #[must_use]
+    pub fn key(&self) -> String {
+        let v = self.0;
+        let mut key = format!("{:020}.{:020}.{:020}", v.major, v.minor, v.patch);
+        if v.pre.is_empty() {
+            // sorts after the '-' that introduces a pre-release
+            key.push('~');
+        } else {
+            key.push('-');
+            for (i, id) in v.pre.as_str().split('.').enumerate() {
+                if i > 0 {
+                    // sorts below every identifier character, so that a
+                    // shorter list of identifiers comes first
+                    key.push(' ');
+                }
+                if id.bytes().all(|b| b.is_ascii_digit()) {
+                    // numeric identifiers have no leading zeros, so a longer
+                    // one is always greater
+                    key.push_str(&format!("0{:03}{}", id.len(), id));
+                } else {
+                    key.push('1');
+                    key.push_str(id);
+                }
+            }
+        }
+        key
+    }
+}
+
+/// Serialize `VersionKey` to sortable text.
+impl ToSql for VersionKey<'_> {
+    #[inline]
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        Ok(ToSqlOutput::from(self.key()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::VersionKey;
+    use crate::types::InvalidVersion;
+    use crate::{Connection, Error, Result};
+    use semver::Version;
+
+    fn checked_memory_handle() -> Result<Connection> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE versions (v TEXT, k TEXT)")?;
+        Ok(db)
+    }
+
+    #[test]
+    fn test_sql_version() -> Result<()> {
+        let db = checked_memory_handle()?;
+        for s in &[
+            "1.2.3",
+            "0.1.0-alpha.1",
+            "1.0.0+build.5",
+            "2.0.0-rc.1+sha.abc",
+        ] {
+            let v = Version::parse(s).unwrap();
+            db.execute("INSERT INTO versions (v) VALUES (?)", [&v])?;
+            let stored: String = db.query_row("SELECT v FROM versions", [], |r| r.get(0))?;
+            assert_eq!(stored, *s);
+            let read: Version = db.query_row("SELECT v FROM versions", [], |r| r.get(0))?;
+            assert_eq!(read, v);
+            db.execute("DELETE FROM versions", [])?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_version() -> Result<()> {
+        let db = checked_memory_handle()?;
+        let err = db
+            .query_row("SELECT '1.2'", [], |r| r.get::<_, Version>(0))
+            .unwrap_err();
+        match err {
+            Error::FromSqlConversionFailure(_, _, e) => {
+                let e = e.downcast::<InvalidVersion>().unwrap();
+                assert_eq!(e.input(), "1.2");
+                assert!(e.to_string().starts_with("invalid version \"1.2\""));
+            }
+            e => panic!("Expected conversion failure, got {}", e),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_version_key_order() -> Result<()> {
+        // in increasing precedence, from the semver specification
+        let ordered = [
+            "0.9.0",
+            "1.0.0-alpha",
+            "1.0.0-alpha.1",
+            "1.0.0-alpha.beta",
+            "1.0.0-beta",
+            "1.0.0-beta.2",
+            "1.0.0-beta.11",
+            "1.0.0-rc.1",
+            "1.0.0",
+            "1.2.0",
+            "1.10.0",
+            "10.0.0",
+        ];
+        let db = checked_memory_handle()?;
+        for s in ordered.iter().rev() {
+            let v = Version::parse(s).unwrap();
+            db.execute(
+                "INSERT INTO versions (v, k) VALUES (?1, ?2)",
+                (&v, VersionKey(&v)),
+            )?;
+        }
+        let mut stmt = db.prepare("SELECT v FROM versions ORDER BY k")?;
+        let sorted = stmt
+            .query_map([], |r| r.get::<_, String>(0))?
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(sorted, ordered);
+
+        let a = Version::parse("1.0.0+a").unwrap();
+        let b = Version::parse("1.0.0+b").unwrap();
+        assert_eq!(VersionKey(&a).key(), VersionKey(&b).key());
+        Ok(())
+    }
+}
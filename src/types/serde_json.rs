@@ -1,70 +1,11 @@
-//! [`ToSql`] and [`FromSql`] implementation for JSON `Value`.
-
-use serde_json::{Number, Value};
-
-use crate::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
-use crate::{Error, Result};
-
-/// Serialize JSON `Value` to text:
-///
-///
-/// | JSON   | SQLite    |
-/// |----------|---------|
-/// | Null     | NULL    |
-/// | Bool     | 'true' / 'false' |
-/// | Number   | INT or REAL except u64 |
-/// | _ | TEXT |
-impl ToSql for Value {
-    #[inline]
-    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
-        match self {
-            Value::Null => Ok(ToSqlOutput::Borrowed(ValueRef::Null)),
-            Value::Number(n) if n.is_i64() => Ok(ToSqlOutput::from(n.as_i64().unwrap())),
-            Value::Number(n) if n.is_f64() => Ok(ToSqlOutput::from(n.as_f64().unwrap())),
-            _ => serde_json::to_string(self)
-                .map(ToSqlOutput::from)
-                .map_err(|err| Error::ToSqlConversionFailure(err.into())),
-        }
-    }
-}
-
-/// Deserialize SQLite value to JSON `Value`:
-///
-/// | SQLite   | JSON    |
-/// |----------|---------|
-/// | NULL     | Null    |
-/// | 'null'   | Null    |
-/// | 'true'   | Bool    |
-/// | 1        | Number  |
-/// | 0.1      | Number  |
-/// | '"text"' | String  |
-/// | 'text'   | _Error_ |
-/// | '[0, 1]' | Array   |
-/// | '{"x": 1}' | Object  |
-impl FromSql for Value {
-    #[inline]
-    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
-        match value {
-            ValueRef::Text(s) => serde_json::from_slice(s), // KO for b"text"
-            ValueRef::Blob(b) => serde_json::from_slice(b),
-            ValueRef::Integer(i) => Ok(Value::Number(Number::from(i))),
-            ValueRef::Real(f) => {
-                match Number::from_f64(f) {
-                    Some(n) => Ok(Value::Number(n)),
-                    _ => return Err(FromSqlError::InvalidType), // FIXME
-                }
-            }
-            ValueRef::Null => Ok(Value::Null),
-        }
-        .map_err(|err| FromSqlError::Other(Box::new(err)))
-    }
-}
-
+//! Round trips through SQLite of the JSON `Value` conversions of `rusqlite-types`.
 #[cfg(test)]
 mod test {
-    use crate::types::ToSql;
-    use crate::{Connection, Result};
+    use crate::types::{Json, JsonError, ToSql};
+    use crate::{Connection, Error, Result};
+    use serde::Deserialize;
     use serde_json::{Number, Value};
+    use std::collections::HashMap;
 
     fn checked_memory_handle() -> Result<Connection> {
         let db = Connection::open_in_memory()?;
@@ -132,4 +73,133 @@ mod test {
         assert!(v.is_err());
         Ok(())
     }
+
+    #[derive(Debug, Default, Deserialize, PartialEq)]
+    struct Item {
+        name: String,
+        qty: i64,
+    }
+
+    fn items() -> Result<Connection> {
+        let db = Connection::open_in_memory()?;
+        db.execute_batch(
+            "CREATE TABLE items (name TEXT, qty INTEGER);
+             INSERT INTO items VALUES ('a', 1), ('b', 2), ('c', 3);",
+        )?;
+        Ok(db)
+    }
+
+    fn json_error(err: Error) -> JsonError {
+        match err {
+            Error::FromSqlConversionFailure(_, _, err) => {
+                err.downcast_ref::<JsonError>().unwrap().clone()
+            }
+            err => panic!("unexpected error {}", err),
+        }
+    }
+
+    #[test]
+    fn test_json_group_array() -> Result<()> {
+        let db = items()?;
+        let Json(items): Json<Vec<Item>> = db.one_column(
+            "SELECT json_group_array(json_object('name', name, 'qty', qty)) FROM items",
+        )?;
+        assert_eq!(items.len(), 3);
+        assert_eq!(
+            items[1],
+            Item {
+                name: "b".to_owned(),
+                qty: 2
+            }
+        );
+
+        let qty: HashMap<String, i64> = db.query_row(
+            "SELECT json_group_object(name, qty) FROM items",
+            [],
+            |row| row.get_json(0),
+        )?;
+        assert_eq!(qty["c"], 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_error_pointer() -> Result<()> {
+        let db = items()?;
+        db.execute_batch("UPDATE items SET qty = 'many' WHERE name = 'c'")?;
+        let err = db
+            .query_row(
+                "SELECT json_group_array(json_object('name', name, 'qty', qty)) FROM items",
+                [],
+                |row| row.get_json::<_, Vec<Item>>(0),
+            )
+            .unwrap_err();
+        let err = json_error(err);
+        assert_eq!(err.pointer(), "/2/qty");
+        assert!(err.to_string().ends_with(" at /2/qty"), "{}", err);
+
+        // a missing field is reported on its object
+        let err = db
+            .query_row("SELECT json_array(json_object('name', 'a'))", [], |row| {
+                row.get_json::<_, Vec<Item>>(0)
+            })
+            .unwrap_err();
+        assert_eq!(json_error(err).pointer(), "/0");
+
+        // keys are escaped
+        let err = db
+            .query_row("SELECT json_object('a/b~', 'x')", [], |row| {
+                row.get_json::<_, HashMap<String, i64>>(0)
+            })
+            .unwrap_err();
+        assert_eq!(json_error(err).pointer(), "/a~1b~0");
+        Ok(())
+    }
+
+    #[test]
+    fn test_jsonb() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        // [{"name":"a","qty":2}]
+        let jsonb: &[u8] = b"\xcb\x0f\xcc\x0d\x47name\x17a\x37qty\x132";
+        let items: Vec<Item> = db.query_row("SELECT ?1", [jsonb], |row| row.get_json(0))?;
+        assert_eq!(
+            items,
+            [Item {
+                name: "a".to_owned(),
+                qty: 2
+            }]
+        );
+        // [0x1F, "\u00e9\n", null, true, 1.5]
+        let jsonb: &[u8] = b"\xcb\x15\x440x1F\xc8\x08\\u00e9\\n\x00\x01\x351.5";
+        let value: Value = db.query_row("SELECT ?1", [jsonb], |row| row.get_json(0))?;
+        assert_eq!(value, serde_json::json!([31, "\u{e9}\n", null, true, 1.5]));
+
+        // JSON text in a BLOB
+        let items: Vec<Item> = db.query_row(
+            "SELECT CAST('[{\"name\": \"b\", \"qty\": 3}]' AS BLOB)",
+            [],
+            |row| row.get_json(0),
+        )?;
+        assert_eq!(items[0].qty, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_null() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        let err = db
+            .query_row("SELECT NULL", [], |row| row.get_json::<_, Vec<Item>>(0))
+            .unwrap_err();
+        assert!(
+            matches!(err, Error::FromSqlConversionFailure(..)),
+            "{}",
+            err
+        );
+        let Json(items): Json<Vec<Item>, true> = db.one_column("SELECT NULL")?;
+        assert!(items.is_empty());
+        let Json(map): Json<HashMap<String, Item>, true> = db.one_column("SELECT NULL")?;
+        assert!(map.is_empty());
+        let items: Option<Vec<Item>> = db.query_row("SELECT NULL", [], |row| row.get_json(0))?;
+        assert_eq!(items, None);
+        Ok(())
+    }
 }
@@ -1,76 +1,130 @@
-//! [`ToSql`] and [`FromSql`] implementation for [`time::OffsetDateTime`].
-use crate::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
-use crate::{Error, Result};
-use time::format_description::well_known::Rfc3339;
-use time::format_description::FormatItem;
-use time::macros::format_description;
-use time::{OffsetDateTime, PrimitiveDateTime, UtcOffset};
-
-const PRIMITIVE_SHORT_DATE_TIME_FORMAT: &[FormatItem<'_>] =
-    format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
-const PRIMITIVE_DATE_TIME_FORMAT: &[FormatItem<'_>] =
-    format_description!("[year]-[month]-[day] [hour]:[minute]:[second].[subsecond]");
-const PRIMITIVE_DATE_TIME_Z_FORMAT: &[FormatItem<'_>] =
-    format_description!("[year]-[month]-[day] [hour]:[minute]:[second].[subsecond]Z");
-const OFFSET_SHORT_DATE_TIME_FORMAT: &[FormatItem<'_>] = format_description!(
-    "[year]-[month]-[day] [hour]:[minute]:[second][offset_hour sign:mandatory]:[offset_minute]"
-);
-const OFFSET_DATE_TIME_FORMAT: &[FormatItem<'_>] = format_description!(
-    "[year]-[month]-[day] [hour]:[minute]:[second].[subsecond][offset_hour sign:mandatory]:[offset_minute]"
-);
-const LEGACY_DATE_TIME_FORMAT: &[FormatItem<'_>] = format_description!(
-    "[year]-[month]-[day] [hour]:[minute]:[second]:[subsecond] [offset_hour sign:mandatory]:[offset_minute]"
-);
-
-impl ToSql for OffsetDateTime {
-    #[inline]
-    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
-        // FIXME keep original offset
-        let time_string = self
-            .to_offset(UtcOffset::UTC)
-            .format(&PRIMITIVE_DATE_TIME_Z_FORMAT)
-            .map_err(|err| Error::ToSqlConversionFailure(err.into()))?;
-        Ok(ToSqlOutput::from(time_string))
-    }
+//! [`time::OffsetDateTime`] values stored as TEXT, and the migration of
+//! those written in the legacy `YYYY-MM-DD HH:MM:SS:SSS [+-]HH:MM` format.
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result};
+//! fn migrate(conn: &Connection) -> Result<()> {
+//!     let migration = conn.migrate_datetime_column("event", "created_at", 1000)?;
+//!     if migration.unparseable > 0 {
+//!         eprintln!("not dates: rowids {:?}", migration.unparseable_rowids);
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use std::convert::TryFrom;
+
+use time::OffsetDateTime;
+
+pub use rusqlite_types::time::is_legacy_format;
+
+use crate::pragma::Sql;
+use crate::types::{FromSql, ToSql, Value, ValueRef};
+use crate::{params, Connection, Result, Savepoint};
+
+/// The most rowids of unparseable values kept in a [`DateTimeMigration`].
+pub const MAX_UNPARSEABLE_ROWIDS: usize = 100;
+
+/// What a [`Connection::migrate_datetime_column`] did.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DateTimeMigration {
+    /// The number of values rewritten from the legacy format.
+    pub rewritten: u64,
+    /// The number of values which are not dates, left as they were.
+    pub unparseable: u64,
+    /// The rowids of the first [`MAX_UNPARSEABLE_ROWIDS`] unparseable
+    /// values.
+    pub unparseable_rowids: Vec<i64>,
+    /// The number of transactions committed.
+    pub chunks: u64,
 }
 
-impl FromSql for OffsetDateTime {
-    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
-        value.as_str().and_then(|s| {
-            if s.len() > 10 && s.as_bytes()[10] == b'T' {
-                // YYYY-MM-DDTHH:MM:SS.SSS[+-]HH:MM
-                return OffsetDateTime::parse(s, &Rfc3339)
-                    .map_err(|err| FromSqlError::Other(Box::new(err)));
-            }
-            let s = s.strip_suffix('Z').unwrap_or(s);
-            match s.len() {
-                len if len <= 19 => {
-                    // TODO YYYY-MM-DDTHH:MM:SS
-                    PrimitiveDateTime::parse(s, &PRIMITIVE_SHORT_DATE_TIME_FORMAT)
-                        .map(PrimitiveDateTime::assume_utc)
+impl Connection {
+    /// Rewrite the values of `column` in `table` stored in the legacy
+    /// `YYYY-MM-DD HH:MM:SS:SSS [+-]HH:MM` format, see [`is_legacy_format`],
+    /// to the format written by the [`ToSql`] implementation of
+    /// [`OffsetDateTime`], which reads them back as the same instant.
+    ///
+    /// The table is scanned in rowid order, `batch_size` rows at a time, each
+    /// batch being rewritten in its own transaction. NULLs and values in the
+    /// other formats are left as they are, and so are values which
+    /// [`FromSql`] would not read as an [`OffsetDateTime`], which are
+    /// counted as unparseable. Running it again on a migrated column changes
+    /// nothing.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the table has no rowid or the column does not
+    /// exist, or if a batch fails, in which case the previous batches stay
+    /// committed.
+    pub fn migrate_datetime_column(
+        &self,
+        table: &str,
+        column: &str,
+        batch_size: usize,
+    ) -> Result<DateTimeMigration> {
+        let mut table_sql = Sql::new();
+        table_sql.push_identifier(table);
+        let mut column_sql = Sql::new();
+        column_sql.push_identifier(column);
+        let (table, column) = (table_sql.as_str(), column_sql.as_str());
+        let mut select = self.prepare(&format!(
+            "SELECT rowid, {column} FROM {table} WHERE rowid > ?1 ORDER BY rowid LIMIT ?2"
+        ))?;
+        let mut update = self.prepare(&format!(
+            "UPDATE {table} SET {column} = ?1 WHERE rowid = ?2"
+        ))?;
+
+        let batch_size = i64::try_from(batch_size.max(1)).unwrap_or(i64::MAX);
+        let mut migration = DateTimeMigration::default();
+        let mut last_rowid = i64::MIN;
+        loop {
+            let sp = Savepoint::with_depth(self, 0)?;
+            let batch = select
+                .query_map(params![last_rowid, batch_size], |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, Value>(1)?))
+                })?
+                .collect::<Result<Vec<_>>>()?;
+            let last = match batch.last() {
+                Some(&(rowid, _)) => rowid,
+                None => return Ok(migration),
+            };
+            for (rowid, value) in batch {
+                let value = ValueRef::from(&value);
+                if value == ValueRef::Null {
+                    continue;
                 }
-                _ if s.as_bytes()[19] == b':' => {
-                    // legacy
-                    OffsetDateTime::parse(s, &LEGACY_DATE_TIME_FORMAT)
+                let datetime = match OffsetDateTime::column_result(value) {
+                    Ok(datetime) => datetime,
+                    Err(_) => {
+                        migration.unparseable += 1;
+                        if migration.unparseable_rowids.len() < MAX_UNPARSEABLE_ROWIDS {
+                            migration.unparseable_rowids.push(rowid);
+                        }
+                        continue;
+                    }
+                };
+                if value.as_str().is_ok_and(is_legacy_format) {
+                    update.execute(params![datetime.to_sql()?, rowid])?;
+                    migration.rewritten += 1;
                 }
-                _ if s.as_bytes()[19] == b'.' => OffsetDateTime::parse(s, &OFFSET_DATE_TIME_FORMAT)
-                    .or_else(|err| {
-                        PrimitiveDateTime::parse(s, &PRIMITIVE_DATE_TIME_FORMAT)
-                            .map(PrimitiveDateTime::assume_utc)
-                            .map_err(|_| err)
-                    }),
-                _ => OffsetDateTime::parse(s, &OFFSET_SHORT_DATE_TIME_FORMAT),
             }
-            .map_err(|err| FromSqlError::Other(Box::new(err)))
-        })
+            sp.commit()?;
+            migration.chunks += 1;
+            last_rowid = last;
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
+    use super::{is_legacy_format, MAX_UNPARSEABLE_ROWIDS};
+    use crate::types::{ToSql, ToSqlOutput, Value};
     use crate::{Connection, Result};
     use time::format_description::well_known::Rfc3339;
-    use time::OffsetDateTime;
+    use time::macros::format_description;
+    use time::{OffsetDateTime, UtcOffset};
 
     #[test]
     fn test_offset_date_time() -> Result<()> {
@@ -164,4 +218,120 @@ mod test {
         result.unwrap();
         Ok(())
     }
+
+    #[test]
+    fn test_is_legacy_format() {
+        assert!(is_legacy_format("2013-10-07 08:23:19:120 +00:00"));
+        assert!(is_legacy_format("2013-10-07 04:23:19:120 -04:00"));
+        assert!(!is_legacy_format("2013-10-07 08:23:19.120Z"));
+        assert!(!is_legacy_format("2013-10-07T08:23:19.120Z"));
+        assert!(!is_legacy_format("2013-10-07 08:23:19"));
+        // shaped like it, but not a date
+        assert!(!is_legacy_format("2013-13-07 08:23:19:120 +00:00"));
+        assert!(!is_legacy_format("garbage"));
+    }
+
+    fn seed(db: &Connection) -> Result<()> {
+        db.execute_batch(
+            "CREATE TABLE event (id INTEGER PRIMARY KEY, at);
+             INSERT INTO event (at) VALUES
+                 ('2013-10-07 08:23:19:120 +00:00'),
+                 ('2013-10-07 08:23:19.120Z'),
+                 ('garbage'),
+                 ('2013-10-07 04:23:19:5 -04:00'),
+                 (NULL),
+                 ('2013-10-07T04:23:19.120-04:00'),
+                 ('2013-13-07 08:23:19:120 +00:00'),
+                 (42),
+                 ('2020-02-29 23:59:59:999999999 +05:30');",
+        )
+    }
+
+    #[test]
+    fn test_migrate_datetime_column() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        seed(&db)?;
+        let read = |db: &Connection| -> Result<Vec<(Value, Option<OffsetDateTime>)>> {
+            let mut stmt = db.prepare("SELECT at FROM event ORDER BY id")?;
+            let rows = stmt.query_map([], |r| Ok((r.get(0)?, r.get(0).ok())))?;
+            rows.collect()
+        };
+        let before = read(&db)?;
+
+        let migration = db.migrate_datetime_column("event", "at", 2)?;
+        assert_eq!(migration.rewritten, 3);
+        assert_eq!(migration.unparseable, 3);
+        assert_eq!(migration.unparseable_rowids, [3, 7, 8]);
+        assert_eq!(migration.chunks, 5);
+
+        let after = read(&db)?;
+        for ((old, old_t), (new, new_t)) in before.iter().zip(&after) {
+            // the same instants are read back
+            assert_eq!(old_t, new_t);
+            match old {
+                Value::Text(s) if is_legacy_format(s) => {
+                    let canonical = match new {
+                        Value::Text(s) => s,
+                        _ => panic!("{:?}", new),
+                    };
+                    assert!(!is_legacy_format(canonical));
+                    assert!(canonical.ends_with('Z'), "{}", canonical);
+                }
+                _ => assert_eq!(old, new),
+            }
+        }
+        assert_eq!(
+            after[0].0,
+            Value::Text("2013-10-07 08:23:19.12Z".to_owned())
+        );
+
+        // nothing is left to migrate
+        let migration = db.migrate_datetime_column("event", "at", 100)?;
+        assert_eq!(migration.rewritten, 0);
+        assert_eq!(migration.unparseable, 3);
+        assert_eq!(read(&db)?, after);
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_datetime_column_errors() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        seed(&db)?;
+        db.migrate_datetime_column("event", "missing", 10)
+            .unwrap_err();
+        db.migrate_datetime_column("missing", "at", 10).unwrap_err();
+
+        db.execute_batch(
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 200)
+             INSERT INTO event (at) SELECT 'not a date' FROM n;",
+        )?;
+        let migration = db.migrate_datetime_column("event", "at", 0)?;
+        assert_eq!(migration.unparseable, 203);
+        assert_eq!(migration.unparseable_rowids.len(), MAX_UNPARSEABLE_ROWIDS);
+        Ok(())
+    }
+
+    #[test]
+    fn test_same_as_format() {
+        let format =
+            format_description!("[year]-[month]-[day] [hour]:[minute]:[second].[subsecond]Z");
+        for &nanos in &[
+            0i128,
+            1,
+            1_000,
+            120_000_000,
+            1_234_567_891_011_121_314,
+            -62_135_596_800_000_000_000,
+            253_402_300_799_999_999_999,
+        ] {
+            let ts = OffsetDateTime::from_unix_timestamp_nanos(nanos)
+                .unwrap()
+                .to_offset(UtcOffset::from_hms(-7, -30, 0).unwrap());
+            let text = match ts.to_sql().unwrap() {
+                ToSqlOutput::Inline(text) => text.as_str().to_owned(),
+                output => panic!("Unexpected output {:?}", output),
+            };
+            assert_eq!(text, ts.to_offset(UtcOffset::UTC).format(&format).unwrap());
+        }
+    }
 }
@@ -3,9 +3,11 @@
 use std::os::raw::c_int;
 use std::os::raw::c_void;
 use std::panic::catch_unwind;
+use std::ptr;
 use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
 
-use crate::ffi;
+use crate::{ffi, Connection, Error, Result};
 
 struct UnlockNotification {
     cond: Condvar,      // Condition variable to wait on
@@ -27,11 +29,27 @@ impl UnlockNotification {
         self.cond.notify_one();
     }
 
-    fn wait(&self) {
+    // Returns whether the notification fired before the timeout.
+    fn wait(&self, timeout: Option<Duration>) -> bool {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
         let mut fired = unpoison(self.mutex.lock());
         while !*fired {
-            fired = unpoison(self.cond.wait(fired));
+            fired = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return false;
+                    }
+                    unpoison(self.cond.wait_timeout(fired, deadline - now)).0
+                }
+                None => unpoison(self.cond.wait(fired)),
+            };
         }
+        true
+    }
+
+    fn has_fired(&self) -> bool {
+        *unpoison(self.mutex.lock())
     }
 }
 
@@ -61,14 +79,20 @@ pub unsafe fn is_locked(db: *mut ffi::sqlite3, rc: c_int) -> bool {
 ///
 /// This function calls `sqlite3_unlock_notify()` to register for an
 /// unlock-notify callback, then blocks until that callback is delivered
-/// and returns `SQLITE_OK`. The caller should then retry the failed operation.
+/// and returns `Ok(true)`. The caller should then retry the failed operation.
 ///
 /// Or, if `sqlite3_unlock_notify()` indicates that to block would deadlock
-/// the system, then this function returns `SQLITE_LOCKED` immediately. In
-/// this case the caller should not retry the operation and should roll
-/// back the current transaction (if any).
-#[cfg(feature = "unlock_notify")]
-pub unsafe fn wait_for_unlock_notify(db: *mut ffi::sqlite3) -> c_int {
+/// the system, then this function returns `Error::UnlockNotifyDeadlock`
+/// immediately. In this case the caller should not retry the operation and
+/// should roll back the current transaction (if any).
+///
+/// If the callback is not delivered within `timeout`, it is cancelled and
+/// this function returns `Ok(false)`: the caller should report the error of
+/// the failed operation.
+pub unsafe fn wait_for_unlock_notify(
+    db: *mut ffi::sqlite3,
+    timeout: Option<Duration>,
+) -> Result<bool> {
     let un = UnlockNotification::new();
     /* Register for an unlock-notify callback. */
     let rc = ffi::sqlite3_unlock_notify(
@@ -79,18 +103,50 @@ pub unsafe fn wait_for_unlock_notify(db: *mut ffi::sqlite3) -> c_int {
     debug_assert!(
         rc == ffi::SQLITE_LOCKED || rc == ffi::SQLITE_LOCKED_SHAREDCACHE || rc == ffi::SQLITE_OK
     );
-    if rc == ffi::SQLITE_OK {
-        un.wait();
+    if rc != ffi::SQLITE_OK {
+        return Err(Error::UnlockNotifyDeadlock);
+    }
+    if un.wait(timeout) {
+        return Ok(true);
+    }
+    // SQLite invokes the callbacks under the mutex taken to cancel ours, so
+    // `un` is no longer referenced once this returns.
+    ffi::sqlite3_unlock_notify(db, None, ptr::null_mut());
+    Ok(un.has_fired())
+}
+
+impl Connection {
+    /// Set the longest time a statement waits for a lock held by another
+    /// connection to the same shared cache to be released, `None` meaning
+    /// no limit, which is the default.
+    ///
+    /// Past that time, the statement fails with
+    /// [`ErrorCode::DatabaseLocked`](crate::ErrorCode::DatabaseLocked) as it
+    /// would without the `unlock_notify` feature. If waiting would deadlock,
+    /// it fails right away with
+    /// [`Error::UnlockNotifyDeadlock`](crate::Error::UnlockNotifyDeadlock).
+    #[cfg_attr(docsrs, doc(cfg(feature = "unlock_notify")))]
+    #[inline]
+    pub fn set_unlock_notify_timeout(&self, timeout: Option<Duration>) {
+        self.db.borrow_mut().unlock_notify_timeout = timeout;
     }
-    rc
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{Connection, OpenFlags, Result, Transaction, TransactionBehavior};
+    use crate::{
+        Connection, Error, ErrorCode, OpenFlags, Result, Transaction, TransactionBehavior,
+    };
     use std::sync::mpsc::sync_channel;
     use std::thread;
-    use std::time;
+    use std::time::{self, Duration, Instant};
+
+    fn open_shared(name: &str) -> Result<Connection> {
+        let flags = OpenFlags::SQLITE_OPEN_READ_WRITE
+            | OpenFlags::SQLITE_OPEN_CREATE
+            | OpenFlags::SQLITE_OPEN_URI;
+        Connection::open_with_flags(format!("file:{name}?mode=memory&cache=shared"), flags)
+    }
 
     #[test]
     fn test_unlock_notify() -> Result<()> {
@@ -114,4 +170,79 @@ mod test {
         child.join().unwrap();
         Ok(())
     }
+
+    #[test]
+    fn test_blocked_writer() -> Result<()> {
+        let reader = open_shared("unlock_notify_writer")?;
+        reader.execute_batch("CREATE TABLE foo (x); INSERT INTO foo VALUES (1); BEGIN;")?;
+        // the read lock on foo is held until the transaction ends
+        let count: i64 = reader.one_column("SELECT count(*) FROM foo")?;
+        assert_eq!(count, 1);
+
+        let (started, waiting) = sync_channel(0);
+        let writer = thread::spawn(move || -> Result<Instant> {
+            let db = open_shared("unlock_notify_writer")?;
+            started.send(()).unwrap();
+            db.execute("INSERT INTO foo VALUES (2)", [])?;
+            Ok(Instant::now())
+        });
+        waiting.recv().unwrap();
+        thread::sleep(Duration::from_millis(50));
+        let released = Instant::now();
+        reader.execute_batch("COMMIT")?;
+        assert!(writer.join().unwrap()? >= released);
+        let count: i64 = reader.one_column("SELECT count(*) FROM foo")?;
+        assert_eq!(count, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unlock_notify_timeout() -> Result<()> {
+        let reader = open_shared("unlock_notify_timeout")?;
+        reader.execute_batch("CREATE TABLE foo (x); BEGIN;")?;
+        let count: i64 = reader.one_column("SELECT count(*) FROM foo")?;
+        assert_eq!(count, 0);
+
+        let writer = open_shared("unlock_notify_timeout")?;
+        writer.set_unlock_notify_timeout(Some(Duration::from_millis(50)));
+        let start = Instant::now();
+        let err = writer
+            .execute("INSERT INTO foo VALUES (1)", [])
+            .unwrap_err();
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::DatabaseLocked));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        reader.execute_batch("COMMIT")?;
+        writer.execute("INSERT INTO foo VALUES (1)", [])?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_unlock_notify_deadlock() -> Result<()> {
+        let db = open_shared("unlock_notify_deadlock")?;
+        db.execute_batch("CREATE TABLE t1 (x); CREATE TABLE t2 (x); BEGIN;")?;
+        db.execute("INSERT INTO t2 VALUES (1)", [])?;
+
+        let (started, waiting) = sync_channel(0);
+        let other = thread::spawn(move || -> Result<()> {
+            let db = open_shared("unlock_notify_deadlock")?;
+            db.execute_batch("BEGIN")?;
+            let count: i64 = db.one_column("SELECT count(*) FROM t1")?;
+            assert_eq!(count, 0);
+            started.send(()).unwrap();
+            // waits for the write transaction of the main thread
+            db.execute("INSERT INTO t2 VALUES (2)", [])?;
+            db.execute_batch("COMMIT")
+        });
+        waiting.recv().unwrap();
+        thread::sleep(Duration::from_millis(50));
+        // waits for the read lock of the other thread on t1
+        let err = db.execute("INSERT INTO t1 VALUES (1)", []).unwrap_err();
+        assert_eq!(err, Error::UnlockNotifyDeadlock);
+        db.execute_batch("ROLLBACK")?;
+        other.join().unwrap()?;
+        let count: i64 = db.one_column("SELECT count(*) FROM t2")?;
+        assert_eq!(count, 1);
+        Ok(())
+    }
 }
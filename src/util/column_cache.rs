@@ -0,0 +1,80 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// Maps column names, as they were looked up, to column indices.
+#[derive(Default, Clone, Debug)]
+pub(crate) struct ColumnIndexCache(RefCell<ColumnIndexes>);
+
+#[derive(Default, Clone, Debug)]
+struct ColumnIndexes {
+    // Re-prepare count of the statement when the indices were found: a
+    // re-prepared statement may have other columns.
+    reprepares: i32,
+    indices: HashMap<Box<str>, usize>,
+}
+
+impl ColumnIndexCache {
+    /// Returns the index cached for `name` if the statement was not
+    /// re-prepared since and `is_valid` agrees, or else the one `func` finds.
+    pub fn get_or_insert_with<V, F>(
+        &self,
+        name: &str,
+        reprepares: i32,
+        is_valid: V,
+        func: F,
+    ) -> Option<usize>
+    where
+        V: FnOnce(usize) -> bool,
+        F: FnOnce() -> Option<usize>,
+    {
+        let mut cache = self.0.borrow_mut();
+        if cache.reprepares != reprepares {
+            cache.indices.clear();
+            cache.reprepares = reprepares;
+        }
+        match cache.indices.get(name) {
+            Some(&i) if is_valid(i) => return Some(i),
+            Some(_) => cache.indices.clear(),
+            None => {}
+        }
+        let i = func()?;
+        cache.indices.insert(name.into(), i);
+        Some(i)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_cache() {
+        let c = ColumnIndexCache::default();
+        assert_eq!(
+            c.get_or_insert_with("foo", 0, |_| true, || Some(3)),
+            Some(3)
+        );
+        let v = c.get_or_insert_with(
+            "foo",
+            0,
+            |_| true,
+            || {
+                panic!("shouldn't be called this time");
+            },
+        );
+        assert_eq!(v, Some(3));
+        // re-prepared or no longer valid
+        assert_eq!(
+            c.get_or_insert_with("foo", 1, |_| true, || Some(4)),
+            Some(4)
+        );
+        assert_eq!(
+            c.get_or_insert_with("foo", 1, |i| i != 4, || Some(5)),
+            Some(5)
+        );
+        assert_eq!(c.get_or_insert_with("bar", 1, |_| true, || None), None);
+        assert_eq!(
+            c.get_or_insert_with("bar", 1, |_| true, || Some(0)),
+            Some(0)
+        );
+    }
+}
@@ -1,6 +1,8 @@
 // Internal utilities
+pub(crate) mod column_cache;
 pub(crate) mod param_cache;
 mod small_cstr;
+pub(crate) use column_cache::ColumnIndexCache;
 pub(crate) use param_cache::ParamIndexCache;
 pub(crate) use small_cstr::SmallCString;
 
@@ -0,0 +1,147 @@
+//! SQL functions generating and converting UUIDs.
+//!
+//! UUIDs are bound and read as 16-byte BLOBs by the `uuid` feature, which
+//! [`Connection::register_uuid_functions`] lets SQL produce and display.
+
+use uuid::Uuid;
+
+use crate::functions::{Context, FunctionFlags};
+use crate::types::ValueRef;
+use crate::{Connection, Error, Result};
+
+impl Connection {
+    /// Register the following functions:
+    ///
+    /// * `uuid4()` returns a random (version 4) UUID as a 16-byte BLOB,
+    /// * `uuid_str(X)` returns the UUID `X`, a 16-byte BLOB or any text form
+    ///   understood by [`Uuid::parse_str`], as hyphenated lower case TEXT,
+    /// * `uuid_blob(X)` returns the UUID `X` given as TEXT as a 16-byte BLOB.
+    ///
+    /// The conversions return NULL for NULL and are deterministic, while
+    /// `uuid4()` is not.
+    ///
+    /// ```rust
+    /// # use rusqlite::{Connection, Result};
+    /// fn create_user(conn: &Connection, name: &str) -> Result<String> {
+    ///     conn.register_uuid_functions()?;
+    ///     conn.query_row(
+    ///         "INSERT INTO user (id, name) VALUES (uuid4(), ?) RETURNING uuid_str(id)",
+    ///         [name],
+    ///         |row| row.get(0),
+    ///     )
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the functions could not be registered. An
+    /// invalid UUID fails the statement with an error naming its type.
+    pub fn register_uuid_functions(&self) -> Result<()> {
+        let flags = FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_INNOCUOUS;
+        self.create_scalar_function("uuid4", 0, flags, |_| {
+            Ok(Uuid::new_v4().as_bytes().to_vec())
+        })?;
+        let flags = flags | FunctionFlags::SQLITE_DETERMINISTIC;
+        self.create_scalar_function("uuid_str", 1, flags, |ctx| {
+            Ok(uuid_arg(ctx, "uuid_str", true)?.map(|uuid| uuid.hyphenated().to_string()))
+        })?;
+        self.create_scalar_function("uuid_blob", 1, flags, |ctx| {
+            Ok(uuid_arg(ctx, "uuid_blob", false)?.map(|uuid| uuid.as_bytes().to_vec()))
+        })
+    }
+}
+
+fn uuid_arg(ctx: &Context<'_>, name: &str, blob: bool) -> Result<Option<Uuid>> {
+    let value = ctx.get_raw(0);
+    let uuid = match value {
+        ValueRef::Null => return Ok(None),
+        ValueRef::Blob(b) if blob => Uuid::from_slice(b).ok(),
+        ValueRef::Text(t) => std::str::from_utf8(t)
+            .ok()
+            .and_then(|t| Uuid::parse_str(t).ok()),
+        _ => None,
+    };
+    match uuid {
+        Some(uuid) => Ok(Some(uuid)),
+        None => Err(Error::UserFunctionError(
+            format!("{name}: invalid UUID of type {}", value.data_type()).into(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use uuid::Uuid;
+
+    use crate::{Connection, Result};
+
+    fn db() -> Result<Connection> {
+        let db = Connection::open_in_memory()?;
+        db.register_uuid_functions()?;
+        Ok(db)
+    }
+
+    #[test]
+    fn test_uuid4() -> Result<()> {
+        let db = db()?;
+        let s: String = db.one_column("SELECT uuid_str(uuid4())")?;
+        let uuid = Uuid::parse_str(&s).unwrap();
+        assert_eq!(uuid.get_version_num(), 4);
+        assert_eq!(s, uuid.hyphenated().to_string());
+
+        // not deterministic: each row gets its own
+        let n: i64 = db.one_column("SELECT count(DISTINCT uuid4()) FROM (VALUES (1), (2), (3))")?;
+        assert_eq!(n, 3);
+        let uuid: Uuid = db.one_column("SELECT uuid4()")?;
+        assert_eq!(uuid.get_version_num(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_uuid_round_trip() -> Result<()> {
+        let db = db()?;
+        let uuid = Uuid::new_v4();
+        let s: String = db.query_row("SELECT uuid_str(?)", [uuid], |r| r.get(0))?;
+        assert_eq!(s, uuid.hyphenated().to_string());
+        let back: Uuid = db.query_row("SELECT uuid_blob(?)", [&s], |r| r.get(0))?;
+        assert_eq!(back, uuid);
+
+        // other text forms are normalized
+        let simple = uuid.simple().to_string().to_uppercase();
+        let s2: String = db.query_row("SELECT uuid_str(?)", [simple], |r| r.get(0))?;
+        assert_eq!(s2, s);
+        let same: bool =
+            db.one_column("SELECT uuid_blob(uuid_str(x)) = x FROM (SELECT uuid4() AS x)")?;
+        assert!(same);
+        Ok(())
+    }
+
+    #[test]
+    fn test_uuid_invalid() -> Result<()> {
+        let db = db()?;
+        let null: Option<String> = db.one_column("SELECT uuid_str(NULL)")?;
+        assert_eq!(null, None);
+        for (sql, msg) in [
+            (
+                "SELECT uuid_str(42)",
+                "uuid_str: invalid UUID of type Integer",
+            ),
+            (
+                "SELECT uuid_str(x'0102')",
+                "uuid_str: invalid UUID of type Blob",
+            ),
+            (
+                "SELECT uuid_blob('not a uuid')",
+                "uuid_blob: invalid UUID of type Text",
+            ),
+            (
+                "SELECT uuid_blob(uuid4())",
+                "uuid_blob: invalid UUID of type Blob",
+            ),
+        ] {
+            let err = db.one_column::<String>(sql).unwrap_err();
+            assert!(err.to_string().contains(msg), "{}: {}", sql, err);
+        }
+        Ok(())
+    }
+}
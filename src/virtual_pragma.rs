@@ -0,0 +1,355 @@
+//! Application-defined pragmas, answered by Rust closures.
+//!
+//! SQLite offers every `PRAGMA` to the file of the database it applies to,
+//! through the
+//! [`SQLITE_FCNTL_PRAGMA`](https://sqlite.org/c3ref/c_fcntl_begin_atomic_write.html#sqlitefcntlpragma)
+//! file control, before handling it itself. Registering a pragma wraps the
+//! I/O methods of the main database file, so that the pragmas registered
+//! are answered by their closure and all the others are passed through to
+//! the VFS.
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int, c_void};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::sync::Mutex;
+
+#[cfg(feature = "serialize")]
+use crate::serialize::DeserializeFlags;
+use crate::types::Value;
+use crate::util::SqliteMallocString;
+use crate::{ffi, Connection, Error, Result, MAIN_DB};
+
+type Pragma = Box<dyn Fn(Option<&str>) -> Result<Option<Value>> + Send + 'static>;
+
+// The I/O methods installed on the main database file: a copy of the
+// methods of the VFS, except for xFileControl and xClose. `methods` must
+// stay the first field, SQLite only handing back a pointer to it.
+#[repr(C)]
+struct Shim {
+    methods: ffi::sqlite3_io_methods,
+    original: *const ffi::sqlite3_io_methods,
+    pragmas: Mutex<HashMap<String, Pragma>>,
+}
+
+impl Shim {
+    // The shim installed on `file`, if any.
+    unsafe fn of(file: *mut ffi::sqlite3_file) -> Option<*mut Shim> {
+        let methods = (*file).pMethods;
+        if methods.is_null() {
+            return None;
+        }
+        match (*methods).xClose {
+            Some(close) if close as *const () == shim_close as *const () => {
+                Some(methods as *mut Shim)
+            }
+            _ => None,
+        }
+    }
+}
+
+unsafe extern "C" fn shim_close(file: *mut ffi::sqlite3_file) -> c_int {
+    let shim = Box::from_raw((*file).pMethods as *mut Shim);
+    (*file).pMethods = shim.original;
+    match (*shim.original).xClose {
+        Some(close) => close(file),
+        None => ffi::SQLITE_OK,
+    }
+}
+
+unsafe extern "C" fn shim_file_control(
+    file: *mut ffi::sqlite3_file,
+    op: c_int,
+    arg: *mut c_void,
+) -> c_int {
+    let shim = &*((*file).pMethods as *const Shim);
+    if op == ffi::SQLITE_FCNTL_PRAGMA {
+        // [error message or result, name, argument or NULL]
+        let args = arg as *mut *mut c_char;
+        let name = CStr::from_ptr(*args.add(1))
+            .to_string_lossy()
+            .to_lowercase();
+        let pragmas = shim.pragmas.lock().unwrap();
+        if let Some(pragma) = pragmas.get(&name) {
+            let value = *args.add(2);
+            let value = if value.is_null() {
+                None
+            } else {
+                Some(CStr::from_ptr(value).to_string_lossy())
+            };
+            let r = catch_unwind(AssertUnwindSafe(|| pragma(value.as_deref())));
+            let (rc, text) = match r {
+                Ok(Ok(value)) => match value_to_text(&name, value) {
+                    Ok(text) => (ffi::SQLITE_OK, text),
+                    Err(msg) => (ffi::SQLITE_ERROR, Some(msg)),
+                },
+                Ok(Err(Error::SqliteFailure(err, msg))) => (
+                    err.extended_code,
+                    Some(msg.unwrap_or_else(|| err.to_string())),
+                ),
+                Ok(Err(err)) => (ffi::SQLITE_ERROR, Some(err.to_string())),
+                Err(_) => (ffi::SQLITE_ERROR, Some(format!("pragma {name} panicked"))),
+            };
+            // without a result, the pragma returns no row
+            *args = text.map_or(ptr::null_mut(), |text| {
+                SqliteMallocString::from_str(&text).into_raw()
+            });
+            return rc;
+        }
+    }
+    match (*shim.original).xFileControl {
+        Some(file_control) => file_control(file, op, arg),
+        None => ffi::SQLITE_NOTFOUND,
+    }
+}
+
+// SQLite returns the result of a pragma as TEXT.
+fn value_to_text(name: &str, value: Option<Value>) -> std::result::Result<Option<String>, String> {
+    match value {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::Integer(i)) => Ok(Some(i.to_string())),
+        Some(Value::Real(f)) => Ok(Some(f.to_string())),
+        Some(Value::Text(s)) => Ok(Some(s)),
+        Some(Value::Blob(_)) => Err(format!("pragma {name} cannot return a BLOB")),
+    }
+}
+
+impl Connection {
+    /// Register `pragma` to answer `PRAGMA name;` and `PRAGMA name = arg;`
+    /// (or `PRAGMA name(arg);`) on the main database, replacing any previous
+    /// registration of `name`. The name is not case sensitive, and may
+    /// shadow a pragma of SQLite.
+    ///
+    /// `pragma` is called with the argument, if any, and its result is
+    /// returned as a single row of TEXT, or no row for `None` and
+    /// `Value::Null`. An error fails the statement.
+    ///
+    /// `pragma` is called when the statement is prepared, so a statement
+    /// from the [cache](Connection::prepare_cached) does not call it again.
+    ///
+    /// An in-memory or temporary main database has no file until it grows.
+    /// With the `serialize` feature, such a database is silently moved to the
+    /// [memdb](https://sqlite.org/c3ref/deserialize.html) VFS first, which
+    /// copies its content; without it, registering a pragma on it fails.
+    ///
+    /// ```rust
+    /// # use rusqlite::{Connection, Result};
+    /// # use rusqlite::types::Value;
+    /// fn register(conn: &Connection) -> Result<String> {
+    ///     conn.register_virtual_pragma("myapp_version", |_| {
+    ///         Ok(Some(Value::Text("1.2.0".to_owned())))
+    ///     })?;
+    ///     conn.query_row("PRAGMA myapp_version", [], |row| row.get(0))
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the main database has no file and cannot be moved
+    /// to memdb.
+    pub fn register_virtual_pragma<F>(&self, name: &str, pragma: F) -> Result<()>
+    where
+        F: Fn(Option<&str>) -> Result<Option<Value>> + Send + 'static,
+    {
+        let mut file = self.main_file()?;
+        if file.is_null() || unsafe { (*file).pMethods.is_null() } {
+            file = self.move_to_memdb()?;
+        }
+        let shim = match unsafe { Shim::of(file) } {
+            Some(shim) => shim,
+            None => unsafe {
+                let original = (*file).pMethods;
+                let mut methods = *original;
+                methods.xClose = Some(shim_close);
+                methods.xFileControl = Some(shim_file_control);
+                let shim = Box::into_raw(Box::new(Shim {
+                    methods,
+                    original,
+                    pragmas: Mutex::new(HashMap::new()),
+                }));
+                (*file).pMethods = shim.cast();
+                shim
+            },
+        };
+        let mut pragmas = unsafe { (*shim).pragmas.lock().unwrap() };
+        pragmas.insert(name.to_lowercase(), Box::new(pragma));
+        Ok(())
+    }
+
+    /// Remove the pragma `name` registered by
+    /// [`register_virtual_pragma`](Connection::register_virtual_pragma),
+    /// returning whether there was one. Once the last pragma is removed, the
+    /// I/O methods of the main database file are restored.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the main database file cannot be retrieved.
+    pub fn unregister_virtual_pragma(&self, name: &str) -> Result<bool> {
+        let file = self.main_file()?;
+        if file.is_null() {
+            return Ok(false);
+        }
+        let shim = match unsafe { Shim::of(file) } {
+            Some(shim) => shim,
+            None => return Ok(false),
+        };
+        let (removed, empty) = {
+            let mut pragmas = unsafe { (*shim).pragmas.lock().unwrap() };
+            let removed = pragmas.remove(&name.to_lowercase()).is_some();
+            (removed, pragmas.is_empty())
+        };
+        if empty {
+            unsafe {
+                let shim = Box::from_raw(shim);
+                (*file).pMethods = shim.original;
+            }
+        }
+        Ok(removed)
+    }
+
+    #[cfg(feature = "serialize")]
+    fn move_to_memdb(&self) -> Result<*mut ffi::sqlite3_file> {
+        let data = self.serialize(MAIN_DB)?;
+        self.deserialize_owned(MAIN_DB, data, DeserializeFlags::default())?;
+        self.main_file()
+    }
+
+    #[cfg(not(feature = "serialize"))]
+    fn move_to_memdb(&self) -> Result<*mut ffi::sqlite3_file> {
+        Err(Error::SqliteFailure(
+            ffi::Error::new(ffi::SQLITE_MISUSE),
+            Some(
+                "virtual pragmas on a database without a file need the serialize feature"
+                    .to_owned(),
+            ),
+        ))
+    }
+
+    fn main_file(&self) -> Result<*mut ffi::sqlite3_file> {
+        let name = MAIN_DB.as_cstring()?;
+        let mut file: *mut ffi::sqlite3_file = ptr::null_mut();
+        let c = self.db.borrow();
+        let r = unsafe {
+            ffi::sqlite3_file_control(
+                c.db(),
+                name.as_ptr(),
+                ffi::SQLITE_FCNTL_FILE_POINTER,
+                (&mut file as *mut *mut ffi::sqlite3_file).cast(),
+            )
+        };
+        c.decode_result(r)?;
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::types::Value;
+    use crate::{Connection, Result};
+
+    fn version(arg: Option<&str>) -> Result<Option<Value>> {
+        Ok(Some(match arg {
+            None => Value::Integer(3),
+            Some(arg) => Value::Text(format!("v{arg}")),
+        }))
+    }
+
+    fn file_db() -> Result<(tempfile::TempDir, Connection)> {
+        let temp = tempfile::tempdir().unwrap();
+        let db = Connection::open(temp.path().join("test.db3"))?;
+        Ok((temp, db))
+    }
+
+    #[test]
+    fn test_virtual_pragma() -> Result<()> {
+        let (_temp, db) = file_db()?;
+        db.execute_batch("CREATE TABLE foo(x)")?;
+        db.register_virtual_pragma("myapp_version", version)?;
+
+        let v: String = db.query_row("PRAGMA myapp_version", [], |r| r.get(0))?;
+        assert_eq!(v, "3");
+        let v: String = db.query_row("PRAGMA MyApp_Version = 7", [], |r| r.get(0))?;
+        assert_eq!(v, "v7");
+        let v: String = db.query_row("PRAGMA main.myapp_version('x')", [], |r| r.get(0))?;
+        assert_eq!(v, "vx");
+
+        // unknown pragmas are passed through
+        let mode: String = db.query_row("PRAGMA journal_mode", [], |r| r.get(0))?;
+        assert_eq!(mode, "delete");
+        db.execute_batch("PRAGMA not_a_pragma")?;
+        let n: i64 = db.query_row("SELECT count(*) FROM foo", [], |r| r.get(0))?;
+        assert_eq!(n, 0);
+        Ok(())
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn test_virtual_pragma_in_memory() -> Result<()> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let db = Connection::open_in_memory()?;
+        db.execute_batch("CREATE TABLE foo(x); INSERT INTO foo VALUES (1)")?;
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        db.register_virtual_pragma("myapp_flush_cache", move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(None)
+        })?;
+        db.register_virtual_pragma("myapp_version", version)?;
+
+        db.execute_batch("PRAGMA myapp_flush_cache")?;
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        let v: String = db.query_row("PRAGMA myapp_version", [], |r| r.get(0))?;
+        assert_eq!(v, "3");
+        // the content survives the move to memdb
+        let n: i64 = db.query_row("SELECT x FROM foo", [], |r| r.get(0))?;
+        assert_eq!(n, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_virtual_pragma_error() -> Result<()> {
+        let (_temp, db) = file_db()?;
+        db.register_virtual_pragma("myapp_fail", |_| {
+            Err(crate::Error::SqliteFailure(
+                crate::ffi::Error::new(crate::ffi::SQLITE_BUSY),
+                Some("cache is busy".to_owned()),
+            ))
+        })?;
+        let err = db.execute_batch("PRAGMA myapp_fail").unwrap_err();
+        assert!(err.to_string().contains("cache is busy"), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unregister_virtual_pragma() -> Result<()> {
+        let (_temp, db) = file_db()?;
+        db.register_virtual_pragma("myapp_version", version)?;
+        db.register_virtual_pragma("user_version", |_| Ok(Some(Value::Integer(42))))?;
+        let v: String = db.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+        assert_eq!(v, "42");
+
+        assert!(db.unregister_virtual_pragma("USER_VERSION")?);
+        assert!(!db.unregister_virtual_pragma("user_version")?);
+        let v: i64 = db.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+        assert_eq!(v, 0);
+
+        assert!(db.unregister_virtual_pragma("myapp_version")?);
+        // unknown pragmas are ignored by SQLite
+        let mut stmt = db.prepare("PRAGMA myapp_version")?;
+        assert!(stmt.query([])?.next()?.is_none());
+        Ok(())
+    }
+
+    #[cfg(not(feature = "serialize"))]
+    #[test]
+    fn test_virtual_pragma_in_memory_without_serialize() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        let err = db
+            .register_virtual_pragma("myapp_version", version)
+            .unwrap_err();
+        assert!(err.to_string().contains("serialize feature"), "{}", err);
+        Ok(())
+    }
+}
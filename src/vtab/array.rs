@@ -25,14 +25,40 @@
 //!     Ok(())
 //! }
 //! ```
+//!
+//! With `modern_sqlite`, the values can also be shared as an
+//! `Arc<Vec<Value>>`, bound with
+//! [`Statement::bind_pointer`](crate::Statement::bind_pointer) and
+//! [`POINTER_TYPE`]:
+//!
+//! ```rust,no_run
+//! # use rusqlite::{types::Value, vtab::array, Connection, Result};
+//! # use std::sync::Arc;
+//! fn example(db: &Connection, ids: Vec<i64>) -> Result<usize> {
+//!     array::load_module(&db)?;
+//!     let values = Arc::new(ids.into_iter().map(Value::from).collect::<Vec<Value>>());
+//!     let mut stmt = db.prepare("SELECT * FROM item WHERE id IN rarray(?1)")?;
+//!     stmt.bind_pointer(1, values, array::POINTER_TYPE)?;
+//!     let mut rows = stmt.raw_query();
+//!     let mut count = 0;
+//!     while rows.next()?.is_some() {
+//!         count += 1;
+//!     }
+//!     Ok(count)
+//! }
+//! ```
 
 use std::default::Default;
+#[cfg(feature = "modern_sqlite")]
+use std::ffi::CStr;
 use std::marker::PhantomData;
 use std::os::raw::{c_char, c_int, c_void};
 use std::rc::Rc;
+#[cfg(feature = "modern_sqlite")]
+use std::sync::Arc;
 
 use crate::ffi;
-use crate::types::{ToSql, ToSqlOutput, Value};
+use crate::types::Value;
 use crate::vtab::{
     eponymous_only_module, Context, IndexConstraintOp, IndexInfo, VTab, VTabConnection, VTabCursor,
     Values,
@@ -48,14 +74,16 @@ pub(crate) unsafe extern "C" fn free_array(p: *mut c_void) {
 }
 
 /// Array parameter / pointer
+///
+/// Its [`ToSql`](crate::ToSql) implementation, in `rusqlite-types`, makes a
+/// [`ToSqlOutputExt::array`](crate::types::ToSqlOutputExt::array) output.
 pub type Array = Rc<Vec<Value>>;
 
-impl ToSql for Array {
-    #[inline]
-    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
-        Ok(ToSqlOutput::Array(self.clone()))
-    }
-}
+/// The type string of an `Arc<Vec<Value>>` bound with
+/// [`Statement::bind_pointer`](crate::Statement::bind_pointer) for `rarray`.
+#[cfg(feature = "modern_sqlite")] // 3.20.0
+#[cfg_attr(docsrs, doc(cfg(feature = "modern_sqlite")))]
+pub const POINTER_TYPE: &CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"rarray_arc\0") };
 
 /// Register the "rarray" module.
 pub fn load_module(conn: &Connection) -> Result<()> {
@@ -130,10 +158,29 @@ struct ArrayTabCursor<'vtab> {
     /// The rowid
     row_id: i64,
     /// Pointer to the array of values ("pointer")
-    ptr: Option<Array>,
+    ptr: Option<ArrayValues>,
     phantom: PhantomData<&'vtab ArrayTab>,
 }
 
+// The values of a cursor, bound as an `Array` or a shared vector.
+enum ArrayValues {
+    Rc(Array),
+    #[cfg(feature = "modern_sqlite")]
+    Arc(Arc<Vec<Value>>),
+}
+
+impl std::ops::Deref for ArrayValues {
+    type Target = [Value];
+
+    fn deref(&self) -> &[Value] {
+        match self {
+            ArrayValues::Rc(a) => a,
+            #[cfg(feature = "modern_sqlite")]
+            ArrayValues::Arc(a) => a,
+        }
+    }
+}
+
 impl ArrayTabCursor<'_> {
     fn new<'vtab>() -> ArrayTabCursor<'vtab> {
         ArrayTabCursor {
@@ -154,7 +201,11 @@ impl ArrayTabCursor<'_> {
 unsafe impl VTabCursor for ArrayTabCursor<'_> {
     fn filter(&mut self, idx_num: c_int, _idx_str: Option<&str>, args: &Values<'_>) -> Result<()> {
         if idx_num > 0 {
-            self.ptr = args.get_array(0);
+            self.ptr = args.get_array(0).map(ArrayValues::Rc);
+            #[cfg(feature = "modern_sqlite")]
+            if self.ptr.is_none() {
+                self.ptr = args.get_pointer(0, POINTER_TYPE).map(ArrayValues::Arc);
+            }
         } else {
             self.ptr = None;
         }
@@ -220,4 +271,42 @@ mod test {
         assert_eq!(1, Rc::strong_count(&ptr));
         Ok(())
     }
+    #[cfg(feature = "modern_sqlite")]
+    #[test]
+    fn test_array_pointer() -> Result<()> {
+        use std::ffi::CStr;
+        use std::sync::Arc;
+
+        let db = Connection::open_in_memory()?;
+        array::load_module(&db)?;
+        db.execute_batch(
+            "CREATE TABLE item (id INTEGER PRIMARY KEY);
+             WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 30000)
+             INSERT INTO item SELECT i FROM n;",
+        )?;
+        let ids: Vec<Value> = (0..10_000).map(|i| Value::from(i * 3 + 1)).collect();
+        let ids = Arc::new(ids);
+        {
+            let mut stmt =
+                db.prepare("SELECT count(*), sum(id) FROM item WHERE id IN rarray(?1)")?;
+            stmt.bind_pointer(1, ids.clone(), array::POINTER_TYPE)?;
+            assert_eq!(2, Arc::strong_count(&ids));
+            let (count, sum): (i64, i64) = {
+                let mut rows = stmt.raw_query();
+                let row = rows.next()?.unwrap();
+                (row.get(0)?, row.get(1)?)
+            };
+            assert_eq!(count, 10_000);
+            assert_eq!(sum, (0..10_000).map(|i| i * 3 + 1).sum::<i64>());
+
+            // any other type string is NULL
+            let carray = CStr::from_bytes_with_nul(b"carray\0").unwrap();
+            stmt.bind_pointer(1, ids.clone(), carray)?;
+            let mut rows = stmt.raw_query();
+            let count: i64 = rows.next()?.unwrap().get(0)?;
+            assert_eq!(count, 0);
+        }
+        assert_eq!(1, Arc::strong_count(&ids));
+        Ok(())
+    }
 }
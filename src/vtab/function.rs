@@ -0,0 +1,414 @@
+//! Table-valued functions backed by Rust iterators.
+//!
+//! [`create_table_function`] registers an eponymous-only virtual table whose
+//! arguments are hidden columns, as `generate_series` does, so that
+//! `SELECT * FROM my_func(1, 2)` is `SELECT * FROM my_func WHERE arg1 = 1 AND
+//! arg2 = 2`. Its rows are produced by an iterator made for each call from the
+//! arguments.
+use std::marker::PhantomData;
+use std::os::raw::c_int;
+use std::rc::Rc;
+
+use crate::ffi;
+use crate::types::{Type, Value};
+use crate::vtab::{
+    eponymous_only_module, escape_double_quote, Context, IndexConstraintOp, IndexInfo, VTab,
+    VTabConnection, VTabCursor, Values,
+};
+use crate::{Connection, Error, Result};
+
+/// The rows of a table-valued function, each with a value for every column.
+pub type TableFunctionRows = Box<dyn Iterator<Item = Result<Vec<Value>>>>;
+
+type Factory = dyn Fn(&Values<'_>) -> Result<TableFunctionRows> + Send;
+
+struct TableFunction {
+    name: String,
+    schema: String,
+    columns: usize,
+    arguments: Vec<String>,
+    factory: Box<Factory>,
+}
+
+/// Register the table-valued function `name`, with the `columns` it returns
+/// and the `arguments` it takes.
+///
+/// `factory` is called with the values of all the arguments, in order, each
+/// time the function is evaluated, and returns an iterator over its rows. The
+/// arguments are also hidden columns, which are returned as given. An error
+/// returned by `factory` or the iterator aborts the query with its message.
+///
+/// ```rust
+/// # use rusqlite::{Connection, Result};
+/// # use rusqlite::types::{Type, Value};
+/// # use rusqlite::vtab::create_table_function;
+/// fn repeat(conn: &Connection) -> Result<Vec<String>> {
+///     create_table_function(
+///         conn,
+///         "repeat",
+///         &[("value", Type::Null)],
+///         &["value_in", "times"],
+///         |args| {
+///             let value: Value = args.get(0)?;
+///             let times: usize = args.get(1)?;
+///             Ok(Box::new(
+///                 std::iter::repeat_with(move || Ok(vec![value.clone()])).take(times),
+///             ))
+///         },
+///     )?;
+///     let mut stmt = conn.prepare("SELECT value FROM repeat('a', 3)")?;
+///     let rows = stmt.query_map([], |row| row.get(0))?;
+///     rows.collect()
+/// }
+/// ```
+///
+/// # Failure
+///
+/// Will return `Err` if the function could not be registered. A query not
+/// giving a value to every argument fails with an error naming the missing
+/// one.
+pub fn create_table_function<F>(
+    conn: &Connection,
+    name: &str,
+    columns: &[(&str, Type)],
+    arguments: &[&str],
+    factory: F,
+) -> Result<()>
+where
+    F: Fn(&Values<'_>) -> Result<TableFunctionRows> + Send + 'static,
+{
+    let mut schema = "CREATE TABLE x(".to_owned();
+    for (i, (column, ty)) in columns.iter().enumerate() {
+        if i > 0 {
+            schema.push(',');
+        }
+        schema.push_str(&format!("\"{}\"", escape_double_quote(column)));
+        schema.push_str(match ty {
+            Type::Integer => " INTEGER",
+            Type::Real => " REAL",
+            Type::Text => " TEXT",
+            Type::Blob => " BLOB",
+            Type::Null => "",
+        });
+    }
+    for argument in arguments {
+        schema.push_str(&format!(",\"{}\" HIDDEN", escape_double_quote(argument)));
+    }
+    schema.push(')');
+    let function = TableFunction {
+        name: name.to_owned(),
+        schema,
+        columns: columns.len(),
+        arguments: arguments.iter().map(|&a| a.to_owned()).collect(),
+        factory: Box::new(factory),
+    };
+    conn.create_module(
+        name,
+        eponymous_only_module::<FunctionTab>(),
+        Some(Rc::new(function)),
+    )
+}
+
+#[repr(C)]
+struct FunctionTab {
+    /// Base class. Must be first
+    base: ffi::sqlite3_vtab,
+    function: Rc<TableFunction>,
+}
+
+unsafe impl<'vtab> VTab<'vtab> for FunctionTab {
+    type Aux = Rc<TableFunction>;
+    type Cursor = FunctionTabCursor<'vtab>;
+
+    fn connect(
+        _: &mut VTabConnection,
+        aux: Option<&Rc<TableFunction>>,
+        _args: &[&[u8]],
+    ) -> Result<(String, FunctionTab)> {
+        let function = aux.expect("table function without aux").clone();
+        Ok((
+            function.schema.clone(),
+            FunctionTab {
+                base: ffi::sqlite3_vtab::default(),
+                function,
+            },
+        ))
+    }
+
+    fn best_index(&self, info: &mut IndexInfo) -> Result<()> {
+        let function = &self.function;
+        // The constraint giving the value of each argument, and whether one
+        // was not usable in this plan
+        let mut constraints = vec![None; function.arguments.len()];
+        let mut unusable = vec![false; function.arguments.len()];
+        for (i, constraint) in info.constraints().enumerate() {
+            let column = constraint.column() as usize;
+            if column < function.columns
+                || column >= function.columns + function.arguments.len()
+                || constraint.operator() != IndexConstraintOp::SQLITE_INDEX_CONSTRAINT_EQ
+            {
+                continue;
+            }
+            let argument = column - function.columns;
+            if !constraint.is_usable() {
+                unusable[argument] = true;
+            } else if constraints[argument].is_none() {
+                constraints[argument] = Some(i);
+            }
+        }
+        for (argument, constraint) in constraints.iter().enumerate() {
+            match constraint {
+                Some(i) => {
+                    let mut usage = info.constraint_usage(*i);
+                    usage.set_argv_index(argument as c_int + 1);
+                    usage.set_omit(true);
+                }
+                // Another plan may provide it
+                None if unusable[argument] => {
+                    return Err(Error::SqliteFailure(
+                        ffi::Error::new(ffi::SQLITE_CONSTRAINT),
+                        None,
+                    ));
+                }
+                None => {
+                    return Err(Error::ModuleError(format!(
+                        "{}: missing argument {}",
+                        function.name, function.arguments[argument]
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn open(&'vtab mut self) -> Result<FunctionTabCursor<'vtab>> {
+        Ok(FunctionTabCursor {
+            base: ffi::sqlite3_vtab_cursor::default(),
+            function: &self.function,
+            args: Vec::new(),
+            rows: None,
+            row: None,
+            row_id: 0,
+            phantom: PhantomData,
+        })
+    }
+}
+
+#[repr(C)]
+struct FunctionTabCursor<'vtab> {
+    /// Base class. Must be first
+    base: ffi::sqlite3_vtab_cursor,
+    function: &'vtab TableFunction,
+    /// The values of the arguments
+    args: Vec<Value>,
+    rows: Option<TableFunctionRows>,
+    /// The current row, `None` at EOF
+    row: Option<Vec<Value>>,
+    /// The rowid
+    row_id: i64,
+    phantom: PhantomData<&'vtab FunctionTab>,
+}
+
+impl FunctionTabCursor<'_> {
+    fn advance(&mut self) -> Result<()> {
+        self.row = match self.rows.as_mut().and_then(Iterator::next) {
+            Some(row) => {
+                let row = row?;
+                if row.len() != self.function.columns {
+                    return Err(Error::ModuleError(format!(
+                        "{}: expected {} values per row, got {}",
+                        self.function.name,
+                        self.function.columns,
+                        row.len()
+                    )));
+                }
+                Some(row)
+            }
+            None => None,
+        };
+        self.row_id += 1;
+        Ok(())
+    }
+}
+
+unsafe impl VTabCursor for FunctionTabCursor<'_> {
+    fn filter(&mut self, _idx_num: c_int, _idx_str: Option<&str>, args: &Values<'_>) -> Result<()> {
+        self.args = args.iter().map(Value::from).collect();
+        self.rows = Some((self.function.factory)(args)?);
+        self.row_id = 0;
+        self.advance()
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.advance()
+    }
+
+    fn eof(&self) -> bool {
+        self.row.is_none()
+    }
+
+    fn column(&self, ctx: &mut Context, i: c_int) -> Result<()> {
+        let i = i as usize;
+        let value = match (&self.row, i.checked_sub(self.function.columns)) {
+            (Some(row), None) => &row[i],
+            (_, Some(argument)) => &self.args[argument],
+            (None, None) => &Value::Null,
+        };
+        ctx.set_result(value)
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.row_id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::types::{Type, Value};
+    use crate::vtab::create_table_function;
+    use crate::{Connection, Error, Result};
+
+    fn fibonacci(db: &Connection) -> Result<()> {
+        create_table_function(
+            db,
+            "fibonacci",
+            &[("value", Type::Integer)],
+            &["n"],
+            |args| {
+                let n: i64 = args.get(0)?;
+                if n < 0 {
+                    return Err(Error::ModuleError(format!("fibonacci: negative n {n}")));
+                }
+                let rows = (0..n).scan((0i64, 1i64), |(a, b), _| {
+                    let value = *a;
+                    *a = *b;
+                    *b = b.checked_add(value)?;
+                    Some(Ok(vec![Value::Integer(value)]))
+                });
+                Ok(Box::new(rows))
+            },
+        )
+    }
+
+    #[test]
+    fn test_fibonacci() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        fibonacci(&db)?;
+        let mut stmt = db.prepare("SELECT value FROM fibonacci(8)")?;
+        let values: Vec<i64> = stmt.query_map([], |r| r.get(0))?.collect::<Result<_>>()?;
+        assert_eq!(values, [0, 1, 1, 2, 3, 5, 8, 13]);
+
+        // the argument is a hidden column, the rowid counts rows
+        let (n, rowid): (i64, i64) =
+            db.query_row("SELECT n, rowid FROM fibonacci WHERE n = 3", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })?;
+        assert_eq!((n, rowid), (3, 1));
+        let n: i64 = db.one_column("SELECT count(*) FROM fibonacci(0)")?;
+        assert_eq!(n, 0);
+
+        // the argument is given by the other table of the join
+        db.execute_batch("CREATE TABLE t(n); INSERT INTO t VALUES (1), (3), (5);")?;
+        let mut stmt = db.prepare(
+            "SELECT t.n, group_concat(f.value) FROM t, fibonacci(t.n) AS f GROUP BY t.n",
+        )?;
+        let rows: Vec<(i64, String)> = stmt
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?
+            .collect::<Result<_>>()?;
+        assert_eq!(
+            rows,
+            [
+                (1, "0".to_owned()),
+                (3, "0,1,1".to_owned()),
+                (5, "0,1,1,2,3".to_owned())
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors() -> Result<()> {
+        let db = Connection::open_in_memory()?;
+        fibonacci(&db)?;
+        let err = db
+            .one_column::<i64>("SELECT value FROM fibonacci")
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("fibonacci: missing argument n"),
+            "{}",
+            err
+        );
+        let err = db
+            .one_column::<i64>("SELECT value FROM fibonacci(-1)")
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("fibonacci: negative n -1"),
+            "{}",
+            err
+        );
+
+        create_table_function(&db, "fails", &[("x", Type::Null)], &[], |_| {
+            let rows = (0..3).map(|i| match i {
+                2 => Err(Error::ModuleError("third row".to_owned())),
+                _ => Ok(vec![Value::Integer(i)]),
+            });
+            Ok(Box::new(rows))
+        })?;
+        let mut stmt = db.prepare("SELECT x FROM fails")?;
+        let mut rows = stmt.query([])?;
+        assert!(rows.next()?.is_some());
+        assert!(rows.next()?.is_some());
+        let err = rows.next().unwrap_err();
+        assert!(err.to_string().contains("third row"), "{}", err);
+        Ok(())
+    }
+
+    #[cfg(feature = "regexp")]
+    #[test]
+    fn test_regex_split() -> Result<()> {
+        use regex::Regex;
+
+        let db = Connection::open_in_memory()?;
+        create_table_function(
+            &db,
+            "regex_split",
+            &[("part", Type::Text)],
+            &["text", "pattern"],
+            |args| {
+                let text: String = args.get(0)?;
+                let pattern: String = args.get(1)?;
+                let re = Regex::new(&pattern).map_err(|e| Error::ModuleError(e.to_string()))?;
+                let parts: Vec<_> = re
+                    .split(&text)
+                    .map(|part| Ok(vec![Value::Text(part.to_owned())]))
+                    .collect();
+                Ok(Box::new(parts.into_iter()))
+            },
+        )?;
+        db.execute_batch(
+            "CREATE TABLE doc(id INTEGER PRIMARY KEY, tags TEXT);
+             INSERT INTO doc VALUES (1, 'a, b,c'), (2, 'b'), (3, 'c ,a');",
+        )?;
+        let mut stmt = db.prepare(
+            "SELECT s.part, group_concat(doc.id) FROM doc, regex_split(doc.tags, '\\s*,\\s*') AS s
+             GROUP BY s.part ORDER BY s.part",
+        )?;
+        let rows: Vec<(String, String)> = stmt
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?
+            .collect::<Result<_>>()?;
+        assert_eq!(
+            rows,
+            [
+                ("a".to_owned(), "1,3".to_owned()),
+                ("b".to_owned(), "1,2".to_owned()),
+                ("c".to_owned(), "1,3".to_owned())
+            ]
+        );
+
+        let err = db
+            .one_column::<String>("SELECT part FROM regex_split('a', '(')")
+            .unwrap_err();
+        assert!(err.to_string().contains("unclosed group"), "{}", err);
+        Ok(())
+    }
+}
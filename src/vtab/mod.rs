@@ -9,10 +9,14 @@
 //! `USING` clause.
 //!
 //! (See [SQLite doc](http://sqlite.org/vtab.html))
+//!
+//! Simple table-valued functions can instead be registered with
+//! [`create_table_function`].
 use std::borrow::Cow::{self, Borrowed, Owned};
 use std::marker::PhantomData;
 use std::marker::Sync;
 use std::os::raw::{c_char, c_int, c_void};
+use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::ptr;
 use std::slice;
 
@@ -20,8 +24,10 @@ use crate::context::set_result;
 use crate::error::error_from_sqlite_code;
 use crate::ffi;
 pub use crate::ffi::{sqlite3_vtab, sqlite3_vtab_cursor};
-use crate::types::{FromSql, FromSqlError, ToSql, ValueRef};
+use crate::panic_policy::stash_panic;
+use crate::types::{self, FromSql, FromSqlError, ToSql, ValueRef};
 use crate::{str_to_cstring, Connection, Error, InnerConnection, Result};
+pub use function::{create_table_function, TableFunctionRows};
 
 // let conn: Connection = ...;
 // let mod: Module = ...; // VTab builder
@@ -103,13 +109,16 @@ const ZERO_MODULE: ffi::sqlite3_module = unsafe {
 
 macro_rules! module {
     ($lt:lifetime, $vt:ty, $ct:ty, $xc:expr, $xd:expr, $xu:expr) => {
+        module!($lt, $vt, $ct, $xc, Some(rust_connect::<$vt>), $xd, $xu)
+    };
+    ($lt:lifetime, $vt:ty, $ct:ty, $xc:expr, $xn:expr, $xd:expr, $xu:expr) => {
     #[allow(clippy::needless_update)]
     &Module {
         base: ffi::sqlite3_module {
             // We don't use V3
             iVersion: 2,
             xCreate: $xc,
-            xConnect: Some(rust_connect::<$vt>),
+            xConnect: $xn,
             xBestIndex: Some(rust_best_index::<$vt>),
             xDisconnect: Some(rust_disconnect::<$vt>),
             xDestroy: $xd,
@@ -144,13 +153,13 @@ macro_rules! module {
 pub fn update_module<'vtab, T: UpdateVTab<'vtab>>() -> &'static Module<'vtab, T> {
     match T::KIND {
         VTabKind::EponymousOnly => {
-            module!('vtab, T, T::Cursor, None, None, Some(rust_update::<T>))
+            module!('vtab, T, T::Cursor, None, Some(rust_connect_writable::<T>), None, Some(rust_update::<T>))
         }
         VTabKind::Eponymous => {
-            module!('vtab, T, T::Cursor, Some(rust_connect::<T>), Some(rust_disconnect::<T>), Some(rust_update::<T>))
+            module!('vtab, T, T::Cursor, Some(rust_connect_writable::<T>), Some(rust_connect_writable::<T>), Some(rust_disconnect::<T>), Some(rust_update::<T>))
         }
         _ => {
-            module!('vtab, T, T::Cursor, Some(rust_create::<T>), Some(rust_destroy::<T>), Some(rust_update::<T>))
+            module!('vtab, T, T::Cursor, Some(rust_create_writable::<T>), Some(rust_connect_writable::<T>), Some(rust_destroy::<T>), Some(rust_update::<T>))
         }
     }
 }
@@ -197,7 +206,31 @@ pub enum VTabConfig {
     DirectOnly = 3,
 }
 
+/// The conflict resolution mode of the statement calling
+/// [`UpdateVTab`] methods, as returned by
+/// [`VTabConnection::on_conflict`].
+#[repr(i32)]
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ConflictMode {
+    /// Equivalent to SQLITE_ROLLBACK
+    Rollback = 1,
+    /// Equivalent to SQLITE_IGNORE
+    Ignore = 2,
+    /// Equivalent to SQLITE_FAIL
+    Fail = 3,
+    /// Equivalent to SQLITE_ABORT
+    Abort = 4,
+    /// Equivalent to SQLITE_REPLACE
+    Replace = 5,
+}
+
 /// `feature = "vtab"`
+///
+/// A writable table may keep a copy of the connection passed to
+/// [`connect`](VTab::connect) to call [`on_conflict`](VTabConnection::on_conflict)
+/// from its [`UpdateVTab`] methods.
+#[derive(Clone)]
 pub struct VTabConnection(*mut ffi::sqlite3);
 
 impl VTabConnection {
@@ -206,7 +239,22 @@ impl VTabConnection {
         crate::error::check(unsafe { ffi::sqlite3_vtab_config(self.0, config as c_int) })
     }
 
-    // TODO sqlite3_vtab_on_conflict (http://sqlite.org/c3ref/vtab_on_conflict.html) & xUpdate
+    /// Returns the `ON CONFLICT` mode of the statement, only meaningful from
+    /// the [`UpdateVTab`] methods. A constraint violation should then be
+    /// reported by returning `SQLITE_CONSTRAINT` for `Rollback`, `Fail` and
+    /// `Abort`, skipped for `Ignore`, and resolved by deleting the
+    /// conflicting rows for `Replace`.
+    ///
+    /// (See [SQLite doc](https://sqlite.org/c3ref/vtab_on_conflict.html))
+    pub fn on_conflict(&mut self) -> ConflictMode {
+        match unsafe { ffi::sqlite3_vtab_on_conflict(self.0) } {
+            ffi::SQLITE_ROLLBACK => ConflictMode::Rollback,
+            ffi::SQLITE_IGNORE => ConflictMode::Ignore,
+            ffi::SQLITE_FAIL => ConflictMode::Fail,
+            ffi::SQLITE_REPLACE => ConflictMode::Replace,
+            _ => ConflictMode::Abort,
+        }
+    }
 
     /// Get access to the underlying SQLite database connection handle.
     ///
@@ -300,7 +348,12 @@ pub trait CreateVTab<'vtab>: VTab<'vtab> {
     }
 }
 
-/// Writable virtual table instance trait.
+/// Writable virtual table instance trait, registered with
+/// [`update_module`] so that `INSERT`, `UPDATE` and `DELETE` statements call
+/// its methods.
+///
+/// Tables declared `WITHOUT ROWID` are not supported: creating or connecting
+/// one fails.
 ///
 /// (See [SQLite doc](https://sqlite.org/vtab.html#xupdate))
 pub trait UpdateVTab<'vtab>: CreateVTab<'vtab> {
@@ -329,16 +382,21 @@ pub enum IndexConstraintOp {
     SQLITE_INDEX_CONSTRAINT_LT,
     SQLITE_INDEX_CONSTRAINT_GE,
     SQLITE_INDEX_CONSTRAINT_MATCH,
-    SQLITE_INDEX_CONSTRAINT_LIKE,         // 3.10.0
-    SQLITE_INDEX_CONSTRAINT_GLOB,         // 3.10.0
-    SQLITE_INDEX_CONSTRAINT_REGEXP,       // 3.10.0
-    SQLITE_INDEX_CONSTRAINT_NE,           // 3.21.0
-    SQLITE_INDEX_CONSTRAINT_ISNOT,        // 3.21.0
-    SQLITE_INDEX_CONSTRAINT_ISNOTNULL,    // 3.21.0
-    SQLITE_INDEX_CONSTRAINT_ISNULL,       // 3.21.0
-    SQLITE_INDEX_CONSTRAINT_IS,           // 3.21.0
-    SQLITE_INDEX_CONSTRAINT_LIMIT,        // 3.38.0
-    SQLITE_INDEX_CONSTRAINT_OFFSET,       // 3.38.0
+    SQLITE_INDEX_CONSTRAINT_LIKE,      // 3.10.0
+    SQLITE_INDEX_CONSTRAINT_GLOB,      // 3.10.0
+    SQLITE_INDEX_CONSTRAINT_REGEXP,    // 3.10.0
+    SQLITE_INDEX_CONSTRAINT_NE,        // 3.21.0
+    SQLITE_INDEX_CONSTRAINT_ISNOT,     // 3.21.0
+    SQLITE_INDEX_CONSTRAINT_ISNOTNULL, // 3.21.0
+    SQLITE_INDEX_CONSTRAINT_ISNULL,    // 3.21.0
+    SQLITE_INDEX_CONSTRAINT_IS,        // 3.21.0
+    /// The value of a `LIMIT` clause, pushed down when the table is the only
+    /// one of the query.
+    SQLITE_INDEX_CONSTRAINT_LIMIT, // 3.38.0
+    /// The value of an `OFFSET` clause, as `LIMIT`.
+    SQLITE_INDEX_CONSTRAINT_OFFSET, // 3.38.0
+    /// A function overloaded by `xFindFunction`, with the code it returned,
+    /// from `SQLITE_INDEX_CONSTRAINT_FUNCTION` (150) to 255.
     SQLITE_INDEX_CONSTRAINT_FUNCTION(u8), // 3.25.0
 }
 
@@ -378,6 +436,25 @@ bitflags::bitflags! {
     }
 }
 
+/// What SQLite needs from the rows of a virtual table, as returned by
+/// [`IndexInfo::distinct`].
+#[cfg(feature = "modern_sqlite")] // SQLite >= 3.38.0
+#[cfg_attr(docsrs, doc(cfg(feature = "modern_sqlite")))]
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum DistinctMode {
+    /// The rows must be in the order of the ORDER BY, if any.
+    Ordered,
+    /// Rows with the same values of the ORDER BY columns (for a GROUP BY) must
+    /// be adjacent, in any order.
+    Grouped,
+    /// Only one of the rows with the same values of the ORDER BY columns (for
+    /// a DISTINCT) is needed, in any order.
+    Distinct,
+    /// As `Distinct`, with the rows in order.
+    DistinctOrdered,
+}
+
 /// Pass information into and receive the reply from the
 /// [`VTab::best_index`] method.
 ///
@@ -505,28 +582,56 @@ impl IndexInfo {
         Ok(unsafe { CStr::from_ptr(collation) }.to_str()?)
     }
 
-    /*/// Determine if a virtual table query is DISTINCT
+    /// Determine if a virtual table query is DISTINCT, i.e. how much of the
+    /// ORDER BY the table may leave to SQLite when it
+    /// [consumes](IndexInfo::set_order_by_consumed) it.
+    ///
+    /// (See [SQLite doc](https://sqlite.org/c3ref/vtab_distinct.html))
     #[cfg(feature = "modern_sqlite")] // SQLite >= 3.38.0
     #[cfg_attr(docsrs, doc(cfg(feature = "modern_sqlite")))]
-    pub fn distinct(&self) -> c_int {
-        unsafe { ffi::sqlite3_vtab_distinct(self.0) }
+    #[must_use]
+    pub fn distinct(&self) -> DistinctMode {
+        match unsafe { ffi::sqlite3_vtab_distinct(self.0) } {
+            1 => DistinctMode::Grouped,
+            2 => DistinctMode::Distinct,
+            3 => DistinctMode::DistinctOrdered,
+            _ => DistinctMode::Ordered,
+        }
     }
 
-    /// Constraint values
+    /*/// Constraint values
     #[cfg(feature = "modern_sqlite")] // SQLite >= 3.38.0
     #[cfg_attr(docsrs, doc(cfg(feature = "modern_sqlite")))]
     pub fn set_rhs_value(&mut self, constraint_idx: c_int, value: ValueRef) -> Result<()> {
         // TODO ValueRef to sqlite3_value
         crate::error::check(unsafe { ffi::sqlite3_vtab_rhs_value(self.O, constraint_idx, value) })
     }
+    */
 
-    /// Identify and handle IN constraints
+    /// Returns whether the `constraint_idx`th constraint is an `IN` operator
+    /// which [`set_handle_in`](IndexInfo::set_handle_in) can have passed to
+    /// [`VTabCursor::filter`] all at once.
     #[cfg(feature = "modern_sqlite")] // SQLite >= 3.38.0
     #[cfg_attr(docsrs, doc(cfg(feature = "modern_sqlite")))]
-    pub fn set_in_constraint(&mut self, constraint_idx: c_int, b_handle: c_int) -> bool {
-        unsafe { ffi::sqlite3_vtab_in(self.0, constraint_idx, b_handle) != 0 }
-    } // TODO sqlite3_vtab_in_first / sqlite3_vtab_in_next https://sqlite.org/c3ref/vtab_in_first.html
-    */
+    #[must_use]
+    pub fn is_in_constraint(&self, constraint_idx: usize) -> bool {
+        unsafe { ffi::sqlite3_vtab_in(self.0, constraint_idx as c_int, -1) != 0 }
+    }
+
+    /// Requests, if `handle` is true, that the `constraint_idx`th constraint,
+    /// an `IN` operator, be passed to [`VTabCursor::filter`] as a single
+    /// argument holding all the values of the list, read with
+    /// [`Values::in_values`], instead of calling `filter` once for each.
+    /// The constraint must also be given an
+    /// [`argv_index`](IndexConstraintUsage::set_argv_index), and should be
+    /// omitted. Returns whether the constraint is such an `IN` operator.
+    ///
+    /// (See [SQLite doc](https://sqlite.org/c3ref/vtab_in.html))
+    #[cfg(feature = "modern_sqlite")] // SQLite >= 3.38.0
+    #[cfg_attr(docsrs, doc(cfg(feature = "modern_sqlite")))]
+    pub fn set_handle_in(&mut self, constraint_idx: usize, handle: bool) -> bool {
+        unsafe { ffi::sqlite3_vtab_in(self.0, constraint_idx as c_int, c_int::from(handle)) != 0 }
+    }
 }
 
 /// Iterate on index constraint and its associated usage.
@@ -611,6 +716,13 @@ impl IndexConstraintUsage<'_> {
     }
 
     /// if `omit`, do not code a test for this constraint
+    ///
+    /// [`VTabCursor::filter`] is only passed the values of the constraints
+    /// with an `argv_index`, so the table should record which of them it
+    /// must then enforce in the [`idx_num`](IndexInfo::set_idx_num) or
+    /// [`idx_str`](IndexInfo::set_idx_str) it picks. For
+    /// `SQLITE_INDEX_CONSTRAINT_LIMIT` and `SQLITE_INDEX_CONSTRAINT_OFFSET`,
+    /// omitting means that the table applies the limit or offset itself.
     #[inline]
     pub fn set_omit(&mut self, omit: bool) {
         self.0.omit = omit as std::os::raw::c_uchar;
@@ -731,16 +843,14 @@ impl Values<'_> {
     /// Returns value at `idx`
     pub fn get<T: FromSql>(&self, idx: usize) -> Result<T> {
         let arg = self.args[idx];
-        let value = unsafe { ValueRef::from_value(arg) };
+        let value = unsafe { types::value_ref(arg) };
         FromSql::column_result(value).map_err(|err| match err {
             FromSqlError::InvalidType => Error::InvalidFilterParameterType(idx, value.data_type()),
             FromSqlError::Other(err) => {
                 Error::FromSqlConversionFailure(idx, value.data_type(), err)
             }
-            FromSqlError::InvalidBlobSize { .. } => {
-                Error::FromSqlConversionFailure(idx, value.data_type(), Box::new(err))
-            }
             FromSqlError::OutOfRange(i) => Error::IntegralValueOutOfRange(idx, i),
+            _ => Error::FromSqlConversionFailure(idx, value.data_type(), Box::new(err)),
         })
     }
 
@@ -764,6 +874,20 @@ impl Values<'_> {
         }
     }
 
+    /// Returns the `idx`th value if it is a pointer of type `type_name` to a
+    /// `T`, bound with [`Statement::bind_pointer`](crate::Statement::bind_pointer),
+    /// and `None` otherwise.
+    #[cfg(feature = "modern_sqlite")] // 3.20.0
+    #[cfg_attr(docsrs, doc(cfg(feature = "modern_sqlite")))]
+    #[must_use]
+    pub fn get_pointer<T: std::any::Any + Send + Sync>(
+        &self,
+        idx: usize,
+        type_name: &'static std::ffi::CStr,
+    ) -> Option<std::sync::Arc<T>> {
+        unsafe { crate::pointer::from_value(self.args[idx], type_name) }
+    }
+
     /// Turns `Values` into an iterator.
     #[inline]
     #[must_use]
@@ -772,7 +896,65 @@ impl Values<'_> {
             iter: self.args.iter(),
         }
     }
-    // TODO sqlite3_vtab_in_first / sqlite3_vtab_in_next https://sqlite.org/c3ref/vtab_in_first.html & 3.38.0
+
+    /// Returns an iterator over the values of the `IN` list passed as the
+    /// `idx`th value, for a constraint handled with
+    /// [`IndexInfo::set_handle_in`].
+    ///
+    /// The values are copied, as SQLite reuses the same memory for each.
+    /// Iterating fails if the `idx`th value is not such a list.
+    #[cfg(feature = "modern_sqlite")] // SQLite >= 3.38.0
+    #[cfg_attr(docsrs, doc(cfg(feature = "modern_sqlite")))]
+    #[must_use]
+    pub fn in_values(&self, idx: usize) -> InValues<'_> {
+        InValues {
+            list: self.args[idx],
+            started: false,
+            done: false,
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// Iterator over the values of an `IN` constraint, returned by
+/// [`Values::in_values`].
+#[cfg(feature = "modern_sqlite")] // SQLite >= 3.38.0
+#[cfg_attr(docsrs, doc(cfg(feature = "modern_sqlite")))]
+pub struct InValues<'a> {
+    list: *mut ffi::sqlite3_value,
+    started: bool,
+    done: bool,
+    phantom: PhantomData<&'a Values<'a>>,
+}
+
+#[cfg(feature = "modern_sqlite")] // SQLite >= 3.38.0
+impl Iterator for InValues<'_> {
+    type Item = Result<types::Value>;
+
+    fn next(&mut self) -> Option<Result<types::Value>> {
+        if self.done {
+            return None;
+        }
+        let mut value = ptr::null_mut();
+        let rc = unsafe {
+            if self.started {
+                ffi::sqlite3_vtab_in_next(self.list, &mut value)
+            } else {
+                self.started = true;
+                ffi::sqlite3_vtab_in_first(self.list, &mut value)
+            }
+        };
+        match rc {
+            ffi::SQLITE_OK => Some(Ok(unsafe { types::value_ref(value) }.into())),
+            rc => {
+                self.done = true;
+                match rc {
+                    ffi::SQLITE_DONE => None,
+                    rc => Some(Err(error_from_sqlite_code(rc, None))),
+                }
+            }
+        }
+    }
 }
 
 impl<'a> IntoIterator for &'a Values<'a> {
@@ -797,7 +979,7 @@ impl<'a> Iterator for ValueIter<'a> {
     fn next(&mut self) -> Option<ValueRef<'a>> {
         self.iter
             .next()
-            .map(|&raw| unsafe { ValueRef::from_value(raw) })
+            .map(|&raw| unsafe { types::value_ref(raw) })
     }
 
     #[inline]
@@ -943,45 +1125,7 @@ unsafe extern "C" fn rust_create<'vtab, T>(
 where
     T: CreateVTab<'vtab>,
 {
-    use std::ffi::CStr;
-
-    let mut conn = VTabConnection(db);
-    let aux = aux.cast::<T::Aux>();
-    let args = slice::from_raw_parts(argv, argc as usize);
-    let vec = args
-        .iter()
-        .map(|&cs| CStr::from_ptr(cs).to_bytes()) // FIXME .to_str() -> Result<&str, Utf8Error>
-        .collect::<Vec<_>>();
-    match T::create(&mut conn, aux.as_ref(), &vec[..]) {
-        Ok((sql, vtab)) => match std::ffi::CString::new(sql) {
-            Ok(c_sql) => {
-                let rc = ffi::sqlite3_declare_vtab(db, c_sql.as_ptr());
-                if rc == ffi::SQLITE_OK {
-                    let boxed_vtab: *mut T = Box::into_raw(Box::new(vtab));
-                    *pp_vtab = boxed_vtab.cast::<ffi::sqlite3_vtab>();
-                    ffi::SQLITE_OK
-                } else {
-                    let err = error_from_sqlite_code(rc, None);
-                    *err_msg = alloc(&err.to_string());
-                    rc
-                }
-            }
-            Err(err) => {
-                *err_msg = alloc(&err.to_string());
-                ffi::SQLITE_ERROR
-            }
-        },
-        Err(Error::SqliteFailure(err, s)) => {
-            if let Some(s) = s {
-                *err_msg = alloc(&s);
-            }
-            err.extended_code
-        }
-        Err(err) => {
-            *err_msg = alloc(&err.to_string());
-            ffi::SQLITE_ERROR
-        }
-    }
+    declare_vtab(db, aux, argc, argv, pp_vtab, err_msg, T::create, false)
 }
 
 unsafe extern "C" fn rust_connect<'vtab, T>(
@@ -995,45 +1139,123 @@ unsafe extern "C" fn rust_connect<'vtab, T>(
 where
     T: VTab<'vtab>,
 {
-    use std::ffi::CStr;
-
-    let mut conn = VTabConnection(db);
-    let aux = aux.cast::<T::Aux>();
-    let args = slice::from_raw_parts(argv, argc as usize);
-    let vec = args
-        .iter()
-        .map(|&cs| CStr::from_ptr(cs).to_bytes()) // FIXME .to_str() -> Result<&str, Utf8Error>
-        .collect::<Vec<_>>();
-    match T::connect(&mut conn, aux.as_ref(), &vec[..]) {
-        Ok((sql, vtab)) => match std::ffi::CString::new(sql) {
-            Ok(c_sql) => {
-                let rc = ffi::sqlite3_declare_vtab(db, c_sql.as_ptr());
-                if rc == ffi::SQLITE_OK {
-                    let boxed_vtab: *mut T = Box::into_raw(Box::new(vtab));
-                    *pp_vtab = boxed_vtab.cast::<ffi::sqlite3_vtab>();
-                    ffi::SQLITE_OK
-                } else {
-                    let err = error_from_sqlite_code(rc, None);
+    declare_vtab(db, aux, argc, argv, pp_vtab, err_msg, T::connect, false)
+}
+
+// xCreate of `update_module`, which does not support WITHOUT ROWID tables.
+unsafe extern "C" fn rust_create_writable<'vtab, T>(
+    db: *mut ffi::sqlite3,
+    aux: *mut c_void,
+    argc: c_int,
+    argv: *const *const c_char,
+    pp_vtab: *mut *mut ffi::sqlite3_vtab,
+    err_msg: *mut *mut c_char,
+) -> c_int
+where
+    T: UpdateVTab<'vtab>,
+{
+    declare_vtab(db, aux, argc, argv, pp_vtab, err_msg, T::create, true)
+}
+
+// xConnect of `update_module`, which does not support WITHOUT ROWID tables.
+unsafe extern "C" fn rust_connect_writable<'vtab, T>(
+    db: *mut ffi::sqlite3,
+    aux: *mut c_void,
+    argc: c_int,
+    argv: *const *const c_char,
+    pp_vtab: *mut *mut ffi::sqlite3_vtab,
+    err_msg: *mut *mut c_char,
+) -> c_int
+where
+    T: UpdateVTab<'vtab>,
+{
+    declare_vtab(db, aux, argc, argv, pp_vtab, err_msg, T::connect, true)
+}
+
+type CreateFn<'vtab, T> =
+    fn(&mut VTabConnection, Option<&<T as VTab<'vtab>>::Aux>, &[&[u8]]) -> Result<(String, T)>;
+
+#[allow(clippy::too_many_arguments)]
+unsafe fn declare_vtab<'vtab, T>(
+    db: *mut ffi::sqlite3,
+    aux: *mut c_void,
+    argc: c_int,
+    argv: *const *const c_char,
+    pp_vtab: *mut *mut ffi::sqlite3_vtab,
+    err_msg: *mut *mut c_char,
+    create: CreateFn<'vtab, T>,
+    writable: bool,
+) -> c_int
+where
+    T: VTab<'vtab>,
+{
+    catch_panic(|| {
+        use std::ffi::CStr;
+
+        let mut conn = VTabConnection(db);
+        let aux = aux.cast::<T::Aux>();
+        let args = slice::from_raw_parts(argv, argc as usize);
+        let vec = args
+            .iter()
+            .map(|&cs| CStr::from_ptr(cs).to_bytes()) // FIXME .to_str() -> Result<&str, Utf8Error>
+            .collect::<Vec<_>>();
+        match create(&mut conn, aux.as_ref(), &vec[..]) {
+            Ok((sql, _)) if writable && is_without_rowid(&sql) => {
+                *err_msg = alloc("writable WITHOUT ROWID virtual tables are not supported");
+                ffi::SQLITE_ERROR
+            }
+            Ok((sql, vtab)) => match std::ffi::CString::new(sql) {
+                Ok(c_sql) => {
+                    let rc = ffi::sqlite3_declare_vtab(db, c_sql.as_ptr());
+                    if rc == ffi::SQLITE_OK {
+                        let boxed_vtab: *mut T = Box::into_raw(Box::new(vtab));
+                        *pp_vtab = boxed_vtab.cast::<ffi::sqlite3_vtab>();
+                        ffi::SQLITE_OK
+                    } else {
+                        let err = error_from_sqlite_code(rc, None);
+                        *err_msg = alloc(&err.to_string());
+                        rc
+                    }
+                }
+                Err(err) => {
                     *err_msg = alloc(&err.to_string());
-                    rc
+                    ffi::SQLITE_ERROR
                 }
+            },
+            Err(Error::SqliteFailure(err, s)) => {
+                if let Some(s) = s {
+                    *err_msg = alloc(&s);
+                }
+                err.extended_code
             }
             Err(err) => {
                 *err_msg = alloc(&err.to_string());
                 ffi::SQLITE_ERROR
             }
-        },
-        Err(Error::SqliteFailure(err, s)) => {
-            if let Some(s) = s {
-                *err_msg = alloc(&s);
-            }
-            err.extended_code
         }
-        Err(err) => {
-            *err_msg = alloc(&err.to_string());
-            ffi::SQLITE_ERROR
+    })
+}
+
+// Whether the `CREATE TABLE` statement declaring a virtual table ends with
+// `WITHOUT ROWID`.
+fn is_without_rowid(sql: &str) -> bool {
+    let options = match sql.rfind(')') {
+        Some(i) => &sql[i + 1..],
+        None => return false,
+    };
+    let mut words = options
+        .split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+        .filter(|w| !w.is_empty());
+    while let Some(word) = words.next() {
+        if word.eq_ignore_ascii_case("without")
+            && words
+                .next()
+                .is_some_and(|w| w.eq_ignore_ascii_case("rowid"))
+        {
+            return true;
         }
     }
+    false
 }
 
 unsafe extern "C" fn rust_best_index<'vtab, T>(
@@ -1043,59 +1265,65 @@ unsafe extern "C" fn rust_best_index<'vtab, T>(
 where
     T: VTab<'vtab>,
 {
-    let vt = vtab.cast::<T>();
-    let mut idx_info = IndexInfo(info);
-    match (*vt).best_index(&mut idx_info) {
-        Ok(_) => ffi::SQLITE_OK,
-        Err(Error::SqliteFailure(err, s)) => {
-            if let Some(err_msg) = s {
-                set_err_msg(vtab, &err_msg);
+    catch_panic(|| {
+        let vt = vtab.cast::<T>();
+        let mut idx_info = IndexInfo(info);
+        match (*vt).best_index(&mut idx_info) {
+            Ok(_) => ffi::SQLITE_OK,
+            Err(Error::SqliteFailure(err, s)) => {
+                if let Some(err_msg) = s {
+                    set_err_msg(vtab, &err_msg);
+                }
+                err.extended_code
+            }
+            Err(err) => {
+                set_err_msg(vtab, &err.to_string());
+                ffi::SQLITE_ERROR
             }
-            err.extended_code
-        }
-        Err(err) => {
-            set_err_msg(vtab, &err.to_string());
-            ffi::SQLITE_ERROR
         }
-    }
+    })
 }
 
 unsafe extern "C" fn rust_disconnect<'vtab, T>(vtab: *mut ffi::sqlite3_vtab) -> c_int
 where
     T: VTab<'vtab>,
 {
-    if vtab.is_null() {
-        return ffi::SQLITE_OK;
-    }
-    let vtab = vtab.cast::<T>();
-    drop(Box::from_raw(vtab));
-    ffi::SQLITE_OK
+    catch_panic(|| {
+        if vtab.is_null() {
+            return ffi::SQLITE_OK;
+        }
+        let vtab = vtab.cast::<T>();
+        drop(Box::from_raw(vtab));
+        ffi::SQLITE_OK
+    })
 }
 
 unsafe extern "C" fn rust_destroy<'vtab, T>(vtab: *mut ffi::sqlite3_vtab) -> c_int
 where
     T: CreateVTab<'vtab>,
 {
-    if vtab.is_null() {
-        return ffi::SQLITE_OK;
-    }
-    let vt = vtab.cast::<T>();
-    match (*vt).destroy() {
-        Ok(_) => {
-            drop(Box::from_raw(vt));
-            ffi::SQLITE_OK
+    catch_panic(|| {
+        if vtab.is_null() {
+            return ffi::SQLITE_OK;
         }
-        Err(Error::SqliteFailure(err, s)) => {
-            if let Some(err_msg) = s {
-                set_err_msg(vtab, &err_msg);
+        let vt = vtab.cast::<T>();
+        match (*vt).destroy() {
+            Ok(_) => {
+                drop(Box::from_raw(vt));
+                ffi::SQLITE_OK
+            }
+            Err(Error::SqliteFailure(err, s)) => {
+                if let Some(err_msg) = s {
+                    set_err_msg(vtab, &err_msg);
+                }
+                err.extended_code
+            }
+            Err(err) => {
+                set_err_msg(vtab, &err.to_string());
+                ffi::SQLITE_ERROR
             }
-            err.extended_code
-        }
-        Err(err) => {
-            set_err_msg(vtab, &err.to_string());
-            ffi::SQLITE_ERROR
         }
-    }
+    })
 }
 
 unsafe extern "C" fn rust_open<'vtab, T: 'vtab>(
@@ -1105,33 +1333,37 @@ unsafe extern "C" fn rust_open<'vtab, T: 'vtab>(
 where
     T: VTab<'vtab>,
 {
-    let vt = vtab.cast::<T>();
-    match (*vt).open() {
-        Ok(cursor) => {
-            let boxed_cursor: *mut T::Cursor = Box::into_raw(Box::new(cursor));
-            *pp_cursor = boxed_cursor.cast::<ffi::sqlite3_vtab_cursor>();
-            ffi::SQLITE_OK
-        }
-        Err(Error::SqliteFailure(err, s)) => {
-            if let Some(err_msg) = s {
-                set_err_msg(vtab, &err_msg);
+    catch_panic(|| {
+        let vt = vtab.cast::<T>();
+        match (*vt).open() {
+            Ok(cursor) => {
+                let boxed_cursor: *mut T::Cursor = Box::into_raw(Box::new(cursor));
+                *pp_cursor = boxed_cursor.cast::<ffi::sqlite3_vtab_cursor>();
+                ffi::SQLITE_OK
+            }
+            Err(Error::SqliteFailure(err, s)) => {
+                if let Some(err_msg) = s {
+                    set_err_msg(vtab, &err_msg);
+                }
+                err.extended_code
+            }
+            Err(err) => {
+                set_err_msg(vtab, &err.to_string());
+                ffi::SQLITE_ERROR
             }
-            err.extended_code
-        }
-        Err(err) => {
-            set_err_msg(vtab, &err.to_string());
-            ffi::SQLITE_ERROR
         }
-    }
+    })
 }
 
 unsafe extern "C" fn rust_close<C>(cursor: *mut ffi::sqlite3_vtab_cursor) -> c_int
 where
     C: VTabCursor,
 {
-    let cr = cursor.cast::<C>();
-    drop(Box::from_raw(cr));
-    ffi::SQLITE_OK
+    catch_panic(|| {
+        let cr = cursor.cast::<C>();
+        drop(Box::from_raw(cr));
+        ffi::SQLITE_OK
+    })
 }
 
 unsafe extern "C" fn rust_filter<C>(
@@ -1144,34 +1376,40 @@ unsafe extern "C" fn rust_filter<C>(
 where
     C: VTabCursor,
 {
-    use std::ffi::CStr;
-    use std::str;
-    let idx_name = if idx_str.is_null() {
-        None
-    } else {
-        let c_slice = CStr::from_ptr(idx_str).to_bytes();
-        Some(str::from_utf8_unchecked(c_slice))
-    };
-    let args = slice::from_raw_parts_mut(argv, argc as usize);
-    let values = Values { args };
-    let cr = cursor as *mut C;
-    cursor_error(cursor, (*cr).filter(idx_num, idx_name, &values))
+    catch_panic(|| {
+        use std::ffi::CStr;
+        use std::str;
+        let idx_name = if idx_str.is_null() {
+            None
+        } else {
+            let c_slice = CStr::from_ptr(idx_str).to_bytes();
+            Some(str::from_utf8_unchecked(c_slice))
+        };
+        let args = slice::from_raw_parts_mut(argv, argc as usize);
+        let values = Values { args };
+        let cr = cursor as *mut C;
+        cursor_error(cursor, (*cr).filter(idx_num, idx_name, &values))
+    })
 }
 
 unsafe extern "C" fn rust_next<C>(cursor: *mut ffi::sqlite3_vtab_cursor) -> c_int
 where
     C: VTabCursor,
 {
-    let cr = cursor as *mut C;
-    cursor_error(cursor, (*cr).next())
+    catch_panic(|| {
+        let cr = cursor as *mut C;
+        cursor_error(cursor, (*cr).next())
+    })
 }
 
 unsafe extern "C" fn rust_eof<C>(cursor: *mut ffi::sqlite3_vtab_cursor) -> c_int
 where
     C: VTabCursor,
 {
-    let cr = cursor.cast::<C>();
-    (*cr).eof() as c_int
+    catch_panic(|| {
+        let cr = cursor.cast::<C>();
+        (*cr).eof() as c_int
+    })
 }
 
 unsafe extern "C" fn rust_column<C>(
@@ -1182,9 +1420,11 @@ unsafe extern "C" fn rust_column<C>(
 where
     C: VTabCursor,
 {
-    let cr = cursor.cast::<C>();
-    let mut ctxt = Context(ctx);
-    result_error(ctx, (*cr).column(&mut ctxt, i))
+    catch_panic(|| {
+        let cr = cursor.cast::<C>();
+        let mut ctxt = Context(ctx);
+        result_error(ctx, (*cr).column(&mut ctxt, i))
+    })
 }
 
 unsafe extern "C" fn rust_rowid<C>(
@@ -1194,14 +1434,16 @@ unsafe extern "C" fn rust_rowid<C>(
 where
     C: VTabCursor,
 {
-    let cr = cursor.cast::<C>();
-    match (*cr).rowid() {
-        Ok(rowid) => {
-            *p_rowid = rowid;
-            ffi::SQLITE_OK
+    catch_panic(|| {
+        let cr = cursor.cast::<C>();
+        match (*cr).rowid() {
+            Ok(rowid) => {
+                *p_rowid = rowid;
+                ffi::SQLITE_OK
+            }
+            err => cursor_error(cursor, err),
         }
-        err => cursor_error(cursor, err),
-    }
+    })
 }
 
 unsafe extern "C" fn rust_update<'vtab, T: 'vtab>(
@@ -1213,38 +1455,49 @@ unsafe extern "C" fn rust_update<'vtab, T: 'vtab>(
 where
     T: UpdateVTab<'vtab>,
 {
-    assert!(argc >= 1);
-    let args = slice::from_raw_parts_mut(argv, argc as usize);
-    let vt = vtab.cast::<T>();
-    let r = if args.len() == 1 {
-        (*vt).delete(ValueRef::from_value(args[0]))
-    } else if ffi::sqlite3_value_type(args[0]) == ffi::SQLITE_NULL {
-        // TODO Make the distinction between argv[1] == NULL and argv[1] != NULL ?
-        let values = Values { args };
-        match (*vt).insert(&values) {
-            Ok(rowid) => {
-                *p_rowid = rowid;
-                Ok(())
+    catch_panic(|| {
+        assert!(argc >= 1);
+        let args = slice::from_raw_parts_mut(argv, argc as usize);
+        let vt = vtab.cast::<T>();
+        let r = if args.len() == 1 {
+            (*vt).delete(types::value_ref(args[0]))
+        } else if ffi::sqlite3_value_type(args[0]) == ffi::SQLITE_NULL {
+            // TODO Make the distinction between argv[1] == NULL and argv[1] != NULL ?
+            let values = Values { args };
+            match (*vt).insert(&values) {
+                Ok(rowid) => {
+                    *p_rowid = rowid;
+                    Ok(())
+                }
+                Err(e) => Err(e),
             }
-            Err(e) => Err(e),
-        }
-    } else {
-        let values = Values { args };
-        (*vt).update(&values)
-    };
-    match r {
-        Ok(_) => ffi::SQLITE_OK,
-        Err(Error::SqliteFailure(err, s)) => {
-            if let Some(err_msg) = s {
-                set_err_msg(vtab, &err_msg);
+        } else {
+            let values = Values { args };
+            (*vt).update(&values)
+        };
+        match r {
+            Ok(_) => ffi::SQLITE_OK,
+            Err(Error::SqliteFailure(err, s)) => {
+                if let Some(err_msg) = s {
+                    set_err_msg(vtab, &err_msg);
+                }
+                err.extended_code
+            }
+            Err(err) => {
+                set_err_msg(vtab, &err.to_string());
+                ffi::SQLITE_ERROR
             }
-            err.extended_code
-        }
-        Err(err) => {
-            set_err_msg(vtab, &err.to_string());
-            ffi::SQLITE_ERROR
         }
-    }
+    })
+}
+
+// Runs the body of a method called by SQLite, turning a panic into
+// `SQLITE_ERROR` (see `panic_policy.rs`).
+unsafe fn catch_panic(f: impl FnOnce() -> c_int) -> c_int {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        stash_panic(payload);
+        ffi::SQLITE_ERROR
+    })
 }
 
 /// Virtual table cursors can set an error message by assigning a string to
@@ -1321,6 +1574,7 @@ pub mod array;
 #[cfg(feature = "csvtab")]
 #[cfg_attr(docsrs, doc(cfg(feature = "csvtab")))]
 pub mod csvtab;
+mod function;
 #[cfg(feature = "series")]
 #[cfg_attr(docsrs, doc(cfg(feature = "series")))]
 pub mod series; // SQLite >= 3.9.0
@@ -1353,4 +1607,16 @@ mod test {
         assert_eq!(Some(false), super::parse_boolean("off"));
         assert_eq!(Some(false), super::parse_boolean("false"));
     }
+    #[test]
+    fn test_is_without_rowid() {
+        use super::is_without_rowid;
+        assert!(!is_without_rowid("CREATE TABLE x(a, b)"));
+        assert!(is_without_rowid(
+            "CREATE TABLE x(a PRIMARY KEY, b) WITHOUT ROWID"
+        ));
+        assert!(is_without_rowid(
+            "CREATE TABLE x(a PRIMARY KEY) strict, without\n rowid;"
+        ));
+        assert!(!is_without_rowid("CREATE TABLE x(\"without rowid\")"));
+    }
 }
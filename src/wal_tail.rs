@@ -0,0 +1,383 @@
+//! Change data capture of the writes made to a database by other connections
+//! and processes.
+//!
+//! The [update hook](crate::Connection::update_hook) only sees changes made
+//! through its own connection. A [`WalTailer`] instead watches a database
+//! file: it polls [`PRAGMA data_version`](https://sqlite.org/pragma.html#pragma_data_version),
+//! which is cheap, and when another connection has committed it compares
+//! each table with a shadow copy kept in memory, using
+//! [`sqlite3session_diff`](https://sqlite.org/session/sqlite3session_diff.html).
+//! Everything committed since the previous poll, however many transactions
+//! it took, is reported as a single [`Changeset`].
+//!
+//! The shadow copy holds every watched row, so memory use grows with the
+//! database, and each table that changed is compared and copied in full.
+//! In WAL mode, the short read transactions of the tailer never block the
+//! writers.
+//!
+//! ```rust,no_run
+//! # use rusqlite::Result;
+//! use fallible_streaming_iterator::FallibleStreamingIterator;
+//! use rusqlite::wal_tail::WalTailer;
+//! use std::ops::ControlFlow;
+//!
+//! fn follow(path: &str) -> Result<()> {
+//!     let mut tailer = WalTailer::open(path)?;
+//!     tailer.run(|changeset| {
+//!         let mut iter = changeset.iter()?;
+//!         while let Some(item) = iter.next()? {
+//!             let op = item.op()?;
+//!             println!("{:?} on {}", op.code(), op.table_name());
+//!         }
+//!         Ok(ControlFlow::Continue(()))
+//!     })
+//! }
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use fallible_streaming_iterator::FallibleStreamingIterator;
+
+use crate::pragma::Sql;
+use crate::session::{Changeset, Session};
+use crate::{Connection, DatabaseName, OpenFlags, Result};
+
+const SHADOW: &str = "wal_tail_shadow";
+
+fn shadow_table(name: &str) -> String {
+    let mut sql = Sql::new();
+    sql.push_identifier(SHADOW);
+    sql.push_dot();
+    sql.push_identifier(name);
+    sql.as_str().to_owned()
+}
+
+fn main_table(name: &str) -> String {
+    let mut sql = Sql::new();
+    sql.push_identifier("main");
+    sql.push_dot();
+    sql.push_identifier(name);
+    sql.as_str().to_owned()
+}
+
+/// Reports the changes other connections commit to a database, as described
+/// in the [module documentation](self).
+///
+/// Only tables with a primary key are watched, as the session extension
+/// requires one to identify rows. A table created or altered by another
+/// connection is compared with an empty shadow, so all of its rows are
+/// reported as inserted; the rows of a dropped table are not reported.
+#[derive(Debug)]
+pub struct WalTailer {
+    conn: Connection,
+    // name and `CREATE TABLE` statement of each watched table
+    tables: BTreeMap<String, String>,
+    data_version: u32,
+    poll_interval: Duration,
+}
+
+impl WalTailer {
+    /// Open the database at `path` and take the shadow copy of its current
+    /// content, so that only the changes committed afterwards are reported.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the database does not exist or cannot be read.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<WalTailer> {
+        let flags = OpenFlags::SQLITE_OPEN_READ_WRITE
+            | OpenFlags::SQLITE_OPEN_URI
+            | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        let conn = Connection::open_with_flags(path, flags)?;
+        conn.execute_batch(&format!("ATTACH ':memory:' AS {SHADOW}"))?;
+        let mut tailer = WalTailer {
+            conn,
+            tables: BTreeMap::new(),
+            data_version: 0,
+            poll_interval: Duration::from_millis(100),
+        };
+        tailer.sync(false)?;
+        Ok(tailer)
+    }
+
+    /// How long [`run`](WalTailer::run) sleeps between two polls. The
+    /// default is 100 milliseconds.
+    #[inline]
+    #[must_use]
+    pub fn poll_interval(&self) -> Duration {
+        self.poll_interval
+    }
+
+    /// Set how long [`run`](WalTailer::run) sleeps between two polls.
+    #[inline]
+    pub fn set_poll_interval(&mut self, interval: Duration) {
+        self.poll_interval = interval;
+    }
+
+    /// Returns the changes committed by other connections since the
+    /// previous poll, or `None` if no watched table has changed.
+    pub fn poll(&mut self) -> Result<Option<Changeset>> {
+        if self.data_version()? == self.data_version {
+            return Ok(None);
+        }
+        self.sync(true)
+    }
+
+    /// Poll the database every [`poll_interval`](WalTailer::poll_interval),
+    /// calling `f` with each changeset until it returns `ControlFlow::Break`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if a poll or `f` fails.
+    pub fn run<F>(&mut self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&Changeset) -> Result<ControlFlow<()>>,
+    {
+        loop {
+            if let Some(changeset) = self.poll()? {
+                if f(&changeset)?.is_break() {
+                    return Ok(());
+                }
+            }
+            thread::sleep(self.poll_interval);
+        }
+    }
+
+    fn data_version(&self) -> Result<u32> {
+        self.conn
+            .pragma_query_value(Some(DatabaseName::Main), "data_version", |row| row.get(0))
+    }
+
+    fn load_tables(&self) -> Result<BTreeMap<String, String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT m.name, m.sql FROM main.sqlite_master m \
+             WHERE m.type = 'table' AND m.sql LIKE 'CREATE TABLE %' \
+             AND m.name NOT LIKE 'sqlite_%' \
+             AND EXISTS (SELECT 1 FROM pragma_table_info(m.name, 'main') WHERE pk > 0)",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    // Bring the shadow copy up to date with the main database, returning
+    // the changes between the two if `diff` is set.
+    fn sync(&mut self, diff: bool) -> Result<Option<Changeset>> {
+        let tx = self.conn.unchecked_transaction()?;
+        let data_version = self.data_version()?;
+        let tables = self.load_tables()?;
+        for (name, sql) in &self.tables {
+            if tables.get(name) != Some(sql) {
+                tx.execute_batch(&format!("DROP TABLE {}", shadow_table(name)))?;
+            }
+        }
+        for (name, sql) in &tables {
+            if self.tables.get(name) != Some(sql) {
+                // the schema name and the keywords before the table name are
+                // normalized away in sqlite_master
+                let definition = &sql["CREATE TABLE ".len()..];
+                tx.execute_batch(&format!("CREATE TABLE {SHADOW}.{definition}"))?;
+            }
+        }
+
+        let (changeset, changed) = if diff {
+            let mut session = Session::new(&tx)?;
+            for name in tables.keys() {
+                session.attach(Some(name))?;
+                session.diff(DatabaseName::Attached(SHADOW), name)?;
+            }
+            if session.is_empty() {
+                (None, BTreeSet::new())
+            } else {
+                let changeset = session.changeset()?;
+                let mut changed = BTreeSet::new();
+                let mut iter = changeset.iter()?;
+                while let Some(item) = iter.next()? {
+                    changed.insert(item.op()?.table_name().to_owned());
+                }
+                drop(iter);
+                (Some(changeset), changed)
+            }
+        } else {
+            (None, tables.keys().cloned().collect())
+        };
+        for name in &changed {
+            let shadow = shadow_table(name);
+            tx.execute_batch(&format!(
+                "DELETE FROM {shadow}; INSERT INTO {shadow} SELECT * FROM {}",
+                main_table(name)
+            ))?;
+        }
+        tx.commit()?;
+        self.tables = tables;
+        self.data_version = data_version;
+        Ok(changeset)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use fallible_streaming_iterator::FallibleStreamingIterator;
+    use std::ops::ControlFlow;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::WalTailer;
+    use crate::hooks::Action;
+    use crate::session::Changeset;
+    use crate::types::Value;
+    use crate::{Connection, Result};
+
+    fn writer(path: &std::path::Path) -> Result<Connection> {
+        let db = Connection::open(path)?;
+        let mode: String = db.pragma_update_and_check(None, "journal_mode", "WAL", |r| r.get(0))?;
+        assert_eq!(mode, "wal");
+        db.execute_batch(
+            "CREATE TABLE foo (id INTEGER PRIMARY KEY, name TEXT);
+             CREATE TABLE no_pk (x);
+             INSERT INTO foo VALUES (1, 'one'), (2, 'two'), (3, 'three');",
+        )?;
+        Ok(db)
+    }
+
+    fn changes(changeset: &Changeset) -> Result<Vec<(String, Action, Vec<Value>)>> {
+        let mut changes = Vec::new();
+        let mut iter = changeset.iter()?;
+        while let Some(item) = iter.next()? {
+            let op = item.op()?;
+            let mut values = Vec::new();
+            for col in 0..op.number_of_columns() as usize {
+                let value = match op.code() {
+                    Action::SQLITE_DELETE => item.old_value(col)?,
+                    _ => item.new_value(col)?,
+                };
+                values.push(value.into());
+            }
+            changes.push((op.table_name().to_owned(), op.code(), values));
+        }
+        Ok(changes)
+    }
+
+    #[test]
+    fn test_poll() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("tail.db3");
+        let db = writer(&path)?;
+        let mut tailer = WalTailer::open(&path)?;
+        assert!(tailer.poll()?.is_none());
+
+        db.execute_batch(
+            "INSERT INTO foo VALUES (4, 'four');
+             UPDATE foo SET name = 'TWO' WHERE id = 2;
+             DELETE FROM foo WHERE id = 3;
+             INSERT INTO no_pk VALUES (1);",
+        )?;
+        let changeset = tailer.poll()?.unwrap();
+        let mut changes = changes(&changeset)?;
+        changes.sort_by_key(|(_, action, _)| format!("{:?}", action));
+        // an update only carries the new values of the changed columns
+        assert_eq!(
+            changes,
+            vec![
+                (
+                    "foo".to_owned(),
+                    Action::SQLITE_DELETE,
+                    vec![Value::Integer(3), Value::Text("three".to_owned())]
+                ),
+                (
+                    "foo".to_owned(),
+                    Action::SQLITE_INSERT,
+                    vec![Value::Integer(4), Value::Text("four".to_owned())]
+                ),
+                (
+                    "foo".to_owned(),
+                    Action::SQLITE_UPDATE,
+                    vec![Value::Null, Value::Text("TWO".to_owned())]
+                ),
+            ]
+        );
+        assert!(tailer.poll()?.is_none());
+
+        // a change to a table without primary key is not reported
+        db.execute_batch("INSERT INTO no_pk VALUES (2)")?;
+        assert!(tailer.poll()?.is_none());
+
+        // nor is a change reverted before the poll
+        db.execute_batch(
+            "UPDATE foo SET name = 'x' WHERE id = 1;
+             UPDATE foo SET name = 'one' WHERE id = 1;",
+        )?;
+        assert!(tailer.poll()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_batches_are_coalesced() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("tail.db3");
+        let db = writer(&path)?;
+        let mut tailer = WalTailer::open(&path)?;
+        for batch in 0..10 {
+            db.execute_batch("BEGIN")?;
+            for i in 0..100 {
+                db.execute(
+                    "INSERT INTO foo (id, name) VALUES (?, 'batch')",
+                    [100 + batch * 100 + i],
+                )?;
+            }
+            db.execute_batch("COMMIT")?;
+        }
+        db.execute_batch("CREATE TABLE bar (k TEXT PRIMARY KEY); INSERT INTO bar VALUES ('a');")?;
+
+        let changes = changes(&tailer.poll()?.unwrap())?;
+        assert_eq!(changes.len(), 1001);
+        assert!(changes
+            .iter()
+            .all(|(_, action, _)| *action == Action::SQLITE_INSERT));
+        assert_eq!(
+            changes.iter().filter(|(table, ..)| table == "bar").count(),
+            1
+        );
+        assert!(tailer.poll()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_run() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("tail.db3");
+        writer(&path)?;
+        let mut tailer = WalTailer::open(&path)?;
+        tailer.set_poll_interval(Duration::from_millis(5));
+
+        let writer_path = path.clone();
+        let handle = thread::spawn(move || -> Result<()> {
+            let db = Connection::open(writer_path)?;
+            for id in 10..13 {
+                thread::sleep(Duration::from_millis(20));
+                db.execute("INSERT INTO foo (id) VALUES (?)", [id])?;
+            }
+            Ok(())
+        });
+        let mut ids = Vec::new();
+        tailer.run(|changeset| {
+            for (_, _, values) in changes(changeset)? {
+                ids.push(values[0].clone());
+            }
+            Ok(if ids.len() == 3 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            })
+        })?;
+        handle.join().unwrap()?;
+        ids.sort_by_key(|v| format!("{:?}", v));
+        assert_eq!(
+            ids,
+            vec![Value::Integer(10), Value::Integer(11), Value::Integer(12)]
+        );
+        Ok(())
+    }
+}
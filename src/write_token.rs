@@ -0,0 +1,213 @@
+//! Read-your-own-writes across connections to the same database file.
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::ffi;
+#[cfg(feature = "modern_sqlite")]
+use crate::transaction::TransactionState;
+use crate::{Connection, Error, ErrorCode, InnerConnection, Result};
+
+/// Marks a committed write, so that another connection to the same database
+/// can make sure it reads it, with [`Connection::wait_for`].
+///
+/// Created by [`Connection::commit_token`]. A token is cheap to clone and can
+/// be sent to other threads, e.g. along with a request handed from the
+/// connection that wrote to the one that will read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WriteToken {
+    path: Option<PathBuf>,
+    commit: u64,
+}
+
+// How long to sleep between attempts to start a read transaction.
+const RETRY_INTERVAL: Duration = Duration::from_millis(5);
+
+impl Connection {
+    /// Returns a token for the writes this connection has committed so far.
+    ///
+    /// Call it right after the write, e.g. after
+    /// [`Transaction::commit`](crate::Transaction::commit), and pass the token
+    /// to [`wait_for`](Connection::wait_for) on the connection that reads
+    /// next.
+    ///
+    /// Commits are numbered per database file in a process-wide registry when
+    /// this method is called, which the connection joins on first use of a
+    /// token; no commit hook is installed, so one set with
+    /// [`commit_hook`](Connection::commit_hook) is left alone. Tokens only
+    /// cover connections of this process.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if a transaction is open, as its writes are not
+    /// committed yet.
+    pub fn commit_token(&self) -> Result<WriteToken> {
+        let mut db = self.db.borrow_mut();
+        if !db.is_autocommit() {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_MISUSE),
+                Some("commit_token called inside a transaction".to_owned()),
+            ));
+        }
+        let path = db.register_path().map(|p| p.to_path_buf());
+        let commit = path.as_deref().map_or(0, crate::busy::add_commit);
+        db.observed_commit = commit;
+        Ok(WriteToken { path, commit })
+    }
+
+    /// Makes sure the next read on this connection sees the write marked by
+    /// `token`.
+    ///
+    /// Under WAL, a connection reads from the snapshot taken when its read
+    /// transaction started, so a transaction (or a statement that was not
+    /// reset) opened before the write would miss it. When no read
+    /// transaction is open, the next one is started here, retrying for up to
+    /// `timeout` while the database is busy, and its snapshot includes the
+    /// write.
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// # use std::time::Duration;
+    /// fn read_after_write(writer: &Connection, reader: &Connection) -> Result<i64> {
+    ///     writer.execute("INSERT INTO log (msg) VALUES ('hello')", [])?;
+    ///     let token = writer.commit_token()?;
+    ///     reader.wait_for(&token, Duration::from_secs(1))?;
+    ///     reader.query_row("SELECT count(*) FROM log", [], |row| row.get(0))
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` with [`ErrorCode::DatabaseBusy`] if a read
+    /// transaction that may have started before the write is still open, as
+    /// this connection cannot see the write before it ends, or if the
+    /// database stays busy for `timeout`. Will return `Err` if `token` is for
+    /// another database.
+    pub fn wait_for(&self, token: &WriteToken, timeout: Duration) -> Result<()> {
+        let path = self
+            .db
+            .borrow_mut()
+            .register_path()
+            .map(|p| p.to_path_buf());
+        if path != token.path {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_MISUSE),
+                Some("write token is for another database".to_owned()),
+            ));
+        }
+        let path = match path {
+            Some(path) => path,
+            // in-memory databases are not shared through a file
+            None => return Ok(()),
+        };
+        if self.db.borrow().observed_commit >= token.commit {
+            return Ok(());
+        }
+        if self.db.borrow().holds_snapshot() {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_BUSY),
+                Some("a read transaction started before the write is still open".to_owned()),
+            ));
+        }
+        let commit = crate::busy::last_commit(&path);
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(())) {
+                Ok(()) => break,
+                Err(e)
+                    if e.sqlite_error_code() == Some(ErrorCode::DatabaseBusy)
+                        && Instant::now() < deadline =>
+                {
+                    thread::sleep(RETRY_INTERVAL);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        self.db.borrow_mut().observed_commit = commit;
+        Ok(())
+    }
+}
+
+impl InnerConnection {
+    // Returns true if a read transaction may be open, pinning the snapshot
+    // the connection reads from.
+    fn holds_snapshot(&self) -> bool {
+        #[cfg(feature = "modern_sqlite")]
+        if let Ok(state) = self.txn_state(None) {
+            return state != TransactionState::None;
+        }
+        !self.is_autocommit() || self.is_busy()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::{Connection, ErrorCode, Result};
+
+    fn open_wal(path: &std::path::Path) -> Result<Connection> {
+        let db = Connection::open(path)?;
+        db.pragma_update_and_check(None, "journal_mode", "wal", |_| Ok(()))?;
+        Ok(db)
+    }
+
+    #[test]
+    fn test_wait_for() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let writer = open_wal(&path)?;
+        let reader = open_wal(&path)?;
+        writer.execute_batch("CREATE TABLE t(x INTEGER)")?;
+
+        // a read transaction started before the write misses it
+        reader.execute_batch("BEGIN")?;
+        let count: i64 = reader.query_row("SELECT count(*) FROM t", [], |r| r.get(0))?;
+        assert_eq!(count, 0);
+        writer.execute("INSERT INTO t VALUES (1)", [])?;
+        let token = writer.commit_token()?;
+        let count: i64 = reader.query_row("SELECT count(*) FROM t", [], |r| r.get(0))?;
+        assert_eq!(count, 0);
+        let err = reader
+            .wait_for(&token, Duration::from_millis(10))
+            .unwrap_err();
+        assert_eq!(err.sqlite_error_code(), Some(ErrorCode::DatabaseBusy));
+
+        reader.execute_batch("COMMIT")?;
+        reader.wait_for(&token, Duration::from_millis(10))?;
+        let count: i64 = reader.query_row("SELECT count(*) FROM t", [], |r| r.get(0))?;
+        assert_eq!(count, 1);
+
+        // once seen, the write stays visible to later read transactions
+        reader.execute_batch("BEGIN")?;
+        reader.query_row("SELECT count(*) FROM t", [], |_| Ok(()))?;
+        reader.wait_for(&token, Duration::from_millis(10))?;
+        reader.execute_batch("COMMIT")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit_token_misuse() -> Result<()> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut db = open_wal(&temp_dir.path().join("a.db3"))?;
+        let other = open_wal(&temp_dir.path().join("b.db3"))?;
+
+        let tx = db.transaction()?;
+        assert!(tx.commit_token().is_err());
+        tx.commit()?;
+
+        let token = db.commit_token()?;
+        assert_eq!(
+            other
+                .wait_for(&token, Duration::from_millis(10))
+                .unwrap_err()
+                .sqlite_error_code(),
+            Some(ErrorCode::ApiMisuse)
+        );
+
+        let memory = Connection::open_in_memory()?;
+        let token = memory.commit_token()?;
+        memory.wait_for(&token, Duration::from_millis(10))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+//! Ensure conversions written against `rusqlite-types` alone, and
+//! conversions written for the former `rusqlite::Result` signature, work
+//! through a `Connection`.
+
+use rusqlite::{Connection, Error, Result};
+use rusqlite_types::{
+    FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ToSqlResult, ValueRef,
+};
+
+#[derive(Debug, PartialEq)]
+struct Percent(u8);
+
+impl ToSql for Percent {
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        if self.0 > 100 {
+            return Err(format!("{}% is above 100%", self.0).into());
+        }
+        Ok(ToSqlOutput::from(self.0))
+    }
+}
+
+impl FromSql for Percent {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match u8::column_result(value)? {
+            p @ 0..=100 => Ok(Percent(p)),
+            p => Err(FromSqlError::OutOfRange(p.into())),
+        }
+    }
+}
+
+// As written against `rusqlite::Result<ToSqlOutput<'_>>`, with only the
+// return type changed.
+struct Legacy(Option<i64>);
+
+fn check(n: Option<i64>) -> Result<i64> {
+    n.ok_or(Error::InvalidQuery)
+}
+
+impl ToSql for Legacy {
+    fn to_sql(&self) -> ToSqlResult<'_> {
+        if self.0 == Some(0) {
+            return Err(Error::ToSqlConversionFailure("zero".into()).into());
+        }
+        Ok(ToSqlOutput::from(check(self.0)?))
+    }
+}
+
+#[test]
+fn test_third_party_round_trip() -> Result<()> {
+    let db = Connection::open_in_memory()?;
+    db.execute_batch("CREATE TABLE foo (p INTEGER)")?;
+    db.execute("INSERT INTO foo (p) VALUES (?1)", [Percent(42)])?;
+    let p: Percent = db.query_row("SELECT p FROM foo", [], |r| r.get(0))?;
+    assert_eq!(p, Percent(42));
+
+    let err = db
+        .execute("INSERT INTO foo (p) VALUES (?1)", [Percent(101)])
+        .unwrap_err();
+    match err {
+        Error::ToSqlConversionFailure(err) => assert_eq!(err.to_string(), "101% is above 100%"),
+        err => panic!("Unexpected error {}", err),
+    }
+    let err = db
+        .query_row("SELECT 150", [], |r| r.get::<_, Percent>(0))
+        .unwrap_err();
+    assert_eq!(err, Error::IntegralValueOutOfRange(0, 150));
+    Ok(())
+}
+
+#[test]
+fn test_legacy_to_sql_errors() -> Result<()> {
+    let db = Connection::open_in_memory()?;
+    db.execute_batch("CREATE TABLE foo (n INTEGER)")?;
+    db.execute("INSERT INTO foo (n) VALUES (?1)", [Legacy(Some(3))])?;
+    let n: i64 = db.query_row("SELECT n FROM foo", [], |r| r.get(0))?;
+    assert_eq!(n, 3);
+
+    // the errors are those the former signature returned
+    let err = db
+        .execute("INSERT INTO foo (n) VALUES (?1)", [Legacy(None)])
+        .unwrap_err();
+    assert_eq!(err, Error::InvalidQuery);
+    let err = db
+        .execute("INSERT INTO foo (n) VALUES (?1)", [Legacy(Some(0))])
+        .unwrap_err();
+    match err {
+        Error::ToSqlConversionFailure(err) => assert_eq!(err.to_string(), "zero"),
+        err => panic!("Unexpected error {}", err),
+    }
+    Ok(())
+}
@@ -98,3 +98,604 @@ fn test_dummy_module() -> rusqlite::Result<()> {
     assert_eq!(1, dummy);
     Ok(())
 }
+
+#[cfg(feature = "vtab")]
+#[test]
+fn test_update_module() -> rusqlite::Result<()> {
+    use rusqlite::ffi;
+    use rusqlite::vtab::{
+        sqlite3_vtab, sqlite3_vtab_cursor, update_module, ConflictMode, Context, CreateVTab,
+        IndexInfo, UpdateVTab, VTab, VTabConnection, VTabCursor, VTabKind, Values,
+    };
+    use rusqlite::{Connection, Error, Result};
+    use std::marker::PhantomData;
+    use std::os::raw::c_int;
+
+    // (rowid, key, value), with unique keys
+    type Row = (i64, String, i64);
+
+    #[repr(C)]
+    struct KvTab {
+        /// Base class. Must be first
+        base: sqlite3_vtab,
+        db: VTabConnection,
+        rows: Vec<Row>,
+        without_rowid: bool,
+    }
+
+    impl KvTab {
+        fn position(&self, rowid: i64) -> Option<usize> {
+            self.rows.iter().position(|r| r.0 == rowid)
+        }
+
+        // Resolves a conflict on `key` with another row than `rowid`, returning
+        // whether to skip the change.
+        fn resolve(&mut self, key: &str, rowid: Option<i64>) -> Result<bool> {
+            let conflict = match self.rows.iter().position(|r| r.1 == key) {
+                Some(i) if Some(self.rows[i].0) != rowid => i,
+                _ => return Ok(false),
+            };
+            match self.db.on_conflict() {
+                ConflictMode::Ignore => Ok(true),
+                ConflictMode::Replace => {
+                    self.rows.remove(conflict);
+                    Ok(false)
+                }
+                _ => Err(Error::SqliteFailure(
+                    ffi::Error::new(ffi::SQLITE_CONSTRAINT),
+                    Some(format!("duplicate key {key}")),
+                )),
+            }
+        }
+    }
+
+    unsafe impl<'vtab> VTab<'vtab> for KvTab {
+        type Aux = ();
+        type Cursor = KvTabCursor<'vtab>;
+
+        fn connect(
+            db: &mut VTabConnection,
+            _aux: Option<&()>,
+            args: &[&[u8]],
+        ) -> Result<(String, KvTab)> {
+            let without_rowid = args.len() > 3 && args[3] == b"without_rowid";
+            let vtab = KvTab {
+                base: sqlite3_vtab::default(),
+                db: db.clone(),
+                rows: Vec::new(),
+                without_rowid,
+            };
+            let sql = if without_rowid {
+                "CREATE TABLE x(key PRIMARY KEY, value) WITHOUT ROWID"
+            } else {
+                "CREATE TABLE x(key, value)"
+            };
+            Ok((sql.to_owned(), vtab))
+        }
+
+        fn best_index(&self, info: &mut IndexInfo) -> Result<()> {
+            info.set_estimated_cost(self.rows.len() as f64);
+            Ok(())
+        }
+
+        fn open(&'vtab mut self) -> Result<KvTabCursor<'vtab>> {
+            assert!(!self.without_rowid);
+            Ok(KvTabCursor {
+                base: sqlite3_vtab_cursor::default(),
+                rows: self.rows.clone(),
+                i: 0,
+                phantom: PhantomData,
+            })
+        }
+    }
+
+    impl CreateVTab<'_> for KvTab {
+        const KIND: VTabKind = VTabKind::Default;
+    }
+
+    impl UpdateVTab<'_> for KvTab {
+        fn delete(&mut self, arg: rusqlite::types::ValueRef<'_>) -> Result<()> {
+            let rowid = arg.as_i64()?;
+            if let Some(i) = self.position(rowid) {
+                self.rows.remove(i);
+            }
+            Ok(())
+        }
+
+        fn insert(&mut self, args: &Values<'_>) -> Result<i64> {
+            let key: String = args.get(2)?;
+            let value: i64 = args.get(3)?;
+            if self.resolve(&key, None)? {
+                return Ok(self.rows.iter().find(|r| r.1 == key).unwrap().0);
+            }
+            let rowid = match args.get::<Option<i64>>(1)? {
+                Some(rowid) => rowid,
+                None => self.rows.iter().map(|r| r.0).max().unwrap_or(0) + 1,
+            };
+            self.rows.push((rowid, key, value));
+            Ok(rowid)
+        }
+
+        fn update(&mut self, args: &Values<'_>) -> Result<()> {
+            let old: i64 = args.get(0)?;
+            let new: i64 = args.get(1)?;
+            let key: String = args.get(2)?;
+            let value: i64 = args.get(3)?;
+            if self.resolve(&key, Some(old))? {
+                return Ok(());
+            }
+            if let Some(i) = self.position(old) {
+                self.rows[i] = (new, key, value);
+            }
+            Ok(())
+        }
+    }
+
+    #[repr(C)]
+    struct KvTabCursor<'vtab> {
+        /// Base class. Must be first
+        base: sqlite3_vtab_cursor,
+        /// A copy of the rows, which statements may change while scanning
+        rows: Vec<Row>,
+        i: usize,
+        phantom: PhantomData<&'vtab KvTab>,
+    }
+
+    unsafe impl VTabCursor for KvTabCursor<'_> {
+        fn filter(&mut self, _: c_int, _: Option<&str>, _: &Values<'_>) -> Result<()> {
+            self.i = 0;
+            Ok(())
+        }
+
+        fn next(&mut self) -> Result<()> {
+            self.i += 1;
+            Ok(())
+        }
+
+        fn eof(&self) -> bool {
+            self.i >= self.rows.len()
+        }
+
+        fn column(&self, ctx: &mut Context, col: c_int) -> Result<()> {
+            let row = &self.rows[self.i];
+            match col {
+                0 => ctx.set_result(&row.1),
+                _ => ctx.set_result(&row.2),
+            }
+        }
+
+        fn rowid(&self) -> Result<i64> {
+            Ok(self.rows[self.i].0)
+        }
+    }
+
+    fn contents(db: &Connection) -> Result<Vec<(i64, String, i64)>> {
+        let mut stmt = db.prepare("SELECT rowid, key, value FROM kv ORDER BY rowid")?;
+        let rows = stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))?;
+        rows.collect()
+    }
+
+    let db = Connection::open_in_memory()?;
+    db.create_module("kvtab", update_module::<KvTab>(), None)?;
+    db.execute_batch("CREATE VIRTUAL TABLE kv USING kvtab")?;
+
+    let n = db.execute("INSERT INTO kv VALUES ('a', 1), ('b', 2), ('c', 3)", [])?;
+    assert_eq!(n, 3);
+    assert_eq!(db.changes(), 3);
+    db.execute("INSERT INTO kv (rowid, key, value) VALUES (10, 'd', 4)", [])?;
+    assert_eq!(db.last_insert_rowid(), 10);
+
+    let n = db.execute("UPDATE kv SET value = value * 10 WHERE value >= 2", [])?;
+    assert_eq!(n, 3);
+    let n = db.execute("DELETE FROM kv WHERE key = 'b'", [])?;
+    assert_eq!(n, 1);
+    assert_eq!(
+        contents(&db)?,
+        [
+            (1, "a".to_owned(), 1),
+            (3, "c".to_owned(), 30),
+            (10, "d".to_owned(), 40)
+        ]
+    );
+
+    // ON CONFLICT
+    let err = db
+        .execute("INSERT INTO kv VALUES ('a', 5)", [])
+        .unwrap_err();
+    assert_eq!(
+        err.sqlite_error_code(),
+        Some(rusqlite::ErrorCode::ConstraintViolation)
+    );
+    assert!(err.to_string().contains("duplicate key a"), "{}", err);
+    db.execute("INSERT OR IGNORE INTO kv VALUES ('a', 6)", [])?;
+    db.execute("UPDATE OR REPLACE kv SET key = 'a' WHERE key = 'c'", [])?;
+    assert_eq!(
+        contents(&db)?,
+        [(3, "a".to_owned(), 30), (10, "d".to_owned(), 40)]
+    );
+
+    let err = db
+        .execute_batch("CREATE VIRTUAL TABLE kv2 USING kvtab(without_rowid)")
+        .unwrap_err();
+    assert!(err.to_string().contains("WITHOUT ROWID"), "{}", err);
+    Ok(())
+}
+
+#[cfg(all(feature = "vtab", feature = "modern_sqlite"))]
+#[test]
+fn test_in_constraint() -> rusqlite::Result<()> {
+    use rusqlite::types::Value;
+    use rusqlite::vtab::{
+        eponymous_only_module, sqlite3_vtab, sqlite3_vtab_cursor, Context, IndexConstraintOp,
+        IndexInfo, VTab, VTabConnection, VTabCursor, Values,
+    };
+    use rusqlite::{Connection, Result};
+    use std::marker::PhantomData;
+    use std::os::raw::c_int;
+    use std::sync::{Arc, Mutex};
+
+    // the keys passed to each filter call
+    type Calls = Arc<Mutex<Vec<Vec<Value>>>>;
+
+    #[repr(C)]
+    struct KeysTab {
+        /// Base class. Must be first
+        base: sqlite3_vtab,
+        calls: Calls,
+    }
+
+    unsafe impl<'vtab> VTab<'vtab> for KeysTab {
+        type Aux = Calls;
+        type Cursor = KeysTabCursor<'vtab>;
+
+        fn connect(
+            _: &mut VTabConnection,
+            aux: Option<&Calls>,
+            _args: &[&[u8]],
+        ) -> Result<(String, KeysTab)> {
+            let vtab = KeysTab {
+                base: sqlite3_vtab::default(),
+                calls: aux.unwrap().clone(),
+            };
+            Ok(("CREATE TABLE x(key, value)".to_owned(), vtab))
+        }
+
+        fn best_index(&self, info: &mut IndexInfo) -> Result<()> {
+            let mut key = None;
+            for (i, c) in info.constraints().enumerate() {
+                if c.is_usable()
+                    && c.column() == 0
+                    && c.operator() == IndexConstraintOp::SQLITE_INDEX_CONSTRAINT_EQ
+                {
+                    key = Some(i);
+                }
+            }
+            match key {
+                Some(i) => {
+                    let in_list = info.set_handle_in(i, true);
+                    let mut usage = info.constraint_usage(i);
+                    usage.set_argv_index(1);
+                    usage.set_omit(true);
+                    info.set_idx_num(if in_list { 2 } else { 1 });
+                    info.set_estimated_cost(1.);
+                }
+                None => {
+                    info.set_idx_num(0);
+                    info.set_estimated_cost(1e9);
+                }
+            }
+            Ok(())
+        }
+
+        fn open(&'vtab mut self) -> Result<KeysTabCursor<'vtab>> {
+            Ok(KeysTabCursor {
+                base: sqlite3_vtab_cursor::default(),
+                calls: self.calls.clone(),
+                keys: Vec::new(),
+                i: 0,
+                phantom: PhantomData,
+            })
+        }
+    }
+
+    #[repr(C)]
+    struct KeysTabCursor<'vtab> {
+        /// Base class. Must be first
+        base: sqlite3_vtab_cursor,
+        calls: Calls,
+        keys: Vec<Value>,
+        i: usize,
+        phantom: PhantomData<&'vtab KeysTab>,
+    }
+
+    unsafe impl VTabCursor for KeysTabCursor<'_> {
+        fn filter(&mut self, idx_num: c_int, _: Option<&str>, args: &Values<'_>) -> Result<()> {
+            // a batch fetch of all the keys
+            self.keys = match idx_num {
+                2 => args.in_values(0).collect::<Result<_>>()?,
+                1 => vec![args.get(0)?],
+                _ => Vec::new(),
+            };
+            self.calls.lock().unwrap().push(self.keys.clone());
+            self.i = 0;
+            Ok(())
+        }
+
+        fn next(&mut self) -> Result<()> {
+            self.i += 1;
+            Ok(())
+        }
+
+        fn eof(&self) -> bool {
+            self.i >= self.keys.len()
+        }
+
+        fn column(&self, ctx: &mut Context, col: c_int) -> Result<()> {
+            let key = &self.keys[self.i];
+            match col {
+                0 => ctx.set_result(key),
+                _ => match key {
+                    Value::Text(s) => ctx.set_result(&s.to_uppercase()),
+                    _ => ctx.set_result(&rusqlite::types::Null),
+                },
+            }
+        }
+
+        fn rowid(&self) -> Result<i64> {
+            Ok(self.i as i64)
+        }
+    }
+
+    let db = Connection::open_in_memory()?;
+    let calls = Calls::default();
+    db.create_module(
+        "keys",
+        eponymous_only_module::<KeysTab>(),
+        Some(calls.clone()),
+    )?;
+
+    let mut stmt = db.prepare("SELECT value FROM keys WHERE key IN ('a', 'b', 'c') ORDER BY 1")?;
+    let values: Vec<String> = stmt.query_map([], |r| r.get(0))?.collect::<Result<_>>()?;
+    assert_eq!(values, ["A", "B", "C"]);
+    {
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.len(), 1);
+        let mut keys = calls[0].clone();
+        keys.sort_by_key(|k| format!("{:?}", k));
+        assert_eq!(
+            keys,
+            [
+                Value::Text("a".to_owned()),
+                Value::Text("b".to_owned()),
+                Value::Text("c".to_owned())
+            ]
+        );
+    }
+
+    // a plain equality is still passed as is
+    calls.lock().unwrap().clear();
+    let value: String = db.query_row("SELECT value FROM keys WHERE key = 'z'", [], |r| r.get(0))?;
+    assert_eq!(value, "Z");
+    assert_eq!(*calls.lock().unwrap(), [[Value::Text("z".to_owned())]]);
+    Ok(())
+}
+
+#[cfg(all(feature = "vtab", feature = "modern_sqlite"))]
+#[test]
+fn test_limit_pushdown() -> rusqlite::Result<()> {
+    use rusqlite::vtab::{
+        eponymous_only_module, sqlite3_vtab, sqlite3_vtab_cursor, Context, DistinctMode,
+        IndexConstraintOp, IndexFlags, IndexInfo, VTab, VTabConnection, VTabCursor, Values,
+    };
+    use rusqlite::{Connection, Result};
+    use std::marker::PhantomData;
+    use std::os::raw::c_int;
+    use std::sync::{Arc, Mutex};
+
+    // idx_num bits: the arguments passed to filter
+    const EQ: c_int = 1;
+    const LIMIT: c_int = 2;
+    const OFFSET: c_int = 4;
+    const MAX: i64 = 1_000_000;
+
+    #[derive(Debug, Default)]
+    struct Stats {
+        // rows produced by the cursors
+        rows: i64,
+        // limit passed to the last filter call
+        limit: Option<i64>,
+        distinct: Option<DistinctMode>,
+        collations: Vec<String>,
+    }
+
+    #[repr(C)]
+    struct NumbersTab {
+        /// Base class. Must be first
+        base: sqlite3_vtab,
+        stats: Arc<Mutex<Stats>>,
+    }
+
+    unsafe impl<'vtab> VTab<'vtab> for NumbersTab {
+        type Aux = Arc<Mutex<Stats>>;
+        type Cursor = NumbersCursor<'vtab>;
+
+        fn connect(
+            _: &mut VTabConnection,
+            aux: Option<&Arc<Mutex<Stats>>>,
+            _args: &[&[u8]],
+        ) -> Result<(String, NumbersTab)> {
+            let vtab = NumbersTab {
+                base: sqlite3_vtab::default(),
+                stats: aux.unwrap().clone(),
+            };
+            Ok(("CREATE TABLE x(value)".to_owned(), vtab))
+        }
+
+        fn best_index(&self, info: &mut IndexInfo) -> Result<()> {
+            let mut stats = self.stats.lock().unwrap();
+            stats.distinct = Some(info.distinct());
+            let mut idx_num = 0;
+            let mut args = Vec::new();
+            for (i, c) in info.constraints().enumerate() {
+                if !c.is_usable() {
+                    continue;
+                }
+                match c.operator() {
+                    IndexConstraintOp::SQLITE_INDEX_CONSTRAINT_EQ if c.column() == 0 => {
+                        stats.collations.push(info.collation(i)?.to_owned());
+                        idx_num |= EQ;
+                        args.push((EQ, i));
+                    }
+                    IndexConstraintOp::SQLITE_INDEX_CONSTRAINT_LIMIT => {
+                        idx_num |= LIMIT;
+                        args.push((LIMIT, i));
+                    }
+                    IndexConstraintOp::SQLITE_INDEX_CONSTRAINT_OFFSET => {
+                        idx_num |= OFFSET;
+                        args.push((OFFSET, i));
+                    }
+                    _ => {}
+                }
+            }
+            // filter expects its arguments in the order of the bits
+            args.sort_unstable();
+            for (n, &(_, i)) in args.iter().enumerate() {
+                let mut usage = info.constraint_usage(i);
+                usage.set_argv_index(n as c_int + 1);
+                usage.set_omit(true);
+            }
+            info.set_idx_num(idx_num);
+            if idx_num & EQ != 0 {
+                info.set_estimated_cost(1.);
+                info.set_estimated_rows(1);
+                info.set_idx_flags(IndexFlags::SQLITE_INDEX_SCAN_UNIQUE);
+            } else {
+                info.set_estimated_cost(MAX as f64);
+                info.set_estimated_rows(MAX);
+            }
+            // the rows are in ascending order
+            let ordered = info
+                .order_bys()
+                .all(|o| o.column() == 0 && !o.is_order_by_desc());
+            info.set_order_by_consumed(ordered);
+            Ok(())
+        }
+
+        fn open(&'vtab mut self) -> Result<NumbersCursor<'vtab>> {
+            Ok(NumbersCursor {
+                base: sqlite3_vtab_cursor::default(),
+                stats: self.stats.clone(),
+                value: 0,
+                end: 0,
+                phantom: PhantomData,
+            })
+        }
+    }
+
+    #[repr(C)]
+    struct NumbersCursor<'vtab> {
+        /// Base class. Must be first
+        base: sqlite3_vtab_cursor,
+        stats: Arc<Mutex<Stats>>,
+        value: i64,
+        end: i64,
+        phantom: PhantomData<&'vtab NumbersTab>,
+    }
+
+    unsafe impl VTabCursor for NumbersCursor<'_> {
+        fn filter(&mut self, idx_num: c_int, _: Option<&str>, args: &Values<'_>) -> Result<()> {
+            let mut args = args.iter();
+            let (mut start, mut end) = (1, MAX);
+            if idx_num & EQ != 0 {
+                let v = args.next().unwrap().as_i64()?;
+                start = v.max(1);
+                end = v.min(MAX);
+            }
+            let limit = if idx_num & LIMIT != 0 {
+                Some(args.next().unwrap().as_i64()?)
+            } else {
+                None
+            };
+            if idx_num & OFFSET != 0 {
+                start += args.next().unwrap().as_i64()?;
+            }
+            if let Some(limit) = limit {
+                end = end.min(start + limit - 1);
+            }
+            self.stats.lock().unwrap().limit = limit;
+            self.value = start;
+            self.end = end;
+            Ok(())
+        }
+
+        fn next(&mut self) -> Result<()> {
+            self.value += 1;
+            Ok(())
+        }
+
+        fn eof(&self) -> bool {
+            self.value > self.end
+        }
+
+        fn column(&self, ctx: &mut Context, _: c_int) -> Result<()> {
+            self.stats.lock().unwrap().rows += 1;
+            ctx.set_result(&self.value)
+        }
+
+        fn rowid(&self) -> Result<i64> {
+            Ok(self.value)
+        }
+    }
+
+    let db = Connection::open_in_memory()?;
+    let stats = Arc::new(Mutex::new(Stats::default()));
+    db.create_module(
+        "numbers",
+        eponymous_only_module::<NumbersTab>(),
+        Some(stats.clone()),
+    )?;
+    let reset = || *stats.lock().unwrap() = Stats::default();
+
+    // the limit is delivered to filter, which only produces those rows
+    let values: Vec<i64> = db
+        .prepare("SELECT value FROM numbers LIMIT 5 OFFSET 10")?
+        .query_map([], |r| r.get(0))?
+        .collect::<Result<_>>()?;
+    assert_eq!(values, [11, 12, 13, 14, 15]);
+    {
+        let stats = stats.lock().unwrap();
+        assert_eq!(stats.limit, Some(5));
+        assert_eq!(stats.rows, 5);
+        assert_eq!(stats.distinct, Some(DistinctMode::Ordered));
+    }
+
+    reset();
+    let sum: i64 = db.query_row(
+        "SELECT sum(value) FROM (SELECT value FROM numbers ORDER BY value LIMIT 3)",
+        [],
+        |r| r.get(0),
+    )?;
+    assert_eq!(sum, 6);
+    assert_eq!(stats.lock().unwrap().rows, 3);
+
+    reset();
+    let value: i64 = db.query_row("SELECT value FROM numbers WHERE value = 42", [], |r| {
+        r.get(0)
+    })?;
+    assert_eq!(value, 42);
+    {
+        let stats = stats.lock().unwrap();
+        assert_eq!(stats.rows, 1);
+        assert_eq!(stats.limit, None);
+        assert!(stats.collations.iter().all(|c| c == "BINARY"));
+    }
+
+    reset();
+    db.query_row(
+        "SELECT count(*) FROM (SELECT DISTINCT value FROM numbers WHERE value = 7)",
+        [],
+        |r| r.get::<_, i64>(0),
+    )?;
+    assert_eq!(stats.lock().unwrap().distinct, Some(DistinctMode::Distinct));
+    Ok(())
+}